@@ -0,0 +1,244 @@
+//! Which remote locations `serve` will touch on a client's say-so.
+//!
+//! A job can name its input, its output and its callback by URL, and the
+//! server then fetches, uploads or POSTs there with its own network access
+//! and its own S3 credentials. Left open, that lets any client read the
+//! cloud's metadata service, probe the private network, or read and
+//! overwrite any object the credentials reach. So nothing remote is allowed
+//! until the operator lists it with `--allow-remote`, each entry a prefix:
+//!
+//! * `s3://bucket` or `s3://bucket/some/prefix/` for objects in a bucket;
+//! * `https://host` or `https://host:8443/some/path/` for URLs.
+//!
+//! A prefix only matches up to a `/` (so `https://example.com` doesn't
+//! allow `https://example.com.evil.org`), paths with `.` or `..` segments
+//! are refused whatever they start with, and an `http(s)://` host is
+//! refused if it resolves to a loopback, private, link-local or otherwise
+//! internal address, even if listed. That lookup is made when the job is
+//! submitted, and made again when the job connects (`resolve_public`, as
+//! the HTTP client's resolver), so a name that turns internal in between
+//! is caught; redirects aren't followed at all, so an allowed host can't
+//! send us on elsewhere. The callback URL the operator gives with
+//! `--callback` is trusted as it is.
+
+use anyhow::{bail, Context, Result};
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
+
+/// The remote prefixes clients may name.
+#[derive(Debug, Clone, Default)]
+pub struct Allowlist {
+    prefixes: Vec<String>,
+}
+
+impl Allowlist {
+    /// An allow-list of `prefixes`, each an `s3://` or `http(s)://` prefix.
+    pub fn new(prefixes: Vec<String>) -> Result<Self> {
+        for prefix in &prefixes {
+            let Some(rest) = ["s3://", "http://", "https://"]
+                .iter()
+                .find_map(|scheme| prefix.strip_prefix(scheme))
+            else {
+                bail!("'{}' is not an s3://, http:// or https:// prefix", prefix);
+            };
+            if rest.split('/').next().unwrap_or("").is_empty() {
+                bail!("'{}' names no bucket or host", prefix);
+            }
+        }
+        Ok(Allowlist { prefixes })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.prefixes.is_empty()
+    }
+
+    pub fn prefixes(&self) -> &[String] {
+        &self.prefixes
+    }
+
+    /// Whether a client may have the server read, write or POST to
+    /// `location`; the error says why not.
+    pub fn check(&self, location: &str) -> Result<()> {
+        if self.prefixes.is_empty() {
+            bail!("this server takes no remote locations (see serve --allow-remote)");
+        }
+        let (scheme, rest) = location
+            .split_once("://")
+            .context("not an s3:// or http(s):// location")?;
+        let path = rest.split(['?', '#']).next().unwrap_or("");
+        if path.split('/').skip(1).any(|segment| {
+            matches!(
+                segment.to_ascii_lowercase().as_str(),
+                "." | ".." | "%2e" | "%2e%2e" | ".%2e" | "%2e."
+            )
+        }) {
+            bail!("'{}' has a . or .. in its path", location);
+        }
+        if !self.prefixes.iter().any(|prefix| within(location, prefix)) {
+            bail!(
+                "'{}' is not under any of this server's allowed prefixes",
+                location
+            );
+        }
+        if scheme == "http" || scheme == "https" {
+            check_host(rest, if scheme == "https" { 443 } else { 80 })?;
+        }
+        Ok(())
+    }
+}
+
+// `location` is `prefix` or below it
+fn within(location: &str, prefix: &str) -> bool {
+    let Some(after) = location.strip_prefix(prefix) else {
+        return false;
+    };
+    after.is_empty() || prefix.ends_with('/') || after.starts_with('/')
+}
+
+// Refuse a URL's host (from `rest`, what follows the `://`) if any address
+// it resolves to is internal
+fn check_host(rest: &str, default_port: u16) -> Result<()> {
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host_port = authority.rsplit('@').next().unwrap_or(authority);
+    let (host, port) = match host_port.strip_prefix('[') {
+        // [v6]:port
+        Some(bracketed) => {
+            let (host, after) = bracketed
+                .split_once(']')
+                .context("unclosed [ in the host")?;
+            (host, after.strip_prefix(':'))
+        }
+        None => match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        },
+    };
+    let port = match port {
+        Some(port) => port
+            .parse()
+            .with_context(|| format!("bad port '{}'", port))?,
+        None => default_port,
+    };
+    let netloc = if host.contains(':') {
+        format!("[{}]:{}", host, port)
+    } else {
+        format!("{}:{}", host, port)
+    };
+    resolve_public(&netloc).with_context(|| format!("could not check '{}'", host))?;
+    Ok(())
+}
+
+/// Look up `netloc` (`host:port`), refusing it if any address it resolves
+/// to is internal. Given to an HTTP agent as its resolver, the check is
+/// made against the addresses it's about to connect to.
+pub fn resolve_public(netloc: &str) -> io::Result<Vec<SocketAddr>> {
+    let addresses: Vec<SocketAddr> = netloc.to_socket_addrs()?.collect();
+    if let Some(internal) = addresses.iter().find(|a| is_internal(a.ip())) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is an internal address ({})", netloc, internal.ip()),
+        ));
+    }
+    Ok(addresses)
+}
+
+fn is_internal(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_internal_v4(v4),
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_internal_v4(v4),
+            None => is_internal_v6(v6),
+        },
+    }
+}
+
+fn is_internal_v4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        // Carrier-grade NAT, 100.64.0.0/10
+        || (a == 100 && (64..128).contains(&b))
+        || a == 0
+}
+
+fn is_internal_v6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local, fc00::/7
+        || (first & 0xfe00) == 0xfc00
+        // Link-local, fe80::/10
+        || (first & 0xffc0) == 0xfe80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allow(prefixes: &[&str]) -> Allowlist {
+        Allowlist::new(prefixes.iter().map(|p| p.to_string()).collect()).unwrap()
+    }
+
+    #[test]
+    fn nothing_is_allowed_by_default() {
+        let none = Allowlist::default();
+        assert!(none.check("s3://bucket/scan.obj").is_err());
+        assert!(none.check("https://93.184.215.14/scan.obj").is_err());
+    }
+
+    #[test]
+    fn buckets_and_prefixes() {
+        let list = allow(&["s3://scans", "s3://results/team-a/"]);
+        assert!(list.check("s3://scans/a/b.obj").is_ok());
+        assert!(list.check("s3://scans-private/b.obj").is_err());
+        assert!(list.check("s3://results/team-a/out.stl").is_ok());
+        assert!(list.check("s3://results/team-b/out.stl").is_err());
+        assert!(list.check("s3://scans/../other/x").is_err());
+    }
+
+    #[test]
+    fn hosts_match_whole() {
+        let list = allow(&["https://93.184.215.14"]);
+        assert!(list.check("https://93.184.215.14/scan.obj").is_ok());
+        assert!(list
+            .check("https://93.184.215.14.evil.org/scan.obj")
+            .is_err());
+        assert!(list.check("https://93.184.215.14:8443/scan.obj").is_err());
+        assert!(list.check("https://93.184.215.14@127.0.0.1/").is_err());
+    }
+
+    #[test]
+    fn internal_addresses_are_refused_even_if_listed() {
+        let list = allow(&[
+            "http://169.254.169.254",
+            "http://127.0.0.1",
+            "http://[::1]",
+            "http://10.0.0.7:9000",
+        ]);
+        assert!(list
+            .check("http://169.254.169.254/latest/meta-data/")
+            .is_err());
+        assert!(list.check("http://127.0.0.1/").is_err());
+        assert!(list.check("http://[::1]/").is_err());
+        assert!(list.check("http://10.0.0.7:9000/x").is_err());
+    }
+
+    #[test]
+    fn the_resolver_refuses_internal_addresses() {
+        assert!(resolve_public("127.0.0.1:80").is_err());
+        assert!(resolve_public("[::1]:443").is_err());
+        assert!(resolve_public("169.254.169.254:80").is_err());
+        assert_eq!(resolve_public("93.184.215.14:443").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn bad_prefixes_are_refused() {
+        assert!(Allowlist::new(vec!["/etc".to_string()]).is_err());
+        assert!(Allowlist::new(vec!["s3://".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! Numbers in text formats: formatting for the exporters (ASCII STL, OBJ)
+//! and `--lenient` clean-up for OBJ files other tools wrote.
+//!
+//! Coordinates go through `ryu`, which writes the shortest text that reads
+//! back as the same float and is a lot quicker than `{}` in the write loops.
+//! `--ascii-precision N` rounds to N decimal places first, which makes the
+//! shortest text short indeed: a scan in millimetres rarely needs more
+//! than 3 or 4.
+//!
+//! Rust's formatting never looks at the locale, so what we write always
+//! has a `.` for the decimal point whatever machine it runs on. Other tools
+//! aren't so careful: a German locale gets you `v 1,5 0,25 3`. By default
+//! such a file is refused with a hint; with `--lenient` vertex lines are
+//! tidied up before parsing: decimal commas become points, Fortran-style
+//! `1.5D+03` exponents become `e`, and odd Unicode spaces become plain ones.
+//! Exponents, a leading `+` and extra whitespace are fine either way.
+//!
+//! The precision comes with the rest of the writers' `WriteOptions` (see
+//! `output`) and the leniency with the loaders' `InputLimits`, so each
+//! caller says what it wants.
+
+use std::borrow::Cow;
+use std::io::{self, Write};
+
+/// The most decimal places worth asking for: past this an f32 has nothing left.
+pub const MAX_PRECISION: u32 = 9;
+
+/// Tidy the numbers on an OBJ file's vertex lines (`v`, `vn`, `vt`, `vp`)
+/// so a strict float parser takes them. Everything else is left alone.
+pub fn normalize_obj(text: &str) -> Cow<'_, str> {
+    let needs_work = |line: &str| {
+        is_vertex_line(line)
+            && line
+                .chars()
+                .any(|c| c == ',' || c == 'd' || c == 'D' || (c.is_whitespace() && !c.is_ascii()))
+    };
+    if !text.lines().any(needs_work) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if !needs_work(line) {
+            out.push_str(line);
+            continue;
+        }
+        let (content, ending) = match line.find(['\r', '\n']) {
+            Some(at) => line.split_at(at),
+            None => (line, ""),
+        };
+        let mut tokens = content.split(char::is_whitespace).filter(|t| !t.is_empty());
+        out.push_str(tokens.next().unwrap_or_default());
+        for token in tokens {
+            out.push(' ');
+            out.push_str(&normalize_number(token));
+        }
+        out.push_str(ending);
+    }
+    Cow::Owned(out)
+}
+
+fn is_vertex_line(line: &str) -> bool {
+    let keyword = line.trim_start().split(char::is_whitespace).next();
+    matches!(keyword, Some("v" | "vn" | "vt" | "vp"))
+}
+
+// One coordinate: "1,5" -> "1.5", "1.5D+03" -> "1.5e+03". Anything that
+// still isn't a number is passed through for the parser to complain about.
+fn normalize_number(token: &str) -> Cow<'_, str> {
+    let mut fixed = token.to_string();
+    // A single comma and no point can only be a decimal comma; "1,000.5"
+    // (thousands separators) we don't try to guess at
+    if fixed.matches(',').count() == 1 && !fixed.contains('.') {
+        fixed = fixed.replace(',', ".");
+    }
+    if let Some(at) = fixed.find(['d', 'D']) {
+        let before = fixed[..at].chars().last();
+        let after = fixed[at + 1..].chars().next();
+        if before.is_some_and(|c| c.is_ascii_digit() || c == '.')
+            && after.is_some_and(|c| c.is_ascii_digit() || c == '+' || c == '-')
+        {
+            fixed.replace_range(at..at + 1, "e");
+        }
+    }
+    if fixed == token {
+        Cow::Borrowed(token)
+    } else {
+        Cow::Owned(fixed)
+    }
+}
+
+/// Write `prefix`, then each value after a space, then a newline:
+/// `vertex 1 2.5 -0.125`. Values are rounded to `precision` decimal
+/// places if given.
+pub fn write_line(
+    out: &mut impl Write,
+    prefix: &str,
+    values: &[f32],
+    precision: Option<u32>,
+) -> io::Result<()> {
+    let scale = precision.map(|decimals| 10f64.powi(decimals as i32));
+    let mut buffer = ryu::Buffer::new();
+    out.write_all(prefix.as_bytes())?;
+    for &value in values {
+        let value = match scale {
+            Some(scale) if value.is_finite() => {
+                let rounded = ((f64::from(value) * scale).round() / scale) as f32;
+                // Keep "-0" out of the file
+                if rounded == 0.0 {
+                    0.0
+                } else {
+                    rounded
+                }
+            }
+            _ => value,
+        };
+        let text = buffer.format(value);
+        // "1.0" -> "1", like `{}` used to write
+        let text = text.strip_suffix(".0").unwrap_or(text);
+        out.write_all(b" ")?;
+        out.write_all(text.as_bytes())?;
+    }
+    out.write_all(b"\n")
+}
@@ -0,0 +1,609 @@
+//! Mesh auditing: what's wrong with this scan?
+//!
+//! Every kind of problem has a stable code, like a compiler lint, so that
+//! downstream tools can filter on them and users can silence the ones they
+//! don't care about with `--allow W007` (or `--allow inverted-normals`).
+//! Codes are never renumbered or reused; new checks get new numbers.
+//!
+//! | code | name                | what it means                                   |
+//! |------|---------------------|-------------------------------------------------|
+//! | W001 | non-manifold-edge   | an edge is shared by more than two faces        |
+//! | W002 | open-boundary       | edges with only one face: holes, not watertight |
+//! | W003 | degenerate-face     | faces with a repeated corner or no area         |
+//! | W004 | duplicate-vertex    | vertices at exactly the same position           |
+//! | W005 | unreferenced-vertex | vertices no face uses (stray points)            |
+//! | W006 | high-poly-count     | too many faces to be web safe                   |
+//! | W007 | inverted-normals    | faces wound against their neighbours            |
+//! | W008 | duplicate-face      | the same three corners used by two faces        |
+//! | W009 | empty-remesh        | remeshing produced no surface at all            |
+//! | W010 | zero-volume         | a closed shell that encloses nothing            |
+//! | W011 | implausible-scale   | too big to be real: probably the wrong units    |
+//! | W012 | thin-walls          | walls under two voxels thick nearly everywhere  |
+//! | W013 | coarse-resolution   | voxels too big for the finest detail            |
+
+use crate::completeness::Completeness;
+use crate::mesh::Mesh;
+use crate::messages;
+use crate::placement::{self, Placement};
+use crate::sandbox;
+use crate::sanity;
+use crate::symmetry::Symmetry;
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::str::FromStr;
+
+// Same rule of thumb as the old auditor: past this it won't be web safe
+const HEAVY_FACE_COUNT: usize = 100_000;
+
+/// A kind of finding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Code {
+    NonManifoldEdge,
+    OpenBoundary,
+    DegenerateFace,
+    DuplicateVertex,
+    UnreferencedVertex,
+    HighPolyCount,
+    InvertedNormals,
+    DuplicateFace,
+    EmptyRemesh,
+    ZeroVolume,
+    ImplausibleScale,
+    ThinWalls,
+    CoarseResolution,
+}
+
+impl Code {
+    pub const ALL: &'static [Code] = &[
+        Code::NonManifoldEdge,
+        Code::OpenBoundary,
+        Code::DegenerateFace,
+        Code::DuplicateVertex,
+        Code::UnreferencedVertex,
+        Code::HighPolyCount,
+        Code::InvertedNormals,
+        Code::DuplicateFace,
+        Code::EmptyRemesh,
+        Code::ZeroVolume,
+        Code::ImplausibleScale,
+        Code::ThinWalls,
+        Code::CoarseResolution,
+    ];
+
+    /// The stable code, e.g. `W001`.
+    pub fn id(self) -> &'static str {
+        match self {
+            Code::NonManifoldEdge => "W001",
+            Code::OpenBoundary => "W002",
+            Code::DegenerateFace => "W003",
+            Code::DuplicateVertex => "W004",
+            Code::UnreferencedVertex => "W005",
+            Code::HighPolyCount => "W006",
+            Code::InvertedNormals => "W007",
+            Code::DuplicateFace => "W008",
+            Code::EmptyRemesh => "W009",
+            Code::ZeroVolume => "W010",
+            Code::ImplausibleScale => "W011",
+            Code::ThinWalls => "W012",
+            Code::CoarseResolution => "W013",
+        }
+    }
+
+    /// The readable name, e.g. `non-manifold-edge`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Code::NonManifoldEdge => "non-manifold-edge",
+            Code::OpenBoundary => "open-boundary",
+            Code::DegenerateFace => "degenerate-face",
+            Code::DuplicateVertex => "duplicate-vertex",
+            Code::UnreferencedVertex => "unreferenced-vertex",
+            Code::HighPolyCount => "high-poly-count",
+            Code::InvertedNormals => "inverted-normals",
+            Code::DuplicateFace => "duplicate-face",
+            Code::EmptyRemesh => "empty-remesh",
+            Code::ZeroVolume => "zero-volume",
+            Code::ImplausibleScale => "implausible-scale",
+            Code::ThinWalls => "thin-walls",
+            Code::CoarseResolution => "coarse-resolution",
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}", self.id(), self.name())
+    }
+}
+
+impl FromStr for Code {
+    type Err = String;
+
+    /// Accepts either the code or the name, any case.
+    fn from_str(s: &str) -> Result<Self, String> {
+        Code::ALL
+            .iter()
+            .copied()
+            .find(|c| c.id().eq_ignore_ascii_case(s) || c.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown warning code '{}'", s))
+    }
+}
+
+impl Serialize for Code {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.id())
+    }
+}
+
+/// One problem, and where it is.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub code: Code,
+    pub name: &'static str,
+    pub message: String,
+    /// How many edges / faces / vertices are affected.
+    pub count: usize,
+    /// Affected faces, where the check is about faces or edges.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub faces: Vec<usize>,
+    /// Affected vertices, where the check is about vertices.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub vertices: Vec<usize>,
+}
+
+impl Finding {
+    pub fn new(code: Code, count: usize, message: String) -> Self {
+        Finding {
+            code,
+            name: code.name(),
+            message,
+            count,
+            faces: Vec::new(),
+            vertices: Vec::new(),
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.message)
+    }
+}
+
+/// Everything the audit found out about a mesh.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditReport {
+    pub vertices: usize,
+    pub faces: usize,
+    pub bounds: ([f32; 3], [f32; 3]),
+    /// Closed and manifold: every edge has exactly two faces.
+    pub watertight: bool,
+    /// Enclosed by the closed shells, in cubic model units; open shells
+    /// don't count.
+    pub volume: f64,
+    /// The file carried per-vertex colours.
+    pub coloured: bool,
+    pub findings: Vec<Finding>,
+    /// Findings dropped by `--allow`.
+    pub allowed: usize,
+    /// Which way up it probably goes, and in what units.
+    pub placement: Placement,
+    /// Only when asked for (`--symmetry`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub symmetry: Option<Symmetry>,
+    /// Only when asked for (`--completeness`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completeness: Option<Completeness>,
+    /// The connected pieces, the most faces first, each with its own
+    /// counts: which of several parts in one file is the broken one.
+    pub shells: Vec<Shell>,
+}
+
+/// The topology counts for one connected piece of a mesh. Vertices no face
+/// uses belong to no shell; they are only counted for the whole mesh.
+#[derive(Debug, Clone, Serialize)]
+pub struct Shell {
+    pub faces: usize,
+    /// Edges with one face, on this shell.
+    pub open_edges: usize,
+    /// Edges with three or more faces, one of which is this shell's. Such
+    /// an edge joins shells rather than belonging to one, so it counts for
+    /// each shell with a face on it.
+    pub non_manifold_edges: usize,
+    pub degenerate_faces: usize,
+    pub duplicate_faces: usize,
+    /// Closed and manifold.
+    pub watertight: bool,
+    /// Enclosed volume; 0 unless watertight.
+    pub volume: f64,
+}
+
+impl AuditReport {
+    /// The bounding box's corners, for people.
+    pub fn describe_bounds(&self) -> String {
+        let corner = |p: [f32; 3]| format!("({:.3}, {:.3}, {:.3})", p[0], p[1], p[2]);
+        messages::text(
+            "audit.bounds_range",
+            &[
+                ("from", &corner(self.bounds.0)),
+                ("to", &corner(self.bounds.1)),
+            ],
+        )
+    }
+
+    /// Drop the findings the user has said they don't care about.
+    pub fn allow(&mut self, allowed: &[Code]) {
+        let before = self.findings.len();
+        self.findings.retain(|f| !allowed.contains(&f.code));
+        self.allowed += before - self.findings.len();
+    }
+}
+
+/// Run every check on `mesh`.
+pub fn audit(mesh: &Mesh) -> AuditReport {
+    let mut findings = Vec::new();
+    let edges = edge_faces(mesh);
+
+    // 1. Edge topology: non-manifold and boundary edges
+    let mut non_manifold = Vec::new();
+    let mut boundary = Vec::new();
+    for (&edge, faces) in &edges {
+        match faces.len() {
+            1 => boundary.push((edge, faces[0].0)),
+            2 => {}
+            _ => non_manifold.push((edge, faces.iter().map(|&(f, _)| f).collect::<Vec<_>>())),
+        }
+    }
+    if !non_manifold.is_empty() {
+        let mut finding = Finding::new(
+            Code::NonManifoldEdge,
+            non_manifold.len(),
+            messages::text("findings.W001", &[("count", &non_manifold.len())]),
+        );
+        finding.faces = sorted_unique(non_manifold.iter().flat_map(|(_, f)| f.iter().copied()));
+        findings.push(finding);
+    }
+    if !boundary.is_empty() {
+        let mut finding = Finding::new(
+            Code::OpenBoundary,
+            boundary.len(),
+            messages::text("findings.W002", &[("count", &boundary.len())]),
+        );
+        finding.faces = sorted_unique(boundary.iter().map(|&(_, f)| f));
+        findings.push(finding);
+    }
+    let watertight = mesh.face_count() > 0 && boundary.is_empty() && non_manifold.is_empty();
+
+    // 2. Faces: degenerate and duplicated
+    let (min, max) = mesh.bounds();
+    let diagonal_sq: f32 = (0..3).map(|a| (max[a] - min[a]).powi(2)).sum();
+    let tiny_area = diagonal_sq * 1e-12;
+    let mut degenerate = Vec::new();
+    let mut seen_faces = HashSet::new();
+    let mut duplicate_faces = Vec::new();
+    for f in 0..mesh.face_count() {
+        let [a, b, c] = mesh.face(f);
+        if a == b || b == c || a == c || face_area_sq(mesh, f) <= tiny_area * tiny_area {
+            degenerate.push(f);
+        }
+        let mut key = [a, b, c];
+        key.sort_unstable();
+        if !seen_faces.insert(key) {
+            duplicate_faces.push(f);
+        }
+    }
+    if !degenerate.is_empty() {
+        let mut finding = Finding::new(
+            Code::DegenerateFace,
+            degenerate.len(),
+            messages::text("findings.W003", &[("count", &degenerate.len())]),
+        );
+        finding.faces = degenerate.clone();
+        findings.push(finding);
+    }
+    if !duplicate_faces.is_empty() {
+        let mut finding = Finding::new(
+            Code::DuplicateFace,
+            duplicate_faces.len(),
+            messages::text("findings.W008", &[("count", &duplicate_faces.len())]),
+        );
+        finding.faces = duplicate_faces.clone();
+        findings.push(finding);
+    }
+
+    // 3. Vertices: coincident copies and strays
+    sandbox::checkpoint();
+    let mut first_at = HashMap::new();
+    let mut duplicates = Vec::new();
+    for v in 0..mesh.vertex_count() {
+        let key = mesh.vertex(v).map(f32::to_bits);
+        if first_at.insert(key, v).is_some() {
+            duplicates.push(v);
+        }
+    }
+    if !duplicates.is_empty() {
+        let mut finding = Finding::new(
+            Code::DuplicateVertex,
+            duplicates.len(),
+            messages::text("findings.W004", &[("count", &duplicates.len())]),
+        );
+        finding.vertices = duplicates;
+        findings.push(finding);
+    }
+    let mut used = vec![false; mesh.vertex_count()];
+    for &i in &mesh.indices {
+        used[i as usize] = true;
+    }
+    let unused: Vec<usize> = (0..used.len()).filter(|&v| !used[v]).collect();
+    if !unused.is_empty() {
+        let mut finding = Finding::new(
+            Code::UnreferencedVertex,
+            unused.len(),
+            messages::text("findings.W005", &[("count", &unused.len())]),
+        );
+        finding.vertices = unused;
+        findings.push(finding);
+    }
+
+    // 4. Size
+    if mesh.face_count() > HEAVY_FACE_COUNT {
+        findings.push(Finding::new(
+            Code::HighPolyCount,
+            mesh.face_count(),
+            messages::text("findings.W006", &[("count", &mesh.face_count())]),
+        ));
+    }
+
+    // 5. Orientation, and what the closed shells hold
+    let surfaces = surfaces_with(mesh, &edges);
+    let volume = surfaces
+        .iter()
+        .filter(|s| s.closed)
+        .fold(0.0, |total, s| total + s.volume.abs());
+    let shells = shells(
+        &surfaces,
+        mesh.face_count(),
+        &non_manifold,
+        &boundary,
+        &degenerate,
+        &duplicate_faces,
+    );
+    let inverted = inverted_faces(surfaces);
+    if !inverted.is_empty() {
+        let mut finding = Finding::new(
+            Code::InvertedNormals,
+            inverted.len(),
+            messages::text("findings.W007", &[("count", &inverted.len())]),
+        );
+        finding.faces = inverted;
+        findings.push(finding);
+    }
+
+    // 6. Plausibility: flat shells and the wrong units
+    sandbox::checkpoint();
+    let placement = placement::propose(mesh);
+    findings.extend(sanity::plausibility(mesh, &placement));
+
+    findings.sort_by_key(|f| f.code);
+    AuditReport {
+        vertices: mesh.vertex_count(),
+        faces: mesh.face_count(),
+        bounds: (min, max),
+        watertight,
+        volume,
+        coloured: mesh.has_colours(),
+        findings,
+        allowed: 0,
+        placement,
+        symmetry: None,
+        completeness: None,
+        shells,
+    }
+}
+
+// Tally the edge and face problems by the surface they are on
+fn shells(
+    surfaces: &[Surface],
+    face_count: usize,
+    non_manifold: &[((usize, usize), Vec<usize>)],
+    boundary: &[((usize, usize), usize)],
+    degenerate: &[usize],
+    duplicate_faces: &[usize],
+) -> Vec<Shell> {
+    let mut shell_of = vec![0; face_count];
+    let mut shells: Vec<Shell> = surfaces
+        .iter()
+        .enumerate()
+        .map(|(s, surface)| {
+            for &f in surface.even.iter().chain(&surface.odd) {
+                shell_of[f] = s;
+            }
+            Shell {
+                faces: surface.even.len() + surface.odd.len(),
+                open_edges: 0,
+                non_manifold_edges: 0,
+                degenerate_faces: 0,
+                duplicate_faces: 0,
+                watertight: false,
+                volume: 0.0,
+            }
+        })
+        .collect();
+    for (_, faces) in non_manifold {
+        let mut touched: Vec<usize> = faces.iter().map(|&f| shell_of[f]).collect();
+        touched.sort_unstable();
+        touched.dedup();
+        for s in touched {
+            shells[s].non_manifold_edges += 1;
+        }
+    }
+    for &(_, f) in boundary {
+        shells[shell_of[f]].open_edges += 1;
+    }
+    for &f in degenerate {
+        shells[shell_of[f]].degenerate_faces += 1;
+    }
+    for &f in duplicate_faces {
+        shells[shell_of[f]].duplicate_faces += 1;
+    }
+    for (shell, surface) in shells.iter_mut().zip(surfaces) {
+        shell.watertight = shell.open_edges == 0 && shell.non_manifold_edges == 0;
+        if shell.watertight && surface.closed {
+            shell.volume = surface.volume.abs();
+        }
+    }
+    shells.sort_by_key(|s| std::cmp::Reverse(s.faces));
+    shells
+}
+
+/// Undirected edge (low, high) -> the faces using it, and whether each one
+/// walks it low -> high.
+pub type EdgeMap = HashMap<(usize, usize), Vec<(usize, bool)>>;
+
+pub fn edge_faces(mesh: &Mesh) -> EdgeMap {
+    let mut edges: EdgeMap = HashMap::new();
+    for f in 0..mesh.face_count() {
+        let [a, b, c] = mesh.face(f);
+        for (p, q) in [(a, b), (b, c), (c, a)] {
+            if p == q {
+                continue;
+            }
+            edges
+                .entry((p.min(q), p.max(q)))
+                .or_default()
+                .push((f, p < q));
+        }
+    }
+    edges
+}
+
+/// One connected piece of a mesh, with how its faces are wound.
+pub struct Surface {
+    /// Faces wound the same way as the first face of the surface.
+    pub even: Vec<usize>,
+    /// Faces wound the other way.
+    pub odd: Vec<usize>,
+    /// Every edge has exactly two faces.
+    pub closed: bool,
+    /// Enclosed volume with every face turned to agree with `even`.
+    /// Only meaningful when `closed`.
+    pub volume: f64,
+}
+
+/// Split `mesh` into connected surfaces (joined across manifold edges),
+/// working out which faces agree with their neighbours about which way
+/// round they go.
+pub fn surfaces(mesh: &Mesh) -> Vec<Surface> {
+    surfaces_with(mesh, &edge_faces(mesh))
+}
+
+fn surfaces_with(mesh: &Mesh, edges: &EdgeMap) -> Vec<Surface> {
+    let mut neighbours: Vec<Vec<(usize, bool)>> = vec![Vec::new(); mesh.face_count()];
+    for faces in edges.values() {
+        if let [(f, f_forward), (g, g_forward)] = faces[..] {
+            // Consistent neighbours walk their shared edge in opposite
+            // directions; if they agree, one of them is flipped
+            let flips = f_forward == g_forward;
+            neighbours[f].push((g, flips));
+            neighbours[g].push((f, flips));
+        }
+    }
+
+    let mut flipped: Vec<Option<bool>> = vec![None; mesh.face_count()];
+    let mut surfaces = Vec::new();
+    for start in 0..mesh.face_count() {
+        if flipped[start].is_some() {
+            continue;
+        }
+        sandbox::checkpoint();
+        // Flood the component, recording each face's flip relative to `start`
+        let mut component = vec![start];
+        let mut closed = true;
+        flipped[start] = Some(false);
+        let mut queue = VecDeque::from([start]);
+        while let Some(f) = queue.pop_front() {
+            if neighbours[f].len() < 3 {
+                closed = false;
+            }
+            for &(g, flips) in &neighbours[f] {
+                if flipped[g].is_none() {
+                    flipped[g] = Some(flipped[f].unwrap() != flips);
+                    component.push(g);
+                    queue.push_back(g);
+                }
+            }
+        }
+
+        let volume: f64 = component
+            .iter()
+            .map(|&f| {
+                let v = signed_volume(mesh, f);
+                if flipped[f] == Some(true) {
+                    -v
+                } else {
+                    v
+                }
+            })
+            .sum();
+        let (odd, even) = component
+            .into_iter()
+            .partition(|&f| flipped[f] == Some(true));
+        surfaces.push(Surface {
+            even,
+            odd,
+            closed,
+            volume,
+        });
+    }
+    surfaces
+}
+
+// Faces in the minority on their surface are the inverted ones; on a closed
+// surface the sign of the volume decides instead, so a mesh that is
+// consistently inside out is caught too.
+fn inverted_faces(surfaces: Vec<Surface>) -> Vec<usize> {
+    let mut inverted = Vec::new();
+    for surface in surfaces {
+        let wrong = if surface.closed {
+            if surface.volume < 0.0 {
+                surface.even
+            } else {
+                surface.odd
+            }
+        } else if surface.odd.len() <= surface.even.len() {
+            surface.odd
+        } else {
+            surface.even
+        };
+        inverted.extend(wrong);
+    }
+    inverted.sort_unstable();
+    inverted
+}
+
+fn face_area_sq(mesh: &Mesh, f: usize) -> f32 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i));
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]) / 4.0
+}
+
+// Signed volume of the tetrahedron a face makes with the origin
+fn signed_volume(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+    (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+        + a[2] * (b[0] * c[1] - b[1] * c[0]))
+        / 6.0
+}
+
+fn sorted_unique(items: impl Iterator<Item = usize>) -> Vec<usize> {
+    let mut items: Vec<usize> = items.collect();
+    items.sort_unstable();
+    items.dedup();
+    items
+}
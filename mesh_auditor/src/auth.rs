@@ -0,0 +1,213 @@
+//! API keys, rate limits and monthly quotas for `serve`.
+//!
+//! Keys are read from a TOML file (`--api-keys`), or failing that from the
+//! `MESH_API_KEYS` environment variable holding the same TOML:
+//!
+//! ```toml
+//! [[key]]
+//! name = "scanning"              # who this is; recorded on their jobs
+//! key = "s3cret"                 # what clients send
+//! requests_per_minute = 60       # optional
+//! monthly_jobs = 1000            # optional
+//! monthly_triangles = 50000000   # optional; counts triangles in results
+//! ```
+//!
+//! Clients send their key as `Authorization: Bearer <key>` or in an
+//! `X-API-Key` header. Each key only sees its own jobs. With no keys
+//! configured the server stays open to everyone, as before.
+//!
+//! Usage is worked out from the job records themselves (jobs submitted
+//! this calendar month, UTC), so it survives restarts without any extra
+//! bookkeeping. The triangle quota is checked when a job is submitted, so
+//! the job that crosses the line still finishes.
+
+use crate::clock::{self, UtcTime};
+use crate::jobs::{Job, JobStatus};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyFile {
+    #[serde(default, rename = "key")]
+    keys: Vec<ApiKey>,
+}
+
+/// One tenant.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApiKey {
+    pub name: String,
+    key: String,
+    pub requests_per_minute: Option<u32>,
+    pub monthly_jobs: Option<u64>,
+    pub monthly_triangles: Option<u64>,
+}
+
+/// A key's consumption for one month.
+#[derive(Debug, Serialize)]
+pub struct Usage {
+    pub name: String,
+    /// `YYYY-MM`, UTC.
+    pub month: String,
+    pub jobs: u64,
+    pub monthly_jobs: Option<u64>,
+    pub triangles: u64,
+    pub monthly_triangles: Option<u64>,
+}
+
+impl Usage {
+    /// Add up what `key` has used this month, from the job records.
+    pub fn this_month(key: &ApiKey, jobs: &[Job]) -> Self {
+        let now = UtcTime::from_unix(clock::unix_now());
+        let mut usage = Usage {
+            name: key.name.clone(),
+            month: format!("{:04}-{:02}", now.year, now.month),
+            jobs: 0,
+            monthly_jobs: key.monthly_jobs,
+            triangles: 0,
+            monthly_triangles: key.monthly_triangles,
+        };
+        for job in jobs {
+            let submitted = UtcTime::from_unix(job.submitted_at);
+            if job.owner.as_deref() != Some(key.name.as_str())
+                || (submitted.year, submitted.month) != (now.year, now.month)
+            {
+                continue;
+            }
+            // Jobs cancelled before they ran cost nothing
+            if job.status == JobStatus::Cancelled && job.started_at.is_none() {
+                continue;
+            }
+            usage.jobs += 1;
+            usage.triangles += job.triangles.unwrap_or(0) as u64;
+        }
+        usage
+    }
+
+    /// Why another job would go over quota, if it would.
+    pub fn exceeded(&self) -> Option<String> {
+        if let Some(limit) = self.monthly_jobs.filter(|&limit| self.jobs >= limit) {
+            return Some(format!("monthly job quota of {} used up", limit));
+        }
+        if let Some(limit) = self
+            .monthly_triangles
+            .filter(|&limit| self.triangles >= limit)
+        {
+            return Some(format!("monthly triangle quota of {} used up", limit));
+        }
+        None
+    }
+}
+
+pub struct Auth {
+    keys: Vec<ApiKey>,
+    // Token buckets for rate limiting, by key name
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    last: Instant,
+}
+
+impl Auth {
+    /// Load keys from `path`, or from `MESH_API_KEYS` if there is no file.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let (text, source) = match path {
+            Some(path) => (
+                std::fs::read_to_string(path)
+                    .with_context(|| format!("could not read {}", path.display()))?,
+                path.display().to_string(),
+            ),
+            None => match std::env::var("MESH_API_KEYS") {
+                Ok(text) => (text, "MESH_API_KEYS".to_string()),
+                Err(_) => (String::new(), String::new()),
+            },
+        };
+        let file: KeyFile =
+            toml::from_str(&text).with_context(|| format!("bad API key config in {}", source))?;
+
+        let mut names = HashSet::new();
+        let mut secrets = HashSet::new();
+        for key in &file.keys {
+            if key.name.is_empty() || key.key.is_empty() {
+                bail!("every API key needs a name and a key");
+            }
+            if !names.insert(&key.name) {
+                bail!("API key name '{}' is used twice", key.name);
+            }
+            if !secrets.insert(&key.key) {
+                bail!(
+                    "two API keys have the same secret ('{}' and another)",
+                    key.name
+                );
+            }
+            if key.requests_per_minute == Some(0) {
+                bail!("API key '{}' has a rate limit of 0", key.name);
+            }
+        }
+        Ok(Auth {
+            keys: file.keys,
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// No keys configured: anyone may use the server.
+    pub fn is_open(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn key_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// The key whose secret is `secret`.
+    pub fn authenticate(&self, secret: &str) -> Option<&ApiKey> {
+        // Check every key, comparing in constant time, so timing doesn't
+        // give away how much of a guess was right
+        let mut found = None;
+        for key in &self.keys {
+            if constant_time_eq(key.key.as_bytes(), secret.as_bytes()) {
+                found = Some(key);
+            }
+        }
+        found
+    }
+
+    /// Take one request from `key`'s allowance. On refusal, returns how many
+    /// seconds until the next request would be allowed.
+    pub fn check_rate(&self, key: &ApiKey) -> std::result::Result<(), u64> {
+        let Some(per_minute) = key.requests_per_minute else {
+            return Ok(());
+        };
+        let capacity = f64::from(per_minute);
+        let per_second = capacity / 60.0;
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(key.name.clone()).or_insert(Bucket {
+            tokens: capacity,
+            last: Instant::now(),
+        });
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(capacity);
+        bucket.last = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(((1.0 - bucket.tokens) / per_second).ceil() as u64)
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
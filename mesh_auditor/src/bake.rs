@@ -0,0 +1,331 @@
+//! Map baking: the detail of a high-poly scan, painted onto the UVs of a
+//! low-poly version of it.
+//!
+//! Every texel a low-poly face covers in UV space stands for a point on
+//! that face. From there we cast a ray both ways along the (interpolated)
+//! low-poly normal, out to the cast distance, and take the nearest place
+//! it meets the scan. Two maps come out of the one cast:
+//!
+//! - normals: the scan's smooth normal there, written in the tangent frame
+//!   of the low-poly surface (x along +u, y along +v, z out) as
+//!   n × 0.5 + 0.5, the glTF convention;
+//! - displacement: how far along the normal the scan is, outward positive,
+//!   in mesh units.
+//!
+//! With a cage (see `cage`) there is one ray instead, from the cage in
+//! through the low-poly surface, so the first surface it meets is the
+//! outermost one.
+//!
+//! A texel whose rays miss gets the flat normal (0, 0, 1) and no
+//! displacement. Texels just outside the charts are filled from their
+//! neighbours, so filtering and mipmaps at a seam don't pull in the
+//! background.
+
+use crate::bvh::Bvh;
+use crate::gltf::Primitive;
+use crate::mesh::Mesh;
+use crate::sandbox;
+use anyhow::Result;
+use image::{DynamicImage, ImageBuffer, ImageFormat, Luma, Rgb, RgbImage};
+use std::io::Cursor;
+
+/// How to bake.
+#[derive(Debug, Clone, Copy)]
+pub struct BakeOptions {
+    /// Pixels a side.
+    pub size: u32,
+    /// How far from the low-poly surface to look for the scan, in mesh
+    /// units.
+    pub max_distance: f32,
+    /// How many texels to grow each chart by.
+    pub padding: u32,
+}
+
+/// Formats a displacement map can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DisplacementFormat {
+    /// 16-bit greyscale: mid-grey is the surface, see the printed scale
+    Png,
+    /// 32-bit float OpenEXR: distances in mesh units, 0 at the surface
+    Exr,
+}
+
+/// What `bake` made.
+pub struct Baked {
+    pub size: u32,
+    // Per texel, row by row: normal and displacement (None outside charts)
+    maps: Vec<Option<[f32; 4]>>,
+    /// Texels inside a chart.
+    pub texels: usize,
+    /// Of those, the ones whose rays found the scan.
+    pub hits: usize,
+}
+
+/// Bake `high` onto `low`, which must have UVs, casting from `cage` (one
+/// point per vertex of `low`) if given.
+pub fn bake(
+    low: &Primitive,
+    high: &Mesh,
+    cage: Option<&[[f32; 3]]>,
+    options: &BakeOptions,
+) -> Baked {
+    let size = options.size.max(1);
+    let bvh = Bvh::new(high);
+    let high_normals = high.vertex_normals();
+    let mut maps = vec![None; (size * size) as usize];
+    let (mut texels, mut hits) = (0, 0);
+
+    // 1. Rasterize each face in UV space, texel centres only
+    for face in low.indices.chunks_exact(3) {
+        sandbox::checkpoint();
+        let corners = [face[0], face[1], face[2]].map(|i| i as usize);
+        let pixel = corners.map(|i| {
+            let [u, v] = low.texcoords[i];
+            [u * size as f32, (1.0 - v) * size as f32]
+        });
+        let area = edge(pixel[0], pixel[1], pixel[2]);
+        if area.abs() < 1e-12 {
+            continue;
+        }
+        let min_x = pixel.iter().map(|p| p[0]).fold(f32::MAX, f32::min);
+        let max_x = pixel.iter().map(|p| p[0]).fold(f32::MIN, f32::max);
+        let min_y = pixel.iter().map(|p| p[1]).fold(f32::MAX, f32::min);
+        let max_y = pixel.iter().map(|p| p[1]).fold(f32::MIN, f32::max);
+        let x_range = (min_x.floor().max(0.0) as u32)..(max_x.ceil().min(size as f32) as u32);
+        for y in (min_y.floor().max(0.0) as u32)..(max_y.ceil().min(size as f32) as u32) {
+            for x in x_range.clone() {
+                let centre = [x as f32 + 0.5, y as f32 + 0.5];
+                let weights = [
+                    edge(pixel[1], pixel[2], centre) / area,
+                    edge(pixel[2], pixel[0], centre) / area,
+                    edge(pixel[0], pixel[1], centre) / area,
+                ];
+                if weights.iter().any(|&w| w < -1e-4) {
+                    continue;
+                }
+                texels += 1;
+                let ray = Ray {
+                    corners,
+                    weights,
+                    cage,
+                };
+                let found = sample(low, &ray, high, &high_normals, &bvh, options);
+                if found.is_some() {
+                    hits += 1;
+                }
+                maps[(y * size + x) as usize] = Some(found.unwrap_or([0.0, 0.0, 1.0, 0.0]));
+            }
+        }
+    }
+
+    // 2. Grow the charts into the gaps
+    dilate(&mut maps, size, options.padding);
+    Baked {
+        size,
+        maps,
+        texels,
+        hits,
+    }
+}
+
+impl Baked {
+    /// The normal map, flat outside the charts.
+    pub fn normal_map(&self) -> RgbImage {
+        let mut image = RgbImage::new(self.size, self.size);
+        for (pixel, texel) in image.pixels_mut().zip(&self.maps) {
+            let n = texel
+                .and_then(|t| normalize([t[0], t[1], t[2]]))
+                .unwrap_or([0.0, 0.0, 1.0]);
+            *pixel = Rgb(n.map(|x| ((x * 0.5 + 0.5) * 255.0).round().clamp(0.0, 255.0) as u8));
+        }
+        image
+    }
+
+    /// The furthest the scan is from the low-poly surface, either way.
+    pub fn displacement_range(&self) -> f32 {
+        self.maps
+            .iter()
+            .flatten()
+            .map(|t| t[3].abs())
+            .fold(0.0, f32::max)
+    }
+
+    /// The displacement map, encoded. A PNG maps ±`displacement_range` to
+    /// black..white, so a renderer wants midlevel 0.5 and scale twice the
+    /// range; an EXR holds the distances themselves.
+    pub fn displacement_map(&self, format: DisplacementFormat) -> Result<Vec<u8>> {
+        let distances = self.maps.iter().map(|t| t.map_or(0.0, |t| t[3]));
+        let image = match format {
+            DisplacementFormat::Png => {
+                let range = self.displacement_range().max(f32::MIN_POSITIVE);
+                let values = distances
+                    .map(|d| {
+                        ((d / range * 0.5 + 0.5) * 65535.0)
+                            .round()
+                            .clamp(0.0, 65535.0) as u16
+                    })
+                    .collect();
+                DynamicImage::ImageLuma16(
+                    ImageBuffer::<Luma<u16>, _>::from_raw(self.size, self.size, values)
+                        .expect("one value per texel"),
+                )
+            }
+            // The EXR encoder only takes RGB(A): grey it is
+            DisplacementFormat::Exr => DynamicImage::ImageRgb32F(
+                ImageBuffer::from_raw(
+                    self.size,
+                    self.size,
+                    distances.flat_map(|d| [d; 3]).collect(),
+                )
+                .expect("three values per texel"),
+            ),
+        };
+        let mut bytes = Vec::new();
+        let format = match format {
+            DisplacementFormat::Png => ImageFormat::Png,
+            DisplacementFormat::Exr => ImageFormat::OpenExr,
+        };
+        image.write_to(&mut Cursor::new(&mut bytes), format)?;
+        Ok(bytes)
+    }
+}
+
+// One texel's point on a low-poly face, and the cage if there is one
+struct Ray<'a> {
+    corners: [usize; 3],
+    weights: [f32; 3],
+    cage: Option<&'a [[f32; 3]]>,
+}
+
+// The scan seen from one point of the low-poly surface: its normal in the
+// surface's tangent frame, and its distance along the surface normal
+fn sample(
+    low: &Primitive,
+    ray: &Ray,
+    high: &Mesh,
+    high_normals: &[[f32; 3]],
+    bvh: &Bvh,
+    options: &BakeOptions,
+) -> Option<[f32; 4]> {
+    let Ray {
+        corners, weights, ..
+    } = *ray;
+    let blend = |value: &dyn Fn(usize) -> [f32; 3]| {
+        let mut out = [0.0; 3];
+        for (&i, &w) in corners.iter().zip(&weights) {
+            let v = value(i);
+            for k in 0..3 {
+                out[k] += v[k] * w;
+            }
+        }
+        out
+    };
+    let point = blend(&|i| low.positions[i]);
+    let normal = normalize(blend(&|i| low.normals[i]))?;
+    let along_u = blend(&|i| {
+        let t = low.tangents[i];
+        [t[0], t[1], t[2]]
+    });
+    let handedness: f32 = corners
+        .iter()
+        .zip(&weights)
+        .map(|(&i, &w)| low.tangents[i][3] * w)
+        .sum();
+    // Re-square the frame: interpolation bends it
+    let d = dot(along_u, normal);
+    let tangent = normalize([0, 1, 2].map(|k| along_u[k] - normal[k] * d))?;
+    let bitangent = cross(normal, tangent).map(|x| x * handedness.signum());
+
+    // From the cage in, or else the nearest hit out or in
+    let from_cage = ray.cage.and_then(|cage| {
+        let start = blend(&|i| cage[i]);
+        let towards = [0, 1, 2].map(|k| point[k] - start[k]);
+        let height = dot(towards, towards).sqrt();
+        let direction = normalize(towards)?;
+        let hit = bvh.cast(start, direction, height + options.max_distance)?;
+        // Along the cage ray rather than the normal, which is near enough
+        Some((hit, height - hit.t))
+    });
+    let (hit, distance) = match from_cage {
+        Some(found) => found,
+        None if ray.cage.is_some() => return None,
+        None => {
+            let outward = bvh.cast(point, normal, options.max_distance);
+            let inward = bvh.cast(point, normal.map(|x| -x), options.max_distance);
+            match (outward, inward) {
+                (Some(a), Some(b)) if b.t < a.t => (b, -b.t),
+                (Some(a), _) => (a, a.t),
+                (None, Some(b)) => (b, -b.t),
+                (None, None) => return None,
+            }
+        }
+    };
+    let [a, b, c] = high.face(hit.face).map(|i| high_normals[i]);
+    let w = 1.0 - hit.u - hit.v;
+    let found = normalize([0, 1, 2].map(|k| a[k] * w + b[k] * hit.u + c[k] * hit.v))?;
+    let [x, y, z] = normalize([
+        dot(found, tangent),
+        dot(found, bitangent),
+        dot(found, normal),
+    ])?;
+    Some([x, y, z, distance])
+}
+
+// Give uncovered texels next to covered ones their neighbours' average,
+// `passes` times over
+fn dilate(maps: &mut [Option<[f32; 4]>], size: u32, passes: u32) {
+    let size = size as usize;
+    for _ in 0..passes {
+        let mut grown = Vec::new();
+        for y in 0..size {
+            for x in 0..size {
+                if maps[y * size + x].is_some() {
+                    continue;
+                }
+                let mut sum = [0.0f32; 4];
+                let mut count = 0;
+                for ny in y.saturating_sub(1)..(y + 2).min(size) {
+                    for nx in x.saturating_sub(1)..(x + 2).min(size) {
+                        if let Some(t) = maps[ny * size + nx] {
+                            for k in 0..4 {
+                                sum[k] += t[k];
+                            }
+                            count += 1;
+                        }
+                    }
+                }
+                if count > 0 {
+                    grown.push((y * size + x, sum.map(|s| s / count as f32)));
+                }
+            }
+        }
+        if grown.is_empty() {
+            break;
+        }
+        for (i, texel) in grown {
+            maps[i] = Some(texel);
+        }
+    }
+}
+
+// Twice the signed area of (a, b, c)
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> Option<[f32; 3]> {
+    let l = dot(a, a).sqrt();
+    (l > 1e-12).then(|| a.map(|x| x / l))
+}
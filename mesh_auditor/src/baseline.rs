@@ -0,0 +1,131 @@
+//! `audit --baseline`: compare an audit with a "golden" earlier one.
+//!
+//! For recurring scans of the same fixture the first good audit, saved
+//! with `audit --json`, becomes the baseline, and every later scan is
+//! checked against it. What counts as a regression: the mesh is no longer
+//! watertight, a finding appears that wasn't there or affects more than it
+//! did (more open boundary edges means new holes), the face count jumps or
+//! the enclosed volume drifts by more than the tolerances allow. Getting
+//! better is never a regression.
+
+use crate::audit::{AuditReport, Code};
+use crate::messages;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+
+/// The parts of a stored `audit --json` report that are compared.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Baseline {
+    pub faces: usize,
+    pub watertight: bool,
+    /// Missing from reports written before the volume was recorded.
+    #[serde(default)]
+    pub volume: Option<f64>,
+    pub findings: Vec<BaselineFinding>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BaselineFinding {
+    pub code: String,
+    pub count: usize,
+}
+
+/// How much the face count and volume may change, as fractions.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerances {
+    pub faces: f64,
+    pub volume: f64,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read baseline {}", path.display()))?;
+        serde_json::from_str(&text).with_context(|| {
+            format!(
+                "{} is not an audit report (save one with audit --json)",
+                path.display()
+            )
+        })
+    }
+
+    // Count for a code, 0 if it wasn't found then
+    fn count(&self, code: Code) -> usize {
+        self.findings
+            .iter()
+            .filter(|f| f.code.parse::<Code>() == Ok(code))
+            .map(|f| f.count)
+            .sum()
+    }
+}
+
+/// Everything that got worse since the baseline, for people.
+pub fn regressions(current: &AuditReport, baseline: &Baseline, tol: Tolerances) -> Vec<String> {
+    let mut found = Vec::new();
+
+    // 1. Watertightness lost
+    if baseline.watertight && !current.watertight {
+        found.push(messages::text("baseline.watertight", &[]));
+    }
+
+    // 2. Findings that are new or affect more than before
+    for finding in &current.findings {
+        let before = baseline.count(finding.code);
+        if finding.count <= before {
+            continue;
+        }
+        let key = match (finding.code, before) {
+            (Code::OpenBoundary, _) => "baseline.holes",
+            (_, 0) => "baseline.new",
+            _ => "baseline.more",
+        };
+        found.push(messages::text(
+            key,
+            &[
+                ("code", &finding.code),
+                ("count", &finding.count),
+                ("before", &before),
+            ],
+        ));
+    }
+
+    // 3. Face count and volume
+    let faces = relative_change(current.faces as f64, baseline.faces as f64);
+    if faces.abs() > tol.faces {
+        found.push(messages::text(
+            "baseline.faces",
+            &[
+                ("count", &current.faces),
+                ("before", &baseline.faces),
+                ("change", &format!("{:+.1}%", faces * 100.0)),
+            ],
+        ));
+    }
+    if let Some(before) = baseline.volume {
+        let volume = relative_change(current.volume, before);
+        if volume.abs() > tol.volume {
+            found.push(messages::text(
+                "baseline.volume",
+                &[
+                    ("volume", &format!("{:.4}", current.volume)),
+                    ("before", &format!("{:.4}", before)),
+                    ("change", &format!("{:+.1}%", volume * 100.0)),
+                ],
+            ));
+        }
+    }
+    found
+}
+
+fn relative_change(value: f64, before: f64) -> f64 {
+    if before == 0.0 {
+        if value == 0.0 {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        (value - before) / before.abs()
+    }
+}
@@ -0,0 +1,297 @@
+//! `batch`: remesh a whole pile of scans in one go.
+//!
+//! Files run one after another, each under its own job limits, so one bad
+//! scan fails on its own instead of taking the batch down with it. Anything
+//! that wants to show progress implements `BatchObserver`; `LogObserver`
+//! prints the usual scrolling log and `dashboard::Dashboard` draws a live
+//! terminal UI.
+
+use crate::audit::{self, Code, Finding};
+use crate::bench;
+use crate::dump::{self, Stage};
+use crate::extract::marching_cubes;
+use crate::limits::InputLimits;
+use crate::mesh::Mesh;
+use crate::messages;
+use crate::output::WriteOptions;
+use crate::profiles::ScanCorrections;
+use crate::remesh;
+use crate::samples::Storage;
+use crate::sandbox::{self, JobLimits};
+use crate::sanity;
+use crate::slabs::Slabs;
+use crate::stl::save_triangles_as_stl;
+use crate::storage;
+use anyhow::{Context, Result};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub struct BatchOptions {
+    /// Results go here, named after their inputs.
+    pub out_dir: PathBuf,
+    pub resolution: usize,
+    /// Done to each scan as it loads.
+    pub corrections: ScanCorrections,
+    pub storage: Storage,
+    /// How each field's sampling is split across threads.
+    pub slabs: Slabs,
+    /// How the results are written.
+    pub write: WriteOptions,
+    /// Warnings not to report.
+    pub allow: Vec<Code>,
+}
+
+/// What happened to one file.
+#[derive(Debug, Clone, Default)]
+pub struct FileReport {
+    pub input: PathBuf,
+    /// Set once the result has been written.
+    pub output: Option<PathBuf>,
+    pub input_vertices: usize,
+    pub input_faces: usize,
+    /// Of the input, once it has loaded.
+    pub bounds: Option<([f32; 3], [f32; 3])>,
+    pub watertight: Option<bool>,
+    pub output_triangles: usize,
+    pub seconds: f64,
+    pub warnings: Vec<Finding>,
+    pub error: Option<String>,
+}
+
+/// Hears about a batch as it runs. Every method defaults to doing nothing.
+pub trait BatchObserver {
+    fn batch_started(&self, _files: &[PathBuf]) {}
+    fn file_started(&self, _index: usize, _input: &Path) {}
+    fn stage(&self, _index: usize, _stage: &'static str) {}
+    fn warning(&self, _index: usize, _finding: &Finding) {}
+    fn file_finished(&self, _index: usize, _report: &FileReport) {}
+    fn batch_finished(&self, _reports: &[FileReport]) {}
+}
+
+/// The files to run: each input as given, or every mesh in it if it's a
+/// directory.
+pub fn input_files(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            files.extend(bench::corpus_files(input)?);
+        } else {
+            files.push(input.clone());
+        }
+    }
+    Ok(files)
+}
+
+pub fn run(
+    files: &[PathBuf],
+    options: &BatchOptions,
+    limits: &InputLimits,
+    job_limits: &JobLimits,
+    observer: &dyn BatchObserver,
+) -> Result<Vec<FileReport>> {
+    std::fs::create_dir_all(&options.out_dir).with_context(|| {
+        format!(
+            "could not create output directory {}",
+            options.out_dir.display()
+        )
+    })?;
+
+    observer.batch_started(files);
+    let mut reports = Vec::with_capacity(files.len());
+    for (index, input) in files.iter().enumerate() {
+        observer.file_started(index, input);
+        let start = Instant::now();
+        let mut report = FileReport {
+            input: input.clone(),
+            ..Default::default()
+        };
+        let outcome = sandbox::run_job(job_limits, || {
+            process(index, input, options, limits, observer, &mut report)
+        });
+        if let Err(e) = outcome {
+            report.error = Some(format!("{:#}", e));
+        }
+        report.seconds = start.elapsed().as_secs_f64();
+        observer.file_finished(index, &report);
+        reports.push(report);
+    }
+    observer.batch_finished(&reports);
+    Ok(reports)
+}
+
+// Remesh one file, filling in `report` as we go
+fn process(
+    index: usize,
+    input: &Path,
+    options: &BatchOptions,
+    limits: &InputLimits,
+    observer: &dyn BatchObserver,
+    report: &mut FileReport,
+) -> Result<()> {
+    let warn = |report: &mut FileReport, finding: Finding| {
+        if !options.allow.contains(&finding.code) {
+            observer.warning(index, &finding);
+            report.warnings.push(finding);
+        }
+    };
+
+    let stem = input.file_stem().unwrap_or(input.as_os_str());
+    dump::begin(&stem.to_string_lossy());
+    observer.stage(index, "load");
+    let mut mesh = Mesh::load(&input.to_string_lossy(), limits)?;
+    dump::mesh(Stage::Load, &mesh);
+    report.input_vertices = mesh.vertex_count();
+    report.input_faces = mesh.face_count();
+    options.corrections.apply(&mut mesh, options.slabs.threads);
+    let audit = audit::audit(&mesh);
+    report.bounds = Some(audit.bounds);
+    report.watertight = Some(audit.watertight);
+    for finding in audit.findings {
+        warn(report, finding);
+    }
+
+    observer.stage(index, "sample");
+    let field = remesh::sample_scan(
+        &mesh.positions,
+        options.resolution,
+        remesh::INFLUENCE,
+        options.storage,
+        &options.slabs,
+    );
+    dump::field(Stage::Sample, &field);
+    let voxel = field.spacing.iter().copied().fold(0.0f32, f32::max);
+    if let Some(finding) = sanity::resolution(&mesh, voxel, options.resolution) {
+        warn(report, finding);
+    }
+
+    observer.stage(index, "extract");
+    let triangles = marching_cubes(&field, field.iso);
+    dump::triangles(Stage::Extract, &triangles);
+    report.output_triangles = triangles.len() / 9;
+    if triangles.is_empty() {
+        warn(
+            report,
+            Finding::new(Code::EmptyRemesh, 0, messages::text("findings.W009", &[])),
+        );
+    }
+
+    observer.stage(index, "save");
+    let output = options.out_dir.join(stem).with_extension("stl");
+    save_triangles_as_stl(&triangles, &output.to_string_lossy(), &options.write)?;
+    report.output = Some(output);
+    Ok(())
+}
+
+/// The plain scrolling log, for pipes, CI logs and anyone without `--tui`.
+pub struct LogObserver;
+
+impl BatchObserver for LogObserver {
+    fn batch_started(&self, files: &[PathBuf]) {
+        println!("-----------------------------------------");
+        println!("🧬 BATCH REMESH: {} files", files.len());
+        println!("-----------------------------------------");
+    }
+
+    fn file_started(&self, index: usize, input: &Path) {
+        println!("\n▶️  File #{}: {}", index + 1, input.display());
+    }
+
+    fn warning(&self, _index: usize, finding: &Finding) {
+        println!("   ⚠️  {}", finding);
+    }
+
+    fn file_finished(&self, _index: usize, report: &FileReport) {
+        match (&report.error, &report.output) {
+            (Some(e), _) => println!("   ❌ Failed after {:.1}s: {}", report.seconds, e),
+            (None, Some(output)) => println!(
+                "   ✅ {} triangles in {:.1}s, saved to: {}",
+                report.output_triangles,
+                report.seconds,
+                output.display()
+            ),
+            (None, None) => {}
+        }
+    }
+}
+
+/// The closing summary, printed whichever observer ran.
+pub fn print_summary(reports: &[FileReport]) {
+    let failed = reports.iter().filter(|r| r.error.is_some()).count();
+    let warnings: usize = reports.iter().map(|r| r.warnings.len()).sum();
+    let seconds: f64 = reports.iter().map(|r| r.seconds).sum();
+    println!("\n-----------------------------------------");
+    println!("📊 BATCH REPORT");
+    println!("   Files: {}", reports.len());
+    println!("   Succeeded: {}", reports.len() - failed);
+    println!("   Failed: {}", failed);
+    println!("   Warnings: {}", warnings);
+    println!("   Total time: {:.1}s", seconds);
+    for report in reports {
+        if let Some(e) = &report.error {
+            println!("   ❌ {}: {}", report.input.display(), e);
+        }
+    }
+    println!("-----------------------------------------");
+}
+
+/// One row per file, for triaging a day's scans in a spreadsheet.
+pub fn write_csv(reports: &[FileReport], path: &str) -> Result<()> {
+    let mut out = storage::create(path)?;
+    writeln!(
+        out,
+        "input,status,vertices,faces,min_x,min_y,min_z,max_x,max_y,max_z,\
+         watertight,warnings,warning_codes,output_triangles,seconds,output,error"
+    )?;
+    for report in reports {
+        let bounds = match report.bounds {
+            Some((min, max)) => min
+                .iter()
+                .chain(&max)
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>(),
+            None => vec![String::new(); 6],
+        };
+        let mut codes: Vec<&str> = report.warnings.iter().map(|w| w.code.id()).collect();
+        codes.dedup();
+        let row = [
+            report.input.display().to_string(),
+            if report.error.is_some() {
+                "failed"
+            } else {
+                "ok"
+            }
+            .to_string(),
+            report.input_vertices.to_string(),
+            report.input_faces.to_string(),
+        ]
+        .into_iter()
+        .chain(bounds)
+        .chain([
+            report.watertight.map(|w| w.to_string()).unwrap_or_default(),
+            report.warnings.len().to_string(),
+            codes.join(";"),
+            report.output_triangles.to_string(),
+            format!("{:.3}", report.seconds),
+            report
+                .output
+                .as_ref()
+                .map(|o| o.display().to_string())
+                .unwrap_or_default(),
+            report.error.clone().unwrap_or_default(),
+        ])
+        .map(|field| csv_field(&field))
+        .collect::<Vec<_>>();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    out.finish()
+}
+
+// Quote a field if it has anything CSV treats specially
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,182 @@
+//! Benchmarking the pipeline over a corpus of meshes.
+//!
+//! Every file goes through load → sample → extract → save, and we record how long
+//! each stage took plus a few numbers describing the result. Saving those
+//! as a baseline lets a later run point out what got slower or changed.
+
+use crate::extract::{marching_cubes, soup_volume};
+use crate::limits::InputLimits;
+use crate::mesh::{Mesh, INPUT_EXTENSIONS};
+use crate::output::WriteOptions;
+use crate::pipeline::StageTimings;
+use crate::remesh;
+use crate::samples::Storage;
+use crate::slabs::Slabs;
+use crate::stl::save_triangles_as_stl;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Timings and quality metrics for one file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileResult {
+    pub file: String,
+    /// Seconds per stage.
+    pub stages: BTreeMap<String, f64>,
+    pub metrics: BTreeMap<String, f64>,
+}
+
+/// The results store: one run over a corpus.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub resolution: usize,
+    pub files: Vec<FileResult>,
+}
+
+impl BenchReport {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("could not read baseline {}", path.display()))?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// How far a value may move from the baseline before we call it out.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerances {
+    /// Allowed slowdown as a fraction (0.25 = 25% slower).
+    pub time: f64,
+    /// Allowed relative change in any quality metric, either direction.
+    pub metric: f64,
+}
+
+// Stages shorter than this are timer noise, not regressions
+const MIN_TIME_DELTA: f64 = 0.005;
+
+/// Run the pipeline over one mesh on `threads` threads, writing its result
+/// as `write` says.
+pub fn run_file(
+    path: &Path,
+    resolution: usize,
+    limits: &InputLimits,
+    write: &WriteOptions,
+    threads: usize,
+) -> Result<FileResult> {
+    let mut timings = StageTimings::default();
+    let filename = path.to_string_lossy();
+
+    let mesh = timings.time("load", || Mesh::load(&filename, limits))?;
+    let field = timings.time("sample", || {
+        remesh::sample_scan(
+            &mesh.positions,
+            resolution,
+            remesh::INFLUENCE,
+            Storage::Full,
+            &Slabs {
+                threads,
+                ..Slabs::default()
+            },
+        )
+    });
+    let triangles = timings.time("extract", || marching_cubes(&field, field.iso));
+    // Written somewhere scratch: only the time and size matter
+    let scratch = std::env::temp_dir().join(format!("mesh_bench_{}.stl", std::process::id()));
+    let scratch_name = scratch.to_string_lossy();
+    timings.time("save", || {
+        save_triangles_as_stl(&triangles, &scratch_name, write)
+    })?;
+    let output_bytes = std::fs::metadata(&scratch).map(|m| m.len()).unwrap_or(0);
+    let _ = std::fs::remove_file(&scratch);
+
+    let mut metrics = BTreeMap::new();
+    metrics.insert("input_vertices".to_string(), mesh.vertex_count() as f64);
+    metrics.insert("input_faces".to_string(), mesh.face_count() as f64);
+    metrics.insert("output_triangles".to_string(), (triangles.len() / 9) as f64);
+    metrics.insert("output_volume".to_string(), soup_volume(&triangles));
+    metrics.insert("output_bytes".to_string(), output_bytes as f64);
+
+    Ok(FileResult {
+        file: path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        stages: timings.stages.into_iter().collect(),
+        metrics,
+    })
+}
+
+/// Every mesh file in `dir` we know how to load, in a stable order.
+pub fn corpus_files(dir: &Path) -> Result<Vec<std::path::PathBuf>> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .with_context(|| format!("could not read corpus directory {}", dir.display()))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|p| {
+            p.extension().is_some_and(|ext| {
+                INPUT_EXTENSIONS
+                    .iter()
+                    .any(|known| ext.eq_ignore_ascii_case(known))
+            })
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Compare a run with the stored baseline, returning human readable
+/// descriptions of everything that regressed.
+pub fn regressions(current: &BenchReport, baseline: &BenchReport, tol: Tolerances) -> Vec<String> {
+    let mut found = Vec::new();
+    for result in &current.files {
+        let Some(base) = baseline.files.iter().find(|b| b.file == result.file) else {
+            continue;
+        };
+        for (stage, &secs) in &result.stages {
+            if let Some(&before) = base.stages.get(stage) {
+                if secs > before * (1.0 + tol.time) && secs - before > MIN_TIME_DELTA {
+                    found.push(format!(
+                        "{}: stage '{}' took {:.3}s (baseline {:.3}s, {:+.0}%)",
+                        result.file,
+                        stage,
+                        secs,
+                        before,
+                        (secs / before - 1.0) * 100.0
+                    ));
+                }
+            }
+        }
+        for (metric, &value) in &result.metrics {
+            if let Some(&before) = base.metrics.get(metric) {
+                let change = relative_change(value, before);
+                if change.abs() > tol.metric {
+                    found.push(format!(
+                        "{}: {} is {} (baseline {}, {:+.1}%)",
+                        result.file,
+                        metric,
+                        value,
+                        before,
+                        change * 100.0
+                    ));
+                }
+            }
+        }
+    }
+    found
+}
+
+fn relative_change(value: f64, before: f64) -> f64 {
+    if before == 0.0 {
+        if value == 0.0 {
+            0.0
+        } else {
+            f64::INFINITY
+        }
+    } else {
+        (value - before) / before.abs()
+    }
+}
@@ -0,0 +1,406 @@
+//! `mesh_lifter-gui`: the remesher as a desktop window.
+//!
+//! Drop a scan (OBJ, STL or PLY) on the window, or give its path, pick a
+//! quality and remesh it. Progress shows while it runs, the result is drawn
+//! in the window (drag to turn it, scroll to zoom) and can be saved as STL.
+//! It's the library's `load_mesh`, `remesh_voxel` and `write_stl`, as a
+//! program using the crate would call them; loading and remeshing run on
+//! their own thread under the sandbox, so the window stays live and a job
+//! can be cancelled.
+//!
+//! Built with `cargo build --features gui`; the command line and the
+//! server don't need a windowing toolkit.
+
+use eframe::egui;
+use mesh_auditor::sandbox::{self, JobLimits};
+use mesh_auditor::{progress, Mesh, WriteOptions};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn main() -> eframe::Result {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default()
+            .with_inner_size([1000.0, 700.0])
+            .with_drag_and_drop(true),
+        ..Default::default()
+    };
+    let mut app = App::default();
+    if let Some(path) = std::env::args().nth(1) {
+        app.open(path);
+    }
+    eframe::run_native("mesh_lifter", options, Box::new(|_| Ok(Box::new(app))))
+}
+
+/// The qualities on offer, as the server's page has them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Preset {
+    Draft,
+    Good,
+    Best,
+}
+
+impl Preset {
+    const ALL: [Preset; 3] = [Preset::Draft, Preset::Good, Preset::Best];
+
+    /// Grid points along the scan's longest side.
+    fn resolution(self) -> usize {
+        match self {
+            Preset::Draft => 80,
+            Preset::Good => 150,
+            Preset::Best => 250,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Preset::Draft => "Draft (80 per side, quick)",
+            Preset::Good => "Good (150 per side)",
+            Preset::Best => "Best (250 per side, slow)",
+        }
+    }
+}
+
+// What a background job hands back
+enum Outcome {
+    Scan { mesh: Mesh, path: String },
+    Skin(Mesh),
+}
+
+// The job on its thread; one at a time, as the sandbox is process wide
+struct Job {
+    doing: &'static str,
+    result: Receiver<anyhow::Result<Outcome>>,
+    started: Instant,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Showing {
+    Scan,
+    Skin,
+}
+
+struct App {
+    path: String,
+    preset: Preset,
+    scan: Option<Arc<Mesh>>,
+    skin: Option<Mesh>,
+    showing: Showing,
+    save_path: String,
+    job: Option<Job>,
+    status: String,
+    view: View,
+}
+
+impl Default for App {
+    fn default() -> Self {
+        App {
+            path: String::new(),
+            preset: Preset::Good,
+            scan: None,
+            skin: None,
+            showing: Showing::Scan,
+            save_path: String::new(),
+            job: None,
+            status: "Drop an OBJ, STL or PLY scan on the window".to_string(),
+            view: View::default(),
+        }
+    }
+}
+
+impl App {
+    fn open(&mut self, path: String) {
+        self.path = path.clone();
+        self.start("Loading", move || {
+            let mesh = mesh_auditor::load_mesh(&path)?;
+            Ok(Outcome::Scan { mesh, path })
+        });
+    }
+
+    fn remesh(&mut self) {
+        let Some(scan) = self.scan.clone() else {
+            return;
+        };
+        let resolution = self.preset.resolution();
+        self.start("Remeshing", move || {
+            Ok(Outcome::Skin(mesh_auditor::remesh_voxel(
+                &scan, resolution,
+            )?))
+        });
+    }
+
+    fn start(
+        &mut self,
+        doing: &'static str,
+        work: impl FnOnce() -> anyhow::Result<Outcome> + Send + 'static,
+    ) {
+        let (send, result) = mpsc::channel();
+        sandbox::clear_cancel();
+        progress::start(0);
+        std::thread::spawn(move || {
+            let _ = send.send(sandbox::run_job(&JobLimits::default(), work));
+        });
+        self.status = format!("{}...", doing);
+        self.job = Some(Job {
+            doing,
+            result,
+            started: Instant::now(),
+        });
+    }
+
+    // Pick up the job's result, if it has one
+    fn poll(&mut self) {
+        let Some(job) = &self.job else {
+            return;
+        };
+        let outcome = match job.result.try_recv() {
+            Err(TryRecvError::Empty) => return,
+            Ok(outcome) => outcome,
+            // The thread panicked; the message went to the terminal
+            Err(TryRecvError::Disconnected) => Err(anyhow::anyhow!("internal error")),
+        };
+        let seconds = job.started.elapsed().as_secs_f64();
+        let doing = job.doing;
+        self.job = None;
+        match outcome {
+            Ok(Outcome::Scan { mesh, path }) => {
+                self.status = format!(
+                    "Loaded {} ({} triangles) in {:.1}s",
+                    file_name(&path),
+                    mesh.face_count(),
+                    seconds
+                );
+                self.save_path = remeshed_path(&path);
+                self.scan = Some(Arc::new(mesh));
+                self.skin = None;
+                self.showing = Showing::Scan;
+                self.view = View::default();
+            }
+            Ok(Outcome::Skin(mesh)) => {
+                self.status = format!(
+                    "Remeshed to {} triangles in {:.1}s",
+                    mesh.face_count(),
+                    seconds
+                );
+                self.skin = Some(mesh);
+                self.showing = Showing::Skin;
+            }
+            Err(e) => self.status = format!("{} failed: {:#}", doing, e),
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui) {
+        let idle = self.job.is_none();
+        ui.heading("mesh_lifter");
+        ui.separator();
+
+        ui.label("Scan");
+        ui.text_edit_singleline(&mut self.path);
+        if ui
+            .add_enabled(idle && !self.path.is_empty(), egui::Button::new("Open"))
+            .clicked()
+        {
+            self.open(self.path.clone());
+        }
+        if let Some(scan) = &self.scan {
+            ui.label(format!(
+                "{} vertices, {} triangles",
+                scan.vertex_count(),
+                scan.face_count()
+            ));
+        }
+        ui.separator();
+
+        egui::ComboBox::from_label("Quality")
+            .selected_text(self.preset.label())
+            .show_ui(ui, |ui| {
+                for preset in Preset::ALL {
+                    ui.selectable_value(&mut self.preset, preset, preset.label());
+                }
+            });
+        if ui
+            .add_enabled(idle && self.scan.is_some(), egui::Button::new("Remesh"))
+            .clicked()
+        {
+            self.remesh();
+        }
+        if let Some(job) = &self.job {
+            ui.add(
+                egui::ProgressBar::new(progress::fraction() as f32).text(format!(
+                    "{} {:.0}s",
+                    job.doing,
+                    job.started.elapsed().as_secs_f64()
+                )),
+            );
+            if ui.button("Cancel").clicked() {
+                sandbox::cancel();
+            }
+        }
+        ui.separator();
+
+        if let Some(skin) = &self.skin {
+            ui.horizontal(|ui| {
+                ui.label("Show");
+                ui.selectable_value(&mut self.showing, Showing::Scan, "Scan");
+                ui.selectable_value(&mut self.showing, Showing::Skin, "Result");
+            });
+            ui.label("Save the result as");
+            ui.text_edit_singleline(&mut self.save_path);
+            if ui
+                .add_enabled(!self.save_path.is_empty(), egui::Button::new("Save STL"))
+                .clicked()
+            {
+                self.status = match mesh_auditor::write_stl(
+                    skin,
+                    &self.save_path,
+                    &WriteOptions::default(),
+                ) {
+                    Ok(()) => format!("Saved {}", self.save_path),
+                    Err(e) => format!("Saving failed: {:#}", e),
+                };
+            }
+            ui.separator();
+        }
+        ui.label(&self.status);
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.poll();
+        let dropped =
+            ctx.input(|input| input.raw.dropped_files.first().and_then(|f| f.path.clone()));
+        if let Some(path) = dropped {
+            if self.job.is_none() {
+                self.open(path.to_string_lossy().into_owned());
+            }
+        }
+        if self.job.is_some() {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+
+        egui::SidePanel::left("controls")
+            .resizable(false)
+            .exact_width(260.0)
+            .show(ctx, |ui| self.controls(ui));
+        egui::CentralPanel::default().show(ctx, |ui| {
+            let shown = match self.showing {
+                Showing::Skin => self.skin.as_ref(),
+                Showing::Scan => self.scan.as_deref(),
+            };
+            match shown {
+                Some(mesh) => self.view.show(ui, mesh),
+                None => {
+                    ui.centered_and_justified(|ui| ui.label("No scan yet"));
+                }
+            }
+        });
+    }
+}
+
+/// How the mesh is turned and zoomed in the viewer.
+struct View {
+    yaw: f32,
+    pitch: f32,
+    zoom: f32,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        View {
+            yaw: 0.6,
+            pitch: 0.4,
+            zoom: 1.0,
+        }
+    }
+}
+
+impl View {
+    // Draw `mesh` flat shaded, far faces first, filling the space left
+    fn show(&mut self, ui: &mut egui::Ui, mesh: &Mesh) {
+        let (response, painter) = ui.allocate_painter(ui.available_size(), egui::Sense::drag());
+        let drag = response.drag_delta();
+        self.yaw += drag.x * 0.01;
+        self.pitch = (self.pitch + drag.y * 0.01).clamp(-1.5, 1.5);
+        if response.hovered() {
+            let scroll = ui.input(|input| input.smooth_scroll_delta.y);
+            self.zoom = (self.zoom * (scroll * 0.002).exp()).clamp(0.1, 20.0);
+        }
+
+        let (lo, hi) = mesh.bounds();
+        let centre: [f32; 3] = [0, 1, 2].map(|k| (lo[k] + hi[k]) / 2.0);
+        let radius = (0..3)
+            .map(|k| (hi[k] - lo[k]).powi(2))
+            .sum::<f32>()
+            .sqrt()
+            .max(f32::MIN_POSITIVE)
+            / 2.0;
+        let rect = response.rect;
+        let scale = 0.9 * rect.width().min(rect.height()) / 2.0 / radius * self.zoom;
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+        // Turned about y, then about x; z is towards the viewer
+        let turned: Vec<[f32; 3]> = mesh
+            .positions
+            .chunks_exact(3)
+            .map(|p| {
+                let [x, y, z] = [0, 1, 2].map(|k| p[k] - centre[k]);
+                let (x, z) = (x * cos_yaw + z * sin_yaw, z * cos_yaw - x * sin_yaw);
+                let (y, z) = (y * cos_pitch - z * sin_pitch, z * cos_pitch + y * sin_pitch);
+                [x, y, z]
+            })
+            .collect();
+
+        let mut faces: Vec<(f32, [usize; 3])> = (0..mesh.face_count())
+            .map(|f| {
+                let corners = mesh.face(f);
+                (corners.iter().map(|&v| turned[v][2]).sum::<f32>(), corners)
+            })
+            .collect();
+        faces.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut shape = egui::Mesh::default();
+        for (_, corners) in faces {
+            let [a, b, c] = corners.map(|v| turned[v]);
+            let u = [0, 1, 2].map(|k| b[k] - a[k]);
+            let w = [0, 1, 2].map(|k| c[k] - a[k]);
+            let normal = [
+                u[1] * w[2] - u[2] * w[1],
+                u[2] * w[0] - u[0] * w[2],
+                u[0] * w[1] - u[1] * w[0],
+            ];
+            let length = normal.iter().map(|n| n * n).sum::<f32>().sqrt();
+            // Lit from the viewer, both sides alike: scans have holes
+            let light = if length > 0.0 {
+                (normal[2] / length).abs()
+            } else {
+                0.0
+            };
+            let grey = (40.0 + 200.0 * light) as u8;
+            let colour = egui::Color32::from_rgb(grey, grey, grey.saturating_add(15));
+            let first = shape.vertices.len() as u32;
+            for p in [a, b, c] {
+                shape.colored_vertex(rect.center() + egui::vec2(p[0], -p[1]) * scale, colour);
+            }
+            shape.add_triangle(first, first + 1, first + 2);
+        }
+        painter.add(egui::Shape::mesh(shape));
+    }
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().map_or_else(
+        || path.to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+// `scan.obj` -> `scan_remeshed.stl`, beside it
+fn remeshed_path(path: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_remeshed.stl", stem))
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,171 @@
+//! Baking cages: a low-poly mesh blown up just enough to enclose the scan.
+//!
+//! Casting both ways from the low-poly surface (see `bake`) finds the
+//! nearest piece of scan, which in a crease or a thin gap can be the wrong
+//! piece, and misses detail poking out further than the cast reaches. A
+//! cage fixes both: rays start on the cage, outside everything, and run in
+//! towards the low-poly surface, so the first thing they meet is the
+//! outermost scan surface over that texel.
+//!
+//! Each vertex moves out along its averaged normal by how far the scan
+//! stands proud of the faces around it, plus a margin, and the offsets
+//! are smoothed upward so the cage doesn't pinch between vertices. They
+//! are then cut back wherever the cage would run into another part of the
+//! low-poly mesh (two close walls, say) or turn a face inside out.
+
+use crate::bvh::Bvh;
+use crate::mesh::Mesh;
+
+// Offset the scan's furthest point by this much more
+const MARGIN: f32 = 1.25;
+// Smallest offset, as a share of the cast distance
+const FLOOR: f32 = 0.05;
+// An offset face's normal may turn this far (as a dot product) before its
+// corners are pulled back in
+const MIN_NORMAL_DOT: f32 = 0.2;
+const SMOOTHING_PASSES: usize = 2;
+// Where on each face to look: corners, edge midpoints and centre
+const SAMPLES: [[f32; 3]; 7] = [
+    [1.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0],
+    [0.0, 0.0, 1.0],
+    [0.5, 0.5, 0.0],
+    [0.0, 0.5, 0.5],
+    [0.5, 0.0, 0.5],
+    [1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0],
+];
+const UNFOLD_PASSES: usize = 8;
+
+/// A cage around a low-poly mesh.
+#[derive(Debug)]
+pub struct Cage {
+    /// Same vertices (moved out) and faces as the low-poly mesh.
+    pub mesh: Mesh,
+    /// How far each vertex moved.
+    pub offsets: Vec<f32>,
+    /// Vertices whose offset was cut back to keep the cage from crossing
+    /// itself.
+    pub pulled_in: usize,
+}
+
+/// Build a cage round `low` that takes in `high` wherever it's within
+/// `max_distance` of the low-poly surface.
+pub fn build(low: &Mesh, high: &Mesh, max_distance: f32) -> Cage {
+    let normals = low.vertex_normals();
+    let scan = Bvh::new(high);
+    let floor = max_distance * FLOOR;
+
+    // 1. How far out the scan reaches over each face, not just at its
+    // corners: decimated vertices sit on the scan, the bulges are between
+    let mut offsets = vec![floor; low.vertex_count()];
+    for f in 0..low.face_count() {
+        let corners = low.face(f);
+        let mut reach = 0.0f32;
+        for weights in SAMPLES {
+            let mut point = [0.0; 3];
+            let mut normal = [0.0; 3];
+            for (&i, &w) in corners.iter().zip(&weights) {
+                let p = low.vertex(i);
+                for k in 0..3 {
+                    point[k] += p[k] * w;
+                    normal[k] += normals[i][k] * w;
+                }
+            }
+            let length = (0..3).map(|k| normal[k] * normal[k]).sum::<f32>().sqrt();
+            if length > 0.0 {
+                let normal = normal.map(|x| x / length);
+                if let Some(hit) = scan.cast(point, normal, max_distance) {
+                    reach = reach.max(hit.t);
+                }
+            }
+        }
+        for i in corners {
+            offsets[i] = offsets[i].max((reach * MARGIN).min(max_distance));
+        }
+    }
+
+    // 2. Smooth upward: never less than the average round about
+    let mut neighbours = vec![Vec::new(); low.vertex_count()];
+    for f in 0..low.face_count() {
+        let corners = low.face(f);
+        for k in 0..3 {
+            let (a, b) = (corners[k], corners[(k + 1) % 3]);
+            neighbours[a].push(b);
+            neighbours[b].push(a);
+        }
+    }
+    for list in &mut neighbours {
+        list.sort_unstable();
+        list.dedup();
+    }
+    for _ in 0..SMOOTHING_PASSES {
+        offsets = (0..offsets.len())
+            .map(|i| {
+                if neighbours[i].is_empty() {
+                    return offsets[i];
+                }
+                let mean = neighbours[i].iter().map(|&j| offsets[j]).sum::<f32>()
+                    / neighbours[i].len() as f32;
+                offsets[i].max(mean)
+            })
+            .collect();
+    }
+
+    // 3. Stop short of the low-poly mesh's other walls. The ray starts a
+    // little out so it doesn't find the vertex's own faces
+    let mut pulled = vec![false; low.vertex_count()];
+    let itself = Bvh::new(low);
+    for i in 0..low.vertex_count() {
+        let start = floor * 0.01;
+        let p = low.vertex(i);
+        let origin = [0, 1, 2].map(|k| p[k] + normals[i][k] * start);
+        if let Some(hit) = itself.cast(origin, normals[i], offsets[i]) {
+            // Halfway there, so a wall coming the other way fits too
+            offsets[i] = ((hit.t + start) * 0.5).min(offsets[i]);
+            pulled[i] = true;
+        }
+    }
+
+    // 4. Pull in the corners of faces the offset turned over
+    let mut mesh = offset(low, &normals, &offsets);
+    for _ in 0..UNFOLD_PASSES {
+        let mut folded = false;
+        for f in 0..low.face_count() {
+            let before = low.face_normal(f);
+            let after = mesh.face_normal(f);
+            let turn: f32 = (0..3).map(|k| before[k] * after[k]).sum();
+            if turn < MIN_NORMAL_DOT {
+                for i in low.face(f) {
+                    offsets[i] *= 0.5;
+                    pulled[i] = true;
+                }
+                folded = true;
+            }
+        }
+        if !folded {
+            break;
+        }
+        mesh = offset(low, &normals, &offsets);
+    }
+
+    Cage {
+        mesh,
+        offsets,
+        pulled_in: pulled.iter().filter(|&&p| p).count(),
+    }
+}
+
+// `low` with each vertex moved out along its normal
+fn offset(low: &Mesh, normals: &[[f32; 3]], offsets: &[f32]) -> Mesh {
+    let mut mesh = Mesh {
+        positions: Vec::with_capacity(low.positions.len()),
+        indices: low.indices.clone(),
+        ..Default::default()
+    };
+    for i in 0..low.vertex_count() {
+        let p = low.vertex(i);
+        mesh.positions
+            .extend((0..3).map(|k| p[k] + normals[i][k] * offsets[i]));
+    }
+    mesh
+}
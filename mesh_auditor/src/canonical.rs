@@ -0,0 +1,97 @@
+//! `--canonical-order`: byte-identical outputs for the same geometry.
+//!
+//! Before writing, vertices are sorted by position and triangles by their
+//! corners, with each triangle turned (not flipped, so the winding stays)
+//! to start at its smallest corner. Two runs that produce the same
+//! triangles in any order, from any number of threads, then write the same
+//! file, which makes outputs easy to diff in regression tests downstream.
+//!
+//! The writers do this when their `WriteOptions` ask for it (see
+//! `output`).
+
+use crate::mesh::Mesh;
+use std::cmp::Ordering as CmpOrdering;
+
+/// A flat triangle list (9 floats per triangle), canonically ordered.
+pub fn soup(triangles: &[f32]) -> Vec<f32> {
+    let mut sorted: Vec<[[f32; 3]; 3]> = triangles
+        .chunks_exact(9)
+        .map(|t| {
+            let corners = [[t[0], t[1], t[2]], [t[3], t[4], t[5]], [t[6], t[7], t[8]]];
+            let first = (0..3)
+                .min_by(|&a, &b| compare_points(&corners[a], &corners[b]))
+                .unwrap();
+            [0, 1, 2].map(|k| corners[(first + k) % 3])
+        })
+        .collect();
+    sorted.sort_by(|a, b| {
+        a.iter()
+            .zip(b)
+            .map(|(p, q)| compare_points(p, q))
+            .find(|o| o.is_ne())
+            .unwrap_or(CmpOrdering::Equal)
+    });
+    sorted.into_iter().flatten().flatten().collect()
+}
+
+/// An indexed mesh, canonically ordered.
+pub fn mesh(mesh: &Mesh) -> Mesh {
+    // 1. Vertices by position (ties by old index, so it stays total)
+    let mut order: Vec<usize> = (0..mesh.vertex_count()).collect();
+    order.sort_by(|&a, &b| compare_points(&mesh.vertex(a), &mesh.vertex(b)).then(a.cmp(&b)));
+    let mut new_index = vec![0u32; order.len()];
+    let mut positions = Vec::with_capacity(mesh.positions.len());
+    let mut colours = Vec::with_capacity(mesh.colours.len());
+    for (new, &old) in order.iter().enumerate() {
+        new_index[old] = new as u32;
+        positions.extend_from_slice(&mesh.vertex(old));
+        if let Some(c) = mesh.colour(old) {
+            colours.extend_from_slice(&c);
+        }
+    }
+
+    // 2. Faces by material, then by their renumbered corners, each starting
+    //    at its smallest (its UVs turning with it)
+    let mut faces: Vec<(Option<u32>, [u32; 3], usize, usize)> = (0..mesh.face_count())
+        .map(|f| {
+            let corners = mesh.face(f).map(|i| new_index[i]);
+            let first = (0..3).min_by_key(|&k| corners[k]).unwrap();
+            let turned = [0, 1, 2].map(|k| corners[(first + k) % 3]);
+            (mesh.face_material(f), turned, f, first)
+        })
+        .collect();
+    faces.sort_unstable();
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    let mut texcoords = Vec::with_capacity(mesh.texcoords.len());
+    let mut face_materials = Vec::with_capacity(mesh.face_materials.len());
+    for (material, corners, f, first) in faces {
+        indices.extend_from_slice(&corners);
+        for k in 0..3 {
+            if let Some(uv) = mesh.texcoord(f * 3 + (first + k) % 3) {
+                texcoords.extend_from_slice(&uv);
+            }
+        }
+        if !mesh.face_materials.is_empty() {
+            face_materials.push(material);
+        }
+    }
+
+    Mesh {
+        positions,
+        indices,
+        colours,
+        texcoords,
+        face_materials,
+        materials: mesh.materials.clone(),
+        material_libraries: mesh.material_libraries.clone(),
+        notes: mesh.notes.clone(),
+        metadata: mesh.metadata.clone(),
+        vertex_attributes: mesh.pick_vertex_attributes(&order),
+    }
+}
+
+fn compare_points(a: &[f32; 3], b: &[f32; 3]) -> CmpOrdering {
+    a[0].total_cmp(&b[0])
+        .then(a[1].total_cmp(&b[1]))
+        .then(a[2].total_cmp(&b[2]))
+}
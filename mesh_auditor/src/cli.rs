@@ -0,0 +1,29 @@
+//! The command line's side of `mesh_lifter`: each command's handler, which
+//! loads its input, runs the library over it and prints what happened, and
+//! the parts that only make sense on a command line: shell completions and
+//! the man page, the wizard, background mode, the batch dashboard, the run
+//! history and sharing results. They build on the library but aren't part
+//! of it.
+
+use mesh_auditor::{InputLimits, WriteOptions};
+
+pub mod analyse;
+pub mod completions;
+pub mod convert;
+pub mod dashboard;
+pub mod edit;
+pub mod history;
+pub mod inspect;
+pub mod manpage;
+pub mod priority;
+pub mod share;
+pub mod voxel;
+pub mod wizard;
+
+/// What every command runs under besides its own arguments: how much input
+/// to accept, how to write what it makes and how many threads to use.
+pub struct Run {
+    pub limits: InputLimits,
+    pub write: WriteOptions,
+    pub threads: usize,
+}
@@ -0,0 +1,320 @@
+//! `analyze`: how a part will be made, asked of its surface or its field.
+//!
+//! Draft and undercuts look at the faces against a mould's pull; reach
+//! casts along a 3-axis machine's tool directions; porosity looks for voids
+//! sealed inside the sampled field. Each prints a summary, or JSON with
+//! `--json`, and writes what it flags to `--output` for a viewer.
+
+use super::Run;
+use crate::Analysis;
+use anyhow::{bail, Result};
+use mesh_auditor::draft;
+use mesh_auditor::mesh::Mesh;
+use mesh_auditor::output;
+use mesh_auditor::porosity;
+use mesh_auditor::remesh;
+use mesh_auditor::samples::Storage;
+use mesh_auditor::sdf::SampledField;
+use mesh_auditor::slabs::Slabs;
+use mesh_auditor::visibility;
+use std::path::Path;
+
+pub fn run_analysis(analysis: Analysis, run: &Run) -> Result<()> {
+    match analysis {
+        Analysis::Draft {
+            input,
+            direction,
+            min_angle,
+            output,
+            json,
+        } => analyse_draft(&input, direction, min_angle, output.as_deref(), json, run),
+        Analysis::Undercut {
+            input,
+            direction,
+            output,
+            json,
+        } => analyse_undercuts(&input, direction, output.as_deref(), json, run),
+        Analysis::Reach {
+            input,
+            directions,
+            output,
+            json,
+        } => analyse_reach(&input, &directions, output.as_deref(), json, run),
+        Analysis::Porosity {
+            input,
+            iso,
+            resolution,
+            output,
+            json,
+        } => analyse_porosity(&input, iso, resolution, output.as_deref(), json, run),
+    }
+}
+
+// Voids listed in the console report; the JSON has them all
+const VOIDS_LISTED: usize = 10;
+
+fn analyse_porosity(
+    input: &str,
+    iso: Option<f32>,
+    resolution: usize,
+    output: Option<&str>,
+    json: bool,
+    run: &Run,
+) -> Result<()> {
+    if !json {
+        println!("-----------------------------------------");
+        println!("🫧 POROSITY: {}", input);
+        println!("-----------------------------------------");
+    }
+    let is_field = Path::new(input)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("mlsdf"));
+    let field = if is_field {
+        SampledField::load(input, &run.limits)?
+    } else {
+        if resolution < 4 {
+            bail!("resolution must be at least 4");
+        }
+        let mesh = Mesh::load(input, &run.limits)?;
+        if mesh.face_count() == 0 {
+            bail!("the mesh has no faces to be inside of");
+        }
+        let slabs = Slabs {
+            threads: run.threads,
+            ..Slabs::default()
+        };
+        remesh::sample_surface(&mesh, resolution, Storage::Full, &slabs)
+    };
+    let iso = iso.unwrap_or(field.iso);
+    let porosity = porosity::analyse(&field, iso)?;
+    if let Some(path) = output {
+        output::save(
+            &Mesh::from_triangles(&porosity.void_mesh(&field, iso))?,
+            path,
+            &run.write,
+        )?;
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&porosity)?);
+        return Ok(());
+    }
+
+    let [nx, ny, nz] = field.dims;
+    println!(
+        "   • Grid: {}x{}x{}, voxels of {:.6}",
+        nx, ny, nz, porosity.voxel_volume
+    );
+    println!(
+        "   • Solid: {:.4}, voids: {:.4} ({:.3}% porosity)",
+        porosity.solid_volume, porosity.void_volume, porosity.porosity
+    );
+    if porosity.voids.is_empty() {
+        println!("✅ No sealed voids");
+    } else {
+        println!("⚠️  {} sealed voids", porosity.voids.len());
+        for (n, void) in porosity.voids.iter().take(VOIDS_LISTED).enumerate() {
+            let c = void.centre;
+            println!(
+                "     - {}: {:.4} ({} voxels) at ({:.3}, {:.3}, {:.3}), {:.3} under the surface",
+                n + 1,
+                void.volume,
+                void.voxels,
+                c[0],
+                c[1],
+                c[2],
+                void.depth
+            );
+        }
+        if porosity.voids.len() > VOIDS_LISTED {
+            println!(
+                "     - ...and {} smaller",
+                porosity.voids.len() - VOIDS_LISTED
+            );
+        }
+    }
+    if let Some(path) = output {
+        println!("💾 Saved the voids' surfaces to: {}", path);
+    }
+    Ok(())
+}
+
+fn analyse_reach(
+    input: &str,
+    directions: &[[f32; 3]],
+    output: Option<&str>,
+    json: bool,
+    run: &Run,
+) -> Result<()> {
+    if !json {
+        println!("-----------------------------------------");
+        println!("🛠️  MACHINING REACH: {}", input);
+        println!("-----------------------------------------");
+    }
+    let mesh = Mesh::load(input, &run.limits)?;
+    let reach = visibility::reach(&mesh, directions, run.threads)?;
+    if let Some(path) = output {
+        reach.coloured(&mesh).save_obj(path, &run.write)?;
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&reach)?);
+        return Ok(());
+    }
+
+    let share = |area: f64| area / reach.surface_area.max(f64::MIN_POSITIVE) * 100.0;
+    for setup in &reach.directions {
+        let d = setup.direction;
+        println!(
+            "   • From ({:.3}, {:.3}, {:.3}): {:.1}% of the surface",
+            d[0],
+            d[1],
+            d[2],
+            share(setup.area)
+        );
+    }
+    println!(
+        "   • Reached from at least one: {:.2} ({:.1}%)",
+        reach.reached_area,
+        share(reach.reached_area)
+    );
+    if reach.blind_area > 0.0 {
+        println!(
+            "⚠️  Blind: {:.2} ({:.1}%) of the surface can't be reached",
+            reach.blind_area,
+            share(reach.blind_area)
+        );
+        for patch in &reach.patches {
+            let c = patch.centre;
+            println!(
+                "     - {:.2} over {} faces at ({:.3}, {:.3}, {:.3})",
+                patch.area, patch.faces, c[0], c[1], c[2]
+            );
+        }
+    } else {
+        println!("✅ Every face can be reached from one of the directions");
+    }
+    if let Some(path) = output {
+        println!("💾 Saved the reach-coloured mesh to: {}", path);
+    }
+    Ok(())
+}
+
+fn analyse_undercuts(
+    input: &str,
+    direction: [f32; 3],
+    output: Option<&str>,
+    json: bool,
+    run: &Run,
+) -> Result<()> {
+    if !json {
+        println!("-----------------------------------------");
+        println!("🔦 UNDERCUT ANALYSIS: {}", input);
+        println!("-----------------------------------------");
+    }
+    let mesh = Mesh::load(input, &run.limits)?;
+    let undercuts = visibility::undercuts(&mesh, direction, run.threads)?;
+    if let Some(path) = output {
+        undercuts.coloured(&mesh).save_obj(path, &run.write)?;
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&undercuts)?);
+        return Ok(());
+    }
+
+    let share = |area: f64| area / undercuts.surface_area.max(f64::MIN_POSITIVE) * 100.0;
+    let d = undercuts.direction;
+    println!(
+        "   • Parting direction: ({:.3}, {:.3}, {:.3})",
+        d[0], d[1], d[2]
+    );
+    println!(
+        "   • Seen along it: {:.2} ({:.1}%)",
+        undercuts.forward_area,
+        share(undercuts.forward_area)
+    );
+    println!(
+        "   • Seen only against it: {:.2} ({:.1}%)",
+        undercuts.back_area,
+        share(undercuts.back_area)
+    );
+    if undercuts.undercut_area > 0.0 {
+        println!(
+            "⚠️  Undercut: {:.2} ({:.1}%) of the surface",
+            undercuts.undercut_area,
+            share(undercuts.undercut_area)
+        );
+        for patch in &undercuts.patches {
+            let c = patch.centre;
+            println!(
+                "     - {:.2} over {} faces at ({:.3}, {:.3}, {:.3})",
+                patch.area, patch.faces, c[0], c[1], c[2]
+            );
+        }
+    } else {
+        println!("✅ No undercuts: every face is seen from one side or the other");
+    }
+    if let Some(path) = output {
+        println!("💾 Saved the side-coloured mesh to: {}", path);
+    }
+    Ok(())
+}
+
+fn analyse_draft(
+    input: &str,
+    direction: [f32; 3],
+    min_angle: f64,
+    output: Option<&str>,
+    json: bool,
+    run: &Run,
+) -> Result<()> {
+    if !json {
+        println!("-----------------------------------------");
+        println!("📐 DRAFT ANALYSIS: {}", input);
+        println!("-----------------------------------------");
+    }
+    let mesh = Mesh::load(input, &run.limits)?;
+    let draft = draft::analyse(&mesh, direction, min_angle)?;
+    if let Some(path) = output {
+        draft.coloured(&mesh).save_obj(path, &run.write)?;
+    }
+    if json {
+        println!("{}", serde_json::to_string_pretty(&draft)?);
+        return Ok(());
+    }
+
+    let share = |area: f64| area / draft.surface_area.max(f64::MIN_POSITIVE) * 100.0;
+    let d = draft.direction;
+    println!(
+        "   • Pull direction: ({:.3}, {:.3}, {:.3}), at least {}° of draft",
+        d[0], d[1], d[2], draft.min_angle
+    );
+    println!(
+        "   • Releases along the pull: {:.2} ({:.1}%)",
+        draft.forward_area,
+        share(draft.forward_area)
+    );
+    println!(
+        "   • Releases against the pull: {:.2} ({:.1}%)",
+        draft.back_area,
+        share(draft.back_area)
+    );
+    if draft.under_drafted_area > 0.0 {
+        println!(
+            "⚠️  Under-drafted: {:.2} ({:.1}%) of the surface",
+            draft.under_drafted_area,
+            share(draft.under_drafted_area)
+        );
+        for patch in &draft.patches {
+            let c = patch.centre;
+            println!(
+                "     - {:.2} over {} faces at ({:.3}, {:.3}, {:.3}), down to {:.2}°",
+                patch.area, patch.faces, c[0], c[1], c[2], patch.least_angle
+            );
+        }
+    } else {
+        println!("✅ Every face has at least {}° of draft", draft.min_angle);
+    }
+    if let Some(path) = output {
+        println!("💾 Saved the draft-coloured mesh to: {}", path);
+    }
+    Ok(())
+}
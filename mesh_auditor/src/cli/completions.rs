@@ -0,0 +1,357 @@
+//! `completions bash|zsh|fish`: tab completion scripts for the CLI.
+//!
+//! The scripts are written from the clap definitions at run time, so every
+//! subcommand, flag and value list (`--storage bits`, `--report html`) is
+//! covered the moment it is added, with nothing to keep in sync by hand.
+//! Flags that take a free value (a path, a size, a URL) complete file
+//! names, which is right more often than not.
+
+use anyhow::Result;
+use clap::{Arg, Command};
+use std::io::Write;
+
+/// The shells there are scripts for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Write the completion script for `cmd` in `shell` to `out`.
+pub fn write(cmd: &mut Command, shell: Shell, out: &mut impl Write) -> Result<()> {
+    // Building propagates the global flags and adds --help everywhere
+    cmd.build();
+    let script = match shell {
+        Shell::Bash => bash(cmd),
+        Shell::Zsh => zsh(cmd),
+        Shell::Fish => fish(cmd),
+    };
+    out.write_all(script.as_bytes())?;
+    Ok(())
+}
+
+/// Every command under `cmd` (and `cmd` itself), with the path of names
+/// leading to it.
+pub fn walk(cmd: &Command) -> Vec<(Vec<String>, &Command)> {
+    let mut found = vec![(vec![cmd.get_name().to_string()], cmd)];
+    let mut i = 0;
+    while i < found.len() {
+        let (path, parent) = found[i].clone();
+        for sub in visible_subcommands(parent) {
+            let mut path = path.clone();
+            path.push(sub.get_name().to_string());
+            found.push((path, sub));
+        }
+        i += 1;
+    }
+    found
+}
+
+pub fn visible_subcommands(cmd: &Command) -> impl Iterator<Item = &Command> {
+    cmd.get_subcommands().filter(|c| !c.is_hide_set())
+}
+
+pub fn visible_options(cmd: &Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_arguments()
+        .filter(|a| !a.is_positional() && !a.is_hide_set())
+}
+
+pub fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+/// The values a flag can take, if it's a fixed list.
+pub fn choices(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect()
+}
+
+/// The first line of an argument's or command's help.
+pub fn summary(help: Option<&clap::builder::StyledStr>) -> String {
+    help.map(|h| h.to_string())
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or("")
+        .to_string()
+}
+
+// --long and -s spellings of a flag
+fn spellings(arg: &Arg) -> Vec<String> {
+    let mut names: Vec<String> = arg
+        .get_long_and_visible_aliases()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|l| format!("--{}", l))
+        .collect();
+    names.extend(
+        arg.get_short_and_visible_aliases()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|s| format!("-{}", s)),
+    );
+    names
+}
+
+fn bash(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let commands = walk(cmd);
+
+    // 1. Find which (sub)command the words so far are in, skipping the
+    // values of flags that take one
+    let mut valued: Vec<String> = commands
+        .iter()
+        .flat_map(|(_, c)| visible_options(c))
+        .filter(|a| takes_value(a))
+        .flat_map(spellings)
+        .collect();
+    valued.sort();
+    valued.dedup();
+    let mut s = format!(
+        "{f}() {{\n    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\" prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"\n    local cmd=\"{n}\" i\n    for ((i = 1; i < COMP_CWORD; i++)); do\n        case \"$cmd,${{COMP_WORDS[i]}}\" in\n",
+        f = function,
+        n = name
+    );
+    for (path, c) in &commands {
+        for sub in visible_subcommands(c) {
+            s += &format!(
+                "            {},{}) cmd=\"{}__{}\" ;;\n",
+                path.join("__"),
+                sub.get_name(),
+                path.join("__"),
+                sub.get_name()
+            );
+        }
+    }
+    if !valued.is_empty() {
+        s += &format!("            *,{}) ((i++)) ;;\n", valued.join("|*,"));
+    }
+    s += "        esac\n    done\n\n    case \"$cmd\" in\n";
+
+    // 2. What each one completes: a flag's values after the flag, else its
+    // flags and subcommands
+    for (path, c) in &commands {
+        s += &format!("        {})\n", path.join("__"));
+        let valued: Vec<&Arg> = visible_options(c).filter(|a| takes_value(a)).collect();
+        if !valued.is_empty() {
+            s += "            case \"$prev\" in\n";
+            for arg in valued {
+                let words = choices(arg);
+                let action = if words.is_empty() {
+                    // An empty reply falls back to file names
+                    "return".to_string()
+                } else {
+                    format!(
+                        "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return",
+                        words.join(" ")
+                    )
+                };
+                s += &format!(
+                    "                {}) {} ;;\n",
+                    spellings(arg).join("|"),
+                    action
+                );
+            }
+            s += "            esac\n";
+        }
+        let mut words: Vec<String> = visible_subcommands(c)
+            .map(|sub| sub.get_name().to_string())
+            .collect();
+        let flags: Vec<String> = visible_options(c).flat_map(spellings).collect();
+        if c.has_subcommands() {
+            words.extend(flags);
+            s += &format!(
+                "            COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n",
+                words.join(" ")
+            );
+        } else {
+            // Arguments are files, unless it looks like a flag
+            s += &format!(
+                "            [[ $cur == -* ]] && COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n",
+                flags.join(" ")
+            );
+        }
+        s += "            ;;\n";
+    }
+    s += &format!(
+        "    esac\n}}\n\ncomplete -F {} -o bashdefault -o default {}\n",
+        function, name
+    );
+    s
+}
+
+fn zsh(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let mut s = format!("#compdef {}\n\n", name);
+    for (path, c) in walk(cmd) {
+        let function = format!("_{}", path.join("__").replace('-', "_"));
+        s += &format!("{}() {{\n", function);
+
+        // 1. Flags, then what the arguments are
+        let mut specs: Vec<String> = Vec::new();
+        for arg in visible_options(c) {
+            let help = zsh_escape(&summary(arg.get_help()));
+            let repeat = if matches!(
+                arg.get_action(),
+                clap::ArgAction::Append | clap::ArgAction::Count
+            ) {
+                "*"
+            } else {
+                ""
+            };
+            for spelling in spellings(arg) {
+                if takes_value(arg) {
+                    let value = value_name(arg);
+                    let words = choices(arg);
+                    let action = if words.is_empty() {
+                        "_files".to_string()
+                    } else {
+                        format!("({})", words.join(" "))
+                    };
+                    let joiner = if spelling.starts_with("--") { "=" } else { "+" };
+                    specs.push(format!(
+                        "'{}{}{}[{}]:{}:{}'",
+                        repeat, spelling, joiner, help, value, action
+                    ));
+                } else {
+                    specs.push(format!("'{}{}[{}]'", repeat, spelling, help));
+                }
+            }
+        }
+        if c.has_subcommands() {
+            specs.push("': :->command'".to_string());
+            specs.push("'*:: :->argument'".to_string());
+        } else {
+            for arg in c.get_positionals().filter(|a| !a.is_hide_set()) {
+                let help = zsh_escape(&summary(arg.get_help()));
+                let many = arg.get_num_args().is_some_and(|n| n.max_values() > 1);
+                let optional = if arg.is_required_set() { "" } else { ":" };
+                specs.push(format!(
+                    "'{}:{}{}:_files'",
+                    if many { "*" } else { "" },
+                    optional,
+                    if help.is_empty() {
+                        value_name(arg)
+                    } else {
+                        help
+                    }
+                ));
+            }
+        }
+        s += "    local context state line\n    _arguments -C \\\n";
+        for spec in &specs {
+            s += &format!("        {} \\\n", spec);
+        }
+        s += "        && return\n";
+
+        // 2. Subcommands: list them, or hand over to the one chosen
+        if c.has_subcommands() {
+            s += "    case $state in\n        command)\n            local -a commands\n            commands=(\n";
+            for sub in visible_subcommands(c) {
+                s += &format!(
+                    "                '{}:{}'\n",
+                    sub.get_name(),
+                    zsh_escape(&summary(sub.get_about())).replace(':', "\\:")
+                );
+            }
+            s += "            )\n            _describe 'command' commands\n            ;;\n        argument)\n            case $line[1] in\n";
+            for sub in visible_subcommands(c) {
+                s += &format!(
+                    "                {}) {}__{} ;;\n",
+                    sub.get_name(),
+                    function,
+                    sub.get_name().replace('-', "_")
+                );
+            }
+            s += "            esac\n            ;;\n    esac\n";
+        }
+        s += "}\n\n";
+    }
+    s += &format!("_{} \"$@\"\n", name.replace('-', "_"));
+    s
+}
+
+// Safe inside '...[help]' in an _arguments spec
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+}
+
+fn fish(cmd: &Command) -> String {
+    let name = cmd.get_name();
+    let mut s = String::new();
+    for (path, c) in walk(cmd) {
+        // 1. When this command's completions apply: once its name has been
+        // typed, and none of its own subcommands yet
+        let children: Vec<&str> = visible_subcommands(c).map(|sub| sub.get_name()).collect();
+        let mut conditions = Vec::new();
+        if path.len() == 1 {
+            conditions.push("__fish_use_subcommand".to_string());
+        } else {
+            conditions.push(format!(
+                "__fish_seen_subcommand_from {}",
+                path.last().unwrap()
+            ));
+            if !children.is_empty() {
+                conditions.push(format!(
+                    "not __fish_seen_subcommand_from {}",
+                    children.join(" ")
+                ));
+            }
+        }
+        let condition = conditions.join("; and ");
+
+        // 2. Its subcommands and flags
+        for sub in visible_subcommands(c) {
+            s += &format!(
+                "complete -c {} -n \"{}\" -f -a {} -d '{}'\n",
+                name,
+                condition,
+                sub.get_name(),
+                fish_escape(&summary(sub.get_about()))
+            );
+        }
+        for arg in visible_options(c) {
+            let mut line = format!("complete -c {} -n \"{}\"", name, condition);
+            if let Some(longs) = arg.get_long_and_visible_aliases() {
+                for long in longs {
+                    line += &format!(" -l {}", long);
+                }
+            }
+            if let Some(shorts) = arg.get_short_and_visible_aliases() {
+                for short in shorts {
+                    line += &format!(" -s {}", short);
+                }
+            }
+            if takes_value(arg) {
+                let words = choices(arg);
+                if words.is_empty() {
+                    line += " -r -F";
+                } else {
+                    line += &format!(" -r -f -a \"{}\"", words.join(" "));
+                }
+            }
+            line += &format!(" -d '{}'\n", fish_escape(&summary(arg.get_help())));
+            s += &line;
+        }
+    }
+    s
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+/// What to call an argument's value, e.g. `FILE` or `N`.
+pub fn value_name(arg: &Arg) -> String {
+    match arg.get_value_names() {
+        Some(names) if !names.is_empty() => names[0].to_string(),
+        _ => arg.get_id().as_str().to_uppercase(),
+    }
+}
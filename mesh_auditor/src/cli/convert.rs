@@ -0,0 +1,561 @@
+//! `convert`, `decimate` and `bake`: a scan in, the same surface out in
+//! another form.
+//!
+//! `convert` takes the mesh through whichever steps its flags ask for
+//! (orienting, rescaling, placing, decimating, splitting into shells,
+//! textures) and writes it by its output's extension; `decimate` is
+//! `convert` with only the decimation. `bake` decimates the scan (or takes
+//! the low-poly mesh it's given) and bakes the scan's detail onto it as a
+//! normal map, and a displacement map if asked.
+
+use super::edit::print_groups;
+use super::Run;
+use anyhow::{bail, Result};
+use mesh_auditor::bake::{self, DisplacementFormat};
+use mesh_auditor::cage;
+use mesh_auditor::cylinders;
+use mesh_auditor::decimate::{self, DecimateOptions};
+use mesh_auditor::gltf;
+use mesh_auditor::heal;
+use mesh_auditor::labels::{FaceLabels, LabelFilter};
+use mesh_auditor::materials::{self, TextureOptions};
+use mesh_auditor::mesh::Mesh;
+use mesh_auditor::metadata;
+use mesh_auditor::msh;
+use mesh_auditor::optimize;
+use mesh_auditor::orient;
+use mesh_auditor::output;
+use mesh_auditor::placement;
+use mesh_auditor::planes;
+use mesh_auditor::ply;
+use mesh_auditor::segment;
+use mesh_auditor::shrinkage;
+use mesh_auditor::stl::save_mesh_as_stl;
+use mesh_auditor::storage;
+use mesh_auditor::unwrap;
+use mesh_auditor::vtk;
+use std::path::Path;
+
+/// What convert does to the mesh on the way through, besides decimating it.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConvertSteps<'a> {
+    /// Close gaps up to this wide
+    pub heal: Option<f32>,
+    pub keep_orientation: bool,
+    pub auto_orient: bool,
+    /// Scale by the guessed units too
+    pub rescale_units: bool,
+    /// Refit holes as cylinders, with this tolerance (a share of the radius)
+    pub refit_holes: Option<f64>,
+    /// Flatten planar faces, with this tolerance (a share of their size)
+    pub flatten_planes: Option<f64>,
+    /// Scale up to make up for this much print shrinkage
+    pub shrinkage: Option<shrinkage::Shrinkage>,
+    /// Write each connected shell as a node of its own (.glb)
+    pub split_shells: bool,
+    /// Name the physical groups by these labels (.msh), not by shell
+    pub groups: Option<&'a FaceLabels>,
+}
+
+pub fn convert(
+    input: &str,
+    output: &str,
+    steps: &ConvertSteps<'_>,
+    decimation: Option<(&DecimateOptions, Option<&LabelFilter>)>,
+    textures: &TextureOptions,
+    run: &Run,
+) -> Result<()> {
+    println!("📖 Loading {}...", input);
+    let mut mesh = Mesh::load(input, &run.limits)?;
+    println!(
+        "✅ Model Loaded. Vertices: {}, Faces: {}",
+        mesh.vertex_count(),
+        mesh.face_count()
+    );
+    let metadata = &mesh.metadata;
+    if metadata.name.is_some() || metadata.units.is_some() || !metadata.attributes.is_empty() {
+        println!(
+            "🏷️  Name: {}, units: {}, {} other attributes",
+            metadata.name.as_deref().unwrap_or("none"),
+            metadata.units.map_or("not given", |u| u.symbol()),
+            metadata.attributes.len()
+        );
+    }
+    if !mesh.vertex_attributes.is_empty() {
+        let names: Vec<&str> = mesh
+            .vertex_attributes
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        println!("📊 Vertex attributes: {}", names.join(", "));
+    }
+
+    // Before turning shells outward, which needs them closed
+    if let Some(tolerance) = steps.heal {
+        let healed = heal::heal(&mut mesh, tolerance)?;
+        println!(
+            "🩹 Healed to {}: {} vertices snapped together, {} split into open edges, {} faces squashed flat dropped",
+            tolerance, healed.snapped, healed.split, healed.dropped_faces
+        );
+        if healed.open_edges == 0 {
+            println!("   • No open edges left");
+        } else {
+            if let Some(gap) = healed.largest_gap {
+                let verdict = if gap <= tolerance { "within" } else { "over" };
+                println!(
+                    "   • {} open edges left; the widest gap is {}, {} the tolerance",
+                    healed.open_edges, gap, verdict
+                );
+            }
+            if healed.hole_edges > 0 {
+                println!(
+                    "   ⚠️  {} open edges border holes with nothing across to sew to",
+                    healed.hole_edges
+                );
+            }
+        }
+    }
+
+    if !steps.keep_orientation {
+        let fix = orient::orient_outward(&mut mesh);
+        if fix.flipped_shells > 0 {
+            println!(
+                "🔄 Turned {} of {} closed shells right way out ({} faces): they were inside out, probably from a mirrored export",
+                fix.flipped_shells, fix.checked, fix.flipped_faces
+            );
+        }
+        let unchecked = fix.shells - fix.checked;
+        if unchecked > 0 {
+            println!(
+                "   • {} open or inconsistently wound shells left as they are",
+                unchecked
+            );
+        }
+    }
+
+    if steps.auto_orient {
+        let proposal = placement::propose(&mesh);
+        println!("🧭 Proposed up: {}", proposal.describe_up());
+        println!("   • Likely units: {}", proposal.describe_units());
+        placement::apply(&mut mesh, &proposal, steps.rescale_units);
+        println!("   • Turned up to +Z and stood on z = 0");
+        if proposal.units == placement::Units::Metres {
+            if steps.rescale_units {
+                println!("   • Scaled metres to millimetres (x1000)");
+            } else {
+                println!("   • Size left as it is (--rescale-units would scale metres to millimetres, x1000)");
+            }
+        }
+    }
+
+    // Holes before flat faces, so the rims end up exactly on the planes
+    // they open onto (and off their cylinder only by the axis's tilt)
+    let segmentation = if steps.refit_holes.is_some() || steps.flatten_planes.is_some() {
+        Some(segment::segment(
+            &mesh,
+            &segment::SegmentOptions::default(),
+        )?)
+    } else {
+        None
+    };
+    if let (Some(tolerance), Some(segmentation)) = (steps.refit_holes, &segmentation) {
+        if !tolerance.is_finite() || tolerance <= 0.0 {
+            bail!("hole tolerance must be positive");
+        }
+        let holes = cylinders::find(&mesh, segmentation, tolerance);
+        let moved = cylinders::snap(&mut mesh, segmentation, &holes);
+        println!(
+            "⭕ Refitted {} hole(s) as exact cylinders ({} vertices moved)",
+            holes.len(),
+            moved
+        );
+        for hole in &holes {
+            let (c, a) = (hole.centre, hole.axis);
+            println!(
+                "   • Ø {:.4} × {:.4} deep at ({:.3}, {:.3}, {:.3}) along ({:.3}, {:.3}, {:.3}), was {:.4} off RMS",
+                hole.diameter, hole.depth, c[0], c[1], c[2], a[0], a[1], a[2], hole.rms_error
+            );
+        }
+    }
+    if let (Some(tolerance), Some(segmentation)) = (steps.flatten_planes, &segmentation) {
+        if !tolerance.is_finite() || tolerance <= 0.0 {
+            bail!("plane tolerance must be positive");
+        }
+        let planes = planes::find(&mesh, segmentation, tolerance);
+        let moved = planes::snap(&mut mesh, segmentation, &planes);
+        println!(
+            "📐 Flattened {} face(s) onto exact planes ({} vertices moved)",
+            planes.len(),
+            moved
+        );
+        for plane in &planes {
+            let n = plane.normal;
+            println!(
+                "   • {:.2} square units facing ({:.3}, {:.3}, {:.3}), was {:.4} off RMS",
+                plane.area, n[0], n[1], n[2], plane.rms_error
+            );
+        }
+    }
+
+    if let Some((options, only)) = decimation {
+        let before = mesh.face_count();
+        let decimation = match only {
+            Some(only) => {
+                let (decimation, picked, kept) = decimate::decimate_only(&mesh, options, only)?;
+                println!(
+                    "🏷️  Decimating only the {} faces labelled {}",
+                    picked,
+                    only.describe()
+                );
+                if options.target_faces > 0 && kept >= options.target_faces {
+                    println!(
+                        "   ⚠️  The {} faces left alone already reach {}: decimating the rest as far as it goes",
+                        kept, options.target_faces
+                    );
+                }
+                decimation
+            }
+            None => decimate::decimate(&mesh, options),
+        };
+        mesh = decimation.mesh;
+        println!(
+            "🔻 Decimated {} → {} faces ({} edge collapses)",
+            before,
+            mesh.face_count(),
+            decimation.collapses
+        );
+        if decimation.stuck {
+            println!(
+                "   ⚠️  Stopped short of {} faces: no collapse left would keep the surface, its seams and colour edges sound",
+                options.target_faces
+            );
+        }
+        if let (true, Some(max_error)) = (decimation.over_budget, options.max_error) {
+            println!(
+                "   • Stopped at the error budget: every collapse left would move the surface more than {}",
+                max_error
+            );
+        }
+    }
+
+    // Last, so nothing after it measures the part at the wrong size
+    if let Some(shrinkage) = &steps.shrinkage {
+        let [x, y, z] = shrinkage.apply(&mut mesh);
+        println!(
+            "📏 Scaled x{:.5} y{:.5} z{:.5} about the centre to make up for print shrinkage (noted in the file)",
+            x, y, z
+        );
+    }
+
+    let format = match metadata::Format::of(output) {
+        Some(format) => format,
+        None => bail!(
+            "don't know how to write {} (use .stl, .obj, .ply, .glb, .msh, .vtk or .vtu)",
+            output
+        ),
+    };
+    if steps.split_shells && format != metadata::Format::Glb {
+        bail!("--split-shells makes glTF nodes: write .glb");
+    }
+    if steps.groups.is_some() && format != metadata::Format::Msh {
+        bail!("--face-labels alone names a .msh's physical groups: write .msh");
+    }
+    for content in metadata::dropped(&mesh, format) {
+        let keeps = if content == metadata::Content::VertexAttributes {
+            ".ply"
+        } else {
+            ".obj"
+        };
+        println!(
+            "⚠️  {} has no room for {}; dropped (write {} to keep it)",
+            format, content, keeps
+        );
+    }
+    match format {
+        metadata::Format::Stl => save_mesh_as_stl(&mesh, output, &run.write)?,
+        metadata::Format::Ply => ply::save_mesh(&mesh, output, &run.write)?,
+        metadata::Format::Vtk => vtk::save_mesh(&mesh, output, &run.write)?,
+        metadata::Format::Msh => {
+            let groups = msh::save_surface(&mesh, steps.groups, output, &run.write)?;
+            print_groups(&groups);
+        }
+        metadata::Format::Glb => {
+            let nodes = gltf::save_mesh(&mesh, output, steps.split_shells, &run.write)?;
+            if steps.split_shells {
+                println!("🧩 One node per shell: {}", nodes);
+            }
+        }
+        metadata::Format::Obj => {
+            let carried = if storage::Location::parse(output)?.is_remote() {
+                if !mesh.materials.is_empty() {
+                    println!(
+                        "⚠️  Materials are only carried to local outputs; writing geometry only"
+                    );
+                }
+                None
+            } else {
+                let textures = TextureOptions {
+                    threads: run.threads,
+                    ..*textures
+                };
+                materials::carry(&mesh, output, &textures)?
+            };
+            if let Some(carried) = &carried {
+                println!(
+                    "🎨 Materials: {} ({} materials, {} textures)",
+                    carried.library,
+                    mesh.materials.len(),
+                    carried.textures.len()
+                );
+                for texture in &carried.textures {
+                    let pixels = match texture.pixels {
+                        Some((a, b)) if a != b => {
+                            format!(" ({}×{} → {}×{})", a[0], a[1], b[0], b[1])
+                        }
+                        _ => String::new(),
+                    };
+                    println!(
+                        "   • {}: {} → {}{}",
+                        texture.copy.display(),
+                        materials::size(texture.before),
+                        materials::size(texture.after),
+                        pixels
+                    );
+                    if let Some(why) = &texture.kept {
+                        println!("   ⚠️  Copied as is: {}", why);
+                    }
+                }
+                if !textures.copies_only() && !carried.textures.is_empty() {
+                    let before: u64 = carried.textures.iter().map(|t| t.before).sum();
+                    let after: u64 = carried.textures.iter().map(|t| t.after).sum();
+                    let change = after as f64 / before.max(1) as f64 - 1.0;
+                    println!(
+                        "   📊 Textures: {} → {} ({:.0}% {})",
+                        materials::size(before),
+                        materials::size(after),
+                        change.abs() * 100.0,
+                        if change > 0.0 { "larger" } else { "smaller" }
+                    );
+                }
+                for missing in &carried.missing {
+                    println!(
+                        "   ⚠️  Texture {} not found beside the input; reference left as is",
+                        missing
+                    );
+                }
+            }
+            mesh.save_obj_with(
+                output,
+                carried.as_ref().map(|c| c.library.as_str()),
+                &run.write,
+            )?
+        }
+    }
+    println!("💾 SUCCESS! Saved converted file to: {}", output);
+    Ok(())
+}
+
+/// What to bake, and how finely.
+pub struct BakeSettings {
+    pub texture_size: u32,
+    pub max_distance: Option<f32>,
+    pub displacement: Option<DisplacementFormat>,
+    pub cage: bool,
+    pub save_cage: Option<String>,
+}
+
+pub fn bake(
+    input: &str,
+    output: &str,
+    low: Option<&str>,
+    decimation: &DecimateOptions,
+    settings: &BakeSettings,
+    run: &Run,
+) -> Result<()> {
+    let texture_size = settings.texture_size;
+    let extension = Path::new(output)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    if extension.as_deref() != Some("glb") {
+        bail!("bake writes binary glTF: give an output ending in .glb");
+    }
+    println!("-----------------------------------------");
+    println!("🍞 MAP BAKER: initializing...");
+    println!("-----------------------------------------");
+
+    // 1. The scan, and the low-poly mesh to bake onto
+    let high = Mesh::load(input, &run.limits)?;
+    println!("   • Scan: {} faces", high.face_count());
+    let mut low = match low {
+        Some(path) => {
+            let low = Mesh::load(path, &run.limits)?;
+            println!("   • Low poly: {} faces, from {}", low.face_count(), path);
+            low
+        }
+        None => {
+            let target_faces = decimation.target_faces;
+            let decimation = decimate::decimate(&high, decimation);
+            println!(
+                "   • Low poly: {} faces, decimated ({} edge collapses)",
+                decimation.mesh.face_count(),
+                decimation.collapses
+            );
+            if decimation.stuck {
+                println!(
+                    "   ⚠️  Stopped short of {} faces: no collapse left would keep the surface sound",
+                    target_faces
+                );
+            }
+            decimation.mesh
+        }
+    };
+
+    // 2. UVs to bake into
+    let padding = unwrap::UnwrapOptions::default().padding;
+    if low.texcoords.is_empty() {
+        let options = unwrap::UnwrapOptions {
+            texture_size,
+            ..Default::default()
+        };
+        let unwrapped = unwrap::unwrap(&mut low, &options)?;
+        println!(
+            "   • Unwrapped into {} charts, covering {:.0}% of the texture at {:.1} px per unit",
+            unwrapped.charts,
+            unwrapped.coverage * 100.0,
+            unwrapped.density
+        );
+    } else {
+        println!("   • Using the low-poly mesh's own UVs");
+    }
+
+    // 3. Cast from the low-poly surface to the scan
+    let max_distance = cast_distance(&high, settings.max_distance)?;
+    let primitive = gltf::Primitive::new(&low);
+    println!(
+        "   • Reordered for the GPU: {:.2} → {:.2} cache misses per triangle",
+        optimize::acmr(&low.indices),
+        optimize::acmr(&primitive.indices)
+    );
+    let cage = if settings.cage {
+        let cage = cage::build(&low, &high, max_distance);
+        print_cage(&cage);
+        if let Some(path) = &settings.save_cage {
+            output::save(&cage.mesh, path, &run.write)?;
+            println!("   💾 Saved cage to: {}", path);
+        }
+        // One cage point per glTF vertex
+        let points: Vec<[f32; 3]> = primitive
+            .source
+            .iter()
+            .map(|&i| cage.mesh.vertex(i))
+            .collect();
+        println!(
+            "   • Baking {}x{} maps from the cage in, up to {} past the surface...",
+            texture_size, texture_size, max_distance
+        );
+        Some(points)
+    } else {
+        println!(
+            "   • Baking {}x{} maps, looking up to {} each way...",
+            texture_size, texture_size, max_distance
+        );
+        None
+    };
+    let options = bake::BakeOptions {
+        size: texture_size,
+        max_distance,
+        padding,
+    };
+    let baked = bake::bake(&primitive, &high, cage.as_deref(), &options);
+    let missed = baked.texels - baked.hits;
+    println!(
+        "   ✅ BAKE COMPLETE. {} texels, {:.1}% found the scan",
+        baked.texels,
+        baked.hits as f64 * 100.0 / baked.texels.max(1) as f64
+    );
+    if missed * 20 > baked.texels {
+        println!(
+            "   ⚠️  {} texels found nothing and are flat; try a larger --max-distance",
+            missed
+        );
+    }
+
+    // 4. The normal map, embedded in the .glb and beside it
+    let mut png = Vec::new();
+    baked
+        .normal_map()
+        .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+    gltf::write_glb(output, &primitive, Some(&png))?;
+    println!("   💾 Saved model to: {}", output);
+    let stem = Path::new(output).with_extension("").display().to_string();
+    let remote = storage::Location::parse(output)?.is_remote();
+    if remote {
+        println!(
+            "   ⚠️  Maps are only written beside local outputs; the normal map is in the .glb"
+        );
+    } else {
+        let texture = stem.clone() + "_normal.png";
+        std::fs::write(&texture, &png)?;
+        println!("   💾 Saved normal map to: {}", texture);
+    }
+
+    // 5. Displacement, which glTF has no slot for: a file of its own
+    if let (Some(format), false) = (settings.displacement, remote) {
+        let extension = match format {
+            DisplacementFormat::Png => "png",
+            DisplacementFormat::Exr => "exr",
+        };
+        let texture = format!("{}_displacement.{}", stem, extension);
+        std::fs::write(&texture, baked.displacement_map(format)?)?;
+        println!("   💾 Saved displacement map to: {}", texture);
+        let range = baked.displacement_range();
+        match format {
+            DisplacementFormat::Png => println!(
+                "   • Scan is within ±{} of the surface: use midlevel 0.5, scale {}",
+                range,
+                range * 2.0
+            ),
+            DisplacementFormat::Exr => println!(
+                "   • Scan is within ±{} of the surface: use midlevel 0, scale 1",
+                range
+            ),
+        }
+    }
+    Ok(())
+}
+
+/// How far to cast for the scan: given, or 2% of its diagonal.
+pub fn cast_distance(scan: &Mesh, given: Option<f32>) -> Result<f32> {
+    if scan.face_count() == 0 {
+        bail!("the scan has no faces to cast against: remesh a point cloud first");
+    }
+    let distance = given.unwrap_or_else(|| {
+        let (min, max) = scan.bounds();
+        let diagonal = (0..3)
+            .map(|a| (max[a] - min[a]).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        diagonal * 0.02
+    });
+    if !(distance.is_finite() && distance > 0.0) {
+        bail!("--max-distance must be a positive number");
+    }
+    Ok(distance)
+}
+
+pub fn print_cage(cage: &cage::Cage) {
+    let (low, high) = cage
+        .offsets
+        .iter()
+        .fold((f32::MAX, 0.0f32), |(lo, hi), &d| (lo.min(d), hi.max(d)));
+    println!(
+        "   • Cage: offset {} to {} from the low-poly surface",
+        low, high
+    );
+    if cage.pulled_in > 0 {
+        println!(
+            "   • {} cage vertices pulled in so it doesn't cross itself",
+            cage.pulled_in
+        );
+    }
+}
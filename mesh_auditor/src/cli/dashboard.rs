@@ -0,0 +1,267 @@
+//! `batch --tui`: a live terminal dashboard instead of the scrolling log.
+//!
+//! A background thread redraws the whole panel in place a few times a
+//! second with plain ANSI escapes: one progress bar per file (around the
+//! one running, for big batches), the live stage, the latest warnings and an
+//! ETA for the whole batch.
+
+use mesh_auditor::audit::Finding;
+use mesh_auditor::batch::{BatchObserver, FileReport};
+use mesh_auditor::progress;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+const REFRESH: Duration = Duration::from_millis(150);
+const BAR_WIDTH: usize = 24;
+const MAX_ROWS: usize = 12;
+const MAX_WARNINGS: usize = 5;
+
+// How much of a file's time each stage roughly takes, for its progress bar
+const STAGE_WEIGHTS: &[(&str, f64)] = &[
+    ("load", 0.05),
+    ("sample", 0.75),
+    ("extract", 0.15),
+    ("save", 0.05),
+];
+
+enum RowStatus {
+    Queued,
+    Running(&'static str),
+    Done(f64),
+    Failed,
+}
+
+struct Row {
+    name: String,
+    status: RowStatus,
+}
+
+struct State {
+    rows: Vec<Row>,
+    warnings: Vec<String>,
+    started: Instant,
+    /// Lines drawn last time, to move back up over.
+    drawn: usize,
+}
+
+pub struct Dashboard {
+    state: Arc<Mutex<State>>,
+    stop: Arc<AtomicBool>,
+    painter: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Dashboard {
+    pub fn new() -> Self {
+        Dashboard {
+            state: Arc::new(Mutex::new(State {
+                rows: Vec::new(),
+                warnings: Vec::new(),
+                started: Instant::now(),
+                drawn: 0,
+            })),
+            stop: Arc::new(AtomicBool::new(false)),
+            painter: Mutex::new(None),
+        }
+    }
+}
+
+impl Default for Dashboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BatchObserver for Dashboard {
+    fn batch_started(&self, files: &[PathBuf]) {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.rows = files
+                .iter()
+                .map(|f| Row {
+                    name: file_name(f),
+                    status: RowStatus::Queued,
+                })
+                .collect();
+            state.started = Instant::now();
+        }
+        let state = Arc::clone(&self.state);
+        let stop = Arc::clone(&self.stop);
+        *self.painter.lock().unwrap() = Some(std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                paint(&mut state.lock().unwrap());
+                std::thread::sleep(REFRESH);
+            }
+        }));
+    }
+
+    fn file_started(&self, index: usize, _input: &Path) {
+        self.state.lock().unwrap().rows[index].status = RowStatus::Running("load");
+    }
+
+    fn stage(&self, index: usize, stage: &'static str) {
+        self.state.lock().unwrap().rows[index].status = RowStatus::Running(stage);
+    }
+
+    fn warning(&self, index: usize, finding: &Finding) {
+        let mut state = self.state.lock().unwrap();
+        let line = format!("{}: {}", state.rows[index].name, finding);
+        state.warnings.push(line);
+    }
+
+    fn file_finished(&self, index: usize, report: &FileReport) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(e) = &report.error {
+            let line = format!("{}: failed: {}", state.rows[index].name, e);
+            state.warnings.push(line);
+        }
+        state.rows[index].status = match report.error {
+            Some(_) => RowStatus::Failed,
+            None => RowStatus::Done(report.seconds),
+        };
+    }
+
+    fn batch_finished(&self, _reports: &[FileReport]) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(painter) = self.painter.lock().unwrap().take() {
+            let _ = painter.join();
+        }
+        // One last frame so the final state stays on screen
+        paint(&mut self.state.lock().unwrap());
+    }
+}
+
+// How far through its own work a file is, from 0 to 1
+fn file_fraction(status: &RowStatus) -> f64 {
+    match status {
+        RowStatus::Queued => 0.0,
+        RowStatus::Done(_) | RowStatus::Failed => 1.0,
+        RowStatus::Running(stage) => {
+            let mut before = 0.0;
+            for &(name, weight) in STAGE_WEIGHTS {
+                if name == *stage {
+                    // Only the slow stages report progress within themselves
+                    let within = match name {
+                        "sample" | "extract" => progress::fraction(),
+                        _ => 0.0,
+                    };
+                    return before + weight * within;
+                }
+                before += weight;
+            }
+            before
+        }
+    }
+}
+
+fn paint(state: &mut State) {
+    let mut frame = String::new();
+    let finished = state
+        .rows
+        .iter()
+        .filter(|r| matches!(r.status, RowStatus::Done(_) | RowStatus::Failed))
+        .count();
+    let total = state.rows.len().max(1);
+    let overall: f64 = state
+        .rows
+        .iter()
+        .map(|r| file_fraction(&r.status))
+        .sum::<f64>()
+        / total as f64;
+    let elapsed = state.started.elapsed().as_secs_f64();
+    let eta = if finished == state.rows.len() {
+        "done".to_string()
+    } else if overall > 0.01 {
+        clock_time(elapsed * (1.0 - overall) / overall)
+    } else {
+        "--:--".to_string()
+    };
+
+    frame.push_str("-----------------------------------------\n");
+    frame.push_str(&format!(
+        "🧬 BATCH REMESH  {}/{} files  {}  elapsed {}  ETA {}\n",
+        finished,
+        state.rows.len(),
+        bar(overall),
+        clock_time(elapsed),
+        eta
+    ));
+    frame.push_str("-----------------------------------------\n");
+
+    // Big batches: a window around whatever is running now
+    let current = state
+        .rows
+        .iter()
+        .position(|r| matches!(r.status, RowStatus::Running(_) | RowStatus::Queued))
+        .unwrap_or(state.rows.len().saturating_sub(1));
+    let first = current
+        .saturating_sub(MAX_ROWS / 3)
+        .min(state.rows.len().saturating_sub(MAX_ROWS));
+    let last = (first + MAX_ROWS).min(state.rows.len());
+    if first > 0 {
+        frame.push_str(&format!("   … {} earlier\n", first));
+    }
+    for row in &state.rows[first..last] {
+        let status = match row.status {
+            RowStatus::Queued => "queued".to_string(),
+            RowStatus::Running(stage) => format!("▶️  {}", stage),
+            RowStatus::Done(seconds) => format!("✅ {:.1}s", seconds),
+            RowStatus::Failed => "❌ failed".to_string(),
+        };
+        frame.push_str(&format!(
+            "   {} {:>3.0}%  {}  {}\n",
+            bar(file_fraction(&row.status)),
+            file_fraction(&row.status) * 100.0,
+            row.name,
+            status
+        ));
+    }
+    if last < state.rows.len() {
+        frame.push_str(&format!("   … {} more\n", state.rows.len() - last));
+    }
+
+    if !state.warnings.is_empty() {
+        frame.push_str(&format!("\n⚠️  Warnings ({}):\n", state.warnings.len()));
+        let skip = state.warnings.len().saturating_sub(MAX_WARNINGS);
+        for warning in &state.warnings[skip..] {
+            frame.push_str(&format!("   • {}\n", warning));
+        }
+    }
+
+    // Back up over the last frame and clear it, then draw this one
+    let mut out = std::io::stdout().lock();
+    if state.drawn > 0 {
+        let _ = write!(out, "\x1b[{}F\x1b[J", state.drawn);
+    }
+    let _ = out.write_all(frame.as_bytes());
+    let _ = out.flush();
+    state.drawn = frame.lines().count();
+}
+
+fn bar(fraction: f64) -> String {
+    let filled = ((fraction.clamp(0.0, 1.0) * BAR_WIDTH as f64).round()) as usize;
+    format!("[{}{}]", "█".repeat(filled), "░".repeat(BAR_WIDTH - filled))
+}
+
+fn clock_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
@@ -0,0 +1,713 @@
+//! The commands that change a mesh and write it back out: `unwrap`,
+//! `trim`, `repair`, `thicken`, `split`, `tetmesh`, `segment`, `patches`,
+//! `bvh`, `progressive`, `meshlets` and `tile`.
+//!
+//! Each loads its input, hands it to the library module of the same name
+//! and reports what was done before writing the result.
+
+use super::Run;
+use anyhow::{bail, Result};
+use mesh_auditor::audit;
+use mesh_auditor::bvh;
+use mesh_auditor::dump::{self, Stage};
+use mesh_auditor::gltf;
+use mesh_auditor::holes;
+use mesh_auditor::labels::FaceLabels;
+use mesh_auditor::materials;
+use mesh_auditor::mesh::Mesh;
+use mesh_auditor::meshlet;
+use mesh_auditor::metadata;
+use mesh_auditor::output;
+use mesh_auditor::patches;
+use mesh_auditor::progressive;
+use mesh_auditor::segment;
+use mesh_auditor::split;
+use mesh_auditor::storage;
+use mesh_auditor::tetmesh;
+use mesh_auditor::thicken;
+use mesh_auditor::tiles;
+use mesh_auditor::tileset;
+use mesh_auditor::trim;
+use mesh_auditor::unwrap;
+use mesh_auditor::volumes;
+use std::path::Path;
+
+pub fn unwrap_and_save(
+    input: &str,
+    output: &str,
+    options: &unwrap::UnwrapOptions,
+    run: &Run,
+) -> Result<()> {
+    println!("📖 Loading {}...", input);
+    let mut mesh = Mesh::load(input, &run.limits)?;
+    if !mesh.texcoords.is_empty() {
+        println!("⚠️  The mesh already has UVs; they are replaced");
+    }
+    let unwrapped = unwrap::unwrap(&mut mesh, options)?;
+    println!(
+        "🗺️  Unwrapped {} faces into {} charts",
+        mesh.face_count(),
+        unwrapped.charts
+    );
+    println!(
+        "   • Covering {:.0}% of a {} px texture at {:.1} px per unit",
+        unwrapped.coverage * 100.0,
+        options.texture_size,
+        unwrapped.density
+    );
+    if Path::new(output)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("stl"))
+    {
+        bail!("STL has no UVs: write .obj or .glb");
+    }
+    output::save(&mesh, output, &run.write)?;
+    println!("💾 Saved mesh with UVs to: {}", output);
+
+    // The layout, for eyeballing
+    if storage::Location::parse(output)?.is_remote() {
+        println!("⚠️  The layout preview is only written beside local outputs");
+    } else {
+        let preview = Path::new(output).with_extension("").display().to_string() + "_uv.png";
+        unwrap::preview(&mesh, &unwrapped, options.texture_size).save(&preview)?;
+        println!("💾 Saved layout preview to: {}", preview);
+    }
+    Ok(())
+}
+
+pub fn trim_and_save(
+    input: &str,
+    fixtures: &[String],
+    tolerance: f32,
+    output: &str,
+    run: &Run,
+) -> Result<()> {
+    if !tolerance.is_finite() || tolerance < 0.0 {
+        bail!("tolerance must be zero or more");
+    }
+    println!("📖 Loading {}...", input);
+    let mut mesh = Mesh::load(input, &run.limits)?;
+    let fixtures = fixtures
+        .iter()
+        .map(|path| {
+            let fixture = Mesh::load(path, &run.limits)?;
+            println!("   • Fixture {}: {} faces", path, fixture.face_count());
+            Ok(fixture)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let before = mesh.face_count();
+    let trimmed = trim::trim(&mut mesh, &fixtures, tolerance, run.threads);
+    println!(
+        "✂️  Trimmed {} of {} faces ({} vertices) within {} of the fixtures",
+        trimmed.faces, before, trimmed.vertices, tolerance
+    );
+    if trimmed.faces == 0 {
+        println!("   ⚠️  Nothing was near a fixture: is it in the scan's coordinates?");
+    }
+    if mesh.face_count() == 0 {
+        bail!(
+            "nothing is left: every face was within {} of a fixture",
+            tolerance
+        );
+    }
+    output::save(&mesh, output, &run.write)?;
+    println!("💾 Saved trimmed scan to: {}", output);
+    Ok(())
+}
+
+// Rims of holes listed that were left open
+const HOLES_LISTED: usize = 5;
+
+pub fn repair_and_save(
+    input: &str,
+    max_perimeter: Option<f32>,
+    max_area: Option<f32>,
+    output: &str,
+    run: &Run,
+) -> Result<()> {
+    println!("📖 Loading {}...", input);
+    let mut mesh = Mesh::load(input, &run.limits)?;
+    let max_perimeter = match max_perimeter {
+        Some(distance) => f64::from(distance),
+        None => {
+            let (min, max) = mesh.bounds();
+            let diagonal = (0..3)
+                .map(|k| f64::from(max[k] - min[k]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            diagonal / 2.0
+        }
+    };
+    let filled = holes::fill_holes(
+        &mut mesh,
+        holes::HoleLimits {
+            max_perimeter,
+            max_area: max_area.map(f64::from),
+        },
+    )?;
+    let within = match max_area {
+        Some(area) => format!("rims up to {:.4}, areas up to {}", max_perimeter, area),
+        None => format!("rims up to {:.4}", max_perimeter),
+    };
+    println!(
+        "🩹 Filled {} holes ({}) with {} faces",
+        filled.filled.len(),
+        within,
+        filled.faces
+    );
+    if !filled.left_open.is_empty() {
+        println!(
+            "   ⚠️  {} left open, over the limits or with more than {} edges round them:",
+            filled.left_open.len(),
+            holes::MAX_RIM_EDGES
+        );
+        for hole in filled.left_open.iter().take(HOLES_LISTED) {
+            println!(
+                "      • {} edges, rim {:.4}, area {:.4}, at ({:.3}, {:.3}, {:.3})",
+                hole.edges,
+                hole.perimeter,
+                hole.area,
+                hole.centre[0],
+                hole.centre[1],
+                hole.centre[2]
+            );
+        }
+        if filled.left_open.len() > HOLES_LISTED {
+            println!(
+                "      ... and {} more",
+                filled.left_open.len() - HOLES_LISTED
+            );
+        }
+    }
+    output::save(&mesh, output, &run.write)?;
+    println!("💾 Saved repaired mesh to: {}", output);
+    Ok(())
+}
+
+pub fn thicken_and_save(input: &str, offset: f32, output: &str, run: &Run) -> Result<()> {
+    println!("📖 Loading {}...", input);
+    let sheet = Mesh::load(input, &run.limits)?;
+    let thickened = thicken::thicken(&sheet, offset)?;
+    println!(
+        "🧱 Thickened {} faces by {} into {} faces",
+        sheet.face_count(),
+        offset,
+        thickened.mesh.face_count()
+    );
+    if thickened.rim_edges > 0 {
+        println!("   • Rim stitched along {} open edges", thickened.rim_edges);
+    } else {
+        println!("   ⚠️  The surface was already closed: this makes a hollow shell");
+    }
+    if thickened.non_manifold_edges > 0 {
+        println!(
+            "   ⚠️  {} edges shared by three or more faces got no rim; the solid is open there",
+            thickened.non_manifold_edges
+        );
+    }
+    if thickened.folded_faces > 0 {
+        println!(
+            "   ⚠️  {} faces folded over where the surface curves tighter than the offset: try a smaller one, or remesh the result",
+            thickened.folded_faces
+        );
+    }
+    output::save(&thickened.mesh, output, &run.write)?;
+    println!("💾 Saved solid to: {}", output);
+    Ok(())
+}
+
+pub fn split_and_save(
+    input: &str,
+    normal: [f32; 3],
+    at: Option<f32>,
+    pins: &split::Pins,
+    output: &str,
+    run: &Run,
+) -> Result<()> {
+    let outputs = [
+        output::suffixed(output, "pins"),
+        output::suffixed(output, "sockets"),
+    ];
+    for path in &outputs {
+        if metadata::Format::of(path).is_none() {
+            bail!(
+                "don't know how to write {} (use .stl, .obj, .ply, .glb, .msh, .vtk or .vtu)",
+                path
+            );
+        }
+    }
+    println!("📖 Loading {}...", input);
+    let mesh = Mesh::load(input, &run.limits)?;
+    let halves = split::split(&mesh, normal, at, pins)?;
+    println!(
+        "🔪 Cut at {:.4} along the normal: {} face{}, area {:.4}",
+        halves.at,
+        halves.faces,
+        if halves.faces == 1 { "" } else { "s" },
+        halves.area
+    );
+    if pins.per_face > 0 {
+        println!(
+            "   📌 {} pins, {} across, standing {} out; sockets {} wider and {} deeper",
+            halves.pins.len(),
+            pins.diameter,
+            pins.length,
+            pins.clearance * 2.0,
+            pins.clearance
+        );
+        for [x, y, z] in &halves.pins {
+            println!("   • at ({:.3}, {:.3}, {:.3})", x, y, z);
+        }
+        if halves.faces_without_pins > 0 {
+            println!(
+                "   ⚠️  {} of the cut's faces had no room for a pin: try a thinner or shorter one, or cut elsewhere",
+                halves.faces_without_pins
+            );
+        }
+    }
+    for (half, path) in [&halves.below, &halves.above].into_iter().zip(&outputs) {
+        output::save(half, path, &run.write)?;
+        println!("💾 Saved {} faces to: {}", half.face_count(), path);
+    }
+    Ok(())
+}
+
+pub fn tetmesh_and_save(
+    input: &str,
+    resolution: usize,
+    output: &str,
+    groups: Option<&FaceLabels>,
+    run: &Run,
+) -> Result<()> {
+    println!("📖 Loading {}...", input);
+    let surface = Mesh::load(input, &run.limits)?;
+    let filled = tetmesh::tetrahedralise(&surface, resolution, run.threads)?;
+    let mesh = &filled.mesh;
+    let s = filled.spacing;
+    println!(
+        "🧊 Filled {} faces with {} tetrahedra on {} vertices (cells {:.4} x {:.4} x {:.4})",
+        surface.face_count(),
+        mesh.tets.len(),
+        mesh.positions.len(),
+        s[0],
+        s[1],
+        s[2]
+    );
+    let enclosed: f64 = audit::surfaces(&surface)
+        .iter()
+        .map(|shell| shell.volume.abs())
+        .sum();
+    let volume = mesh.volume();
+    println!(
+        "   • Volume: {:.4} (the surface encloses {:.4}, {:+.2}%)",
+        volume,
+        enclosed,
+        (volume / enclosed - 1.0) * 100.0
+    );
+    println!(
+        "   • Boundary vertices onto the surface: {} all the way, {} halfway, {} left on the grid",
+        filled.snapped, filled.partly_snapped, filled.held
+    );
+    println!(
+        "   • Quality (1 is regular): {:.3} at worst, {:.3} on average",
+        filled.min_quality, filled.mean_quality
+    );
+    if filled.held > 0 {
+        println!("   ⚠️  Where vertices stayed on the grid the boundary is stepped: try a higher --resolution");
+    }
+    let groups = tetmesh::write(mesh, &surface, groups, output)?;
+    print_groups(&groups);
+    println!("💾 Saved tetrahedra to: {}", output);
+    Ok(())
+}
+
+// Groups listed by name; a .msh has them all
+const GROUPS_LISTED: usize = 8;
+
+pub fn print_groups(groups: &[(String, usize)]) {
+    if groups.is_empty() {
+        return;
+    }
+    println!("🏷️  Physical groups: {}", groups.len());
+    for (name, elements) in groups.iter().take(GROUPS_LISTED) {
+        println!("   • {}: {} elements", name, elements);
+    }
+    if groups.len() > GROUPS_LISTED {
+        println!("   • ...and {} more", groups.len() - GROUPS_LISTED);
+    }
+}
+
+pub fn segment_and_save(
+    input: &str,
+    options: &segment::SegmentOptions,
+    out_dir: Option<&Path>,
+    labels: Option<&str>,
+    run: &Run,
+) -> Result<()> {
+    if out_dir.is_none() && labels.is_none() {
+        bail!("nowhere to put the regions: give --out-dir and/or --labels");
+    }
+    println!("📖 Loading {}...", input);
+    let mesh = Mesh::load(input, &run.limits)?;
+    let segmentation = segment::segment(&mesh, options)?;
+    println!(
+        "🧩 Split {} faces into {} smooth regions",
+        mesh.face_count(),
+        segmentation.regions.len()
+    );
+    let total: f64 = segmentation.regions.iter().map(|r| r.area).sum();
+    for (r, region) in segmentation.regions.iter().take(5).enumerate() {
+        let n = region.mean_normal;
+        println!(
+            "   • region_{}: {} faces, {:.1}% of the area, facing ({:.2}, {:.2}, {:.2})",
+            r,
+            region.faces,
+            region.area / total.max(f64::MIN_POSITIVE) * 100.0,
+            n[0],
+            n[1],
+            n[2]
+        );
+    }
+    if segmentation.regions.len() > 5 {
+        println!("   • ...and {} smaller", segmentation.regions.len() - 5);
+    }
+    if let Some(dir) = out_dir {
+        segmentation.write_set(&mesh, dir, &run.write)?;
+        println!(
+            "💾 Saved {} regions and regions.json to: {}",
+            segmentation.regions.len(),
+            dir.display()
+        );
+    }
+    if let Some(path) = labels {
+        segmentation.labels().save(path)?;
+        println!("💾 Saved face labels to: {}", path);
+    }
+    Ok(())
+}
+
+pub fn patches_and_save(
+    input: &str,
+    control_points: usize,
+    angle: f64,
+    output: &str,
+    run: &Run,
+) -> Result<()> {
+    println!("📖 Loading {}...", input);
+    let mesh = Mesh::load(input, &run.limits)?;
+    let options = segment::SegmentOptions {
+        angle,
+        ..Default::default()
+    };
+    let segmentation = segment::segment(&mesh, &options)?;
+    let fitted = patches::fit(&mesh, &segmentation, control_points)?;
+    println!(
+        "🧵 Fitted {} patches of {}x{} control points to {} regions",
+        fitted.patches.len(),
+        control_points,
+        control_points,
+        segmentation.regions.len()
+    );
+    for patch in &fitted.patches {
+        println!(
+            "   • region_{}: {} vertices, off by {:.4} RMS, {:.4} at worst",
+            patch.region, patch.vertices, patch.rms_error, patch.max_error
+        );
+    }
+    if fitted.small > 0 {
+        println!("   • {} regions too small for a patch", fitted.small);
+    }
+    if !fitted.folded.is_empty() {
+        let names: Vec<String> = fitted
+            .folded
+            .iter()
+            .map(|r| format!("region_{}", r))
+            .collect();
+        println!(
+            "⚠️  {} fold back over themselves and have no patch (try a smaller --angle): {}",
+            names.len(),
+            names.join(", ")
+        );
+    }
+    if fitted.patches.is_empty() {
+        bail!("no region was large and flat enough for a patch");
+    }
+    patches::write_iges(&fitted.patches, output)?;
+    println!(
+        "💾 Saved untrimmed patches to: {} (open in CAD to save as STEP)",
+        output
+    );
+    Ok(())
+}
+
+pub fn bvh_and_save(input: &str, output: &str, run: &Run) -> Result<()> {
+    if !output.to_lowercase().ends_with(".glb") {
+        bail!("the tree indexes a .glb's triangles: write .glb");
+    }
+    println!("📖 Loading {}...", input);
+    let mesh = Mesh::load(input, &run.limits)?;
+    if mesh.face_count() == 0 {
+        bail!("the mesh has no faces to build a tree over");
+    }
+
+    // 1. The tree is built over the triangles in the order the .glb has them
+    let primitive = gltf::Primitive::new(&mesh);
+    let tree = bvh::Bvh::new(&Mesh {
+        positions: primitive.positions.as_flattened().to_vec(),
+        indices: primitive.indices.clone(),
+        ..Default::default()
+    });
+    let sidecar = Path::new(output)
+        .with_extension("bvh")
+        .display()
+        .to_string();
+
+    // 2. Bounds per part, and where the tree is, go in the node's extras
+    let components = volumes::components(&mesh);
+    let file = Path::new(&sidecar)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let extras = serde_json::json!({
+        "components": components.iter().map(volumes::Component::to_json).collect::<Vec<_>>(),
+        "bvh": { "uri": file, "nodes": tree.node_count() }
+    });
+    gltf::write_glb_with(output, &primitive, None, &extras)?;
+    println!("💾 Saved mesh to: {}", output);
+    println!("📦 Parts: {}", components.len());
+    for (i, part) in components.iter().take(5).enumerate() {
+        let [a, b, c] = part.half_extents.map(|h| h * 2.0);
+        println!(
+            "   • Part {}: {} faces, box {:.3} × {:.3} × {:.3}, sphere radius {:.3}",
+            i + 1,
+            part.faces,
+            a,
+            b,
+            c,
+            part.radius
+        );
+    }
+    if components.len() > 5 {
+        println!("   • ... and {} more", components.len() - 5);
+    }
+
+    // 3. The tree itself
+    tree.write(&sidecar)?;
+    println!("🌳 BVH: {} nodes, {} deep", tree.node_count(), tree.depth());
+    println!("💾 Saved BVH to: {}", sidecar);
+    Ok(())
+}
+
+pub fn progressive_stream(
+    input: &str,
+    base_faces: usize,
+    refinements: Option<usize>,
+    output: &str,
+    run: &Run,
+) -> Result<()> {
+    let is_stream = |path: &str| path.to_lowercase().ends_with(".mlpm");
+    println!("📖 Loading {}...", input);
+    if is_stream(input) {
+        if is_stream(output) {
+            bail!(
+                "a decoded mesh goes to a mesh file: write .obj, .ply, .stl or another mesh format"
+            );
+        }
+        let stream = progressive::Progressive::load(input, &run.limits)?;
+        let applied = refinements.unwrap_or(stream.refinements.len());
+        if applied > stream.refinements.len() {
+            bail!(
+                "the stream has {} refinements, not {}",
+                stream.refinements.len(),
+                applied
+            );
+        }
+        let mesh = stream.decode(Some(applied))?;
+        println!(
+            "📶 Decoded the base and {} of {} refinements: {} vertices, {} faces",
+            applied,
+            stream.refinements.len(),
+            mesh.vertex_count(),
+            mesh.face_count()
+        );
+        output::save(&mesh, output, &run.write)?;
+        println!("💾 Saved mesh to: {}", output);
+        return Ok(());
+    }
+
+    if !is_stream(output) {
+        bail!("the stream is written as .mlpm");
+    }
+    if refinements.is_some() {
+        bail!("--refinements is for decoding an .mlpm");
+    }
+    let mesh = Mesh::load(input, &run.limits)?;
+    let skipped: Vec<String> = [
+        (mesh.has_colours(), "colours"),
+        (!mesh.texcoords.is_empty(), "UVs"),
+        (!mesh.materials.is_empty(), "materials"),
+        (!mesh.vertex_attributes.is_empty(), "vertex attributes"),
+    ]
+    .into_iter()
+    .filter(|&(present, _)| present)
+    .map(|(_, name)| name.to_string())
+    .collect();
+    let stream = progressive::encode(&mesh, base_faces);
+    stream.save(output)?;
+    let total = std::fs::metadata(output).map(|m| m.len()).ok();
+    println!(
+        "📶 Base: {} faces, {} vertices, in the first {}",
+        stream.base_faces.len(),
+        stream.base_positions.len(),
+        materials::size(stream.base_bytes() as u64)
+    );
+    println!(
+        "   • {} refinements back to {} faces{}",
+        stream.refinements.len(),
+        stream.face_count(),
+        total.map_or(String::new(), |bytes| format!(
+            ", {} in all",
+            materials::size(bytes)
+        ))
+    );
+    if stream.base_faces.len() > base_faces {
+        println!(
+            "   ⚠️  The base has more than {} faces: collapsing further would have broken the surface",
+            base_faces
+        );
+    }
+    if !skipped.is_empty() {
+        println!(
+            "   ⚠️  Only the geometry is streamed: the {} are left out",
+            skipped.join(", ")
+        );
+    }
+    println!("💾 Saved progressive mesh to: {}", output);
+    Ok(())
+}
+
+pub fn meshlets(
+    input: &str,
+    output: &str,
+    max_vertices: usize,
+    max_triangles: usize,
+    run: &Run,
+) -> Result<()> {
+    if !output.to_lowercase().ends_with(".glb") {
+        bail!("meshlets index a .glb's vertices: write .glb");
+    }
+    println!("📖 Loading {}...", input);
+    let mesh = Mesh::load(input, &run.limits)?;
+
+    // 1. The .glb first: the meshlets refer to its vertex order
+    let primitive = gltf::Primitive::new(&mesh);
+    let meshlets = meshlet::build(&primitive, max_vertices, max_triangles)?;
+    gltf::write_glb(output, &primitive, None)?;
+    println!("💾 Saved mesh to: {}", output);
+
+    // 2. Then the clusters beside it
+    let sidecar = Path::new(output)
+        .with_extension("meshlets")
+        .display()
+        .to_string();
+    meshlet::write_sidecar(&sidecar, &meshlets)?;
+    let triangles = primitive.indices.len() / 3;
+    let refs: usize = meshlets.iter().map(|m| m.vertices.len()).sum();
+    let cullable = meshlets.iter().filter(|m| m.cone_cutoff < 1.0).count();
+    println!(
+        "🧩 Meshlets: {} (up to {} vertices, {} triangles each)",
+        meshlets.len(),
+        max_vertices,
+        max_triangles
+    );
+    println!(
+        "   • {:.1} triangles and {:.1} vertices per meshlet on average",
+        triangles as f64 / meshlets.len().max(1) as f64,
+        refs as f64 / meshlets.len().max(1) as f64
+    );
+    println!(
+        "   • {} of them ({:.0}%) can be cone culled",
+        cullable,
+        cullable as f64 * 100.0 / meshlets.len().max(1) as f64
+    );
+    println!("💾 Saved meshlets to: {}", sidecar);
+    Ok(())
+}
+
+/// Where `tile` writes to: any of a stitched mesh, a tile set and a 3D
+/// Tiles tileset (with its levels of detail and placement).
+pub struct TileOutputs<'a> {
+    pub merged: Option<&'a str>,
+    pub out_dir: Option<&'a Path>,
+    pub tileset: Option<(&'a Path, usize, Option<tileset::Placement>)>,
+}
+
+pub fn tile(
+    filename: &str,
+    options: &tiles::TileOptions,
+    outputs: &TileOutputs,
+    run: &Run,
+) -> Result<()> {
+    if outputs.merged.is_none() && outputs.out_dir.is_none() && outputs.tileset.is_none() {
+        bail!("nowhere to put the tiles: give --output, --out-dir and/or --tileset");
+    }
+    println!("-----------------------------------------");
+    println!("🧬 VOXEL REMESHER: tiled reconstruction...");
+    println!("-----------------------------------------");
+
+    // 1. Load the scan
+    let mesh = Mesh::load(filename, &run.limits)?;
+    dump::mesh(Stage::Load, &mesh);
+    println!("   • Input Vertices: {}", mesh.vertex_count());
+
+    // 2. Reconstruct every tile the scan touches
+    let tiling = tiles::reconstruct(&mesh.positions, options)?;
+    let [x, y, z] = tiling.counts;
+    println!(
+        "   • Tiles: {}x{}x{} of {} units, {} voxels a side ({} units each)",
+        x, y, z, options.tile_size, options.cells, tiling.voxel
+    );
+    println!(
+        "   • {} tiles with surface, {} with only points nearby",
+        tiling.tiles.len(),
+        tiling.empty
+    );
+    if tiling.tiles.is_empty() {
+        bail!("no tile came out with a surface in it");
+    }
+    let faces: usize = tiling.tiles.iter().map(|t| t.mesh.face_count()).sum();
+    println!("   ✅ RE-SKINNING COMPLETE.");
+    println!("   • New Faces: {}", faces);
+
+    // 3. Write the stitched mesh, the tile set and/or the tileset
+    if let Some(output) = outputs.merged {
+        let merged = tiles::merge(&tiling);
+        output::save(&merged, output, &run.write)?;
+        println!(
+            "   💾 Saved stitched mesh ({} vertices) to: {}",
+            merged.vertex_count(),
+            output
+        );
+    }
+    if let Some(dir) = outputs.out_dir {
+        tiles::write_set(&tiling, dir)?;
+        println!(
+            "   💾 Saved {} tiles and tiles.json to: {}",
+            tiling.tiles.len(),
+            dir.display()
+        );
+    }
+    if let Some((dir, lods, placement)) = outputs.tileset {
+        let written = tileset::write(&tiling, dir, lods, placement)?;
+        println!(
+            "   💾 Saved 3D Tiles tileset ({} files, up to {} levels of detail) to: {}",
+            written.files,
+            written.levels,
+            dir.join("tileset.json").display()
+        );
+        if placement.is_none() {
+            println!("   ⚠️  No --origin given: the tileset sits at the centre of the Earth");
+        }
+    }
+    Ok(())
+}
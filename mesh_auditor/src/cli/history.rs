@@ -0,0 +1,234 @@
+//! `--history db.sqlite`: a running record of every file processed.
+//!
+//! Each file `audit` checks or `batch` remeshes becomes a row in `runs`
+//! (when, which command, which scanner, the size and watertightness of the
+//! mesh, how long it took, whether it failed) and one row in `findings` per
+//! warning code it raised, so the quality dashboards can ask SQLite
+//! directly instead of us growing a report for every question. The average
+//! defect rate per scanner, for instance:
+//!
+//! ```sql
+//! SELECT scanner, AVG(findings > 0) FROM runs GROUP BY scanner;
+//! ```
+//!
+//! The tables are created on first use and only ever appended to; the
+//! scanner is whatever `--scanner` (or `MESH_SCANNER`) says. SQLite comes
+//! from `rusqlite`, built in with the `history` feature (on by default); a
+//! build without it refuses `--history`.
+
+#[cfg(not(feature = "history"))]
+use anyhow::bail;
+#[cfg(feature = "history")]
+use anyhow::Context;
+use anyhow::Result;
+use mesh_auditor::audit::{AuditReport, Code};
+use mesh_auditor::batch::FileReport;
+#[cfg(feature = "history")]
+use mesh_auditor::clock;
+#[cfg(feature = "history")]
+use rusqlite::{params, Connection};
+
+#[cfg(feature = "history")]
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    recorded_at INTEGER NOT NULL,
+    command TEXT NOT NULL,
+    scanner TEXT,
+    input TEXT NOT NULL,
+    output TEXT,
+    vertices INTEGER,
+    faces INTEGER,
+    watertight INTEGER,
+    volume REAL,
+    output_triangles INTEGER,
+    findings INTEGER NOT NULL,
+    seconds REAL NOT NULL,
+    error TEXT
+);
+CREATE TABLE IF NOT EXISTS findings (
+    run INTEGER NOT NULL REFERENCES runs(id),
+    code TEXT NOT NULL,
+    name TEXT NOT NULL,
+    count INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS runs_by_scanner ON runs(scanner, recorded_at);
+";
+
+/// What is known about one processed file. Without the `history` feature
+/// nothing reads it back.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(not(feature = "history"), allow(dead_code))]
+pub struct Record {
+    pub command: &'static str,
+    pub input: String,
+    pub output: Option<String>,
+    pub vertices: Option<usize>,
+    pub faces: Option<usize>,
+    pub watertight: Option<bool>,
+    pub volume: Option<f64>,
+    pub output_triangles: Option<usize>,
+    pub findings: Vec<(Code, usize)>,
+    pub seconds: f64,
+    pub error: Option<String>,
+}
+
+impl Record {
+    pub fn from_audit(input: &str, report: &AuditReport, seconds: f64) -> Self {
+        Record {
+            command: "audit",
+            input: input.to_string(),
+            vertices: Some(report.vertices),
+            faces: Some(report.faces),
+            watertight: Some(report.watertight),
+            volume: Some(report.volume),
+            findings: report.findings.iter().map(|f| (f.code, f.count)).collect(),
+            seconds,
+            ..Default::default()
+        }
+    }
+
+    pub fn from_batch(report: &FileReport) -> Self {
+        // Counts are only known for the parts that ran
+        let loaded = report.input_faces > 0;
+        Record {
+            command: "batch",
+            input: report.input.display().to_string(),
+            output: report.output.as_ref().map(|p| p.display().to_string()),
+            vertices: loaded.then_some(report.input_vertices),
+            faces: loaded.then_some(report.input_faces),
+            watertight: report.watertight,
+            volume: None,
+            output_triangles: report.output.is_some().then_some(report.output_triangles),
+            findings: report.warnings.iter().map(|f| (f.code, f.count)).collect(),
+            seconds: report.seconds,
+            error: report.error.clone(),
+        }
+    }
+}
+
+/// The history database, open for appending.
+#[cfg(feature = "history")]
+pub struct History {
+    db: Connection,
+    scanner: Option<String>,
+}
+
+#[cfg(feature = "history")]
+impl History {
+    pub fn open(path: &str, scanner: Option<String>) -> Result<Self> {
+        let db = Connection::open(path)
+            .with_context(|| format!("could not open the history in {}", path))?;
+        // Several batches may record into the same file at once
+        db.busy_timeout(std::time::Duration::from_secs(5))?;
+        db.execute_batch(SCHEMA)
+            .with_context(|| format!("could not set up the history in {}", path))?;
+        Ok(History { db, scanner })
+    }
+
+    /// Append one file's row and its findings, all or nothing.
+    pub fn record(&self, record: &Record) -> Result<()> {
+        self.insert(record)
+            .context("could not record the run in the history")
+    }
+
+    fn insert(&self, record: &Record) -> Result<()> {
+        let count = |n: Option<usize>| n.map(|n| n as i64);
+        let transaction = self.db.unchecked_transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (recorded_at, command, scanner, input, output, vertices, faces, \
+             watertight, volume, output_triangles, findings, seconds, error) \
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                clock::unix_now() as i64,
+                record.command,
+                self.scanner,
+                record.input,
+                record.output,
+                count(record.vertices),
+                count(record.faces),
+                record.watertight,
+                record.volume,
+                count(record.output_triangles),
+                record.findings.len() as i64,
+                record.seconds,
+                record.error,
+            ],
+        )?;
+        let run = transaction.last_insert_rowid();
+        for &(code, n) in &record.findings {
+            transaction.execute(
+                "INSERT INTO findings (run, code, name, count) VALUES (?, ?, ?, ?)",
+                params![run, code.id(), code.name(), n as i64],
+            )?;
+        }
+        // Dropped without committing, it rolls back
+        transaction.commit()?;
+        Ok(())
+    }
+}
+
+/// Without the `history` feature there is no database to open.
+#[cfg(not(feature = "history"))]
+pub struct History(std::convert::Infallible);
+
+#[cfg(not(feature = "history"))]
+impl History {
+    pub fn open(_path: &str, _scanner: Option<String>) -> Result<Self> {
+        bail!("--history needs SQLite, which this build left out (build with --features history)")
+    }
+
+    pub fn record(&self, _record: &Record) -> Result<()> {
+        match self.0 {}
+    }
+}
+
+#[cfg(all(test, feature = "history"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_runs_and_their_findings() {
+        let path = std::env::temp_dir().join(format!("history_{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let path = path.to_str().unwrap();
+        let history = History::open(path, Some("artec-leo".to_string())).unwrap();
+        let record = Record {
+            command: "audit",
+            input: "scan.obj".to_string(),
+            faces: Some(12),
+            watertight: Some(false),
+            findings: vec![(Code::OpenBoundary, 3), (Code::DuplicateFace, 1)],
+            seconds: 0.5,
+            ..Default::default()
+        };
+        history.record(&record).unwrap();
+        history.record(&Record::default()).unwrap();
+        drop(history);
+
+        // Opening again keeps what is there
+        let db = History::open(path, None).unwrap().db;
+        let runs: i64 = db
+            .query_row("SELECT COUNT(*) FROM runs", [], |row| row.get(0))
+            .unwrap();
+        let (scanner, watertight): (String, bool) = db
+            .query_row(
+                "SELECT scanner, watertight FROM runs WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        let codes: Vec<String> = db
+            .prepare("SELECT code FROM findings WHERE run = 1 ORDER BY code")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        std::fs::remove_file(path).ok();
+        assert_eq!(runs, 2);
+        assert_eq!(scanner, "artec-leo");
+        assert!(!watertight);
+        assert_eq!(codes, ["W002", "W008"]);
+    }
+}
@@ -0,0 +1,573 @@
+//! The commands that look at meshes rather than change them: `audit`,
+//! `fingerprint`, `match`, `batch`, `bench` and `corrupt`.
+//!
+//! They print a report for people, or JSON with `--json`, and `audit` and
+//! `batch` add what they found to `--history` when it's given.
+
+use super::dashboard::Dashboard;
+use super::history::{self, History};
+use super::Run;
+use anyhow::{bail, Result};
+use mesh_auditor::audit::{self, Code};
+use mesh_auditor::baseline::{self, Baseline};
+use mesh_auditor::batch::{self, BatchOptions, LogObserver};
+use mesh_auditor::bench::{self, BenchReport, Tolerances};
+use mesh_auditor::completeness;
+use mesh_auditor::defects::{self, DefectConfig};
+use mesh_auditor::fingerprint;
+use mesh_auditor::limits::InputLimits;
+use mesh_auditor::mesh::Mesh;
+use mesh_auditor::messages;
+use mesh_auditor::output::WriteOptions;
+use mesh_auditor::report::{self, ReportFormat};
+use mesh_auditor::sandbox::JobLimits;
+use mesh_auditor::storage;
+use mesh_auditor::symmetry;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub fn fingerprint(
+    inputs: &[PathBuf],
+    pairs: bool,
+    threshold: f64,
+    json: bool,
+    run: &Run,
+) -> Result<()> {
+    let files = batch::input_files(inputs)?;
+    if files.is_empty() {
+        bail!("no .obj, .stl or .ply files found in the given inputs");
+    }
+    if !json {
+        println!("-----------------------------------------");
+        println!("🧾 FINGERPRINTING: {} files", files.len());
+        println!("-----------------------------------------");
+    }
+
+    let mut prints = Vec::with_capacity(files.len());
+    for path in &files {
+        let mesh = Mesh::load(&path.to_string_lossy(), &run.limits)?;
+        let print = fingerprint::fingerprint(&mesh)?;
+        if !json {
+            println!("   {}  {}", print.hash, path.display());
+        }
+        prints.push(print);
+    }
+
+    // Every pair, most alike first
+    let mut similar = Vec::new();
+    if pairs {
+        for i in 0..prints.len() {
+            for j in i + 1..prints.len() {
+                similar.push((i, j, fingerprint::similarity(&prints[i], &prints[j])));
+            }
+        }
+        similar.sort_by(|a, b| b.2.total_cmp(&a.2));
+    }
+
+    if json {
+        let files: Vec<_> = files
+            .iter()
+            .zip(&prints)
+            .map(|(path, print)| serde_json::json!({ "file": path, "fingerprint": print }))
+            .collect();
+        let pairs: Vec<_> = similar
+            .iter()
+            .map(|&(i, j, s)| {
+                serde_json::json!({
+                    "a": files[i]["file"],
+                    "b": files[j]["file"],
+                    "similarity": s,
+                    "duplicate": s >= threshold,
+                })
+            })
+            .collect();
+        let out = serde_json::json!({ "files": files, "pairs": pairs });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    if pairs {
+        println!("\n📊 PAIRWISE SIMILARITY");
+        for &(i, j, s) in &similar {
+            let mark = if s >= threshold {
+                "⚠️  likely duplicate"
+            } else {
+                ""
+            };
+            println!(
+                "   {:.3}  {}  ↔  {}  {}",
+                s,
+                files[i].display(),
+                files[j].display(),
+                mark
+            );
+        }
+        let duplicates = similar.iter().filter(|p| p.2 >= threshold).count();
+        println!("\n   Likely duplicates (≥ {}): {}", threshold, duplicates);
+    }
+    println!("-----------------------------------------");
+    Ok(())
+}
+
+pub fn match_shape(query: &str, library: &Path, top: usize, json: bool, run: &Run) -> Result<()> {
+    if !json {
+        println!("-----------------------------------------");
+        println!("🔎 SHAPE SEARCH: {} in {}", query, library.display());
+        println!("-----------------------------------------");
+    }
+    let wanted = fingerprint::fingerprint(&Mesh::load(query, &run.limits)?)?;
+    let known = fingerprint::library(library, &run.limits)?;
+    if known.is_empty() {
+        bail!("no meshes found in {}", library.display());
+    }
+
+    let mut ranked: Vec<_> = known
+        .iter()
+        .map(|(path, print)| {
+            (
+                path,
+                fingerprint::similarity(&wanted, print),
+                print.hash == wanted.hash,
+            )
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(top);
+
+    if json {
+        let matches: Vec<_> = ranked
+            .iter()
+            .map(|(path, similarity, same_hash)| {
+                serde_json::json!({ "file": path, "similarity": similarity, "same_hash": same_hash })
+            })
+            .collect();
+        let out = serde_json::json!({ "query": query, "hash": wanted.hash, "matches": matches });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    println!("   • Query hash: {}", wanted.hash);
+    println!("   • Library size: {}", known.len());
+    println!("\n📊 BEST MATCHES");
+    for (rank, (path, similarity, same_hash)) in ranked.iter().enumerate() {
+        let note = if *same_hash { "  ✅ same hash" } else { "" };
+        println!(
+            "   {:>2}. {:.3}  {}{}",
+            rank + 1,
+            similarity,
+            path.display(),
+            note
+        );
+    }
+    println!("-----------------------------------------");
+    Ok(())
+}
+
+/// What an audit writes, and what it checks besides the usual.
+pub struct AuditOptions<'a> {
+    pub json: bool,
+    pub report: Option<(ReportFormat, &'a str)>,
+    pub baseline: Option<(&'a Path, baseline::Tolerances)>,
+    /// The tolerance, and where the heatmap goes.
+    pub symmetry: Option<(Option<f64>, Option<&'a str>)>,
+    pub completeness: bool,
+}
+
+// Shells listed by name in the console report; the JSON has them all
+const SHELLS_LISTED: usize = 5;
+
+pub fn audit(
+    input: &str,
+    options: &AuditOptions,
+    allow: &[Code],
+    history: Option<&History>,
+    run: &Run,
+) -> Result<()> {
+    let json = options.json;
+    let report_file = options.report;
+    if let Some((Some(tolerance), _)) = options.symmetry {
+        if !tolerance.is_finite() || tolerance <= 0.0 {
+            bail!("symmetry tolerance must be positive");
+        }
+    }
+    // A bad baseline should fail before the audit, not after it
+    let baseline = match options.baseline {
+        Some((path, tolerances)) => Some((path, Baseline::load(path)?, tolerances)),
+        None => None,
+    };
+    let start = Instant::now();
+    let mesh = Mesh::load(input, &run.limits)?;
+    let mut report = audit::audit(&mesh);
+    report.allow(allow);
+    if let Some((tolerance, heatmap)) = options.symmetry {
+        report.symmetry = symmetry::measure(&mesh, tolerance, run.threads);
+        if let (Some(path), Some(symmetry)) = (heatmap, &report.symmetry) {
+            symmetry::save_heatmap(&mesh, symmetry, path, &run.write)?;
+        }
+    }
+    if options.completeness {
+        report.completeness = completeness::measure(&mesh);
+    }
+    if let Some(history) = history {
+        history.record(&history::Record::from_audit(
+            input,
+            &report,
+            start.elapsed().as_secs_f64(),
+        ))?;
+    }
+    if let Some((ReportFormat::Html, path)) = report_file {
+        report::write_html(input, &mesh, &report, path)?;
+    }
+    let regressions = baseline
+        .as_ref()
+        .map(|(_, stored, tolerances)| baseline::regressions(&report, stored, *tolerances));
+    if json {
+        // stdout stays a plain report, so it can become the next baseline
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        if let Some(found) = regressions.filter(|found| !found.is_empty()) {
+            for r in &found {
+                eprintln!("❌ {}", r);
+            }
+            bail!("{} regression(s) against the baseline", found.len());
+        }
+        return Ok(());
+    }
+
+    let t = |key: &str| messages::text(key, &[]);
+    println!("-----------------------------------------");
+    println!("🔍 {}", messages::text("audit.start", &[("input", &input)]));
+    println!("-----------------------------------------");
+    println!("   • {}: {}", t("audit.vertices"), report.vertices);
+    println!("   • {}: {}", t("audit.faces"), report.faces);
+    println!("   • {}: {}", t("audit.bounds"), report.describe_bounds());
+    println!(
+        "   • {}: {}",
+        t("audit.watertight"),
+        messages::yes_no(report.watertight)
+    );
+    if report.volume > 0.0 {
+        println!("   • {}: {:.4}", t("audit.volume"), report.volume);
+    }
+    if report.coloured {
+        println!("   • {}: {}", t("audit.colours"), messages::yes_no(true));
+    }
+    // One line per piece, so a file of several parts says which is broken
+    if report.shells.len() > 1 {
+        let watertight = report.shells.iter().filter(|s| s.watertight).count();
+        println!(
+            "   • {}: {}",
+            t("audit.shells"),
+            messages::text(
+                "audit.shells_count",
+                &[("count", &report.shells.len()), ("watertight", &watertight)],
+            )
+        );
+        for (i, shell) in report.shells.iter().take(SHELLS_LISTED).enumerate() {
+            let status = if shell.watertight {
+                messages::text(
+                    "audit.shell_closed",
+                    &[("volume", &format!("{:.4}", shell.volume))],
+                )
+            } else {
+                t("audit.shell_open")
+            };
+            println!(
+                "     - {}",
+                messages::text(
+                    "audit.shell",
+                    &[
+                        ("number", &(i + 1)),
+                        ("faces", &shell.faces),
+                        ("open", &shell.open_edges),
+                        ("non_manifold", &shell.non_manifold_edges),
+                        ("degenerate", &shell.degenerate_faces),
+                        ("duplicate", &shell.duplicate_faces),
+                        ("status", &status),
+                    ],
+                )
+            );
+        }
+        if report.shells.len() > SHELLS_LISTED {
+            println!(
+                "     - {}",
+                messages::text(
+                    "audit.shells_more",
+                    &[("count", &(report.shells.len() - SHELLS_LISTED))]
+                )
+            );
+        }
+    }
+    println!("   • {}: {}", t("audit.up"), report.placement.describe_up());
+    println!(
+        "   • {}: {}",
+        t("audit.units"),
+        report.placement.describe_units()
+    );
+    if let Some(symmetry) = &report.symmetry {
+        println!(
+            "   • {}: {}",
+            t("audit.symmetry"),
+            messages::text(
+                "audit.symmetry_score",
+                &[
+                    ("score", &format!("{:.1}", symmetry.score * 100.0)),
+                    ("tolerance", &format!("{:.4}", symmetry.tolerance)),
+                    ("rms", &format!("{:.4}", symmetry.rms_deviation)),
+                    ("max", &format!("{:.4}", symmetry.max_deviation)),
+                ],
+            )
+        );
+        let n = symmetry.normal;
+        println!(
+            "   • {}: ({:.3}, {:.3}, {:.3})·p = {:.4}",
+            t("audit.mirror_plane"),
+            n[0],
+            n[1],
+            n[2],
+            symmetry.offset
+        );
+    }
+    if let Some(completeness) = &report.completeness {
+        println!(
+            "   • {}: {}",
+            t("audit.completeness"),
+            messages::text(
+                "audit.completeness_estimate",
+                &[
+                    (
+                        "percent",
+                        &format!("{:.1}", completeness.completeness * 100.0)
+                    ),
+                    ("holes", &completeness.holes.to_string()),
+                    ("sparse", &completeness.sparse_regions.to_string()),
+                ],
+            )
+        );
+        // The biggest first, so the operator knows where to rescan
+        for gap in &completeness.gaps {
+            let key = match gap.kind {
+                completeness::GapKind::Hole => "audit.gap_hole",
+                completeness::GapKind::Sparse => "audit.gap_sparse",
+            };
+            let c = gap.centre;
+            println!(
+                "     - {}",
+                messages::text(
+                    key,
+                    &[
+                        ("area", &format!("{:.4}", gap.area)),
+                        ("percent", &format!("{:.1}", gap.share * 100.0)),
+                        (
+                            "centre",
+                            &format!("({:.3}, {:.3}, {:.3})", c[0], c[1], c[2])
+                        ),
+                        ("size", &gap.size.to_string()),
+                    ],
+                )
+            );
+        }
+    }
+    println!();
+    for finding in &report.findings {
+        println!("⚠️  {}", finding);
+    }
+    if report.findings.is_empty() {
+        println!("✅ {}", t("audit.clean"));
+    }
+
+    println!("\n-----------------------------------------");
+    println!("📊 {}", t("audit.final"));
+    println!("   {}: {}", t("audit.warnings"), report.findings.len());
+    if report.allowed > 0 {
+        println!("   {}: {}", t("audit.allowed"), report.allowed);
+    }
+    if let Some((_, path)) = report_file {
+        println!("   💾 {}: {}", t("audit.saved"), path);
+    }
+    if let (Some((_, Some(path))), Some(_)) = (options.symmetry, &report.symmetry) {
+        println!("   💾 {}: {}", t("audit.heatmap"), path);
+    }
+
+    // Against the baseline
+    let mut failed = 0;
+    if let (Some((path, _, _)), Some(found)) = (&baseline, &regressions) {
+        let path = path.display();
+        println!();
+        if found.is_empty() {
+            println!(
+                "✅ {}",
+                messages::text("baseline.clean", &[("path", &path)])
+            );
+        } else {
+            println!(
+                "❌ {}",
+                messages::text(
+                    "baseline.found",
+                    &[("count", &found.len()), ("path", &path)]
+                )
+            );
+            for r in found {
+                println!("   • {}", r);
+            }
+        }
+        failed = found.len();
+    }
+    println!("-----------------------------------------");
+
+    if failed > 0 {
+        bail!("{} regression(s) against the baseline", failed);
+    }
+    Ok(())
+}
+
+pub fn batch(
+    inputs: &[PathBuf],
+    options: &BatchOptions,
+    tui: bool,
+    csv: Option<&str>,
+    history: Option<&History>,
+    limits: &InputLimits,
+    job_limits: &JobLimits,
+) -> Result<()> {
+    let files = batch::input_files(inputs)?;
+    if files.is_empty() {
+        bail!("no .obj, .stl or .ply files found in the given inputs");
+    }
+
+    let reports = if tui && std::io::stdout().is_terminal() {
+        batch::run(&files, options, limits, job_limits, &Dashboard::new())?
+    } else {
+        if tui {
+            println!("⚠️  Not a terminal, falling back to the plain log");
+        }
+        batch::run(&files, options, limits, job_limits, &LogObserver)?
+    };
+    batch::print_summary(&reports);
+    if let Some(path) = csv {
+        batch::write_csv(&reports, path)?;
+        println!("💾 CSV report saved to: {}", path);
+    }
+    if let Some(history) = history {
+        for report in &reports {
+            history.record(&history::Record::from_batch(report))?;
+        }
+        println!("💾 Recorded {} file(s) in the history", reports.len());
+    }
+
+    let failed = reports.iter().filter(|r| r.error.is_some()).count();
+    if failed > 0 {
+        bail!("{} of {} files failed", failed, reports.len());
+    }
+    Ok(())
+}
+
+pub fn bench(
+    corpus: &Path,
+    baseline_path: &Path,
+    update_baseline: bool,
+    resolution: usize,
+    tolerances: Tolerances,
+    run: &Run,
+) -> Result<()> {
+    println!("-----------------------------------------");
+    println!("⏱️  BENCHMARK: {}", corpus.display());
+    println!("-----------------------------------------");
+
+    let files = bench::corpus_files(corpus)?;
+    if files.is_empty() {
+        bail!("no meshes found in {}", corpus.display());
+    }
+
+    let mut report = BenchReport {
+        resolution,
+        files: Vec::new(),
+    };
+    for path in &files {
+        let result = bench::run_file(path, resolution, &run.limits, &run.write, run.threads)?;
+        let total: f64 = result.stages.values().sum();
+        println!("\n{} ({:.3}s)", result.file, total);
+        for (stage, secs) in &result.stages {
+            println!("   • {:<8} {:.3}s", stage, secs);
+        }
+        for (metric, value) in &result.metrics {
+            println!("   • {}: {}", metric, value);
+        }
+        report.files.push(result);
+    }
+
+    println!("\n-----------------------------------------");
+    let mut failed = 0;
+    if baseline_path.exists() {
+        let baseline = BenchReport::load(baseline_path)?;
+        if baseline.resolution != resolution {
+            println!(
+                "⚠️  Baseline was recorded at resolution {}, this run used {}",
+                baseline.resolution, resolution
+            );
+        }
+        let found = bench::regressions(&report, &baseline, tolerances);
+        if found.is_empty() {
+            println!("✅ No regressions against {}", baseline_path.display());
+        } else {
+            println!("❌ {} REGRESSION(S):", found.len());
+            for r in &found {
+                println!("   • {}", r);
+            }
+        }
+        failed = found.len();
+    } else {
+        println!("ℹ️  No baseline at {}", baseline_path.display());
+    }
+
+    if update_baseline || !baseline_path.exists() {
+        report.save(baseline_path)?;
+        println!("💾 Saved baseline to: {}", baseline_path.display());
+    }
+    println!("-----------------------------------------");
+
+    if failed > 0 && !update_baseline {
+        bail!("{} benchmark regression(s)", failed);
+    }
+    Ok(())
+}
+
+pub fn corrupt(
+    source: &str,
+    clean: &Mesh,
+    config: &DefectConfig,
+    output: &str,
+    labels_path: &str,
+    write: &WriteOptions,
+) -> Result<()> {
+    println!("-----------------------------------------");
+    println!("🔨 CORRUPTING: {} (seed {})", source, config.seed);
+    println!("-----------------------------------------");
+
+    let (broken, mut labels) = defects::inject(clean, config);
+    labels.source = source.to_string();
+
+    let removed: usize = labels.holes.iter().map(|h| h.removed_faces.len()).sum();
+    println!(
+        "   • Holes: {} ({} faces removed)",
+        labels.holes.len(),
+        removed
+    );
+    println!("   • Flipped faces: {}", labels.flipped_faces.len());
+    println!(
+        "   • Duplicate vertices: {}",
+        labels.duplicate_vertices.len()
+    );
+    println!("   • Noise sigma: {}", labels.noise_sigma);
+    println!("   • Outlier points: {}", labels.outlier_vertices.len());
+
+    broken.save_obj(output, write)?;
+    let mut labels_file = storage::create(labels_path)?;
+    serde_json::to_writer_pretty(&mut labels_file, &labels)?;
+    labels_file.finish()?;
+    println!(
+        "   💾 Saved test case to: {} (labels: {})",
+        output, labels_path
+    );
+    Ok(())
+}
@@ -0,0 +1,187 @@
+//! `manpage`: a roff man page for the CLI, from the clap definitions.
+//!
+//! One page covers everything: the global flags, then a section per
+//! subcommand with its arguments, defaults and value lists, then the
+//! environment variables any flag can be read from. Install it with
+//! `mesh_lifter manpage > /usr/local/share/man/man1/mesh_lifter.1`.
+
+use super::completions::{choices, summary, value_name, visible_options, walk};
+use anyhow::Result;
+use clap::{Arg, Command};
+use std::io::Write;
+
+/// Write the man page for `cmd` to `out`.
+pub fn write(cmd: &mut Command, out: &mut impl Write) -> Result<()> {
+    cmd.build();
+    let name = cmd.get_name().to_string();
+    let mut page = format!(
+        ".TH {} 1 \"\" \"{} {}\"\n",
+        roff(&name.to_uppercase()),
+        roff(&name),
+        env!("CARGO_PKG_VERSION")
+    );
+
+    // 1. What it is and how it's called
+    page += &format!(
+        ".SH NAME\n{} \\- {}\n",
+        roff(&name),
+        roff(&summary(cmd.get_about()))
+    );
+    page += &format!(
+        ".SH SYNOPSIS\n\\fB{}\\fR [\\fIOPTIONS\\fR] \\fICOMMAND\\fR\n",
+        roff(&name)
+    );
+    if let Some(after) = cmd.get_after_help() {
+        page += &format!(".SH DESCRIPTION\n{}\n", paragraphs(&after.to_string()));
+    }
+
+    // 2. The flags every command takes
+    page += ".SH OPTIONS\n";
+    for arg in visible_options(cmd) {
+        page += &option(arg);
+    }
+
+    // 3. Each command, without the global flags again
+    page += ".SH COMMANDS\n";
+    for (path, c) in walk(cmd).into_iter().skip(1) {
+        // clap's own `help` command, and the commands under it again
+        if path.iter().any(|name| name == "help") {
+            continue;
+        }
+        page += &format!(".SS \"{}\"\n", roff(&path[1..].join(" ")));
+        let about = c
+            .get_long_about()
+            .or(c.get_about())
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+        if !about.is_empty() {
+            page += &format!("{}\n.PP\n", paragraphs(&about));
+        }
+        page += &format!("\\fB{}\\fR", roff(&path.join(" ")));
+        if visible_options(c).any(|a| !is_inherited(a)) {
+            page += " [\\fIOPTIONS\\fR]";
+        }
+        if c.has_subcommands() {
+            page += " \\fICOMMAND\\fR";
+        }
+        for arg in c.get_positionals().filter(|a| !a.is_hide_set()) {
+            let mut value = format!("\\fI{}\\fR", roff(&value_name(arg)));
+            if arg.get_num_args().is_some_and(|n| n.max_values() > 1) {
+                value += "...";
+            }
+            if arg.is_required_set() {
+                page += &format!(" {}", value);
+            } else {
+                page += &format!(" [{}]", value);
+            }
+        }
+        page += "\n";
+        for arg in c.get_positionals().filter(|a| !a.is_hide_set()) {
+            page += &option(arg);
+        }
+        for arg in visible_options(c).filter(|a| !is_inherited(a)) {
+            page += &option(arg);
+        }
+    }
+
+    // 4. Where flags can come from besides the command line
+    let mut variables: Vec<(String, String)> = walk(cmd)
+        .into_iter()
+        .flat_map(|(_, c)| c.get_arguments())
+        .filter_map(|a| {
+            let variable = a.get_env()?.to_string_lossy().into_owned();
+            let flag = a
+                .get_long()
+                .map_or_else(|| value_name(a), |l| format!("--{}", l));
+            Some((variable, flag))
+        })
+        .collect();
+    variables.sort();
+    variables.dedup();
+    if !variables.is_empty() {
+        page += ".SH ENVIRONMENT\n";
+        for (variable, flag) in variables {
+            page += &format!(
+                ".TP\n\\fB{}\\fR\nThe default for \\fB{}\\fR.\n",
+                roff(&variable),
+                roff(&flag)
+            );
+        }
+    }
+
+    out.write_all(page.as_bytes())?;
+    Ok(())
+}
+
+// Global flags, already listed under OPTIONS, and the usual --help
+fn is_inherited(arg: &Arg) -> bool {
+    arg.is_global_set() || arg.get_id() == "help"
+}
+
+// One .TP entry: the spellings, then the help, defaults and choices
+fn option(arg: &Arg) -> String {
+    let mut head = Vec::new();
+    if let Some(short) = arg.get_short() {
+        head.push(format!("\\fB\\-{}\\fR", short));
+    }
+    if let Some(long) = arg.get_long() {
+        head.push(format!("\\fB\\-\\-{}\\fR", roff(long)));
+    }
+    let mut entry = format!(".TP\n{}", head.join(", "));
+    if arg.get_action().takes_values() {
+        if !head.is_empty() {
+            entry += " ";
+        }
+        entry += &format!("\\fI{}\\fR", roff(&value_name(arg)));
+    }
+    entry += "\n";
+
+    let help = arg
+        .get_long_help()
+        .or(arg.get_help())
+        .map(|h| h.to_string())
+        .unwrap_or_default();
+    if !help.is_empty() {
+        entry += &paragraphs(&help);
+        entry += "\n";
+    }
+    let words = choices(arg);
+    if !words.is_empty() {
+        entry += &format!("One of: {}.\n", roff(&words.join(", ")));
+    }
+    let defaults: Vec<String> = arg
+        .get_default_values()
+        .iter()
+        .map(|d| d.to_string_lossy().into_owned())
+        .collect();
+    if !defaults.is_empty() && arg.get_action().takes_values() {
+        entry += &format!("Default: {}.\n", roff(&defaults.join(",")));
+    }
+    entry
+}
+
+// Blank lines start new paragraphs
+fn paragraphs(text: &str) -> String {
+    text.trim()
+        .split("\n\n")
+        .map(|p| {
+            p.lines()
+                .map(|l| {
+                    let l = roff(l.trim());
+                    // A line starting with . or ' would be read as a request
+                    if l.starts_with('.') || l.starts_with('\'') {
+                        format!("\\&{}", l)
+                    } else {
+                        l
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .collect::<Vec<_>>()
+        .join("\n.PP\n")
+}
+
+fn roff(text: &str) -> String {
+    text.replace('\\', "\\e").replace('-', "\\-")
+}
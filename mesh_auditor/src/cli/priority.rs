@@ -0,0 +1,58 @@
+//! Background mode (`--nice`): run at the lowest CPU and disk priority, so
+//! a long batch can share a workstation with the person sitting at it.
+//!
+//! The process is reniced to 19, so any other runnable work gets the cores
+//! first, and on Linux its disk I/O is put in the idle class (what `ionice
+//! -c 3` does), so its reads and writes wait until nothing else wants the
+//! disk. Both are inherited by threads started afterwards, which is why this
+//! is done first thing, before any stage spawns its own.
+
+use anyhow::{bail, Result};
+
+// The lowest priority nice allows
+#[cfg(unix)]
+const NICEST: libc::c_int = 19;
+
+/// Drop this process to background priority.
+#[cfg(unix)]
+pub fn lower() -> Result<()> {
+    // SAFETY: plain system calls on this process, with no pointers involved
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, NICEST) } != 0 {
+        bail!(
+            "couldn't lower the CPU priority: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    #[cfg(target_os = "linux")]
+    idle_io()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn lower() -> Result<()> {
+    bail!("--nice is only supported on Unix-like systems")
+}
+
+// The ioprio_set call has no libc wrapper
+#[cfg(target_os = "linux")]
+fn idle_io() -> Result<()> {
+    const IOPRIO_WHO_PROCESS: libc::c_long = 1;
+    const IOPRIO_CLASS_IDLE: libc::c_long = 3;
+    const IOPRIO_CLASS_SHIFT: libc::c_long = 13;
+    // SAFETY: as above
+    let result = unsafe {
+        libc::syscall(
+            libc::SYS_ioprio_set,
+            IOPRIO_WHO_PROCESS,
+            0,
+            IOPRIO_CLASS_IDLE << IOPRIO_CLASS_SHIFT,
+        )
+    };
+    if result != 0 {
+        bail!(
+            "couldn't lower the I/O priority: {}",
+            std::io::Error::last_os_error()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,101 @@
+//! `--share`: upload the result as glTF and print a link to look at it.
+//!
+//! After a command that writes a mesh, the mesh is read back, written as a
+//! `.glb` and put under `--share-to` (an `s3://bucket/prefix/` or an
+//! `https://` folder that takes PUTs, as `storage` writes them), named
+//! after the output and the start of its SHA-256, so sharing the same
+//! result twice gives the same link and links can't be guessed from the
+//! name alone. `--share-viewer` turns that into a link to the review site:
+//! a URL with `{url}` (the uploaded location, encoded for a query string)
+//! and/or `{name}` (just the file name) in it. Both are usually set once,
+//! in `MESH_SHARE_TO` and `MESH_SHARE_VIEWER`.
+
+use anyhow::{bail, Context, Result};
+use mesh_auditor::gltf;
+use mesh_auditor::limits::InputLimits;
+use mesh_auditor::mesh::Mesh;
+use mesh_auditor::storage;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+/// Where shared results go and how people reach them.
+#[derive(Debug, Clone)]
+pub struct ShareConfig {
+    /// The folder uploads go under.
+    pub to: String,
+    /// A viewer link template, with `{url}` and/or `{name}`.
+    pub viewer: Option<String>,
+}
+
+/// Where a shared result ended up.
+#[derive(Debug, Clone)]
+pub struct Shared {
+    pub location: String,
+    pub link: String,
+}
+
+impl ShareConfig {
+    pub fn validate(&self) -> Result<()> {
+        if !storage::Location::parse(&self.to)?.is_remote() {
+            bail!(
+                "--share-to must be an s3:// or http(s):// location, got '{}'",
+                self.to
+            );
+        }
+        if let Some(viewer) = &self.viewer {
+            if !viewer.contains("{url}") && !viewer.contains("{name}") {
+                bail!("--share-viewer needs {{url}} or {{name}} in it to point at the upload");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Upload the mesh at `output` (as written by the command) as glTF.
+pub fn share(output: &str, config: &ShareConfig, limits: &InputLimits) -> Result<Shared> {
+    // 1. The glTF, staged locally to name it by its contents
+    let mesh = Mesh::load(output, limits)
+        .with_context(|| format!("could not read {} back to share it", output))?;
+    let staged = std::env::temp_dir().join(format!("mesh_share_{}.glb", std::process::id()));
+    let staged_path = staged.to_string_lossy().into_owned();
+    gltf::write_glb(&staged_path, &gltf::Primitive::new(&mesh), None)?;
+    let bytes = std::fs::read(&staged);
+    let _ = std::fs::remove_file(&staged);
+    let bytes = bytes?;
+
+    // 2. Up it goes
+    let stem = Path::new(output)
+        .file_stem()
+        .map_or("model".to_string(), |s| s.to_string_lossy().into_owned());
+    let digest = Sha256::digest(&bytes);
+    let hash: String = digest[..6].iter().map(|b| format!("{:02x}", b)).collect();
+    let name = format!("{}-{}.glb", stem, hash);
+    let location = format!("{}/{}", config.to.trim_end_matches('/'), name);
+    let mut upload = storage::create(&location)?;
+    upload.write_all(&bytes)?;
+    upload
+        .finish()
+        .with_context(|| format!("could not upload the shared copy to {}", location))?;
+
+    // 3. The link
+    let link = match &config.viewer {
+        Some(viewer) => viewer
+            .replace("{url}", &encode(&location))
+            .replace("{name}", &encode(&name)),
+        None => location.clone(),
+    };
+    Ok(Shared { location, link })
+}
+
+// Percent-encode everything but the unreserved characters
+fn encode(text: &str) -> String {
+    text.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
@@ -0,0 +1,551 @@
+//! The commands that go through a sampled field: `remesh`, `extract`,
+//! `generate` and `compose`, and the estimates that say what a remesh
+//! would take before it runs.
+//!
+//! The field is sampled from a scan (or a primitive, or a composition of
+//! them), the surface extracted from it with marching cubes, and
+//! `skin::SkinOutput` writes that surface however the output asks.
+
+use super::Run;
+use crate::ComposeOp;
+use anyhow::{bail, Result};
+use mesh_auditor::compose::{self, Boss, Pedestal};
+use mesh_auditor::dump::{self, Stage};
+use mesh_auditor::estimate;
+use mesh_auditor::extract::{marching_cubes, marching_cubes_sparse, soup_volume};
+use mesh_auditor::materials;
+use mesh_auditor::mesh::Mesh;
+use mesh_auditor::metadata;
+use mesh_auditor::multigrid::SparseField;
+use mesh_auditor::output::{self, WriteOptions};
+use mesh_auditor::primitives::Primitive;
+use mesh_auditor::remesh::{self, Grid, Scan, ScanField};
+use mesh_auditor::samples::Storage;
+use mesh_auditor::sanity;
+use mesh_auditor::sdf::SampledField;
+use mesh_auditor::skin::SkinOutput;
+
+pub fn generate(
+    primitive: &Primitive,
+    grid: &Grid,
+    output: &str,
+    save_sdf: Option<&str>,
+    write: &WriteOptions,
+) -> Result<()> {
+    grid.validate()?;
+    if grid.storage == Storage::Bits {
+        bail!("bits only hold occupancy; a distance field needs full or half storage");
+    }
+    primitive.validate()?;
+    println!("-----------------------------------------");
+    println!(
+        "🧪 GENERATING: {:?} (size {})",
+        primitive.shape, primitive.size
+    );
+    println!("-----------------------------------------");
+
+    let triangles = if grid.coarse_levels > 0 {
+        let field =
+            primitive.sample_sparse(grid.resolution, grid.coarse_levels, grid.slabs.threads);
+        dump::sparse_field(Stage::Sample, &field);
+        print_sampled(&field);
+        if let Some(path) = save_sdf {
+            field.to_dense(grid.storage).save(path)?;
+            println!("   💾 Saved sampled field to: {}", path);
+        }
+        marching_cubes_sparse(&field)
+    } else {
+        let field = primitive.sample(grid.resolution, grid.storage, &grid.slabs);
+        dump::field(Stage::Sample, &field);
+        println!(
+            "   • Field memory: {}",
+            materials::size(field.values.bytes() as u64)
+        );
+        if let Some(path) = save_sdf {
+            field.save(path)?;
+            println!("   💾 Saved sampled field to: {}", path);
+        }
+        marching_cubes(&field, field.iso)
+    };
+    dump::triangles(Stage::Extract, &triangles);
+    println!("   • Triangles: {}", triangles.len() / 9);
+
+    // Compare against the analytic answer to see what the voxels cost us
+    let volume = soup_volume(&triangles);
+    match primitive.exact_volume() {
+        Some(exact) => {
+            let error = (volume - exact) / exact * 100.0;
+            println!(
+                "   • Volume: {:.4} (exact {:.4}, error {:+.2}%)",
+                volume, exact, error
+            );
+        }
+        None => println!("   • Volume: {:.4}", volume),
+    }
+
+    // Written as remesh and extract write theirs
+    let skin = SkinOutput {
+        path: output,
+        weld: None,
+        smooth_iterations: 0,
+        write: *write,
+    };
+    skin.save(&triangles, &[])?;
+    println!("   💾 Saved mesh to: {}", output);
+    Ok(())
+}
+
+pub fn run_compose(op: ComposeOp, run: &Run) -> Result<()> {
+    let (result, output) = match op {
+        ComposeOp::Union {
+            a,
+            b,
+            smooth,
+            output,
+        } => {
+            let (a, b) = (
+                SampledField::load(&a, &run.limits)?,
+                SampledField::load(&b, &run.limits)?,
+            );
+            (compose::smooth_union(&a, &b, smooth), output)
+        }
+        ComposeOp::Subtract {
+            a,
+            b,
+            smooth,
+            output,
+        } => {
+            let (a, b) = (
+                SampledField::load(&a, &run.limits)?,
+                SampledField::load(&b, &run.limits)?,
+            );
+            (compose::subtract(&a, &b, smooth), output)
+        }
+        ComposeOp::Offset {
+            input,
+            distance,
+            output,
+        } => (
+            compose::offset(&SampledField::load(&input, &run.limits)?, distance),
+            output,
+        ),
+        ComposeOp::OffsetXy {
+            input,
+            distance,
+            output,
+        } => (
+            compose::offset_xy(&SampledField::load(&input, &run.limits)?, distance),
+            output,
+        ),
+        ComposeOp::Pedestal {
+            input,
+            shape,
+            height,
+            margin,
+            smooth,
+            output,
+        } => {
+            let pedestal = Pedestal {
+                shape,
+                height,
+                margin,
+            };
+            pedestal.validate()?;
+            let field = SampledField::load(&input, &run.limits)?;
+            let Some(stood) = compose::add_pedestal(&field, &pedestal, smooth) else {
+                bail!("{} has no solid to stand on a pedestal", input);
+            };
+            (stood, output)
+        }
+        ComposeOp::Bosses {
+            input,
+            points,
+            direction,
+            diameter,
+            height,
+            pilot,
+            depth,
+            smooth,
+            output,
+        } => {
+            let boss = Boss {
+                diameter,
+                height,
+                pilot,
+                depth,
+            };
+            let positive = |x: f32| x.is_finite() && x > 0.0;
+            if !(positive(diameter) && positive(height) && positive(pilot) && positive(depth)) {
+                bail!("boss diameter, height, pilot and depth must be positive");
+            }
+            if pilot >= diameter {
+                bail!("the pilot hole has to be narrower than the boss");
+            }
+            if direction.is_some_and(|d| d == [0.0; 3]) {
+                bail!("the direction can't be zero");
+            }
+            let field = SampledField::load(&input, &run.limits)?;
+            let bossed = compose::add_bosses(&field, &points, direction, &boss, smooth);
+            println!(
+                "🔩 {} boss(es), Ø {} × {} with a Ø {} × {} pilot hole",
+                points.len(),
+                diameter,
+                height,
+                pilot,
+                depth
+            );
+            for &i in &bossed.floating {
+                let p = points[i];
+                println!(
+                    "   ⚠️  The boss at ({}, {}, {}) doesn't reach the solid: is the point on the surface?",
+                    p[0], p[1], p[2]
+                );
+            }
+            (bossed.field, output)
+        }
+        ComposeOp::Mask {
+            input,
+            min,
+            max,
+            output,
+        } => (
+            compose::mask_box(&SampledField::load(&input, &run.limits)?, min, max),
+            output,
+        ),
+    };
+    result.save(&output)?;
+    println!(
+        "💾 Saved composed field ({}x{}x{}) to: {}",
+        result.dims[0], result.dims[1], result.dims[2], output
+    );
+    Ok(())
+}
+
+// A scan loaded, made ready and measured up for its grid, as remesh and
+// its estimate both start
+struct Loaded {
+    mesh: Mesh,
+    // Triangles set aside, to write alongside the new skin.
+    kept: Vec<f32>,
+    field: ScanField,
+    iso: f32,
+    // With the resolution the voxel size asked for, if it did.
+    grid: Grid,
+}
+
+fn load_scan(input: &str, scan: &Scan, iso: Option<f32>, grid: &Grid, run: &Run) -> Result<Loaded> {
+    grid.validate()?;
+    scan.validate()?;
+    if scan.pedestal.is_some() && grid.coarse_levels > 0 {
+        bail!("--pedestal needs the whole grid: drop --coarse-levels");
+    }
+    println!("-----------------------------------------");
+    println!("🧬 VOXEL REMESHER: initializing...");
+    println!("-----------------------------------------");
+
+    // 1. Load the messy scan, and set aside what isn't to be remeshed
+    let mesh = Mesh::load(input, &run.limits)?;
+    dump::mesh(Stage::Load, &mesh);
+
+    println!("   • Input Vertices: {}", mesh.vertex_count());
+    let prepared = scan.prepare(mesh, run.threads)?;
+    if let Some(only) = scan.only {
+        println!(
+            "   • Remeshing only the {} faces labelled {}, keeping the other {}",
+            prepared.mesh.face_count(),
+            only.describe(),
+            prepared.kept.len() / 9
+        );
+    }
+    if let Some(scale) = prepared.corrected.scaled {
+        println!("   • Scaled by {} to correct the scanner", scale);
+    }
+    if scan.corrections.outliers.is_some() {
+        println!(
+            "   • Outlier vertices removed: {}",
+            prepared.corrected.outliers_removed
+        );
+    }
+    let mesh = prepared.mesh;
+
+    // 2. The field, and the resolution (Higher = more detail, slower)
+    // The default 50 is fast. For production, you'd want 100-200.
+    let field = scan.field.for_scan(&mesh);
+    if field != scan.field {
+        println!("   • The scan has no faces: skinning its points' occupancy instead");
+    }
+    if field == ScanField::Distance && grid.storage == Storage::Bits {
+        bail!("bits only hold occupancy; the distance field needs full or half storage (or --field occupancy)");
+    }
+    let iso = field.check_iso(iso)?;
+    println!("   • Field: {:?}", field);
+    let resolution = match grid.voxel_size {
+        Some(voxel) => remesh::resolution_for(&mesh, field, voxel),
+        None => grid.resolution,
+    };
+    let [nx, ny, nz] = remesh::grid_dims(&mesh, field, resolution);
+    println!("   • Grid size: {}x{}x{}", nx, ny, nz);
+    let voxel = remesh::voxel_size(&mesh, field, resolution);
+    println!("   • Voxel size: {:.4} (model units)", voxel);
+    if field == ScanField::Occupancy {
+        println!(
+            "   • Point influence: {} voxels ({:.4} model units), solid where density > {}",
+            scan.influence,
+            voxel * scan.influence,
+            iso
+        );
+    } else {
+        println!("   • Isovalue: {}", iso);
+    }
+    println!("   • Threads: {}", grid.slabs.threads);
+    Ok(Loaded {
+        mesh,
+        kept: prepared.kept,
+        field,
+        iso,
+        grid: Grid {
+            resolution,
+            voxel_size: None,
+            ..*grid
+        },
+    })
+}
+
+/// What the remesh would produce, without running it.
+pub fn estimate_remesh(
+    input: &str,
+    scan: &Scan,
+    iso: Option<f32>,
+    grid: &Grid,
+    run: &Run,
+) -> Result<()> {
+    let loaded = load_scan(input, scan, iso, grid, run)?;
+    print_estimate(
+        &loaded.mesh,
+        loaded.field,
+        scan.influence,
+        loaded.iso,
+        &loaded.grid,
+        &run.write,
+    )
+}
+
+pub fn remesh(
+    input: &str,
+    scan: &Scan,
+    iso: Option<f32>,
+    grid: &Grid,
+    output: &SkinOutput,
+    save_sdf: Option<&str>,
+    run: &Run,
+) -> Result<()> {
+    output.validate()?;
+    let Loaded {
+        mesh,
+        kept,
+        field,
+        iso,
+        grid,
+    } = load_scan(input, scan, iso, grid, run)?;
+    let resolution = grid.resolution;
+
+    println!("   • Running Marching Cubes (This acts as the 'Shrink Wrap')...");
+
+    // 3. Sample the field (the slow part), coarse to fine along the
+    // surface if asked to, which extracts as it goes
+    if grid.coarse_levels > 0 {
+        let sparse = remesh::sample_sparse(
+            &mesh,
+            field,
+            resolution,
+            scan.influence,
+            iso,
+            grid.coarse_levels,
+            grid.slabs.threads,
+        );
+        dump::sparse_field(Stage::Sample, &sparse);
+        print_sampled(&sparse);
+        if let Some(path) = save_sdf {
+            sparse.to_dense(grid.storage).save(path)?;
+            println!("   💾 Saved sampled field to: {}", path);
+        }
+        let voxel = sparse.spacing.iter().copied().fold(0.0f32, f32::max);
+        if let Some(finding) = sanity::resolution(&mesh, voxel, resolution) {
+            println!("   ⚠️  {}", finding);
+        }
+        println!("   • Extracting surface at isovalue {}", iso);
+        let skin = marching_cubes_sparse(&sparse);
+        return save_skin(&skin, &kept, output);
+    }
+
+    // Otherwise into a dense grid
+    let mut sampled = remesh::sample(
+        &mesh,
+        field,
+        resolution,
+        scan.influence,
+        grid.storage,
+        &grid.slabs,
+    );
+    if let Some(pedestal) = &scan.pedestal {
+        // The surface the pedestal joins is the one asked for
+        sampled.iso = iso;
+        let Some(stood) = compose::add_pedestal(&sampled, pedestal, 0.0) else {
+            bail!("the field has no solid to stand on a pedestal");
+        };
+        println!(
+            "   • Fused a {:?} pedestal {} deep, {} past the footprint",
+            pedestal.shape, pedestal.height, pedestal.margin
+        );
+        sampled = stood;
+    }
+    dump::field(Stage::Sample, &sampled);
+    println!(
+        "   • Field memory: {}",
+        materials::size(sampled.values.bytes() as u64)
+    );
+    if let Some(path) = save_sdf {
+        sampled.save(path)?;
+        println!("   💾 Saved sampled field to: {}", path);
+    }
+    let voxel = sampled.spacing.iter().copied().fold(0.0f32, f32::max);
+    if let Some(finding) = sanity::resolution(&mesh, voxel, resolution) {
+        println!("   ⚠️  {}", finding);
+    }
+
+    // 4. Generate the new mesh and save the result, with the faces kept
+    let iso = if scan.pedestal.is_some() {
+        sampled.iso
+    } else {
+        iso
+    };
+    extract_and_save(&sampled, iso, &kept, output)
+}
+
+pub fn print_estimate(
+    mesh: &Mesh,
+    field: ScanField,
+    influence: f32,
+    iso: f32,
+    grid: &Grid,
+    write: &WriteOptions,
+) -> Result<()> {
+    let estimate = estimate::remesh(mesh, field, influence, iso, grid, write)?;
+    if let Some(cells) = estimate.cells {
+        println!(
+            "   📏 ESTIMATE for resolution {} (from {} random cells):",
+            estimate.resolution, cells
+        );
+        println!(
+            "   • Triangles: about {} (most likely {} to {})",
+            estimate.triangles, estimate.range.0, estimate.range.1
+        );
+    } else {
+        println!(
+            "   📏 ESTIMATE for resolution {} (small enough to count exactly):",
+            estimate.resolution
+        );
+        println!("   • Triangles: {}", estimate.triangles);
+    }
+    println!(
+        "   • STL file: about {}",
+        materials::size(estimate.stl_bytes)
+    );
+    println!(
+        "   • Field memory: {}",
+        materials::size(estimate.field_bytes)
+    );
+    Ok(())
+}
+
+/// `kept` are triangles to write alongside, as they are.
+pub fn extract_and_save(
+    field: &SampledField,
+    iso: f32,
+    kept: &[f32],
+    output: &SkinOutput,
+) -> Result<()> {
+    if !iso.is_finite() {
+        bail!("isovalue must be a finite number");
+    }
+    println!("   • Extracting surface at isovalue {}", iso);
+    let skin = marching_cubes(field, iso);
+    save_skin(&skin, kept, output)
+}
+
+/// Surfaces at `frames` isovalues across `range`, each to a numbered file
+/// after `output`, or all as one flipbook if that's a .glb.
+pub fn sweep_and_save(
+    field: &SampledField,
+    (from, to): (f32, f32),
+    frames: usize,
+    frame_time: f32,
+    output: &SkinOutput,
+) -> Result<()> {
+    println!("   • Sweeping {} isovalues from {} to {}", frames, from, to);
+    let swept = output.sweep(field, (from, to), frames, frame_time)?;
+    for (frame, level) in swept.iter().enumerate() {
+        if level.triangles == 0 {
+            println!("   {:>3}. iso {:<12} no surface: skipped", frame, level.iso);
+        } else {
+            println!(
+                "   {:>3}. iso {:<12} {:>9} triangles, enclosing {:.4}",
+                frame, level.iso, level.triangles, level.volume
+            );
+        }
+    }
+    if metadata::Format::of(output.path) == Some(metadata::Format::Glb) {
+        println!(
+            "   💾 Saved a {} frame animation to: {}",
+            swept.iter().filter(|level| level.triangles > 0).count(),
+            output.path
+        );
+    } else {
+        println!(
+            "   💾 Saved the sweep to: {} ... {}",
+            output::numbered(output.path, 0, frames),
+            output::numbered(output.path, frames - 1, frames)
+        );
+    }
+    Ok(())
+}
+
+// Report and save an extracted surface, and the faces `kept` alongside as
+// they are
+fn save_skin(new_mesh: &[f32], kept: &[f32], output: &SkinOutput) -> Result<()> {
+    dump::triangles(Stage::Extract, new_mesh);
+
+    println!("   ✅ RE-SKINNING COMPLETE.");
+    println!("   • New Vertices: {}", new_mesh.len() / 3);
+
+    let written = output.save(new_mesh, kept)?;
+    let (vertices, faces) = written.mesh.unwrap_or_default();
+    if let (Some(tolerance), Some((shared, merged, dropped))) = (output.weld, written.welded) {
+        println!(
+            "   🔗 Welded within {}: {} shared corners, {} more joined, {} collapsed faces dropped",
+            tolerance, shared, merged, dropped
+        );
+        println!("   • {} vertices, {} faces", vertices, faces);
+    }
+    if let Some(moved) = written.smoothed {
+        println!(
+            "   🫧 Smoothed {} times ({} of {} vertices; open edges and kept faces stay put)",
+            output.smooth_iterations, moved, vertices
+        );
+    }
+    println!("   💾 Saved mesh to: {}", output.path);
+    Ok(())
+}
+
+// How much of the grid coarse-to-fine sampling got away with
+fn print_sampled(field: &SparseField) {
+    let (sampled, total) = field.sampled();
+    println!(
+        "   • Sampled {} of {} grid points ({:.1}%) from {} levels up, {} cells along the surface",
+        sampled,
+        total,
+        sampled as f64 / total.max(1) as f64 * 100.0,
+        field.levels,
+        field.cells().len()
+    );
+}
@@ -0,0 +1,376 @@
+//! `wizard`: a few questions instead of flags, for people new to the tool.
+//!
+//! What it's for (print, web or archive), how big the result may be and
+//! how good it should look pick the settings; the answers become a `Plan`,
+//! which is shown with the command line that does the same thing, so the
+//! next run can skip the questions. Every question has a default, taken on
+//! an empty answer, and the answers can be piped in one per line.
+
+use super::convert::{convert, ConvertSteps};
+use super::voxel::{print_estimate, remesh};
+use super::Run;
+use anyhow::{bail, Result};
+use mesh_auditor::decimate::DecimateOptions;
+use mesh_auditor::materials::{TextureFormat, TextureOptions};
+use mesh_auditor::mesh::Mesh;
+use mesh_auditor::profiles::ScanCorrections;
+use mesh_auditor::remesh::{self, Grid, Scan, ScanField};
+use mesh_auditor::samples::Storage;
+use mesh_auditor::sandbox::{self, JobLimits};
+use mesh_auditor::skin::SkinOutput;
+use mesh_auditor::slabs::Slabs;
+use std::io::{BufRead, Write};
+use std::path::Path;
+
+/// What the answers asked for.
+#[derive(Debug, Clone)]
+pub enum Plan {
+    /// A watertight voxel remesh, as STL.
+    Print {
+        input: String,
+        output: String,
+        resolution: usize,
+    },
+    /// A decimated OBJ with web-sized textures.
+    Web {
+        input: String,
+        output: String,
+        target_faces: usize,
+        textures: TextureOptions,
+    },
+    /// Every face, as OBJ; only inside-out shells are turned.
+    Archive { input: String, output: String },
+}
+
+impl Plan {
+    pub fn input(&self) -> &str {
+        match self {
+            Plan::Print { input, .. } | Plan::Web { input, .. } | Plan::Archive { input, .. } => {
+                input
+            }
+        }
+    }
+
+    /// The settings, one per line.
+    pub fn describe(&self) -> Vec<String> {
+        match self {
+            Plan::Print {
+                output, resolution, ..
+            } => vec![
+                "Purpose: 3D printing (watertight voxel remesh)".to_string(),
+                format!("Grid: {} points along the longest side", resolution),
+                format!("Output: {}", output),
+            ],
+            Plan::Web {
+                output,
+                target_faces,
+                textures,
+                ..
+            } => vec![
+                "Purpose: web viewing (decimated, light textures)".to_string(),
+                format!("Faces: at most {}", target_faces),
+                format!(
+                    "Textures: at most {} px, JPEG at quality {}",
+                    textures.max_size.unwrap_or(0),
+                    textures.quality
+                ),
+                format!("Output: {}", output),
+            ],
+            Plan::Archive { output, .. } => vec![
+                "Purpose: archiving (every face kept, as OBJ)".to_string(),
+                format!("Output: {}", output),
+            ],
+        }
+    }
+
+    /// The command line that runs the same thing without the questions.
+    pub fn command_line(&self) -> String {
+        let quote = |s: &str| {
+            if s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:".contains(c))
+            {
+                s.to_string()
+            } else {
+                format!("'{}'", s.replace('\'', "'\\''"))
+            }
+        };
+        match self {
+            Plan::Print {
+                input,
+                output,
+                resolution,
+            } => format!(
+                "mesh_lifter remesh {} {} --resolution {} --storage half",
+                quote(input),
+                quote(output),
+                resolution
+            ),
+            Plan::Web {
+                input,
+                output,
+                target_faces,
+                textures,
+            } => format!(
+                "mesh_lifter convert {} {} --target-faces {} --texture-max-size {} --texture-format jpeg --texture-quality {}",
+                quote(input),
+                quote(output),
+                target_faces,
+                textures.max_size.unwrap_or(0),
+                textures.quality
+            ),
+            Plan::Archive { input, output } => format!(
+                "mesh_lifter convert {} {}",
+                quote(input),
+                quote(output)
+            ),
+        }
+    }
+}
+
+/// Ask the questions on `ask`, reading answers from `answers`, and turn
+/// them into a plan. `input` skips the first question.
+pub fn plan(
+    input: Option<String>,
+    answers: &mut impl BufRead,
+    ask: &mut impl Write,
+) -> Result<Plan> {
+    let mut prompt = Prompt { answers, ask };
+
+    // 1. Which scan
+    let input = match input {
+        Some(input) => input,
+        None => loop {
+            let answer = prompt.line("📂 Which scan? (.obj, .stl or .ply): ")?;
+            if !answer.is_empty() {
+                break answer;
+            }
+        },
+    };
+    let stem = Path::new(&input)
+        .file_stem()
+        .map_or("model".to_string(), |s| s.to_string_lossy().into_owned());
+
+    // 2. What for
+    let purpose = prompt.choose(
+        "🎯 What is it for?",
+        &[
+            "3D printing: a closed, watertight STL",
+            "the web: light enough for browsers and phones",
+            "archiving: everything kept as it is",
+        ],
+        0,
+    )?;
+    if purpose == 2 {
+        let output = prompt.path("💾 Save as", &format!("{}_archive.obj", stem))?;
+        return Ok(Plan::Archive { input, output });
+    }
+
+    // 3. How big and how good
+    let size = prompt.choose(
+        "📦 How big may the file be?",
+        &[
+            "small, to email or open on a phone",
+            "medium",
+            "large, whatever it takes",
+        ],
+        1,
+    )?;
+    let quality = prompt.choose(
+        "✨ How good should it look?",
+        &["draft, quick to make", "good", "best, slow"],
+        1,
+    )?;
+
+    Ok(match purpose {
+        0 => {
+            // Finer grids cost both time and file size, so the size caps them
+            let wanted = [80, 150, 250][quality];
+            let cap = [100, 200, usize::MAX][size];
+            let output = prompt.path("💾 Save as", &format!("{}_print.stl", stem))?;
+            Plan::Print {
+                input,
+                output,
+                resolution: wanted.min(cap),
+            }
+        }
+        _ => {
+            let output = prompt.path("💾 Save as", &format!("{}_web.obj", stem))?;
+            Plan::Web {
+                input,
+                output,
+                target_faces: [20_000, 100_000, 300_000][size],
+                textures: TextureOptions {
+                    max_size: Some([1024, 2048, 4096][quality]),
+                    format: Some(TextureFormat::Jpeg),
+                    quality: [70, 85, 95][quality],
+                    ..TextureOptions::default()
+                },
+            }
+        }
+    })
+}
+
+/// Ask whether to go ahead; yes unless told otherwise.
+pub fn confirm(answers: &mut impl BufRead, ask: &mut impl Write) -> Result<bool> {
+    let mut prompt = Prompt { answers, ask };
+    loop {
+        match prompt
+            .line("▶️  Go ahead? [Y/n]: ")?
+            .to_lowercase()
+            .as_str()
+        {
+            "" | "y" | "yes" => return Ok(true),
+            "n" | "no" => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+struct Prompt<'a, R, W> {
+    answers: &'a mut R,
+    ask: &'a mut W,
+}
+
+impl<R: BufRead, W: Write> Prompt<'_, R, W> {
+    fn line(&mut self, question: &str) -> Result<String> {
+        write!(self.ask, "{}", question)?;
+        self.ask.flush()?;
+        let mut answer = String::new();
+        if self.answers.read_line(&mut answer)? == 0 {
+            bail!("no answer: the input ended");
+        }
+        Ok(answer.trim().to_string())
+    }
+
+    // A numbered list; the answer is the number, or nothing for `default`
+    fn choose(&mut self, question: &str, options: &[&str], default: usize) -> Result<usize> {
+        writeln!(self.ask, "\n{}", question)?;
+        for (i, option) in options.iter().enumerate() {
+            writeln!(self.ask, "   {}. {}", i + 1, option)?;
+        }
+        loop {
+            let answer = self.line(&format!(
+                "   Choose 1-{} [{}]: ",
+                options.len(),
+                default + 1
+            ))?;
+            if answer.is_empty() {
+                return Ok(default);
+            }
+            match answer.parse::<usize>() {
+                Ok(n) if (1..=options.len()).contains(&n) => return Ok(n - 1),
+                _ => writeln!(self.ask, "   ⚠️  Please answer with a number from the list")?,
+            }
+        }
+    }
+
+    fn path(&mut self, question: &str, default: &str) -> Result<String> {
+        let answer = self.line(&format!("\n{} [{}]: ", question, default))?;
+        Ok(if answer.is_empty() {
+            default.to_string()
+        } else {
+            answer
+        })
+    }
+}
+
+// Ask, show the plan, and run it (in the sandbox, once it's agreed)
+pub fn run(input: Option<String>, run: &Run, job_limits: &JobLimits) -> Result<()> {
+    let stdin = std::io::stdin();
+    let mut answers = stdin.lock();
+    let mut ask = std::io::stdout();
+    println!("-----------------------------------------");
+    println!("🧙 MESH WIZARD: a few questions, then the right settings");
+    println!("-----------------------------------------");
+    let plan = plan(input, &mut answers, &mut ask)?;
+
+    println!("\n-----------------------------------------");
+    println!("📋 THE PLAN");
+    for line in plan.describe() {
+        println!("   • {}", line);
+    }
+    println!("   • Next time, run: {}", plan.command_line());
+    println!("-----------------------------------------");
+    let print_grid = |resolution| Grid {
+        resolution,
+        storage: Storage::Half,
+        slabs: Slabs {
+            threads: run.threads,
+            ..Slabs::default()
+        },
+        ..Grid::default()
+    };
+    // What a print will come to, before waiting for it
+    if let Plan::Print { resolution, .. } = plan {
+        let mesh = Mesh::load(plan.input(), &run.limits)?;
+        let field = ScanField::Distance.for_scan(&mesh);
+        print_estimate(
+            &mesh,
+            field,
+            remesh::INFLUENCE,
+            field.iso(),
+            &print_grid(resolution),
+            &run.write,
+        )?;
+    }
+    if !confirm(&mut answers, &mut ask)? {
+        println!("👋 Nothing done");
+        return Ok(());
+    }
+    println!();
+
+    sandbox::run_job(job_limits, || match &plan {
+        Plan::Print {
+            input,
+            output,
+            resolution,
+        } => remesh(
+            input,
+            &Scan {
+                corrections: ScanCorrections::default(),
+                only: None,
+                field: ScanField::Distance,
+                influence: remesh::INFLUENCE,
+                pedestal: None,
+            },
+            None,
+            &print_grid(*resolution),
+            &SkinOutput {
+                path: output,
+                weld: None,
+                smooth_iterations: 0,
+                write: run.write,
+            },
+            None,
+            run,
+        ),
+        Plan::Web {
+            input,
+            output,
+            target_faces,
+            textures,
+        } => convert(
+            input,
+            output,
+            &ConvertSteps::default(),
+            Some((
+                &DecimateOptions {
+                    target_faces: *target_faces,
+                    preserve_boundary: false,
+                    max_error: None,
+                },
+                None,
+            )),
+            textures,
+            run,
+        ),
+        Plan::Archive { input, output } => convert(
+            input,
+            output,
+            &ConvertSteps::default(),
+            None,
+            &TextureOptions::default(),
+            run,
+        ),
+    })
+}
@@ -0,0 +1,230 @@
+//! `audit --completeness`: how much of the object the scanner actually saw.
+//!
+//! What it missed shows up two ways. Where it saw nothing there is a hole:
+//! a loop of open boundary edges, whose missing patch is about the loop's
+//! vector area (exact for a flat hole, a little under for a curved one).
+//! Where it saw only a few points the surface is there but stretched over
+//! them: faces many times the median face's area, grouped into regions of
+//! neighbours. The completeness is the surface seen properly over the
+//! surface there should be (everything plus the holes' patches), and the
+//! biggest holes and sparse regions are listed by area, with where they
+//! are, so the operator knows where to point the scanner on a rescan.
+//!
+//! Loops smaller than a typical face are left out: those are cracks, not
+//! missed surface. This assumes a closed object: the outer border of an
+//! open surface (a terrain tile, a face scan) counts as a hole too.
+
+use crate::audit;
+use crate::mesh::Mesh;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Faces this many times the median face's area are sparsely scanned.
+pub const SPARSE_AREA_RATIO: f64 = 16.0;
+// Gaps listed in the report
+const MAX_LISTED: usize = 5;
+
+/// The estimate, and the biggest gaps.
+#[derive(Debug, Clone, Serialize)]
+pub struct Completeness {
+    /// Share (0 to 1) of the estimated full surface that was seen properly.
+    pub completeness: f64,
+    pub surface_area: f64,
+    /// The holes' patches plus the sparse regions.
+    pub missing_area: f64,
+    pub holes: usize,
+    pub sparse_regions: usize,
+    /// Largest first, at most a handful.
+    pub gaps: Vec<Gap>,
+}
+
+/// One hole or sparse region.
+#[derive(Debug, Clone, Serialize)]
+pub struct Gap {
+    pub kind: GapKind,
+    pub area: f64,
+    /// Share of the estimated full surface.
+    pub share: f64,
+    pub centre: [f64; 3],
+    /// Boundary edges round a hole, faces in a sparse region.
+    pub size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GapKind {
+    Hole,
+    Sparse,
+}
+
+/// Estimate how complete the scan is; `None` for a mesh with no faces.
+pub fn measure(mesh: &Mesh) -> Option<Completeness> {
+    if mesh.face_count() == 0 {
+        return None;
+    }
+    let areas: Vec<f64> = (0..mesh.face_count()).map(|f| face_area(mesh, f)).collect();
+    let surface_area: f64 = areas.iter().sum();
+    let edges = audit::edge_faces(mesh);
+    let mut sorted = areas.clone();
+    let middle = sorted.len() / 2;
+    let (_, &mut median, _) = sorted.select_nth_unstable_by(middle, f64::total_cmp);
+
+    // 1. Holes, from the boundary loops. One smaller than a typical face is
+    // a crack or a seam the welder missed, not something the scanner didn't
+    // see, and W002 already reports those edges
+    let mut gaps: Vec<Gap> = boundary_loops(mesh, &edges)
+        .into_iter()
+        .map(|lp| {
+            let points: Vec<[f64; 3]> = lp.iter().map(|&v| mesh.vertex(v).map(f64::from)).collect();
+            Gap {
+                kind: GapKind::Hole,
+                area: vector_area(&points),
+                share: 0.0,
+                centre: mean(&points),
+                size: lp.len(),
+            }
+        })
+        .filter(|gap| gap.area >= median)
+        .collect();
+    let holes = gaps.len();
+
+    // 2. Sparse regions: big faces, joined across their shared edges
+    let cut = median * SPARSE_AREA_RATIO;
+    let sparse: Vec<bool> = areas.iter().map(|&a| a > cut).collect();
+    let mut region = vec![usize::MAX; mesh.face_count()];
+    let mut regions = 0;
+    for start in (0..mesh.face_count()).filter(|&f| sparse[f]) {
+        if region[start] != usize::MAX {
+            continue;
+        }
+        let mut stack = vec![start];
+        region[start] = regions;
+        let (mut area, mut centre, mut faces) = (0.0, [0.0f64; 3], 0);
+        while let Some(f) = stack.pop() {
+            let c = mesh.face_centroid(f);
+            for k in 0..3 {
+                centre[k] += f64::from(c[k]) * areas[f];
+            }
+            area += areas[f];
+            faces += 1;
+            let [a, b, c] = mesh.face(f);
+            for (p, q) in [(a, b), (b, c), (c, a)] {
+                for &(g, _) in edges.get(&(p.min(q), p.max(q))).into_iter().flatten() {
+                    if sparse[g] && region[g] == usize::MAX {
+                        region[g] = regions;
+                        stack.push(g);
+                    }
+                }
+            }
+        }
+        gaps.push(Gap {
+            kind: GapKind::Sparse,
+            area,
+            share: 0.0,
+            centre: centre.map(|x| x / area.max(f64::MIN_POSITIVE)),
+            size: faces,
+        });
+        regions += 1;
+    }
+
+    // 3. Seen over what there should be
+    let patched = gaps
+        .iter()
+        .filter(|g| g.kind == GapKind::Hole)
+        .map(|g| g.area)
+        .sum::<f64>();
+    let full = surface_area + patched;
+    let missing_area: f64 = gaps.iter().map(|g| g.area).sum();
+    for gap in &mut gaps {
+        gap.share = gap.area / full;
+    }
+    gaps.sort_by(|a, b| b.area.total_cmp(&a.area));
+    gaps.truncate(MAX_LISTED);
+    Some(Completeness {
+        completeness: ((full - missing_area) / full).clamp(0.0, 1.0),
+        surface_area,
+        missing_area,
+        holes,
+        sparse_regions: regions,
+        gaps,
+    })
+}
+
+/// Each loop of boundary edges, as vertices in order, running against the
+/// winding of the faces along it. A vertex where two holes touch has two
+/// edges out; each leaves by one of them.
+pub fn boundary_loops(mesh: &Mesh, edges: &audit::EdgeMap) -> Vec<Vec<usize>> {
+    // The way round a hole is against the one face's winding
+    let mut next: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (&(low, high), faces) in edges {
+        if let [(_, forward)] = faces[..] {
+            let (from, to) = if forward { (high, low) } else { (low, high) };
+            next.entry(from).or_default().push(to);
+        }
+    }
+    // In a fixed order, so the same mesh always splits the same way
+    for out in next.values_mut() {
+        out.sort_unstable();
+    }
+    let mut starts: Vec<usize> = next.keys().copied().collect();
+    starts.sort_unstable();
+
+    let mut loops = Vec::new();
+    for start in starts {
+        while next.get(&start).is_some_and(|out| !out.is_empty()) {
+            let mut lp = vec![start];
+            let mut at = start;
+            while let Some(to) = next.get_mut(&at).and_then(|out| out.pop()) {
+                if to == start {
+                    break;
+                }
+                lp.push(to);
+                at = to;
+                // A boundary that doesn't close (bad winding) must still end
+                if lp.len() > mesh.vertex_count() {
+                    break;
+                }
+            }
+            if lp.len() >= 3 {
+                loops.push(lp);
+            }
+        }
+    }
+    loops
+}
+
+/// Half the length of the sum of edge cross products: the area of a flat
+/// polygon, and of its shadow on the best plane for a bent one.
+pub fn vector_area(points: &[[f64; 3]]) -> f64 {
+    let mut sum = [0.0f64; 3];
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        sum[0] += a[1] * b[2] - a[2] * b[1];
+        sum[1] += a[2] * b[0] - a[0] * b[2];
+        sum[2] += a[0] * b[1] - a[1] * b[0];
+    }
+    0.5 * (sum[0] * sum[0] + sum[1] * sum[1] + sum[2] * sum[2]).sqrt()
+}
+
+fn mean(points: &[[f64; 3]]) -> [f64; 3] {
+    let n = points.len().max(1) as f64;
+    let mut centre = [0.0f64; 3];
+    for p in points {
+        for k in 0..3 {
+            centre[k] += p[k] / n;
+        }
+    }
+    centre
+}
+
+fn face_area(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    0.5 * (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt()
+}
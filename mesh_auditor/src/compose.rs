@@ -0,0 +1,398 @@
+//! Combining saved fields: a small CSG toolkit on top of scan data.
+//!
+//! Every operation works on signed distances (negative inside), so inputs
+//! are converted with `SampledField::to_signed_distance` first and the result
+//! is always a signed distance field with its surface at 0. When two fields
+//! are combined, the second one is resampled onto the grid of the result.
+
+use crate::sandbox;
+use crate::sdf::{FieldKind, SampledField};
+use anyhow::{bail, Result};
+
+/// The shape of a display pedestal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PedestalShape {
+    /// A slab round the footprint's bounding rectangle
+    Box,
+    /// A disc round the footprint's bounding rectangle
+    Cylinder,
+}
+
+/// A pedestal to stand a model on: `height` deep under its lowest point,
+/// reaching `margin` past its footprint on every side.
+#[derive(Debug, Clone, Copy)]
+pub struct Pedestal {
+    pub shape: PedestalShape,
+    pub height: f32,
+    pub margin: f32,
+}
+
+impl Pedestal {
+    pub fn validate(&self) -> Result<()> {
+        if !self.height.is_finite() || self.height <= 0.0 {
+            bail!("pedestal height must be positive");
+        }
+        if !self.margin.is_finite() || self.margin < 0.0 {
+            bail!("pedestal margin must be zero or more");
+        }
+        Ok(())
+    }
+}
+
+/// Smoothly merge two fields. `smoothing` is the blend radius in world
+/// units; 0 gives a plain (sharp) union.
+pub fn smooth_union(a: &SampledField, b: &SampledField, smoothing: f32) -> SampledField {
+    // The union has to cover both inputs, so grow the grid to fit
+    let a = a.to_signed_distance();
+    let b = b.to_signed_distance();
+    let (a_min, a_max) = a.bounds();
+    let (b_min, b_max) = b.bounds();
+    let mut origin = [0.0; 3];
+    let mut dims = [0; 3];
+    for axis in 0..3 {
+        origin[axis] = a_min[axis].min(b_min[axis]);
+        let extent = a_max[axis].max(b_max[axis]) - origin[axis];
+        dims[axis] = if a.spacing[axis] > 0.0 {
+            (extent / a.spacing[axis]).ceil() as usize + 1
+        } else {
+            1
+        };
+    }
+    let a = a.resample(origin, a.spacing, dims);
+    let b = b.resample(a.origin, a.spacing, a.dims);
+
+    combine(&a, &b, |da, db| smooth_min(da, db, smoothing))
+}
+
+/// Carve `b` out of `a`, with an optional smooth fillet along the cut.
+pub fn subtract(a: &SampledField, b: &SampledField, smoothing: f32) -> SampledField {
+    let a = a.to_signed_distance();
+    let b = b.to_signed_distance().resample(a.origin, a.spacing, a.dims);
+    combine(&a, &b, |da, db| -smooth_min(-da, db, smoothing))
+}
+
+/// Grow (positive distance) or shrink (negative distance) the surface.
+pub fn offset(a: &SampledField, distance: f32) -> SampledField {
+    let mut out = a.to_signed_distance();
+    out.values = out.values.iter().map(|v| v - distance).collect();
+    out
+}
+
+/// Grow (positive distance) or shrink (negative distance) the surface
+/// across X and Y only, as a slicer's horizontal expansion does: each layer
+/// of the part grows by the distance, so a wall moves all of it, a slope
+/// as much as it faces sideways, and a top or bottom stays put.
+///
+/// FDM prints come out about half an extrusion width too wide across the
+/// bed, so an offset of minus that makes up for it. The surface moves by
+/// the distance times the sideways share of its normal, from the field's
+/// gradient; that holds to first order, so for an offset of a few voxels
+/// or more, remesh finer.
+pub fn offset_xy(a: &SampledField, distance: f32) -> SampledField {
+    let a = a.to_signed_distance();
+    let mut out = a.clone();
+    // Central differences, one-sided at the grid's edges
+    let slope = |p: [usize; 3], k: usize| {
+        let mut ahead = p;
+        let mut behind = p;
+        ahead[k] = (p[k] + 1).min(a.dims[k] - 1);
+        behind[k] = p[k].saturating_sub(1);
+        let run = (ahead[k] - behind[k]) as f32 * a.spacing[k];
+        if run > 0.0 {
+            (a.get(ahead[0], ahead[1], ahead[2]) - a.get(behind[0], behind[1], behind[2])) / run
+        } else {
+            0.0
+        }
+    };
+    for z in 0..a.dims[2] {
+        for y in 0..a.dims[1] {
+            sandbox::checkpoint();
+            for x in 0..a.dims[0] {
+                let g = [0, 1, 2].map(|k| slope([x, y, z], k));
+                let length = (g[0] * g[0] + g[1] * g[1] + g[2] * g[2]).sqrt();
+                if length == 0.0 {
+                    continue;
+                }
+                let sideways = g[0].hypot(g[1]) / length;
+                let i = a.index(x, y, z);
+                out.values.set(i, a.values.get(i) - distance * sideways);
+            }
+        }
+    }
+    out
+}
+
+/// Keep only the part of the field inside an axis-aligned box.
+pub fn mask_box(a: &SampledField, min: [f32; 3], max: [f32; 3]) -> SampledField {
+    let mut out = a.to_signed_distance();
+    for z in 0..out.dims[2] {
+        for y in 0..out.dims[1] {
+            sandbox::checkpoint();
+            for x in 0..out.dims[0] {
+                let p = out.position(x, y, z);
+                let i = out.index(x, y, z);
+                out.values
+                    .set(i, out.values.get(i).max(box_distance(p, min, max)));
+            }
+        }
+    }
+    out
+}
+
+/// Fuse a pedestal under the solid in `a`, up being +z, growing the grid
+/// to hold it. The pedestal's top runs a grid step into the model so the
+/// two join rather than touch; `smoothing` fillets where they meet. `None`
+/// if `a` has no solid to stand on one.
+pub fn add_pedestal(a: &SampledField, pedestal: &Pedestal, smoothing: f32) -> Option<SampledField> {
+    // 1. The footprint: the solid's shadow on the xy plane, and its bottom
+    let mut low = [f32::MAX; 3];
+    let mut high = [f32::MIN; 3];
+    for z in 0..a.dims[2] {
+        for y in 0..a.dims[1] {
+            for x in 0..a.dims[0] {
+                if a.kind.is_inside(a.get(x, y, z), a.iso) {
+                    let p = a.position(x, y, z);
+                    for axis in 0..3 {
+                        low[axis] = low[axis].min(p[axis]);
+                        high[axis] = high[axis].max(p[axis]);
+                    }
+                }
+            }
+        }
+    }
+    if low[0] > high[0] {
+        return None;
+    }
+    let top = low[2] + a.spacing[2];
+    let bottom = low[2] - pedestal.height;
+    let centre = [0.5 * (low[0] + high[0]), 0.5 * (low[1] + high[1])];
+    let half = [
+        0.5 * (high[0] - low[0]) + pedestal.margin,
+        0.5 * (high[1] - low[1]) + pedestal.margin,
+    ];
+    // The disc holds the whole rectangle
+    let radius = (0.5 * (high[0] - low[0])).hypot(0.5 * (high[1] - low[1])) + pedestal.margin;
+    let reach = match pedestal.shape {
+        PedestalShape::Box => half,
+        PedestalShape::Cylinder => [radius; 2],
+    };
+    let distance = |p: [f32; 3]| match pedestal.shape {
+        PedestalShape::Box => box_distance(
+            p,
+            [centre[0] - half[0], centre[1] - half[1], bottom],
+            [centre[0] + half[0], centre[1] + half[1], top],
+        ),
+        PedestalShape::Cylinder => {
+            let across = (p[0] - centre[0]).hypot(p[1] - centre[1]) - radius;
+            let along = (p[2] - 0.5 * (top + bottom)).abs() - 0.5 * (top - bottom);
+            across.max(0.0).hypot(along.max(0.0)) + across.max(along).min(0.0)
+        }
+    };
+
+    // 2. A grid over both, with a step of room round the pedestal
+    let a = a.to_signed_distance();
+    let (a_min, a_max) = a.bounds();
+    let mut origin = [0.0; 3];
+    let mut dims = [0; 3];
+    for axis in 0..3 {
+        let (lo, hi) = if axis < 2 {
+            (centre[axis] - reach[axis], centre[axis] + reach[axis])
+        } else {
+            (bottom, top)
+        };
+        let room = 2.0 * a.spacing[axis];
+        origin[axis] = a_min[axis].min(lo - room);
+        let extent = a_max[axis].max(hi + room) - origin[axis];
+        dims[axis] = if a.spacing[axis] > 0.0 {
+            (extent / a.spacing[axis]).ceil() as usize + 1
+        } else {
+            1
+        };
+    }
+    let mut out = a.resample(origin, a.spacing, dims);
+
+    // 3. The union, sample by sample
+    for z in 0..out.dims[2] {
+        for y in 0..out.dims[1] {
+            sandbox::checkpoint();
+            for x in 0..out.dims[0] {
+                let i = out.index(x, y, z);
+                let d = distance(out.position(x, y, z));
+                out.values
+                    .set(i, smooth_min(out.values.get(i), d, smoothing));
+            }
+        }
+    }
+    Some(out)
+}
+
+/// A boss for a heat-set insert: a `diameter` cylinder standing `height`
+/// off the surface, with a `pilot` hole `depth` deep down its middle.
+#[derive(Debug, Clone, Copy)]
+pub struct Boss {
+    pub diameter: f32,
+    pub height: f32,
+    pub pilot: f32,
+    pub depth: f32,
+}
+
+/// What `add_bosses` made.
+pub struct Bossed {
+    pub field: SampledField,
+    /// Bosses whose foot doesn't reach the solid, by their order in the
+    /// list: the point was too far off the surface.
+    pub floating: Vec<usize>,
+}
+
+/// Stand a `boss` at each of `points` on the surface of `a`, along
+/// `direction` or else the surface normal there, and drill its pilot
+/// hole. The foot sinks half a diameter into the wall so it joins it;
+/// `smoothing` fillets where it does. The grid grows to hold them.
+pub fn add_bosses(
+    a: &SampledField,
+    points: &[[f32; 3]],
+    direction: Option<[f32; 3]>,
+    boss: &Boss,
+    smoothing: f32,
+) -> Bossed {
+    let a = a.to_signed_distance();
+    let sink = 0.5 * boss.diameter;
+
+    // 1. Each boss's axis: foot inside the wall, up to its top
+    let axes: Vec<([f32; 3], [f32; 3])> = points
+        .iter()
+        .map(|&p| {
+            let up = match direction {
+                Some(d) => d,
+                None => {
+                    let h = a.spacing.iter().copied().fold(f32::MAX, f32::min);
+                    let along = |k: usize| {
+                        let mut ahead = p;
+                        let mut behind = p;
+                        ahead[k] += h;
+                        behind[k] -= h;
+                        a.value_at(ahead) - a.value_at(behind)
+                    };
+                    [along(0), along(1), along(2)]
+                }
+            };
+            let length = (up[0] * up[0] + up[1] * up[1] + up[2] * up[2]).sqrt();
+            let up = if length > 0.0 {
+                up.map(|x| x / length)
+            } else {
+                [0.0, 0.0, 1.0]
+            };
+            ([0, 1, 2].map(|k| p[k] - up[k] * sink), up)
+        })
+        .collect();
+    let floating = axes
+        .iter()
+        .enumerate()
+        .filter(|(_, (foot, _))| a.value_at(*foot) > 0.0)
+        .map(|(i, _)| i)
+        .collect();
+
+    // 2. A grid over the field and every boss
+    let (mut low, mut high) = a.bounds();
+    let length = sink + boss.height;
+    let radius = 0.5 * boss.diameter;
+    for (foot, up) in &axes {
+        for k in 0..3 {
+            let top = foot[k] + up[k] * length;
+            low[k] = low[k].min(foot[k].min(top) - radius - 2.0 * a.spacing[k]);
+            high[k] = high[k].max(foot[k].max(top) + radius + 2.0 * a.spacing[k]);
+        }
+    }
+    let mut dims = [0; 3];
+    for k in 0..3 {
+        dims[k] = if a.spacing[k] > 0.0 {
+            ((high[k] - low[k]) / a.spacing[k]).ceil() as usize + 1
+        } else {
+            1
+        };
+    }
+    let mut out = a.resample(low, a.spacing, dims);
+
+    // 3. Every boss on, then every hole out, so no boss fills another's
+    // hole. A hole runs a grid step past the top so it opens cleanly
+    let overshoot = a.spacing.iter().copied().fold(0.0f32, f32::max);
+    for z in 0..out.dims[2] {
+        for y in 0..out.dims[1] {
+            sandbox::checkpoint();
+            for x in 0..out.dims[0] {
+                let p = out.position(x, y, z);
+                let i = out.index(x, y, z);
+                let mut value = out.values.get(i);
+                for (foot, up) in &axes {
+                    let boss_distance = capped_cylinder(p, *foot, *up, length, radius);
+                    value = smooth_min(value, boss_distance, smoothing);
+                }
+                for (foot, up) in &axes {
+                    let start = [0, 1, 2].map(|k| foot[k] + up[k] * (length - boss.depth));
+                    let hole =
+                        capped_cylinder(p, start, *up, boss.depth + overshoot, 0.5 * boss.pilot);
+                    value = value.max(-hole);
+                }
+                out.values.set(i, value);
+            }
+        }
+    }
+    Bossed {
+        field: out,
+        floating,
+    }
+}
+
+// Apply `op` sample by sample to two fields on the same grid
+fn combine(a: &SampledField, b: &SampledField, op: impl Fn(f32, f32) -> f32) -> SampledField {
+    SampledField {
+        dims: a.dims,
+        origin: a.origin,
+        spacing: a.spacing,
+        kind: FieldKind::SignedDistance,
+        iso: 0.0,
+        values: a
+            .values
+            .iter()
+            .zip(b.values.iter())
+            .map(|(da, db)| op(da, db))
+            .collect(),
+    }
+}
+
+// Polynomial smooth minimum (Quilez). Falls back to `min` when k is 0.
+fn smooth_min(a: f32, b: f32, k: f32) -> f32 {
+    if k <= 0.0 {
+        return a.min(b);
+    }
+    let h = (0.5 + 0.5 * (b - a) / k).clamp(0.0, 1.0);
+    b + (a - b) * h - k * h * (1.0 - h)
+}
+
+// Signed distance from a point to a cylinder of `radius` running `length`
+// from `start` along the unit vector `axis`
+fn capped_cylinder(p: [f32; 3], start: [f32; 3], axis: [f32; 3], length: f32, radius: f32) -> f32 {
+    let d = [0, 1, 2].map(|k| p[k] - start[k]);
+    let t = (0..3).map(|k| d[k] * axis[k]).sum::<f32>();
+    let radial = (0..3)
+        .map(|k| (d[k] - axis[k] * t).powi(2))
+        .sum::<f32>()
+        .sqrt();
+    let across = radial - radius;
+    let along = (t - 0.5 * length).abs() - 0.5 * length;
+    across.max(0.0).hypot(along.max(0.0)) + across.max(along).min(0.0)
+}
+
+// Signed distance from a point to an axis-aligned box
+fn box_distance(p: [f32; 3], min: [f32; 3], max: [f32; 3]) -> f32 {
+    let mut outside_sq = 0.0f32;
+    let mut inside = f32::MIN;
+    for axis in 0..3 {
+        let center = 0.5 * (min[axis] + max[axis]);
+        let half = 0.5 * (max[axis] - min[axis]);
+        let q = (p[axis] - center).abs() - half;
+        outside_sq += q.max(0.0).powi(2);
+        inside = inside.max(q);
+    }
+    outside_sq.sqrt() + inside.min(0.0)
+}
@@ -0,0 +1,267 @@
+//! `convert --refit-holes`: replacing lumpy scanned bolt holes with true
+//! cylinders.
+//!
+//! A drilled hole's wall comes out of `segment` as one smooth region, and
+//! every normal on a cylinder is square to its axis, so the axis is the
+//! direction the region's normals vary least in. Looking down it, the
+//! wall's vertices lie on a circle, fitted by least squares. A region is
+//! taken for a hole when that circle fits within `tolerance` (a share of
+//! its radius), the wall goes most of the way round, and its normals face
+//! the axis, not away from it (that would be a pin, not a hole).
+//!
+//! Each hole is rebuilt by moving its wall's vertices straight out or in
+//! onto the fitted cylinder. The faces stay as they were, so the rims
+//! still meet the faces round them and a watertight mesh stays watertight.
+
+use crate::mesh::Mesh;
+use crate::segment::Segmentation;
+use crate::volumes::principal_axes;
+use serde::Serialize;
+
+/// Default largest RMS misfit, as a share of the radius.
+pub const DEFAULT_TOLERANCE: f64 = 0.05;
+// The wall must cover this many of the 36 ten-degree sectors round the axis
+const MIN_SECTORS: usize = 33;
+// Normals must be within about 8 degrees of square to the axis on average
+const MAX_AXIAL_NORMAL: f64 = 0.15;
+// Share of the wall's area that must face the axis
+const MIN_INWARD: f64 = 0.8;
+
+/// A hole found and refitted.
+#[derive(Debug, Clone, Serialize)]
+pub struct Hole {
+    pub region: usize,
+    /// Unit direction of the axis, and the middle of the wall on it.
+    pub axis: [f64; 3],
+    pub centre: [f64; 3],
+    pub diameter: f64,
+    /// Along the axis, from one rim to the other.
+    pub depth: f64,
+    /// How far the scanned wall was from the cylinder, RMS.
+    pub rms_error: f64,
+    pub vertices: usize,
+}
+
+/// The regions of `segmentation` that are holes fitting a cylinder within
+/// `tolerance` of their radius.
+pub fn find(mesh: &Mesh, segmentation: &Segmentation, tolerance: f64) -> Vec<Hole> {
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); segmentation.regions.len()];
+    for (f, &r) in segmentation.face_regions.iter().enumerate() {
+        members[r].push(f);
+    }
+    members
+        .iter()
+        .enumerate()
+        .filter_map(|(region, faces)| fit(mesh, region, faces, tolerance))
+        .collect()
+}
+
+/// Move every vertex of each hole's wall onto its cylinder; how many moved.
+pub fn snap(mesh: &mut Mesh, segmentation: &Segmentation, holes: &[Hole]) -> usize {
+    let mut moved = 0;
+    for hole in holes {
+        let mut seen = vec![false; mesh.vertex_count()];
+        for f in 0..mesh.face_count() {
+            if segmentation.face_regions[f] != hole.region {
+                continue;
+            }
+            for v in mesh.face(f) {
+                if std::mem::replace(&mut seen[v], true) {
+                    continue;
+                }
+                let p = mesh.vertex(v).map(f64::from);
+                let d = sub(p, hole.centre);
+                let along = dot(d, hole.axis);
+                let radial = [0, 1, 2].map(|k| d[k] - along * hole.axis[k]);
+                let length = dot(radial, radial).sqrt();
+                if length == 0.0 {
+                    continue;
+                }
+                let scale = hole.diameter * 0.5 / length;
+                for (k, r) in radial.iter().enumerate() {
+                    mesh.positions[v * 3 + k] =
+                        (hole.centre[k] + along * hole.axis[k] + r * scale) as f32;
+                }
+                moved += 1;
+            }
+        }
+    }
+    moved
+}
+
+fn fit(mesh: &Mesh, region: usize, faces: &[usize], tolerance: f64) -> Option<Hole> {
+    // 1. The axis: the direction the normals spread least in
+    let mut spread = [[0.0f64; 3]; 3];
+    let mut area = 0.0;
+    let mut weighted: Vec<([f64; 3], [f64; 3], f64)> = Vec::with_capacity(faces.len());
+    for &f in faces {
+        let [a, b, c] = mesh.face(f).map(|v| mesh.vertex(v).map(f64::from));
+        let n = cross(sub(b, a), sub(c, a));
+        let twice = dot(n, n).sqrt();
+        if twice == 0.0 {
+            continue;
+        }
+        let n = n.map(|x| x / twice);
+        let a_f = twice * 0.5;
+        for i in 0..3 {
+            for j in 0..3 {
+                spread[i][j] += n[i] * n[j] * a_f;
+            }
+        }
+        area += a_f;
+        let centroid = [0, 1, 2].map(|k| (a[k] + b[k] + c[k]) / 3.0);
+        weighted.push((centroid, n, a_f));
+    }
+    if weighted.len() < 8 || area == 0.0 {
+        return None;
+    }
+    let axis = principal_axes(spread)[2];
+    let axial = weighted
+        .iter()
+        .map(|w| dot(w.1, axis).abs() * w.2)
+        .sum::<f64>()
+        / area;
+    if axial > MAX_AXIAL_NORMAL {
+        return None;
+    }
+
+    // 2. The circle, looking down the axis
+    let mut vertices: Vec<usize> = faces.iter().flat_map(|&f| mesh.face(f)).collect();
+    vertices.sort_unstable();
+    vertices.dedup();
+    let u = perpendicular(axis);
+    let w = cross(axis, u);
+    let flat: Vec<[f64; 2]> = vertices
+        .iter()
+        .map(|&v| {
+            let p = mesh.vertex(v).map(f64::from);
+            [dot(p, u), dot(p, w)]
+        })
+        .collect();
+    let (middle, radius) = circle(&flat)?;
+    let rms = (flat
+        .iter()
+        .map(|p| {
+            (((p[0] - middle[0]).powi(2) + (p[1] - middle[1]).powi(2)).sqrt() - radius).powi(2)
+        })
+        .sum::<f64>()
+        / flat.len() as f64)
+        .sqrt();
+    if rms > tolerance * radius {
+        return None;
+    }
+
+    // 3. All the way round, and facing in
+    let mut sectors = [false; 36];
+    for p in &flat {
+        let angle = (p[1] - middle[1]).atan2(p[0] - middle[0]);
+        let sector = ((angle + std::f64::consts::PI) / std::f64::consts::TAU * 36.0) as usize;
+        sectors[sector.min(35)] = true;
+    }
+    if sectors.iter().filter(|&&s| s).count() < MIN_SECTORS {
+        return None;
+    }
+    let inward = weighted
+        .iter()
+        .filter(|(c, n, _)| {
+            let towards = [middle[0] - dot(*c, u), middle[1] - dot(*c, w)];
+            towards[0] * dot(*n, u) + towards[1] * dot(*n, w) > 0.0
+        })
+        .map(|w| w.2)
+        .sum::<f64>();
+    if inward < MIN_INWARD * area {
+        return None;
+    }
+
+    // 4. Where along the axis the wall runs
+    let heights: Vec<f64> = vertices
+        .iter()
+        .map(|&v| dot(mesh.vertex(v).map(f64::from), axis))
+        .collect();
+    let low = heights.iter().copied().fold(f64::MAX, f64::min);
+    let high = heights.iter().copied().fold(f64::MIN, f64::max);
+    let mid = (low + high) * 0.5;
+    Some(Hole {
+        region,
+        axis,
+        centre: [0, 1, 2].map(|k| u[k] * middle[0] + w[k] * middle[1] + axis[k] * mid),
+        diameter: radius * 2.0,
+        depth: high - low,
+        rms_error: rms,
+        vertices: vertices.len(),
+    })
+}
+
+// Least-squares circle through 2D points (Kåsa): x² + y² + Dx + Ey + F = 0
+// is linear in D, E and F. Centred first, for a well-conditioned solve
+fn circle(points: &[[f64; 2]]) -> Option<([f64; 2], f64)> {
+    let n = points.len() as f64;
+    let mean = points
+        .iter()
+        .fold([0.0; 2], |m, p| [m[0] + p[0] / n, m[1] + p[1] / n]);
+    let mut a = [[0.0f64; 3]; 3];
+    let mut b = [0.0f64; 3];
+    for p in points {
+        let (x, y) = (p[0] - mean[0], p[1] - mean[1]);
+        let row = [x, y, 1.0];
+        let rhs = -(x * x + y * y);
+        for i in 0..3 {
+            for j in 0..3 {
+                a[i][j] += row[i] * row[j];
+            }
+            b[i] += row[i] * rhs;
+        }
+    }
+    let [d, e, f] = solve3(a, b)?;
+    let (cx, cy) = (-d * 0.5, -e * 0.5);
+    let r_sq = cx * cx + cy * cy - f;
+    (r_sq > 0.0).then(|| ([cx + mean[0], cy + mean[1]], r_sq.sqrt()))
+}
+
+/// Solve `a x = b` by Cramer's rule; `None` when singular.
+pub fn solve3(a: [[f64; 3]; 3], b: [f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let whole = det(a);
+    if whole.abs() < 1e-300 {
+        return None;
+    }
+    Some([0, 1, 2].map(|k| {
+        let mut m = a;
+        for i in 0..3 {
+            m[i][k] = b[i];
+        }
+        det(m) / whole
+    }))
+}
+
+// Some unit vector square to `a`
+fn perpendicular(a: [f64; 3]) -> [f64; 3] {
+    let other = if a[0].abs() < 0.9 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 1.0, 0.0]
+    };
+    let p = cross(a, other);
+    let length = dot(p, p).sqrt();
+    p.map(|x| x / length)
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
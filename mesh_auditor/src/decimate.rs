@@ -0,0 +1,752 @@
+//! Quadric error decimation: fewer triangles, same shape.
+//!
+//! The classic Garland–Heckbert scheme. Every vertex carries a quadric,
+//! the sum of squared distances to the planes of the faces around it, and
+//! collapsing an edge merges its two ends into the one point that keeps
+//! that sum smallest. Edges are collapsed cheapest first until the target
+//! face count is reached, or, given an error budget, until every collapse
+//! left would move the surface further than that. The distance measured is
+//! the quadric's own: the area-weighted RMS distance from the merged vertex
+//! to the planes of all the original faces it now stands for.
+//!
+//! A collapse is refused when it would fold a face over (its normal turning
+//! by more than ~80°) or pinch the surface into a non-manifold one (the
+//! two ends sharing neighbours other than the faces between them), so a
+//! clean mesh stays clean.
+//!
+//! Colours, UVs, materials and vertex attributes come through too. Where the faces round a
+//! vertex disagree on its UV or material, that vertex is on a seam, and a
+//! collapse may only slide attributes along the faces it removes: a seam
+//! vertex can swallow a neighbour but never moves off the seam, and an
+//! edge running from one seam to another across a chart is left alone, so
+//! textures stay where they were painted. Joining two very different
+//! colours costs extra, which keeps sharp colour edges crisp.
+//!
+//! With `preserve_boundary`, the vertices on the mesh's open borders are
+//! locked: nothing collapses them away or moves them, though their inside
+//! neighbours may still collapse onto them. Borders come out exactly as
+//! they went in, so decimated terrain tiles still meet their neighbours.
+
+use crate::labels::{self, LabelFilter};
+use crate::mesh::Mesh;
+use crate::sandbox;
+use anyhow::{bail, Result};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+// A neighbouring face may turn this far (as a dot product of normals)
+const MIN_NORMAL_DOT: f64 = 0.2;
+// Cost of merging black into white across an edge, per length^4 (the
+// units of the quadric error)
+const COLOUR_WEIGHT: f64 = 1.0;
+
+/// What `decimate` did.
+#[derive(Debug)]
+pub struct Decimation {
+    pub mesh: Mesh,
+    pub collapses: usize,
+    /// The target wasn't reached: every collapse left would have broken
+    /// the surface.
+    pub stuck: bool,
+    /// The target wasn't reached: the collapses left would have gone over
+    /// the error budget.
+    pub over_budget: bool,
+}
+
+/// How far to decimate, and what to keep.
+#[derive(Debug, Clone, Copy)]
+pub struct DecimateOptions {
+    /// Stop at this many faces (or fewer); 0 to go as far as the error
+    /// budget allows.
+    pub target_faces: usize,
+    /// Collapse nothing that would put a vertex further than this from the
+    /// faces it replaces (see the module doc), in model units.
+    pub max_error: Option<f64>,
+    /// Lock the vertices on open borders.
+    pub preserve_boundary: bool,
+}
+
+/// Every collapse `decimate_recorded` made, in order, for undoing them
+/// (see `progressive`).
+#[derive(Debug, Default)]
+pub struct History {
+    /// The faces it started from: `mesh`'s, less any with a repeated
+    /// corner. Collapses refer to faces by their place here.
+    pub faces: Vec<[usize; 3]>,
+    pub collapses: Vec<Collapse>,
+}
+
+/// Vertex `gone` merged into `kept`.
+#[derive(Debug, Clone)]
+pub struct Collapse {
+    pub kept: usize,
+    pub gone: usize,
+    /// Where `kept` was before, and where it went.
+    pub kept_from: [f32; 3],
+    pub kept_to: [f32; 3],
+    /// Where `gone` was.
+    pub gone_at: [f32; 3],
+    /// The faces that had `gone` as a corner and have `kept` instead.
+    pub moved: Vec<usize>,
+    /// The faces across the edge, which went, with their corners then.
+    pub removed: Vec<(usize, [usize; 3])>,
+}
+
+/// Collapse edges of `mesh` until it has at most `options.target_faces`
+/// faces, or no collapse within `options.max_error` is left.
+pub fn decimate(mesh: &Mesh, options: &DecimateOptions) -> Decimation {
+    run(mesh, options, None)
+}
+
+/// `decimate` only the faces of `mesh` that `only` picks, with the cut
+/// round them locked so they still meet the rest; the faces left alone
+/// count against the target too. Returns the whole mesh's decimation, and
+/// how many faces were picked and left alone.
+pub fn decimate_only(
+    mesh: &Mesh,
+    options: &DecimateOptions,
+    only: &LabelFilter,
+) -> Result<(Decimation, usize, usize)> {
+    let (picked, rest) = only.split(mesh)?;
+    if picked.face_count() == 0 {
+        bail!("no face is labelled {}", only.describe());
+    }
+    let kept = rest.face_count();
+    let picked_options = DecimateOptions {
+        target_faces: options.target_faces.saturating_sub(kept),
+        preserve_boundary: true,
+        ..*options
+    };
+    let mut decimation = decimate(&picked, &picked_options);
+    decimation.mesh = labels::rejoin(decimation.mesh, &rest);
+    Ok((decimation, picked.face_count(), kept))
+}
+
+/// `decimate`, noting every collapse as it goes.
+pub fn decimate_recorded(mesh: &Mesh, options: &DecimateOptions) -> (Decimation, History) {
+    let mut history = History::default();
+    let decimation = run(mesh, options, Some(&mut history));
+    (decimation, history)
+}
+
+fn run(mesh: &Mesh, options: &DecimateOptions, mut history: Option<&mut History>) -> Decimation {
+    let target_faces = options.target_faces;
+    let mut state = State::new(mesh);
+    if let Some(history) = history.as_deref_mut() {
+        history.faces = state.faces.clone();
+    }
+    if options.preserve_boundary {
+        state.locked = (0..mesh.vertex_count())
+            .map(|v| state.on_boundary(v))
+            .collect();
+    }
+    // Every edge once, whichever faces it belongs to
+    let mut edges: Vec<(usize, usize)> = state
+        .faces
+        .iter()
+        .flat_map(|f| (0..3).map(move |k| (f[k].min(f[(k + 1) % 3]), f[k].max(f[(k + 1) % 3]))))
+        .collect();
+    edges.sort_unstable();
+    edges.dedup();
+    let mut heap = BinaryHeap::with_capacity(edges.len());
+    for (a, b) in edges {
+        state.push(&mut heap, a, b);
+    }
+
+    let mut collapses = 0;
+    let mut refused = false;
+    while state.live_faces > target_faces.max(1) {
+        if collapses % 1024 == 0 {
+            sandbox::checkpoint();
+        }
+        let Some(Reverse(candidate)) = heap.pop() else {
+            break;
+        };
+        let Candidate { a, b, stamps, .. } = candidate;
+        // Stale: one end has moved (or gone) since this was queued
+        if state.removed[a] || state.removed[b] || stamps != (state.stamp[a], state.stamp[b]) {
+            continue;
+        }
+        // Over budget now; it comes back if a neighbour's collapse changes it
+        if options.max_error.is_some_and(|max| candidate.error > max) {
+            refused = true;
+            continue;
+        }
+        // The faces round them may have changed anyway: think again
+        let Some(fixed) = state.plan(a, b) else {
+            continue;
+        };
+        if fixed && candidate.target != state.positions[a] {
+            state.push(&mut heap, a, b);
+            continue;
+        }
+        let before = history.is_some().then(|| state.snapshot(a, b));
+        if state.collapse(a, b, candidate.target) {
+            if let (Some(history), Some(collapse)) = (history.as_deref_mut(), before) {
+                history.collapses.push(state.finish_snapshot(collapse));
+            }
+            collapses += 1;
+            for n in state.neighbours(a) {
+                state.push(&mut heap, a, n);
+            }
+        }
+    }
+
+    let short = state.live_faces > target_faces.max(1);
+    Decimation {
+        mesh: state.into_mesh(mesh),
+        collapses,
+        stuck: short && !refused,
+        over_budget: short && refused,
+    }
+}
+
+// Symmetric 4x4 matrix, upper triangle: the error of a point p is
+// [p 1] Q [p 1]^T
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    fn plane(n: [f64; 3], d: f64, weight: f64) -> Self {
+        let [a, b, c] = n;
+        Quadric(
+            [
+                a * a,
+                a * b,
+                a * c,
+                a * d,
+                b * b,
+                b * c,
+                b * d,
+                c * c,
+                c * d,
+                d * d,
+            ]
+            .map(|x| x * weight),
+        )
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (x, y) in self.0.iter_mut().zip(other.0) {
+            *x += y;
+        }
+    }
+
+    // The area of the faces summed in, each plane's normal being a unit
+    fn area(&self) -> f64 {
+        self.0[0] + self.0[4] + self.0[7]
+    }
+
+    fn error(&self, p: [f64; 3]) -> f64 {
+        let q = &self.0;
+        let [x, y, z] = p;
+        q[0] * x * x
+            + 2.0 * q[1] * x * y
+            + 2.0 * q[2] * x * z
+            + 2.0 * q[3] * x
+            + q[4] * y * y
+            + 2.0 * q[5] * y * z
+            + 2.0 * q[6] * y
+            + q[7] * z * z
+            + 2.0 * q[8] * z
+            + q[9]
+    }
+
+    // The point of least error, if the quadric pins one down
+    fn minimum(&self) -> Option<[f64; 3]> {
+        let q = &self.0;
+        let m = [[q[0], q[1], q[2]], [q[1], q[4], q[5]], [q[2], q[5], q[7]]];
+        let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+        let scale = q[0].abs() + q[4].abs() + q[7].abs();
+        if det.abs() <= 1e-12 * scale.powi(3) {
+            return None;
+        }
+        // Cramer's rule on m p = -[q3 q6 q8]
+        let rhs = [-q[3], -q[6], -q[8]];
+        let solve = |column: usize| {
+            let mut c = m;
+            for row in 0..3 {
+                c[row][column] = rhs[row];
+            }
+            (c[0][0] * (c[1][1] * c[2][2] - c[1][2] * c[2][1])
+                - c[0][1] * (c[1][0] * c[2][2] - c[1][2] * c[2][0])
+                + c[0][2] * (c[1][0] * c[2][1] - c[1][1] * c[2][0]))
+                / det
+        };
+        Some([solve(0), solve(1), solve(2)])
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    cost: f64,
+    // RMS distance of the target from the faces' planes
+    error: f64,
+    // Collapse b into a
+    a: usize,
+    b: usize,
+    target: [f64; 3],
+    stamps: (u32, u32),
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    // Ties by the vertices, so the result doesn't depend on heap order
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cost
+            .total_cmp(&other.cost)
+            .then((self.a, self.b).cmp(&(other.a, other.b)))
+    }
+}
+
+struct State {
+    positions: Vec<[f64; 3]>,
+    faces: Vec<[usize; 3]>,
+    face_alive: Vec<bool>,
+    live_faces: usize,
+    vertex_faces: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    // Per face material and corner UVs (zero without them): together they
+    // say which chart a face belongs to round each of its corners
+    materials: Vec<Option<u32>>,
+    corner_uvs: Vec<[[f32; 2]; 3]>,
+    // Per vertex, empty if the mesh has none
+    colours: Vec<[f64; 3]>,
+    // Per vertex attribute, its values
+    attributes: Vec<Vec<f32>>,
+    // Per vertex, empty unless borders are preserved
+    locked: Vec<bool>,
+    removed: Vec<bool>,
+    // Bumped whenever a vertex moves, to spot stale heap entries
+    stamp: Vec<u32>,
+}
+
+impl State {
+    fn new(mesh: &Mesh) -> Self {
+        let positions: Vec<[f64; 3]> = (0..mesh.vertex_count())
+            .map(|i| mesh.vertex(i).map(f64::from))
+            .collect();
+        // Faces with a repeated corner have no plane and no use
+        let kept: Vec<usize> = (0..mesh.face_count())
+            .filter(|&f| {
+                let [a, b, c] = mesh.face(f);
+                a != b && b != c && a != c
+            })
+            .collect();
+        let faces: Vec<[usize; 3]> = kept.iter().map(|&f| mesh.face(f)).collect();
+        let materials = kept.iter().map(|&f| mesh.face_material(f)).collect();
+        let corner_uvs = kept
+            .iter()
+            .map(|&f| [0, 1, 2].map(|k| mesh.texcoord(f * 3 + k).unwrap_or([0.0; 2])))
+            .collect();
+        let colours = (0..mesh.vertex_count())
+            .filter_map(|i| mesh.colour(i))
+            .map(|c| c.map(f64::from))
+            .collect();
+        let mut vertex_faces = vec![Vec::new(); positions.len()];
+        let mut quadrics = vec![Quadric::default(); positions.len()];
+        for (f, face) in faces.iter().enumerate() {
+            let [a, b, c] = face.map(|i| positions[i]);
+            let n = cross(sub(b, a), sub(c, a));
+            let area = length(n) / 2.0;
+            // Area-weighted, so a sliver doesn't count as much as a big face
+            let plane = if area > 0.0 {
+                let n = n.map(|x| x / (2.0 * area));
+                Quadric::plane(n, -dot(n, a), area)
+            } else {
+                Quadric::default()
+            };
+            for &i in face {
+                vertex_faces[i].push(f);
+                quadrics[i].add(&plane);
+            }
+        }
+        State {
+            removed: vec![false; positions.len()],
+            stamp: vec![0; positions.len()],
+            face_alive: vec![true; faces.len()],
+            live_faces: faces.len(),
+            positions,
+            faces,
+            vertex_faces,
+            quadrics,
+            materials,
+            corner_uvs,
+            colours,
+            attributes: mesh
+                .vertex_attributes
+                .iter()
+                .map(|a| a.values.clone())
+                .collect(),
+            locked: Vec::new(),
+        }
+    }
+
+    // Which chart face f is in at its corner v
+    fn wedge(&self, f: usize, v: usize) -> (Option<u32>, [u32; 2]) {
+        let k = self.faces[f].iter().position(|&i| i == v).unwrap();
+        let uv = self.corner_uvs[f][k];
+        (self.materials[f], [uv[0].to_bits(), uv[1].to_bits()])
+    }
+
+    fn uv(&self, f: usize, v: usize) -> [f32; 2] {
+        let k = self.faces[f].iter().position(|&i| i == v).unwrap();
+        self.corner_uvs[f][k]
+    }
+
+    fn shared_faces(&self, a: usize, b: usize) -> Vec<usize> {
+        self.vertex_faces[a]
+            .iter()
+            .copied()
+            .filter(|&f| self.face_alive[f] && self.faces[f].contains(&b))
+            .collect()
+    }
+
+    // Can b collapse into a without tearing a seam? Every face round b has
+    // to be in the chart of one of the faces going, so its corner can take
+    // on a's attributes from there; if some face round a isn't, a can't
+    // move either. Locked vertices neither go nor move. Some(true) means
+    // "only if a stays put"
+    fn plan(&self, a: usize, b: usize) -> Option<bool> {
+        let locked = |v: usize| self.locked.get(v).copied().unwrap_or(false);
+        if locked(b) {
+            return None;
+        }
+        let shared = self.shared_faces(a, b);
+        if shared.is_empty() {
+            return None;
+        }
+        let matched = |f: usize, v: usize| {
+            shared.contains(&f) || shared.iter().any(|&s| self.wedge(s, v) == self.wedge(f, v))
+        };
+        let live = |v: usize| {
+            self.vertex_faces[v]
+                .iter()
+                .copied()
+                .filter(|&f| self.face_alive[f])
+        };
+        if !live(b).all(|f| matched(f, b)) {
+            return None;
+        }
+        Some(locked(a) || !live(a).all(|f| matched(f, a)))
+    }
+
+    fn edge_faces(&self, a: usize, b: usize) -> usize {
+        self.vertex_faces[a]
+            .iter()
+            .filter(|&&f| self.face_alive[f] && self.faces[f].contains(&b))
+            .count()
+    }
+
+    fn on_boundary(&self, v: usize) -> bool {
+        self.neighbours(v)
+            .into_iter()
+            .any(|n| self.edge_faces(v, n) == 1)
+    }
+
+    fn neighbours(&self, v: usize) -> Vec<usize> {
+        let mut out: Vec<usize> = self.vertex_faces[v]
+            .iter()
+            .filter(|&&f| self.face_alive[f])
+            .flat_map(|&f| self.faces[f])
+            .filter(|&n| n != v)
+            .collect();
+        out.sort_unstable();
+        out.dedup();
+        out
+    }
+
+    // Queue the cheaper way of collapsing the edge a-b, if either is allowed
+    fn push(&self, heap: &mut BinaryHeap<Reverse<Candidate>>, a: usize, b: usize) {
+        let mut q = self.quadrics[a];
+        q.add(&self.quadrics[b]);
+        let (pa, pb) = (self.positions[a], self.positions[b]);
+        let midpoint = [0, 1, 2].map(|k| (pa[k] + pb[k]) / 2.0);
+        // The optimum if there is one, else the best of the ends and middle
+        let free = q
+            .minimum()
+            .filter(|p| p.iter().all(|x| x.is_finite()))
+            .unwrap_or_else(|| {
+                [pa, pb, midpoint]
+                    .into_iter()
+                    .min_by(|x, y| q.error(*x).total_cmp(&q.error(*y)))
+                    .unwrap()
+            });
+        let best = [(a, b), (b, a)]
+            .into_iter()
+            .filter_map(|(keep, gone)| {
+                let fixed = self.plan(keep, gone)?;
+                let target = if fixed { self.positions[keep] } else { free };
+                let squared = q.error(target).max(0.0);
+                let cost = squared + self.colour_cost(keep, gone);
+                Some(Candidate {
+                    cost,
+                    error: (squared / q.area().max(f64::MIN_POSITIVE)).sqrt(),
+                    a: keep,
+                    b: gone,
+                    target,
+                    stamps: (self.stamp[keep], self.stamp[gone]),
+                })
+            })
+            .min();
+        if let Some(candidate) = best {
+            heap.push(Reverse(candidate));
+        }
+    }
+
+    // Extra cost for smearing one colour into another
+    fn colour_cost(&self, a: usize, b: usize) -> f64 {
+        if self.colours.is_empty() {
+            return 0.0;
+        }
+        let difference = sub(self.colours[a], self.colours[b]);
+        let edge = sub(self.positions[a], self.positions[b]);
+        COLOUR_WEIGHT * dot(difference, difference) * dot(edge, edge).powi(2)
+    }
+
+    // How far along a-b the target is, 0 at a
+    fn along(&self, a: usize, b: usize, target: [f64; 3]) -> f64 {
+        let edge = sub(self.positions[b], self.positions[a]);
+        let length = dot(edge, edge);
+        if length > 0.0 {
+            (dot(sub(target, self.positions[a]), edge) / length).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    // The record of collapsing b into a, as far as it can be filled in
+    // beforehand
+    fn snapshot(&self, a: usize, b: usize) -> Collapse {
+        let point = |v: usize| self.positions[v].map(|x| x as f32);
+        Collapse {
+            kept: a,
+            gone: b,
+            kept_from: point(a),
+            kept_to: point(a),
+            gone_at: point(b),
+            moved: self.vertex_faces[b]
+                .iter()
+                .copied()
+                .filter(|&f| self.face_alive[f])
+                .collect(),
+            removed: self
+                .shared_faces(a, b)
+                .into_iter()
+                .map(|f| (f, self.faces[f]))
+                .collect(),
+        }
+    }
+
+    // The rest of it, once done
+    fn finish_snapshot(&self, mut collapse: Collapse) -> Collapse {
+        collapse.kept_to = self.positions[collapse.kept].map(|x| x as f32);
+        collapse
+            .moved
+            .retain(|f| !collapse.removed.iter().any(|(r, _)| r == f));
+        collapse
+    }
+
+    // Merge b into a at `target`, if that leaves a sound surface
+    fn collapse(&mut self, a: usize, b: usize, target: [f64; 3]) -> bool {
+        // 1. Link condition: the ends may only share the neighbours across
+        //    the faces they both belong to
+        let shared_faces = self.shared_faces(a, b);
+        if shared_faces.is_empty() {
+            return false;
+        }
+        let across: Vec<usize> = shared_faces
+            .iter()
+            .flat_map(|&f| self.faces[f])
+            .filter(|&v| v != a && v != b)
+            .collect();
+        let around_b = self.neighbours(b);
+        let common = self
+            .neighbours(a)
+            .into_iter()
+            .filter(|n| around_b.binary_search(n).is_ok())
+            .count();
+        if common != across.len() {
+            return false;
+        }
+        // An edge across the inside joining two boundary points would pinch
+        // the surface to a single vertex there
+        if shared_faces.len() == 2 && self.on_boundary(a) && self.on_boundary(b) {
+            return false;
+        }
+
+        // 2. No face left behind may flip or collapse to nothing
+        for &v in &[a, b] {
+            for &f in &self.vertex_faces[v] {
+                if !self.face_alive[f] || shared_faces.contains(&f) {
+                    continue;
+                }
+                let corners = self.faces[f].map(|i| self.positions[i]);
+                let moved = self.faces[f].map(|i| {
+                    if i == a || i == b {
+                        target
+                    } else {
+                        self.positions[i]
+                    }
+                });
+                let before = cross(sub(corners[1], corners[0]), sub(corners[2], corners[0]));
+                let after = cross(sub(moved[1], moved[0]), sub(moved[2], moved[0]));
+                let (lb, la) = (length(before), length(after));
+                if la <= 1e-12 * lb.max(f64::MIN_POSITIVE)
+                    || dot(before, after) < MIN_NORMAL_DOT * lb * la
+                {
+                    return false;
+                }
+            }
+        }
+
+        // 3. Work out the new attributes while the faces going are still
+        //    there to read them from: every corner of a or b ends up at the
+        //    target, in its own chart
+        let t = self.along(a, b, target) as f32;
+        let lerp = |p: [f32; 2], q: [f32; 2]| [p[0] + (q[0] - p[0]) * t, p[1] + (q[1] - p[1]) * t];
+        let mut new_uvs = Vec::new();
+        for &v in &[a, b] {
+            for &f in &self.vertex_faces[v] {
+                if !self.face_alive[f] || shared_faces.contains(&f) {
+                    continue;
+                }
+                let Some(&s) = shared_faces
+                    .iter()
+                    .find(|&&s| self.wedge(s, v) == self.wedge(f, v))
+                else {
+                    // a's own chart, off the edge: a stays put, as planned
+                    continue;
+                };
+                let k = self.faces[f].iter().position(|&i| i == v).unwrap();
+                new_uvs.push((f, k, lerp(self.uv(s, a), self.uv(s, b))));
+            }
+        }
+        // Nor may a face turn over in its chart
+        for &(f, k, uv) in &new_uvs {
+            let before = self.corner_uvs[f];
+            let mut after = before;
+            after[k] = uv;
+            let (sb, sa) = (uv_area(before), uv_area(after));
+            if sb != 0.0 && (sa == 0.0 || sa.signum() != sb.signum()) {
+                return false;
+            }
+        }
+        for (f, k, uv) in new_uvs {
+            self.corner_uvs[f][k] = uv;
+        }
+        for values in &mut self.attributes {
+            values[a] += (values[b] - values[a]) * t;
+        }
+        if !self.colours.is_empty() {
+            let (ca, cb) = (self.colours[a], self.colours[b]);
+            let t = f64::from(t);
+            self.colours[a] = [0, 1, 2].map(|k| ca[k] + (cb[k] - ca[k]) * t);
+        }
+
+        // 4. Do it
+        for &f in &shared_faces {
+            self.face_alive[f] = false;
+            self.live_faces -= 1;
+        }
+        let moved_faces = std::mem::take(&mut self.vertex_faces[b]);
+        for f in moved_faces {
+            if !self.face_alive[f] {
+                continue;
+            }
+            for corner in self.faces[f].iter_mut() {
+                if *corner == b {
+                    *corner = a;
+                }
+            }
+            self.vertex_faces[a].push(f);
+        }
+        let face_alive = &self.face_alive;
+        self.vertex_faces[a].retain(|&f| face_alive[f]);
+        let qb = self.quadrics[b];
+        self.quadrics[a].add(&qb);
+        self.positions[a] = target;
+        self.removed[b] = true;
+        self.stamp[a] += 1;
+        self.stamp[b] += 1;
+        true
+    }
+
+    fn into_mesh(self, source: &Mesh) -> Mesh {
+        let mut remap = vec![u32::MAX; self.positions.len()];
+        let mut mesh = Mesh {
+            materials: source.materials.clone(),
+            material_libraries: source.material_libraries.clone(),
+            metadata: source.metadata.clone(),
+            vertex_attributes: source.pick_vertex_attributes(&[]),
+            ..Default::default()
+        };
+        for (f, face) in self.faces.iter().enumerate() {
+            if !self.face_alive[f] {
+                continue;
+            }
+            for &v in face {
+                if remap[v] == u32::MAX {
+                    remap[v] = mesh.vertex_count() as u32;
+                    mesh.positions.extend(self.positions[v].map(|x| x as f32));
+                    if let Some(c) = self.colours.get(v) {
+                        mesh.colours.extend(c.map(|x| x as f32));
+                    }
+                    for (attribute, values) in
+                        mesh.vertex_attributes.iter_mut().zip(&self.attributes)
+                    {
+                        attribute.values.push(values[v]);
+                    }
+                }
+                mesh.indices.push(remap[v]);
+            }
+            if !source.texcoords.is_empty() {
+                mesh.texcoords.extend(self.corner_uvs[f].as_flattened());
+            }
+            if !source.face_materials.is_empty() {
+                mesh.face_materials.push(self.materials[f]);
+            }
+        }
+        mesh
+    }
+}
+
+// Twice the signed area of a face in UV space
+fn uv_area([a, b, c]: [[f32; 2]; 3]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (c[0] - a[0]) * (b[1] - a[1])
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
@@ -0,0 +1,153 @@
+//! Procedural defect injection for building labeled repair test cases.
+//!
+//! Start from a clean mesh, break it in controlled ways, and write down
+//! exactly what was broken. The label file is the ground truth a repair
+//! benchmark checks its findings against.
+
+use crate::mesh::Mesh;
+use crate::rng::Rng;
+use crate::sandbox;
+use serde::Serialize;
+
+/// How much of each kind of damage to do.
+#[derive(Debug, Clone, Default)]
+pub struct DefectConfig {
+    pub seed: u64,
+    /// Number of holes to punch.
+    pub holes: usize,
+    /// Every face whose centroid is within this distance of a hole's
+    /// centre is removed.
+    pub hole_radius: f32,
+    /// Number of faces to turn inside out.
+    pub flipped_faces: usize,
+    /// Number of vertices to split into two coincident copies.
+    pub duplicate_vertices: usize,
+    /// Standard deviation of the Gaussian jitter added to every vertex.
+    pub noise: f32,
+    /// Number of stray points scattered around the object.
+    pub outliers: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Hole {
+    pub center: [f32; 3],
+    pub radius: f32,
+    /// Faces removed, as indices into the *original* mesh.
+    pub removed_faces: Vec<usize>,
+}
+
+/// Ground truth for a generated test case.
+#[derive(Debug, Default, Serialize)]
+pub struct DefectLabels {
+    /// The clean mesh the defects were injected into.
+    pub source: String,
+    pub seed: u64,
+    pub holes: Vec<Hole>,
+    /// Indices into the output mesh.
+    pub flipped_faces: Vec<usize>,
+    /// (original vertex, its new coincident copy) in the output mesh.
+    pub duplicate_vertices: Vec<(usize, usize)>,
+    pub noise_sigma: f32,
+    /// Unreferenced vertices in the output mesh.
+    pub outlier_vertices: Vec<usize>,
+}
+
+/// Damage `clean` according to `config`, returning the broken mesh and
+/// what was done to it.
+pub fn inject(clean: &Mesh, config: &DefectConfig) -> (Mesh, DefectLabels) {
+    let mut rng = Rng::new(config.seed);
+    let mut mesh = clean.clone();
+    let mut labels = DefectLabels {
+        seed: config.seed,
+        noise_sigma: config.noise,
+        ..Default::default()
+    };
+    if mesh.face_count() == 0 {
+        return (mesh, labels);
+    }
+
+    // 1. Holes: centred on random faces, removed by distance
+    let mut removed = vec![false; clean.face_count()];
+    for _ in 0..config.holes {
+        sandbox::checkpoint();
+        let center = clean.face_centroid(rng.below(clean.face_count()));
+        let r2 = config.hole_radius * config.hole_radius;
+        let mut faces = Vec::new();
+        for (f, gone) in removed.iter_mut().enumerate() {
+            if !*gone && distance_sq(clean.face_centroid(f), center) <= r2 {
+                *gone = true;
+                faces.push(f);
+            }
+        }
+        labels.holes.push(Hole {
+            center,
+            radius: config.hole_radius,
+            removed_faces: faces,
+        });
+    }
+    mesh.indices = clean
+        .indices
+        .chunks_exact(3)
+        .zip(&removed)
+        .filter(|(_, gone)| !**gone)
+        .flat_map(|(f, _)| f.iter().copied())
+        .collect();
+
+    // 2. Flipped faces: swap two corners to reverse the winding
+    let face_count = mesh.face_count();
+    for _ in 0..config.flipped_faces.min(face_count) {
+        let f = loop {
+            let f = rng.below(face_count);
+            if !labels.flipped_faces.contains(&f) {
+                break f;
+            }
+        };
+        mesh.flip_face(f);
+        labels.flipped_faces.push(f);
+    }
+
+    // 3. Noise: jitter every vertex (before duplicating, so copies stay
+    //    exactly coincident)
+    if config.noise > 0.0 {
+        for v in mesh.positions.iter_mut() {
+            *v += rng.gaussian(config.noise);
+        }
+    }
+
+    // 4. Duplicate vertices: copy a vertex and move half of its faces over,
+    //    leaving an invisible crack
+    for _ in 0..config.duplicate_vertices {
+        sandbox::checkpoint();
+        let original = rng.below(clean.vertex_count());
+        let copy = mesh.vertex_count();
+        let p = mesh.vertex(original);
+        let colour = mesh.colour(original).unwrap_or_default();
+        mesh.push_vertex(p, colour);
+        let users: Vec<usize> = (0..mesh.indices.len())
+            .filter(|&i| mesh.indices[i] as usize == original)
+            .collect();
+        for &i in users.iter().skip(users.len() / 2) {
+            mesh.indices[i] = copy as u32;
+        }
+        labels.duplicate_vertices.push((original, copy));
+    }
+
+    // 5. Outliers: loose points in a box 50% bigger than the object
+    let (min, max) = clean.bounds();
+    for _ in 0..config.outliers {
+        let mut p = [0.0; 3];
+        for axis in 0..3 {
+            let pad = (max[axis] - min[axis]) * 0.25;
+            p[axis] = rng.range(min[axis] - pad, max[axis] + pad);
+        }
+        labels.outlier_vertices.push(mesh.vertex_count());
+        // Noise off a scanner is usually dark
+        mesh.push_vertex(p, [0.0; 3]);
+    }
+
+    (mesh, labels)
+}
+
+fn distance_sq(a: [f32; 3], b: [f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
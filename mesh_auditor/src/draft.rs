@@ -0,0 +1,183 @@
+//! `analyze draft`: whether a part will come out of its mould.
+//!
+//! A face's draft is its angle to the pull direction's walls: 90 degrees
+//! for a face looking straight along the pull, 0 for a wall parallel to
+//! it, negative for a face looking back the other way (which the other
+//! half of the mould releases). A face whose draft is within `min_angle`
+//! of zero either way would drag along the mould as it opens, so that is
+//! flagged as under-drafted. Neighbouring under-drafted faces are grouped
+//! into patches, and the biggest are listed with where they are, so the
+//! designer knows which walls need tapering.
+
+use crate::audit;
+use crate::mesh::Mesh;
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+// Patches listed in the report
+const MAX_LISTED: usize = 5;
+// Colours of the coded mesh
+const RELEASED_FORWARD: [f32; 3] = [0.2, 0.75, 0.3];
+const RELEASED_BACK: [f32; 3] = [0.2, 0.45, 0.9];
+const UNDER_DRAFTED: [f32; 3] = [0.95, 0.8, 0.15];
+
+/// Draft over the whole surface.
+#[derive(Debug, Clone, Serialize)]
+pub struct Draft {
+    /// Unit pull direction.
+    pub direction: [f64; 3],
+    pub min_angle: f64,
+    pub surface_area: f64,
+    /// Faces with at least `min_angle` of draft towards the pull, and away
+    /// from it.
+    pub forward_area: f64,
+    pub back_area: f64,
+    pub under_drafted_area: f64,
+    /// The biggest under-drafted patches, biggest first.
+    pub patches: Vec<Patch>,
+    /// Draft of each face, in degrees.
+    #[serde(skip)]
+    pub face_angles: Vec<f64>,
+}
+
+/// Neighbouring faces short of draft.
+#[derive(Debug, Clone, Serialize)]
+pub struct Patch {
+    pub faces: usize,
+    pub area: f64,
+    pub centre: [f64; 3],
+    /// The draft nearest zero in the patch.
+    pub least_angle: f64,
+}
+
+/// Measure the draft of every face of `mesh` for a pull along `direction`.
+pub fn analyse(mesh: &Mesh, direction: [f32; 3], min_angle: f64) -> Result<Draft> {
+    let length = direction
+        .iter()
+        .map(|&x| f64::from(x).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    if length == 0.0 || !length.is_finite() {
+        bail!("the pull direction must not be zero");
+    }
+    if !(0.0..90.0).contains(&min_angle) {
+        bail!("the minimum draft angle must be from 0 up to 90 degrees");
+    }
+    let direction = direction.map(|x| f64::from(x) / length);
+
+    // 1. Each face's draft, and the totals
+    let count = mesh.face_count();
+    let areas: Vec<f64> = (0..count).map(|f| face_area(mesh, f)).collect();
+    let face_angles: Vec<f64> = (0..count)
+        .map(|f| {
+            let n = mesh.face_normal(f).map(f64::from);
+            let along = n[0] * direction[0] + n[1] * direction[1] + n[2] * direction[2];
+            along.clamp(-1.0, 1.0).asin().to_degrees()
+        })
+        .collect();
+    let short = |f: usize| face_angles[f].abs() < min_angle;
+    let mut draft = Draft {
+        direction,
+        min_angle,
+        surface_area: areas.iter().sum(),
+        forward_area: 0.0,
+        back_area: 0.0,
+        under_drafted_area: 0.0,
+        patches: Vec::new(),
+        face_angles: Vec::new(),
+    };
+    for f in 0..count {
+        if short(f) {
+            draft.under_drafted_area += areas[f];
+        } else if face_angles[f] > 0.0 {
+            draft.forward_area += areas[f];
+        } else {
+            draft.back_area += areas[f];
+        }
+    }
+
+    // 2. Group the under-drafted faces across shared edges
+    let mut neighbours = vec![Vec::new(); count];
+    for faces in audit::edge_faces(mesh).values() {
+        for (i, &(f, _)) in faces.iter().enumerate() {
+            for &(g, _) in &faces[i + 1..] {
+                neighbours[f].push(g);
+                neighbours[g].push(f);
+            }
+        }
+    }
+    let mut seen = vec![false; count];
+    for start in 0..count {
+        if seen[start] || !short(start) {
+            continue;
+        }
+        seen[start] = true;
+        let mut patch = Patch {
+            faces: 0,
+            area: 0.0,
+            centre: [0.0; 3],
+            least_angle: face_angles[start],
+        };
+        let mut stack = vec![start];
+        while let Some(f) = stack.pop() {
+            patch.faces += 1;
+            patch.area += areas[f];
+            let c = mesh.face_centroid(f);
+            for (sum, x) in patch.centre.iter_mut().zip(c) {
+                *sum += f64::from(x) * areas[f];
+            }
+            if face_angles[f].abs() < patch.least_angle.abs() {
+                patch.least_angle = face_angles[f];
+            }
+            for &g in &neighbours[f] {
+                if !seen[g] && short(g) {
+                    seen[g] = true;
+                    stack.push(g);
+                }
+            }
+        }
+        if patch.area > 0.0 {
+            patch.centre = patch.centre.map(|x| x / patch.area);
+        }
+        draft.patches.push(patch);
+    }
+    draft
+        .patches
+        .sort_by(|a, b| b.area.total_cmp(&a.area).then(b.faces.cmp(&a.faces)));
+    draft.patches.truncate(MAX_LISTED);
+    draft.face_angles = face_angles;
+    Ok(draft)
+}
+
+impl Draft {
+    /// `mesh` coloured by draft: green where it releases forward, blue where
+    /// it releases back, yellow where it is short of draft.
+    pub fn coloured(&self, mesh: &Mesh) -> Mesh {
+        let colours: Vec<[f32; 3]> = self
+            .face_angles
+            .iter()
+            .map(|&a| {
+                if a.abs() < self.min_angle {
+                    UNDER_DRAFTED
+                } else if a > 0.0 {
+                    RELEASED_FORWARD
+                } else {
+                    RELEASED_BACK
+                }
+            })
+            .collect();
+        mesh.with_face_colours(&colours)
+    }
+}
+
+fn face_area(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    0.5 * (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt()
+}
@@ -0,0 +1,120 @@
+//! `--debug-dump DIR`: keep what each stage produced, to see where a bad
+//! result went wrong.
+//!
+//! After each stage the intermediate result is written under `DIR`, in a
+//! folder per input (batch files and server jobs each get their own), named
+//! after the stage: `1_load.obj`, `2_sample.mlsdf`, `3_extract.stl`.
+//! Fields can be big, so `--debug-stages` picks which stages to keep.
+//!
+//! Dumps are a debugging aid: if one can't be written we say so and carry
+//! on rather than failing the job. The setting is process-wide, since the
+//! stages that dump are deep in the library; the dumps are written with
+//! the `WriteOptions` it was given.
+
+use crate::mesh::Mesh;
+use crate::multigrid::SparseField;
+use crate::output::WriteOptions;
+use crate::samples::Storage;
+use crate::sdf::SampledField;
+use crate::stl::save_triangles_as_stl;
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// A pipeline stage whose result can be dumped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Stage {
+    /// The mesh as loaded
+    Load,
+    /// The sampled field
+    Sample,
+    /// The extracted surface
+    Extract,
+}
+
+impl Stage {
+    fn file_name(self) -> &'static str {
+        match self {
+            Stage::Load => "1_load.obj",
+            Stage::Sample => "2_sample.mlsdf",
+            Stage::Extract => "3_extract.stl",
+        }
+    }
+}
+
+struct Config {
+    dir: PathBuf,
+    stages: Vec<Stage>,
+    /// Sub-folder for whatever is being processed now.
+    current: Option<String>,
+    write: WriteOptions,
+}
+
+static CONFIG: Mutex<Option<Config>> = Mutex::new(None);
+
+/// Turn dumping on, writing meshes with `write`. No `dir`, no dumps.
+pub fn configure(dir: Option<PathBuf>, stages: Vec<Stage>, write: WriteOptions) {
+    *CONFIG.lock().unwrap() = dir.map(|dir| Config {
+        dir,
+        stages,
+        current: None,
+        write,
+    });
+}
+
+/// Start dumping into a fresh folder for `name` (an input's stem or a job id).
+pub fn begin(name: &str) {
+    if let Some(config) = CONFIG.lock().unwrap().as_mut() {
+        config.current = Some(name.to_string());
+    }
+}
+
+pub fn mesh(stage: Stage, mesh: &Mesh) {
+    write(stage, |path, options| mesh.save_obj(path, options));
+}
+
+pub fn field(stage: Stage, field: &SampledField) {
+    write(stage, |path, _| field.save(path));
+}
+
+/// A coarse-to-fine field, filled out to the full grid first (only if
+/// this stage is being dumped: that's the memory it was meant to save).
+pub fn sparse_field(stage: Stage, field: &SparseField) {
+    write(stage, |path, _| field.to_dense(Storage::Full).save(path));
+}
+
+pub fn triangles(stage: Stage, triangles: &[f32]) {
+    write(stage, |path, options| {
+        save_triangles_as_stl(triangles, path, options)
+    });
+}
+
+fn write(stage: Stage, save: impl FnOnce(&str, &WriteOptions) -> Result<()>) {
+    let (path, options) = {
+        let config = CONFIG.lock().unwrap();
+        let Some(config) = config.as_ref().filter(|c| c.stages.contains(&stage)) else {
+            return;
+        };
+        let mut dir = config.dir.clone();
+        if let Some(current) = &config.current {
+            dir.push(current);
+        }
+        if let Err(e) = std::fs::create_dir_all(&dir) {
+            println!(
+                "   ⚠️  Could not create dump folder {}: {}",
+                dir.display(),
+                e
+            );
+            return;
+        }
+        (dir.join(stage.file_name()), config.write)
+    };
+    let path = path.to_string_lossy();
+    // Quiet on success: the batch dashboard owns the terminal
+    if let Err(e) = save(&path, &options) {
+        println!(
+            "   ⚠️  Could not dump {:?} stage to {}: {:#}",
+            stage, path, e
+        );
+    }
+}
@@ -0,0 +1,150 @@
+//! What a remesh will produce, predicted before committing to it.
+//!
+//! The occupancy field's balls are a few voxels across at any resolution,
+//! so a coarse grid doesn't show a smaller copy of the fine surface but a
+//! blobbier one, and its triangle count says little about the fine one's
+//! (nor does a distance field's, whose detail a coarse grid rounds off).
+//! So the fine field itself is sampled, but only at random cells: marching
+//! cubes on each makes exactly the triangles the full run would put there,
+//! and their mean over the cells tried, times the cells in the grid, is the
+//! whole surface's count. Nothing about the surface's shape or which way
+//! it faces biases that, and the spread of the counts says how close the
+//! answer is, so cells are drawn until it is within a few percent (a thin
+//! surface in a big grid needs more of them, since fewer cells hit it).
+//! Cells are a tiny share of a big grid either way. The file size follows
+//! from the bytes the sampled triangles took to write.
+
+use crate::extract::{marching_cubes, polygonise};
+use crate::mesh::Mesh;
+use crate::output::WriteOptions;
+use crate::remesh::{self, Grid, ScanField};
+use crate::rng::Rng;
+use crate::samples::Storage;
+use crate::sandbox;
+use crate::sdf::Field;
+use crate::stl;
+use anyhow::Result;
+
+/// Resolutions up to this are cheap enough to just run.
+pub const EXACT_UP_TO: usize = 48;
+// Keep drawing cells until the standard error is under this share of the
+// estimate...
+const TARGET_ERROR: f64 = 0.02;
+// ... checking after every batch, but giving up after this many
+const BATCH: usize = 4096;
+const MAX_CELLS: usize = 1 << 20;
+
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    pub resolution: usize,
+    /// Cells sampled, or `None` when the grid was small enough to run.
+    pub cells: Option<usize>,
+    pub triangles: u64,
+    /// Where the count is, 19 times in 20 (the estimate plus or minus
+    /// two standard errors).
+    pub range: (u64, u64),
+    /// Of the STL, as it would be written (`--ascii-precision` and all).
+    pub stl_bytes: u64,
+    /// Of the dense field, in the storage asked for.
+    pub field_bytes: u64,
+}
+
+/// Predict a voxel remesh of `scan` through `field` on `grid` (its points
+/// reaching `influence` steps, for occupancy), extracted at `iso` and
+/// written as `write` says.
+pub fn remesh(
+    scan: &Mesh,
+    field: ScanField,
+    influence: f32,
+    iso: f32,
+    grid: &Grid,
+    write: &WriteOptions,
+) -> Result<Estimate> {
+    let resolution = grid.resolution;
+    let dims = remesh::grid_dims(scan, field, resolution);
+    let field_bytes = grid.storage.bytes(dims.iter().product()) as u64;
+    let header = stl::soup_size(&[], write)?;
+
+    // 1. Small enough to just count
+    if resolution <= EXACT_UP_TO {
+        let storage = match field {
+            ScanField::Distance => Storage::Full,
+            ScanField::Occupancy => Storage::Bits,
+        };
+        let sampled = remesh::sample(scan, field, resolution, influence, storage, &grid.slabs);
+        let triangles = marching_cubes(&sampled, iso);
+        let count = (triangles.len() / 9) as u64;
+        return Ok(Estimate {
+            resolution,
+            cells: None,
+            triangles: count,
+            range: (count, count),
+            stl_bytes: stl::soup_size(&triangles, write)?,
+            field_bytes,
+        });
+    }
+
+    // 2. Random cells, until the mean is known well enough
+    let kind = field.kind();
+    let (field, origin, spacing): (Box<dyn Field>, _, _) = match field {
+        ScanField::Distance => {
+            let (field, origin, spacing) = remesh::surface_grid(scan, resolution);
+            (Box::new(field), origin, spacing)
+        }
+        ScanField::Occupancy => {
+            let (field, origin, spacing) =
+                remesh::scan_grid(&scan.positions, resolution, influence, grid.slabs.threads);
+            (Box::new(field), origin, spacing)
+        }
+    };
+    let sides = dims.map(|d| d - 1);
+    let total = sides.iter().map(|&s| s as f64).product::<f64>();
+    let mut rng = Rng::new(0);
+    let (mut sum, mut sum_sq, mut bytes) = (0.0f64, 0.0f64, 0u64);
+    let mut tried = 0;
+    let mut triangles = Vec::new();
+    let error = loop {
+        sandbox::checkpoint();
+        for _ in 0..BATCH {
+            let cell = sides.map(|s| rng.below(s));
+            let corner = |p: [usize; 3]| {
+                let value = field.z(p[0], p[1], p[2]) as f32;
+                (
+                    value,
+                    [0, 1, 2].map(|k| origin[k] + p[k] as f32 * spacing[k]),
+                )
+            };
+            triangles.clear();
+            polygonise(&corner, kind, iso, cell, &mut triangles);
+            let count = (triangles.len() / 9) as f64;
+            sum += count;
+            sum_sq += count * count;
+            if !triangles.is_empty() {
+                bytes += stl::soup_size(&triangles, write)? - header;
+            }
+        }
+        tried += BATCH;
+        let n = tried as f64;
+        let mean = sum / n;
+        let standard_error = ((sum_sq / n - mean * mean).max(0.0) / n).sqrt();
+        if (mean > 0.0 && standard_error <= TARGET_ERROR * mean) || tried >= MAX_CELLS {
+            break standard_error;
+        }
+    };
+
+    let n = tried as f64;
+    let estimate = sum / n * total;
+    let error = error * total;
+    let per_triangle = if sum > 0.0 { bytes as f64 / sum } else { 0.0 };
+    Ok(Estimate {
+        resolution,
+        cells: Some(tried),
+        triangles: estimate.round() as u64,
+        range: (
+            (estimate - 2.0 * error).max(0.0).round() as u64,
+            (estimate + 2.0 * error).round() as u64,
+        ),
+        stl_bytes: header + (per_triangle * estimate) as u64,
+        field_bytes,
+    })
+}
@@ -0,0 +1,223 @@
+//! Surface extraction from a sampled field.
+//!
+//! We only borrow the classic lookup tables from the `marching_cubes` crate;
+//! the walk over the grid is done here so it can run on a pre-sampled
+//! `SampledField` (and therefore on fields loaded back from disk).
+
+use crate::multigrid::SparseField;
+use crate::progress;
+use crate::sandbox;
+use crate::sdf::{FieldKind, SampledField};
+use marching_cubes::tables::{EDGE_TABLE, TRI_TABLE};
+
+// Corner offsets of a cell, in the order the lookup tables expect.
+const CORNERS: [[usize; 3]; 8] = [
+    [0, 0, 0],
+    [1, 0, 0],
+    [1, 1, 0],
+    [0, 1, 0],
+    [0, 0, 1],
+    [1, 0, 1],
+    [1, 1, 1],
+    [0, 1, 1],
+];
+
+// Which two corners each of the 12 cell edges connects, the one nearer
+// the grid's origin first: a cell and its neighbour then interpolate a
+// shared edge the same way round, to the same bits, so their triangles
+// share the vertex exactly.
+const EDGES: [[usize; 2]; 12] = [
+    [0, 1],
+    [1, 2],
+    [3, 2],
+    [0, 3],
+    [4, 5],
+    [5, 6],
+    [7, 6],
+    [4, 7],
+    [0, 4],
+    [1, 5],
+    [2, 6],
+    [3, 7],
+];
+
+/// Run marching cubes over the whole grid.
+///
+/// Returns a flat triangle soup: `[x1, y1, z1, x2, y2, z2, x3, y3, z3, ...]`,
+/// nine floats per triangle, wound counter-clockwise seen from outside.
+pub fn marching_cubes(field: &SampledField, iso: f32) -> Vec<f32> {
+    let mut triangles = Vec::new();
+    let [nx, ny, nz] = field.dims;
+    if nx < 2 || ny < 2 || nz < 2 {
+        return triangles;
+    }
+
+    let corner = |[x, y, z]: [usize; 3]| (field.get(x, y, z), field.position(x, y, z));
+    progress::start(nz - 1);
+    for z in 0..nz - 1 {
+        for y in 0..ny - 1 {
+            sandbox::checkpoint();
+            for x in 0..nx - 1 {
+                polygonise(&corner, field.kind, iso, [x, y, z], &mut triangles);
+            }
+        }
+        progress::tick();
+    }
+    triangles
+}
+
+/// Marching cubes over just the fine cells a coarse-to-fine sampling
+/// kept, which are the only ones the surface can cross. The soup comes
+/// out as `marching_cubes` would make it from the full grid.
+pub fn marching_cubes_sparse(field: &SparseField) -> Vec<f32> {
+    let mut triangles = Vec::new();
+    let corner = |[x, y, z]: [usize; 3]| (field.get(x, y, z), field.position(x, y, z));
+    let cells = field.cells();
+    progress::start(cells.len().div_ceil(4096));
+    for chunk in cells.chunks(4096) {
+        sandbox::checkpoint();
+        for &cell in chunk {
+            polygonise(&corner, field.kind, field.iso, cell, &mut triangles);
+        }
+        progress::tick();
+    }
+    triangles
+}
+
+/// Add the triangles of one cell, whose lowest corner is `cell`, to
+/// `triangles`. `corner` gives each grid point's value and position.
+pub fn polygonise(
+    corner: &impl Fn([usize; 3]) -> (f32, [f32; 3]),
+    kind: FieldKind,
+    iso: f32,
+    [x, y, z]: [usize; 3],
+    triangles: &mut Vec<f32>,
+) {
+    // 1. Look up the 8 corner samples of this cell
+    let mut values = [0.0f32; 8];
+    let mut positions = [[0.0f32; 3]; 8];
+    let mut cube_index = 0usize;
+    for (i, c) in CORNERS.iter().enumerate() {
+        (values[i], positions[i]) = corner([x + c[0], y + c[1], z + c[2]]);
+        if !kind.is_inside(values[i], iso) {
+            cube_index |= 1 << i;
+        }
+    }
+
+    // 2. Entirely inside or entirely outside: no surface here
+    let edge_mask = EDGE_TABLE[cube_index];
+    if edge_mask == 0 {
+        return;
+    }
+
+    // 3. Place a vertex on every edge the surface crosses
+    let mut edge_points = [[0.0f32; 3]; 12];
+    for (e, [a, b]) in EDGES.iter().enumerate() {
+        if edge_mask & (1 << e) != 0 {
+            edge_points[e] = interpolate(iso, positions[*a], positions[*b], values[*a], values[*b]);
+        }
+    }
+
+    // 4. Emit the triangles for this configuration
+    for tri in TRI_TABLE[cube_index].chunks(3) {
+        if tri[0] < 0 {
+            break;
+        }
+        for &e in tri {
+            triangles.extend_from_slice(&edge_points[e as usize]);
+        }
+    }
+}
+
+// Find where along an edge the field crosses the isovalue.
+fn interpolate(iso: f32, p1: [f32; 3], p2: [f32; 3], v1: f32, v2: f32) -> [f32; 3] {
+    let denom = v2 - v1;
+    let t = if denom.abs() < 1e-12 {
+        0.5
+    } else {
+        ((iso - v1) / denom).clamp(0.0, 1.0)
+    };
+    [
+        p1[0] + t * (p2[0] - p1[0]),
+        p1[1] + t * (p2[1] - p1[1]),
+        p1[2] + t * (p2[2] - p1[2]),
+    ]
+}
+
+/// Enclosed volume of a closed triangle soup (the divergence theorem:
+/// sum the signed volumes of the tetrahedra each face makes with the origin).
+pub fn soup_volume(triangles: &[f32]) -> f64 {
+    triangles
+        .chunks_exact(9)
+        .map(|t| {
+            let [ax, ay, az, bx, by, bz, cx, cy, cz] =
+                std::array::from_fn::<f64, 9, _>(|i| t[i] as f64);
+            (ax * (by * cz - bz * cy) - ay * (bx * cz - bz * cx) + az * (bx * cy - by * cx)) / 6.0
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit;
+    use crate::mesh::Mesh;
+    use crate::primitives::{Primitive, Shape};
+    use crate::samples::Storage;
+    use crate::slabs::Slabs;
+
+    // Edges with other than two faces: zero for a closed surface
+    fn open_edges(triangles: &[f32]) -> usize {
+        let mesh = Mesh::from_triangles(triangles).unwrap();
+        audit::edge_faces(&mesh)
+            .values()
+            .filter(|faces| faces.len() != 2)
+            .count()
+    }
+
+    fn shape(shape: Shape) -> Primitive {
+        Primitive {
+            shape,
+            size: 2.0,
+            detail: 0.2,
+            cells: 2,
+            seed: 0,
+        }
+    }
+
+    #[test]
+    fn sphere_is_watertight() {
+        let field = shape(Shape::Sphere).sample(40, Storage::Full, &Slabs::default());
+        let triangles = marching_cubes(&field, 0.0);
+        assert!(!triangles.is_empty());
+        assert_eq!(open_edges(&triangles), 0);
+    }
+
+    #[test]
+    fn torus_is_watertight() {
+        let field = shape(Shape::Torus).sample(48, Storage::Full, &Slabs::default());
+        let triangles = marching_cubes(&field, 0.0);
+        assert!(!triangles.is_empty());
+        assert_eq!(open_edges(&triangles), 0);
+    }
+
+    #[test]
+    fn sparse_extraction_is_watertight() {
+        let field = shape(Shape::Sphere).sample_sparse(40, 2, 2);
+        assert_eq!(open_edges(&marching_cubes_sparse(&field)), 0);
+    }
+
+    #[test]
+    fn sphere_volume() {
+        let primitive = shape(Shape::Sphere);
+        let field = primitive.sample(60, Storage::Full, &Slabs::default());
+        let volume = soup_volume(&marching_cubes(&field, 0.0));
+        let exact = primitive.exact_volume().unwrap();
+        assert!(
+            (volume - exact).abs() < exact * 0.01,
+            "{} vs {}",
+            volume,
+            exact
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Geometric fingerprints, for spotting the same scan twice in an archive.
+//!
+//! A fingerprint has two parts, both blind to vertex and face order and to
+//! where the mesh sits, how it is turned and how big it is:
+//!
+//! * `hash`: a short hex digest of coarsely rounded shape moments (the
+//!   spread of the surface along its three principal axes, and how much
+//!   volume it wraps per unit area). Exact and cheap, so the same file
+//!   re-exported, moved or scaled hashes the same; good for a first pass.
+//! * `d2`: the D2 shape distribution (Osada et al.): a histogram of the
+//!   distances between random pairs of surface points, relative to their
+//!   mean. Comparing two of these gives a similarity from 0 to 1 that
+//!   degrades gracefully with noise, holes and remeshing.
+//!
+//! For `match`, a library folder's fingerprints are cached in
+//! `.fingerprints.json` inside it, so only new or changed files are read
+//! again on the next query.
+
+use crate::bench;
+use crate::limits::InputLimits;
+use crate::mesh::Mesh;
+use crate::rng::Rng;
+use crate::sandbox;
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const BINS: usize = 64;
+// Distances past this many times the mean all land in the last bin
+const MAX_RATIO: f64 = 3.0;
+const PAIRS: usize = 200_000;
+// Same seed every time, so a fingerprint never changes between runs
+const SEED: u64 = 0xD2;
+// Bump whenever the fingerprint changes meaning, to throw old caches away
+const CACHE_VERSION: u32 = 1;
+const CACHE_FILE: &str = ".fingerprints.json";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub hash: String,
+    /// Principal spreads (largest first) over the surface area.
+    pub moments: [f64; 3],
+    /// Enclosed volume over area^1.5; 0 for open sheets.
+    pub compactness: f64,
+    /// D2 histogram, summing to 1.
+    pub d2: Vec<f64>,
+}
+
+pub fn fingerprint(mesh: &Mesh) -> Result<Fingerprint> {
+    // 1. Area of every face, for weighting
+    let areas: Vec<f64> = (0..mesh.face_count())
+        .map(|f| triangle_area(corners(mesh, f)))
+        .collect();
+    let total: f64 = areas.iter().sum();
+    if total <= 0.0 {
+        bail!("mesh has no surface area to fingerprint");
+    }
+
+    // 2. Moments, integrated exactly over each triangle
+    let mut first = [0.0; 3];
+    let mut second = [[0.0; 3]; 3];
+    let mut volume = 0.0;
+    for (f, &area) in areas.iter().enumerate() {
+        let [a, b, c] = corners(mesh, f);
+        let sum = [a[0] + b[0] + c[0], a[1] + b[1] + c[1], a[2] + b[2] + c[2]];
+        for i in 0..3 {
+            first[i] += area * sum[i] / 3.0;
+            for j in 0..3 {
+                // ∫ x_i x_j dA = A/12 (Σ v_i v_j + S_i S_j)
+                let corners_sum = a[i] * a[j] + b[i] * b[j] + c[i] * c[j];
+                second[i][j] += area / 12.0 * (corners_sum + sum[i] * sum[j]);
+            }
+        }
+        volume += dot(a, cross(b, c)) / 6.0;
+    }
+    let mean = first.map(|m| m / total);
+    let mut covariance = [[0.0; 3]; 3];
+    for i in 0..3 {
+        for j in 0..3 {
+            covariance[i][j] = second[i][j] / total - mean[i] * mean[j];
+        }
+    }
+    let moments = symmetric_eigenvalues(covariance).map(|l| l.max(0.0) / total);
+    let compactness = volume.abs() / total.powf(1.5);
+
+    // 3. Coarse moments -> hash. Two significant-ish digits is loose enough
+    // to shrug off float noise from a transform
+    let mut hasher = Sha256::new();
+    for value in moments.iter().chain([&compactness]) {
+        hasher.update(((value * 1000.0).round() as i64).to_le_bytes());
+    }
+    let digest = hasher.finalize();
+    let hash = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+
+    Ok(Fingerprint {
+        hash,
+        moments,
+        compactness,
+        d2: d2_histogram(mesh, &areas, total),
+    })
+}
+
+/// How alike two shapes are, from 0 (nothing in common) to 1 (the same
+/// distribution): one minus half the L1 distance between the histograms.
+pub fn similarity(a: &Fingerprint, b: &Fingerprint) -> f64 {
+    let distance: f64 = a.d2.iter().zip(&b.d2).map(|(x, y)| (x - y).abs()).sum();
+    (1.0 - distance / 2.0).clamp(0.0, 1.0)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Cache {
+    version: u32,
+    files: BTreeMap<String, CacheEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    size: u64,
+    modified: u64,
+    fingerprint: Fingerprint,
+}
+
+/// Fingerprints of every mesh in `dir`, from the cache where the file
+/// hasn't changed. Files that fail to load are reported and skipped.
+pub fn library(dir: &Path, limits: &InputLimits) -> Result<Vec<(PathBuf, Fingerprint)>> {
+    let cache_path = dir.join(CACHE_FILE);
+    let mut cache = std::fs::read_to_string(&cache_path)
+        .ok()
+        .and_then(|text| serde_json::from_str::<Cache>(&text).ok())
+        .filter(|c| c.version == CACHE_VERSION)
+        .unwrap_or(Cache {
+            version: CACHE_VERSION,
+            files: BTreeMap::new(),
+        });
+
+    let mut prints = Vec::new();
+    let mut fresh = BTreeMap::new();
+    let mut computed = 0;
+    for path in bench::corpus_files(dir)? {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let metadata = std::fs::metadata(&path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |d| d.as_secs());
+        let entry = match cache.files.remove(&name) {
+            Some(e) if e.size == metadata.len() && e.modified == modified => e,
+            _ => {
+                let print = match Mesh::load(&path.to_string_lossy(), limits)
+                    .and_then(|mesh| fingerprint(&mesh))
+                {
+                    Ok(print) => print,
+                    Err(e) => {
+                        println!("   ⚠️  Skipping {}: {:#}", path.display(), e);
+                        continue;
+                    }
+                };
+                computed += 1;
+                CacheEntry {
+                    size: metadata.len(),
+                    modified,
+                    fingerprint: print,
+                }
+            }
+        };
+        prints.push((path, entry.fingerprint.clone()));
+        fresh.insert(name, entry);
+    }
+
+    // A read-only library just doesn't get a cache
+    if computed > 0 || !cache.files.is_empty() {
+        cache.files = fresh;
+        if let Ok(text) = serde_json::to_string(&cache) {
+            let _ = std::fs::write(&cache_path, text);
+        }
+    }
+    Ok(prints)
+}
+
+fn d2_histogram(mesh: &Mesh, areas: &[f64], total: f64) -> Vec<f64> {
+    // Faces in a fixed order (by their corners' positions) so the samples
+    // don't depend on how the file happens to list them
+    let mut order: Vec<usize> = (0..areas.len()).filter(|&f| areas[f] > 0.0).collect();
+    let key = |f: usize| {
+        let mut c = corners(mesh, f);
+        c.sort_by(|p, q| p.partial_cmp(q).unwrap());
+        c
+    };
+    order.sort_by(|&f, &g| key(f).partial_cmp(&key(g)).unwrap());
+    let mut cumulative = Vec::with_capacity(order.len());
+    let mut running = 0.0;
+    for &f in &order {
+        running += areas[f] / total;
+        cumulative.push(running);
+    }
+
+    let mut rng = Rng::new(SEED);
+    let sample = |rng: &mut Rng| {
+        let u = f64::from(rng.unit());
+        let i = cumulative.partition_point(|&c| c < u).min(order.len() - 1);
+        let [a, b, c] = corners(mesh, order[i]);
+        // Uniform point in the triangle
+        let (r1, r2) = (f64::from(rng.unit()).sqrt(), f64::from(rng.unit()));
+        [0, 1, 2].map(|k| (1.0 - r1) * a[k] + r1 * (1.0 - r2) * b[k] + r1 * r2 * c[k])
+    };
+    let mut distances = Vec::with_capacity(PAIRS);
+    for n in 0..PAIRS {
+        if n % 10_000 == 0 {
+            sandbox::checkpoint();
+        }
+        let p = sample(&mut rng);
+        let q = sample(&mut rng);
+        distances.push(length(sub(p, q)));
+    }
+
+    // Relative to the mean, so scale drops out
+    let mean = distances.iter().sum::<f64>() / PAIRS as f64;
+    let mut histogram = vec![0.0; BINS];
+    for d in distances {
+        let ratio = if mean > 0.0 { d / mean } else { 0.0 };
+        let bin = ((ratio / MAX_RATIO * BINS as f64) as usize).min(BINS - 1);
+        histogram[bin] += 1.0 / PAIRS as f64;
+    }
+    histogram
+}
+
+fn corners(mesh: &Mesh, f: usize) -> [[f64; 3]; 3] {
+    mesh.face(f).map(|i| mesh.vertex(i).map(f64::from))
+}
+
+fn triangle_area([a, b, c]: [[f64; 3]; 3]) -> f64 {
+    length(cross(sub(b, a), sub(c, a))) / 2.0
+}
+
+// Eigenvalues of a symmetric 3x3 matrix, largest first (the closed-form
+// trigonometric solution)
+fn symmetric_eigenvalues(m: [[f64; 3]; 3]) -> [f64; 3] {
+    let off = m[0][1].powi(2) + m[0][2].powi(2) + m[1][2].powi(2);
+    let mut values = if off <= f64::EPSILON * (m[0][0].abs() + m[1][1].abs() + m[2][2].abs()) {
+        [m[0][0], m[1][1], m[2][2]]
+    } else {
+        let q = (m[0][0] + m[1][1] + m[2][2]) / 3.0;
+        let p =
+            (((m[0][0] - q).powi(2) + (m[1][1] - q).powi(2) + (m[2][2] - q).powi(2) + 2.0 * off)
+                / 6.0)
+                .sqrt();
+        let b = |i: usize, j: usize| (m[i][j] - if i == j { q } else { 0.0 }) / p;
+        let det = b(0, 0) * (b(1, 1) * b(2, 2) - b(1, 2) * b(2, 1))
+            - b(0, 1) * (b(1, 0) * b(2, 2) - b(1, 2) * b(2, 0))
+            + b(0, 2) * (b(1, 0) * b(2, 1) - b(1, 1) * b(2, 0));
+        let phi = (det / 2.0).clamp(-1.0, 1.0).acos() / 3.0;
+        let largest = q + 2.0 * p * phi.cos();
+        let smallest = q + 2.0 * p * (phi + 2.0 * std::f64::consts::PI / 3.0).cos();
+        [largest, 3.0 * q - largest - smallest, smallest]
+    };
+    values.sort_by(|a, b| b.partial_cmp(a).unwrap());
+    values
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
@@ -0,0 +1,543 @@
+//! Binary glTF (`.glb`) output, for meshes headed to the web.
+//!
+//! glTF wants one set of attributes per vertex, so a vertex sitting on a
+//! UV seam is split into one copy per UV it has there. Normals are smooth
+//! (area-weighted across the original vertex, seams or not) and, when the
+//! mesh has UVs, every vertex gets a tangent with its handedness in `w` so
+//! a tangent-space normal map (see `bake`) can be applied. Triangles and
+//! vertices are put in the order GPUs draw fastest (see `optimize`).
+//! Vertex colours go as `COLOR_0`, and vertex attributes as custom ones,
+//! `temperature` as `_TEMPERATURE`, for shaders and viewers that know to
+//! look for them. A scene can have several nodes, each with a mesh of its
+//! own and the same material, or be a flipbook showing its nodes one after
+//! another. Everything, images included, goes in the one binary chunk.
+
+use crate::audit;
+use crate::mesh::Mesh;
+use crate::optimize;
+use crate::output::WriteOptions;
+use crate::storage;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const LINEAR: u32 = 9729;
+const LINEAR_MIPMAP_LINEAR: u32 = 9987;
+
+/// A mesh as glTF lays it out: indexed triangles over split vertices.
+#[derive(Debug, Default)]
+pub struct Primitive {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    /// OBJ convention (v up), like `Mesh::texcoords`; flipped on writing.
+    /// Empty if the mesh has no UVs, and so are `tangents`.
+    pub texcoords: Vec<[f32; 2]>,
+    pub tangents: Vec<[f32; 4]>,
+    /// RGB in 0..1 as the mesh has them; empty if it has none.
+    pub colours: Vec<[f32; 3]>,
+    pub indices: Vec<u32>,
+    /// The mesh vertex each vertex was split from.
+    pub source: Vec<usize>,
+    /// The mesh's vertex attributes, by glTF name, one value per vertex.
+    pub attributes: Vec<(String, Vec<f32>)>,
+}
+
+impl Primitive {
+    pub fn new(mesh: &Mesh) -> Self {
+        let smooth = mesh.vertex_normals();
+        let uvs = !mesh.texcoords.is_empty();
+        let mut primitive = Primitive::default();
+
+        // 1. One vertex per (position, UV) pair
+        let mut split: HashMap<(usize, [u32; 2]), u32> = HashMap::new();
+        for (corner, &i) in mesh.indices.iter().enumerate() {
+            let i = i as usize;
+            let uv = mesh.texcoord(corner).unwrap_or([0.0; 2]);
+            let next = primitive.positions.len() as u32;
+            let index = *split
+                .entry((i, [uv[0].to_bits(), uv[1].to_bits()]))
+                .or_insert_with(|| {
+                    primitive.positions.push(mesh.vertex(i));
+                    primitive.normals.push(smooth[i]);
+                    if uvs {
+                        primitive.texcoords.push(uv);
+                    }
+                    primitive.source.push(i);
+                    next
+                });
+            primitive.indices.push(index);
+        }
+
+        // 2. Tangents: the direction of +u on each face, summed per vertex
+        if uvs {
+            let mut along_u = vec![[0.0f32; 3]; primitive.positions.len()];
+            let mut along_v = vec![[0.0f32; 3]; primitive.positions.len()];
+            for face in primitive.indices.chunks_exact(3) {
+                let [a, b, c] = [face[0], face[1], face[2]].map(|i| i as usize);
+                let (p, t) = (&primitive.positions, &primitive.texcoords);
+                let (e1, e2) = (sub(p[b], p[a]), sub(p[c], p[a]));
+                let (du1, dv1) = (t[b][0] - t[a][0], t[b][1] - t[a][1]);
+                let (du2, dv2) = (t[c][0] - t[a][0], t[c][1] - t[a][1]);
+                let r = du1 * dv2 - du2 * dv1;
+                if r.abs() < f32::EPSILON {
+                    continue;
+                }
+                let tu = [0, 1, 2].map(|k| (e1[k] * dv2 - e2[k] * dv1) / r);
+                let tv = [0, 1, 2].map(|k| (e2[k] * du1 - e1[k] * du2) / r);
+                for i in [a, b, c] {
+                    for k in 0..3 {
+                        along_u[i][k] += tu[k];
+                        along_v[i][k] += tv[k];
+                    }
+                }
+            }
+            primitive.tangents = (0..primitive.positions.len())
+                .map(|i| tangent(primitive.normals[i], along_u[i], along_v[i]))
+                .collect();
+        }
+
+        // 3. Reorder for the GPU, then fetch the colours and attributes in
+        // that order
+        optimize::optimize(&mut primitive);
+        if mesh.has_colours() {
+            primitive.colours = primitive
+                .source
+                .iter()
+                .map(|&v| mesh.colour(v).unwrap_or_default())
+                .collect();
+        }
+        primitive.attributes = mesh
+            .vertex_attributes
+            .iter()
+            .map(|a| {
+                let name: String = a
+                    .name
+                    .chars()
+                    .map(|c| match c {
+                        'a'..='z' | 'A'..='Z' | '0'..='9' => c.to_ascii_uppercase(),
+                        _ => '_',
+                    })
+                    .collect();
+                let values = primitive.source.iter().map(|&v| a.values[v]).collect();
+                (format!("_{}", name), values)
+            })
+            .collect();
+        primitive
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len()
+    }
+}
+
+// A unit tangent at right angles to `normal`, pointing as near `along_u`
+// as it can, with w = -1 where the UVs are mirrored
+fn tangent(normal: [f32; 3], along_u: [f32; 3], along_v: [f32; 3]) -> [f32; 4] {
+    let d = dot(normal, along_u);
+    let mut t = [0, 1, 2].map(|k| along_u[k] - normal[k] * d);
+    if dot(t, t) < 1e-20 {
+        // No usable UVs here: any perpendicular will do
+        let helper = if normal[0].abs() < 0.9 {
+            [1.0, 0.0, 0.0]
+        } else {
+            [0.0, 1.0, 0.0]
+        };
+        t = cross(helper, normal);
+    }
+    let l = dot(t, t).sqrt();
+    let w = if dot(cross(normal, t), along_v) < 0.0 {
+        -1.0
+    } else {
+        1.0
+    };
+    [t[0] / l, t[1] / l, t[2] / l, w]
+}
+
+/// Write `mesh` as a `.glb`: one node named after it, or with
+/// `split_shells` one per connected shell, the most faces first, numbered
+/// after it. Every node carries the mesh's units, attributes and notes in
+/// its extras. Returns how many nodes were written.
+pub fn save_mesh(
+    mesh: &Mesh,
+    location: &str,
+    split_shells: bool,
+    options: &WriteOptions,
+) -> Result<usize> {
+    let mesh = options.ordered(mesh);
+    let metadata = &mesh.metadata;
+    let mut extras = serde_json::Map::new();
+    if let Some(units) = metadata.units {
+        extras.insert("units".to_string(), json!(units.symbol()));
+    }
+    if !metadata.attributes.is_empty() {
+        extras.insert("attributes".to_string(), json!(metadata.attributes));
+    }
+    if !mesh.notes.is_empty() {
+        extras.insert("notes".to_string(), json!(mesh.notes));
+    }
+    let extras = if extras.is_empty() {
+        Value::Null
+    } else {
+        Value::Object(extras)
+    };
+
+    let base = metadata.name.as_deref().unwrap_or("shell");
+    let parts: Vec<(Option<String>, Primitive)> = if split_shells {
+        let mut shells: Vec<Vec<usize>> = audit::surfaces(&mesh)
+            .into_iter()
+            .map(|s| {
+                let mut faces = s.even;
+                faces.extend(s.odd);
+                faces
+            })
+            .collect();
+        shells.sort_by_key(|faces| std::cmp::Reverse(faces.len()));
+        let mut shell_of = vec![0; mesh.face_count()];
+        for (s, faces) in shells.iter().enumerate() {
+            for &f in faces {
+                shell_of[f] = s;
+            }
+        }
+        (0..shells.len())
+            .map(|s| {
+                let part = mesh.select_faces(|f| shell_of[f] == s);
+                (Some(format!("{}_{}", base, s + 1)), Primitive::new(&part))
+            })
+            .collect()
+    } else {
+        vec![(metadata.name.clone(), Primitive::new(&mesh))]
+    };
+    let nodes: Vec<Node> = parts
+        .iter()
+        .map(|(name, primitive)| Node {
+            name: name.as_deref(),
+            primitive,
+            extras: extras.clone(),
+        })
+        .collect();
+    write_scene(location, &nodes, None)?;
+    Ok(nodes.len())
+}
+
+/// Write `primitive` as a `.glb`, its material using `normal_map` (PNG
+/// bytes) as a tangent-space normal texture if given.
+pub fn write_glb(location: &str, primitive: &Primitive, normal_map: Option<&[u8]>) -> Result<()> {
+    write_glb_with(location, primitive, normal_map, &Value::Null)
+}
+
+/// Like `write_glb`, with `extras` (unless null) on the mesh's node for
+/// engines to pick up.
+pub fn write_glb_with(
+    location: &str,
+    primitive: &Primitive,
+    normal_map: Option<&[u8]>,
+    extras: &Value,
+) -> Result<()> {
+    let node = Node {
+        name: None,
+        primitive,
+        extras: extras.clone(),
+    };
+    write_scene(location, &[node], normal_map)
+}
+
+/// One node of a scene: its mesh, and what to call it.
+pub struct Node<'a> {
+    pub name: Option<&'a str>,
+    pub primitive: &'a Primitive,
+    /// For engines to pick up; left out if null.
+    pub extras: Value,
+}
+
+/// Write `nodes` as a `.glb`, side by side in one scene, all with the one
+/// material (using `normal_map` as in `write_glb`).
+pub fn write_scene(location: &str, nodes: &[Node], normal_map: Option<&[u8]>) -> Result<()> {
+    write_document(location, nodes, normal_map, None)
+}
+
+/// Write `nodes` as a `.glb` flipbook: one looping animation showing them
+/// one at a time, each for `seconds`, in order. Viewers that don't play
+/// animations show the first.
+///
+/// Morph targets would blend between frames, but they need every frame
+/// to have the same vertices, which surfaces extracted at different
+/// isovalues don't; each frame is a node of its own instead, scaled to
+/// nothing while it's not showing.
+pub fn write_flipbook(location: &str, nodes: &[Node], seconds: f32) -> Result<()> {
+    write_document(location, nodes, None, Some(seconds))
+}
+
+fn write_document(
+    location: &str,
+    nodes: &[Node],
+    normal_map: Option<&[u8]>,
+    flipbook: Option<f32>,
+) -> Result<()> {
+    // 1. The binary chunk: each mesh's attributes and indices, then the
+    // image
+    let mut bin: Vec<u8> = Vec::new();
+    let mut views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut meshes = Vec::new();
+    for node in nodes {
+        let primitive = node.primitive;
+        let mut attributes = serde_json::Map::new();
+        let count = primitive.vertex_count();
+        let mut attribute = |name: &str, values: &[f32], kind: &str, bin: &mut Vec<u8>| {
+            if values.is_empty() {
+                return;
+            }
+            let offset = bin.len();
+            for value in values {
+                bin.extend_from_slice(&value.to_le_bytes());
+            }
+            attributes.insert(name.to_string(), json!(accessors.len()));
+            let mut accessor = json!({
+                "bufferView": views.len(),
+                "componentType": FLOAT,
+                "count": count,
+                "type": kind
+            });
+            if name == "POSITION" {
+                // Required for positions: the bounds
+                let (min, max) = bounds(&primitive.positions);
+                accessor["min"] = json!(min);
+                accessor["max"] = json!(max);
+            }
+            accessors.push(accessor);
+            views.push(json!({
+                "buffer": 0,
+                "byteOffset": offset,
+                "byteLength": bin.len() - offset,
+                "target": ARRAY_BUFFER
+            }));
+        };
+        attribute(
+            "POSITION",
+            primitive.positions.as_flattened(),
+            "VEC3",
+            &mut bin,
+        );
+        attribute("NORMAL", primitive.normals.as_flattened(), "VEC3", &mut bin);
+        // glTF puts v = 0 at the top of the image
+        let flipped: Vec<f32> = primitive
+            .texcoords
+            .iter()
+            .flat_map(|&[u, v]| [u, 1.0 - v])
+            .collect();
+        attribute("TEXCOORD_0", &flipped, "VEC2", &mut bin);
+        attribute(
+            "TANGENT",
+            primitive.tangents.as_flattened(),
+            "VEC4",
+            &mut bin,
+        );
+        attribute(
+            "COLOR_0",
+            primitive.colours.as_flattened(),
+            "VEC3",
+            &mut bin,
+        );
+        for (name, values) in &primitive.attributes {
+            attribute(name, values, "SCALAR", &mut bin);
+        }
+
+        let offset = bin.len();
+        for index in &primitive.indices {
+            bin.extend_from_slice(&index.to_le_bytes());
+        }
+        let indices = accessors.len();
+        accessors.push(json!({
+            "bufferView": views.len(),
+            "componentType": UNSIGNED_INT,
+            "count": primitive.indices.len(),
+            "type": "SCALAR"
+        }));
+        views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bin.len() - offset,
+            "target": ELEMENT_ARRAY_BUFFER
+        }));
+        meshes.push(json!({
+            "primitives": [{
+                "attributes": attributes,
+                "indices": indices,
+                "material": 0,
+                "mode": 4
+            }]
+        }));
+    }
+
+    // 2. The nodes, and the material, with the normal map if there is one
+    let scene_nodes: Vec<Value> = nodes
+        .iter()
+        .enumerate()
+        .map(|(i, node)| {
+            let mut value = json!({ "mesh": i });
+            if let Some(name) = node.name {
+                value["name"] = json!(name);
+            }
+            if !node.extras.is_null() {
+                value["extras"] = node.extras.clone();
+            }
+            if flipbook.is_some() && i > 0 {
+                value["scale"] = json!([0.0, 0.0, 0.0]);
+            }
+            value
+        })
+        .collect();
+    let mut material = json!({
+        "name": "baked",
+        "pbrMetallicRoughness": {
+            "baseColorFactor": [0.8, 0.8, 0.8, 1.0],
+            "metallicFactor": 0.0,
+            "roughnessFactor": 0.7
+        }
+    });
+    let mut document = json!({
+        "asset": { "version": "2.0", "generator": "mesh_lifter" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": scene_nodes,
+        "meshes": meshes,
+        "accessors": accessors
+    });
+    if let Some(seconds) = flipbook {
+        document["animations"] = json!([flipbook_animation(
+            nodes.len(),
+            seconds,
+            &mut bin,
+            &mut views,
+            &mut accessors
+        )]);
+        document["accessors"] = json!(accessors);
+    }
+    if let Some(png) = normal_map {
+        let offset = bin.len();
+        bin.extend_from_slice(png);
+        document["images"] = json!([{ "bufferView": views.len(), "mimeType": "image/png" }]);
+        views.push(json!({ "buffer": 0, "byteOffset": offset, "byteLength": png.len() }));
+        document["samplers"] = json!([{
+            "magFilter": LINEAR,
+            "minFilter": LINEAR_MIPMAP_LINEAR
+        }]);
+        document["textures"] = json!([{ "source": 0, "sampler": 0 }]);
+        material["normalTexture"] = json!({ "index": 0 });
+    }
+    document["materials"] = json!([material]);
+    while !bin.len().is_multiple_of(4) {
+        bin.push(0);
+    }
+    document["bufferViews"] = json!(views);
+    document["buffers"] = json!([{ "byteLength": bin.len() }]);
+
+    // 3. Header, then the JSON (space padded) and binary chunks
+    let mut text = serde_json::to_vec(&document)?;
+    while !text.len().is_multiple_of(4) {
+        text.push(b' ');
+    }
+    let total = 12 + 8 + text.len() + 8 + bin.len();
+    let mut out = storage::create(location)?;
+    out.write_all(b"glTF")?;
+    out.write_all(&2u32.to_le_bytes())?;
+    out.write_all(&u32::try_from(total)?.to_le_bytes())?;
+    out.write_all(&(text.len() as u32).to_le_bytes())?;
+    out.write_all(b"JSON")?;
+    out.write_all(&text)?;
+    out.write_all(&(bin.len() as u32).to_le_bytes())?;
+    out.write_all(b"BIN\0")?;
+    out.write_all(&bin)?;
+    out.finish()
+}
+
+// The flipbook's keyframes into `bin`: node i at full size from key i to
+// key i + 1 and at none otherwise, stepping rather than blending, with a
+// last key repeating the one before so the last frame lasts as long as the
+// others before the loop comes round
+fn flipbook_animation(
+    frames: usize,
+    seconds: f32,
+    bin: &mut Vec<u8>,
+    views: &mut Vec<Value>,
+    accessors: &mut Vec<Value>,
+) -> Value {
+    let mut floats = |values: &[f32], kind: &str, count: usize, accessors: &mut Vec<Value>| {
+        let offset = bin.len();
+        for value in values {
+            bin.extend_from_slice(&value.to_le_bytes());
+        }
+        accessors.push(json!({
+            "bufferView": views.len(),
+            "componentType": FLOAT,
+            "count": count,
+            "type": kind
+        }));
+        views.push(json!({
+            "buffer": 0,
+            "byteOffset": offset,
+            "byteLength": bin.len() - offset
+        }));
+        accessors.len() - 1
+    };
+    let times: Vec<f32> = (0..=frames).map(|k| k as f32 * seconds).collect();
+    let input = floats(&times, "SCALAR", times.len(), accessors);
+    // Required for animation inputs: the bounds
+    accessors[input]["min"] = json!([0.0]);
+    accessors[input]["max"] = json!([times[frames]]);
+
+    let mut channels = Vec::new();
+    let mut samplers = Vec::new();
+    for node in 0..frames {
+        let scales: Vec<f32> = (0..=frames)
+            .flat_map(|k| {
+                let showing = k.min(frames - 1) == node;
+                [f32::from(u8::from(showing)); 3]
+            })
+            .collect();
+        let output = floats(&scales, "VEC3", times.len(), accessors);
+        channels.push(json!({
+            "sampler": samplers.len(),
+            "target": { "node": node, "path": "scale" }
+        }));
+        samplers.push(json!({
+            "input": input,
+            "output": output,
+            "interpolation": "STEP"
+        }));
+    }
+    json!({ "name": "flipbook", "channels": channels, "samplers": samplers })
+}
+
+fn bounds(points: &[[f32; 3]]) -> ([f32; 3], [f32; 3]) {
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in points {
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    (min, max)
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
@@ -0,0 +1,356 @@
+//! `convert --heal 0.01`: closing a mesh's gaps to a CAD sewing tolerance.
+//!
+//! CAD importers sew a mesh's faces into a solid along edges whose ends
+//! are within their sewing tolerance of each other, and leave anything
+//! wider as a gap in the shell. A mesh exported in pieces, or written with
+//! too few digits, comes apart in exactly such hairline gaps. Healing
+//! closes what a given tolerance would, in two passes over the open edges
+//! (those with one face):
+//!
+//! 1. Open-edge vertices within the tolerance of each other are snapped
+//!    together, to the middle of their cluster. Faces squashed flat by it
+//!    go.
+//! 2. An open-edge vertex within the tolerance of an open edge, part way
+//!    along it (a T-junction, where the two sides of a gap were cut into
+//!    edges of different lengths), splits that edge's face there, so both
+//!    sides have an edge to pair up.
+//!
+//! What is left open is measured: each open edge's gap is the distance to
+//! the nearest open edge running the other way, which is the one across a
+//! seam from it. An open edge with no such edge near it borders a hole,
+//! not a gap, and no tolerance near this one would sew it.
+//!
+//! `weld` is the first pass over every vertex, for joining corners that
+//! are near each other but not on an open edge: the slivers marching
+//! cubes makes where the surface grazes a grid point, say.
+
+use crate::audit;
+use crate::kdtree::KdTree;
+use crate::mesh::Mesh;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+
+// Open edges looked at round each one for the edge across its gap
+const NEIGHBOURS: usize = 32;
+// Passes of T-junction splitting; each can split a face along one edge
+const MAX_PASSES: usize = 4;
+
+/// What healing did, and what it left.
+#[derive(Debug, Default)]
+pub struct Healed {
+    /// Vertices merged into another.
+    pub snapped: usize,
+    /// Faces squashed to nothing by snapping, and dropped.
+    pub dropped_faces: usize,
+    /// Vertices an open edge was split at to meet them.
+    pub split: usize,
+    /// Open edges left.
+    pub open_edges: usize,
+    /// The widest gap between open edges left, if any are across a gap
+    /// from another.
+    pub largest_gap: Option<f32>,
+    /// Open edges with nothing across from them: the rims of holes.
+    pub hole_edges: usize,
+}
+
+/// Close `mesh`'s gaps up to `tolerance` wide (see the module docs).
+pub fn heal(mesh: &mut Mesh, tolerance: f32) -> Result<Healed> {
+    if !tolerance.is_finite() || tolerance <= 0.0 {
+        bail!("the sewing tolerance must be a positive distance");
+    }
+    let mut healed = Healed::default();
+    let (snapped, dropped_faces) = snap(mesh, tolerance);
+    healed.snapped = snapped;
+    healed.dropped_faces = dropped_faces;
+    for _ in 0..MAX_PASSES {
+        let split = split_t_junctions(mesh, tolerance);
+        if split == 0 {
+            break;
+        }
+        healed.split += split;
+    }
+    let open = open_edges(mesh);
+    healed.open_edges = open.len();
+    (healed.largest_gap, healed.hole_edges) = gaps(mesh, &open);
+    Ok(healed)
+}
+
+// Every open edge, walked the way its face walks it
+fn open_edges(mesh: &Mesh) -> Vec<(usize, usize, usize)> {
+    let mut open: Vec<(usize, usize, usize)> = audit::edge_faces(mesh)
+        .into_iter()
+        .filter_map(|((low, high), faces)| match faces[..] {
+            [(f, true)] => Some((low, high, f)),
+            [(f, false)] => Some((high, low, f)),
+            _ => None,
+        })
+        .collect();
+    open.sort_unstable();
+    open
+}
+
+/// Join `mesh`'s vertices closer than `tolerance`, on open edges or not;
+/// returns the vertices merged away and the faces dropped.
+pub fn weld(mesh: &mut Mesh, tolerance: f32) -> Result<(usize, usize)> {
+    if !tolerance.is_finite() || tolerance <= 0.0 {
+        bail!("the weld tolerance must be a positive distance");
+    }
+    let all: Vec<usize> = (0..mesh.vertex_count()).collect();
+    Ok(snap_vertices(mesh, &all, tolerance))
+}
+
+// 1. Merge open-edge vertices closer than `tolerance`; (vertices merged
+// away, faces dropped)
+fn snap(mesh: &mut Mesh, tolerance: f32) -> (usize, usize) {
+    let mut on_rim: Vec<usize> = open_edges(mesh)
+        .iter()
+        .flat_map(|&(p, q, _)| [p, q])
+        .collect();
+    on_rim.sort_unstable();
+    on_rim.dedup();
+    snap_vertices(mesh, &on_rim, tolerance)
+}
+
+// Merge those of `on_rim` closer than `tolerance`, each cluster to its
+// middle
+fn snap_vertices(mesh: &mut Mesh, on_rim: &[usize], tolerance: f32) -> (usize, usize) {
+    let points: Vec<[f32; 3]> = on_rim.iter().map(|&v| mesh.vertex(v)).collect();
+    let tree = KdTree::new(&points, 1);
+
+    // Clusters of rim vertices, by union-find over close pairs
+    let mut parent: Vec<usize> = (0..points.len()).collect();
+    for (i, &p) in points.iter().enumerate() {
+        for (j, dist_sq) in tree.nearest_k(p, 8) {
+            if j != i && dist_sq <= tolerance * tolerance {
+                let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                parent[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut sums: HashMap<usize, ([f32; 3], usize)> = HashMap::new();
+    for (i, p) in points.iter().enumerate() {
+        let (sum, n) = sums.entry(root(&mut parent, i)).or_default();
+        *sum = [0, 1, 2].map(|k| sum[k] + p[k]);
+        *n += 1;
+    }
+
+    // Each cluster onto its first vertex, at the cluster's middle
+    let mut remap: Vec<u32> = (0..mesh.vertex_count() as u32).collect();
+    let mut snapped = 0;
+    for i in 0..points.len() {
+        let r = root(&mut parent, i);
+        if r == i {
+            let (sum, n) = sums[&r];
+            let middle = sum.map(|s| s / n as f32);
+            mesh.positions[on_rim[i] * 3..on_rim[i] * 3 + 3].copy_from_slice(&middle);
+        } else {
+            remap[on_rim[i]] = on_rim[r] as u32;
+            snapped += 1;
+        }
+    }
+    if snapped == 0 {
+        return (0, 0);
+    }
+    // Their attributes to the cluster's mean too
+    for attribute in &mut mesh.vertex_attributes {
+        let mut sums: HashMap<usize, (f32, usize)> = HashMap::new();
+        for (i, &v) in on_rim.iter().enumerate() {
+            let (sum, n) = sums.entry(root(&mut parent, i)).or_default();
+            *sum += attribute.values[v];
+            *n += 1;
+        }
+        for (r, (sum, n)) in sums {
+            attribute.values[on_rim[r]] = sum / n as f32;
+        }
+    }
+    for i in &mut mesh.indices {
+        *i = remap[*i as usize];
+    }
+    let keep: Vec<bool> = (0..mesh.face_count())
+        .map(|f| {
+            let [a, b, c] = mesh.face(f);
+            a != b && b != c && c != a
+        })
+        .collect();
+    let dropped = keep.iter().filter(|&&k| !k).count();
+    // Faces, and then the vertices merged away with nothing using them
+    *mesh = mesh.select_faces(|f| keep[f]);
+    (snapped, dropped)
+}
+
+// An open edge p -> q and the vertices along it, by how far along
+type Split = (usize, usize, Vec<(f32, usize)>);
+
+// The cluster `i` is in, by its lowest member
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+// 2. Split open edges at open-edge vertices lying along them; how many
+// vertices edges were split at
+fn split_t_junctions(mesh: &mut Mesh, tolerance: f32) -> usize {
+    let open = open_edges(mesh);
+    if open.is_empty() {
+        return 0;
+    }
+    let mut on_rim: Vec<usize> = open.iter().flat_map(|&(p, q, _)| [p, q]).collect();
+    on_rim.sort_unstable();
+    on_rim.dedup();
+    let points: Vec<[f32; 3]> = on_rim.iter().map(|&v| mesh.vertex(v)).collect();
+    let tree = KdTree::new(&points, 1);
+
+    // For each face, the vertices to split its open edge at, by how far
+    // along it (one edge a face a pass)
+    let mut splits: HashMap<usize, Split> = HashMap::new();
+    for &(p, q, f) in &open {
+        if splits.get(&f).is_some_and(|(a, b, _)| (*a, *b) != (p, q)) {
+            continue;
+        }
+        let (a, b) = (mesh.vertex(p), mesh.vertex(q));
+        let corners = mesh.face(f);
+        let middle = [0, 1, 2].map(|k| 0.5 * (a[k] + b[k]));
+        let half_sq = distance_sq(a, middle);
+        // Any vertex along the edge is within half its length of the middle
+        let reach = half_sq.sqrt() + tolerance;
+        let mut along = Vec::new();
+        for (i, dist_sq) in tree.nearest_k(middle, NEIGHBOURS) {
+            let v = on_rim[i];
+            if dist_sq > reach * reach || corners.contains(&v) {
+                continue;
+            }
+            let (t, closest) = closest_on_segment(a, b, points[i]);
+            if t > 0.0 && t < 1.0 && distance_sq(closest, points[i]) <= tolerance * tolerance {
+                along.push((t, v));
+            }
+        }
+        if !along.is_empty() {
+            along.sort_by(|x, y| x.0.total_cmp(&y.0));
+            splits.insert(f, (p, q, along));
+        }
+    }
+
+    let mut split = 0;
+    let mut faces: Vec<usize> = splits.keys().copied().collect();
+    faces.sort_unstable();
+    for f in faces {
+        let (p, q, along) = &splits[&f];
+        let corners = mesh.face(f);
+        let k = (0..3)
+            .find(|&k| corners[k] == *p && corners[(k + 1) % 3] == *q)
+            .expect("the open edge is one of its face's");
+        let r = corners[(k + 2) % 3];
+        // A fan from the far corner across the points along the edge,
+        // wound as the face was: the first in its place, the rest after
+        let mut chain = vec![(0.0, *p)];
+        chain.extend(along.iter().copied());
+        chain.push((1.0, *q));
+        let (uv_p, uv_q) = (mesh.texcoord(f * 3 + k), mesh.texcoord(f * 3 + (k + 1) % 3));
+        let uv = |t: f32| {
+            uv_p.zip(uv_q)
+                .map(|(a, b)| [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t])
+        };
+        let uv_r = mesh.texcoord(f * 3 + (k + 2) % 3);
+        let material = mesh.face_material(f);
+        for (n, pair) in chain.windows(2).enumerate() {
+            let [(t0, v0), (t1, v1)] = [pair[0], pair[1]];
+            let triangle = [v0, v1, r].map(|v| v as u32);
+            let uvs = [uv(t0), uv(t1), uv_r];
+            if n == 0 {
+                mesh.indices[f * 3..f * 3 + 3].copy_from_slice(&triangle);
+                if let [Some(a), Some(b), Some(c)] = uvs {
+                    mesh.texcoords[f * 6..f * 6 + 6]
+                        .copy_from_slice(&[a[0], a[1], b[0], b[1], c[0], c[1]]);
+                }
+                continue;
+            }
+            mesh.indices.extend_from_slice(&triangle);
+            if let [Some(a), Some(b), Some(c)] = uvs {
+                mesh.texcoords
+                    .extend_from_slice(&[a[0], a[1], b[0], b[1], c[0], c[1]]);
+            }
+            if !mesh.face_materials.is_empty() {
+                mesh.face_materials.push(material);
+            }
+        }
+        split += along.len();
+    }
+    split
+}
+
+// The widest gap between open edges and the edges across from them, and
+// how many open edges have none
+fn gaps(mesh: &Mesh, open: &[(usize, usize, usize)]) -> (Option<f32>, usize) {
+    let segments: Vec<([f32; 3], [f32; 3])> = open
+        .iter()
+        .map(|&(p, q, _)| (mesh.vertex(p), mesh.vertex(q)))
+        .collect();
+    let middles: Vec<[f32; 3]> = segments
+        .iter()
+        .map(|(a, b)| [0, 1, 2].map(|k| 0.5 * (a[k] + b[k])))
+        .collect();
+    let tree = KdTree::new(&middles, 1);
+    let mut largest: Option<f32> = None;
+    let mut holes = 0;
+    for (e, &(a, b)) in segments.iter().enumerate() {
+        let direction = unit(sub(b, a));
+        // The nearest edge running back the other way (one sharing an end
+        // with this is the other side of a wedge-shaped gap), and the
+        // farther of this edge's ends from it
+        let across = tree
+            .nearest_k(middles[e], NEIGHBOURS)
+            .into_iter()
+            .filter(|&(o, _)| {
+                let (c, d) = segments[o];
+                o != e && dot(direction, unit(sub(d, c))) < -0.5
+            })
+            .map(|(o, _)| {
+                let (c, d) = segments[o];
+                let gap = |p| distance_sq(closest_on_segment(c, d, p).1, p);
+                gap(a).max(gap(b)).sqrt()
+            })
+            .min_by(f32::total_cmp);
+        match across {
+            Some(gap) => largest = Some(largest.map_or(gap, |l| l.max(gap))),
+            None => holes += 1,
+        }
+    }
+    (largest, holes)
+}
+
+// Where along a -> b the point nearest `p` is (0 to 1), and the point
+fn closest_on_segment(a: [f32; 3], b: [f32; 3], p: [f32; 3]) -> (f32, [f32; 3]) {
+    let ab = sub(b, a);
+    let length_sq = dot(ab, ab);
+    let t = if length_sq > 0.0 {
+        (dot(sub(p, a), ab) / length_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    (t, [0, 1, 2].map(|k| a[k] + ab[k] * t))
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn distance_sq(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let d = sub(a, b);
+    dot(d, d)
+}
+
+fn unit(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    if length > 0.0 {
+        v.map(|x| x / length)
+    } else {
+        v
+    }
+}
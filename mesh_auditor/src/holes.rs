@@ -0,0 +1,180 @@
+//! `repair --fill-holes`: patching a scan's small holes where they are,
+//! without remeshing the whole of it.
+//!
+//! A hole is a loop of open edges (edges with one face). Each small enough
+//! to fill, by the length of its rim and the area it spans, is closed with
+//! the triangulation of its rim that has the least area (Barequet and
+//! Sharir's dynamic programme): flat across a flat hole, and a taut sheet
+//! across a bent one. No new vertices are added, so a big hole's patch is
+//! made of long thin triangles; a remesh smooths those out if they matter.
+//! The patch is wound like the faces round it, takes the material of the
+//! face along the rim's first edge, and each corner's UV from a face
+//! there.
+//!
+//! The programme takes time in the cube of the rim's edge count, so rims
+//! of more than `MAX_RIM_EDGES` are left open whatever their size, as are
+//! rims passing through a vertex twice (two holes meeting at a point),
+//! which no patch of that kind can close without folding.
+
+use crate::audit;
+use crate::completeness::{boundary_loops, vector_area};
+use crate::mesh::Mesh;
+use crate::sandbox;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+
+/// The most edges round a hole that will be filled.
+pub const MAX_RIM_EDGES: usize = 300;
+
+/// Which holes to fill.
+#[derive(Debug, Clone, Copy)]
+pub struct HoleLimits {
+    /// Longest rim, in model units.
+    pub max_perimeter: f64,
+    /// Biggest area spanned, in square model units; any if `None`.
+    pub max_area: Option<f64>,
+}
+
+/// One hole, filled or not.
+#[derive(Debug, Clone)]
+pub struct Hole {
+    pub edges: usize,
+    pub perimeter: f64,
+    pub area: f64,
+    /// The mean of its rim's vertices.
+    pub centre: [f32; 3],
+}
+
+/// What filling did.
+#[derive(Debug, Default)]
+pub struct Filled {
+    pub filled: Vec<Hole>,
+    /// Faces added across them.
+    pub faces: usize,
+    /// Holes over the limits, or too tangled to fill, biggest first.
+    pub left_open: Vec<Hole>,
+}
+
+/// Fill `mesh`'s holes within `limits`.
+pub fn fill_holes(mesh: &mut Mesh, limits: HoleLimits) -> Result<Filled> {
+    if !limits.max_perimeter.is_finite() || limits.max_perimeter <= 0.0 {
+        bail!("the largest perimeter must be a positive distance");
+    }
+    if limits
+        .max_area
+        .is_some_and(|area| !area.is_finite() || area <= 0.0)
+    {
+        bail!("the largest area must be a positive number");
+    }
+    let edges = audit::edge_faces(mesh);
+
+    // A corner at each rim vertex, for its UV, from an open edge's face
+    let mut corner_at: HashMap<usize, usize> = HashMap::new();
+    let mut face_along: HashMap<(usize, usize), usize> = HashMap::new();
+    for (&(low, high), faces) in &edges {
+        if let [(f, forward)] = faces[..] {
+            let (from, to) = if forward { (high, low) } else { (low, high) };
+            face_along.insert((from, to), f);
+            let corners = mesh.face(f);
+            for v in [low, high] {
+                let k = corners
+                    .iter()
+                    .position(|&c| c == v)
+                    .expect("the edge is its face's");
+                corner_at.entry(v).or_insert(f * 3 + k);
+            }
+        }
+    }
+
+    let mut filled = Filled::default();
+    for rim in boundary_loops(mesh, &edges) {
+        let points: Vec<[f64; 3]> = rim.iter().map(|&v| mesh.vertex(v).map(f64::from)).collect();
+        let perimeter = (0..points.len())
+            .map(|i| distance(points[i], points[(i + 1) % points.len()]))
+            .sum::<f64>();
+        let n = points.len() as f64;
+        let hole = Hole {
+            edges: rim.len(),
+            perimeter,
+            area: vector_area(&points),
+            centre: [0, 1, 2].map(|k| (points.iter().map(|p| p[k]).sum::<f64>() / n) as f32),
+        };
+        let pinched = rim.iter().collect::<HashSet<_>>().len() < rim.len();
+        if hole.perimeter > limits.max_perimeter
+            || limits.max_area.is_some_and(|area| hole.area > area)
+            || hole.edges > MAX_RIM_EDGES
+            || pinched
+        {
+            filled.left_open.push(hole);
+            continue;
+        }
+        sandbox::checkpoint();
+        let material = face_along
+            .get(&(rim[0], rim[1]))
+            .and_then(|&f| mesh.face_material(f));
+        let uvs: Vec<Option<[f32; 2]>> = rim
+            .iter()
+            .map(|v| corner_at.get(v).and_then(|&corner| mesh.texcoord(corner)))
+            .collect();
+        for [a, b, c] in least_area(&points) {
+            mesh.indices
+                .extend_from_slice(&[rim[a], rim[b], rim[c]].map(|v| v as u32));
+            if !mesh.texcoords.is_empty() {
+                for i in [a, b, c] {
+                    mesh.texcoords
+                        .extend_from_slice(&uvs[i].unwrap_or([0.0; 2]));
+                }
+            }
+            if !mesh.face_materials.is_empty() {
+                mesh.face_materials.push(material);
+            }
+            filled.faces += 1;
+        }
+        filled.filled.push(hole);
+    }
+    filled
+        .left_open
+        .sort_by(|a, b| b.perimeter.total_cmp(&a.perimeter));
+    Ok(filled)
+}
+
+// The triangles, as positions in `rim`, that close it with the least
+// area; each runs the way the rim does
+fn least_area(rim: &[[f64; 3]]) -> Vec<[usize; 3]> {
+    let n = rim.len();
+    // best[i][j]: the least area closing rim i..=j with the chord j -> i,
+    // and the vertex the triangle on that chord has
+    let mut best = vec![vec![(0.0f64, 0usize); n]; n];
+    for span in 2..n {
+        for i in 0..n - span {
+            let j = i + span;
+            best[i][j] = (i + 1..j)
+                .map(|m| {
+                    let area = best[i][m].0 + best[m][j].0 + triangle_area(rim[i], rim[m], rim[j]);
+                    (area, m)
+                })
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .expect("a span of two has a vertex between");
+        }
+    }
+    let mut triangles = Vec::with_capacity(n - 2);
+    let mut chords = vec![(0, n - 1)];
+    while let Some((i, j)) = chords.pop() {
+        if j - i < 2 {
+            continue;
+        }
+        let m = best[i][j].1;
+        triangles.push([i, m, j]);
+        chords.push((i, m));
+        chords.push((m, j));
+    }
+    triangles
+}
+
+fn triangle_area(a: [f64; 3], b: [f64; 3], c: [f64; 3]) -> f64 {
+    vector_area(&[a, b, c])
+}
+
+fn distance(a: [f64; 3], b: [f64; 3]) -> f64 {
+    (0..3).map(|k| (a[k] - b[k]).powi(2)).sum::<f64>().sqrt()
+}
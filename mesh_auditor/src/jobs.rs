@@ -0,0 +1,560 @@
+//! The persistent job queue behind `serve`.
+//!
+//! Every job lives in its own directory under the data dir:
+//!
+//! ```text
+//! <data-dir>/<job id>/job.json     the job record (status, params, timings)
+//! <data-dir>/<job id>/input.obj    the uploaded scan, or one fetched from
+//!                                  a remote `input` location (as .stl or
+//!                                  .ply if that's what it names)
+//! <data-dir>/<job id>/output.stl   the result, once the job is done
+//! ```
+//!
+//! The record is rewritten (atomically, via rename) on every state change,
+//! so a restarted service can pick up exactly where it left off: queued
+//! jobs stay queued, and jobs that were running when we went down are put
+//! back in the queue to run again.
+
+use crate::clock::unix_now;
+use crate::dump::{self, Stage};
+use crate::extract::marching_cubes;
+use crate::limits::InputLimits;
+use crate::mesh::{Mesh, INPUT_EXTENSIONS};
+use crate::metrics::{JobMetrics, Metrics};
+use crate::output::WriteOptions;
+use crate::pipeline::StageTimings;
+use crate::remesh::{self, ScanField};
+use crate::samples::Storage;
+use crate::sandbox::{self, AbortReason, JobLimits};
+use crate::slabs::Slabs;
+use crate::stl::save_triangles_as_stl;
+use crate::storage::{self, Hosts};
+use crate::webhook::Callbacks;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex};
+use std::time::Instant;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+/// Everything we know about one job.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    /// Submission order, used to break priority ties first-come first-served.
+    pub seq: u64,
+    /// Higher runs first.
+    pub priority: i32,
+    pub status: JobStatus,
+    pub resolution: usize,
+    pub iso: Option<f32>,
+    pub submitted_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub triangles: Option<usize>,
+    pub error: Option<String>,
+    /// Where to POST a summary when the job finishes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub callback: Option<String>,
+    /// Remote scan to read instead of an uploaded `input.obj`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    /// Remote location to also write the result to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// Name of the API key that submitted the job.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+}
+
+impl JobStatus {
+    /// The name used in the API and in metrics labels.
+    pub fn name(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Done => "done",
+            JobStatus::Failed => "failed",
+            JobStatus::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl Job {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            JobStatus::Done | JobStatus::Failed | JobStatus::Cancelled
+        )
+    }
+}
+
+/// What a client asks for when submitting.
+#[derive(Debug, Clone)]
+pub struct JobRequest {
+    pub priority: i32,
+    pub resolution: usize,
+    pub iso: Option<f32>,
+    pub callback: Option<String>,
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub owner: Option<String>,
+}
+
+pub struct JobQueue {
+    dir: PathBuf,
+    callbacks: Callbacks,
+    write: WriteOptions,
+    slabs: Slabs,
+    pub metrics: Metrics,
+    state: Mutex<QueueState>,
+    wakeup: Condvar,
+}
+
+struct QueueState {
+    jobs: HashMap<String, Job>,
+    next_seq: u64,
+}
+
+impl JobQueue {
+    /// Open (or create) the queue in `dir`, recovering any jobs left over
+    /// from a previous run. Jobs sample on `slabs` and write their STL as
+    /// `write` says.
+    pub fn open(
+        dir: &Path,
+        callbacks: Callbacks,
+        write: WriteOptions,
+        slabs: Slabs,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("could not create data dir {}", dir.display()))?;
+
+        let mut jobs = HashMap::new();
+        let mut next_seq = 1;
+        for entry in fs::read_dir(dir)? {
+            let record = entry?.path().join("job.json");
+            if !record.exists() {
+                continue;
+            }
+            let text = fs::read_to_string(&record)?;
+            let mut job: Job = match serde_json::from_str(&text) {
+                Ok(job) => job,
+                Err(e) => {
+                    println!(
+                        "⚠️  Skipping unreadable job record {}: {}",
+                        record.display(),
+                        e
+                    );
+                    continue;
+                }
+            };
+            if job.status == JobStatus::Running {
+                // We died mid-job; run it again from the start
+                job.status = JobStatus::Queued;
+                job.started_at = None;
+            }
+            next_seq = next_seq.max(job.seq + 1);
+            jobs.insert(job.id.clone(), job);
+        }
+
+        let queue = JobQueue {
+            dir: dir.to_path_buf(),
+            callbacks,
+            write,
+            slabs,
+            metrics: Metrics::default(),
+            state: Mutex::new(QueueState { jobs, next_seq }),
+            wakeup: Condvar::new(),
+        };
+        for job in queue.state.lock().unwrap().jobs.values() {
+            queue.persist(job)?;
+        }
+        Ok(queue)
+    }
+
+    pub fn job_dir(&self, id: &str) -> PathBuf {
+        self.dir.join(id)
+    }
+
+    pub fn input_path(&self, id: &str) -> PathBuf {
+        self.job_dir(id).join("input.obj")
+    }
+
+    pub fn output_path(&self, id: &str) -> PathBuf {
+        self.job_dir(id).join("output.stl")
+    }
+
+    /// Add a job. The upload is stored before the job becomes visible.
+    pub fn submit(&self, input: &[u8], request: JobRequest) -> Result<Job> {
+        let mut state = self.state.lock().unwrap();
+        let seq = state.next_seq;
+        state.next_seq += 1;
+
+        let job = Job {
+            id: format!("job-{:06}", seq),
+            seq,
+            priority: request.priority,
+            status: JobStatus::Queued,
+            resolution: request.resolution,
+            iso: request.iso,
+            submitted_at: unix_now(),
+            started_at: None,
+            finished_at: None,
+            triangles: None,
+            error: None,
+            callback: request.callback,
+            input: request.input,
+            output: request.output,
+            owner: request.owner,
+        };
+        fs::create_dir_all(self.job_dir(&job.id))?;
+        if job.input.is_none() {
+            fs::write(self.input_path(&job.id), input)?;
+        }
+        self.persist(&job)?;
+        state.jobs.insert(job.id.clone(), job.clone());
+        self.wakeup.notify_all();
+        Ok(job)
+    }
+
+    pub fn get(&self, id: &str) -> Option<Job> {
+        self.state.lock().unwrap().jobs.get(id).cloned()
+    }
+
+    /// All jobs, oldest first.
+    pub fn list(&self) -> Vec<Job> {
+        let mut jobs: Vec<Job> = self.state.lock().unwrap().jobs.values().cloned().collect();
+        jobs.sort_by_key(|j| j.seq);
+        jobs
+    }
+
+    /// Cancel a job. Queued jobs never start; a running job is stopped at its
+    /// next checkpoint. Finished jobs are left alone.
+    pub fn cancel(&self, id: &str) -> Result<Job> {
+        let mut state = self.state.lock().unwrap();
+        let Some(job) = state.jobs.get_mut(id) else {
+            bail!("no such job: {}", id);
+        };
+        match job.status {
+            JobStatus::Queued => {
+                job.status = JobStatus::Cancelled;
+                job.finished_at = Some(unix_now());
+                let job = job.clone();
+                self.persist(&job)?;
+                self.metrics.record_cancelled();
+                self.callbacks.job_finished(&job);
+                return Ok(job);
+            }
+            // The worker records the cancellation when the job unwinds
+            JobStatus::Running => sandbox::cancel(),
+            _ => {}
+        }
+        Ok(job.clone())
+    }
+
+    /// Block until there is a job to run, mark it running and return it.
+    fn take_next(&self) -> Result<Job> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            let next = state
+                .jobs
+                .values()
+                .filter(|j| j.status == JobStatus::Queued)
+                .max_by_key(|j| (j.priority, std::cmp::Reverse(j.seq)))
+                .map(|j| j.id.clone());
+            if let Some(id) = next {
+                let job = state.jobs.get_mut(&id).unwrap();
+                job.status = JobStatus::Running;
+                job.started_at = Some(unix_now());
+                let job = job.clone();
+                self.persist(&job)?;
+                return Ok(job);
+            }
+            state = self.wakeup.wait(state).unwrap();
+        }
+    }
+
+    fn finish(&self, id: &str, outcome: Result<usize>) -> Result<Job> {
+        let mut state = self.state.lock().unwrap();
+        // Any cancel aimed at this job is moot now
+        sandbox::clear_cancel();
+        let job = state.jobs.get_mut(id).context("job vanished")?;
+        job.finished_at = Some(unix_now());
+        match outcome {
+            Ok(triangles) => {
+                job.status = JobStatus::Done;
+                job.triangles = Some(triangles);
+            }
+            Err(e) => {
+                job.status = match e.downcast_ref::<AbortReason>() {
+                    Some(AbortReason::Cancelled) => JobStatus::Cancelled,
+                    _ => JobStatus::Failed,
+                };
+                job.error = Some(format!("{:#}", e));
+            }
+        }
+        let job = job.clone();
+        self.persist(&job)?;
+        self.callbacks.job_finished(&job);
+        Ok(job)
+    }
+
+    /// Run jobs forever, one at a time, each under `job_limits`.
+    pub fn work(&self, limits: &InputLimits, job_limits: &JobLimits) -> Result<()> {
+        loop {
+            let job = self.take_next()?;
+            println!("▶️  {} started (priority {})", job.id, job.priority);
+            sandbox::reset_peak();
+            let base = sandbox::allocated();
+            let start = Instant::now();
+            let mut timings = StageTimings::default();
+            // A bug in one job shouldn't take the worker, and every job
+            // queued behind it, down with it
+            let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+                sandbox::run_job(job_limits, || self.execute(&job, limits, &mut timings))
+            }))
+            .unwrap_or_else(|payload| Err(anyhow::Error::new(Panicked(panic_message(&*payload)))));
+
+            let (input_triangles, output_triangles) = *outcome.as_ref().unwrap_or(&(0, 0));
+            let failure = outcome.as_ref().err().map(failure_reason);
+            let job = self.finish(&job.id, outcome.map(|(_, output)| output))?;
+            self.metrics.record_job(JobMetrics {
+                status: job.status.name(),
+                failure: failure.filter(|_| job.status == JobStatus::Failed),
+                seconds: start.elapsed().as_secs_f64(),
+                timings: &timings,
+                input_triangles,
+                output_triangles,
+                peak_memory: sandbox::peak().saturating_sub(base),
+                process_peak: sandbox::peak(),
+            });
+            match &job.error {
+                None => println!("✅ {} done", job.id),
+                Some(e) => println!("❌ {} {:?}: {}", job.id, job.status, e),
+            }
+        }
+    }
+
+    // The actual remesh. Returns the number of triangles read and written.
+    fn execute(
+        &self,
+        job: &Job,
+        limits: &InputLimits,
+        timings: &mut StageTimings,
+    ) -> Result<(usize, usize)> {
+        dump::begin(&job.id);
+        let mesh = timings.time("load", || -> Result<Mesh> {
+            let input = match &job.input {
+                Some(location) => self.fetch(&job.id, location, limits)?,
+                None => self.input_path(&job.id),
+            };
+            Mesh::load(&input.to_string_lossy(), limits)
+        })?;
+        dump::mesh(Stage::Load, &mesh);
+        // The same field `mesh_lifter remesh` would use by default
+        let kind = ScanField::default().for_scan(&mesh);
+        let iso = kind.check_iso(job.iso)?;
+        let field = timings.time("sample", || {
+            remesh::sample(
+                &mesh,
+                kind,
+                job.resolution,
+                remesh::INFLUENCE,
+                Storage::Full,
+                &self.slabs,
+            )
+        });
+        dump::field(Stage::Sample, &field);
+        let triangles = timings.time("extract", || marching_cubes(&field, iso));
+        dump::triangles(Stage::Extract, &triangles);
+        timings.time("save", || -> Result<()> {
+            let path = self.output_path(&job.id);
+            save_triangles_as_stl(&triangles, &path.to_string_lossy(), &self.write)?;
+            if let Some(output) = &job.output {
+                storage::upload(output, &fs::read(&path)?, Hosts::Public)?;
+            }
+            Ok(())
+        })?;
+        Ok((mesh.face_count(), triangles.len() / 9))
+    }
+
+    // Copy a remote input into the job's directory, connecting only to
+    // public addresses: the client chose the location
+    fn fetch(&self, id: &str, location: &str, limits: &InputLimits) -> Result<PathBuf> {
+        let extension = Path::new(location.split(['?', '#']).next().unwrap_or(location))
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .filter(|e| INPUT_EXTENSIONS.contains(&e.as_str()))
+            .unwrap_or_else(|| "obj".to_string());
+        let path = self.job_dir(id).join(format!("input.{}", extension));
+        let mut input = storage::open_from(location, limits, Hosts::Public)?;
+        let mut file = fs::File::create(&path)?;
+        std::io::copy(&mut input, &mut file)
+            .with_context(|| format!("could not fetch {}", location))?;
+        Ok(path)
+    }
+
+    // Write the job record, atomically replacing the old one
+    fn persist(&self, job: &Job) -> Result<()> {
+        let dir = self.job_dir(&job.id);
+        let tmp = dir.join("job.json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(job)?)?;
+        fs::rename(&tmp, dir.join("job.json"))?;
+        Ok(())
+    }
+}
+
+// How a failed job's error is labelled in the metrics
+fn failure_reason(error: &anyhow::Error) -> &'static str {
+    if error.is::<Panicked>() {
+        return "panic";
+    }
+    match error.downcast_ref::<AbortReason>() {
+        Some(AbortReason::Timeout { .. }) => "timeout",
+        Some(AbortReason::Memory { .. }) => "memory",
+        _ => "error",
+    }
+}
+
+/// A job that panicked, with what it said.
+#[derive(Debug)]
+struct Panicked(String);
+
+impl fmt::Display for Panicked {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "internal error: {}", self.0)
+    }
+}
+
+impl std::error::Error for Panicked {}
+
+// What `panic!` was given, when it was given text
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(text) = payload.downcast_ref::<&str>() {
+        text.to_string()
+    } else if let Some(text) = payload.downcast_ref::<String>() {
+        text.clone()
+    } else {
+        "the job panicked".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn panics_become_failures() {
+        let payload = panic::catch_unwind(|| panic!("index {} out of range", 7)).unwrap_err();
+        let error = anyhow::Error::new(Panicked(panic_message(&*payload)));
+        assert_eq!(failure_reason(&error), "panic");
+        assert_eq!(error.to_string(), "internal error: index 7 out of range");
+    }
+
+    fn request(priority: i32) -> JobRequest {
+        JobRequest {
+            priority,
+            resolution: 32,
+            iso: None,
+            callback: None,
+            input: None,
+            output: None,
+            owner: Some("lab".to_string()),
+        }
+    }
+
+    fn open(dir: &Path) -> JobQueue {
+        let callbacks = Callbacks {
+            default_url: None,
+            public_url: "http://localhost".to_string(),
+        };
+        JobQueue::open(dir, callbacks, WriteOptions::default(), Slabs::default()).unwrap()
+    }
+
+    #[test]
+    fn jobs_remesh_as_the_library_does() {
+        let dir = std::env::temp_dir().join(format!("jobs_remesh_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let queue = open(&dir);
+        let cube = b"v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nv 0 0 1\nv 1 0 1\nv 1 1 1\nv 0 1 1\n\
+                     f 1 3 2\nf 1 4 3\nf 5 6 7\nf 5 7 8\nf 1 2 6\nf 1 6 5\n\
+                     f 2 3 7\nf 2 7 6\nf 3 4 8\nf 3 8 7\nf 4 1 5\nf 4 5 8\n";
+        queue.submit(cube, request(0)).unwrap();
+        let job = queue.take_next().unwrap();
+        let limits = InputLimits::default();
+        let (read, written) = queue
+            .execute(&job, &limits, &mut StageTimings::default())
+            .unwrap();
+
+        let scan = Mesh::load(&queue.input_path(&job.id).to_string_lossy(), &limits).unwrap();
+        assert_eq!(read, 12);
+        assert_eq!(
+            written,
+            crate::remesh_voxel(&scan, 32).unwrap().face_count()
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn jobs_survive_a_restart() {
+        let dir = std::env::temp_dir().join(format!("jobs_restart_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        {
+            let queue = open(&dir);
+            let first = queue.submit(b"v 0 0 0", request(0)).unwrap();
+            let urgent = queue.submit(b"v 1 1 1", request(5)).unwrap();
+            let dropped = queue.submit(b"v 2 2 2", request(0)).unwrap();
+            queue.cancel(&dropped.id).unwrap();
+            // Highest priority first, and it is running when we "crash"
+            let running = queue.take_next().unwrap();
+            assert_eq!(running.id, urgent.id);
+            assert_eq!(queue.get(&first.id).unwrap().status, JobStatus::Queued);
+        }
+        // A record too damaged to read is skipped, not fatal
+        fs::create_dir_all(dir.join("job-broken")).unwrap();
+        fs::write(dir.join("job-broken").join("job.json"), "{ not json").unwrap();
+
+        let queue = open(&dir);
+        let jobs = queue.list();
+        assert_eq!(jobs.len(), 3);
+        let status: Vec<JobStatus> = jobs.iter().map(|j| j.status).collect();
+        assert_eq!(
+            status,
+            [JobStatus::Queued, JobStatus::Queued, JobStatus::Cancelled]
+        );
+        assert!(
+            jobs[1].started_at.is_none(),
+            "put back to run from the start"
+        );
+        assert_eq!(jobs[1].priority, 5);
+        assert_eq!(jobs[0].owner.as_deref(), Some("lab"));
+        assert_eq!(fs::read(queue.input_path(&jobs[0].id)).unwrap(), b"v 0 0 0");
+
+        // Numbering carries on after the jobs already there
+        let next = queue.submit(b"v 3 3 3", request(0)).unwrap();
+        assert_eq!(next.id, "job-000004");
+
+        // And finishing is recorded too
+        let running = queue.take_next().unwrap();
+        queue.finish(&running.id, Ok(12)).unwrap();
+        drop(queue);
+        let done = open(&dir).get(&running.id).unwrap();
+        assert_eq!(done.status, JobStatus::Done);
+        assert_eq!(done.triangles, Some(12));
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,216 @@
+//! A KD-tree over a point set, for nearest-point queries.
+//!
+//! The tree is implicit: the points are reordered so that each subtree is a
+//! contiguous run with its splitting point in the middle, and the only extra
+//! storage is the splitting axis of each middle point. Halves are disjoint
+//! runs, so the top few levels are built on their own threads, which for
+//! tens of millions of scan points is most of the work.
+//!
+//! Grid sampling asks about many points close together (a row of voxels),
+//! so `nearest_batch` takes them a chunk at a time and walks the tree once
+//! per chunk: a branch is only skipped when it is too far for every point
+//! in the chunk, and each point read is compared against the whole chunk
+//! while it is in cache.
+
+use crate::sandbox;
+use crate::threads;
+
+// Runs this short are searched point by point
+const LEAF: usize = 8;
+// Don't hand a run this short to another thread; it is over before the
+// thread starts
+const PARALLEL_MIN: usize = 65_536;
+// Queries walked down the tree together
+const CHUNK: usize = 64;
+
+#[derive(Debug, Clone)]
+pub struct KdTree {
+    points: Vec<[f32; 3]>,
+    // Where each point came from in the input
+    index: Vec<u32>,
+    // The splitting axis of the middle point of each run
+    axes: Vec<u8>,
+}
+
+impl KdTree {
+    /// Build the tree over `points`, on up to `threads` threads.
+    pub fn new(points: &[[f32; 3]], threads: usize) -> Self {
+        let mut entries: Vec<([f32; 3], u32)> = points
+            .iter()
+            .enumerate()
+            .map(|(i, &p)| (p, i as u32))
+            .collect();
+        let mut axes = vec![0u8; points.len()];
+        // Each level of threads halves the run, so this many levels fill them
+        let depth = threads.max(1).next_power_of_two().trailing_zeros() as usize;
+        build(&mut entries, &mut axes, depth);
+        KdTree {
+            points: entries.iter().map(|e| e.0).collect(),
+            index: entries.iter().map(|e| e.1).collect(),
+            axes,
+        }
+    }
+
+    /// The input index of the point nearest `query`, and its squared
+    /// distance.
+    pub fn nearest(&self, query: [f32; 3]) -> Option<(usize, f32)> {
+        let mut best = [(u32::MAX, f32::MAX)];
+        self.search(&[query], &mut best);
+        (best[0].0 != u32::MAX).then(|| (best[0].0 as usize, best[0].1))
+    }
+
+    /// `nearest` for every query, in order: `None` only for an empty tree.
+    /// Queries near each other in the slice should be near each other in
+    /// space (a row of a grid, say) for the batching to pay off.
+    pub fn nearest_batch(&self, queries: &[[f32; 3]]) -> Vec<Option<(usize, f32)>> {
+        let mut found = Vec::with_capacity(queries.len());
+        let mut best = [(u32::MAX, f32::MAX); CHUNK];
+        for chunk in queries.chunks(CHUNK) {
+            sandbox::checkpoint();
+            let best = &mut best[..chunk.len()];
+            best.fill((u32::MAX, f32::MAX));
+            self.search(chunk, best);
+            found.extend(
+                best.iter()
+                    .map(|&(i, d)| (i != u32::MAX).then_some((i as usize, d))),
+            );
+        }
+        found
+    }
+
+    /// The `k` points nearest `query`, nearest first, as (input index,
+    /// squared distance). A point of the tree at `query` is one of them.
+    pub fn nearest_k(&self, query: [f32; 3], k: usize) -> Vec<(usize, f32)> {
+        let mut best: Vec<(u32, f32)> = Vec::with_capacity(k + 1);
+        let mut stack: Vec<(usize, usize, f32)> = vec![(0, self.points.len(), 0.0)];
+        while let Some((start, end, gap)) = stack.pop() {
+            if k == 0 || (best.len() == k && gap >= best[k - 1].1) {
+                continue;
+            }
+            let mut offer = |i: usize| {
+                let p = self.points[i];
+                let dist_sq = (p[0] - query[0]).powi(2)
+                    + (p[1] - query[1]).powi(2)
+                    + (p[2] - query[2]).powi(2);
+                if best.len() < k || dist_sq < best[k - 1].1 {
+                    let at = best.partition_point(|b| b.1 <= dist_sq);
+                    best.insert(at, (self.index[i], dist_sq));
+                    best.truncate(k);
+                }
+            };
+            if end - start <= LEAF {
+                (start..end).for_each(&mut offer);
+                continue;
+            }
+            let middle = start + (end - start) / 2;
+            offer(middle);
+            let axis = self.axes[middle] as usize;
+            let split = self.points[middle][axis];
+            let below = (start, middle, (query[axis] - split).max(0.0).powi(2));
+            let above = (middle + 1, end, (split - query[axis]).max(0.0).powi(2));
+            if query[axis] < split {
+                stack.push(above);
+                stack.push(below);
+            } else {
+                stack.push(below);
+                stack.push(above);
+            }
+        }
+        best.into_iter().map(|(i, d)| (i as usize, d)).collect()
+    }
+
+    // One walk of the tree for all of `queries`, keeping the best (input
+    // index, squared distance) of each in `best`
+    fn search(&self, queries: &[[f32; 3]], best: &mut [(u32, f32)]) {
+        // The queries' box, to measure how far a branch is from all of them
+        let mut low = [f32::MAX; 3];
+        let mut high = [f32::MIN; 3];
+        for q in queries {
+            for k in 0..3 {
+                low[k] = low[k].min(q[k]);
+                high[k] = high[k].max(q[k]);
+            }
+        }
+        let centre = [0, 1, 2].map(|k| (low[k] + high[k]) * 0.5);
+
+        // Runs still to visit, with how far their side of the split is from
+        // the box (squared): pruned when that can't beat the worst best
+        let mut stack: Vec<(usize, usize, f32)> = vec![(0, self.points.len(), 0.0)];
+        while let Some((start, end, gap)) = stack.pop() {
+            let worst = best.iter().map(|b| b.1).fold(0.0f32, f32::max);
+            if gap >= worst {
+                continue;
+            }
+            if end - start <= LEAF {
+                for i in start..end {
+                    self.compare(i, queries, best);
+                }
+                continue;
+            }
+            let middle = start + (end - start) / 2;
+            self.compare(middle, queries, best);
+            let axis = self.axes[middle] as usize;
+            let split = self.points[middle][axis];
+            // Nearer half first, so the further one is more often pruned
+            let below = (start, middle, (low[axis] - split).max(0.0).powi(2));
+            let above = (middle + 1, end, (split - high[axis]).max(0.0).powi(2));
+            if centre[axis] < split {
+                stack.push(above);
+                stack.push(below);
+            } else {
+                stack.push(below);
+                stack.push(above);
+            }
+        }
+    }
+
+    // Offer point `i` to every query
+    fn compare(&self, i: usize, queries: &[[f32; 3]], best: &mut [(u32, f32)]) {
+        let p = self.points[i];
+        for (q, b) in queries.iter().zip(best.iter_mut()) {
+            let dist_sq = (p[0] - q[0]).powi(2) + (p[1] - q[1]).powi(2) + (p[2] - q[2]).powi(2);
+            if dist_sq < b.1 {
+                *b = (self.index[i], dist_sq);
+            }
+        }
+    }
+}
+
+// Arrange `entries` into the implicit tree: split at the middle along the
+// widest axis, then the halves, the first `depth` levels on their own threads
+fn build(entries: &mut [([f32; 3], u32)], axes: &mut [u8], depth: usize) {
+    let len = entries.len();
+    if len <= LEAF {
+        return;
+    }
+    sandbox::checkpoint();
+    let mut low = [f32::MAX; 3];
+    let mut high = [f32::MIN; 3];
+    for (p, _) in entries.iter() {
+        for k in 0..3 {
+            low[k] = low[k].min(p[k]);
+            high[k] = high[k].max(p[k]);
+        }
+    }
+    let axis = (0..3)
+        .max_by(|&a, &b| (high[a] - low[a]).total_cmp(&(high[b] - low[b])))
+        .unwrap_or(0);
+    let middle = len / 2;
+    entries.select_nth_unstable_by(middle, |a, b| a.0[axis].total_cmp(&b.0[axis]));
+    axes[middle] = axis as u8;
+
+    let (below, rest) = entries.split_at_mut(middle);
+    let above = &mut rest[1..];
+    let (axes_below, axes_rest) = axes.split_at_mut(middle);
+    let axes_above = &mut axes_rest[1..];
+    if depth == 0 || len < PARALLEL_MIN {
+        build(below, axes_below, 0);
+        build(above, axes_above, 0);
+        return;
+    }
+    std::thread::scope(|scope| {
+        let other = scope.spawn(|| build(above, axes_above, depth - 1));
+        build(below, axes_below, depth - 1);
+        threads::join_all([other]);
+    });
+}
@@ -0,0 +1,227 @@
+//! KTX2 textures with Basis Universal, for `--texture-format ktx2`.
+//!
+//! GPUs can't sample a JPEG: a viewer decodes it and uploads every pixel,
+//! four bytes each. A Basis Universal texture stays compressed on the GPU
+//! (viewers transcode it to whatever block format the hardware has), so a
+//! big scan's textures take a fraction of the video memory and load
+//! faster. glTF viewers read it through `KHR_texture_basisu`.
+//!
+//! We encode UASTC (the high quality Basis mode) with a full mipmap chain,
+//! and write the KTX2 container ourselves: the encoder we link only writes
+//! its own `.basis` files, but for UASTC those hold the same 16-byte blocks
+//! KTX2 wants, one slice per mip level. Each level is then Zstandard
+//! supercompressed. `--texture-quality` sets how hard the encoder works the
+//! blocks to compress well (rate-distortion optimisation): lower quality,
+//! smaller file. At 100 it is off.
+
+use anyhow::{bail, Result};
+use basis_universal::{
+    BasisTextureFormat, ColorSpace, Compressor, CompressorParams, UASTC_QUALITY_DEFAULT,
+};
+use image::DynamicImage;
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, b'K', b'T', b'X', b' ', b'2', b'0', 0xBB, b'\r', b'\n', 0x1A, b'\n',
+];
+const SUPERCOMPRESSION_ZSTD: u32 = 2;
+// Slow, but a texture is encoded once and downloaded many times
+const ZSTD_LEVEL: i32 = 18;
+// Data format descriptor values (Khronos Data Format spec)
+const MODEL_UASTC: u8 = 166;
+const PRIMARIES_BT709: u8 = 1;
+const TRANSFER_LINEAR: u8 = 1;
+const TRANSFER_SRGB: u8 = 2;
+const CHANNEL_UASTC_RGB: u8 = 0;
+const CHANNEL_UASTC_RGBA: u8 = 3;
+// UASTC blocks are 4x4 texels in 16 bytes
+const BLOCK_BYTES: usize = 16;
+
+/// `image` as a KTX2 file, encoded on `threads` threads. `data` marks
+/// normal/bump maps, which are encoded as linear values rather than sRGB
+/// colours.
+pub fn encode(
+    image: &DynamicImage,
+    data: bool,
+    alpha: bool,
+    quality: u8,
+    threads: usize,
+) -> Result<Vec<u8>> {
+    let (width, height) = (image.width(), image.height());
+    if width > basis_universal::TEXTURE_DIMENSION_MAX
+        || height > basis_universal::TEXTURE_DIMENSION_MAX
+    {
+        bail!(
+            "{}×{} is too big for Basis Universal (at most {} a side; try --texture-max-size)",
+            width,
+            height,
+            basis_universal::TEXTURE_DIMENSION_MAX
+        );
+    }
+
+    // 1. Encode to .basis
+    let pixels = if alpha {
+        image.to_rgba8().into_raw()
+    } else {
+        image.to_rgb8().into_raw()
+    };
+    let mut params = CompressorParams::new();
+    params.set_basis_format(BasisTextureFormat::UASTC4x4);
+    params.set_uastc_quality_level(UASTC_QUALITY_DEFAULT);
+    params.set_generate_mipmaps(true);
+    if data {
+        params.tune_for_normal_maps();
+        params.set_color_space(ColorSpace::Linear);
+    } else {
+        params.set_color_space(ColorSpace::Srgb);
+    }
+    // quality 85 -> 0.6, 50 -> 2: the useful range per the encoder's docs
+    params.set_rdo_uastc((quality < 100).then(|| f32::from(100 - quality) / 25.0));
+    params
+        .source_image_mut(0)
+        .init(&pixels, width, height, if alpha { 4 } else { 3 });
+    let mut compressor = Compressor::new(threads as u32);
+    // SAFETY: the parameters are all set through the wrapper's own setters,
+    // and the source image is initialised from a buffer of the right size
+    unsafe {
+        if !compressor.init(&params) {
+            bail!("the Basis Universal encoder would not start");
+        }
+        if let Err(e) = compressor.process() {
+            bail!("Basis Universal encoding failed: {:?}", e);
+        }
+    }
+
+    // 2. The UASTC blocks of each mip level, largest first
+    let levels = uastc_levels(compressor.basis_file())?;
+
+    // 3. Wrap them up
+    container(width, height, &levels, data, alpha)
+}
+
+// Level data (raw UASTC blocks) out of a .basis file, by mip level
+fn uastc_levels(basis: &[u8]) -> Result<Vec<&[u8]>> {
+    let uint = |at: usize, len: usize| -> Result<usize> {
+        let Some(bytes) = basis.get(at..at + len) else {
+            bail!("Basis Universal wrote a truncated file");
+        };
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0usize, |n, &b| (n << 8) | usize::from(b)))
+    };
+    // Offsets within `basis_file_header` and `basis_slice_desc`
+    if uint(0, 2)? != usize::from(b'B') << 8 | usize::from(b's') {
+        bail!("Basis Universal wrote something that isn't a .basis file");
+    }
+    const UASTC_4X4: usize = 1;
+    if uint(20, 1)? != UASTC_4X4 {
+        bail!("Basis Universal wrote ETC1S where UASTC was asked for");
+    }
+    let slices = uint(14, 3)?;
+    let table = uint(65, 4)?;
+    let mut levels = Vec::with_capacity(slices);
+    for s in 0..slices {
+        let desc = table + s * 23;
+        let (level, blocks_x, blocks_y) =
+            (uint(desc + 3, 1)?, uint(desc + 9, 2)?, uint(desc + 11, 2)?);
+        let (offset, size) = (uint(desc + 13, 4)?, uint(desc + 17, 4)?);
+        if level != s || size != blocks_x * blocks_y * BLOCK_BYTES {
+            bail!("Basis Universal wrote slices we don't understand");
+        }
+        let Some(data) = basis.get(offset..offset + size) else {
+            bail!("Basis Universal wrote a truncated file");
+        };
+        levels.push(data);
+    }
+    if levels.is_empty() {
+        bail!("Basis Universal wrote no image");
+    }
+    Ok(levels)
+}
+
+fn container(
+    width: u32,
+    height: u32,
+    levels: &[&[u8]],
+    data: bool,
+    alpha: bool,
+) -> Result<Vec<u8>> {
+    let compressed: Vec<Vec<u8>> = levels
+        .iter()
+        .map(|level| zstd::bulk::compress(level, ZSTD_LEVEL))
+        .collect::<std::io::Result<_>>()?;
+
+    // Data format descriptor: one basic block, one sample
+    let mut dfd = Vec::with_capacity(44);
+    dfd.extend_from_slice(&44u32.to_le_bytes()); // total size
+    dfd.extend_from_slice(&0u32.to_le_bytes()); // vendor 0 (Khronos), basic descriptor
+    dfd.extend_from_slice(&2u16.to_le_bytes()); // version
+    dfd.extend_from_slice(&40u16.to_le_bytes()); // block size: 24 + 16 per sample
+    let transfer = if data { TRANSFER_LINEAR } else { TRANSFER_SRGB };
+    dfd.extend_from_slice(&[MODEL_UASTC, PRIMARIES_BT709, transfer, 0]);
+    dfd.extend_from_slice(&[3, 3, 0, 0]); // block dimensions, minus one
+    dfd.extend_from_slice(&[BLOCK_BYTES as u8, 0, 0, 0, 0, 0, 0, 0]); // bytes per plane
+    let channel = if alpha {
+        CHANNEL_UASTC_RGBA
+    } else {
+        CHANNEL_UASTC_RGB
+    };
+    dfd.extend_from_slice(&0u16.to_le_bytes()); // bit offset
+    dfd.extend_from_slice(&[127, channel]); // bit length - 1, channel
+    dfd.extend_from_slice(&[0; 4]); // sample position
+    dfd.extend_from_slice(&0u32.to_le_bytes()); // lower
+    dfd.extend_from_slice(&u32::MAX.to_le_bytes()); // upper
+
+    // Key/value data: who wrote it
+    let mut kvd = Vec::new();
+    let entry = b"KTXwriter\0mesh_lifter\0";
+    kvd.extend_from_slice(&(entry.len() as u32).to_le_bytes());
+    kvd.extend_from_slice(entry);
+    while kvd.len() % 4 != 0 {
+        kvd.push(0);
+    }
+
+    // Header, index and level index, then the descriptor, key/values and
+    // levels in that order; levels go smallest first in the file
+    let header_len = 12 + 9 * 4 + 4 * 4 + 2 * 8 + levels.len() * 3 * 8;
+    let dfd_offset = header_len;
+    let kvd_offset = dfd_offset + dfd.len();
+    let mut offset = kvd_offset + kvd.len();
+    let mut level_offsets = vec![0; levels.len()];
+    for (l, level) in compressed.iter().enumerate().rev() {
+        level_offsets[l] = offset;
+        offset += level.len();
+    }
+
+    let mut out = Vec::with_capacity(offset);
+    out.extend_from_slice(&IDENTIFIER);
+    for value in [
+        0, // VK_FORMAT_UNDEFINED: the format is in the descriptor
+        1, // type size
+        width,
+        height,
+        0, // depth: 2D
+        0, // layers: not an array
+        1, // faces: not a cube map
+        levels.len() as u32,
+        SUPERCOMPRESSION_ZSTD,
+    ] {
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    for value in [dfd_offset, dfd.len(), kvd_offset, kvd.len()] {
+        out.extend_from_slice(&u32::try_from(value)?.to_le_bytes());
+    }
+    out.extend_from_slice(&0u64.to_le_bytes()); // no supercompression global data
+    out.extend_from_slice(&0u64.to_le_bytes());
+    for ((level, raw), &at) in compressed.iter().zip(levels).zip(&level_offsets) {
+        out.extend_from_slice(&(at as u64).to_le_bytes());
+        out.extend_from_slice(&(level.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(raw.len() as u64).to_le_bytes());
+    }
+    out.extend_from_slice(&dfd);
+    out.extend_from_slice(&kvd);
+    for level in compressed.iter().rev() {
+        out.extend_from_slice(level);
+    }
+    Ok(out)
+}
@@ -0,0 +1,197 @@
+//! Per-face labels: a name for every face of a mesh.
+//!
+//! Written as JSON, the names once and then an index into them per face, so
+//! a million-face scan's map stays small:
+//!
+//! ```json
+//! { "names": ["region_0", "region_1"], "faces": [0, 0, 1, 0, 1] }
+//! ```
+//!
+//! `segment --labels` writes one, and a person can paint their own in any
+//! tool that saves a PLY with an integer `label` on each face; its names
+//! come from `comment label 3 damaged` lines in the header, or are just
+//! the numbers.
+//!
+//! With `--face-labels` and `--only-label`, a stage touches only the faces
+//! carrying the labels named (decimation in `convert`, the voxel skin in
+//! `remesh`) and leaves every other face exactly as it was. A decimated
+//! selection still meets the rest, its cut locked in place; a remeshed one
+//! is a fresh skin written beside the faces kept, not stitched to them.
+//! The faces must be those of the input, in its order: the file's face
+//! count is checked against the mesh's.
+
+use crate::limits::InputLimits;
+use crate::mesh::Mesh;
+use crate::ply;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FaceLabels {
+    pub names: Vec<String>,
+    /// An index into `names` for each face, in the mesh's order.
+    pub faces: Vec<u32>,
+}
+
+/// `--only-label`: which faces a stage may change.
+#[derive(Debug, Clone)]
+pub struct LabelFilter {
+    labels: FaceLabels,
+    // Indexed like `labels.names`
+    wanted: Vec<bool>,
+    source: String,
+}
+
+impl FaceLabels {
+    /// Read labels from a .json map or a labelled .ply.
+    pub fn load(path: &str, limits: &InputLimits) -> Result<Self> {
+        let is_ply = Path::new(path)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("ply"));
+        let labels = if is_ply {
+            Self::from_ply(&ply::load(path, limits)?)
+                .with_context(|| format!("no face labels in {}", path))?
+        } else {
+            let mut text = String::new();
+            crate::storage::open(path, limits)?.read_to_string(&mut text)?;
+            serde_json::from_str(&text).with_context(|| format!("bad face labels in {}", path))?
+        };
+        if let Some(&bad) = labels
+            .faces
+            .iter()
+            .find(|&&l| l as usize >= labels.names.len())
+        {
+            bail!(
+                "{} gives a face label {} but names only {}",
+                path,
+                bad,
+                labels.names.len()
+            );
+        }
+        Ok(labels)
+    }
+
+    // The `label` of each face, named by the header's `comment label N name`
+    // lines
+    fn from_ply(ply: &ply::Ply) -> Result<Self> {
+        let Some(values) = ply.element("face").and_then(|faces| faces.scalar("label")) else {
+            bail!("the faces have no 'label' property");
+        };
+        let mut faces = Vec::with_capacity(values.len());
+        for &v in values {
+            if v < 0.0 || v.fract() != 0.0 || v > f64::from(u16::MAX) {
+                bail!("face label {} isn't a small whole number", v);
+            }
+            faces.push(v as u32);
+        }
+        let count = faces.iter().max().map_or(0, |&m| m as usize + 1);
+        let mut names: Vec<String> = (0..count).map(|n| n.to_string()).collect();
+        for comment in &ply.comments {
+            let words: Vec<&str> = comment.split_whitespace().collect();
+            if let ["label", n, name] = words[..] {
+                if let Some(slot) = n.parse::<usize>().ok().and_then(|n| names.get_mut(n)) {
+                    *slot = name.to_string();
+                }
+            }
+        }
+        Ok(FaceLabels { names, faces })
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)
+            .with_context(|| format!("could not write face labels to {}", path))
+    }
+}
+
+impl LabelFilter {
+    /// Faces carrying any of the labels `only`, from `labels` (read from
+    /// `source`).
+    pub fn new(labels: FaceLabels, only: &[String], source: &str) -> Result<Self> {
+        let wanted: Vec<bool> = labels.names.iter().map(|n| only.contains(n)).collect();
+        if let Some(missing) = only.iter().find(|o| !labels.names.contains(o)) {
+            bail!(
+                "no label '{}' in {} (it has: {})",
+                missing,
+                source,
+                labels.names.join(", ")
+            );
+        }
+        Ok(LabelFilter {
+            labels,
+            wanted,
+            source: source.to_string(),
+        })
+    }
+
+    /// The names picked, for messages.
+    pub fn describe(&self) -> String {
+        let names: Vec<&str> = (0..self.wanted.len())
+            .filter(|&l| self.wanted[l])
+            .map(|l| self.labels.names[l].as_str())
+            .collect();
+        names.join(", ")
+    }
+
+    /// Split `mesh` into the faces picked and the rest, after checking the
+    /// labels are for it.
+    pub fn split(&self, mesh: &Mesh) -> Result<(Mesh, Mesh)> {
+        if self.labels.faces.len() != mesh.face_count() {
+            bail!(
+                "{} labels {} faces but the mesh has {}",
+                self.source,
+                self.labels.faces.len(),
+                mesh.face_count()
+            );
+        }
+        let picked = |f: usize| self.wanted[self.labels.faces[f] as usize];
+        Ok((mesh.select_faces(picked), mesh.select_faces(|f| !picked(f))))
+    }
+}
+
+/// Put a processed selection back with the faces left alone, joining the
+/// vertices they share along the cut: those at exactly the same spot.
+pub fn rejoin(mut processed: Mesh, rest: &Mesh) -> Mesh {
+    let mut at: HashMap<[u32; 3], u32> = HashMap::new();
+    for v in 0..processed.vertex_count() {
+        at.entry(processed.vertex(v).map(f32::to_bits))
+            .or_insert(v as u32);
+    }
+    let new_index: Vec<u32> = (0..rest.vertex_count())
+        .map(|v| {
+            let p = rest.vertex(v);
+            *at.entry(p.map(f32::to_bits)).or_insert_with(|| {
+                let index = processed.vertex_count() as u32;
+                processed.push_vertex(p, rest.colour(v).unwrap_or([1.0; 3]));
+                processed.copy_vertex_attributes(index as usize, rest, v);
+                index
+            })
+        })
+        .collect();
+    // UVs only survive when both sides have them; a face without a
+    // material is fine beside one with
+    let uvs = !processed.texcoords.is_empty() && !rest.texcoords.is_empty();
+    if !uvs {
+        processed.texcoords.clear();
+    }
+    let materials = !processed.face_materials.is_empty() || !rest.face_materials.is_empty();
+    if materials {
+        processed
+            .face_materials
+            .resize(processed.face_count(), None);
+    }
+    for f in 0..rest.face_count() {
+        processed.indices.extend(rest.face(f).map(|v| new_index[v]));
+        if uvs {
+            processed
+                .texcoords
+                .extend_from_slice(&rest.texcoords[f * 6..f * 6 + 6]);
+        }
+        if materials {
+            processed.face_materials.push(rest.face_material(f));
+        }
+    }
+    processed
+}
@@ -0,0 +1,140 @@
+//! Audit, convert and voxel-remesh 3D scans.
+//!
+//! The `mesh_lifter` command line is a thin layer over this library, so
+//! another program can do what it does without shelling out to it. The
+//! functions at the top level cover the common path; every stage the
+//! command line runs is in the modules below, with the same options.
+//!
+//! ```no_run
+//! let scan = mesh_auditor::load_mesh("scan.obj")?;
+//! let report = mesh_auditor::audit::audit(&scan);
+//! println!("{} findings", report.findings.len());
+//! let skin = mesh_auditor::remesh_voxel(&scan, 100)?;
+//! mesh_auditor::write_stl(&skin, "skin.stl", &Default::default())?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+pub mod allowlist;
+pub mod ascii;
+pub mod audit;
+mod auth;
+pub mod bake;
+pub mod baseline;
+pub mod batch;
+pub mod bench;
+pub mod bvh;
+pub mod cage;
+pub mod canonical;
+pub mod clock;
+pub mod completeness;
+pub mod compose;
+pub mod cylinders;
+pub mod decimate;
+pub mod defects;
+pub mod draft;
+pub mod dump;
+pub mod estimate;
+pub mod extract;
+pub mod fingerprint;
+pub mod gltf;
+pub mod heal;
+pub mod holes;
+mod jobs;
+mod kdtree;
+mod ktx2;
+pub mod labels;
+pub mod limits;
+pub mod materials;
+pub mod mesh;
+pub mod meshlet;
+pub mod messages;
+pub mod metadata;
+mod metrics;
+pub mod msh;
+pub mod multigrid;
+pub mod optimize;
+pub mod orient;
+mod outliers;
+pub mod output;
+pub mod patches;
+mod pipeline;
+pub mod placement;
+pub mod planes;
+pub mod ply;
+pub mod porosity;
+pub mod primitives;
+pub mod profiles;
+pub mod progress;
+pub mod progressive;
+pub mod remesh;
+pub mod report;
+mod rng;
+pub mod samples;
+pub mod sandbox;
+pub mod sanity;
+pub mod sdf;
+pub mod segment;
+pub mod server;
+pub mod shrinkage;
+pub mod skin;
+pub mod slabs;
+pub mod smooth;
+pub mod split;
+pub mod stl;
+pub mod storage;
+pub mod symmetry;
+pub mod tetmesh;
+pub mod thicken;
+pub mod threads;
+pub mod tiles;
+pub mod tileset;
+pub mod trim;
+pub mod unwrap;
+pub mod visibility;
+pub mod volumes;
+pub mod vtk;
+mod webhook;
+
+pub use audit::AuditReport;
+pub use limits::InputLimits;
+pub use mesh::Mesh;
+pub use output::WriteOptions;
+
+use anyhow::{bail, Result};
+use samples::Storage;
+use slabs::Slabs;
+
+/// Load an OBJ file, within the default input limits.
+pub fn load_obj(filename: &str) -> Result<Mesh> {
+    Mesh::load_obj(filename, &InputLimits::default())
+}
+
+/// Load an OBJ, STL or PLY file (by extension), within the default input limits.
+pub fn load_mesh(filename: &str) -> Result<Mesh> {
+    Mesh::load(filename, &InputLimits::default())
+}
+
+/// Shrink-wrap `scan` in a fresh voxel skin on a `resolution` grid a
+/// side, as `mesh_lifter remesh` does with its defaults.
+pub fn remesh_voxel(scan: &Mesh, resolution: usize) -> Result<Mesh> {
+    if resolution < 2 {
+        bail!("resolution must be at least 2");
+    }
+    if scan.vertex_count() == 0 {
+        bail!("the scan has no vertices");
+    }
+    let field = remesh::sample(
+        scan,
+        remesh::ScanField::default().for_scan(scan),
+        resolution,
+        remesh::INFLUENCE,
+        Storage::Full,
+        &Slabs::default(),
+    );
+    Mesh::from_triangles(&extract::marching_cubes(&field, field.iso))
+}
+
+/// Write `mesh` as STL, ASCII or binary as `options` say.
+pub fn write_stl(mesh: &Mesh, filename: &str, options: &WriteOptions) -> Result<()> {
+    stl::save_mesh_as_stl(mesh, filename, options)
+}
@@ -0,0 +1,110 @@
+//! Guards for reading untrusted files.
+//!
+//! Uploads come from the public, so a file may be truncated, enormous,
+//! or deliberately malformed. Every loader takes an `InputLimits` and must
+//! fail with an error, never a panic or an unbounded allocation.
+
+use anyhow::{bail, Context, Result};
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Upper bounds on what a loader is willing to read.
+#[derive(Debug, Clone, Copy)]
+pub struct InputLimits {
+    /// Largest input file, in bytes.
+    pub max_input_size: u64,
+    /// Most triangles a loaded mesh may have (after triangulation).
+    pub max_triangles: usize,
+    /// Tidy up loosely written numbers on OBJ vertex lines (decimal
+    /// commas and the like, see `ascii`) instead of refusing the file.
+    pub lenient: bool,
+}
+
+impl Default for InputLimits {
+    fn default() -> Self {
+        InputLimits {
+            max_input_size: 2 << 30,
+            max_triangles: 50_000_000,
+            lenient: false,
+        }
+    }
+}
+
+impl InputLimits {
+    /// Open a file for reading, refusing it up front if it is already too
+    /// big and cutting the read off if it keeps growing (pipes, devices).
+    pub fn open(&self, filename: &str) -> Result<LimitedReader<File>> {
+        let file = File::open(filename).with_context(|| format!("could not open {}", filename))?;
+        let size = file.metadata()?.len();
+        if size > self.max_input_size {
+            bail!(
+                "{} is {} bytes, over the {} byte input limit",
+                filename,
+                size,
+                self.max_input_size
+            );
+        }
+        Ok(LimitedReader::new(file, self.max_input_size))
+    }
+
+    pub fn check_triangles(&self, count: usize) -> Result<()> {
+        if count > self.max_triangles {
+            bail!(
+                "mesh has {} triangles, over the limit of {}",
+                count,
+                self.max_triangles
+            );
+        }
+        Ok(())
+    }
+}
+
+/// A reader that errors out, rather than silently truncating, once more
+/// than its budget of bytes has been read.
+pub struct LimitedReader<R> {
+    inner: R,
+    remaining: u64,
+}
+
+impl<R> LimitedReader<R> {
+    pub fn new(inner: R, limit: u64) -> Self {
+        LimitedReader {
+            inner,
+            remaining: limit,
+        }
+    }
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Ask for one byte more than allowed so we can tell "exactly at the
+        // limit" apart from "past it"
+        let want = buf.len().min(self.remaining.saturating_add(1) as usize);
+        let n = self.inner.read(&mut buf[..want])?;
+        if n as u64 > self.remaining {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "input exceeds the maximum input size",
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+/// Parse a byte size such as `4096`, `512K`, `200M` or `2G`.
+pub fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let s = s.trim();
+    let (digits, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+        Some('K') => (&s[..s.len() - 1], 1u64 << 10),
+        Some('M') => (&s[..s.len() - 1], 1u64 << 20),
+        Some('G') => (&s[..s.len() - 1], 1u64 << 30),
+        _ => (s, 1),
+    };
+    digits
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| format!("'{}' is not a valid size", s))
+}
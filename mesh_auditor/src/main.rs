@@ -1,163 +1,1740 @@
-use anyhow::Result;
-use std::env;
-use std::fs::File;
-use std::io::Write;
-use marching_cubes::{marching_cubes, Field};
+//! The `mesh_lifter` command line: parses flags and prints progress,
+//! leaving the work to the library.
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Usage: cargo run -- <scan_file.obj>");
-        return Ok(());
-    }
-    let filename = &args[1];
-
-    println!("-----------------------------------------");
-    println!("🧬 VOXEL REMESHER: initializing...");
-    println!("-----------------------------------------");
-
-    // 1. Load the messy scan
-    let load_options = tobj::LoadOptions { triangulate: true, ..Default::default() };
-    let (models, _) = tobj::load_obj(filename, &load_options)?;
-    let mesh = &models[0].mesh;
-
-    println!("   • Input Vertices: {}", mesh.positions.len() / 3);
-
-    // 2. Define the resolution (Higher = more detail, slower)
-    // For a demo, 50 is fast. For production, you'd want 100-200.
-    let resolution = 50; 
-    
-    // 3. Find the Bounding Box of the object
-    let (min_bound, max_bound) = get_bounds(&mesh.positions);
-    println!("   • Bounding Box found. Grid size: {}x{}x{}", resolution, resolution, resolution);
-
-    // 4. Create the "Field" (The Voxel Grid)
-    // We are creating a "Metaball" effect: The points of the scan emit a 'field'.
-    // Where the field is strong, we draw the skin.
-    let field = MeshDistanceField {
-        positions: &mesh.positions,
-        min: min_bound,
-        max: max_bound,
-        resolution,
-    };
+use allowlist::Allowlist;
+use anyhow::{bail, Context, Result};
+use audit::Code;
+use bake::DisplacementFormat;
+use batch::BatchOptions;
+use bench::Tolerances;
+use clap::{CommandFactory, Parser, Subcommand};
+use cli::analyse::run_analysis;
+use cli::convert::{bake, cast_distance, convert, print_cage, BakeSettings, ConvertSteps};
+use cli::edit::{
+    bvh_and_save, meshlets, patches_and_save, progressive_stream, repair_and_save,
+    segment_and_save, split_and_save, tetmesh_and_save, thicken_and_save, tile, trim_and_save,
+    unwrap_and_save, TileOutputs,
+};
+use cli::inspect::{audit, batch, bench, corrupt, fingerprint, match_shape, AuditOptions};
+use cli::voxel::{
+    estimate_remesh, extract_and_save, generate, remesh, run_compose, sweep_and_save,
+};
+use cli::{completions, history, manpage, priority, share, wizard, Run};
+use completions::Shell;
+use compose::{Pedestal, PedestalShape};
+use decimate::DecimateOptions;
+use defects::DefectConfig;
+use dump::Stage;
+use history::History;
+use labels::{FaceLabels, LabelFilter};
+use limits::InputLimits;
+use materials::{TextureFormat, TextureOptions};
+use mesh::Mesh;
+use mesh_auditor::{
+    allowlist, ascii, audit, bake, baseline, batch, bench, cage, compose, cylinders, decimate,
+    defects, dump, labels, limits, materials, mesh, meshlet, messages, metadata, output, patches,
+    planes, primitives, profiles, progressive, remesh, report, samples, sandbox, sdf, segment,
+    server, shrinkage, skin, slabs, split, stl, tetmesh, thicken, threads, tiles, tileset, unwrap,
+};
+use output::WriteOptions;
+use primitives::{Primitive, Shape};
+use profiles::{Profile, ScanCorrections};
+use remesh::{Grid, Scan, ScanField};
+use report::ReportFormat;
+use samples::Storage;
+use sandbox::{JobLimits, TrackingAllocator};
+use sdf::SampledField;
+use server::ServeConfig;
+use share::ShareConfig;
+use skin::SkinOutput;
+use slabs::Slabs;
+use std::path::PathBuf;
+use std::time::Duration;
+use stl::StlFormat;
 
-    println!("   • Running Marching Cubes (This acts as the 'Shrink Wrap')...");
-    
-    // 5. Generate the new mesh
-    // The '0.5' is the density threshold. 
-    let new_mesh = marching_cubes(&field, 0.5);
+mod cli;
 
-    println!("   ✅ RE-SKINNING COMPLETE.");
-    println!("   • New Vertices: {}", new_mesh.len() / 3);
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
 
-    // 6. Save the Result
-    save_triangles_as_stl(&new_mesh, "repaired_voxel_skin.stl")?;
+#[derive(Parser)]
+#[command(
+    name = "mesh_lifter",
+    about = "Audit, convert and voxel-remesh 3D scans",
+    after_help = "Input and output files can be local paths, http(s):// URLs or s3://bucket/key objects."
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// Refuse input files bigger than this (e.g. 4096, 512K, 200M, 2G)
+    #[arg(long, global = true, value_parser = limits::parse_size, default_value = "2G")]
+    max_input_size: u64,
+    /// Refuse meshes with more triangles than this
+    #[arg(long, global = true, default_value_t = 50_000_000)]
+    max_triangles: usize,
+    /// Abort the job if it runs longer than this many seconds
+    #[arg(long, global = true, value_name = "SECONDS")]
+    timeout: Option<f64>,
+    /// Abort the job if it needs more memory than this (e.g. 512M, 4G)
+    #[arg(long, global = true, value_parser = limits::parse_size)]
+    max_memory: Option<u64>,
+    /// Don't report this warning (a code like W007 or a name like inverted-normals); repeatable
+    #[arg(long, global = true, value_name = "CODE")]
+    allow: Vec<Code>,
+    /// Decimal places for coordinates in text exports (ASCII STL, OBJ)
+    #[arg(long, global = true, value_name = "N",
+          value_parser = clap::value_parser!(u32).range(0..=ascii::MAX_PRECISION as i64))]
+    ascii_precision: Option<u32>,
+    /// How STL outputs are written (binary is about a quarter the size)
+    #[arg(long, global = true, value_enum, default_value_t = StlFormat::Ascii)]
+    stl_format: StlFormat,
+    /// Accept OBJ numbers with decimal commas and other loose formatting
+    #[arg(long, global = true)]
+    lenient: bool,
+    /// Sort vertices and triangles before writing, so equal geometry gives identical files
+    #[arg(long, global = true)]
+    canonical_order: bool,
+    /// Write what each stage produced under this folder, for debugging
+    #[arg(long, global = true, value_name = "DIR")]
+    debug_dump: Option<PathBuf>,
+    /// Which stages --debug-dump keeps
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        value_delimiter = ',',
+        default_value = "load,sample,extract"
+    )]
+    debug_stages: Vec<Stage>,
+    /// Use at most this many threads in the parallel stages (0: every core)
+    #[arg(long, global = true, env = "RAYON_NUM_THREADS", value_name = "N")]
+    threads: Option<usize>,
+    /// Run at the lowest CPU and disk priority, so the machine stays usable
+    #[arg(long, global = true)]
+    nice: bool,
+    /// Upload the resulting mesh as glTF and print a link to view it
+    #[arg(long, global = true)]
+    share: bool,
+    /// Where --share uploads go (an s3://bucket/prefix/ or an https:// folder)
+    #[arg(long, global = true, env = "MESH_SHARE_TO", value_name = "LOCATION")]
+    share_to: Option<String>,
+    /// Link to print for a shared result, with {url} and/or {name} in it
+    #[arg(long, global = true, env = "MESH_SHARE_VIEWER", value_name = "URL")]
+    share_viewer: Option<String>,
+    /// Record every audited or batch-processed file's metrics in this SQLite database
+    #[arg(long, global = true, env = "MESH_HISTORY", value_name = "DB")]
+    history: Option<String>,
+    /// Which scanner the input came from, as recorded in --history
+    #[arg(long, global = true, env = "MESH_SCANNER", value_name = "NAME")]
+    scanner: Option<String>,
+    /// Apply this scanner's corrections and defaults from the profiles file
+    #[arg(long, global = true, env = "MESH_PROFILE", value_name = "NAME")]
+    profile: Option<String>,
+    /// The scanner profiles file
+    #[arg(long, global = true, env = "MESH_PROFILES", value_name = "FILE", default_value = profiles::DEFAULT_FILE)]
+    profiles: PathBuf,
+    /// Per-face labels for --only-label, or to name a .msh's physical groups by: a .json map (as segment --labels writes) or a .ply with a face 'label'
+    #[arg(long, global = true, value_name = "FILE")]
+    face_labels: Option<String>,
+    /// Only decimate (convert) or remesh the faces with this label, leaving the rest as they are; repeatable
+    #[arg(long, global = true, value_name = "NAME")]
+    only_label: Vec<String>,
+    /// Write reports in this language (en, de) or from this .toml message catalog
+    #[arg(long, global = true, value_name = "LANG", default_value = "en")]
+    lang: String,
+}
 
-    Ok(())
+#[derive(Subcommand)]
+enum Command {
+    /// Answer a few questions instead of picking flags, then run what they call for
+    Wizard {
+        /// The scan (asked for if not given)
+        input: Option<String>,
+    },
+    /// Check a mesh for problems and print coded findings
+    Audit {
+        /// The mesh to check (.obj, .stl or .ply)
+        input: String,
+        /// Print JSON for other tools instead of the readable report
+        #[arg(long)]
+        json: bool,
+        /// Also write a shareable report with a 3D view of the problems
+        #[arg(long, value_enum, value_name = "FORMAT")]
+        report: Option<ReportFormat>,
+        /// Where the report goes
+        #[arg(long, default_value = "audit_report.html")]
+        report_output: String,
+        /// An earlier `audit --json` of the same fixture to flag regressions against
+        #[arg(long, value_name = "REPORT")]
+        baseline: Option<PathBuf>,
+        /// Allowed relative change in face count before flagging
+        #[arg(long, default_value_t = 0.05)]
+        face_tolerance: f64,
+        /// Allowed relative change in enclosed volume before flagging
+        #[arg(long, default_value_t = 0.02)]
+        volume_tolerance: f64,
+        /// Find the best mirror plane and score how symmetric the part is
+        #[arg(long)]
+        symmetry: bool,
+        /// Deviation from the mirror still counted as symmetric, in model units [default: 1% of the diagonal]
+        #[arg(long, value_name = "DISTANCE")]
+        symmetry_tolerance: Option<f64>,
+        /// Also write the mesh coloured by deviation from its mirror (.obj); implies --symmetry
+        #[arg(long, value_name = "FILE")]
+        symmetry_heatmap: Option<String>,
+        /// Estimate how much of the object the scan covers, from its holes and sparse regions
+        #[arg(long)]
+        completeness: bool,
+    },
+    /// Convert a mesh to another format (.stl, .obj, .ply, .glb, .msh, .vtk or .vtu)
+    Convert {
+        input: String,
+        output: String,
+        /// Close gaps up to this wide first (a CAD sewing tolerance, in model units), reporting the widest left
+        #[arg(long, value_name = "TOLERANCE")]
+        heal: Option<f32>,
+        /// Leave inside-out shells as they are instead of turning them outward
+        #[arg(long)]
+        keep_orientation: bool,
+        /// Guess up from the shape: turn up to +Z and stand on z=0 (the units are guessed and reported, not applied)
+        #[arg(long)]
+        auto_orient: bool,
+        /// With --auto-orient, also scale by the guessed units: a model guessed to be in metres becomes millimetres (x1000)
+        #[arg(long, requires = "auto_orient")]
+        rescale_units: bool,
+        /// Find near-cylindrical holes and rebuild their walls as exact cylinders, reporting the diameters
+        #[arg(long)]
+        refit_holes: bool,
+        /// Largest RMS misfit of a wall still taken for a hole, as a share of its radius
+        #[arg(long, default_value_t = cylinders::DEFAULT_TOLERANCE, value_name = "SHARE")]
+        hole_tolerance: f64,
+        /// Find large near-flat faces and project them onto exact planes, keeping the creases between them sharp
+        #[arg(long)]
+        flatten_planes: bool,
+        /// Largest RMS distance of a face still taken for flat, as a share of its size
+        #[arg(long, default_value_t = planes::DEFAULT_TOLERANCE, value_name = "SHARE")]
+        plane_tolerance: f64,
+        /// Scale up per axis about the centre to make up for print shrinkage (e.g. x:0.4%,y:0.4%,z:0.6%), noted in the output
+        #[arg(long, value_parser = shrinkage::parse, value_name = "SHRINKAGE")]
+        compensate_shrinkage: Option<shrinkage::Shrinkage>,
+        /// Decimate to at most this many faces first, keeping UV seams, materials and colour edges
+        #[arg(long, value_name = "FACES")]
+        target_faces: Option<usize>,
+        /// When decimating, leave open borders exactly as they are (terrain tiles, partial scans)
+        #[arg(long)]
+        preserve_boundary: bool,
+        /// Settings for a delivery target; texture flags given as well win
+        #[arg(long, value_enum)]
+        preset: Option<Preset>,
+        /// Shrink carried textures so no side is longer than this
+        #[arg(long, value_name = "PIXELS")]
+        texture_max_size: Option<u32>,
+        /// Re-encode carried textures (normal/bump maps and transparent ones are never made JPEG)
+        #[arg(long, value_enum)]
+        texture_format: Option<TextureFormat>,
+        /// Quality for lossy re-encoded textures (JPEG, KTX2)
+        #[arg(long, value_name = "1-100", value_parser = clap::value_parser!(u8).range(1..=100))]
+        texture_quality: Option<u8>,
+        /// With .glb output, make each connected shell a node of its own
+        #[arg(long)]
+        split_shells: bool,
+    },
+    /// Decimate a mesh to a face count and/or an error budget, keeping UV seams, materials and colour edges
+    Decimate {
+        input: String,
+        /// Where the result goes (.stl, .obj or .ply)
+        output: String,
+        /// Stop at this many faces (or fewer)
+        #[arg(long, visible_alias = "target-tris", value_name = "FACES")]
+        target_faces: Option<usize>,
+        /// Collapse nothing that moves the surface further than this (RMS, in model units)
+        #[arg(long, value_name = "DISTANCE")]
+        max_error: Option<f64>,
+        /// Leave open borders exactly as they are (terrain tiles, partial scans)
+        #[arg(long)]
+        preserve_boundary: bool,
+    },
+    /// Cut known fixtures (clamps, stands) out of a scan before reconstructing it
+    Trim {
+        /// The scan (.obj, .stl or .ply)
+        input: String,
+        /// A fixture model, in the scan's coordinates; repeatable
+        #[arg(long, value_name = "FIXTURE", required = true)]
+        subtract: Vec<String>,
+        /// Scan geometry this close to a fixture's surface goes, in model units
+        #[arg(long, default_value_t = 0.5)]
+        tolerance: f32,
+        #[arg(short, long, default_value = "trimmed.obj")]
+        output: String,
+    },
+    /// Repair a mesh where it is, without remeshing it
+    Repair {
+        /// The mesh (.obj, .stl or .ply)
+        input: String,
+        /// Patch holes whose rims are within the limits below
+        #[arg(long)]
+        fill_holes: bool,
+        /// Longest rim of a hole to fill, in model units (default: half the bounding box's diagonal)
+        #[arg(long, value_name = "DISTANCE", requires = "fill_holes")]
+        max_perimeter: Option<f32>,
+        /// Biggest hole to fill, by the area it spans in square model units
+        #[arg(long, value_name = "AREA", requires = "fill_holes")]
+        max_area: Option<f32>,
+        #[arg(short, long, default_value = "repaired.obj")]
+        output: String,
+    },
+    /// Give an open surface (a relief, a patch) a thickness, closing it into a printable solid
+    Thicken {
+        /// The open surface (.obj, .stl or .ply)
+        input: String,
+        /// How thick, along the normals (negative: against them), e.g. 2mm or 0.5cm; bare numbers are model units
+        #[arg(long, value_parser = thicken::parse_offset, allow_hyphen_values = true)]
+        offset: f32,
+        #[arg(short, long, default_value = "thickened.stl")]
+        output: String,
+    },
+    /// Cut a model in two along a plane, with alignment pins on one half and sockets for them in the other
+    Split {
+        /// The closed model (.obj, .stl or .ply)
+        input: String,
+        /// The plane's normal: x, y, z or x,y,z. The pins stand out along it
+        #[arg(long, value_parser = parse_direction, allow_hyphen_values = true, default_value = "z")]
+        normal: [f32; 3],
+        /// Where the plane crosses the normal, e.g. 40mm (default: halfway through the model)
+        #[arg(long, value_parser = thicken::parse_offset, allow_hyphen_values = true)]
+        at: Option<f32>,
+        /// Pins on each separate face of the cut (0 for none)
+        #[arg(long, default_value_t = 2)]
+        pins: usize,
+        /// Pin diameter, e.g. 5mm; bare numbers are model units
+        #[arg(long, value_parser = thicken::parse_offset, default_value = "5")]
+        pin_diameter: f32,
+        /// How far a pin stands out of the cut
+        #[arg(long, value_parser = thicken::parse_offset, default_value = "6")]
+        pin_length: f32,
+        /// Gap between a pin and its socket, round the side and at the end
+        #[arg(long, value_parser = thicken::parse_offset, default_value = "0.2")]
+        clearance: f32,
+        /// Where the halves go, as <name>_pins and <name>_sockets beside it (.stl, .obj, .ply, ...)
+        #[arg(short, long, default_value = "split.stl")]
+        output: String,
+    },
+    /// Fill a closed surface with tetrahedra for FEA, written as Gmsh .msh or VTK
+    Tetmesh {
+        /// The closed surface (.obj, .stl or .ply)
+        input: String,
+        /// Grid points along the longest side: each cell inside becomes six tetrahedra
+        #[arg(long, default_value_t = tetmesh::DEFAULT_RESOLUTION)]
+        resolution: usize,
+        /// Where the tetrahedra go (.msh, .vtk or .vtu)
+        #[arg(short, long, default_value = "tets.msh")]
+        output: String,
+    },
+    /// Decimate a high-poly scan and bake its detail into a normal map on a .glb
+    Bake {
+        /// The high-poly scan (.obj, .stl or .ply)
+        input: String,
+        /// Where the low-poly model goes (.glb); the normal map is also written beside it as PNG
+        output: String,
+        /// Bake onto this low-poly mesh instead of decimating the scan (its UVs are used if it has them)
+        #[arg(long)]
+        low: Option<String>,
+        /// Faces to decimate the scan down to
+        #[arg(long, default_value_t = 5000)]
+        target_faces: usize,
+        /// When decimating, leave open borders exactly as they are
+        #[arg(long)]
+        preserve_boundary: bool,
+        /// Normal map size in pixels
+        #[arg(long, default_value_t = 1024)]
+        texture_size: u32,
+        /// How far from the low-poly surface to look for the scan (default: 2% of its diagonal)
+        #[arg(long)]
+        max_distance: Option<f32>,
+        /// Also bake a displacement map beside the output, for subdivision at render time
+        #[arg(long, value_enum, value_name = "FORMAT")]
+        displacement: Option<DisplacementFormat>,
+        /// Cast in from an automatic cage round the low-poly mesh instead of both ways from its surface
+        #[arg(long)]
+        cage: bool,
+        /// Also write the cage (.obj or .stl); implies --cage
+        #[arg(long, value_name = "PATH")]
+        save_cage: Option<String>,
+    },
+    /// Build a baking cage: a low-poly mesh inflated just enough to enclose its scan
+    Cage {
+        /// The low-poly mesh (.obj, .stl or .ply)
+        input: String,
+        /// The high-poly scan it stands in for
+        #[arg(long)]
+        scan: String,
+        /// How far out to look for the scan (default: 2% of its diagonal)
+        #[arg(long)]
+        max_distance: Option<f32>,
+        #[arg(short, long, default_value = "cage.obj")]
+        output: String,
+    },
+    /// Write a mesh as .glb with per-part bounding boxes and spheres in its extras and a .bvh sidecar
+    Bvh {
+        input: String,
+        /// The .glb to write; the tree goes beside it with a .bvh extension
+        output: String,
+    },
+    /// Write a mesh as .glb with a .meshlets sidecar of clusters and culling cones for mesh shaders
+    Meshlets {
+        input: String,
+        /// The .glb to write; the sidecar goes beside it with a .meshlets extension
+        output: String,
+        /// Most vertices per meshlet
+        #[arg(long, default_value_t = meshlet::DEFAULT_MAX_VERTICES)]
+        meshlet_vertices: usize,
+        /// Most triangles per meshlet
+        #[arg(long, default_value_t = meshlet::DEFAULT_MAX_TRIANGLES)]
+        meshlet_triangles: usize,
+    },
+    /// Encode a mesh as a progressive .mlpm stream, a coarse base then refinements, for viewers to sharpen as it downloads; or decode one
+    Progressive {
+        /// A mesh (.obj, .stl or .ply) to encode, or an .mlpm to decode
+        input: String,
+        /// Faces in the base, what shows before any refinement arrives
+        #[arg(long, default_value_t = progressive::DEFAULT_BASE_FACES)]
+        base_faces: usize,
+        /// Decoding: stop after this many refinements (default: all of them)
+        #[arg(long, value_name = "N")]
+        refinements: Option<usize>,
+        /// The .mlpm to write, or the mesh a decoded one goes to
+        #[arg(short, long, default_value = "progressive.mlpm")]
+        output: String,
+    },
+    /// Cut a mesh into UV charts and pack them, writing it with UVs (.obj or .glb) and a layout preview PNG
+    Unwrap {
+        input: String,
+        output: String,
+        /// Start a new chart where the surface turns more than this many degrees from a chart's first face
+        #[arg(long, default_value_t = 45.0)]
+        seam_angle: f64,
+        /// Start a new chart past this many faces
+        #[arg(long, default_value_t = 20_000)]
+        max_chart_faces: usize,
+        /// The texture the charts are packed for, in pixels a side
+        #[arg(long, default_value_t = 1024)]
+        texture_size: u32,
+        /// Empty pixels kept round each chart
+        #[arg(long, default_value_t = 4)]
+        padding: u32,
+    },
+    /// Split a scan into smooth regions, written one file each and/or as a face-label map
+    Segment {
+        input: String,
+        /// Neighbouring faces join a region while their normals are within this many degrees
+        #[arg(long, default_value_t = 12.0)]
+        angle: f64,
+        /// Faces turning more than this many degrees on average to their neighbours don't grow a region
+        #[arg(long, default_value_t = 6.0)]
+        curvature: f64,
+        /// Merge regions with fewer faces into a neighbour
+        #[arg(long, default_value_t = 20)]
+        min_faces: usize,
+        /// Write each region here as an .obj, with a regions.json index
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+        /// Write the region of every face here (.json)
+        #[arg(long)]
+        labels: Option<String>,
+    },
+    /// Fit smooth B-spline patches to a scan's regions and write them as IGES, for CAD
+    Patches {
+        input: String,
+        /// Control points along each side of a patch (4-24): more follow finer detail
+        #[arg(long, default_value_t = patches::DEFAULT_CONTROL_POINTS)]
+        control_points: usize,
+        /// Neighbouring faces join a region while their normals are within this many degrees
+        #[arg(long, default_value_t = 12.0)]
+        angle: f64,
+        /// Where to write the patches (.igs)
+        #[arg(short, long, default_value = "patches.igs")]
+        output: String,
+    },
+    /// Manufacturability checks of a part: mould draft, undercuts, machining reach, internal voids
+    Analyze {
+        #[command(subcommand)]
+        analysis: Analysis,
+    },
+    /// Shape fingerprints for finding duplicate scans, optionally compared pairwise
+    Fingerprint {
+        /// Meshes (.obj, .stl, .ply) and/or directories of them
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Compare every pair and list them, most similar first
+        #[arg(long)]
+        pairs: bool,
+        /// Similarity (0-1) at or above which a pair is called a likely duplicate
+        #[arg(long, default_value_t = 0.97)]
+        threshold: f64,
+        /// Print JSON instead of the readable report
+        #[arg(long)]
+        json: bool,
+    },
+    /// Rank the meshes in a library folder by how closely they match a query shape
+    Match {
+        /// The unknown part (.obj, .stl or .ply)
+        query: String,
+        /// Folder of known meshes to search
+        #[arg(long)]
+        library: PathBuf,
+        /// How many matches to show
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Print JSON instead of the readable report
+        #[arg(long)]
+        json: bool,
+    },
+    /// Shrink-wrap a messy scan into a fresh voxel skin
+    Remesh {
+        /// The scan to remesh (.obj, .stl or .ply)
+        input: String,
+        /// Also keep the sampled field so it can be re-extracted later
+        #[arg(long, value_name = "FIELD.mlsdf")]
+        save_sdf: Option<String>,
+        /// Isovalue to extract the surface at (occupancy: a density between 0 and 1) [default: 0 for distance, 0.5 for occupancy]
+        #[arg(long, visible_alias = "iso-threshold")]
+        iso: Option<f32>,
+        /// The field to skin (a scan without faces always gets occupancy)
+        #[arg(long, value_enum, default_value_t = ScanField::Distance)]
+        field: ScanField,
+        /// Grid points along the longest side; the others get as many as cubic voxels need [default: 50, or the --profile's]
+        #[arg(long)]
+        resolution: Option<usize>,
+        /// Size the grid by its voxels' edge instead (model units): the resolution is whatever covers the scan with them
+        #[arg(long, value_name = "DISTANCE", conflicts_with = "resolution")]
+        voxel_size: Option<f32>,
+        /// How far each point of an occupancy field reaches, in voxels
+        #[arg(long, default_value_t = remesh::INFLUENCE, value_name = "VOXELS")]
+        influence_radius: f32,
+        /// Multiply every coordinate by this first (a scanner's scale drift)
+        #[arg(long)]
+        scale: Option<f32>,
+        /// Drop vertices whose mean neighbour distance is this many standard deviations above average
+        #[arg(long, value_name = "SIGMAS")]
+        outlier_ratio: Option<f32>,
+        /// Neighbours per vertex for --outlier-ratio
+        #[arg(long, value_name = "K")]
+        outlier_neighbours: Option<usize>,
+        /// How to keep the field in memory (bits: 32x smaller, exact for --field occupancy; half: 2x)
+        #[arg(long, value_enum, default_value_t = Storage::Full)]
+        storage: Storage,
+        /// Sample coarse to fine from a grid 2^N times coarser, refining only near the surface
+        #[arg(long, default_value_t = 0, value_name = "N")]
+        coarse_levels: usize,
+        /// Grid layers each thread samples at a time (thinner balances threads better, thicker repeats fewer points)
+        #[arg(long, default_value_t = slabs::DEFAULT_LAYERS, value_name = "N")]
+        slab_layers: usize,
+        /// Fuse a display pedestal of this shape under the model (up is +Z)
+        #[arg(long, value_enum)]
+        pedestal: Option<PedestalShape>,
+        /// How deep the pedestal goes under the model's lowest point, in model units
+        #[arg(long, default_value_t = 5.0, value_name = "DISTANCE")]
+        pedestal_height: f32,
+        /// How far the pedestal reaches past the model's footprint, in model units
+        #[arg(long, default_value_t = 2.0, value_name = "DISTANCE")]
+        pedestal_margin: f32,
+        /// Only predict the triangle count and file size, from quick coarse runs
+        #[arg(long)]
+        estimate: bool,
+        /// Join corners closer than this into one vertex (model units), dropping faces that collapse; not for .stl
+        #[arg(long, value_name = "DISTANCE")]
+        weld: Option<f32>,
+        /// Taubin-smooth the new surface this many times, taking out the voxel stair steps without shrinking it
+        #[arg(long, default_value_t = 0, value_name = "N")]
+        smooth_iterations: usize,
+        /// Where the skin goes: .stl, or .obj, .ply, .glb, .msh, .vtk or .vtu with shared vertices
+        #[arg(default_value = "repaired_voxel_skin.stl")]
+        output: String,
+    },
+    /// Re-extract a surface from a saved .mlsdf field
+    Extract {
+        input: String,
+        /// Isovalue to extract at (defaults to the one stored in the file)
+        #[arg(long, conflicts_with = "sweep")]
+        iso: Option<f32>,
+        /// Extract at evenly spaced isovalues from FROM to TO instead: numbered files (skin_000.stl, ...), or one animated .glb
+        #[arg(long, value_name = "FROM,TO", value_parser = parse_sweep, allow_hyphen_values = true)]
+        sweep: Option<(f32, f32)>,
+        /// How many isovalues the sweep takes, ends included
+        #[arg(long, default_value_t = 10, requires = "sweep")]
+        frames: usize,
+        /// How long each isovalue shows in an animated .glb sweep, in seconds
+        #[arg(long, default_value_t = 0.5, requires = "sweep")]
+        frame_time: f32,
+        /// Join corners closer than this into one vertex (model units), dropping faces that collapse; not for .stl
+        #[arg(long, value_name = "DISTANCE")]
+        weld: Option<f32>,
+        /// Taubin-smooth the new surface this many times, taking out the voxel stair steps without shrinking it
+        #[arg(long, default_value_t = 0, value_name = "N")]
+        smooth_iterations: usize,
+        /// Where the skin goes: .stl, or .obj, .ply, .glb, .msh, .vtk or .vtu with shared vertices
+        #[arg(short, long, default_value = "repaired_voxel_skin.stl")]
+        output: String,
+    },
+    /// Remesh a scan too big for one grid tile by tile, into one stitched mesh, one file per tile and/or a 3D Tiles tileset
+    Tile {
+        /// The scan to remesh (.obj, .stl or .ply)
+        input: String,
+        /// Edge length of a tile, in mesh units
+        #[arg(long)]
+        tile_size: f32,
+        /// Voxels along each edge of a tile
+        #[arg(long, default_value_t = tiles::DEFAULT_CELLS)]
+        cells: usize,
+        /// Write the stitched tiles as one mesh (.stl, .obj or .glb)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Write each tile here as a .glb, with a tiles.json index
+        #[arg(long)]
+        out_dir: Option<PathBuf>,
+        /// Write a 3D Tiles tileset here (tileset.json and .glb levels of detail) for Cesium
+        #[arg(long)]
+        tileset: Option<PathBuf>,
+        /// Levels of detail per tile in the tileset, each a quarter the faces of the last
+        #[arg(long, default_value_t = 3)]
+        lods: usize,
+        /// Place the tileset on the globe: mesh x, y, z become metres east, north and up from here
+        #[arg(long, value_name = "LON,LAT,HEIGHT", value_parser = parse_placement, allow_hyphen_values = true)]
+        origin: Option<tileset::Placement>,
+    },
+    /// Build a test mesh from an analytic shape (sphere, box, torus, ...)
+    Generate {
+        #[arg(long, value_enum)]
+        shape: Shape,
+        /// Overall size in world units (diameter / edge length)
+        #[arg(long, default_value_t = 10.0)]
+        size: f32,
+        /// Torus tube radius (under 0.25), gyroid wall or noise amplitude (under 1), as a fraction of size
+        #[arg(long, default_value_t = 0.1)]
+        detail: f32,
+        /// Gyroid unit cells across the cube
+        #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u32).range(1..))]
+        cells: u32,
+        /// Seed for the noise shape
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Grid points per side
+        #[arg(long, default_value_t = 64)]
+        resolution: usize,
+        /// How to keep the distance field in memory (half: half the size, within 0.05%)
+        #[arg(long, value_enum, default_value_t = Storage::Full)]
+        storage: Storage,
+        /// Sample coarse to fine from a grid 2^N times coarser, refining only near the surface
+        #[arg(long, default_value_t = 0, value_name = "N")]
+        coarse_levels: usize,
+        /// Grid layers each thread samples at a time (thinner balances threads better, thicker repeats fewer points)
+        #[arg(long, default_value_t = slabs::DEFAULT_LAYERS, value_name = "N")]
+        slab_layers: usize,
+        /// Where the mesh goes: .stl, or .obj, .ply, .glb, .msh, .vtk or .vtu with shared vertices
+        #[arg(short, long, default_value = "generated.stl")]
+        output: String,
+        /// Also keep the sampled field
+        #[arg(long, value_name = "FIELD.mlsdf")]
+        save_sdf: Option<String>,
+    },
+    /// Break a clean mesh in controlled ways to make a labeled repair test case
+    Corrupt {
+        /// The clean mesh (.obj, .stl or .ply)
+        input: String,
+        /// Where to write the damaged mesh (.obj)
+        #[arg(short, long)]
+        output: String,
+        /// Where to write the ground-truth labels (defaults to <output>.json)
+        #[arg(long)]
+        labels: Option<String>,
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+        /// Number of holes to punch
+        #[arg(long, default_value_t = 0)]
+        holes: usize,
+        /// Hole radius in world units (defaults to 5% of the bounding box diagonal)
+        #[arg(long)]
+        hole_radius: Option<f32>,
+        /// Number of faces to flip
+        #[arg(long, default_value_t = 0)]
+        flip: usize,
+        /// Number of vertices to split into coincident duplicates
+        #[arg(long, default_value_t = 0)]
+        duplicates: usize,
+        /// Standard deviation of per-vertex Gaussian noise, in world units
+        #[arg(long, default_value_t = 0.0)]
+        noise: f32,
+        /// Number of stray outlier points to scatter around the object
+        #[arg(long, default_value_t = 0)]
+        outliers: usize,
+    },
+    /// Time the pipeline over a directory of meshes and compare with a baseline
+    Bench {
+        /// Directory of meshes to run
+        #[arg(long)]
+        corpus: PathBuf,
+        /// Stored results to compare against (defaults to <corpus>/bench_baseline.json)
+        #[arg(long)]
+        baseline: Option<PathBuf>,
+        /// Overwrite the baseline with this run's results
+        #[arg(long)]
+        update_baseline: bool,
+        /// Grid points per side
+        #[arg(long, default_value_t = remesh::DEFAULT_RESOLUTION)]
+        resolution: usize,
+        /// Allowed slowdown per stage before flagging, as a fraction
+        #[arg(long, default_value_t = 0.25)]
+        time_tolerance: f64,
+        /// Allowed relative change in quality metrics before flagging
+        #[arg(long, default_value_t = 0.01)]
+        metric_tolerance: f64,
+    },
+    /// Remesh many scans in one go
+    Batch {
+        /// Scans (.obj, .stl, .ply) and/or directories of them
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Where the remeshed .stl files go
+        #[arg(long, default_value = "remeshed")]
+        out_dir: PathBuf,
+        /// Grid points along the longest side [default: 50, or the --profile's]
+        #[arg(long)]
+        resolution: Option<usize>,
+        /// Multiply every coordinate by this first (a scanner's scale drift)
+        #[arg(long)]
+        scale: Option<f32>,
+        /// Drop vertices whose mean neighbour distance is this many standard deviations above average
+        #[arg(long, value_name = "SIGMAS")]
+        outlier_ratio: Option<f32>,
+        /// Neighbours per vertex for --outlier-ratio
+        #[arg(long, value_name = "K")]
+        outlier_neighbours: Option<usize>,
+        /// How to keep each field in memory (bits: 32x smaller, just as exact; half: 2x)
+        #[arg(long, value_enum, default_value_t = Storage::Full)]
+        storage: Storage,
+        /// Grid layers each thread samples at a time (thinner balances threads better, thicker repeats fewer points)
+        #[arg(long, default_value_t = slabs::DEFAULT_LAYERS, value_name = "N")]
+        slab_layers: usize,
+        /// Show a live dashboard instead of the scrolling log
+        #[arg(long)]
+        tui: bool,
+        /// Also write a CSV with one row per file
+        #[arg(long, value_name = "FILE.csv")]
+        csv: Option<String>,
+    },
+    /// Run as an HTTP service with a persistent job queue
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        /// Where jobs, uploads and results are kept across restarts
+        #[arg(long, default_value = "mesh_jobs")]
+        data_dir: PathBuf,
+        /// POST a JSON summary here whenever a job finishes (jobs can override it)
+        #[arg(long, value_name = "URL")]
+        callback: Option<String>,
+        /// Base URL clients reach this server at, for result links in callbacks
+        #[arg(long, value_name = "URL")]
+        public_url: Option<String>,
+        /// TOML file of API keys and quotas (else read from $MESH_API_KEYS)
+        #[arg(long, value_name = "FILE", env = "MESH_API_KEYS_FILE")]
+        api_keys: Option<PathBuf>,
+        /// Let clients name remote inputs, outputs and callbacks under this
+        /// prefix (s3://bucket/prefix or https://host/path; repeatable).
+        /// Without it the server only takes uploads
+        #[arg(long, value_name = "PREFIX")]
+        allow_remote: Vec<String>,
+    },
+    /// Combine saved fields into a new .mlsdf (smooth union, subtract, ...)
+    Compose {
+        #[command(subcommand)]
+        op: ComposeOp,
+    },
+    /// Print a tab completion script for a shell
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Print a man page covering every command
+    Manpage,
 }
 
-// --- HELPER STRUCTURES ---
+#[derive(Subcommand)]
+enum Analysis {
+    /// Draft angles against a mould's pull direction, flagging walls that won't release
+    Draft {
+        input: String,
+        /// Pull direction as x,y,z
+        #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true, default_value = "0,0,1")]
+        direction: [f32; 3],
+        /// Faces with less draft than this many degrees either way are under-drafted
+        #[arg(long, default_value_t = 2.0)]
+        min_angle: f64,
+        /// Write the mesh coloured by draft here (.obj): green and blue release, yellow is short
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Print JSON instead of the readable report
+        #[arg(long)]
+        json: bool,
+    },
+    /// Surface seen from neither side of a parting direction, which a two-part mould can't form
+    Undercut {
+        input: String,
+        /// Parting direction as x,y,z
+        #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true, default_value = "0,0,1")]
+        direction: [f32; 3],
+        /// Write the mesh coloured by side here (.obj): green and blue are seen, red is undercut
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Print JSON instead of the readable report
+        #[arg(long)]
+        json: bool,
+    },
+    /// Share of the surface a 3-axis machine reaches from a set of tool directions, and what it can't
+    Reach {
+        input: String,
+        /// A direction the tool comes in from, as +x, -z, ... or x,y,z (repeat for each setup)
+        #[arg(
+            long = "from",
+            value_name = "DIRECTION",
+            value_parser = parse_direction,
+            allow_hyphen_values = true,
+            default_values = ["+z", "-z", "+x", "-x", "+y", "-y"]
+        )]
+        directions: Vec<[f32; 3]>,
+        /// Write the mesh coloured by reach here (.obj): grey is reached, red is blind
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Print JSON instead of the readable report
+        #[arg(long)]
+        json: bool,
+    },
+    /// Voids sealed inside a part (pores, shrinkage, lack of fusion): their sizes, depths and the porosity
+    Porosity {
+        /// A sampled field (.mlsdf, e.g. from a CT volume) or a closed mesh
+        input: String,
+        /// Isovalue the part's surface is at [default: the field's own; 0 for a mesh]
+        #[arg(long)]
+        iso: Option<f32>,
+        /// Grid points along the longest side, for sampling a mesh
+        #[arg(long, default_value_t = remesh::DEFAULT_RESOLUTION)]
+        resolution: usize,
+        /// Write the voids' surfaces here, facing out of them
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Print JSON instead of the readable report
+        #[arg(long)]
+        json: bool,
+    },
+}
 
-// This struct defines our "Voxel Grid"
-struct MeshDistanceField<'a> {
-    positions: &'a [f32],
-    min: (f32, f32, f32),
-    max: (f32, f32, f32),
-    resolution: usize,
+#[derive(Subcommand)]
+enum ComposeOp {
+    /// Merge two fields, optionally blending where they meet
+    Union {
+        a: String,
+        b: String,
+        /// Blend radius in world units (0 = sharp)
+        #[arg(long, default_value_t = 0.0)]
+        smooth: f32,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Carve field B out of field A
+    Subtract {
+        a: String,
+        b: String,
+        /// Fillet radius in world units (0 = sharp)
+        #[arg(long, default_value_t = 0.0)]
+        smooth: f32,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Grow (positive) or shrink (negative) the surface by a distance
+    Offset {
+        input: String,
+        #[arg(long, allow_hyphen_values = true)]
+        distance: f32,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Grow (positive) or shrink (negative) the surface across X and Y only; -half an extrusion width undoes an FDM print's spread
+    OffsetXy {
+        input: String,
+        #[arg(long, allow_hyphen_values = true)]
+        distance: f32,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Stand the field's solid on a pedestal, fused to its underside (up is +Z)
+    Pedestal {
+        input: String,
+        #[arg(long, value_enum, default_value_t = PedestalShape::Box)]
+        shape: PedestalShape,
+        /// How deep it goes under the solid's lowest point, in world units
+        #[arg(long, default_value_t = 5.0)]
+        height: f32,
+        /// How far it reaches past the solid's footprint, in world units
+        #[arg(long, default_value_t = 2.0)]
+        margin: f32,
+        /// Fillet radius where it meets the solid, in world units (0 = sharp)
+        #[arg(long, default_value_t = 0.0)]
+        smooth: f32,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Add bosses with pilot holes for heat-set inserts at points on the surface
+    Bosses {
+        input: String,
+        /// A point on the surface to stand a boss on, as x,y,z; repeatable
+        #[arg(long = "at", value_name = "X,Y,Z", value_parser = parse_vec3, allow_hyphen_values = true, required = true)]
+        points: Vec<[f32; 3]>,
+        /// Which way the bosses stand, as x,y,z [default: out along the surface at each point]
+        #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+        direction: Option<[f32; 3]>,
+        /// Outside diameter of a boss, in world units
+        #[arg(long, default_value_t = 8.0)]
+        diameter: f32,
+        /// How far a boss stands off the surface, in world units
+        #[arg(long, default_value_t = 6.0)]
+        height: f32,
+        /// Pilot hole diameter, for the insert to melt into (see its datasheet)
+        #[arg(long, default_value_t = 4.0)]
+        pilot: f32,
+        /// Pilot hole depth from the top of the boss
+        #[arg(long, default_value_t = 6.0)]
+        depth: f32,
+        /// Fillet radius where a boss meets the surface, in world units (0 = sharp)
+        #[arg(long, default_value_t = 0.0)]
+        smooth: f32,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Keep only what lies inside a box
+    Mask {
+        input: String,
+        /// Box corner as x,y,z
+        #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+        min: [f32; 3],
+        /// Opposite box corner as x,y,z
+        #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+        max: [f32; 3],
+        #[arg(short, long)]
+        output: String,
+    },
 }
 
-// This implements the trait required by the crate.
-// It answers the question: "What is the density at coordinates (x,y,z)?"
-impl<'a> Field for MeshDistanceField<'a> {
-    fn dimensions(&self) -> [usize; 3] {
-        [self.resolution, self.resolution, self.resolution]
+fn main() -> Result<()> {
+    let mut cli = Cli::parse();
+    messages::set_lang(&cli.lang)?;
+    if cli.nice {
+        priority::lower()?;
+        println!("🐢 Background mode: lowest CPU and disk priority");
+    }
+    if let Some(dir) = &cli.debug_dump {
+        println!(
+            "🐞 Dumping {:?} stages to: {}",
+            cli.debug_stages,
+            dir.display()
+        );
     }
+    let write = WriteOptions {
+        stl_format: cli.stl_format,
+        ascii_precision: cli.ascii_precision,
+        canonical_order: cli.canonical_order,
+    };
+    let threads = threads::count(cli.threads);
+    dump::configure(cli.debug_dump, cli.debug_stages, write);
+    let limits = InputLimits {
+        max_input_size: cli.max_input_size,
+        max_triangles: cli.max_triangles,
+        lenient: cli.lenient,
+    };
+    let timeout = match cli.timeout {
+        Some(secs) if !secs.is_finite() || secs <= 0.0 => bail!("timeout must be positive"),
+        Some(secs) => Some(Duration::from_secs_f64(secs)),
+        None => None,
+    };
+    let job_limits = JobLimits {
+        timeout,
+        max_memory: cli.max_memory.map(|bytes| bytes as usize),
+    };
+    // Checked before the work, not after it
+    let share = if cli.share {
+        let config = ShareConfig {
+            to: cli
+                .share_to
+                .clone()
+                .context("--share needs somewhere to upload to: set --share-to or MESH_SHARE_TO")?,
+            viewer: cli.share_viewer.clone(),
+        };
+        config.validate()?;
+        let Some(output) = cli.command.mesh_output() else {
+            bail!("--share works with the commands that write a mesh (convert, remesh, extract, generate, cage, trim, thicken)");
+        };
+        Some((config, output.to_string()))
+    } else {
+        None
+    };
+    let profile = match &cli.profile {
+        Some(name) => {
+            let profile = profiles::load(&cli.profiles, name)?;
+            cli.command.apply_profile(&profile);
+            Some(profile)
+        }
+        None => None,
+    };
+    let history = match &cli.history {
+        Some(path) => {
+            if !matches!(cli.command, Command::Audit { .. } | Command::Batch { .. }) {
+                bail!("--history records the files audit and batch process");
+            }
+            let scanner = cli.scanner.clone().or(profile.map(|p| p.name));
+            Some(History::open(path, scanner)?)
+        }
+        None => None,
+    };
+    let only = match (&cli.face_labels, cli.only_label.is_empty()) {
+        (None, true) => None,
+        (None, false) => {
+            bail!("--only-label needs --face-labels to say which faces carry which label")
+        }
+        // Alone, the labels name the physical groups of a .msh
+        (Some(_), true) => match &cli.command {
+            Command::Convert { output, .. } | Command::Tetmesh { output, .. }
+                if metadata::Format::of(output) == Some(metadata::Format::Msh) =>
+            {
+                None
+            }
+            _ => bail!(
+                "--face-labels needs --only-label to pick the faces to work on, or a .msh output to group them in"
+            ),
+        },
+        (Some(path), false) => {
+            match &cli.command {
+                Command::Convert {
+                    target_faces: None, ..
+                } => bail!(
+                    "--only-label in convert picks the faces to decimate: give --target-faces"
+                ),
+                Command::Convert { .. } | Command::Decimate { .. } | Command::Remesh { .. } => {}
+                _ => bail!("--only-label works with decimate, convert --target-faces and remesh"),
+            }
+            let labels = FaceLabels::load(path, &limits)?;
+            Some(LabelFilter::new(labels, &cli.only_label, path)?)
+        }
+    };
+    let groups = match &cli.face_labels {
+        Some(path) if cli.only_label.is_empty() => Some(FaceLabels::load(path, &limits)?),
+        _ => None,
+    };
+    let run = Run {
+        limits,
+        write,
+        threads,
+    };
 
-    // This is the heavy lifting.
-    // For every voxel, we calculate its value based on proximity to the scan points.
-    fn z(&self, x: usize, y: usize, z: usize) -> f64 {
-        // Convert grid coordinates (0, 1, 2) to World Coordinates (0.5mm, 1.0mm...)
-        let step_x = (self.max.0 - self.min.0) / self.resolution as f32;
-        let step_y = (self.max.1 - self.min.1) / self.resolution as f32;
-        let step_z = (self.max.2 - self.min.2) / self.resolution as f32;
-
-        let world_x = self.min.0 + (x as f32 * step_x);
-        let world_y = self.min.1 + (y as f32 * step_y);
-        let world_z = self.min.2 + (z as f32 * step_z);
-
-        // SIMPLE ALGORITHM (Metaball Style):
-        // Find the distance to the CLOSEST vertex in the original scan.
-        // In a real production app, you would use a 'KdTree' to make this instant.
-        // Here, we loop through points (Slow but simple for code clarity).
-        
-        let mut min_dist_sq = f32::MAX;
-        
-        // OPTIMIZATION: Just check every 10th point to speed up the demo
-        for i in (0..self.positions.len()).step_by(30) {
-            let px = self.positions[i];
-            let py = self.positions[i+1];
-            let pz = self.positions[i+2];
-
-            let dist_sq = (px - world_x).powi(2) + (py - world_y).powi(2) + (pz - world_z).powi(2);
-            if dist_sq < min_dist_sq {
-                min_dist_sq = dist_sq;
+    match cli.command {
+        // Batches and the server apply the limits to each job they run, not
+        // to themselves
+        Command::Batch {
+            inputs,
+            out_dir,
+            resolution,
+            scale,
+            outlier_ratio,
+            outlier_neighbours,
+            storage,
+            slab_layers,
+            tui,
+            csv,
+        } => {
+            if slab_layers == 0 {
+                bail!("a slab needs at least one layer");
             }
+            let corrections = ScanCorrections::new(scale, outlier_ratio, outlier_neighbours);
+            corrections.validate()?;
+            let options = BatchOptions {
+                out_dir,
+                resolution: resolution.unwrap_or(remesh::DEFAULT_RESOLUTION),
+                corrections,
+                storage,
+                slabs: Slabs {
+                    layers: slab_layers,
+                    threads,
+                },
+                allow: cli.allow,
+                write,
+            };
+            batch(
+                &inputs,
+                &options,
+                tui,
+                csv.as_deref(),
+                history.as_ref(),
+                &limits,
+                &job_limits,
+            )
         }
+        Command::Serve {
+            addr,
+            data_dir,
+            callback,
+            public_url,
+            api_keys,
+            allow_remote,
+        } => {
+            let config = ServeConfig {
+                addr,
+                data_dir,
+                callback,
+                public_url,
+                api_keys,
+                allow_remote: Allowlist::new(allow_remote)?,
+                write,
+                slabs: Slabs {
+                    threads,
+                    ..Slabs::default()
+                },
+            };
+            server::serve(config, limits, job_limits)
+        }
+        // Nobody should time out while thinking about an answer
+        Command::Wizard { input } => wizard::run(input, &run, &job_limits),
+        command => {
+            sandbox::run_job(&job_limits, || {
+                run_command(
+                    command,
+                    &cli.allow,
+                    history.as_ref(),
+                    only.as_ref(),
+                    groups.as_ref(),
+                    &run,
+                )
+            })?;
+            if let Some((config, output)) = &share {
+                share_output(output, config, &limits)?;
+            }
+            Ok(())
+        }
+    }
+}
 
-        // Return a density value. 
-        // If we are close to a point, return 1.0. If far, return 0.0.
-        // We use an inverse distance function.
-        let threshold = (step_x * 3.0).powi(2); // Radius of influence
-        if min_dist_sq < threshold {
-            return 1.0;
+fn run_command(
+    command: Command,
+    allow: &[Code],
+    history: Option<&History>,
+    only: Option<&LabelFilter>,
+    groups: Option<&FaceLabels>,
+    run: &Run,
+) -> Result<()> {
+    match command {
+        Command::Fingerprint {
+            inputs,
+            pairs,
+            threshold,
+            json,
+        } => fingerprint(&inputs, pairs, threshold, json, run),
+        Command::Match {
+            query,
+            library,
+            top,
+            json,
+        } => match_shape(&query, &library, top, json, run),
+        Command::Convert {
+            input,
+            output,
+            heal,
+            keep_orientation,
+            auto_orient,
+            rescale_units,
+            refit_holes,
+            hole_tolerance,
+            flatten_planes,
+            plane_tolerance,
+            compensate_shrinkage,
+            target_faces,
+            preserve_boundary,
+            preset,
+            texture_max_size,
+            texture_format,
+            texture_quality,
+            split_shells,
+        } => {
+            let mut textures = preset.map(Preset::textures).unwrap_or_default();
+            textures.max_size = texture_max_size.or(textures.max_size);
+            textures.format = texture_format.or(textures.format);
+            textures.quality = texture_quality.unwrap_or(textures.quality);
+            let steps = ConvertSteps {
+                heal,
+                keep_orientation,
+                auto_orient,
+                rescale_units,
+                refit_holes: refit_holes.then_some(hole_tolerance),
+                flatten_planes: flatten_planes.then_some(plane_tolerance),
+                shrinkage: compensate_shrinkage,
+                split_shells,
+                groups,
+            };
+            convert(
+                &input,
+                &output,
+                &steps,
+                target_faces
+                    .map(|target_faces| DecimateOptions {
+                        target_faces,
+                        preserve_boundary,
+                        max_error: None,
+                    })
+                    .as_ref()
+                    .map(|options| (options, only)),
+                &textures,
+                run,
+            )
+        }
+        Command::Decimate {
+            input,
+            output,
+            target_faces,
+            max_error,
+            preserve_boundary,
+        } => {
+            if target_faces.is_none() && max_error.is_none() {
+                bail!("say how far to decimate: --target-faces, --max-error or both");
+            }
+            if max_error.is_some_and(|e| !e.is_finite() || e < 0.0) {
+                bail!("the error budget must be a distance of 0 or more");
+            }
+            let options = DecimateOptions {
+                target_faces: target_faces.unwrap_or(0),
+                preserve_boundary,
+                max_error,
+            };
+            let steps = ConvertSteps {
+                keep_orientation: true,
+                ..Default::default()
+            };
+            convert(
+                &input,
+                &output,
+                &steps,
+                Some((&options, only)),
+                &TextureOptions::default(),
+                run,
+            )
+        }
+        Command::Trim {
+            input,
+            subtract,
+            tolerance,
+            output,
+        } => trim_and_save(&input, &subtract, tolerance, &output, run),
+        Command::Repair {
+            input,
+            fill_holes,
+            max_perimeter,
+            max_area,
+            output,
+        } => {
+            if !fill_holes {
+                bail!("nothing to repair: pass --fill-holes");
+            }
+            repair_and_save(&input, max_perimeter, max_area, &output, run)
+        }
+        Command::Thicken {
+            input,
+            offset,
+            output,
+        } => thicken_and_save(&input, offset, &output, run),
+        Command::Split {
+            input,
+            normal,
+            at,
+            pins,
+            pin_diameter,
+            pin_length,
+            clearance,
+            output,
+        } => {
+            let pins = split::Pins {
+                per_face: pins,
+                diameter: pin_diameter,
+                length: pin_length,
+                clearance,
+            };
+            split_and_save(&input, normal, at, &pins, &output, run)
+        }
+        Command::Tetmesh {
+            input,
+            resolution,
+            output,
+        } => tetmesh_and_save(&input, resolution, &output, groups, run),
+        Command::Bake {
+            input,
+            output,
+            low,
+            target_faces,
+            preserve_boundary,
+            texture_size,
+            max_distance,
+            displacement,
+            cage,
+            save_cage,
+        } => {
+            let settings = BakeSettings {
+                texture_size,
+                max_distance,
+                displacement,
+                cage: cage || save_cage.is_some(),
+                save_cage,
+            };
+            let decimation = DecimateOptions {
+                target_faces,
+                preserve_boundary,
+                max_error: None,
+            };
+            bake(&input, &output, low.as_deref(), &decimation, &settings, run)
+        }
+        Command::Cage {
+            input,
+            scan,
+            max_distance,
+            output,
+        } => {
+            let low = Mesh::load(&input, &run.limits)?;
+            let high = Mesh::load(&scan, &run.limits)?;
+            let max_distance = cast_distance(&high, max_distance)?;
+            let cage = cage::build(&low, &high, max_distance);
+            print_cage(&cage);
+            output::save(&cage.mesh, &output, &run.write)?;
+            println!("💾 Saved cage to: {}", output);
+            Ok(())
+        }
+        Command::Bvh { input, output } => bvh_and_save(&input, &output, run),
+        Command::Meshlets {
+            input,
+            output,
+            meshlet_vertices,
+            meshlet_triangles,
+        } => meshlets(&input, &output, meshlet_vertices, meshlet_triangles, run),
+        Command::Progressive {
+            input,
+            base_faces,
+            refinements,
+            output,
+        } => progressive_stream(&input, base_faces, refinements, &output, run),
+        Command::Unwrap {
+            input,
+            output,
+            seam_angle,
+            max_chart_faces,
+            texture_size,
+            padding,
+        } => {
+            let options = unwrap::UnwrapOptions {
+                seam_angle,
+                max_chart_faces,
+                texture_size,
+                padding,
+            };
+            unwrap_and_save(&input, &output, &options, run)
+        }
+        Command::Segment {
+            input,
+            angle,
+            curvature,
+            min_faces,
+            out_dir,
+            labels,
+        } => {
+            let options = segment::SegmentOptions {
+                angle,
+                curvature,
+                min_faces,
+            };
+            segment_and_save(&input, &options, out_dir.as_deref(), labels.as_deref(), run)
+        }
+        Command::Patches {
+            input,
+            control_points,
+            angle,
+            output,
+        } => patches_and_save(&input, control_points, angle, &output, run),
+        Command::Audit {
+            input,
+            json,
+            report,
+            report_output,
+            baseline,
+            face_tolerance,
+            volume_tolerance,
+            symmetry,
+            symmetry_tolerance,
+            symmetry_heatmap,
+            completeness,
+        } => {
+            let tolerances = baseline::Tolerances {
+                faces: face_tolerance,
+                volume: volume_tolerance,
+            };
+            let options = AuditOptions {
+                json,
+                report: report.map(|format| (format, report_output.as_str())),
+                baseline: baseline.as_deref().map(|path| (path, tolerances)),
+                symmetry: (symmetry || symmetry_heatmap.is_some())
+                    .then_some((symmetry_tolerance, symmetry_heatmap.as_deref())),
+                completeness,
+            };
+            audit(&input, &options, allow, history, run)
+        }
+        Command::Remesh {
+            input,
+            save_sdf,
+            iso,
+            field,
+            resolution,
+            voxel_size,
+            influence_radius,
+            scale,
+            outlier_ratio,
+            outlier_neighbours,
+            storage,
+            coarse_levels,
+            slab_layers,
+            pedestal,
+            pedestal_height,
+            pedestal_margin,
+            estimate,
+            weld,
+            smooth_iterations,
+            output,
+        } => {
+            let scan = Scan {
+                corrections: ScanCorrections::new(scale, outlier_ratio, outlier_neighbours),
+                only,
+                field,
+                influence: influence_radius,
+                pedestal: pedestal.map(|shape| Pedestal {
+                    shape,
+                    height: pedestal_height,
+                    margin: pedestal_margin,
+                }),
+            };
+            let grid = Grid {
+                resolution: resolution.unwrap_or(remesh::DEFAULT_RESOLUTION),
+                voxel_size,
+                storage,
+                coarse_levels,
+                slabs: Slabs {
+                    layers: slab_layers,
+                    threads: run.threads,
+                },
+            };
+            if estimate {
+                return estimate_remesh(&input, &scan, iso, &grid, run);
+            }
+            let output = SkinOutput {
+                path: &output,
+                weld,
+                smooth_iterations,
+                write: run.write,
+            };
+            remesh(&input, &scan, iso, &grid, &output, save_sdf.as_deref(), run)
+        }
+        Command::Extract {
+            input,
+            iso,
+            sweep,
+            frames,
+            frame_time,
+            weld,
+            smooth_iterations,
+            output,
+        } => {
+            let output = SkinOutput {
+                path: &output,
+                weld,
+                smooth_iterations,
+                write: run.write,
+            };
+            output.validate()?;
+            println!("-----------------------------------------");
+            println!("🧬 VOXEL REMESHER: re-extracting saved field...");
+            println!("-----------------------------------------");
+            let field = SampledField::load(&input, &run.limits)?;
+            println!(
+                "   • Grid size: {}x{}x{}",
+                field.dims[0], field.dims[1], field.dims[2]
+            );
+            match sweep {
+                Some(range) => sweep_and_save(&field, range, frames, frame_time, &output),
+                None => extract_and_save(&field, iso.unwrap_or(field.iso), &[], &output),
+            }
+        }
+        Command::Tile {
+            input,
+            tile_size,
+            cells,
+            output,
+            out_dir,
+            tileset,
+            lods,
+            origin,
+        } => {
+            let options = tiles::TileOptions { tile_size, cells };
+            let outputs = TileOutputs {
+                merged: output.as_deref(),
+                out_dir: out_dir.as_deref(),
+                tileset: tileset.as_deref().map(|dir| (dir, lods, origin)),
+            };
+            tile(&input, &options, &outputs, run)
+        }
+        Command::Analyze { analysis } => run_analysis(analysis, run),
+        Command::Compose { op } => run_compose(op, run),
+        Command::Completions { shell } => {
+            completions::write(&mut Cli::command(), shell, &mut std::io::stdout())
+        }
+        Command::Manpage => manpage::write(&mut Cli::command(), &mut std::io::stdout()),
+        Command::Batch { .. } | Command::Serve { .. } | Command::Wizard { .. } => {
+            unreachable!("batch, serve and wizard are dispatched before the job sandbox")
+        }
+        Command::Corrupt {
+            input,
+            output,
+            labels,
+            seed,
+            holes,
+            hole_radius,
+            flip,
+            duplicates,
+            noise,
+            outliers,
+        } => {
+            let clean = Mesh::load(&input, &run.limits)?;
+            let hole_radius = hole_radius.unwrap_or_else(|| {
+                let (min, max) = clean.bounds();
+                let diagonal = (0..3)
+                    .map(|a| (max[a] - min[a]).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                diagonal * 0.05
+            });
+            let config = DefectConfig {
+                seed,
+                holes,
+                hole_radius,
+                flipped_faces: flip,
+                duplicate_vertices: duplicates,
+                noise,
+                outliers,
+            };
+            let labels_path = labels.unwrap_or_else(|| format!("{}.json", output));
+            corrupt(&input, &clean, &config, &output, &labels_path, &run.write)
+        }
+        Command::Bench {
+            corpus,
+            baseline,
+            update_baseline,
+            resolution,
+            time_tolerance,
+            metric_tolerance,
+        } => {
+            let baseline = baseline.unwrap_or_else(|| corpus.join("bench_baseline.json"));
+            let tolerances = Tolerances {
+                time: time_tolerance,
+                metric: metric_tolerance,
+            };
+            bench(
+                &corpus,
+                &baseline,
+                update_baseline,
+                resolution,
+                tolerances,
+                run,
+            )
+        }
+        Command::Generate {
+            shape,
+            size,
+            detail,
+            cells,
+            seed,
+            resolution,
+            storage,
+            coarse_levels,
+            slab_layers,
+            output,
+            save_sdf,
+        } => {
+            let primitive = Primitive {
+                shape,
+                size,
+                detail,
+                cells,
+                seed,
+            };
+            generate(
+                &primitive,
+                &Grid {
+                    resolution,
+                    voxel_size: None,
+                    storage,
+                    coarse_levels,
+                    slabs: Slabs {
+                        layers: slab_layers,
+                        threads: run.threads,
+                    },
+                },
+                &output,
+                save_sdf.as_deref(),
+                &run.write,
+            )
         }
-        0.0
     }
 }
 
-// Helper to find the size of the object
-fn get_bounds(positions: &[f32]) -> ((f32, f32, f32), (f32, f32, f32)) {
-    let mut min = (f32::MAX, f32::MAX, f32::MAX);
-    let mut max = (f32::MIN, f32::MIN, f32::MIN);
-
-    for chunk in positions.chunks(3) {
-        if let [x, y, z] = chunk {
-            if *x < min.0 { min.0 = *x; }
-            if *y < min.1 { min.1 = *y; }
-            if *z < min.2 { min.2 = *z; }
-            
-            if *x > max.0 { max.0 = *x; }
-            if *y > max.1 { max.1 = *y; }
-            if *z > max.2 { max.2 = *z; }
+/// `convert --preset`: settings for where the result is going.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum Preset {
+    /// Browsers and phones: textures at most 2048 px, as JPEG at quality 85
+    Web,
+}
+
+impl Command {
+    // The mesh a command writes, for --share
+    fn mesh_output(&self) -> Option<&str> {
+        match self {
+            Command::Convert { output, .. }
+            | Command::Extract {
+                output,
+                sweep: None,
+                ..
+            }
+            | Command::Generate { output, .. }
+            | Command::Cage { output, .. }
+            | Command::Trim { output, .. }
+            | Command::Repair { output, .. }
+            | Command::Thicken { output, .. } => Some(output),
+            Command::Remesh {
+                output,
+                estimate: false,
+                ..
+            } => Some(output),
+            _ => None,
+        }
+    }
+
+    // Fill in what the flags left to the scanner's profile
+    fn apply_profile(&mut self, profile: &Profile) {
+        match self {
+            Command::Remesh {
+                resolution,
+                scale,
+                outlier_ratio,
+                outlier_neighbours,
+                ..
+            }
+            | Command::Batch {
+                resolution,
+                scale,
+                outlier_ratio,
+                outlier_neighbours,
+                ..
+            } => {
+                *resolution = resolution.or(profile.resolution);
+                *scale = scale.or(profile.scale);
+                *outlier_ratio = outlier_ratio.or(profile.outlier_ratio);
+                *outlier_neighbours = outlier_neighbours.or(profile.outlier_neighbours);
+            }
+            _ => {}
         }
     }
-    // Add some padding so the object isn't touching the edge of the grid
-    let padding = 0.2;
-    (
-        (min.0 - padding, min.1 - padding, min.2 - padding),
-        (max.0 + padding, max.1 + padding, max.2 + padding)
-    )
 }
 
-// Basic STL Writer for the output
-fn save_triangles_as_stl(triangles: &[usize], filename: &str) -> Result<()> {
-    // The 'marching_cubes' crate returns a flat list of coordinates
-    // [x1, y1, z1, x2, y2, z2, ...]
-    
-    let mut file = File::create(filename)?;
-    writeln!(file, "solid voxel_skin")?;
-
-    for chunk in triangles.chunks(9) {
-        // chunk contains 3 vertices (9 floats)
-        writeln!(file, "facet normal 0 0 0")?;
-        writeln!(file, "outer loop")?;
-        writeln!(file, "vertex {} {} {}", chunk[0], chunk[1], chunk[2])?; // V1
-        writeln!(file, "vertex {} {} {}", chunk[3], chunk[4], chunk[5])?; // V2
-        writeln!(file, "vertex {} {} {}", chunk[6], chunk[7], chunk[8])?; // V3
-        writeln!(file, "endloop")?;
-        writeln!(file, "endfacet")?;
+impl Preset {
+    fn textures(self) -> TextureOptions {
+        match self {
+            Preset::Web => TextureOptions {
+                max_size: Some(2048),
+                format: Some(TextureFormat::Jpeg),
+                quality: 85,
+                ..TextureOptions::default()
+            },
+        }
     }
-    
-    writeln!(file, "endsolid voxel_skin")?;
+}
+
+// Upload what the command wrote, for --share
+fn share_output(output: &str, config: &ShareConfig, limits: &InputLimits) -> Result<()> {
+    println!("🔗 Sharing {}...", output);
+    let shared = share::share(output, config, limits)?;
+    println!("   💾 Uploaded to: {}", shared.location);
+    println!("   🔗 View it at: {}", shared.link);
     Ok(())
 }
+
+// Parse a "lon,lat,height" command line value, in degrees and metres
+fn parse_placement(s: &str) -> std::result::Result<tileset::Placement, String> {
+    let parts: Vec<&str> = s.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!("expected lon,lat,height but got '{}'", s));
+    }
+    let mut v = [0.0f64; 3];
+    for (slot, part) in v.iter_mut().zip(parts) {
+        *slot = part
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a number", part))?;
+    }
+    if !(-180.0..=180.0).contains(&v[0]) || !(-90.0..=90.0).contains(&v[1]) {
+        return Err(format!("'{}' is not a longitude and latitude", s));
+    }
+    Ok(tileset::Placement {
+        longitude: v[0],
+        latitude: v[1],
+        height: v[2],
+    })
+}
+
+// Parse an "x,y,z" command line value
+// A tool direction: an axis such as "+x" or "-z", or x,y,z
+fn parse_direction(s: &str) -> std::result::Result<[f32; 3], String> {
+    Ok(match s.trim() {
+        "+x" | "x" => [1.0, 0.0, 0.0],
+        "-x" => [-1.0, 0.0, 0.0],
+        "+y" | "y" => [0.0, 1.0, 0.0],
+        "-y" => [0.0, -1.0, 0.0],
+        "+z" | "z" => [0.0, 0.0, 1.0],
+        "-z" => [0.0, 0.0, -1.0],
+        _ => return parse_vec3(s),
+    })
+}
+
+fn parse_sweep(s: &str) -> std::result::Result<(f32, f32), String> {
+    let Some((from, to)) = s.split_once(',') else {
+        return Err(format!("expected from,to but got '{}'", s));
+    };
+    let number = |part: &str| {
+        part.trim()
+            .parse::<f32>()
+            .ok()
+            .filter(|v| v.is_finite())
+            .ok_or_else(|| format!("'{}' is not a number", part))
+    };
+    Ok((number(from)?, number(to)?))
+}
+
+fn parse_vec3(s: &str) -> std::result::Result<[f32; 3], String> {
+    let parts: Vec<&str> = s.split(',').collect();
+    if parts.len() != 3 {
+        return Err(format!("expected x,y,z but got '{}'", s));
+    }
+    let mut v = [0.0; 3];
+    for (slot, part) in v.iter_mut().zip(parts) {
+        *slot = part
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a number", part))?;
+    }
+    Ok(v)
+}
@@ -0,0 +1,372 @@
+//! Materials and textures riding along with an OBJ.
+//!
+//! An OBJ names its material libraries (`mtllib part.mtl`) and each MTL
+//! names its texture images (`map_Kd wood.png`), all as paths relative to
+//! the file that mentions them. Converting just the geometry would leave a
+//! model that opens grey, so `convert` to `.obj` carries them over: every
+//! library goes into one `<stem>.mtl` next to the output, and every texture
+//! is copied into `<stem>_textures/` with the MTL rewritten to point there.
+//!
+//! For delivery (`--preset web`) textures can also be shrunk to a maximum
+//! size and re-encoded (`--texture-max-size`, `--texture-format`,
+//! `--texture-quality`). Normal, bump and displacement maps and anything
+//! with transparency are never made JPEG: the artefacts show up as lumpy
+//! lighting and the alpha would be lost. They go PNG instead. KTX2 (see
+//! `ktx2`) keeps both, and handles normal maps as linear data.
+//!
+//! These paths come from the file, so they're only followed if they stay
+//! inside the input's folder: a hostile upload could otherwise have us
+//! read (and then copy out) any file on the machine. Remote inputs get no
+//! materials at all.
+
+use crate::ktx2;
+use crate::limits::InputLimits;
+use crate::mesh::Mesh;
+use crate::sandbox;
+use crate::threads;
+use anyhow::{Context, Result};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType as PngFilter, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::ImageFormat;
+use std::collections::{HashMap, HashSet};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// What `carry` does with textures. The default copies them as they are.
+#[derive(Debug, Clone, Copy)]
+pub struct TextureOptions {
+    /// Shrink so neither side is longer than this many pixels.
+    pub max_size: Option<u32>,
+    /// Re-encode in this format.
+    pub format: Option<TextureFormat>,
+    /// Quality for the lossy formats (JPEG, KTX2), 1-100.
+    pub quality: u8,
+    /// Threads to encode KTX2 on.
+    pub threads: usize,
+}
+
+impl Default for TextureOptions {
+    fn default() -> Self {
+        TextureOptions {
+            max_size: None,
+            format: None,
+            quality: 85,
+            threads: threads::count(None),
+        }
+    }
+}
+
+impl TextureOptions {
+    pub fn copies_only(&self) -> bool {
+        self.max_size.is_none() && self.format.is_none()
+    }
+}
+
+/// Formats carried textures can be re-encoded in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextureFormat {
+    /// Lossy, smallest for photos; `--texture-quality` applies
+    Jpeg,
+    /// Lossless
+    Png,
+    /// Lossless WebP (smaller than PNG, but not lossy: quality is ignored)
+    Webp,
+    /// KTX2 with Basis Universal (UASTC) and mipmaps, for GPUs; quality trades size for detail
+    Ktx2,
+}
+
+/// What `carry` wrote.
+#[derive(Debug, Default)]
+pub struct Carried {
+    /// The combined library, as the OBJ's `mtllib` line names it.
+    pub library: String,
+    pub textures: Vec<CarriedTexture>,
+    /// Texture references that couldn't be followed, left as they were.
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct CarriedTexture {
+    pub copy: PathBuf,
+    /// File sizes in bytes, before and after.
+    pub before: u64,
+    pub after: u64,
+    /// Pixel sizes, before and after, if it was decoded.
+    pub pixels: Option<([u32; 2], [u32; 2])>,
+    /// Why it was copied as is when it should have been re-encoded.
+    pub kept: Option<String>,
+}
+
+/// `reference` (as written in a file in `base`), if it resolves to a file
+/// inside `base`.
+pub fn resolve(base: &Path, reference: &Path) -> Option<PathBuf> {
+    let base = base.canonicalize().ok()?;
+    let path = base.join(reference).canonicalize().ok()?;
+    (path.starts_with(&base) && path.is_file()).then_some(path)
+}
+
+/// Read one material library for the OBJ loader, if it's one we may read.
+pub fn load_library(
+    base: &Path,
+    reference: &Path,
+    limits: &InputLimits,
+) -> Option<(PathBuf, tobj::MTLLoadResult)> {
+    let path = resolve(base, reference)?;
+    let mut reader = BufReader::new(limits.open(&path.to_string_lossy()).ok()?);
+    let loaded = tobj::load_mtl_buf(&mut reader);
+    Some((path, loaded))
+}
+
+/// Write `mesh`'s material libraries and textures next to the OBJ at
+/// `output`, shrinking and re-encoding the textures if asked. Nothing to
+/// do (`None`) if the mesh has none.
+pub fn carry(mesh: &Mesh, output: &str, options: &TextureOptions) -> Result<Option<Carried>> {
+    if mesh.material_libraries.is_empty() {
+        return Ok(None);
+    }
+    let output = Path::new(output);
+    let stem = output
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "mesh".to_string());
+    let folder = output.parent().unwrap_or(Path::new(""));
+    let texture_folder = format!("{}_textures", stem);
+    let mut carried = Carried {
+        library: format!("{}.mtl", stem),
+        ..Default::default()
+    };
+
+    // 1. Read the libraries and find every texture, noting the ones used
+    //    as data (normal, bump, displacement maps), which mustn't go lossy
+    let mut libraries = Vec::new();
+    let mut textures: Vec<(PathBuf, bool)> = Vec::new();
+    for library in &mesh.material_libraries {
+        let base = library.parent().unwrap_or(Path::new("")).to_path_buf();
+        let text = std::fs::read_to_string(library)
+            .with_context(|| format!("could not read {}", library.display()))?;
+        for line in text.lines() {
+            let Some((keyword, _, reference)) = texture_reference(line) else {
+                continue;
+            };
+            let Some(texture) = resolve(&base, Path::new(reference)) else {
+                carried.missing.push(reference.to_string());
+                continue;
+            };
+            let data = is_data_map(&keyword);
+            match textures.iter_mut().find(|(t, _)| *t == texture) {
+                Some((_, was_data)) => *was_data |= data,
+                None => textures.push((texture, data)),
+            }
+        }
+        libraries.push((base, text));
+    }
+
+    // 2. Copy or re-encode each one, under a name of its own (two
+    //    `albedo.png`s from different folders mustn't overwrite each other)
+    let target = folder.join(&texture_folder);
+    if !textures.is_empty() {
+        std::fs::create_dir_all(&target)
+            .with_context(|| format!("could not create {}", target.display()))?;
+    }
+    let mut names: HashMap<PathBuf, String> = HashMap::new();
+    let mut taken = HashSet::new();
+    for (source, data) in textures {
+        sandbox::checkpoint();
+        let texture = write_texture(&source, data, &target, &mut taken, options)?;
+        let name = texture
+            .copy
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        names.insert(source, name);
+        carried.textures.push(texture);
+    }
+
+    // 3. The combined library, pointing at the copies
+    let mut text = String::new();
+    for (base, source) in &libraries {
+        for line in source.lines() {
+            let name = texture_reference(line).and_then(|(_, options, reference)| {
+                let texture = resolve(base, Path::new(reference))?;
+                Some((options, names.get(&texture)?))
+            });
+            match name {
+                // Options (`-s 1 1 1`) come before the file name, so keep them
+                Some((options, name)) => {
+                    text.push_str(options);
+                    text.push_str(&texture_folder);
+                    text.push('/');
+                    text.push_str(name);
+                }
+                None => text.push_str(line),
+            }
+            text.push('\n');
+        }
+    }
+    let path = folder.join(&carried.library);
+    let mut file = std::fs::File::create(&path)
+        .with_context(|| format!("could not create {}", path.display()))?;
+    file.write_all(text.as_bytes())?;
+    Ok(Some(carried))
+}
+
+/// A byte count for people: `3.2 MB`.
+pub fn size(bytes: u64) -> String {
+    match bytes {
+        0..1_000 => format!("{} B", bytes),
+        1_000..1_000_000 => format!("{:.1} KB", bytes as f64 / 1e3),
+        _ => format!("{:.1} MB", bytes as f64 / 1e6),
+    }
+}
+
+// Copy one texture into `target`, or decode, shrink and re-encode it
+fn write_texture(
+    source: &Path,
+    data: bool,
+    target: &Path,
+    taken: &mut HashSet<String>,
+    options: &TextureOptions,
+) -> Result<CarriedTexture> {
+    let before = std::fs::metadata(source)?.len();
+    let stem = source
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "texture".to_string());
+    let source_extension = source
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let copy_as_is =
+        |taken: &mut HashSet<String>, kept: Option<String>| -> Result<CarriedTexture> {
+            let copy = target.join(unique_name(taken, &stem, &source_extension));
+            std::fs::copy(source, &copy).with_context(|| {
+                format!("could not copy {} to {}", source.display(), copy.display())
+            })?;
+            Ok(CarriedTexture {
+                copy,
+                before,
+                after: before,
+                pixels: None,
+                kept,
+            })
+        };
+    if options.copies_only() {
+        return copy_as_is(taken, None);
+    }
+
+    // Decoding goes by content, not extension; anything we can't read
+    // (DDS, EXR, ...) is still better copied than lost
+    let decoded = image::ImageReader::open(source)
+        .and_then(|r| r.with_guessed_format())
+        .map_err(image::ImageError::from)
+        .and_then(|r| r.decode());
+    let mut image = match decoded {
+        Ok(image) => image,
+        Err(e) => return copy_as_is(taken, Some(format!("could not decode it: {}", e))),
+    };
+    let original = [image.width(), image.height()];
+    if let Some(max) = options
+        .max_size
+        .filter(|&m| m < original[0].max(original[1]))
+    {
+        // `resize` keeps the aspect ratio within the box
+        image = image.resize(max, max, FilterType::Lanczos3);
+    }
+
+    // Lossy formats would smear normals and throw away transparency
+    let has_alpha =
+        image.color().has_alpha() && image.to_rgba8().pixels().any(|p| p.0[3] != u8::MAX);
+    if options.format == Some(TextureFormat::Ktx2) {
+        let bytes = ktx2::encode(&image, data, has_alpha, options.quality, options.threads)?;
+        let copy = target.join(unique_name(taken, &stem, "ktx2"));
+        std::fs::write(&copy, &bytes)
+            .with_context(|| format!("could not write {}", copy.display()))?;
+        return Ok(CarriedTexture {
+            after: bytes.len() as u64,
+            copy,
+            before,
+            pixels: Some((original, [image.width(), image.height()])),
+            kept: None,
+        });
+    }
+    let format = match options.format {
+        Some(TextureFormat::Jpeg) if data || has_alpha => ImageFormat::Png,
+        Some(TextureFormat::Jpeg) => ImageFormat::Jpeg,
+        Some(TextureFormat::Png) => ImageFormat::Png,
+        Some(TextureFormat::Webp) => ImageFormat::WebP,
+        Some(TextureFormat::Ktx2) => unreachable!("written above"),
+        None => match ImageFormat::from_path(source) {
+            Ok(format) if format.writing_enabled() => format,
+            _ => ImageFormat::Png,
+        },
+    };
+    let extension = format.extensions_str()[0];
+    let copy = target.join(unique_name(taken, &stem, extension));
+    let file = std::fs::File::create(&copy)
+        .with_context(|| format!("could not create {}", copy.display()))?;
+    let mut out = BufWriter::new(file);
+    match format {
+        ImageFormat::Jpeg => {
+            // JPEG has no alpha channel to write
+            let rgb = image.to_rgb8();
+            let encoder = JpegEncoder::new_with_quality(&mut out, options.quality);
+            rgb.write_with_encoder(encoder)?;
+        }
+        ImageFormat::Png => {
+            let encoder =
+                PngEncoder::new_with_quality(&mut out, CompressionType::Best, PngFilter::Adaptive);
+            image.write_with_encoder(encoder)?;
+        }
+        ImageFormat::WebP => {
+            let encoder = WebPEncoder::new_lossless(&mut out);
+            image.to_rgba8().write_with_encoder(encoder)?;
+        }
+        _ => image.write_to(&mut out, format)?,
+    }
+    out.flush()?;
+    drop(out);
+    Ok(CarriedTexture {
+        after: std::fs::metadata(&copy)?.len(),
+        copy,
+        before,
+        pixels: Some((original, [image.width(), image.height()])),
+        kept: None,
+    })
+}
+
+// `stem.extension`, or `stem_2.extension` and so on if that's taken
+fn unique_name(taken: &mut HashSet<String>, stem: &str, extension: &str) -> String {
+    let dot = if extension.is_empty() { "" } else { "." };
+    let mut name = format!("{}{}{}", stem, dot, extension);
+    let mut n = 1;
+    while !taken.insert(name.to_ascii_lowercase()) {
+        n += 1;
+        name = format!("{}_{}{}{}", stem, n, dot, extension);
+    }
+    name
+}
+
+// Maps that hold numbers rather than colours
+fn is_data_map(keyword: &str) -> bool {
+    matches!(keyword, "bump" | "map_bump" | "norm" | "map_kn" | "disp")
+}
+
+// A texture statement (`map_Kd -o 0 0 0 wood.png`) split into its
+// keyword (lowercase), everything up to the file name, and the file name:
+// the last word. File names with spaces in them aren't supported
+fn texture_reference(line: &str) -> Option<(String, &str, &str)> {
+    let line = line.trim_end();
+    let keyword = line.split_whitespace().next()?.to_ascii_lowercase();
+    let is_texture = keyword.starts_with("map_")
+        || matches!(
+            keyword.as_str(),
+            "bump" | "disp" | "decal" | "refl" | "norm"
+        );
+    if !is_texture {
+        return None;
+    }
+    let at = line.rfind([' ', '\t'])? + 1;
+    Some((keyword, &line[..at], &line[at..]))
+}
@@ -0,0 +1,659 @@
+//! Our own indexed triangle mesh.
+//!
+//! Same flat layout tobj uses (`[x, y, z, x, y, z, ...]` positions and
+//! three indices per triangle), so converting is cheap, but owned by us so
+//! we can edit it.
+//!
+//! Many scanners also write an RGB colour after the xyz on each `v` line
+//! (`v 1 2 3 0.8 0.4 0.1`). It isn't in the OBJ spec, but tobj reads it
+//! and we keep it in `colours`, so it survives into the OBJ we write and
+//! the audit report's model. STL has nowhere to put it.
+//!
+//! Texture coordinates are kept per face corner rather than per vertex:
+//! a UV seam then needs no split vertices, so the audits see the same
+//! welded surface with or without them. Each face also remembers its
+//! material, and the mesh which libraries those came from (see `materials`).
+
+use crate::ascii;
+use crate::limits::InputLimits;
+use crate::materials;
+use crate::metadata::Metadata;
+use crate::output::WriteOptions;
+use crate::ply;
+use crate::stl;
+use crate::storage::{self, Location};
+use anyhow::{bail, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<f32>,
+    pub indices: Vec<u32>,
+    /// Per-vertex RGB in 0..1, laid out like `positions`. Empty when the
+    /// file had no colours.
+    pub colours: Vec<f32>,
+    /// UV per face corner, two per entry of `indices`. Empty when the file
+    /// had none.
+    pub texcoords: Vec<f32>,
+    /// Per face, an index into `materials`. Empty when no face has one.
+    pub face_materials: Vec<Option<u32>>,
+    /// Material names.
+    pub materials: Vec<String>,
+    /// The MTL files the materials were read from.
+    pub material_libraries: Vec<PathBuf>,
+    /// What was done to the geometry that whoever reads the file should
+    /// know (a shrinkage allowance, say), one line each. Writers put them
+    /// where the format has room: OBJ comments, the STL header.
+    pub notes: Vec<String>,
+    /// Name, units and other attributes the file carried (see `metadata`).
+    pub metadata: Metadata,
+    /// Named scalars per vertex that the geometry doesn't use (a scan's
+    /// confidence, a simulation's temperature or strain). Edits carry them
+    /// along, interpolating where vertices merge; PLY and glTF write them.
+    pub vertex_attributes: Vec<VertexAttribute>,
+}
+
+/// One value per vertex, by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VertexAttribute {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+/// Mesh formats we can read, by extension.
+pub const INPUT_EXTENSIONS: &[&str] = &["obj", "stl", "ply"];
+
+impl Mesh {
+    /// Load a mesh file, picking the reader by extension: `.stl` files are
+    /// read as STL, `.ply` as PLY, anything else as OBJ.
+    pub fn load(filename: &str, limits: &InputLimits) -> Result<Self> {
+        let extension = Path::new(filename)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("stl") => stl::load_stl(filename, limits),
+            Some("ply") => ply::load_mesh(filename, limits),
+            _ => Self::load_obj(filename, limits),
+        }
+    }
+
+    /// A mesh from a flat triangle list (9 floats a triangle, as marching
+    /// cubes and STL give them), with corners at exactly the same spot
+    /// joined into shared vertices.
+    pub fn from_triangles(triangles: &[f32]) -> Result<Self> {
+        let mut mesh = Mesh::default();
+        let mut index_of: HashMap<[u32; 3], u32> = HashMap::new();
+        for corner in triangles.chunks_exact(3) {
+            let corner = [corner[0], corner[1], corner[2]];
+            let next = u32::try_from(mesh.vertex_count())?;
+            let index = *index_of.entry(corner.map(f32::to_bits)).or_insert_with(|| {
+                mesh.positions.extend_from_slice(&corner);
+                next
+            });
+            mesh.indices.push(index);
+        }
+        Ok(mesh)
+    }
+
+    /// Load every object in an OBJ file into one mesh.
+    pub fn load_obj(filename: &str, limits: &InputLimits) -> Result<Self> {
+        let load_options = tobj::LoadOptions {
+            triangulate: true,
+            ..Default::default()
+        };
+        let mut reader = BufReader::new(storage::open(filename, limits)?);
+        // Material libraries only from beside a local file: a hostile
+        // `mtllib` line could otherwise point us at any file on the machine
+        let base = match Location::parse(filename)? {
+            Location::Local(path) => Path::new(&path).parent().map(Path::to_path_buf),
+            _ => None,
+        };
+        // tobj wants an `Fn`, hence the cell
+        let libraries = RefCell::new(Vec::new());
+        let load_materials = |reference: &Path| {
+            let loaded = base
+                .as_deref()
+                .and_then(|base| materials::load_library(base, reference, limits));
+            match loaded {
+                Some((path, Ok(loaded))) => {
+                    libraries.borrow_mut().push(path);
+                    Ok(loaded)
+                }
+                Some((_, Err(e))) => Err(e),
+                None => Err(tobj::LoadError::OpenFileFailed),
+            }
+        };
+        // Our `# @key value` comments come first, before anything tobj reads
+        let mut metadata = Metadata::default();
+        loop {
+            let buffer = reader.fill_buf()?;
+            let Some(rest) = buffer.strip_prefix(b"#") else {
+                break;
+            };
+            let Some(end) = rest.iter().position(|&b| b == b'\n') else {
+                break;
+            };
+            metadata.read_comment(&String::from_utf8_lossy(&rest[..end]));
+            reader.consume(end + 2);
+        }
+        let loaded = if limits.lenient {
+            let mut text = String::new();
+            reader.read_to_string(&mut text)?;
+            let text = ascii::normalize_obj(&text);
+            tobj::load_obj_buf(&mut text.as_bytes(), &load_options, load_materials)
+        } else {
+            tobj::load_obj_buf(&mut reader, &load_options, load_materials)
+        };
+        let (models, loaded_materials) = match loaded {
+            Err(
+                e @ (tobj::LoadError::PositionParseError
+                | tobj::LoadError::NormalParseError
+                | tobj::LoadError::TexcoordParseError),
+            ) if !limits.lenient => {
+                bail!(
+                    "{} in {} (numbers written with a decimal comma or other \
+                     loose formatting can be read with --lenient)",
+                    e,
+                    filename
+                )
+            }
+            loaded => loaded?,
+        };
+        // tobj gives an empty file one empty object
+        if models.iter().all(|m| m.mesh.positions.is_empty()) {
+            bail!("no 3D objects found in {}", filename);
+        }
+
+        let triangles = models
+            .iter()
+            .try_fold(0usize, |n, m| n.checked_add(m.mesh.indices.len() / 3));
+        limits.check_triangles(triangles.unwrap_or(usize::MAX))?;
+
+        // Colours only count if every object has them: half a coloured mesh
+        // can't be written back out as one
+        let coloured = models.iter().all(|m| {
+            !m.mesh.vertex_color.is_empty() && m.mesh.vertex_color.len() == m.mesh.positions.len()
+        });
+        let textured = models.iter().all(|m| {
+            !m.mesh.texcoord_indices.is_empty()
+                && m.mesh.texcoord_indices.len() == m.mesh.indices.len()
+        });
+        // A library that didn't load leaves its faces without a material
+        let materials = loaded_materials.unwrap_or_default();
+        let mut mesh = Mesh {
+            materials: materials.iter().map(|m| m.name.clone()).collect(),
+            material_libraries: libraries.into_inner(),
+            metadata,
+            ..Default::default()
+        };
+        // tobj names objects without an `o` or `g` line itself
+        if let Some(model) = models.iter().find(|m| m.name != "unnamed_object") {
+            mesh.metadata.name.get_or_insert_with(|| model.name.clone());
+        }
+        let has_materials = models
+            .iter()
+            .any(|m| m.mesh.material_id.is_some_and(|id| id < materials.len()));
+        // A `usemtl` part way through an object makes tobj start a new
+        // model of the same name with its own copy of the vertices, which
+        // would tear the surface along every material boundary. Vertices of
+        // such a run of models are welded back together by position.
+        // (Only looked for when there are such runs, to keep big plain
+        // scans quick.)
+        let weld = models.windows(2).any(|w| w[0].name == w[1].name);
+        let mut run: HashMap<[u32; 6], u32> = HashMap::new();
+        let mut run_name = None;
+        for m in &models {
+            if run_name != Some(&m.name) {
+                run.clear();
+                run_name = Some(&m.name);
+            }
+            let split = !run.is_empty();
+            let mut remap = Vec::with_capacity(m.mesh.positions.len() / 3);
+            for (j, p) in m.mesh.positions.chunks_exact(3).enumerate() {
+                let colour = if coloured {
+                    &m.mesh.vertex_color[j * 3..j * 3 + 3]
+                } else {
+                    &[0.0; 3]
+                };
+                let key = [p[0], p[1], p[2], colour[0], colour[1], colour[2]].map(f32::to_bits);
+                if let Some(&welded) = run.get(&key).filter(|_| split) {
+                    remap.push(welded);
+                    continue;
+                }
+                let Ok(index) = u32::try_from(mesh.vertex_count()) else {
+                    bail!("{} has too many vertices", filename);
+                };
+                mesh.positions.extend_from_slice(p);
+                if coloured {
+                    mesh.colours.extend_from_slice(colour);
+                }
+                if weld {
+                    run.entry(key).or_insert(index);
+                }
+                remap.push(index);
+            }
+            for &i in &m.mesh.indices {
+                let Some(&index) = remap.get(i as usize) else {
+                    bail!("{} has a face using a missing vertex", filename);
+                };
+                mesh.indices.push(index);
+            }
+            if textured {
+                for &t in &m.mesh.texcoord_indices {
+                    let Some(uv) = m.mesh.texcoords.get(t as usize * 2..t as usize * 2 + 2) else {
+                        bail!("{} has a face using a missing texture coordinate", filename);
+                    };
+                    mesh.texcoords.extend_from_slice(uv);
+                }
+            }
+            if has_materials {
+                let material = m
+                    .mesh
+                    .material_id
+                    .filter(|&id| id < materials.len())
+                    .map(|id| id as u32);
+                let faces = m.mesh.indices.len() / 3;
+                mesh.face_materials
+                    .extend(std::iter::repeat_n(material, faces));
+            }
+        }
+        if !has_materials {
+            mesh.materials.clear();
+            mesh.material_libraries.clear();
+        }
+        // Some scanners write 0..255 rather than 0..1
+        if mesh.colours.iter().any(|&c| c > 1.0) {
+            for c in mesh.colours.iter_mut() {
+                *c /= 255.0;
+            }
+        }
+        mesh.validate()?;
+        Ok(mesh)
+    }
+
+    /// Check the invariants the rest of the code relies on: every index
+    /// names a real vertex and every coordinate is a finite number.
+    pub fn validate(&self) -> Result<()> {
+        if !self.positions.len().is_multiple_of(3) || !self.indices.len().is_multiple_of(3) {
+            bail!("mesh arrays are not a whole number of vertices/triangles");
+        }
+        if !self.colours.is_empty() && self.colours.len() != self.positions.len() {
+            bail!("mesh has colours for some vertices but not others");
+        }
+        if let Some(a) = self
+            .vertex_attributes
+            .iter()
+            .find(|a| a.values.len() != self.vertex_count())
+        {
+            bail!("mesh has '{}' for some vertices but not others", a.name);
+        }
+        if !self.texcoords.is_empty() && self.texcoords.len() != self.indices.len() * 2 {
+            bail!("mesh has texture coordinates for some corners but not others");
+        }
+        if !self.face_materials.is_empty() && self.face_materials.len() != self.face_count() {
+            bail!("mesh has materials for some faces but not others");
+        }
+        let materials = self.materials.len();
+        if let Some(m) = self
+            .face_materials
+            .iter()
+            .flatten()
+            .find(|&&m| m as usize >= materials)
+        {
+            bail!("face uses material {} but there are only {}", m, materials);
+        }
+        if let Some(v) = self.positions.iter().find(|v| !v.is_finite()) {
+            bail!("mesh has a non-finite coordinate ({})", v);
+        }
+        let count = self.vertex_count();
+        if let Some(i) = self.indices.iter().find(|&&i| i as usize >= count) {
+            bail!("face refers to vertex {} but there are only {}", i, count);
+        }
+        Ok(())
+    }
+
+    pub fn vertex_count(&self) -> usize {
+        self.positions.len() / 3
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    pub fn vertex(&self, i: usize) -> [f32; 3] {
+        [
+            self.positions[i * 3],
+            self.positions[i * 3 + 1],
+            self.positions[i * 3 + 2],
+        ]
+    }
+
+    pub fn has_colours(&self) -> bool {
+        !self.colours.is_empty()
+    }
+
+    /// A vertex's colour, if the mesh has colours.
+    pub fn colour(&self, i: usize) -> Option<[f32; 3]> {
+        self.has_colours().then(|| {
+            [
+                self.colours[i * 3],
+                self.colours[i * 3 + 1],
+                self.colours[i * 3 + 2],
+            ]
+        })
+    }
+
+    /// Add a vertex, keeping `colours` in step: `colour` is used only if
+    /// the mesh has colours. Its vertex attributes are NaN, no value, until
+    /// set (see `copy_vertex_attributes`).
+    pub fn push_vertex(&mut self, position: [f32; 3], colour: [f32; 3]) {
+        self.positions.extend_from_slice(&position);
+        if self.has_colours() {
+            self.colours.extend_from_slice(&colour);
+        }
+        for attribute in &mut self.vertex_attributes {
+            attribute.values.push(f32::NAN);
+        }
+    }
+
+    /// Give vertex `to` the attributes vertex `v` of `from` has, by name.
+    pub fn copy_vertex_attributes(&mut self, to: usize, from: &Mesh, v: usize) {
+        for attribute in &mut self.vertex_attributes {
+            if let Some(source) = from
+                .vertex_attributes
+                .iter()
+                .find(|a| a.name == attribute.name)
+            {
+                attribute.values[to] = source.values[v];
+            }
+        }
+    }
+
+    /// The vertex attributes of `vertices`, in that order: those of a mesh
+    /// made of just them.
+    pub fn pick_vertex_attributes(&self, vertices: &[usize]) -> Vec<VertexAttribute> {
+        self.vertex_attributes
+            .iter()
+            .map(|a| VertexAttribute {
+                name: a.name.clone(),
+                values: vertices.iter().map(|&v| a.values[v]).collect(),
+            })
+            .collect()
+    }
+
+    pub fn face(&self, f: usize) -> [usize; 3] {
+        [
+            self.indices[f * 3] as usize,
+            self.indices[f * 3 + 1] as usize,
+            self.indices[f * 3 + 2] as usize,
+        ]
+    }
+
+    /// UV of the `corner`th entry of `indices`, if the mesh has UVs.
+    pub fn texcoord(&self, corner: usize) -> Option<[f32; 2]> {
+        (!self.texcoords.is_empty())
+            .then(|| [self.texcoords[corner * 2], self.texcoords[corner * 2 + 1]])
+    }
+
+    pub fn face_material(&self, f: usize) -> Option<u32> {
+        self.face_materials.get(f).copied().flatten()
+    }
+
+    /// Reverse a face's winding, taking its UVs along.
+    pub fn flip_face(&mut self, f: usize) {
+        self.indices.swap(f * 3 + 1, f * 3 + 2);
+        if !self.texcoords.is_empty() {
+            self.texcoords.swap(f * 6 + 2, f * 6 + 4);
+            self.texcoords.swap(f * 6 + 3, f * 6 + 5);
+        }
+    }
+
+    /// A new mesh of just the faces `keep` says yes to, with their UVs and
+    /// materials and only the vertices they use (and their attributes),
+    /// renumbered as they come.
+    pub fn select_faces(&self, keep: impl Fn(usize) -> bool) -> Mesh {
+        let mut selected = Mesh {
+            materials: self.materials.clone(),
+            material_libraries: self.material_libraries.clone(),
+            notes: self.notes.clone(),
+            metadata: self.metadata.clone(),
+            ..Mesh::default()
+        };
+        let mut new_index = vec![u32::MAX; self.vertex_count()];
+        let mut picked = Vec::new();
+        for f in (0..self.face_count()).filter(|&f| keep(f)) {
+            for v in self.face(f) {
+                if new_index[v] == u32::MAX {
+                    new_index[v] = selected.vertex_count() as u32;
+                    picked.push(v);
+                    selected
+                        .positions
+                        .extend_from_slice(&self.positions[v * 3..v * 3 + 3]);
+                    if self.has_colours() {
+                        selected
+                            .colours
+                            .extend_from_slice(&self.colours[v * 3..v * 3 + 3]);
+                    }
+                }
+                selected.indices.push(new_index[v]);
+            }
+            if !self.texcoords.is_empty() {
+                selected
+                    .texcoords
+                    .extend_from_slice(&self.texcoords[f * 6..f * 6 + 6]);
+            }
+            if !self.face_materials.is_empty() {
+                selected.face_materials.push(self.face_material(f));
+            }
+        }
+        selected.vertex_attributes = self.pick_vertex_attributes(&picked);
+        selected
+    }
+
+    /// A copy coloured `colours[f]` on each face `f`. Every face gets three
+    /// vertices of its own, so neighbours' colours don't blend across the
+    /// edges between them.
+    pub fn with_face_colours(&self, colours: &[[f32; 3]]) -> Mesh {
+        let mut coloured = Mesh::default();
+        for (f, colour) in colours.iter().enumerate().take(self.face_count()) {
+            for v in self.face(f) {
+                coloured.indices.push(coloured.vertex_count() as u32);
+                coloured.positions.extend_from_slice(&self.vertex(v));
+                coloured.colours.extend_from_slice(colour);
+            }
+        }
+        coloured
+    }
+
+    /// Average of a face's three corners.
+    pub fn face_centroid(&self, f: usize) -> [f32; 3] {
+        let [a, b, c] = self.face(f).map(|i| self.vertex(i));
+        [
+            (a[0] + b[0] + c[0]) / 3.0,
+            (a[1] + b[1] + c[1]) / 3.0,
+            (a[2] + b[2] + c[2]) / 3.0,
+        ]
+    }
+
+    /// Unit normal of a face, by its winding; straight up for a face with
+    /// no area.
+    pub fn face_normal(&self, f: usize) -> [f32; 3] {
+        let [a, b, c] = self.face(f).map(|i| self.vertex(i));
+        let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+        let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+        let n = [
+            u[1] * v[2] - u[2] * v[1],
+            u[2] * v[0] - u[0] * v[2],
+            u[0] * v[1] - u[1] * v[0],
+        ];
+        let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+        if length > 0.0 {
+            n.map(|x| x / length)
+        } else {
+            [0.0, 0.0, 1.0]
+        }
+    }
+
+    /// Smooth unit normal of each vertex: its faces' normals, weighted by
+    /// area. Straight up for a vertex no face uses.
+    pub fn vertex_normals(&self) -> Vec<[f32; 3]> {
+        let mut normals = vec![[0.0f32; 3]; self.vertex_count()];
+        for f in 0..self.face_count() {
+            let corners = self.face(f);
+            let [a, b, c] = corners.map(|i| self.vertex(i));
+            let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+            let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+            // Unnormalised, the cross product is already twice the area
+            let n = [
+                u[1] * v[2] - u[2] * v[1],
+                u[2] * v[0] - u[0] * v[2],
+                u[0] * v[1] - u[1] * v[0],
+            ];
+            for i in corners {
+                for k in 0..3 {
+                    normals[i][k] += n[k];
+                }
+            }
+        }
+        for n in &mut normals {
+            let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+            *n = if length > 0.0 {
+                n.map(|x| x / length)
+            } else {
+                [0.0, 0.0, 1.0]
+            };
+        }
+        normals
+    }
+
+    /// Axis-aligned bounding box as (min, max).
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for v in self.positions.chunks_exact(3) {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(v[axis]);
+                max[axis] = max[axis].max(v[axis]);
+            }
+        }
+        (min, max)
+    }
+
+    pub fn save_obj(&self, filename: &str, options: &WriteOptions) -> Result<()> {
+        self.save_obj_with(filename, None, options)
+    }
+
+    /// Write an OBJ, naming `mtllib` as its material library if given (see
+    /// `materials::carry`). UVs and `usemtl` groups are written either way.
+    pub fn save_obj_with(
+        &self,
+        filename: &str,
+        mtllib: Option<&str>,
+        options: &WriteOptions,
+    ) -> Result<()> {
+        let mesh = options.ordered(self);
+        let precision = options.ascii_precision;
+        let mut file = storage::create(filename)?;
+        // Our comments first, where `load_obj` looks for them
+        for comment in mesh.metadata.comments() {
+            writeln!(file, "# {}", comment)?;
+        }
+        for note in &mesh.notes {
+            writeln!(file, "# {}", note)?;
+        }
+        if let Some(mtllib) = mtllib {
+            writeln!(file, "mtllib {}", mtllib)?;
+        }
+        if let Some(name) = &mesh.metadata.name {
+            writeln!(file, "o {}", name.replace(['\n', '\r'], " "))?;
+        }
+        for i in 0..mesh.vertex_count() {
+            let v = mesh.vertex(i);
+            match mesh.colour(i) {
+                // The scanners' `v x y z r g b`
+                Some(c) => {
+                    let values = [v[0], v[1], v[2], c[0], c[1], c[2]];
+                    ascii::write_line(&mut file, "v", &values, precision)?
+                }
+                None => ascii::write_line(&mut file, "v", &v, precision)?,
+            }
+        }
+        // One `vt` per distinct UV, in order of first use
+        let mut uv_index: HashMap<[u32; 2], usize> = HashMap::new();
+        let mut corner_uvs = Vec::with_capacity(mesh.texcoords.len() / 2);
+        for uv in mesh.texcoords.chunks_exact(2) {
+            let next = uv_index.len();
+            let index = *uv_index
+                .entry([uv[0].to_bits(), uv[1].to_bits()])
+                .or_insert_with(|| next);
+            if index == next {
+                ascii::write_line(&mut file, "vt", uv, precision)?;
+            }
+            corner_uvs.push(index);
+        }
+        // OBJ indices start at 1
+        let mut material = None;
+        for (f, v) in mesh.indices.chunks_exact(3).enumerate() {
+            if mesh.face_material(f) != material {
+                material = mesh.face_material(f);
+                // A name no library has reads as no material
+                let name = material.map_or("none", |m| &mesh.materials[m as usize]);
+                writeln!(file, "usemtl {}", name)?;
+            }
+            if corner_uvs.is_empty() {
+                writeln!(file, "f {} {} {}", v[0] + 1, v[1] + 1, v[2] + 1)?;
+            } else {
+                let t = &corner_uvs[f * 3..f * 3 + 3];
+                writeln!(
+                    file,
+                    "f {}/{} {}/{} {}/{}",
+                    v[0] + 1,
+                    t[0] + 1,
+                    v[1] + 1,
+                    t[1] + 1,
+                    v[2] + 1,
+                    t[2] + 1
+                )?;
+            }
+        }
+        file.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Load `text` as an OBJ file
+    fn load(name: &str, text: &str) -> Result<Mesh> {
+        let path = std::env::temp_dir().join(format!("{}_{}.obj", name, std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        std::fs::write(&path, text).unwrap();
+        let mesh = Mesh::load(&path, &InputLimits::default());
+        std::fs::remove_file(&path).ok();
+        mesh
+    }
+
+    #[test]
+    fn obj_loads() {
+        let mesh = load(
+            "obj_quad",
+            "# @units mm\no plate\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n",
+        )
+        .unwrap();
+        assert_eq!((mesh.vertex_count(), mesh.face_count()), (4, 2));
+        assert_eq!(mesh.metadata.name.as_deref(), Some("plate"));
+    }
+
+    #[test]
+    fn malformed_obj_is_an_error() {
+        assert!(load("obj_empty", "").is_err());
+        assert!(load("obj_comments", "# nothing here\n").is_err());
+        assert!(load("obj_bad_number", "v 0 0 zero\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").is_err());
+        assert!(load("obj_missing_vertex", "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 9\n").is_err());
+        assert!(load("obj_not_finite", "v 0 0 inf\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! Meshlets for mesh-shading pipelines: the triangles cut into small
+//! clusters, each with the culling data a task shader needs.
+//!
+//! Clusters are filled in index order, which after `optimize` is the
+//! vertex cache order and so already walks the surface in tight patches;
+//! a meshlet closes when one more triangle would take it over either
+//! limit. Each meshlet gets a bounding sphere and a normal cone: if the
+//! camera is inside the cone behind `cone_apex`, that is if
+//! `dot(normalize(cone_apex - camera), cone_axis) >= cone_cutoff`, every
+//! triangle in it faces away and the whole meshlet can be skipped.
+//!
+//! The sidecar (`.meshlets`, little-endian throughout) is:
+//!
+//! ```text
+//! header    "MLET"  u32 version (1)  u32 meshlets  u32 vertex refs  u32 triangles
+//! meshlets  per meshlet, 60 bytes:
+//!           u32 vertex_offset  u32 vertex_count
+//!           u32 triangle_offset  u32 triangle_count
+//!           f32×3 centre  f32 radius
+//!           f32×3 cone_apex  f32×3 cone_axis  f32 cone_cutoff
+//! vertices  u32 per ref: an index into the .glb's vertices
+//! triangles u8×3 per triangle: indices into its meshlet's vertex refs,
+//!           padded with zeros to a multiple of 4 bytes
+//! ```
+//!
+//! Offsets count entries (vertex refs, triangles), not bytes.
+
+use crate::gltf::Primitive;
+use crate::storage;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::io::Write;
+
+/// Vertices a meshlet may use, by default (what most GPUs like).
+pub const DEFAULT_MAX_VERTICES: usize = 64;
+/// Triangles a meshlet may hold, by default.
+pub const DEFAULT_MAX_TRIANGLES: usize = 124;
+// Local indices are bytes
+const MOST_VERTICES: usize = 255;
+const MOST_TRIANGLES: usize = 512;
+// A cone whose triangles spread wider than this (as the smallest dot
+// product with the axis) can never be culled, so it isn't tried
+const MIN_CONE_DOT: f32 = 0.1;
+const VERSION: u32 = 1;
+
+/// One cluster of triangles.
+#[derive(Debug, Clone)]
+pub struct Meshlet {
+    /// The primitive's vertices this meshlet uses.
+    pub vertices: Vec<u32>,
+    /// Its triangles, as indices into `vertices`.
+    pub triangles: Vec<[u8; 3]>,
+    pub centre: [f32; 3],
+    pub radius: f32,
+    pub cone_apex: [f32; 3],
+    pub cone_axis: [f32; 3],
+    /// 1 when the meshlet can't be cone culled.
+    pub cone_cutoff: f32,
+}
+
+/// Cut `primitive` into meshlets of at most `max_vertices` vertices and
+/// `max_triangles` triangles.
+pub fn build(
+    primitive: &Primitive,
+    max_vertices: usize,
+    max_triangles: usize,
+) -> Result<Vec<Meshlet>> {
+    if !(3..=MOST_VERTICES).contains(&max_vertices) {
+        bail!("a meshlet needs 3 to {} vertices", MOST_VERTICES);
+    }
+    if !(1..=MOST_TRIANGLES).contains(&max_triangles) {
+        bail!("a meshlet needs 1 to {} triangles", MOST_TRIANGLES);
+    }
+
+    // 1. Fill meshlets in index order
+    let mut meshlets = Vec::new();
+    let mut vertices: Vec<u32> = Vec::new();
+    let mut local: HashMap<u32, u8> = HashMap::new();
+    let mut triangles: Vec<[u8; 3]> = Vec::new();
+    for face in primitive.indices.chunks_exact(3) {
+        let new = face
+            .iter()
+            .enumerate()
+            .filter(|&(k, i)| !local.contains_key(i) && !face[..k].contains(i))
+            .count();
+        if vertices.len() + new > max_vertices || triangles.len() == max_triangles {
+            meshlets.push(finish(primitive, &vertices, &triangles));
+            vertices.clear();
+            local.clear();
+            triangles.clear();
+        }
+        let corners = [face[0], face[1], face[2]].map(|i| {
+            *local.entry(i).or_insert_with(|| {
+                vertices.push(i);
+                (vertices.len() - 1) as u8
+            })
+        });
+        triangles.push(corners);
+    }
+    if !triangles.is_empty() {
+        meshlets.push(finish(primitive, &vertices, &triangles));
+    }
+    Ok(meshlets)
+}
+
+// A filled meshlet with its bounds worked out
+fn finish(primitive: &Primitive, vertices: &[u32], triangles: &[[u8; 3]]) -> Meshlet {
+    let point = |local: u8| primitive.positions[vertices[local as usize] as usize];
+
+    // 1. The sphere round the box round the vertices
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for &v in vertices {
+        let p = primitive.positions[v as usize];
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    let centre = [0, 1, 2].map(|k| (min[k] + max[k]) * 0.5);
+    let radius = vertices
+        .iter()
+        .map(|&v| {
+            let d = sub(primitive.positions[v as usize], centre);
+            dot(d, d)
+        })
+        .fold(0.0f32, f32::max)
+        .sqrt();
+
+    // 2. The cone: the mean face normal, and how far the faces stray
+    let normals: Vec<([f32; 3], [f32; 3])> = triangles
+        .iter()
+        .filter_map(|t| {
+            let [a, b, c] = t.map(point);
+            normalize(cross(sub(b, a), sub(c, a))).map(|n| (a, n))
+        })
+        .collect();
+    let mut meshlet = Meshlet {
+        vertices: vertices.to_vec(),
+        triangles: triangles.to_vec(),
+        centre,
+        radius,
+        cone_apex: centre,
+        cone_axis: [0.0, 0.0, 1.0],
+        cone_cutoff: 1.0,
+    };
+    let mut sum = [0.0f32; 3];
+    for (_, n) in &normals {
+        for k in 0..3 {
+            sum[k] += n[k];
+        }
+    }
+    let Some(axis) = normalize(sum) else {
+        return meshlet;
+    };
+    meshlet.cone_axis = axis;
+    let min_dot = normals
+        .iter()
+        .map(|(_, n)| dot(*n, axis))
+        .fold(1.0f32, f32::min);
+    if min_dot <= MIN_CONE_DOT {
+        return meshlet;
+    }
+    meshlet.cone_cutoff = (1.0 - min_dot * min_dot).sqrt();
+
+    // 3. The apex: the point on the axis behind every face's plane, so a
+    // camera further back inside the cone sees only their backs
+    let behind = normals
+        .iter()
+        .map(|&(a, n)| dot(sub(a, centre), n) / dot(axis, n))
+        .fold(f32::MAX, f32::min);
+    meshlet.cone_apex = [0, 1, 2].map(|k| centre[k] + axis[k] * behind);
+    meshlet
+}
+
+/// Write `meshlets` in the sidecar format above.
+pub fn write_sidecar(location: &str, meshlets: &[Meshlet]) -> Result<()> {
+    let refs: usize = meshlets.iter().map(|m| m.vertices.len()).sum();
+    let triangles: usize = meshlets.iter().map(|m| m.triangles.len()).sum();
+    let mut bytes: Vec<u8> = Vec::new();
+    bytes.extend_from_slice(b"MLET");
+    for value in [
+        VERSION,
+        meshlets.len() as u32,
+        refs as u32,
+        triangles as u32,
+    ] {
+        bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    let (mut vertex_offset, mut triangle_offset) = (0u32, 0u32);
+    for m in meshlets {
+        for value in [
+            vertex_offset,
+            m.vertices.len() as u32,
+            triangle_offset,
+            m.triangles.len() as u32,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let floats = m
+            .centre
+            .iter()
+            .chain([&m.radius])
+            .chain(&m.cone_apex)
+            .chain(&m.cone_axis)
+            .chain([&m.cone_cutoff]);
+        for value in floats {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        vertex_offset += m.vertices.len() as u32;
+        triangle_offset += m.triangles.len() as u32;
+    }
+    for m in meshlets {
+        for v in &m.vertices {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+    }
+    for m in meshlets {
+        for t in &m.triangles {
+            bytes.extend_from_slice(t);
+        }
+    }
+    while !bytes.len().is_multiple_of(4) {
+        bytes.push(0);
+    }
+    let mut out = storage::create(location)?;
+    out.write_all(&bytes)?;
+    out.finish()
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(a: [f32; 3]) -> Option<[f32; 3]> {
+    let l = dot(a, a).sqrt();
+    (l > 1e-12).then(|| a.map(|x| x / l))
+}
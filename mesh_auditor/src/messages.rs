@@ -0,0 +1,115 @@
+//! The words reports are written in (`--lang`).
+//!
+//! Every sentence an audit shows a person, on the terminal, in the HTML
+//! report or in a finding's `message`, comes from a catalog, by key, with
+//! `{name}` marking where a value goes. What scripts read is left alone:
+//! codes like `W007`, names like `inverted-normals`, and JSON field names
+//! are the same in every language, so `--allow` and anything filtering on
+//! them keep working whoever runs the tool.
+//!
+//! Catalogs are TOML files of tables of strings (see `messages/en.toml`).
+//! English and German are built in; `--lang` also takes the path of a
+//! catalog someone has written, which needs only the messages it
+//! translates, as English fills in the rest. Like the ASCII settings, the
+//! language is process wide and set once at start-up.
+
+use anyhow::{bail, Context, Result};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::OnceLock;
+
+/// The catalogs compiled in, by language tag.
+pub const BUILT_IN: &[(&str, &str)] = &[
+    ("en", include_str!("messages/en.toml")),
+    ("de", include_str!("messages/de.toml")),
+];
+
+type Catalog = HashMap<String, String>;
+
+static ENGLISH: OnceLock<Catalog> = OnceLock::new();
+// Unset means English
+static CHOSEN: OnceLock<Catalog> = OnceLock::new();
+
+/// Write reports in `lang`: a built-in tag like `de`, or the path of a
+/// `.toml` catalog.
+pub fn set_lang(lang: &str) -> Result<()> {
+    let catalog = match BUILT_IN
+        .iter()
+        .find(|(tag, _)| tag.eq_ignore_ascii_case(lang))
+    {
+        Some((tag, text)) => {
+            parse(text).with_context(|| format!("bad built-in catalog {}", tag))?
+        }
+        None if Path::new(lang).is_file() => {
+            let text = std::fs::read_to_string(lang)
+                .with_context(|| format!("could not read {}", lang))?;
+            let catalog = parse(&text).with_context(|| format!("bad message catalog {}", lang))?;
+            // A key English doesn't have is a typo the report would never show
+            if let Some(key) = catalog.keys().find(|k| !english().contains_key(*k)) {
+                bail!("{} has a message English doesn't: {}", lang, key);
+            }
+            catalog
+        }
+        None => bail!(
+            "unknown language '{}' (built in: {}; or give a .toml catalog)",
+            lang,
+            BUILT_IN
+                .iter()
+                .map(|(tag, _)| *tag)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    let _ = CHOSEN.set(catalog);
+    Ok(())
+}
+
+/// The message `key` (like `audit.watertight`) in the chosen language,
+/// with each `{name}` in it replaced by its value in `args`.
+pub fn text(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let template = CHOSEN
+        .get()
+        .and_then(|c| c.get(key))
+        .or_else(|| english().get(key))
+        // Only a key missing from en.toml gets here: show it, not nothing
+        .map_or(key, String::as_str);
+    let mut message = template.to_string();
+    for (name, value) in args {
+        message = message.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    message
+}
+
+/// `text` for a yes-or-no answer.
+pub fn yes_no(answer: bool) -> String {
+    text(if answer { "audit.yes" } else { "audit.no" }, &[])
+}
+
+fn english() -> &'static Catalog {
+    ENGLISH.get_or_init(|| parse(BUILT_IN[0].1).expect("en.toml is valid"))
+}
+
+// Tables become dotted keys: `[audit] faces` is `audit.faces`
+fn parse(text: &str) -> Result<Catalog> {
+    fn flatten(prefix: &str, table: &toml::Table, out: &mut Catalog) -> Result<()> {
+        for (name, value) in table {
+            let key = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}.{}", prefix, name)
+            };
+            match value {
+                toml::Value::String(s) => {
+                    out.insert(key, s.clone());
+                }
+                toml::Value::Table(t) => flatten(&key, t, out)?,
+                _ => bail!("{} should be text", key),
+            }
+        }
+        Ok(())
+    }
+    let mut catalog = Catalog::new();
+    flatten("", &text.parse::<toml::Table>()?, &mut catalog)?;
+    Ok(catalog)
+}
@@ -0,0 +1,224 @@
+//! What a mesh says about itself, beside its geometry, and which formats
+//! have room for which of it.
+//!
+//! Formats disagree about metadata. OBJ has object names and free comments,
+//! STL a solid name (or an 80 byte header) and nothing else, PLY comments
+//! and any number of values per vertex, VTK values per vertex and nothing
+//! about the whole, glTF its own per-vertex colours and attributes and
+//! free `extras` for the rest, and Gmsh's MSH nothing but its physical
+//! groups.
+//! `Mesh::metadata` holds the union: a name, the units the coordinates are
+//! in, and named attributes of any other kind. Where a format has nowhere
+//! of its own for units or attributes but does have comments, they are
+//! written as `@key value` comments (`# @units mm`, `comment @part 12-A`),
+//! which our readers pick back out; other comments are left alone.
+//!
+//! `Format::carries` is the mapping: for each format, which of a mesh's
+//! contents survive being written to it. `dropped` lists what a given mesh
+//! would lose, so a conversion can say so rather than lose it quietly.
+
+use crate::mesh::Mesh;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// The object's name: OBJ's `o`, STL's solid name.
+    pub name: Option<String>,
+    /// What one unit of the coordinates is, when the file says.
+    pub units: Option<Units>,
+    /// Anything else, by name.
+    pub attributes: BTreeMap<String, String>,
+}
+
+/// Length units, as 3MF and most CAD packages know them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Units {
+    Micron,
+    Millimetre,
+    Centimetre,
+    Metre,
+    Inch,
+    Foot,
+}
+
+impl Units {
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Units::Micron => "um",
+            Units::Millimetre => "mm",
+            Units::Centimetre => "cm",
+            Units::Metre => "m",
+            Units::Inch => "in",
+            Units::Foot => "ft",
+        }
+    }
+
+    /// Units from a symbol or name (`mm`, `millimeter`, `inches`, ...).
+    pub fn parse(text: &str) -> Option<Units> {
+        let text = text.trim().to_ascii_lowercase();
+        let units = match text.trim_end_matches('s') {
+            "um" | "µm" | "micron" | "micrometer" | "micrometre" => Units::Micron,
+            "mm" | "millimeter" | "millimetre" => Units::Millimetre,
+            "cm" | "centimeter" | "centimetre" => Units::Centimetre,
+            "m" | "meter" | "metre" => Units::Metre,
+            "in" | "inch" | "inche" => Units::Inch,
+            "ft" | "foot" | "feet" => Units::Foot,
+            _ => return None,
+        };
+        Some(units)
+    }
+}
+
+/// The mesh formats we know the metadata rules of, by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Obj,
+    Stl,
+    Ply,
+    /// Legacy `.vtk` and XML `.vtu` alike.
+    Vtk,
+    /// Binary glTF.
+    Glb,
+    /// Gmsh.
+    Msh,
+}
+
+/// One kind of thing a mesh can carry besides its triangles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Content {
+    Name,
+    Units,
+    Attributes,
+    Notes,
+    Colours,
+    Texcoords,
+    Materials,
+    VertexAttributes,
+}
+
+impl fmt::Display for Content {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Content::Name => "the object name",
+            Content::Units => "units",
+            Content::Attributes => "custom attributes",
+            Content::Notes => "notes",
+            Content::Colours => "vertex colours",
+            Content::Texcoords => "texture coordinates",
+            Content::Materials => "materials",
+            Content::VertexAttributes => "vertex attributes",
+        })
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Format::Obj => "OBJ",
+            Format::Stl => "STL",
+            Format::Ply => "PLY",
+            Format::Vtk => "VTK",
+            Format::Glb => "glTF",
+            Format::Msh => "Gmsh MSH",
+        })
+    }
+}
+
+impl Format {
+    pub fn of(path: &str) -> Option<Format> {
+        let extension = Path::new(path)
+            .extension()?
+            .to_string_lossy()
+            .to_lowercase();
+        match extension.as_str() {
+            "obj" => Some(Format::Obj),
+            "stl" => Some(Format::Stl),
+            "ply" => Some(Format::Ply),
+            "vtk" | "vtu" => Some(Format::Vtk),
+            "glb" => Some(Format::Glb),
+            "msh" => Some(Format::Msh),
+            _ => None,
+        }
+    }
+
+    /// Whether writing to this format keeps `content`.
+    pub fn carries(self, content: Content) -> bool {
+        match self {
+            // Comments hold whatever has no statement of its own, but
+            // nothing per vertex beyond a colour
+            Format::Obj => content != Content::VertexAttributes,
+            // The solid name is all: notes are folded into it
+            Format::Stl => matches!(content, Content::Name | Content::Notes),
+            Format::Ply => !matches!(content, Content::Texcoords | Content::Materials),
+            // Point data; a face's material is kept as its index, but not
+            // which material that is
+            Format::Vtk => matches!(content, Content::Colours | Content::VertexAttributes),
+            // Units, attributes and notes in the node's extras; the
+            // material is always our plain one
+            Format::Glb => content != Content::Materials,
+            // Nodes and elements in physical groups, and nothing else
+            Format::Msh => false,
+        }
+    }
+}
+
+/// What `mesh` has that writing it as `format` would lose.
+pub fn dropped(mesh: &Mesh, format: Format) -> Vec<Content> {
+    let metadata = &mesh.metadata;
+    [
+        (Content::Name, metadata.name.is_some()),
+        (Content::Units, metadata.units.is_some()),
+        (Content::Attributes, !metadata.attributes.is_empty()),
+        (Content::Notes, !mesh.notes.is_empty()),
+        (Content::Colours, mesh.has_colours()),
+        (Content::Texcoords, !mesh.texcoords.is_empty()),
+        (Content::Materials, !mesh.materials.is_empty()),
+        (
+            Content::VertexAttributes,
+            !mesh.vertex_attributes.is_empty(),
+        ),
+    ]
+    .into_iter()
+    .filter(|&(content, present)| present && !format.carries(content))
+    .map(|(content, _)| content)
+    .collect()
+}
+
+impl Metadata {
+    /// The `@key value` comments for what a comment-only format has no
+    /// other place for: units, then the attributes in name order.
+    pub fn comments(&self) -> Vec<String> {
+        let units = self.units.map(|u| format!("@units {}", u.symbol()));
+        let attributes = self
+            .attributes
+            .iter()
+            .map(|(key, value)| format!("@{} {}", key, value.replace(['\n', '\r'], " ")));
+        units.into_iter().chain(attributes).collect()
+    }
+
+    /// Take in a comment (without its `#` or `comment`) if it is one of
+    /// ours. Returns whether it was.
+    pub fn read_comment(&mut self, comment: &str) -> bool {
+        let Some(rest) = comment.trim().strip_prefix('@') else {
+            return false;
+        };
+        let (key, value) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        if key.is_empty() {
+            return false;
+        }
+        let value = value.trim();
+        match key {
+            "units" => match Units::parse(value) {
+                Some(units) => self.units = Some(units),
+                None => return false,
+            },
+            "name" => self.name = Some(value.to_string()),
+            _ => {
+                self.attributes.insert(key.to_string(), value.to_string());
+            }
+        }
+        true
+    }
+}
@@ -0,0 +1,280 @@
+//! Prometheus metrics for `serve`, rendered in the plain-text exposition
+//! format at `GET /metrics`.
+//!
+//! Everything is kept in one mutex-guarded struct: it is touched a handful
+//! of times per job, so contention is not a concern.
+
+use crate::pipeline::StageTimings;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+// Seconds: from a tiny cube to a big overnight scan
+const DURATION_BUCKETS: &[f64] = &[
+    0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 300.0,
+];
+// Bytes: 1 MiB to 16 GiB in steps of 4x
+const MEMORY_BUCKETS: &[f64] = &[
+    1048576.0,
+    4194304.0,
+    16777216.0,
+    67108864.0,
+    268435456.0,
+    1073741824.0,
+    4294967296.0,
+    17179869184.0,
+];
+
+struct Histogram {
+    bounds: &'static [f64],
+    /// Per bucket, not cumulative; summed up when rendering.
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, value: f64) {
+        if let Some(i) = self.bounds.iter().position(|&b| value <= b) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, labels, sep, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{}_bucket{{{}{}le=\"+Inf\"}} {}",
+            name, labels, sep, self.count
+        );
+        let braces = |labels: &str| {
+            if labels.is_empty() {
+                String::new()
+            } else {
+                format!("{{{}}}", labels)
+            }
+        };
+        let _ = writeln!(out, "{}_sum{} {}", name, braces(labels), self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, braces(labels), self.count);
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    /// Finished jobs by final status.
+    jobs: BTreeMap<&'static str, u64>,
+    /// Failed jobs by why they failed.
+    failures: BTreeMap<&'static str, u64>,
+    stages: BTreeMap<String, Histogram>,
+    job_duration: Option<Histogram>,
+    job_memory: Option<Histogram>,
+    input_triangles: u64,
+    output_triangles: u64,
+    memory_high_water: usize,
+}
+
+/// The server's counters and histograms.
+#[derive(Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+/// What one finished job contributes.
+pub struct JobMetrics<'a> {
+    /// `done`, `failed` or `cancelled`.
+    pub status: &'static str,
+    /// For failed jobs: `timeout`, `memory`, `panic` or `error`.
+    pub failure: Option<&'static str>,
+    pub seconds: f64,
+    pub timings: &'a StageTimings,
+    pub input_triangles: usize,
+    pub output_triangles: usize,
+    /// Most memory the job had allocated at once, in bytes.
+    pub peak_memory: usize,
+    /// Process-wide high-water mark at the end of the job.
+    pub process_peak: usize,
+}
+
+impl Metrics {
+    pub fn record_job(&self, job: JobMetrics) {
+        let mut inner = self.inner.lock().unwrap();
+        *inner.jobs.entry(job.status).or_default() += 1;
+        if let Some(reason) = job.failure {
+            *inner.failures.entry(reason).or_default() += 1;
+        }
+        for (stage, seconds) in &job.timings.stages {
+            inner
+                .stages
+                .entry(stage.clone())
+                .or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+                .observe(*seconds);
+        }
+        inner
+            .job_duration
+            .get_or_insert_with(|| Histogram::new(DURATION_BUCKETS))
+            .observe(job.seconds);
+        inner
+            .job_memory
+            .get_or_insert_with(|| Histogram::new(MEMORY_BUCKETS))
+            .observe(job.peak_memory as f64);
+        inner.input_triangles += job.input_triangles as u64;
+        inner.output_triangles += job.output_triangles as u64;
+        inner.memory_high_water = inner.memory_high_water.max(job.process_peak);
+    }
+
+    /// A job cancelled before it ever ran.
+    pub fn record_cancelled(&self) {
+        *self
+            .inner
+            .lock()
+            .unwrap()
+            .jobs
+            .entry("cancelled")
+            .or_default() += 1;
+    }
+
+    /// Everything, in Prometheus text format. The queue gauges and current
+    /// allocation are passed in since they live elsewhere.
+    pub fn render(&self, queued: usize, running: usize, allocated: usize) -> String {
+        let inner = self.inner.lock().unwrap();
+        let mut out = String::new();
+
+        header(
+            &mut out,
+            "mesh_jobs_processed_total",
+            "counter",
+            "Jobs finished, by final status.",
+        );
+        for status in ["done", "failed", "cancelled"] {
+            let n = inner.jobs.get(status).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "mesh_jobs_processed_total{{status=\"{}\"}} {}",
+                status, n
+            );
+        }
+
+        header(
+            &mut out,
+            "mesh_job_failures_total",
+            "counter",
+            "Failed jobs, by cause.",
+        );
+        for reason in ["timeout", "memory", "panic", "error"] {
+            let n = inner.failures.get(reason).copied().unwrap_or(0);
+            let _ = writeln!(
+                out,
+                "mesh_job_failures_total{{reason=\"{}\"}} {}",
+                reason, n
+            );
+        }
+
+        header(
+            &mut out,
+            "mesh_jobs_queued",
+            "gauge",
+            "Jobs waiting to run.",
+        );
+        let _ = writeln!(out, "mesh_jobs_queued {}", queued);
+        header(
+            &mut out,
+            "mesh_jobs_running",
+            "gauge",
+            "Jobs running right now.",
+        );
+        let _ = writeln!(out, "mesh_jobs_running {}", running);
+
+        header(
+            &mut out,
+            "mesh_triangles_processed_total",
+            "counter",
+            "Triangles read from inputs and written to results.",
+        );
+        let _ = writeln!(
+            out,
+            "mesh_triangles_processed_total{{direction=\"input\"}} {}",
+            inner.input_triangles
+        );
+        let _ = writeln!(
+            out,
+            "mesh_triangles_processed_total{{direction=\"output\"}} {}",
+            inner.output_triangles
+        );
+
+        header(
+            &mut out,
+            "mesh_stage_duration_seconds",
+            "histogram",
+            "Time spent in each pipeline stage.",
+        );
+        for (stage, histogram) in &inner.stages {
+            let labels = format!("stage=\"{}\"", stage);
+            histogram.render(&mut out, "mesh_stage_duration_seconds", &labels);
+        }
+
+        header(
+            &mut out,
+            "mesh_job_duration_seconds",
+            "histogram",
+            "Wall-clock time per job.",
+        );
+        if let Some(histogram) = &inner.job_duration {
+            histogram.render(&mut out, "mesh_job_duration_seconds", "");
+        }
+
+        header(
+            &mut out,
+            "mesh_job_memory_peak_bytes",
+            "histogram",
+            "Most memory each job had allocated at once.",
+        );
+        if let Some(histogram) = &inner.job_memory {
+            histogram.render(&mut out, "mesh_job_memory_peak_bytes", "");
+        }
+
+        header(
+            &mut out,
+            "mesh_memory_high_water_bytes",
+            "gauge",
+            "Most memory the process has had allocated at once while running a job.",
+        );
+        let _ = writeln!(
+            out,
+            "mesh_memory_high_water_bytes {}",
+            inner.memory_high_water
+        );
+        header(
+            &mut out,
+            "mesh_memory_allocated_bytes",
+            "gauge",
+            "Memory allocated right now.",
+        );
+        let _ = writeln!(out, "mesh_memory_allocated_bytes {}", allocated);
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
@@ -0,0 +1,267 @@
+//! Gmsh MSH 4.1 output, for solvers that take their boundary conditions
+//! by name: CFD and FEA set-ups pick out an inlet or a fixed face by its
+//! physical group.
+//!
+//! Each group of triangles is a surface entity of its own with a physical
+//! group of the same name, and tetrahedra (from `tetmesh`) are one volume
+//! entity bounded by all of them. The groups come from `--face-labels`
+//! when there are any, a group per label, and otherwise from the mesh's
+//! connected shells, `shell_1` the one with the most faces. A volume's
+//! boundary triangles take the label of the input face nearest them.
+//!
+//! Entities are given without geometry (no points or curves), which Gmsh
+//! and the solvers that read MSH take as a discrete model.
+
+use crate::audit;
+use crate::bvh::Bvh;
+use crate::labels::FaceLabels;
+use crate::mesh::Mesh;
+use crate::output::WriteOptions;
+use crate::storage;
+use crate::tetmesh::TetMesh;
+use anyhow::{bail, Result};
+use std::io::Write;
+
+// Element types: three-node triangle, four-node tetrahedron
+const TRIANGLE: u32 = 2;
+const TETRAHEDRON: u32 = 4;
+
+/// Triangles under one name.
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub name: String,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// Write `mesh`'s faces, in physical groups by `labels` (for its faces, in
+/// its order) or else by shell. Returns the groups' names and sizes.
+pub fn save_surface(
+    mesh: &Mesh,
+    labels: Option<&FaceLabels>,
+    location: &str,
+    options: &WriteOptions,
+) -> Result<Vec<(String, usize)>> {
+    let group_of = match labels {
+        Some(labels) => {
+            if labels.faces.len() != mesh.face_count() {
+                bail!(
+                    "the face labels are for {} faces but the mesh has {}: label the mesh as written",
+                    labels.faces.len(),
+                    mesh.face_count()
+                );
+            }
+            labels.faces.clone()
+        }
+        None => shell_of(mesh),
+    };
+    // Each face's group goes through canonical ordering as its material
+    let mut tagged = Mesh {
+        positions: mesh.positions.clone(),
+        indices: mesh.indices.clone(),
+        face_materials: group_of.into_iter().map(Some).collect(),
+        ..Default::default()
+    };
+    tagged = options.ordered(&tagged).into_owned();
+    let triangles: Vec<[u32; 3]> = (0..tagged.face_count())
+        .map(|f| tagged.face(f).map(|v| v as u32))
+        .collect();
+    let group_of: Vec<u32> = tagged.face_materials.iter().flatten().copied().collect();
+    let groups = grouped(&triangles, &group_of, labels);
+    let positions: Vec<[f32; 3]> = (0..tagged.vertex_count())
+        .map(|v| tagged.vertex(v))
+        .collect();
+    write(location, &positions, &groups, None)?;
+    Ok(summary(&groups))
+}
+
+/// Write `tets`, filling `surface`, as a volume bounded by its boundary
+/// triangles: grouped by the `labels` of `surface`'s nearest faces, or
+/// else by shell of the boundary.
+pub fn save_volume(
+    tets: &TetMesh,
+    surface: &Mesh,
+    labels: Option<&FaceLabels>,
+    location: &str,
+) -> Result<Vec<(String, usize)>> {
+    let boundary = tets.boundary();
+    let group_of = match labels {
+        Some(labels) => {
+            if labels.faces.len() != surface.face_count() {
+                bail!(
+                    "the face labels are for {} faces but the surface has {}",
+                    labels.faces.len(),
+                    surface.face_count()
+                );
+            }
+            let bvh = Bvh::new(surface);
+            boundary
+                .iter()
+                .map(|triangle| {
+                    let corners = triangle.map(|v| tets.positions[v as usize]);
+                    let centre =
+                        [0, 1, 2].map(|k| (corners[0][k] + corners[1][k] + corners[2][k]) / 3.0);
+                    bvh.nearest(centre, f32::INFINITY)
+                        .map_or(0, |(face, _, _)| labels.faces[face])
+                })
+                .collect()
+        }
+        None => {
+            let skin = Mesh {
+                positions: tets.positions.concat(),
+                indices: boundary.concat(),
+                ..Default::default()
+            };
+            shell_of(&skin)
+        }
+    };
+    let groups = grouped(&boundary, &group_of, labels);
+    let name = surface.metadata.name.as_deref().unwrap_or("solid");
+    write(location, &tets.positions, &groups, Some((name, &tets.tets)))?;
+    let mut sizes = summary(&groups);
+    sizes.push((name.to_string(), tets.tets.len()));
+    Ok(sizes)
+}
+
+// Each face's shell, numbered from the one with the most faces
+fn shell_of(mesh: &Mesh) -> Vec<u32> {
+    let mut shells: Vec<Vec<usize>> = audit::surfaces(mesh)
+        .into_iter()
+        .map(|s| [s.even, s.odd].concat())
+        .collect();
+    shells.sort_by_key(|faces| std::cmp::Reverse(faces.len()));
+    let mut shell_of = vec![0; mesh.face_count()];
+    for (s, faces) in shells.iter().enumerate() {
+        for &f in faces {
+            shell_of[f] = s as u32;
+        }
+    }
+    shell_of
+}
+
+// The triangles by group number, named by `labels` or as shells; groups
+// with no triangles are left out
+fn grouped(triangles: &[[u32; 3]], group_of: &[u32], labels: Option<&FaceLabels>) -> Vec<Group> {
+    let count = group_of.iter().max().map_or(0, |&g| g as usize + 1);
+    let mut groups: Vec<Group> = (0..count)
+        .map(|g| Group {
+            name: match labels {
+                Some(labels) => labels.names[g].clone(),
+                None => format!("shell_{}", g + 1),
+            },
+            triangles: Vec::new(),
+        })
+        .collect();
+    for (triangle, &g) in triangles.iter().zip(group_of) {
+        groups[g as usize].triangles.push(*triangle);
+    }
+    groups.retain(|g| !g.triangles.is_empty());
+    groups
+}
+
+fn summary(groups: &[Group]) -> Vec<(String, usize)> {
+    groups
+        .iter()
+        .map(|g| (g.name.clone(), g.triangles.len()))
+        .collect()
+}
+
+/// Write `positions` with `surfaces` as surface entities and physical
+/// groups 1, 2, ..., and `volume`'s tetrahedra (if any) as volume 1, the
+/// physical group after them.
+pub fn write(
+    location: &str,
+    positions: &[[f32; 3]],
+    surfaces: &[Group],
+    volume: Option<(&str, &[[u32; 4]])>,
+) -> Result<()> {
+    let mut out = storage::create(location)?;
+    writeln!(out, "$MeshFormat\n4.1 0 8\n$EndMeshFormat")?;
+
+    // 1. Physical names: the surfaces, then the volume
+    let quote = |name: &str| name.replace('"', "'");
+    let physical = surfaces.len() + usize::from(volume.is_some());
+    writeln!(out, "$PhysicalNames\n{}", physical)?;
+    for (i, group) in surfaces.iter().enumerate() {
+        writeln!(out, "2 {} \"{}\"", i + 1, quote(&group.name))?;
+    }
+    if let Some((name, _)) = volume {
+        writeln!(out, "3 {} \"{}\"", surfaces.len() + 1, quote(name))?;
+    }
+    writeln!(out, "$EndPhysicalNames")?;
+
+    // 2. Entities, each with its bounding box and physical group
+    let bounds = |corners: &mut dyn Iterator<Item = u32>| {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for v in corners {
+            let p = positions[v as usize];
+            for k in 0..3 {
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+        }
+        format!(
+            "{} {} {} {} {} {}",
+            min[0], min[1], min[2], max[0], max[1], max[2]
+        )
+    };
+    writeln!(
+        out,
+        "$Entities\n0 0 {} {}",
+        surfaces.len(),
+        usize::from(volume.is_some())
+    )?;
+    for (i, group) in surfaces.iter().enumerate() {
+        let corners = &mut group.triangles.iter().flatten().copied();
+        writeln!(out, "{} {} 1 {} 0", i + 1, bounds(corners), i + 1)?;
+    }
+    if let Some((_, tets)) = volume {
+        let corners = &mut tets.iter().flatten().copied();
+        let bounding: Vec<String> = (1..=surfaces.len()).map(|s| s.to_string()).collect();
+        writeln!(
+            out,
+            "1 {} 1 {} {} {}",
+            bounds(corners),
+            surfaces.len() + 1,
+            surfaces.len(),
+            bounding.join(" ")
+        )?;
+    }
+    writeln!(out, "$EndEntities")?;
+
+    // 3. The nodes, all in one block on the highest entity
+    let nodes = positions.len();
+    let (dimension, tag) = if volume.is_some() { (3, 1) } else { (2, 1) };
+    writeln!(out, "$Nodes\n1 {} 1 {}", nodes, nodes)?;
+    writeln!(out, "{} {} 0 {}", dimension, tag, nodes)?;
+    for node in 1..=nodes {
+        writeln!(out, "{}", node)?;
+    }
+    for p in positions {
+        writeln!(out, "{} {} {}", p[0], p[1], p[2])?;
+    }
+    writeln!(out, "$EndNodes")?;
+
+    // 4. The elements, a block per entity, numbered on from block to block
+    let tets = volume.map_or(&[][..], |(_, tets)| tets);
+    let elements = surfaces.iter().map(|g| g.triangles.len()).sum::<usize>() + tets.len();
+    let blocks = surfaces.len() + usize::from(volume.is_some());
+    writeln!(out, "$Elements\n{} {} 1 {}", blocks, elements, elements)?;
+    let mut next = 1;
+    for (i, group) in surfaces.iter().enumerate() {
+        writeln!(out, "2 {} {} {}", i + 1, TRIANGLE, group.triangles.len())?;
+        for [a, b, c] in &group.triangles {
+            writeln!(out, "{} {} {} {}", next, a + 1, b + 1, c + 1)?;
+            next += 1;
+        }
+    }
+    if volume.is_some() {
+        writeln!(out, "3 1 {} {}", TETRAHEDRON, tets.len())?;
+        for [a, b, c, d] in tets {
+            writeln!(out, "{} {} {} {} {}", next, a + 1, b + 1, c + 1, d + 1)?;
+            next += 1;
+        }
+    }
+    writeln!(out, "$EndElements")?;
+    out.finish()
+}
@@ -0,0 +1,315 @@
+//! Coarse-to-fine sampling: a field sampled at full resolution only near
+//! its surface.
+//!
+//! The surface of a fine grid passes through a sliver of its cells, so
+//! sampling every point spends nearly all its time on empty space and
+//! solid interior. Here the field is first sampled on a grid `2^levels`
+//! times coarser. The cells the surface might cross are split in eight and
+//! their new corners sampled, and so on down to the full resolution. Every
+//! other cell is settled at the coarsest level that shows it, and only the
+//! cells along the surface reach the fine grid, where marching cubes
+//! (`extract::marching_cubes_sparse`) visits just those. Each level's new
+//! corners are sampled together, split across the threads.
+//!
+//! A cell the surface might cross is one whose corners fall on both sides
+//! of the isovalue. For a signed distance field it is also any cell with a
+//! corner nearer the surface than the cell is wide, since a true distance
+//! can't change faster than the point moves. A density field has no such
+//! bound, but if its solid is made of balls (`Field::feature_radius`)
+//! wider than a cell's half-diagonal, no ball can reach into a cell
+//! without some cell the surface crosses lying within a half-diagonal
+//! (and a cell) of it. So for those, every cell that near one the surface
+//! crosses is split too, and at levels whose cells are too big for that,
+//! all of them are.
+//! A hollow inside the solid smaller than a cell can still be missed.
+
+use crate::progress;
+use crate::samples::{Samples, Storage};
+use crate::sandbox;
+use crate::sdf::{Field, FieldKind, SampledField};
+use crate::threads;
+use std::collections::{HashMap, HashSet};
+
+/// Most levels worth asking for: a coarse cell 256 fine cells across.
+pub const MAX_LEVELS: usize = 8;
+
+/// A field sampled coarse to fine.
+#[derive(Debug, Clone)]
+pub struct SparseField {
+    /// Of the full-resolution grid.
+    pub dims: [usize; 3],
+    pub origin: [f32; 3],
+    pub spacing: [f32; 3],
+    pub kind: FieldKind,
+    /// The isovalue the refinement followed; extract at this one.
+    pub iso: f32,
+    pub levels: usize,
+    // Every grid point sampled, by its index in the full grid
+    values: HashMap<usize, f32>,
+    // Cells that were split no further: their level and their lowest
+    // corner, in fine grid points
+    settled: Vec<(usize, [usize; 3])>,
+    // The fine cells along the surface, in the order the dense walk meets them
+    cells: Vec<[usize; 3]>,
+}
+
+impl SparseField {
+    /// Sample `field` coarse to fine, from a grid `2^levels` times coarser,
+    /// following the surface at `iso`.
+    pub fn sample<F: Field + Sync>(
+        field: &F,
+        origin: [f32; 3],
+        spacing: [f32; 3],
+        kind: FieldKind,
+        iso: f32,
+        levels: usize,
+        threads: usize,
+    ) -> Self {
+        let dims = field.dimensions();
+        let levels = levels.min(MAX_LEVELS);
+        let mut sparse = SparseField {
+            dims,
+            origin,
+            spacing,
+            kind,
+            iso,
+            levels,
+            values: HashMap::new(),
+            settled: Vec::new(),
+            cells: Vec::new(),
+        };
+        if dims.iter().any(|&d| d < 2) {
+            return sparse;
+        }
+        let last = dims.map(|d| d - 1);
+
+        // 1. Every cell of the coarsest grid
+        let width = 1usize << levels;
+        let counts = last.map(|n| n.div_ceil(width));
+        let mut candidates: Vec<[usize; 3]> = Vec::new();
+        for z in 0..counts[2] {
+            for y in 0..counts[1] {
+                for x in 0..counts[0] {
+                    candidates.push([x, y, z]);
+                }
+            }
+        }
+
+        progress::start(levels + 1);
+        for level in (0..=levels).rev() {
+            let width = 1usize << level;
+            // Where (in fine grid points) a cell's corner sits
+            let at = |c: [usize; 3], d: [usize; 3]| {
+                [0, 1, 2].map(|k| ((c[k] + d[k]) * width).min(last[k]))
+            };
+
+            // 2. Sample the candidates' corners not sampled yet, across
+            // the threads, and flag those the surface might cross: all of
+            // them, for a density field whose balls could hide in a cell
+            // this size
+            let mut wanted: Vec<usize> = candidates
+                .iter()
+                .flat_map(|&cell| {
+                    (0..8).map(move |corner| at(cell, [corner & 1, (corner >> 1) & 1, corner >> 2]))
+                })
+                .map(|p| sparse.index(p))
+                .filter(|i| !sparse.values.contains_key(i))
+                .collect();
+            wanted.sort_unstable();
+            wanted.dedup();
+            sparse.sample_points(field, &wanted, threads);
+            let half_diagonal = (0..3)
+                .map(|k| (width as f32 * spacing[k] * 0.5).powi(2))
+                .sum::<f32>()
+                .sqrt();
+            let blind = kind == FieldKind::Density
+                && field.feature_radius().is_none_or(|r| r <= half_diagonal);
+            // How many cells along each axis a half-diagonal spans, and one
+            // for the cell the surface crosses between
+            let reach_cells =
+                [0, 1, 2].map(|k| (half_diagonal / (width as f32 * spacing[k])) as usize + 2);
+            let mut flagged: HashSet<[usize; 3]> = HashSet::new();
+            for (n, &cell) in candidates.iter().enumerate() {
+                if n.is_multiple_of(4096) {
+                    sandbox::checkpoint();
+                }
+                let lo = at(cell, [0, 0, 0]);
+                let hi = at(cell, [1, 1, 1]);
+                let reach = (0..3)
+                    .map(|k| ((hi[k] - lo[k]) as f32 * spacing[k]).powi(2))
+                    .sum::<f32>()
+                    .sqrt();
+                let mut inside = 0;
+                let mut near = false;
+                for corner in 0..8 {
+                    let p = at(cell, [corner & 1, (corner >> 1) & 1, corner >> 2]);
+                    let value = sparse.get(p[0], p[1], p[2]);
+                    inside += usize::from(kind.is_inside(value, iso));
+                    near |= kind == FieldKind::SignedDistance && (value - iso).abs() <= reach;
+                }
+                if near || (inside != 0 && inside != 8) || (blind && level > 0) {
+                    flagged.insert(cell);
+                }
+            }
+
+            // 3. At the finest level these are what marching cubes sees
+            if level == 0 {
+                candidates.retain(|c| flagged.contains(c));
+                sparse.cells = candidates;
+                break;
+            }
+
+            // 4. Split the flagged cells, and for a density field the cells
+            // near them. The rest are settled here
+            let mut split: HashSet<[usize; 3]> = flagged.clone();
+            if kind == FieldKind::Density && !blind {
+                let candidate_set: HashSet<[usize; 3]> = candidates.iter().copied().collect();
+                let [rx, ry, rz] = reach_cells;
+                for cell in &flagged {
+                    sandbox::checkpoint();
+                    for z in cell[2].saturating_sub(rz)..=cell[2] + rz {
+                        for y in cell[1].saturating_sub(ry)..=cell[1] + ry {
+                            for x in cell[0].saturating_sub(rx)..=cell[0] + rx {
+                                if candidate_set.contains(&[x, y, z]) {
+                                    split.insert([x, y, z]);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            let half = width / 2;
+            let mut children = Vec::new();
+            for &cell in &candidates {
+                if !split.contains(&cell) {
+                    sparse.settled.push((level, at(cell, [0, 0, 0])));
+                    continue;
+                }
+                for child in 0..8 {
+                    let c = [0, 1, 2].map(|k| cell[k] * 2 + ((child >> k) & 1));
+                    if (0..3).all(|k| c[k] * half < last[k]) {
+                        children.push(c);
+                    }
+                }
+            }
+            // Dense order, so the soup matches the full grid's
+            children.sort_by_key(|c| [c[2], c[1], c[0]]);
+            candidates = children;
+            progress::tick();
+        }
+        progress::tick();
+        sparse
+    }
+
+    // Index of fine grid point `p` in the full grid
+    fn index(&self, p: [usize; 3]) -> usize {
+        p[0] + self.dims[0] * (p[1] + self.dims[1] * p[2])
+    }
+
+    // Sample `field` at these fine grid points (by index, in order), a run
+    // of them on each of `threads` threads
+    fn sample_points<F: Field + Sync>(&mut self, field: &F, points: &[usize], threads: usize) {
+        let [nx, ny, _] = self.dims;
+        let run = points.len().div_ceil(threads.max(1)).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = points
+                .chunks(run)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .enumerate()
+                            .map(|(n, &i)| {
+                                if n.is_multiple_of(4096) {
+                                    sandbox::checkpoint();
+                                }
+                                field.z(i % nx, (i / nx) % ny, i / (nx * ny)) as f32
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for (chunk, values) in points.chunks(run).zip(threads::join_all(handles)) {
+                self.values.extend(chunk.iter().copied().zip(values));
+            }
+        });
+    }
+
+    /// The sample at fine grid point (x, y, z), which must be one that
+    /// was sampled (every corner of `cells` is).
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[&self.index([x, y, z])]
+    }
+
+    /// World coordinates of fine grid point (x, y, z).
+    pub fn position(&self, x: usize, y: usize, z: usize) -> [f32; 3] {
+        [
+            self.origin[0] + x as f32 * self.spacing[0],
+            self.origin[1] + y as f32 * self.spacing[1],
+            self.origin[2] + z as f32 * self.spacing[2],
+        ]
+    }
+
+    /// The fine cells along the surface, by their lowest corner.
+    pub fn cells(&self) -> &[[usize; 3]] {
+        &self.cells
+    }
+
+    /// How many grid points were sampled, of the full grid's.
+    pub fn sampled(&self) -> (usize, usize) {
+        (self.values.len(), self.dims.iter().product())
+    }
+
+    /// The full grid, as `storage`: points never sampled are interpolated
+    /// from the corners of the cell they were settled in.
+    pub fn to_dense(&self, storage: Storage) -> SampledField {
+        let [nx, ny, _] = self.dims;
+        let last = self.dims.map(|d| d.saturating_sub(1));
+        let mut values = Samples::filled(storage, self.dims.iter().product(), 0.0);
+        for &(level, lo) in &self.settled {
+            sandbox::checkpoint();
+            let width = 1usize << level;
+            let hi = [0, 1, 2].map(|k| (lo[k] + width).min(last[k]));
+            let corner = |c: usize| {
+                let p = [0, 1, 2].map(|k| if (c >> k) & 1 == 1 { hi[k] } else { lo[k] });
+                self.get(p[0], p[1], p[2])
+            };
+            let corners: [f32; 8] = std::array::from_fn(corner);
+            for z in lo[2]..=hi[2] {
+                for y in lo[1]..=hi[1] {
+                    for x in lo[0]..=hi[0] {
+                        // How far across the cell, 0 to 1 on each axis
+                        let p = [x, y, z];
+                        let t = [0, 1, 2].map(|k| {
+                            if hi[k] > lo[k] {
+                                (p[k] - lo[k]) as f32 / (hi[k] - lo[k]) as f32
+                            } else {
+                                0.0
+                            }
+                        });
+                        let value = (0..8)
+                            .map(|c| {
+                                (0..3)
+                                    .map(|k| if (c >> k) & 1 == 1 { t[k] } else { 1.0 - t[k] })
+                                    .product::<f32>()
+                                    * corners[c]
+                            })
+                            .sum();
+                        values.set(x + nx * (y + ny * z), value);
+                    }
+                }
+            }
+        }
+        for (&index, &value) in &self.values {
+            values.set(index, value);
+        }
+        SampledField {
+            dims: self.dims,
+            origin: self.origin,
+            spacing: self.spacing,
+            kind: self.kind,
+            iso: self.iso,
+            values,
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Index and vertex ordering for GPUs, done to every glTF we write.
+//!
+//! Three passes, the same ones meshoptimizer runs, in the same order:
+//!
+//! 1. vertex cache: triangles are reordered with Tom Forsyth's linear-speed
+//!    algorithm so vertices the GPU has just transformed get reused before
+//!    they fall out of its post-transform cache;
+//! 2. overdraw: the new order is cut into runs, wherever the cache had to
+//!    start over anyway and then wherever a fresh start costs little, and
+//!    the runs are sorted so the ones facing out from the middle of the
+//!    mesh draw first and hide what's behind them from the depth test;
+//! 3. vertex fetch: vertices are renumbered in the order the triangles
+//!    first use them, so reading them walks memory front to back.
+//!
+//! None of it changes what's drawn, only the order it's drawn in.
+
+use crate::gltf::Primitive;
+use crate::sandbox;
+use std::ops::Range;
+
+// The post-transform cache we optimise for, in vertices
+const CACHE_SIZE: usize = 32;
+// Forsyth's tuning: the last triangle's vertices score this, whatever
+// their place in the cache, so strips don't run backwards
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const CACHE_DECAY_POWER: f32 = 1.5;
+// Vertices with few triangles left are worth finishing off
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+// How much worse than its run's average a cut may leave the cache doing
+// (meshoptimizer's default)
+const OVERDRAW_THRESHOLD: f64 = 1.05;
+
+/// Run all three passes over `primitive`.
+pub fn optimize(primitive: &mut Primitive) {
+    let count = primitive.vertex_count();
+    let cached = vertex_cache(&primitive.indices, count);
+    primitive.indices = overdraw(&cached, &primitive.positions);
+    vertex_fetch(primitive);
+}
+
+/// Average cache misses per triangle (ACMR) drawing `indices` through the
+/// cache we optimise for: 3 is no reuse at all, about 0.6 the best a
+/// regular grid can do.
+pub fn acmr(indices: &[u32]) -> f64 {
+    if indices.len() < 3 {
+        return 0.0;
+    }
+    let mut cache = Vec::with_capacity(CACHE_SIZE + 1);
+    let misses: usize = indices
+        .iter()
+        .map(|&i| usize::from(touch(&mut cache, i)))
+        .sum();
+    misses as f64 / (indices.len() / 3) as f64
+}
+
+// Look `vertex` up in an LRU cache, moving it to the front; true on a miss
+fn touch(cache: &mut Vec<u32>, vertex: u32) -> bool {
+    let hit = cache.iter().position(|&v| v == vertex);
+    if let Some(at) = hit {
+        cache.remove(at);
+    }
+    cache.insert(0, vertex);
+    cache.truncate(CACHE_SIZE);
+    hit.is_none()
+}
+
+// How many of face `f`'s corners miss the cache
+fn face_misses(cache: &mut Vec<u32>, indices: &[u32], f: usize) -> usize {
+    indices[f * 3..f * 3 + 3]
+        .iter()
+        .filter(|&&i| touch(cache, i))
+        .count()
+}
+
+// How much we'd like to draw a face using this vertex next
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache = match cache_position {
+        None => 0.0,
+        Some(p) if p < 3 => LAST_TRIANGLE_SCORE,
+        Some(p) => {
+            let scale = 1.0 / (CACHE_SIZE - 3) as f32;
+            (1.0 - (p - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+        }
+    };
+    cache + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+// Forsyth's greedy walk: always draw the best-scoring face that touches
+// the cache, and only when there's none go looking further afield
+fn vertex_cache(indices: &[u32], vertex_count: usize) -> Vec<u32> {
+    let faces = indices.len() / 3;
+
+    // 1. Which faces use each vertex. Each vertex's list is kept with its
+    // faces still to draw at the front, `remaining` long
+    let mut remaining = vec![0usize; vertex_count];
+    for &i in indices {
+        remaining[i as usize] += 1;
+    }
+    let mut offsets = vec![0usize; vertex_count + 1];
+    for v in 0..vertex_count {
+        offsets[v + 1] = offsets[v] + remaining[v];
+    }
+    let mut filled = offsets.clone();
+    let mut adjacency = vec![0usize; indices.len()];
+    for (corner, &i) in indices.iter().enumerate() {
+        adjacency[filled[i as usize]] = corner / 3;
+        filled[i as usize] += 1;
+    }
+
+    // 2. Starting scores: nothing is cached yet
+    let mut position: Vec<Option<usize>> = vec![None; vertex_count];
+    let mut score: Vec<f32> = remaining.iter().map(|&r| vertex_score(None, r)).collect();
+    let mut face_score: Vec<f32> = indices
+        .chunks_exact(3)
+        .map(|f| f.iter().map(|&i| score[i as usize]).sum())
+        .collect();
+    let mut drawn = vec![false; faces];
+    let mut cache: Vec<u32> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut out = Vec::with_capacity(faces * 3);
+    let mut best = (0..faces).max_by(|&a, &b| face_score[a].total_cmp(&face_score[b]));
+    let mut scan = 0;
+
+    while out.len() < faces * 3 {
+        if out.len().is_multiple_of(3 * 4096) {
+            sandbox::checkpoint();
+        }
+        // 3. Draw the best face, or the next undrawn one if the cache
+        // has nothing to offer
+        let face = match best {
+            Some(f) => f,
+            None => {
+                while drawn[scan] {
+                    scan += 1;
+                }
+                scan
+            }
+        };
+        drawn[face] = true;
+        let corners = &indices[face * 3..face * 3 + 3];
+        out.extend_from_slice(corners);
+        for &i in corners {
+            let v = i as usize;
+            let live = &mut adjacency[offsets[v]..offsets[v] + remaining[v]];
+            if let Some(at) = live.iter().position(|&f| f == face) {
+                live.swap(at, remaining[v] - 1);
+            }
+            remaining[v] -= 1;
+        }
+
+        // 4. Its vertices go to the front of the cache, pushing the
+        // oldest ones out the back
+        let mut next: Vec<u32> = corners.to_vec();
+        next.extend(cache.iter().copied().filter(|v| !corners.contains(v)));
+        for &evicted in next.iter().skip(CACHE_SIZE) {
+            position[evicted as usize] = None;
+        }
+        let touched = next.clone();
+        next.truncate(CACHE_SIZE);
+        cache = next;
+        for (p, &v) in cache.iter().enumerate() {
+            position[v as usize] = Some(p);
+        }
+
+        // 5. Rescore the vertices that moved, and their faces, and pick
+        // the best of those faces for next time
+        best = None;
+        let mut best_score = f32::MIN;
+        for &v in &touched {
+            let v = v as usize;
+            let new = vertex_score(position[v], remaining[v]);
+            let delta = new - score[v];
+            score[v] = new;
+            for &f in &adjacency[offsets[v]..offsets[v] + remaining[v]] {
+                face_score[f] += delta;
+            }
+        }
+        for &v in &cache {
+            let v = v as usize;
+            for &f in &adjacency[offsets[v]..offsets[v] + remaining[v]] {
+                if face_score[f] > best_score {
+                    best_score = face_score[f];
+                    best = Some(f);
+                }
+            }
+        }
+    }
+    out
+}
+
+// Cut the cache-ordered faces into runs, then draw the runs that face out
+// from the middle of the mesh first
+fn overdraw(indices: &[u32], positions: &[[f32; 3]]) -> Vec<u32> {
+    // 1. Hard cuts, where a face missed the cache on all three corners:
+    // the walk had run out of neighbours and jumped
+    let faces = indices.len() / 3;
+    let mut hard: Vec<Range<usize>> = Vec::new();
+    let mut cache = Vec::with_capacity(CACHE_SIZE + 1);
+    let mut start = 0;
+    for f in 0..faces {
+        if face_misses(&mut cache, indices, f) == 3 && f > start {
+            hard.push(start..f);
+            start = f;
+        }
+    }
+    if faces > start {
+        hard.push(start..faces);
+    }
+
+    // 2. Soft cuts inside those: as soon as a run, drawn from a cold
+    // cache, does nearly as well as the whole hard run does, it can stand
+    // on its own
+    let mut runs: Vec<Range<usize>> = Vec::new();
+    for run in hard {
+        cache.clear();
+        let run_misses: usize = run
+            .clone()
+            .map(|f| face_misses(&mut cache, indices, f))
+            .sum();
+        let limit = OVERDRAW_THRESHOLD * run_misses as f64 / run.len() as f64;
+        cache.clear();
+        let (mut start, mut misses) = (run.start, 0);
+        for f in run.clone() {
+            misses += face_misses(&mut cache, indices, f);
+            if misses as f64 / (f + 1 - start) as f64 <= limit && f + 1 < run.end {
+                runs.push(start..f + 1);
+                cache.clear();
+                (start, misses) = (f + 1, 0);
+            }
+        }
+        runs.push(start..run.end);
+    }
+
+    // 3. Each run's area-weighted centre and normal
+    let mut centre = [0.0f32; 3];
+    let mut total_area = 0.0f32;
+    let mut summary = Vec::with_capacity(runs.len());
+    for run in &runs {
+        let mut run_centre = [0.0f32; 3];
+        let mut normal = [0.0f32; 3];
+        let mut area = 0.0f32;
+        for f in run.clone() {
+            let [a, b, c] = [0, 1, 2].map(|k| positions[indices[f * 3 + k] as usize]);
+            let n = cross(sub(b, a), sub(c, a));
+            let face_area = dot(n, n).sqrt() * 0.5;
+            for k in 0..3 {
+                normal[k] += n[k];
+                run_centre[k] += (a[k] + b[k] + c[k]) / 3.0 * face_area;
+            }
+            area += face_area;
+        }
+        for k in 0..3 {
+            centre[k] += run_centre[k];
+        }
+        total_area += area;
+        summary.push((run_centre, normal, area));
+    }
+    if total_area <= 0.0 {
+        return indices.to_vec();
+    }
+    let centre = centre.map(|x| x / total_area);
+
+    // 4. Most outward first. A run of nothing but slivers keeps its place
+    let outwardness: Vec<f32> = summary
+        .iter()
+        .map(|&(run_centre, normal, area)| {
+            let length = dot(normal, normal).sqrt();
+            if area <= 0.0 || length <= 0.0 {
+                return 0.0;
+            }
+            let from_centre = [0, 1, 2].map(|k| run_centre[k] / area - centre[k]);
+            dot(from_centre, normal) / length
+        })
+        .collect();
+    let mut order: Vec<usize> = (0..runs.len()).collect();
+    order.sort_by(|&a, &b| outwardness[b].total_cmp(&outwardness[a]));
+    order
+        .into_iter()
+        .flat_map(|r| indices[runs[r].start * 3..runs[r].end * 3].iter().copied())
+        .collect()
+}
+
+// Renumber vertices in the order the faces first use them, dropping any
+// no face uses
+fn vertex_fetch(primitive: &mut Primitive) {
+    let mut remap = vec![u32::MAX; primitive.vertex_count()];
+    let mut order = Vec::with_capacity(primitive.vertex_count());
+    for index in &mut primitive.indices {
+        let slot = &mut remap[*index as usize];
+        if *slot == u32::MAX {
+            *slot = order.len() as u32;
+            order.push(*index as usize);
+        }
+        *index = *slot;
+    }
+    fn reorder<T: Copy>(values: &mut Vec<T>, order: &[usize]) {
+        if !values.is_empty() {
+            *values = order.iter().map(|&i| values[i]).collect();
+        }
+    }
+    reorder(&mut primitive.positions, &order);
+    reorder(&mut primitive.normals, &order);
+    reorder(&mut primitive.texcoords, &order);
+    reorder(&mut primitive.tangents, &order);
+    reorder(&mut primitive.source, &order);
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
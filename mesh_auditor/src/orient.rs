@@ -0,0 +1,46 @@
+//! Turning inside-out meshes the right way out.
+//!
+//! Some exporters bake a mirrored (negative scale) transform into the
+//! vertices without reversing the faces, so the whole mesh comes out inside
+//! out: every face agrees with its neighbours, but they all point in. On a
+//! closed shell that shows up as a negative enclosed volume, and the fix is
+//! to reverse every face of that shell.
+//!
+//! Open shells have no inside to point at, and shells whose faces disagree
+//! among themselves need a real repair, so both are left alone here.
+
+use crate::audit;
+use crate::mesh::Mesh;
+
+/// What `orient_outward` did.
+#[derive(Debug, Default)]
+pub struct OrientationFix {
+    pub shells: usize,
+    /// Closed, consistently wound shells: the ones we can judge.
+    pub checked: usize,
+    pub flipped_shells: usize,
+    pub flipped_faces: usize,
+}
+
+/// Reverse every closed shell whose volume comes out negative.
+pub fn orient_outward(mesh: &mut Mesh) -> OrientationFix {
+    let surfaces = audit::surfaces(mesh);
+    let mut fix = OrientationFix {
+        shells: surfaces.len(),
+        ..Default::default()
+    };
+    for surface in surfaces {
+        if !surface.closed || !surface.odd.is_empty() {
+            continue;
+        }
+        fix.checked += 1;
+        if surface.volume < 0.0 {
+            for &f in &surface.even {
+                mesh.flip_face(f);
+            }
+            fix.flipped_shells += 1;
+            fix.flipped_faces += surface.even.len();
+        }
+    }
+    fix
+}
@@ -0,0 +1,126 @@
+//! Statistical outlier removal: stray points a scanner leaves floating
+//! around the object (dust, reflections, the turntable's edge).
+//!
+//! Every vertex gets its mean distance to its `neighbours` nearest others;
+//! on the object's skin those are all about the point spacing, while a
+//! stray's are far larger. Vertices whose mean is more than `ratio`
+//! standard deviations above the average are dropped, with every face that
+//! used them. The voxel remesher grows a blob round every point it is
+//! given, so each stray left in becomes a lump in the result.
+
+use crate::kdtree::KdTree;
+use crate::mesh::Mesh;
+use crate::sandbox;
+use crate::threads;
+
+/// Neighbours per vertex when nobody says otherwise.
+pub const DEFAULT_NEIGHBOURS: usize = 8;
+
+/// When a vertex counts as an outlier.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutlierFilter {
+    /// How many nearest vertices to average the distance to.
+    pub neighbours: usize,
+    /// Standard deviations above the average mean distance to cut at:
+    /// lower removes more.
+    pub ratio: f32,
+}
+
+/// The vertices `filter` calls outliers, in order, measured on `threads`
+/// threads.
+pub fn find(mesh: &Mesh, filter: &OutlierFilter, threads: usize) -> Vec<usize> {
+    let points: Vec<[f32; 3]> = (0..mesh.vertex_count()).map(|v| mesh.vertex(v)).collect();
+    if filter.neighbours == 0 || points.len() <= filter.neighbours {
+        return Vec::new();
+    }
+
+    // 1. Each vertex's mean distance to its neighbours, a run of vertices
+    // per thread
+    let tree = KdTree::new(&points, threads);
+    let mean_distance = |p: [f32; 3]| {
+        // The nearest is the vertex itself
+        let near = tree.nearest_k(p, filter.neighbours + 1);
+        near[1..].iter().map(|&(_, d)| d.sqrt() as f64).sum::<f64>() / filter.neighbours as f64
+    };
+    let run = points.len().div_ceil(threads.max(1));
+    let mut means = Vec::with_capacity(points.len());
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = points
+            .chunks(run)
+            .map(|chunk| {
+                scope.spawn(|| {
+                    chunk
+                        .iter()
+                        .map(|&p| {
+                            sandbox::checkpoint();
+                            mean_distance(p)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        means.extend(threads::join_all(handles).into_iter().flatten());
+    });
+
+    // 2. Cut at the average plus `ratio` spreads
+    let n = means.len() as f64;
+    let average = means.iter().sum::<f64>() / n;
+    let spread = (means.iter().map(|m| (m - average).powi(2)).sum::<f64>() / n).sqrt();
+    let cut = average + filter.ratio as f64 * spread;
+    (0..means.len()).filter(|&v| means[v] > cut).collect()
+}
+
+/// Drop the outlier vertices and the faces using them; how many vertices
+/// went.
+pub fn remove(mesh: &mut Mesh, filter: &OutlierFilter, threads: usize) -> usize {
+    let outliers = find(mesh, filter, threads);
+    if outliers.is_empty() {
+        return 0;
+    }
+    let mut keep = vec![true; mesh.vertex_count()];
+    for &v in &outliers {
+        keep[v] = false;
+    }
+
+    // 1. Faces, with their UVs and materials, that only use kept vertices
+    let mut indices = Vec::with_capacity(mesh.indices.len());
+    let mut texcoords = Vec::new();
+    let mut face_materials = Vec::new();
+    for f in 0..mesh.face_count() {
+        let corners = mesh.face(f);
+        if !corners.iter().all(|&v| keep[v]) {
+            continue;
+        }
+        indices.extend(corners.map(|v| v as u32));
+        if !mesh.texcoords.is_empty() {
+            texcoords.extend_from_slice(&mesh.texcoords[f * 6..f * 6 + 6]);
+        }
+        if !mesh.face_materials.is_empty() {
+            face_materials.push(mesh.face_material(f));
+        }
+    }
+
+    // 2. The kept vertices, renumbered
+    let mut new_index = vec![0u32; mesh.vertex_count()];
+    let mut positions = Vec::with_capacity(mesh.positions.len());
+    let mut colours = Vec::new();
+    let kept: Vec<usize> = (0..mesh.vertex_count()).filter(|&v| keep[v]).collect();
+    for &v in &kept {
+        new_index[v] = (positions.len() / 3) as u32;
+        positions.extend_from_slice(&mesh.positions[v * 3..v * 3 + 3]);
+        if mesh.has_colours() {
+            colours.extend_from_slice(&mesh.colours[v * 3..v * 3 + 3]);
+        }
+    }
+    for i in &mut indices {
+        *i = new_index[*i as usize];
+    }
+
+    mesh.positions = positions;
+    mesh.colours = colours;
+    mesh.vertex_attributes = mesh.pick_vertex_attributes(&kept);
+    mesh.indices = indices;
+    mesh.texcoords = texcoords;
+    mesh.face_materials = face_materials;
+    outliers.len()
+}
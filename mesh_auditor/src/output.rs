@@ -0,0 +1,112 @@
+//! How meshes are written: the STL flavour, how text formats round their
+//! numbers, and whether outputs are put in canonical order; and writing a
+//! mesh in whichever format its file name asks for.
+//!
+//! Every writer takes a `WriteOptions` from its caller. The command line
+//! builds one from `--stl-format`, `--ascii-precision` and
+//! `--canonical-order` and hands it down to whatever it runs (batch files,
+//! the server's jobs, debug dumps); a program using the library gets
+//! ASCII STL, every digit a float has and triangles in the order they were
+//! made from `WriteOptions::default()`, whatever else is running in the
+//! same process.
+
+use crate::canonical;
+use crate::mesh::Mesh;
+use crate::metadata::Format;
+use crate::stl::{self, StlFormat};
+use crate::{gltf, msh, ply, vtk};
+use anyhow::{bail, Result};
+use std::borrow::Cow;
+use std::path::Path;
+
+/// What the writers are asked to do beyond writing the geometry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WriteOptions {
+    /// Binary or ASCII STL.
+    pub stl_format: StlFormat,
+    /// Decimal places for coordinates in text formats (ASCII STL, OBJ), or
+    /// `None` for as many as the float needs.
+    pub ascii_precision: Option<u32>,
+    /// Sort vertices and triangles first (see `canonical`).
+    pub canonical_order: bool,
+}
+
+impl WriteOptions {
+    /// `mesh` as it should be written: canonically ordered if asked for.
+    pub fn ordered<'a>(&self, mesh: &'a Mesh) -> Cow<'a, Mesh> {
+        if self.canonical_order {
+            Cow::Owned(canonical::mesh(mesh))
+        } else {
+            Cow::Borrowed(mesh)
+        }
+    }
+
+    /// The same for a flat triangle list (9 floats per triangle).
+    pub fn ordered_soup<'a>(&self, triangles: &'a [f32]) -> Cow<'a, [f32]> {
+        if self.canonical_order {
+            Cow::Owned(canonical::soup(triangles))
+        } else {
+            Cow::Borrowed(triangles)
+        }
+    }
+}
+
+/// Write `mesh` as .stl, .obj, .ply, .glb, .msh, .vtk or .vtu, by the
+/// extension of `path`.
+pub fn save(mesh: &Mesh, path: &str, options: &WriteOptions) -> Result<()> {
+    match Format::of(path) {
+        Some(Format::Stl) => stl::save_mesh_as_stl(mesh, path, options),
+        Some(Format::Obj) => mesh.save_obj(path, options),
+        Some(Format::Ply) => ply::save_mesh(mesh, path, options),
+        Some(Format::Glb) => gltf::save_mesh(mesh, path, false, options).map(|_| ()),
+        Some(Format::Msh) => msh::save_surface(mesh, None, path, options).map(|_| ()),
+        Some(Format::Vtk) => vtk::save_mesh(mesh, path, options),
+        None => bail!(
+            "don't know how to write {} (use .stl, .obj, .ply, .glb, .msh, .vtk or .vtu)",
+            path
+        ),
+    }
+}
+
+/// `path` with the frame number before its extension, padded to the width
+/// the last of `frames` needs (and at least 3 digits).
+pub fn numbered(path: &str, frame: usize, frames: usize) -> String {
+    let width = (frames - 1).to_string().len().max(3);
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{:0width$}.{}", stem, frame, extension.to_string_lossy()),
+        None => format!("{}_{:0width$}", stem, frame),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// `path` with `_suffix` before its extension.
+pub fn suffixed(path: &str, suffix: &str) -> String {
+    let path = Path::new(path);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, suffix),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_frames_and_parts_before_the_extension() {
+        assert_eq!(numbered("out/skin.obj", 7, 12), "out/skin_007.obj");
+        assert_eq!(numbered("skin", 42, 1500), "skin_0042");
+        assert_eq!(suffixed("out/part.stl", "pins"), "out/part_pins.stl");
+        assert_eq!(suffixed("part", "sockets"), "part_sockets");
+    }
+
+    #[test]
+    fn refuses_formats_it_cannot_write() {
+        let error = save(&Mesh::default(), "model.fbx", &WriteOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("don't know how to write"));
+    }
+}
@@ -0,0 +1,540 @@
+//! `patches -o part.igs`: smooth B-spline surfaces fitted to a scan's
+//! regions, for CAD to take in as surfaces rather than facets.
+//!
+//! Each large region from `segment` is fitted with a bicubic B-spline
+//! patch. The patch lies over the region's own plane (fitted as
+//! `planes` fits one) and rises off it along the normal, so its height
+//! above the plane is the one thing fitted: by least squares through the
+//! region's vertices, with a little bending energy added so the control
+//! points over gaps in the region (a rectangle seldom fits one exactly)
+//! carry on smoothly instead of going wild. With control points set at
+//! their Greville abscissae across the plane, the patch's x and y follow
+//! the plane exactly and only its height is approximate.
+//!
+//! A patch covers its region's bounding rectangle on that plane and is not
+//! trimmed to the region's outline; neighbouring patches overlap or fall
+//! short where regions meet. A region that folds back over its plane (a
+//! cylinder wall most of the way round) has no height to fit and is
+//! skipped; cut it up, or segment with a smaller angle.
+//!
+//! The patches are written as IGES 5.3 rational B-spline surfaces (entity
+//! 128), which every CAD package reads and can save again as STEP.
+
+use crate::clock::{self, UtcTime};
+use crate::mesh::Mesh;
+use crate::segment::Segmentation;
+use crate::storage;
+use crate::volumes::principal_axes;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::io::Write;
+
+/// Control points along each side of a patch when the caller doesn't say.
+pub const DEFAULT_CONTROL_POINTS: usize = 8;
+/// Fewest and most: a cubic needs four, and the fit solves for every
+/// control point at once.
+pub const CONTROL_POINTS: std::ops::RangeInclusive<usize> = 4..=24;
+const DEGREE: usize = 3;
+// A region must have at least this share of the whole surface's area
+const MIN_AREA_SHARE: f64 = 0.01;
+// Faces turned this close to edge-on to the plane, or past it...
+const EDGE_ON: f64 = 0.1;
+// ... may be this share of the region's area before it counts as folded
+const MAX_FOLDED: f64 = 0.05;
+// Bending energy's weight, against the fit's, per control point
+const SMOOTHING: f64 = 1e-3;
+
+/// A patch fitted to a region.
+#[derive(Debug, Clone, Serialize)]
+pub struct Patch {
+    pub region: usize,
+    /// Control points per side.
+    pub size: usize,
+    /// `size * size` control points, u varying fastest.
+    pub control: Vec<[f64; 3]>,
+    /// How far the region's vertices are off the patch, measured along
+    /// its plane's normal: RMS and worst.
+    pub rms_error: f64,
+    pub max_error: f64,
+    pub vertices: usize,
+}
+
+/// What fitting made of the regions.
+#[derive(Debug, Clone, Default)]
+pub struct Fitted {
+    pub patches: Vec<Patch>,
+    /// Regions too small to be worth a patch.
+    pub small: usize,
+    /// Regions that fold back over their plane, left without one.
+    pub folded: Vec<usize>,
+}
+
+/// Fit a patch of `size` x `size` control points to each large region of
+/// `segmentation`.
+pub fn fit(mesh: &Mesh, segmentation: &Segmentation, size: usize) -> Result<Fitted> {
+    if !CONTROL_POINTS.contains(&size) {
+        bail!(
+            "a patch takes {} to {} control points a side",
+            CONTROL_POINTS.start(),
+            CONTROL_POINTS.end()
+        );
+    }
+    let total: f64 = segmentation.regions.iter().map(|r| r.area).sum();
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); segmentation.regions.len()];
+    for (f, &r) in segmentation.face_regions.iter().enumerate() {
+        members[r].push(f);
+    }
+    let mut fitted = Fitted::default();
+    for (region, faces) in members.iter().enumerate() {
+        if segmentation.regions[region].area < MIN_AREA_SHARE * total {
+            fitted.small += 1;
+            continue;
+        }
+        match fit_region(mesh, segmentation, region, faces, size) {
+            Some(patch) => fitted.patches.push(patch),
+            None => fitted.folded.push(region),
+        }
+    }
+    Ok(fitted)
+}
+
+fn fit_region(
+    mesh: &Mesh,
+    segmentation: &Segmentation,
+    region: usize,
+    faces: &[usize],
+    size: usize,
+) -> Option<Patch> {
+    // 1. The region's plane, its normal the way the region faces, and the
+    // other two axes across it
+    let mut vertices: Vec<usize> = faces.iter().flat_map(|&f| mesh.face(f)).collect();
+    vertices.sort_unstable();
+    vertices.dedup();
+    let points: Vec<[f64; 3]> = vertices
+        .iter()
+        .map(|&v| mesh.vertex(v).map(f64::from))
+        .collect();
+    let n = points.len() as f64;
+    let middle = points.iter().fold([0.0; 3], |m, p| {
+        [m[0] + p[0] / n, m[1] + p[1] / n, m[2] + p[2] / n]
+    });
+    let mut spread = [[0.0f64; 3]; 3];
+    for p in &points {
+        let d = sub(*p, middle);
+        for (i, row) in spread.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x += d[i] * d[j];
+            }
+        }
+    }
+    let [across, mut along, mut normal] = principal_axes(spread);
+    if dot(normal, segmentation.regions[region].mean_normal) < 0.0 {
+        // Both, so the patch's u x v still points out
+        normal = normal.map(|x| -x);
+        along = along.map(|x| -x);
+    }
+
+    // 2. A height over the plane, or not if too much of it turns away
+    let folded: f64 = faces
+        .iter()
+        .map(|&f| {
+            let n = mesh.face_normal(f).map(f64::from);
+            if dot(n, normal) < EDGE_ON {
+                face_area(mesh, f)
+            } else {
+                0.0
+            }
+        })
+        .sum();
+    if folded > MAX_FOLDED * segmentation.regions[region].area {
+        return None;
+    }
+    let mut local: Vec<[f64; 3]> = points
+        .iter()
+        .map(|p| {
+            let d = sub(*p, middle);
+            [dot(d, across), dot(d, along), dot(d, normal)]
+        })
+        .collect();
+    // The spread's axes say nothing for a square or a disc: turn them to
+    // where the region's rectangle is smallest, so less of the patch hangs
+    // over nothing
+    let turn = (0..90)
+        .map(|degrees| f64::from(degrees).to_radians())
+        .min_by(|&a, &b| rectangle_area(&local, a).total_cmp(&rectangle_area(&local, b)))
+        .unwrap_or(0.0);
+    let (sin, cos) = turn.sin_cos();
+    for p in &mut local {
+        *p = [p[0] * cos + p[1] * sin, p[1] * cos - p[0] * sin, p[2]];
+    }
+    let (across, along) = (
+        [0, 1, 2].map(|k| across[k] * cos + along[k] * sin),
+        [0, 1, 2].map(|k| along[k] * cos - across[k] * sin),
+    );
+    let low = [0, 1].map(|k| local.iter().map(|p| p[k]).fold(f64::MAX, f64::min));
+    let high = [0, 1].map(|k| local.iter().map(|p| p[k]).fold(f64::MIN, f64::max));
+    if (0..2).any(|k| high[k] - low[k] <= 0.0) {
+        return None;
+    }
+    let uv = |p: &[f64; 3]| [0, 1].map(|k| (p[k] - low[k]) / (high[k] - low[k]));
+
+    // 3. The heights at the control points: least squares plus bending
+    let knots = knots(size);
+    let unknowns = size * size;
+    let mut normal_matrix = vec![0.0; unknowns * unknowns];
+    let mut rhs = vec![0.0; unknowns];
+    for p in &local {
+        let [u, v] = uv(p);
+        let (first_u, bu) = basis(&knots, size, u);
+        let (first_v, bv) = basis(&knots, size, v);
+        let terms: Vec<(usize, f64)> = (0..=DEGREE)
+            .flat_map(|j| {
+                (0..=DEGREE).map(move |i| ((first_v + j) * size + first_u + i, bu[i] * bv[j]))
+            })
+            .collect();
+        for &(a, wa) in &terms {
+            rhs[a] += wa * p[2];
+            for &(b, wb) in &terms {
+                normal_matrix[a * unknowns + b] += wa * wb;
+            }
+        }
+    }
+    // Second differences along both directions, as rows of a penalty
+    let weight = SMOOTHING * local.len() as f64 / unknowns as f64;
+    let index = |i: usize, j: usize| j * size + i;
+    for j in 0..size {
+        for i in 0..size {
+            let mut stencils = Vec::new();
+            if i + 2 < size {
+                stencils.push([index(i, j), index(i + 1, j), index(i + 2, j)]);
+            }
+            if j + 2 < size {
+                stencils.push([index(i, j), index(i, j + 1), index(i, j + 2)]);
+            }
+            for stencil in stencils {
+                let coefficients = [1.0, -2.0, 1.0];
+                for (a, ca) in stencil.iter().zip(coefficients) {
+                    for (b, cb) in stencil.iter().zip(coefficients) {
+                        normal_matrix[a * unknowns + b] += weight * ca * cb;
+                    }
+                }
+            }
+        }
+    }
+    let heights = cholesky_solve(normal_matrix, rhs, unknowns)?;
+
+    // 4. How close that came, and the control points in the world
+    let (mut sum_sq, mut worst) = (0.0f64, 0.0f64);
+    for p in &local {
+        let [u, v] = uv(p);
+        let (first_u, bu) = basis(&knots, size, u);
+        let (first_v, bv) = basis(&knots, size, v);
+        let mut height = 0.0;
+        for (j, wv) in bv.iter().enumerate() {
+            for (i, wu) in bu.iter().enumerate() {
+                height += wu * wv * heights[index(first_u + i, first_v + j)];
+            }
+        }
+        let error = (height - p[2]).abs();
+        sum_sq += error * error;
+        worst = worst.max(error);
+    }
+    let greville: Vec<f64> = (0..size)
+        .map(|i| knots[i + 1..=i + DEGREE].iter().sum::<f64>() / DEGREE as f64)
+        .collect();
+    let mut control = Vec::with_capacity(unknowns);
+    for j in 0..size {
+        for i in 0..size {
+            let s = low[0] + greville[i] * (high[0] - low[0]);
+            let t = low[1] + greville[j] * (high[1] - low[1]);
+            let h = heights[index(i, j)];
+            control
+                .push([0, 1, 2].map(|k| middle[k] + s * across[k] + t * along[k] + h * normal[k]));
+        }
+    }
+    Some(Patch {
+        region,
+        size,
+        control,
+        rms_error: (sum_sq / n).sqrt(),
+        max_error: worst,
+        vertices: vertices.len(),
+    })
+}
+
+/// Write `patches` as an IGES file of B-spline surfaces, in millimetres.
+pub fn write_iges(patches: &[Patch], location: &str) -> Result<()> {
+    // Fixed 80-column records: the data, then the section letter and the
+    // line's number in its section
+    let mut lines: Vec<String> = Vec::new();
+    let mut record = |data: &str, section: char, number: usize| {
+        lines.push(format!("{:<72}{}{:>7}", data, section, number));
+    };
+    record(
+        "Smooth patches fitted to a scanned mesh by mesh_lifter",
+        'S',
+        1,
+    );
+
+    // Global section: delimiters, names, precision, units (2 = mm), dates
+    let now = UtcTime::from_unix(clock::unix_now());
+    let stamp = format!(
+        "{:04}{:02}{:02}.{:02}{:02}{:02}",
+        now.year, now.month, now.day, now.hour, now.minute, now.second
+    );
+    let name = location.rsplit(['/', '\\']).next().unwrap_or(location);
+    let largest = patches
+        .iter()
+        .flat_map(|p| p.control.iter().flatten())
+        .fold(1.0f64, |m, x| m.max(x.abs()));
+    let global = [
+        hollerith(","),
+        hollerith(";"),
+        hollerith("mesh_lifter"),
+        hollerith(name),
+        hollerith("mesh_lifter"),
+        hollerith(env!("CARGO_PKG_VERSION")),
+        "32".to_string(),
+        "38".to_string(),
+        "6".to_string(),
+        "308".to_string(),
+        "15".to_string(),
+        hollerith("mesh_lifter"),
+        real(1.0),
+        "2".to_string(),
+        hollerith("MM"),
+        "1".to_string(),
+        real(1.0),
+        hollerith(&stamp),
+        real(1e-6),
+        real(largest),
+        String::new(),
+        String::new(),
+        "11".to_string(),
+        "0".to_string(),
+        hollerith(&stamp),
+    ];
+    for (n, line) in wrap(&global, 72).iter().enumerate() {
+        record(line, 'G', n + 1);
+    }
+    let (starts, globals) = (1, lines.len() - 1);
+
+    // Each patch: two directory lines, and its parameters
+    let mut directory = Vec::new();
+    let mut parameters = Vec::new();
+    for (k, patch) in patches.iter().enumerate() {
+        let entry = 2 * k + 1;
+        let knots = knots(patch.size);
+        let last = (patch.size - 1).to_string();
+        let mut values = vec![
+            "128".to_string(),
+            last.clone(),
+            last,
+            DEGREE.to_string(),
+            DEGREE.to_string(),
+            // Not closed either way, polynomial, not periodic either way
+            "0".to_string(),
+            "0".to_string(),
+            "1".to_string(),
+            "0".to_string(),
+            "0".to_string(),
+        ];
+        values.extend(knots.iter().map(|&t| real(t)));
+        values.extend(knots.iter().map(|&t| real(t)));
+        values.extend(std::iter::repeat_n(real(1.0), patch.control.len()));
+        values.extend(patch.control.iter().flatten().map(|&x| real(x)));
+        values.extend([0.0, 1.0, 0.0, 1.0].map(real));
+        let first = parameters.len() + 1;
+        for line in wrap(&values, 64) {
+            parameters.push(format!(
+                "{:<64} {:>7}P{:>7}",
+                line,
+                entry,
+                parameters.len() + 1
+            ));
+        }
+        let count = parameters.len() + 1 - first;
+        let fields = |f: [String; 9]| f.map(|x| format!("{:>8}", x)).concat();
+        directory.push(format!(
+            "{}D{:>7}",
+            fields([
+                "128".into(),
+                first.to_string(),
+                "0".into(),
+                "0".into(),
+                "0".into(),
+                "0".into(),
+                "0".into(),
+                "0".into(),
+                "00000000".into(),
+            ]),
+            entry
+        ));
+        directory.push(format!(
+            "{}D{:>7}",
+            fields([
+                "128".into(),
+                "0".into(),
+                "0".into(),
+                count.to_string(),
+                "0".into(),
+                String::new(),
+                String::new(),
+                format!("REGION{}", patch.region),
+                "0".into(),
+            ]),
+            entry + 1
+        ));
+    }
+    let terminate = format!(
+        "S{:>7}G{:>7}D{:>7}P{:>7}",
+        starts,
+        globals,
+        directory.len(),
+        parameters.len()
+    );
+    lines.extend(directory);
+    lines.extend(parameters);
+    lines.push(format!("{:<72}T{:>7}", terminate, 1));
+
+    let mut out = storage::create(location)?;
+    for line in &lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.finish()
+}
+
+// Clamped uniform knots for `size` control points: each end repeated
+// degree + 1 times
+fn knots(size: usize) -> Vec<f64> {
+    let spans = (size - DEGREE) as f64;
+    (0..size + DEGREE + 1)
+        .map(|i| (i.saturating_sub(DEGREE) as f64 / spans).min(1.0))
+        .collect()
+}
+
+// The degree + 1 basis functions not zero at `u`, and the first one's index
+// (Cox-de Boor, as in The NURBS Book, A2.2)
+fn basis(knots: &[f64], size: usize, u: f64) -> (usize, [f64; DEGREE + 1]) {
+    // The span u is in, the last one for u = 1
+    let span = (DEGREE..size)
+        .rev()
+        .find(|&i| knots[i] <= u)
+        .unwrap_or(DEGREE);
+    let mut values = [0.0; DEGREE + 1];
+    let mut left = [0.0; DEGREE + 1];
+    let mut right = [0.0; DEGREE + 1];
+    values[0] = 1.0;
+    for j in 1..=DEGREE {
+        left[j] = u - knots[span + 1 - j];
+        right[j] = knots[span + j] - u;
+        let mut saved = 0.0;
+        for r in 0..j {
+            let temp = values[r] / (right[r + 1] + left[j - r]);
+            values[r] = saved + right[r + 1] * temp;
+            saved = left[j - r] * temp;
+        }
+        values[j] = saved;
+    }
+    (span - DEGREE, values)
+}
+
+// Solve the symmetric positive definite `n` x `n` system `a x = b`; `None`
+// if it isn't positive definite
+fn cholesky_solve(mut a: Vec<f64>, mut b: Vec<f64>, n: usize) -> Option<Vec<f64>> {
+    // a = L Lᵀ, L in the lower triangle
+    for j in 0..n {
+        let mut diagonal = a[j * n + j];
+        for k in 0..j {
+            diagonal -= a[j * n + k] * a[j * n + k];
+        }
+        if diagonal <= 0.0 {
+            return None;
+        }
+        let diagonal = diagonal.sqrt();
+        a[j * n + j] = diagonal;
+        for i in j + 1..n {
+            let mut x = a[i * n + j];
+            for k in 0..j {
+                x -= a[i * n + k] * a[j * n + k];
+            }
+            a[i * n + j] = x / diagonal;
+        }
+    }
+    // Forward through L, back through Lᵀ
+    for i in 0..n {
+        for k in 0..i {
+            b[i] -= a[i * n + k] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+    for i in (0..n).rev() {
+        for k in i + 1..n {
+            b[i] -= a[k * n + i] * b[k];
+        }
+        b[i] /= a[i * n + i];
+    }
+    Some(b)
+}
+
+// An IGES string: its length, H, then the text
+fn hollerith(text: &str) -> String {
+    format!("{}H{}", text.len(), text)
+}
+
+// An IGES real, always with a point and an exponent
+fn real(x: f64) -> String {
+    format!("{:.9E}", x)
+}
+
+// Values as IGES parameter lines of at most `width` columns: comma after
+// each, a semicolon after the last, no value split across lines
+fn wrap(values: &[String], width: usize) -> Vec<String> {
+    let mut lines = vec![String::new()];
+    for (i, value) in values.iter().enumerate() {
+        let end = if i + 1 == values.len() { ';' } else { ',' };
+        let token = format!("{}{}", value, end);
+        let line = lines.last_mut().expect("there is always a line");
+        if !line.is_empty() && line.len() + token.len() > width {
+            lines.push(token);
+        } else {
+            line.push_str(&token);
+        }
+    }
+    lines
+}
+
+// Area of the rectangle round `local`'s first two coordinates, with its
+// sides turned by `angle`
+fn rectangle_area(local: &[[f64; 3]], angle: f64) -> f64 {
+    let (sin, cos) = angle.sin_cos();
+    let mut low = [f64::MAX; 2];
+    let mut high = [f64::MIN; 2];
+    for p in local {
+        let q = [p[0] * cos + p[1] * sin, p[1] * cos - p[0] * sin];
+        for k in 0..2 {
+            low[k] = low[k].min(q[k]);
+            high[k] = high[k].max(q[k]);
+        }
+    }
+    (high[0] - low[0]) * (high[1] - low[1])
+}
+
+fn face_area(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|v| mesh.vertex(v).map(f64::from));
+    let (u, v) = (sub(b, a), sub(c, a));
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    0.5 * dot(n, n).sqrt()
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
@@ -0,0 +1,20 @@
+//! Pipeline instrumentation: how long did each stage take?
+
+use std::time::Instant;
+
+/// Wall-clock time spent in each named stage, in the order they ran.
+#[derive(Debug, Clone, Default)]
+pub struct StageTimings {
+    pub stages: Vec<(String, f64)>,
+}
+
+impl StageTimings {
+    /// Run `f` as the stage called `name`, recording how long it took.
+    pub fn time<T>(&mut self, name: &str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.stages
+            .push((name.to_string(), start.elapsed().as_secs_f64()));
+        result
+    }
+}
@@ -0,0 +1,360 @@
+//! Guessing which way is up and what units a scan is in.
+//!
+//! Scans arrive without orientation metadata, so we propose one from the
+//! shape alone:
+//!
+//! * Up: most parts are scanned sitting on their biggest flat face, so if a
+//!   single plane holds a good share of the surface, its outward normal
+//!   points down. Failing that, the thinnest principal (PCA) axis is taken
+//!   as up, since things rest on their flattest side.
+//! * Scale: real parts are rarely over a few metres or under a millimetre,
+//!   so the bounding box diagonal tells millimetres from metres.
+//!
+//! `convert --auto-orient` turns up to +Z and stands the mesh on z = 0. The
+//! units stay a guess it only reports: a small part in millimetres looks
+//! just like a large one in metres, and scaling a 2 mm bracket to 2 m
+//! ruins it, so metres are only turned into millimetres when
+//! `--rescale-units` asks for it too.
+
+use crate::mesh::Mesh;
+use crate::messages;
+use crate::metadata;
+use serde::Serialize;
+use std::collections::HashMap;
+
+// A plane must hold this share of the area to be trusted as the base
+const MIN_BASE_SHARE: f64 = 0.05;
+// Normals this close (as a dot product, ~8°) count as the same direction
+const SAME_DIRECTION: f64 = 0.99;
+// Within ~15° of an axis we assume the axis was meant
+const SNAP_TO_AXIS: f64 = 0.966;
+// Diagonals under this are taken to be in metres
+const METRES_BELOW: f64 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Units {
+    Millimetres,
+    Metres,
+}
+
+/// The proposed placement of a mesh.
+#[derive(Debug, Clone, Serialize)]
+pub struct Placement {
+    /// Unit vector pointing up.
+    pub up: [f64; 3],
+    /// `up` as an axis (`+Z`, `-Y`, ...) when it is close to one.
+    pub up_axis: Option<String>,
+    /// What the guess was based on: `base` or `pca`.
+    pub method: &'static str,
+    /// Share of the surface on the base plane, for `base`.
+    pub base_share: f64,
+    pub units: Units,
+    pub diagonal: f64,
+}
+
+impl Placement {
+    /// One line for reports: `+Z (base plane, 17% of the surface)`.
+    pub fn describe_up(&self) -> String {
+        let direction = self.up_axis.clone().unwrap_or_else(|| {
+            format!("({:.3}, {:.3}, {:.3})", self.up[0], self.up[1], self.up[2])
+        });
+        match self.method {
+            "base" => messages::text(
+                "placement.base",
+                &[
+                    ("direction", &direction),
+                    ("share", &format!("{:.0}", self.base_share * 100.0)),
+                ],
+            ),
+            _ => messages::text("placement.pca", &[("direction", &direction)]),
+        }
+    }
+
+    pub fn describe_units(&self) -> String {
+        let key = match self.units {
+            Units::Millimetres => "placement.millimetres",
+            Units::Metres => "placement.metres",
+        };
+        messages::text(key, &[("diagonal", &format!("{:.3}", self.diagonal))])
+    }
+}
+
+pub fn propose(mesh: &Mesh) -> Placement {
+    let (min, max) = mesh.bounds();
+    let diagonal = (0..3)
+        .map(|a| f64::from(max[a] - min[a]).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    let units = if diagonal < METRES_BELOW {
+        Units::Metres
+    } else {
+        Units::Millimetres
+    };
+
+    let (up, method, base_share) = match largest_plane(mesh, diagonal) {
+        Some((normal, share)) if share >= MIN_BASE_SHARE => (normal.map(|x| -x), "base", share),
+        _ => (thinnest_axis(mesh), "pca", 0.0),
+    };
+    let up_axis = nearest_axis(up)
+        .filter(|&(_, dot)| dot >= SNAP_TO_AXIS)
+        .map(|(name, _)| name.to_string());
+
+    Placement {
+        up,
+        up_axis,
+        method,
+        base_share,
+        units,
+        diagonal,
+    }
+}
+
+/// Turn `up` to +Z and stand the mesh on z = 0; with `rescale`, also turn
+/// metres into millimetres if that is the guess.
+pub fn apply(mesh: &mut Mesh, placement: &Placement, rescale: bool) {
+    // Snap to the axis if it's near one, so a clean mesh stays clean
+    let up = match nearest_axis(placement.up) {
+        Some((name, dot)) if dot >= SNAP_TO_AXIS => axis_vector(name),
+        _ => placement.up,
+    };
+    let rotation = rotation_onto_z(up);
+    let rescaled = rescale && placement.units == Units::Metres;
+    let scale = if rescaled { 1000.0 } else { 1.0 };
+    for v in mesh.positions.chunks_exact_mut(3) {
+        let p = [f64::from(v[0]), f64::from(v[1]), f64::from(v[2])];
+        for (out, row) in v.iter_mut().zip(&rotation) {
+            *out = ((row[0] * p[0] + row[1] * p[1] + row[2] * p[2]) * scale) as f32;
+        }
+    }
+    let (min, _) = mesh.bounds();
+    for v in mesh.positions.chunks_exact_mut(3) {
+        v[2] -= min[2];
+    }
+    if rescaled {
+        mesh.metadata.units = Some(metadata::Units::Millimetre);
+    }
+}
+
+// The plane holding the most area: (outward normal, share of total area)
+fn largest_plane(mesh: &Mesh, diagonal: f64) -> Option<([f64; 3], f64)> {
+    // Bucket faces by rounded normal and rounded distance from the origin,
+    // so only faces on the same plane add up (a staircase doesn't count)
+    let mut buckets: HashMap<[i64; 4], (f64, [f64; 3])> = HashMap::new();
+    let mut total = 0.0;
+    let step = diagonal.max(f64::MIN_POSITIVE) * 0.01;
+    for f in 0..mesh.face_count() {
+        let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+        let n = cross(sub(b, a), sub(c, a));
+        let area = length(n) / 2.0;
+        if area <= 0.0 {
+            continue;
+        }
+        total += area;
+        let n = n.map(|x| x / (2.0 * area));
+        let offset = dot(n, a);
+        let key = [
+            (n[0] * 10.0).round() as i64,
+            (n[1] * 10.0).round() as i64,
+            (n[2] * 10.0).round() as i64,
+            (offset / step).round() as i64,
+        ];
+        let entry = buckets.entry(key).or_insert((0.0, [0.0; 3]));
+        entry.0 += area;
+        for (sum, component) in entry.1.iter_mut().zip(n) {
+            *sum += component * area;
+        }
+    }
+    // Near ties (a cube has six equal sides) go to whichever already faces
+    // down most, so a mesh that is the right way up stays that way
+    let biggest = buckets.values().map(|b| b.0).fold(0.0, f64::max);
+    let mut candidates: Vec<_> = buckets
+        .into_iter()
+        .filter(|(_, b)| b.0 >= biggest * 0.99)
+        .collect();
+    candidates.sort_by(|a, b| {
+        let down = |(key, (_, sum)): &([i64; 4], (f64, [f64; 3]))| (normalize(*sum)[2], *key);
+        down(a).partial_cmp(&down(b)).unwrap()
+    });
+    let (_, (area, sum)) = candidates.into_iter().next()?;
+    let normal = normalize(sum);
+    // Sanity: the bucket's faces really do face one way
+    (length(sum) / area >= SAME_DIRECTION).then_some((normal, area / total))
+}
+
+// Principal axis with the least spread, signed towards the positive axis
+// it is nearest
+fn thinnest_axis(mesh: &Mesh) -> [f64; 3] {
+    let count = mesh.vertex_count().max(1) as f64;
+    let mut mean = [0.0; 3];
+    for v in mesh.positions.chunks_exact(3) {
+        for k in 0..3 {
+            mean[k] += f64::from(v[k]) / count;
+        }
+    }
+    let mut covariance = [[0.0; 3]; 3];
+    for v in mesh.positions.chunks_exact(3) {
+        let d = [0, 1, 2].map(|k| f64::from(v[k]) - mean[k]);
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += d[i] * d[j] / count;
+            }
+        }
+    }
+    // Power iteration on (trace·I - C) finds C's smallest eigenvector
+    let trace = covariance[0][0] + covariance[1][1] + covariance[2][2];
+    let mut axis = [0.0, 0.0, 1.0];
+    for _ in 0..64 {
+        let next = [0, 1, 2].map(|i| {
+            trace * axis[i]
+                - (covariance[i][0] * axis[0]
+                    + covariance[i][1] * axis[1]
+                    + covariance[i][2] * axis[2])
+        });
+        if length(next) == 0.0 {
+            break;
+        }
+        axis = normalize(next);
+    }
+    let (name, _) = nearest_axis(axis).unwrap_or(("+Z", 1.0));
+    if name.starts_with('-') {
+        axis.map(|x| -x)
+    } else {
+        axis
+    }
+}
+
+fn nearest_axis(v: [f64; 3]) -> Option<(&'static str, f64)> {
+    const AXES: [&str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+    AXES.iter()
+        .map(|&name| (name, dot(v, axis_vector(name))))
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+fn axis_vector(name: &str) -> [f64; 3] {
+    let sign = if name.starts_with('-') { -1.0 } else { 1.0 };
+    match &name[1..] {
+        "X" => [sign, 0.0, 0.0],
+        "Y" => [0.0, sign, 0.0],
+        _ => [0.0, 0.0, sign],
+    }
+}
+
+// Rotation matrix (rows) taking unit vector `up` to +Z (Rodrigues)
+fn rotation_onto_z(up: [f64; 3]) -> [[f64; 3]; 3] {
+    let z = [0.0, 0.0, 1.0];
+    let c = dot(up, z);
+    if c > 1.0 - 1e-12 {
+        return [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    }
+    if c < -1.0 + 1e-12 {
+        // Upside down: half a turn about X
+        return [[1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, -1.0]];
+    }
+    let [x, y, w] = cross(up, z);
+    let k = 1.0 / (1.0 + c);
+    [
+        [c + x * x * k, x * y * k - w, x * w * k + y],
+        [y * x * k + w, c + y * y * k, y * w * k - x],
+        [w * x * k - y, w * y * k + x, c + w * w * k],
+    ]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let l = length(a);
+    if l > 0.0 {
+        a.map(|x| x / l)
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A box `size` across, lying on its side (up along +Y)
+    fn block(size: f32) -> Mesh {
+        let (w, h, d) = (size, size / 4.0, size / 2.0);
+        let positions = [
+            [0.0, 0.0, 0.0],
+            [w, 0.0, 0.0],
+            [w, h, 0.0],
+            [0.0, h, 0.0],
+            [0.0, 0.0, d],
+            [w, 0.0, d],
+            [w, h, d],
+            [0.0, h, d],
+        ];
+        let faces: [[u32; 3]; 12] = [
+            [0, 2, 1],
+            [0, 3, 2],
+            [4, 5, 6],
+            [4, 6, 7],
+            [0, 1, 5],
+            [0, 5, 4],
+            [3, 7, 6],
+            [3, 6, 2],
+            [0, 4, 7],
+            [0, 7, 3],
+            [1, 2, 6],
+            [1, 6, 5],
+        ];
+        Mesh {
+            positions: positions.concat(),
+            indices: faces.concat(),
+            ..Default::default()
+        }
+    }
+
+    fn extent(mesh: &Mesh) -> [f32; 3] {
+        let (min, max) = mesh.bounds();
+        [0, 1, 2].map(|k| max[k] - min[k])
+    }
+
+    #[test]
+    fn small_parts_keep_their_size() {
+        let mut mesh = block(2.0);
+        let proposal = propose(&mesh);
+        assert_eq!(proposal.units, Units::Metres);
+        apply(&mut mesh, &proposal, false);
+        let size = extent(&mesh);
+        assert!((size[0] - 2.0).abs() < 1e-5, "{:?}", size);
+        assert!(
+            (size[2] - 0.5).abs() < 1e-5,
+            "turned thin side up: {:?}",
+            size
+        );
+        assert_eq!(mesh.bounds().0[2], 0.0);
+        assert_eq!(mesh.metadata.units, None);
+    }
+
+    #[test]
+    fn rescaling_is_asked_for() {
+        let mut mesh = block(2.0);
+        let proposal = propose(&mesh);
+        apply(&mut mesh, &proposal, true);
+        assert!((extent(&mesh)[0] - 2000.0).abs() < 1e-2);
+        assert_eq!(mesh.metadata.units, Some(metadata::Units::Millimetre));
+    }
+}
@@ -0,0 +1,219 @@
+//! `convert --flatten-planes`: making machined faces come out flat.
+//!
+//! A milled face comes out of `segment` as one smooth region, lumpy with
+//! scan noise. Its plane is fitted by least squares through the region's
+//! vertices (the normal is the direction they spread least in), and the
+//! region is taken for a flat face when it is a fair share of the part and
+//! its vertices sit within `tolerance` of the plane (a share of the
+//! region's size).
+//!
+//! Every vertex of a flat face is then moved onto its plane, by the
+//! shortest way. One on the border of two flat faces goes onto the line
+//! they meet in, and one where three meet goes onto their corner, so the
+//! creases between machined faces come out sharp instead of rounded off by
+//! each face being flattened on its own. The faces stay as they were, so a
+//! watertight mesh stays watertight.
+
+use crate::cylinders::solve3;
+use crate::mesh::Mesh;
+use crate::segment::Segmentation;
+use crate::volumes::principal_axes;
+use serde::Serialize;
+
+/// Default largest RMS distance from the plane, as a share of the region's
+/// size (its bounding-box diagonal).
+pub const DEFAULT_TOLERANCE: f64 = 0.005;
+// A region must have at least this share of the whole surface's area
+const MIN_AREA_SHARE: f64 = 0.01;
+// A vertex moving further than this many times its faces' tolerance is
+// left to fewer planes
+const MAX_MOVE: f64 = 4.0;
+
+/// A flat face found and flattened.
+#[derive(Debug, Clone, Serialize)]
+pub struct Plane {
+    pub region: usize,
+    /// Unit normal, facing out the way the region does, and the plane's
+    /// distance from the origin along it.
+    pub normal: [f64; 3],
+    pub offset: f64,
+    pub area: f64,
+    /// How far the scanned face was from the plane, RMS.
+    pub rms_error: f64,
+    pub vertices: usize,
+    // The furthest the plane's own vertices may reasonably move
+    #[serde(skip)]
+    reach: f64,
+}
+
+/// The regions of `segmentation` that are large and flat within
+/// `tolerance` of their size.
+pub fn find(mesh: &Mesh, segmentation: &Segmentation, tolerance: f64) -> Vec<Plane> {
+    let total: f64 = segmentation.regions.iter().map(|r| r.area).sum();
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); segmentation.regions.len()];
+    for (f, &r) in segmentation.face_regions.iter().enumerate() {
+        members[r].push(f);
+    }
+    members
+        .iter()
+        .enumerate()
+        .filter(|(region, _)| segmentation.regions[*region].area >= MIN_AREA_SHARE * total)
+        .filter_map(|(region, faces)| fit(mesh, segmentation, region, faces, tolerance))
+        .collect()
+}
+
+/// Move every vertex of each flat face onto its plane, or onto the line or
+/// corner where flat faces meet; how many moved.
+pub fn snap(mesh: &mut Mesh, segmentation: &Segmentation, planes: &[Plane]) -> usize {
+    // 1. The flat faces round each vertex, biggest first (as the regions are
+    // numbered)
+    let mut plane_of = vec![None; segmentation.regions.len()];
+    for (i, plane) in planes.iter().enumerate() {
+        plane_of[plane.region] = Some(i);
+    }
+    let mut touching: Vec<Vec<usize>> = vec![Vec::new(); mesh.vertex_count()];
+    for f in 0..mesh.face_count() {
+        if let Some(i) = plane_of[segmentation.face_regions[f]] {
+            for v in mesh.face(f) {
+                if !touching[v].contains(&i) {
+                    touching[v].push(i);
+                }
+            }
+        }
+    }
+
+    // 2. Onto all of them at once where that is a sensible move, fewer
+    // where it isn't (faces meeting at too shallow an angle to pin a line)
+    let mut moved = 0;
+    for (v, around) in touching.iter_mut().enumerate() {
+        if around.is_empty() {
+            continue;
+        }
+        around.sort_by_key(|&i| planes[i].region);
+        let p = mesh.vertex(v).map(f64::from);
+        let mut k = around.len().min(3);
+        while k > 0 {
+            let chosen: Vec<&Plane> = around[..k].iter().map(|&i| &planes[i]).collect();
+            let limit = MAX_MOVE * chosen.iter().map(|c| c.reach).fold(0.0, f64::max);
+            if let Some(q) = project(p, &chosen) {
+                let d = sub(q, p);
+                if dot(d, d).sqrt() <= limit {
+                    for (i, x) in q.iter().enumerate() {
+                        mesh.positions[v * 3 + i] = *x as f32;
+                    }
+                    moved += 1;
+                    break;
+                }
+            }
+            k -= 1;
+        }
+    }
+    moved
+}
+
+fn fit(
+    mesh: &Mesh,
+    segmentation: &Segmentation,
+    region: usize,
+    faces: &[usize],
+    tolerance: f64,
+) -> Option<Plane> {
+    // 1. The plane through the middle of the vertices, square to the way
+    // they spread least
+    let mut vertices: Vec<usize> = faces.iter().flat_map(|&f| mesh.face(f)).collect();
+    vertices.sort_unstable();
+    vertices.dedup();
+    if vertices.len() < 4 {
+        return None;
+    }
+    let points: Vec<[f64; 3]> = vertices
+        .iter()
+        .map(|&v| mesh.vertex(v).map(f64::from))
+        .collect();
+    let n = points.len() as f64;
+    let middle = points.iter().fold([0.0; 3], |m, p| {
+        [m[0] + p[0] / n, m[1] + p[1] / n, m[2] + p[2] / n]
+    });
+    let mut spread = [[0.0f64; 3]; 3];
+    for p in &points {
+        let d = sub(*p, middle);
+        for i in 0..3 {
+            for j in 0..3 {
+                spread[i][j] += d[i] * d[j];
+            }
+        }
+    }
+    let mut normal = principal_axes(spread)[2];
+    let facing = segmentation.regions[region].mean_normal;
+    if dot(normal, facing) < 0.0 {
+        normal = normal.map(|x| -x);
+    }
+    let offset = dot(normal, middle);
+
+    // 2. Flat enough for its size
+    let rms = (points
+        .iter()
+        .map(|p| (dot(*p, normal) - offset).powi(2))
+        .sum::<f64>()
+        / n)
+        .sqrt();
+    let described = &segmentation.regions[region];
+    let size = (0..3)
+        .map(|k| f64::from(described.max[k] - described.min[k]).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    if size == 0.0 || rms > tolerance * size {
+        return None;
+    }
+    Some(Plane {
+        region,
+        normal,
+        offset,
+        area: described.area,
+        rms_error: rms,
+        vertices: vertices.len(),
+        reach: (tolerance * size).max(rms),
+    })
+}
+
+// The nearest point to `p` on all the planes at once (on one plane, the
+// line two meet in, the corner of three); `None` when they don't pin one
+fn project(p: [f64; 3], planes: &[&Plane]) -> Option<[f64; 3]> {
+    // q = p + Σ c_i n_i, with n_j · q = d_j for every plane j
+    let gap: Vec<f64> = planes.iter().map(|s| s.offset - dot(s.normal, p)).collect();
+    let gram = |i: usize, j: usize| dot(planes[i].normal, planes[j].normal);
+    let c: Vec<f64> = match planes.len() {
+        1 => vec![gap[0]],
+        2 => {
+            let det = 1.0 - gram(0, 1).powi(2);
+            if det < 1e-3 {
+                return None;
+            }
+            vec![
+                (gap[0] - gram(0, 1) * gap[1]) / det,
+                (gap[1] - gram(0, 1) * gap[0]) / det,
+            ]
+        }
+        3 => {
+            let a = [0, 1, 2].map(|i| [0, 1, 2].map(|j| gram(i, j)));
+            let c = solve3(a, [gap[0], gap[1], gap[2]])?;
+            c.to_vec()
+        }
+        _ => return None,
+    };
+    let mut q = p;
+    for (plane, c) in planes.iter().zip(c) {
+        for (k, x) in q.iter_mut().enumerate() {
+            *x += c * plane.normal[k];
+        }
+    }
+    Some(q)
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
@@ -0,0 +1,555 @@
+//! PLY input: the header and its elements, whatever properties they carry.
+//!
+//! A PLY file declares its elements (`vertex`, `face`, or anything else)
+//! with a count and a list of typed properties, then gives every element's
+//! values in order, as text or as little or big endian binary. Scanners and
+//! labelling tools hang their own properties off the standard ones (a
+//! `label` per face, a `confidence` per vertex), so elements are read into
+//! named columns and the caller picks out what it needs.
+//!
+//! `load_mesh` picks out a mesh: `x`, `y`, `z` and any `red`, `green`,
+//! `blue` per vertex, and `vertex_indices` per face, fanned into triangles.
+//! A scan with no faces comes out as a mesh with none, a point cloud. Any
+//! other scalar per vertex (`confidence`, `temperature`) comes along as a
+//! vertex attribute; normals and UVs don't, as they'd be stale after the
+//! first edit. Comments of the `@units mm` kind go into its metadata (see
+//! `metadata`).
+//!
+//! `save_mesh` writes the same back, always as binary: exact floats, and a
+//! fraction of the size of text.
+
+use crate::limits::InputLimits;
+use crate::mesh::{Mesh, VertexAttribute};
+use crate::output::WriteOptions;
+use crate::storage;
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+
+// Vertex properties with a meaning of their own, not taken as attributes
+const STANDARD: &[&str] = &[
+    "x",
+    "y",
+    "z",
+    "nx",
+    "ny",
+    "nz",
+    "red",
+    "green",
+    "blue",
+    "alpha",
+    "s",
+    "t",
+    "u",
+    "v",
+    "texture_u",
+    "texture_v",
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Ply {
+    pub comments: Vec<String>,
+    pub elements: Vec<Element>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Element {
+    pub name: String,
+    pub count: usize,
+    pub properties: Vec<(String, Column)>,
+}
+
+/// One property's values, element by element.
+#[derive(Debug, Clone)]
+pub enum Column {
+    Scalar(Vec<f64>),
+    /// Each element's list is `values[starts[i]..starts[i + 1]]`.
+    List {
+        starts: Vec<usize>,
+        values: Vec<f64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    Little,
+    Big,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Type {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+// A property as the header declares it
+#[derive(Debug, Clone)]
+enum Declared {
+    Scalar(Type),
+    List(Type, Type),
+}
+
+impl Ply {
+    pub fn element(&self, name: &str) -> Option<&Element> {
+        self.elements.iter().find(|e| e.name == name)
+    }
+}
+
+impl Element {
+    pub fn scalar(&self, name: &str) -> Option<&[f64]> {
+        self.properties.iter().find_map(|(n, column)| match column {
+            Column::Scalar(values) if n == name => Some(values.as_slice()),
+            _ => None,
+        })
+    }
+}
+
+/// Read a PLY file.
+pub fn load(filename: &str, limits: &InputLimits) -> Result<Ply> {
+    let mut bytes = Vec::new();
+    storage::open(filename, limits)?.read_to_end(&mut bytes)?;
+    parse(&bytes, limits).with_context(|| format!("{} is not a PLY file we can read", filename))
+}
+
+/// Read a PLY file as a mesh (see the module docs for what is read).
+pub fn load_mesh(filename: &str, limits: &InputLimits) -> Result<Mesh> {
+    let ply = load(filename, limits)?;
+    let mesh = to_mesh(&ply).with_context(|| format!("{} has no mesh we can read", filename))?;
+    // Polygons fan into more triangles than the header's face count
+    limits.check_triangles(mesh.face_count())?;
+    Ok(mesh)
+}
+
+/// Write `mesh` as a binary PLY: positions, colours as bytes if it has
+/// them, a float per vertex attribute, and its faces. Its name, units,
+/// other metadata and notes go in comments.
+pub fn save_mesh(mesh: &Mesh, location: &str, options: &WriteOptions) -> Result<()> {
+    let mesh = options.ordered(mesh);
+    let one_line = |text: &str| text.replace(['\n', '\r'], " ");
+    let mut header = String::from("ply\nformat binary_little_endian 1.0\n");
+    if let Some(name) = &mesh.metadata.name {
+        header += &format!("comment @name {}\n", one_line(name));
+    }
+    for comment in mesh.metadata.comments().iter().chain(&mesh.notes) {
+        header += &format!("comment {}\n", one_line(comment));
+    }
+    header += &format!("element vertex {}\n", mesh.vertex_count());
+    header += "property float x\nproperty float y\nproperty float z\n";
+    if mesh.has_colours() {
+        header += "property uchar red\nproperty uchar green\nproperty uchar blue\n";
+    }
+    for attribute in &mesh.vertex_attributes {
+        // A space would end the name early
+        let name = attribute.name.replace(char::is_whitespace, "_");
+        header += &format!("property float {}\n", name);
+    }
+    header += &format!("element face {}\n", mesh.face_count());
+    header += "property list uchar int vertex_indices\nend_header\n";
+
+    let mut out = storage::create(location)?;
+    out.write_all(header.as_bytes())?;
+    let mut record = Vec::new();
+    for v in 0..mesh.vertex_count() {
+        record.clear();
+        for x in mesh.vertex(v) {
+            record.extend_from_slice(&x.to_le_bytes());
+        }
+        if let Some(colour) = mesh.colour(v) {
+            record.extend(colour.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8));
+        }
+        for attribute in &mesh.vertex_attributes {
+            record.extend_from_slice(&attribute.values[v].to_le_bytes());
+        }
+        out.write_all(&record)?;
+    }
+    for f in 0..mesh.face_count() {
+        record.clear();
+        record.push(3);
+        for i in mesh.face(f) {
+            record.extend_from_slice(&(i as i32).to_le_bytes());
+        }
+        out.write_all(&record)?;
+    }
+    out.finish()
+}
+
+fn to_mesh(ply: &Ply) -> Result<Mesh> {
+    let Some(vertices) = ply.element("vertex") else {
+        bail!("no vertex element");
+    };
+    let (Some(x), Some(y), Some(z)) = (
+        vertices.scalar("x"),
+        vertices.scalar("y"),
+        vertices.scalar("z"),
+    ) else {
+        bail!("vertices without x, y and z");
+    };
+    let mut mesh = Mesh::default();
+    for comment in &ply.comments {
+        mesh.metadata.read_comment(comment);
+    }
+    mesh.positions.reserve(vertices.count * 3);
+    for v in 0..vertices.count {
+        mesh.positions.extend([x[v], y[v], z[v]].map(|c| c as f32));
+    }
+    if let (Some(r), Some(g), Some(b)) = (
+        vertices.scalar("red"),
+        vertices.scalar("green"),
+        vertices.scalar("blue"),
+    ) {
+        // Colours are bytes nearly always, but some writers give floats in
+        // 0..1; only bytes go past 1
+        let bytes = [r, g, b].iter().flat_map(|c| c.iter()).any(|&c| c > 1.0);
+        let scale = if bytes { 1.0 / 255.0 } else { 1.0 };
+        mesh.colours.reserve(vertices.count * 3);
+        for v in 0..vertices.count {
+            mesh.colours
+                .extend([r[v], g[v], b[v]].map(|c| (c * scale).clamp(0.0, 1.0) as f32));
+        }
+    }
+    for (name, column) in &vertices.properties {
+        if let Column::Scalar(values) = column {
+            if !STANDARD.contains(&name.as_str()) {
+                mesh.vertex_attributes.push(VertexAttribute {
+                    name: name.clone(),
+                    values: values.iter().map(|&x| x as f32).collect(),
+                });
+            }
+        }
+    }
+
+    let Some(faces) = ply.element("face") else {
+        return Ok(mesh);
+    };
+    let Some(Column::List { starts, values }) = faces
+        .properties
+        .iter()
+        .find(|(name, _)| name == "vertex_indices" || name == "vertex_index")
+        .map(|(_, column)| column)
+    else {
+        bail!("faces without vertex_indices");
+    };
+    for f in 0..faces.count {
+        let corners = &values[starts[f]..starts[f + 1]];
+        if let Some(&bad) = corners
+            .iter()
+            .find(|&&i| i < 0.0 || i >= vertices.count as f64)
+        {
+            bail!("face {} uses vertex {} of {}", f, bad, vertices.count);
+        }
+        // A polygon, fanned from its first corner
+        for k in 1..corners.len().saturating_sub(1) {
+            mesh.indices
+                .extend([corners[0], corners[k], corners[k + 1]].map(|i| i as u32));
+        }
+    }
+    Ok(mesh)
+}
+
+fn parse(bytes: &[u8], limits: &InputLimits) -> Result<Ply> {
+    // 1. The header, up to "end_header" and its line break
+    let Some(end) = bytes
+        .windows(10)
+        .position(|w| w == b"end_header")
+        .map(|at| at + 10)
+    else {
+        bail!("no end_header");
+    };
+    let header = std::str::from_utf8(&bytes[..end]).context("the header isn't text")?;
+    let mut body = &bytes[end..];
+    if body.starts_with(b"\r\n") {
+        body = &body[2..];
+    } else if body.starts_with(b"\n") {
+        body = &body[1..];
+    }
+    let mut lines = header.lines();
+    if lines.next().map(str::trim) != Some("ply") {
+        bail!("it doesn't start with 'ply'");
+    }
+    let mut format = None;
+    let mut ply = Ply::default();
+    let mut declared: Vec<Vec<Declared>> = Vec::new();
+    for line in lines {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            ["format", kind, _] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::Little,
+                    "binary_big_endian" => Format::Big,
+                    other => bail!("unknown format '{}'", other),
+                })
+            }
+            ["comment", ..] | ["obj_info", ..] => ply.comments.push(
+                line.trim()
+                    .split_once(' ')
+                    .map_or("", |(_, rest)| rest)
+                    .to_string(),
+            ),
+            ["element", name, count] => {
+                let count: usize = count
+                    .parse()
+                    .with_context(|| format!("bad element count '{}'", count))?;
+                if *name == "face" {
+                    limits.check_triangles(count)?;
+                }
+                ply.elements.push(Element {
+                    name: name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+                declared.push(Vec::new());
+            }
+            ["property", "list", count, item, name] => {
+                let (Some(element), Some(types)) = (ply.elements.last_mut(), declared.last_mut())
+                else {
+                    bail!("property '{}' before any element", name);
+                };
+                types.push(Declared::List(parse_type(count)?, parse_type(item)?));
+                element.properties.push((
+                    name.to_string(),
+                    Column::List {
+                        starts: vec![0],
+                        values: Vec::new(),
+                    },
+                ));
+            }
+            ["property", kind, name] => {
+                let (Some(element), Some(types)) = (ply.elements.last_mut(), declared.last_mut())
+                else {
+                    bail!("property '{}' before any element", name);
+                };
+                types.push(Declared::Scalar(parse_type(kind)?));
+                element
+                    .properties
+                    .push((name.to_string(), Column::Scalar(Vec::new())));
+            }
+            ["end_header"] | [] => {}
+            _ => bail!("can't make sense of header line '{}'", line.trim()),
+        }
+    }
+    let Some(format) = format else {
+        bail!("no format line");
+    };
+
+    // 2. The values, element by element
+    let mut reader = Values {
+        format,
+        body,
+        at: 0,
+        words: if format == Format::Ascii {
+            std::str::from_utf8(body)
+                .context("an ASCII PLY body isn't text")?
+                .split_ascii_whitespace()
+                .collect()
+        } else {
+            Vec::new()
+        },
+    };
+    for (element, types) in ply.elements.iter_mut().zip(&declared) {
+        // Every record takes some of the body, so the count can't be more
+        // than what's left would hold: a header can't ask for the moon
+        let smallest: usize = types
+            .iter()
+            .map(|declared| match declared {
+                Declared::Scalar(t) | Declared::List(t, _) => reader.width(*t),
+            })
+            .sum();
+        if smallest == 0 {
+            continue;
+        }
+        if element.count > reader.left() / smallest {
+            bail!(
+                "the header promises {} '{}' records, more than the rest of the file holds",
+                element.count,
+                element.name
+            );
+        }
+        for (scalar, column) in types.iter().zip(&mut element.properties) {
+            if let (Declared::Scalar(_), Column::Scalar(values)) = (scalar, &mut column.1) {
+                values.reserve(element.count);
+            }
+        }
+        for _ in 0..element.count {
+            for (kind, (name, column)) in types.iter().zip(&mut element.properties) {
+                match (kind, column) {
+                    (Declared::Scalar(t), Column::Scalar(values)) => values.push(reader.next(*t)?),
+                    (Declared::List(count, item), Column::List { starts, values }) => {
+                        let n = reader.next(*count)?;
+                        if !(0.0..=1e6).contains(&n) {
+                            bail!("a '{}' list of {} items", name, n);
+                        }
+                        for _ in 0..n as usize {
+                            values.push(reader.next(*item)?);
+                        }
+                        starts.push(values.len());
+                    }
+                    _ => unreachable!("columns are made to match their declarations"),
+                }
+            }
+        }
+    }
+    Ok(ply)
+}
+
+impl Type {
+    // Bytes in binary
+    fn size(self) -> usize {
+        match self {
+            Type::I8 | Type::U8 => 1,
+            Type::I16 | Type::U16 => 2,
+            Type::I32 | Type::U32 | Type::F32 => 4,
+            Type::F64 => 8,
+        }
+    }
+}
+
+fn parse_type(name: &str) -> Result<Type> {
+    Ok(match name {
+        "char" | "int8" => Type::I8,
+        "uchar" | "uint8" => Type::U8,
+        "short" | "int16" => Type::I16,
+        "ushort" | "uint16" => Type::U16,
+        "int" | "int32" => Type::I32,
+        "uint" | "uint32" => Type::U32,
+        "float" | "float32" => Type::F32,
+        "double" | "float64" => Type::F64,
+        other => bail!("unknown property type '{}'", other),
+    })
+}
+
+// The body, read one value at a time
+struct Values<'a> {
+    format: Format,
+    body: &'a [u8],
+    // Next byte (binary) or word (text)
+    at: usize,
+    words: Vec<&'a str>,
+}
+
+impl Values<'_> {
+    // How much of the body a value of `kind` takes: its bytes, or a word
+    fn width(&self, kind: Type) -> usize {
+        if self.format == Format::Ascii {
+            1
+        } else {
+            kind.size()
+        }
+    }
+
+    // Bytes or words not yet read
+    fn left(&self) -> usize {
+        if self.format == Format::Ascii {
+            self.words.len() - self.at
+        } else {
+            self.body.len() - self.at
+        }
+    }
+
+    fn next(&mut self, kind: Type) -> Result<f64> {
+        if self.format == Format::Ascii {
+            let Some(word) = self.words.get(self.at) else {
+                bail!("the file ends early");
+            };
+            self.at += 1;
+            return word
+                .parse()
+                .with_context(|| format!("bad number '{}'", word));
+        }
+        let size = kind.size();
+        let Some(raw) = self.body.get(self.at..self.at + size) else {
+            bail!("the file ends early");
+        };
+        self.at += size;
+        let mut b = [0u8; 8];
+        b[..size].copy_from_slice(raw);
+        if self.format == Format::Big {
+            b[..size].reverse();
+        }
+        Ok(match kind {
+            Type::I8 => f64::from(b[0] as i8),
+            Type::U8 => f64::from(b[0]),
+            Type::I16 => f64::from(i16::from_le_bytes([b[0], b[1]])),
+            Type::U16 => f64::from(u16::from_le_bytes([b[0], b[1]])),
+            Type::I32 => f64::from(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            Type::U32 => f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            Type::F32 => f64::from(f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            Type::F64 => f64::from_le_bytes(b),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn binary(header: &str, body: &[u8]) -> Vec<u8> {
+        let mut bytes = header.as_bytes().to_vec();
+        bytes.extend_from_slice(body);
+        bytes
+    }
+
+    #[test]
+    fn huge_vertex_count_is_refused_before_allocating() {
+        let bytes = binary(
+            "ply\nformat binary_little_endian 1.0\nelement vertex 4000000000\n\
+             property float x\nproperty float y\nproperty float z\nend_header\n",
+            &[0; 24],
+        );
+        let error = parse(&bytes, &InputLimits::default()).unwrap_err();
+        assert!(error.to_string().contains("promises"), "{}", error);
+    }
+
+    #[test]
+    fn huge_ascii_count_is_refused() {
+        let text =
+            "ply\nformat ascii 1.0\nelement vertex 4000000000\nproperty float x\nend_header\n1\n";
+        assert!(parse(text.as_bytes(), &InputLimits::default()).is_err());
+    }
+
+    #[test]
+    fn truncated_body_is_an_error() {
+        let bytes = binary(
+            "ply\nformat binary_little_endian 1.0\nelement vertex 2\n\
+             property float x\nproperty float y\nproperty float z\nend_header\n",
+            &[0; 20],
+        );
+        assert!(parse(&bytes, &InputLimits::default()).is_err());
+    }
+
+    #[test]
+    fn face_out_of_range_is_an_error() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 3\nproperty float x\nproperty float y\n\
+                    property float z\nelement face 1\nproperty list uchar int vertex_indices\n\
+                    end_header\n0 0 0\n1 0 0\n0 1 0\n3 0 1 7\n";
+        let ply = parse(text.as_bytes(), &InputLimits::default()).unwrap();
+        assert!(to_mesh(&ply).is_err());
+    }
+
+    #[test]
+    fn missing_header_end_is_an_error() {
+        assert!(parse(b"ply\nformat ascii 1.0\n", &InputLimits::default()).is_err());
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mesh = Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0],
+            indices: vec![0, 1, 2],
+            ..Default::default()
+        };
+        let path = std::env::temp_dir().join(format!("ply_round_trip_{}.ply", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_mesh(&mesh, path, &WriteOptions::default()).unwrap();
+        let back = load_mesh(path, &InputLimits::default()).unwrap();
+        std::fs::remove_file(path).ok();
+        assert_eq!(back.face_count(), 1);
+        assert_eq!(back.vertex_count(), 3);
+    }
+}
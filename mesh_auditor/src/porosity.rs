@@ -0,0 +1,210 @@
+//! `analyze porosity`: the voids sealed inside a part, as a CT scan shows
+//! gas pores and shrinkage in a casting or lack of fusion in a print.
+//!
+//! The part is a sampled field (a `.mlsdf`, from a CT volume or a remesh,
+//! or a closed mesh's distance field). Every sample on the empty side of
+//! the isovalue that can be reached from the grid's border, stepping
+//! across faces between samples, is outside; every other empty sample is
+//! in a void, and the voids are the connected runs of them. Samples only
+//! touching along an edge or at a corner are not connected, so a crack
+//! one voxel wide running diagonally may be split into several voids, and
+//! a pore that reaches the surface that way counts as sealed.
+//!
+//! Volumes count samples, each standing for a voxel: porosity is the void
+//! voxels' share of the part's (solid and void together). A void's depth
+//! is the distance from its nearest sample to the nearest outside sample,
+//! to within a voxel: how much wall there is over it, for judging whether
+//! machining the surface will open it.
+//!
+//! The void mesh is the field's own surface round each void, so as smooth
+//! as the field is, turned to face out of the void, as if each were a
+//! solid of its own.
+
+use crate::extract::marching_cubes;
+use crate::kdtree::KdTree;
+use crate::sandbox;
+use crate::sdf::{FieldKind, SampledField};
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// The voids of a part, biggest first.
+#[derive(Debug, Clone, Serialize)]
+pub struct Porosity {
+    /// Of one sample's voxel.
+    pub voxel_volume: f64,
+    pub solid_volume: f64,
+    pub void_volume: f64,
+    /// Void volume as a percentage of the part's, voids included.
+    pub porosity: f64,
+    pub voids: Vec<Void>,
+    // Per sample: outside the part
+    #[serde(skip)]
+    outside: Vec<bool>,
+}
+
+/// One sealed void.
+#[derive(Debug, Clone, Serialize)]
+pub struct Void {
+    pub voxels: usize,
+    pub volume: f64,
+    /// The mean of its samples' positions.
+    pub centre: [f32; 3],
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Distance to the part's outside, to within a voxel.
+    pub depth: f32,
+}
+
+/// Find the voids in `field`, solid on `field.kind`'s side of `iso`.
+pub fn analyse(field: &SampledField, iso: f32) -> Result<Porosity> {
+    if !iso.is_finite() {
+        bail!("isovalue must be a finite number");
+    }
+    let [nx, ny, nz] = field.dims;
+    if nx < 2 || ny < 2 || nz < 2 {
+        bail!("the field is too small to have an inside");
+    }
+    let count = nx * ny * nz;
+    let solid: Vec<bool> = field
+        .values
+        .iter()
+        .map(|v| field.kind.is_inside(v, iso))
+        .collect();
+    let coordinates = |i: usize| [i % nx, (i / nx) % ny, i / (nx * ny)];
+    let neighbours = |i: usize| {
+        let [x, y, z] = coordinates(i);
+        [
+            (x > 0).then(|| i - 1),
+            (x + 1 < nx).then(|| i + 1),
+            (y > 0).then(|| i - nx),
+            (y + 1 < ny).then(|| i + nx),
+            (z > 0).then(|| i - nx * ny),
+            (z + 1 < nz).then(|| i + nx * ny),
+        ]
+        .into_iter()
+        .flatten()
+    };
+
+    // 1. Outside: the empty samples reachable from the border
+    let mut outside = vec![false; count];
+    let mut stack: Vec<usize> = (0..count)
+        .filter(|&i| {
+            let [x, y, z] = coordinates(i);
+            !solid[i] && (x == 0 || y == 0 || z == 0 || x == nx - 1 || y == ny - 1 || z == nz - 1)
+        })
+        .collect();
+    for &i in &stack {
+        outside[i] = true;
+    }
+    while let Some(i) = stack.pop() {
+        for j in neighbours(i) {
+            if !solid[j] && !outside[j] {
+                outside[j] = true;
+                stack.push(j);
+            }
+        }
+    }
+
+    // 2. The outside samples next to the part, to measure depth from
+    let skin: Vec<[f32; 3]> = (0..count)
+        .filter(|&i| outside[i] && neighbours(i).any(|j| !outside[j]))
+        .map(|i| {
+            let [x, y, z] = coordinates(i);
+            field.position(x, y, z)
+        })
+        .collect();
+    let tree = KdTree::new(&skin, 1);
+
+    // 3. The voids, a flood at a time
+    let mut seen = vec![false; count];
+    let mut voids = Vec::new();
+    let voxel_volume = field.spacing.iter().map(|&s| f64::from(s)).product::<f64>();
+    for start in 0..count {
+        if solid[start] || outside[start] || seen[start] {
+            continue;
+        }
+        sandbox::checkpoint();
+        let mut members = Vec::new();
+        seen[start] = true;
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            members.push(i);
+            for j in neighbours(i) {
+                if !solid[j] && !seen[j] {
+                    seen[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        let mut sum = [0.0f64; 3];
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        let mut depth = f32::INFINITY;
+        for &i in &members {
+            let [x, y, z] = coordinates(i);
+            let p = field.position(x, y, z);
+            for k in 0..3 {
+                sum[k] += f64::from(p[k]);
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+            // Only the void's rim can be nearest the outside
+            if neighbours(i).any(|j| solid[j]) {
+                if let Some((_, dist_sq)) = tree.nearest(p) {
+                    depth = depth.min(dist_sq.sqrt());
+                }
+            }
+        }
+        let n = members.len();
+        voids.push(Void {
+            voxels: n,
+            volume: n as f64 * voxel_volume,
+            centre: sum.map(|s| (s / n as f64) as f32),
+            min,
+            max,
+            depth,
+        });
+    }
+    voids.sort_by_key(|v| std::cmp::Reverse(v.voxels));
+
+    let solid_volume = solid.iter().filter(|&&s| s).count() as f64 * voxel_volume;
+    let void_volume = voids.iter().fold(0.0, |sum, v| sum + v.volume);
+    let part = solid_volume + void_volume;
+    Ok(Porosity {
+        voxel_volume,
+        solid_volume,
+        void_volume,
+        porosity: if part > 0.0 {
+            void_volume / part * 100.0
+        } else {
+            0.0
+        },
+        voids,
+        outside,
+    })
+}
+
+impl Porosity {
+    /// The voids' surfaces from `field` (the one analysed, at `iso`), as a
+    /// flat triangle list facing out of the voids.
+    pub fn void_mesh(&self, field: &SampledField, iso: f32) -> Vec<f32> {
+        // The outside filled in, so only the voids' walls are left to draw
+        let filled_value = match field.kind {
+            FieldKind::Density => iso + 1.0,
+            FieldKind::SignedDistance => iso - field.spacing.iter().copied().fold(0.0f32, f32::max),
+        };
+        let mut filled = field.clone();
+        for (i, &outside) in self.outside.iter().enumerate() {
+            if outside {
+                filled.values.set(i, filled_value);
+            }
+        }
+        let mut triangles = marching_cubes(&filled, iso);
+        for triangle in triangles.chunks_exact_mut(9) {
+            for k in 0..3 {
+                triangle.swap(3 + k, 6 + k);
+            }
+        }
+        triangles
+    }
+}
@@ -0,0 +1,362 @@
+//! Analytic primitive fields.
+//!
+//! These give us meshes with a known ground truth: a sphere of radius r
+//! really does have volume 4/3 π r³, so extracting one tells us how much
+//! the voxel pipeline itself loses. They are also handy calibration objects.
+//!
+//! All shapes are centred on the origin and report a signed distance
+//! (negative inside).
+
+use crate::multigrid::SparseField;
+use crate::rng::Rng;
+use crate::samples::Storage;
+use crate::sdf::{Field, FieldKind, SampledField};
+use crate::slabs::{SlabField, Slabs};
+use anyhow::{bail, Result};
+use clap::ValueEnum;
+use std::f32::consts::PI;
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Shape {
+    Sphere,
+    Box,
+    Torus,
+    Gyroid,
+    Noise,
+}
+
+/// A shape plus the parameters that size it.
+#[derive(Debug, Clone)]
+pub struct Primitive {
+    pub shape: Shape,
+    /// Overall size: sphere diameter, box edge, torus outer diameter,
+    /// edge of the cube the gyroid/noise blob is cut to.
+    pub size: f32,
+    /// Torus tube radius / gyroid wall thickness / noise amplitude, as a
+    /// fraction of `size`: under 0.25 for a torus (past that the tube
+    /// swallows the hole), under 1 for the others.
+    pub detail: f32,
+    /// Number of gyroid unit cells across the cube, at least 1.
+    pub cells: u32,
+    pub seed: u64,
+}
+
+impl Primitive {
+    /// Whether the parameters describe a shape; the error says which one
+    /// doesn't.
+    pub fn validate(&self) -> Result<()> {
+        if !self.size.is_finite() || self.size <= 0.0 {
+            bail!("size must be positive");
+        }
+        if !self.detail.is_finite() {
+            bail!("detail must be a number");
+        }
+        match self.shape {
+            Shape::Sphere | Shape::Box => {}
+            Shape::Torus if self.detail <= 0.0 || self.detail >= 0.25 => {
+                bail!("a torus's detail (its tube radius over its size) must be between 0 and 0.25")
+            }
+            Shape::Gyroid if self.detail <= 0.0 || self.detail >= 1.0 => {
+                bail!(
+                    "a gyroid's detail (its wall thickness over its size) must be between 0 and 1"
+                )
+            }
+            Shape::Noise if self.detail < 0.0 || self.detail >= 1.0 => {
+                bail!("the noise's detail (its amplitude over its size) must be from 0 to under 1")
+            }
+            Shape::Torus | Shape::Gyroid | Shape::Noise => {}
+        }
+        if self.shape == Shape::Gyroid && self.cells == 0 {
+            bail!("a gyroid needs at least one cell");
+        }
+        Ok(())
+    }
+
+    /// Signed distance from `p` to the surface.
+    pub fn distance(&self, p: [f32; 3], noise: &Perlin) -> f32 {
+        let half = self.size * 0.5;
+        match self.shape {
+            Shape::Sphere => length(p) - half,
+            Shape::Box => box_distance(p, half),
+            Shape::Torus => {
+                let (major, minor) = self.torus_radii();
+                let ring = (p[0] * p[0] + p[1] * p[1]).sqrt() - major;
+                (ring * ring + p[2] * p[2]).sqrt() - minor
+            }
+            Shape::Gyroid => {
+                // Scale so one unit cell spans size / cells
+                let k = 2.0 * PI * self.cells as f32 / self.size;
+                let (x, y, z) = (p[0] * k, p[1] * k, p[2] * k);
+                let g = x.sin() * y.cos() + y.sin() * z.cos() + z.sin() * x.cos();
+                // The gyroid function's gradient is ~1.5k at the surface,
+                // which turns it into an approximate distance
+                let sheet = g.abs() / (1.5 * k) - 0.5 * self.detail * self.size;
+                sheet.max(box_distance(p, half))
+            }
+            Shape::Noise => {
+                let freq = 3.0 / self.size;
+                let n = noise.fractal([p[0] * freq, p[1] * freq, p[2] * freq]);
+                length(p) - half * 0.8 + n * self.detail * self.size
+            }
+        }
+    }
+
+    /// The exact enclosed volume, where one is known.
+    pub fn exact_volume(&self) -> Option<f64> {
+        let half = self.size as f64 * 0.5;
+        match self.shape {
+            Shape::Sphere => Some(4.0 / 3.0 * std::f64::consts::PI * half.powi(3)),
+            Shape::Box => Some((self.size as f64).powi(3)),
+            Shape::Torus => {
+                let (major, minor) = self.torus_radii();
+                Some(2.0 * std::f64::consts::PI.powi(2) * major as f64 * (minor as f64).powi(2))
+            }
+            Shape::Gyroid | Shape::Noise => None,
+        }
+    }
+
+    fn torus_radii(&self) -> (f32, f32) {
+        let minor = self.detail * self.size;
+        (self.size * 0.5 - minor, minor)
+    }
+
+    /// Sample the shape on a cubic grid with `resolution` points per side,
+    /// leaving a little room around it so the surface closes.
+    pub fn sample(&self, resolution: usize, storage: Storage, slabs: &Slabs) -> SampledField {
+        let field = self.field(resolution);
+        SampledField::sample(
+            &field,
+            field.origin,
+            [field.step; 3],
+            FieldKind::SignedDistance,
+            0.0,
+            storage,
+            slabs,
+        )
+    }
+
+    /// The same, coarse to fine from `levels` up (see `multigrid`), on
+    /// `threads` threads.
+    pub fn sample_sparse(&self, resolution: usize, levels: usize, threads: usize) -> SparseField {
+        let field = self.field(resolution);
+        SparseField::sample(
+            &field,
+            field.origin,
+            [field.step; 3],
+            FieldKind::SignedDistance,
+            0.0,
+            levels,
+            threads,
+        )
+    }
+
+    fn field(&self, resolution: usize) -> PrimitiveField<'_> {
+        let extent = self.size * 1.2;
+        PrimitiveField {
+            primitive: self,
+            noise: Perlin::new(self.seed),
+            origin: [-extent * 0.5; 3],
+            step: extent / (resolution - 1) as f32,
+            resolution,
+        }
+    }
+}
+
+// Adapter so a primitive can be sampled like any other voxel grid
+struct PrimitiveField<'a> {
+    primitive: &'a Primitive,
+    noise: Perlin,
+    origin: [f32; 3],
+    step: f32,
+    resolution: usize,
+}
+
+impl Field for PrimitiveField<'_> {
+    fn dimensions(&self) -> [usize; 3] {
+        [self.resolution; 3]
+    }
+
+    fn z(&self, x: usize, y: usize, z: usize) -> f64 {
+        let p = [
+            self.origin[0] + x as f32 * self.step,
+            self.origin[1] + y as f32 * self.step,
+            self.origin[2] + z as f32 * self.step,
+        ];
+        self.primitive.distance(p, &self.noise) as f64
+    }
+}
+
+// A formula reads nothing but itself, so every slab can share it
+impl SlabField for PrimitiveField<'_> {
+    type Local<'a>
+        = &'a Self
+    where
+        Self: 'a;
+
+    fn local(&self, _z: Range<usize>) -> &Self {
+        self
+    }
+}
+
+fn length(p: [f32; 3]) -> f32 {
+    (p[0] * p[0] + p[1] * p[1] + p[2] * p[2]).sqrt()
+}
+
+// Signed distance to a cube with the given half edge length
+fn box_distance(p: [f32; 3], half: f32) -> f32 {
+    let q = [p[0].abs() - half, p[1].abs() - half, p[2].abs() - half];
+    let outside = length([q[0].max(0.0), q[1].max(0.0), q[2].max(0.0)]);
+    let inside = q[0].max(q[1]).max(q[2]).min(0.0);
+    outside + inside
+}
+
+/// Ken Perlin's improved gradient noise, with a seeded permutation table.
+pub struct Perlin {
+    perm: [u8; 512],
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut table: [u8; 256] = std::array::from_fn(|i| i as u8);
+        // Fisher-Yates shuffle
+        let mut rng = Rng::new(seed);
+        for i in (1..256).rev() {
+            table.swap(i, rng.below(i + 1));
+        }
+        let mut perm = [0u8; 512];
+        for (i, p) in perm.iter_mut().enumerate() {
+            *p = table[i & 255];
+        }
+        Perlin { perm }
+    }
+
+    /// Noise in roughly [-1, 1].
+    pub fn noise(&self, p: [f32; 3]) -> f32 {
+        let cell = [p[0].floor(), p[1].floor(), p[2].floor()];
+        let [xi, yi, zi] = cell.map(|c| (c as i32 & 255) as usize);
+        let [x, y, z] = [p[0] - cell[0], p[1] - cell[1], p[2] - cell[2]];
+        let [u, v, w] = [fade(x), fade(y), fade(z)];
+
+        let perm = &self.perm;
+        let a = perm[xi] as usize + yi;
+        let aa = perm[a] as usize + zi;
+        let ab = perm[a + 1] as usize + zi;
+        let b = perm[xi + 1] as usize + yi;
+        let ba = perm[b] as usize + zi;
+        let bb = perm[b + 1] as usize + zi;
+
+        lerp(
+            w,
+            lerp(
+                v,
+                lerp(u, grad(perm[aa], x, y, z), grad(perm[ba], x - 1.0, y, z)),
+                lerp(
+                    u,
+                    grad(perm[ab], x, y - 1.0, z),
+                    grad(perm[bb], x - 1.0, y - 1.0, z),
+                ),
+            ),
+            lerp(
+                v,
+                lerp(
+                    u,
+                    grad(perm[aa + 1], x, y, z - 1.0),
+                    grad(perm[ba + 1], x - 1.0, y, z - 1.0),
+                ),
+                lerp(
+                    u,
+                    grad(perm[ab + 1], x, y - 1.0, z - 1.0),
+                    grad(perm[bb + 1], x - 1.0, y - 1.0, z - 1.0),
+                ),
+            ),
+        )
+    }
+
+    /// A few octaves of noise layered together for a more organic look.
+    pub fn fractal(&self, p: [f32; 3]) -> f32 {
+        let mut total = 0.0;
+        let mut amplitude = 0.5;
+        let mut frequency = 1.0;
+        for _ in 0..4 {
+            total += amplitude * self.noise([p[0] * frequency, p[1] * frequency, p[2] * frequency]);
+            amplitude *= 0.5;
+            frequency *= 2.0;
+        }
+        total
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+    a + t * (b - a)
+}
+
+fn grad(hash: u8, x: f32, y: f32, z: f32) -> f32 {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shape(shape: Shape, detail: f32, cells: u32) -> Primitive {
+        Primitive {
+            shape,
+            size: 10.0,
+            detail,
+            cells,
+            seed: 0,
+        }
+    }
+
+    #[test]
+    fn the_defaults_are_valid() {
+        for s in [
+            Shape::Sphere,
+            Shape::Box,
+            Shape::Torus,
+            Shape::Gyroid,
+            Shape::Noise,
+        ] {
+            assert!(shape(s, 0.1, 2).validate().is_ok(), "{:?}", s);
+        }
+        assert!(shape(Shape::Torus, 0.1, 2).exact_volume().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn a_torus_tube_must_leave_a_hole() {
+        assert!(shape(Shape::Torus, 0.7, 2).validate().is_err());
+        assert!(shape(Shape::Torus, 0.25, 2).validate().is_err());
+        assert!(shape(Shape::Torus, 0.0, 2).validate().is_err());
+        assert!(shape(Shape::Torus, 0.24, 2).validate().is_ok());
+    }
+
+    #[test]
+    fn detail_must_be_a_number() {
+        for s in [Shape::Sphere, Shape::Torus, Shape::Gyroid, Shape::Noise] {
+            assert!(shape(s, f32::NAN, 2).validate().is_err(), "{:?}", s);
+            assert!(shape(s, f32::INFINITY, 2).validate().is_err(), "{:?}", s);
+        }
+        assert!(shape(Shape::Noise, -0.1, 2).validate().is_err());
+        assert!(shape(Shape::Gyroid, 1.5, 2).validate().is_err());
+    }
+
+    #[test]
+    fn a_gyroid_needs_a_cell() {
+        assert!(shape(Shape::Gyroid, 0.1, 0).validate().is_err());
+        assert!(shape(Shape::Gyroid, 0.1, 1).validate().is_ok());
+    }
+}
@@ -0,0 +1,124 @@
+//! `--profile artec-leo`: corrections for a scanner's known bias.
+//!
+//! Each of our scanners drifts in its own way, one a little large, another
+//! noisy, so the fix for each is written down once, in the profiles file
+//! (`mesh_profiles.toml`, or `--profiles` / `MESH_PROFILES`), one table per
+//! device:
+//!
+//! ```toml
+//! [artec-leo]
+//! scale = 0.9985          # multiply every coordinate by this first
+//! outlier_ratio = 2.5     # drop strays (see `outliers`)
+//! outlier_neighbours = 8
+//! resolution = 150        # the grid to remesh at
+//! ```
+//!
+//! Every entry is optional and only a default: a flag given on the command
+//! line wins. The profile's name also becomes the `--history` scanner when
+//! `--scanner` isn't given.
+
+use crate::mesh::Mesh;
+use crate::outliers::{self, OutlierFilter};
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Where profiles are read from unless told otherwise.
+pub const DEFAULT_FILE: &str = "mesh_profiles.toml";
+
+/// One scanner's corrections.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(skip)]
+    pub name: String,
+    pub scale: Option<f32>,
+    pub outlier_ratio: Option<f32>,
+    pub outlier_neighbours: Option<usize>,
+    pub resolution: Option<usize>,
+}
+
+/// What to do to a scan before remeshing it.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScanCorrections {
+    pub scale: Option<f32>,
+    pub outliers: Option<OutlierFilter>,
+}
+
+/// What the corrections did.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Corrected {
+    pub scaled: Option<f32>,
+    pub outliers_removed: usize,
+}
+
+/// Read the profile `name` from the profiles file at `path`.
+pub fn load(path: &Path, name: &str) -> Result<Profile> {
+    let text = std::fs::read_to_string(path).with_context(|| {
+        format!(
+            "could not read scanner profiles from {} (set --profiles or MESH_PROFILES)",
+            path.display()
+        )
+    })?;
+    let mut profiles: BTreeMap<String, Profile> = toml::from_str(&text)
+        .with_context(|| format!("bad scanner profiles in {}", path.display()))?;
+    let Some(mut profile) = profiles.remove(name) else {
+        let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+        bail!(
+            "no scanner profile '{}' in {} (it has: {})",
+            name,
+            path.display(),
+            if known.is_empty() {
+                "none".to_string()
+            } else {
+                known.join(", ")
+            }
+        );
+    };
+    profile.name = name.to_string();
+    Ok(profile)
+}
+
+impl ScanCorrections {
+    /// From the flags; the outlier filter is on once there is a ratio.
+    pub fn new(
+        scale: Option<f32>,
+        outlier_ratio: Option<f32>,
+        outlier_neighbours: Option<usize>,
+    ) -> Self {
+        ScanCorrections {
+            scale,
+            outliers: outlier_ratio.map(|ratio| OutlierFilter {
+                neighbours: outlier_neighbours.unwrap_or(outliers::DEFAULT_NEIGHBOURS),
+                ratio,
+            }),
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.scale.is_some_and(|s| !s.is_finite() || s <= 0.0) {
+            bail!("scale must be a positive number");
+        }
+        if self.outliers.is_some_and(|f| !f.ratio.is_finite()) {
+            bail!("outlier ratio must be a number");
+        }
+        Ok(())
+    }
+
+    /// Apply them to `mesh`: scale first, so the distances compared are
+    /// the corrected ones. Outliers are looked for on `threads` threads.
+    pub fn apply(&self, mesh: &mut Mesh, threads: usize) -> Corrected {
+        let mut corrected = Corrected::default();
+        if let Some(scale) = self.scale.filter(|&s| s != 1.0) {
+            for p in &mut mesh.positions {
+                *p *= scale;
+            }
+            corrected.scaled = Some(scale);
+        }
+        if let Some(filter) = &self.outliers {
+            corrected.outliers_removed = outliers::remove(mesh, filter, threads);
+        }
+        corrected
+    }
+}
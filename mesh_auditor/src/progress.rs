@@ -0,0 +1,31 @@
+//! How far along the current long-running loop is.
+//!
+//! The slow loops (field sampling, marching cubes) call `start` with how
+//! many steps they have and `tick` after each one; a UI thread can poll
+//! `fraction` whenever it likes. Like the sandbox limits this is process
+//! wide, which is fine while we only ever run one job at a time.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DONE: AtomicUsize = AtomicUsize::new(0);
+static TOTAL: AtomicUsize = AtomicUsize::new(0);
+
+/// A new loop of `total` steps is starting.
+pub fn start(total: usize) {
+    DONE.store(0, Ordering::Relaxed);
+    TOTAL.store(total, Ordering::Relaxed);
+}
+
+/// One more step done.
+pub fn tick() {
+    DONE.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Progress through the current loop, from 0 to 1.
+pub fn fraction() -> f64 {
+    let total = TOTAL.load(Ordering::Relaxed);
+    if total == 0 {
+        return 0.0;
+    }
+    (DONE.load(Ordering::Relaxed) as f64 / total as f64).min(1.0)
+}
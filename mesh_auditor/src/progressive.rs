@@ -0,0 +1,465 @@
+//! Progressive meshes and the `.mlpm` stream format, for showing a huge
+//! scan on the web at once and sharpening it as the rest arrives.
+//!
+//! The mesh is decimated down to a small base, every edge collapse noted
+//! (see `decimate`). The file is the base followed by the collapses undone
+//! one by one, last first: refinements, each splitting a vertex back into
+//! two and putting back the faces the collapse took away. A viewer draws
+//! the base as soon as its bytes are in and applies each refinement as it
+//! arrives; stopping after any of them leaves a sound mesh, and after the
+//! last it is the original, less any faces that had a repeated corner.
+//!
+//! Only the geometry goes in: colours, UVs, materials and vertex
+//! attributes are left out, as are the names and units.
+//!
+//! # The `.mlpm` format (version 1)
+//!
+//! All numbers are little-endian. A 28-byte header:
+//!
+//! | offset | size | type | meaning                                        |
+//! |--------|------|------|------------------------------------------------|
+//! | 0      | 4    | bytes| magic `MLPM`                                   |
+//! | 4      | 2    | u16  | format version (`1`)                           |
+//! | 6      | 2    | -    | reserved, must be zero                         |
+//! | 8      | 4    | u32  | base vertices `V`                              |
+//! | 12     | 4    | u32  | base faces `F`                                 |
+//! | 16     | 4    | u32  | refinements `R`                                |
+//! | 20     | 4    | u32  | vertices in the full mesh (`V + R`)            |
+//! | 24     | 4    | u32  | faces in the full mesh                         |
+//!
+//! then the base, `V` positions (f32 x 3) and `F` faces (u32 x 3, vertex
+//! numbers, counter-clockwise seen from outside), then the `R` refinements,
+//! each:
+//!
+//! | size   | type      | meaning                                         |
+//! |--------|-----------|-------------------------------------------------|
+//! | 4      | u32       | the vertex `k` that splits                      |
+//! | 12     | f32 x 3   | `k`'s new position                              |
+//! | 12     | f32 x 3   | the new vertex's position                       |
+//! | 2      | u16       | `m`, faces moving to the new vertex             |
+//! | 4 * m  | u32 x m   | those faces: each has its corner `k` replaced   |
+//! | 1      | u8        | `r`, faces added                                |
+//! | 12 * r | u32 x 3 r | the faces added, as in the base                 |
+//!
+//! Vertices and faces are numbered in the order they arrive: the base's
+//! first, then each refinement's new vertex (one) and its faces added
+//! after them. So to decode, keep the positions and faces in growing
+//! arrays; for each refinement, set vertex `k`'s position, push the new
+//! vertex, replace `k` by the new vertex's number in each listed face, and
+//! push the faces added. A face never lists a vertex that hasn't arrived.
+
+use crate::decimate::{self, DecimateOptions};
+use crate::limits::InputLimits;
+use crate::mesh::Mesh;
+use crate::storage;
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+
+const MAGIC: &[u8; 4] = b"MLPM";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 28;
+
+/// Faces in the base unless asked for another number.
+pub const DEFAULT_BASE_FACES: usize = 1000;
+
+/// A mesh as a base and the refinements back to the whole of it.
+#[derive(Debug, Default)]
+pub struct Progressive {
+    pub base_positions: Vec<[f32; 3]>,
+    pub base_faces: Vec<[u32; 3]>,
+    pub refinements: Vec<Refinement>,
+}
+
+/// One vertex split.
+#[derive(Debug, Clone)]
+pub struct Refinement {
+    pub vertex: u32,
+    pub moved_to: [f32; 3],
+    pub new_vertex: [f32; 3],
+    /// Faces whose corner `vertex` becomes the new vertex.
+    pub faces_moved: Vec<u32>,
+    pub faces_added: Vec<[u32; 3]>,
+}
+
+/// Decimate `mesh` to about `base_faces` faces (fewer collapses may be
+/// possible) and note the way back.
+pub fn encode(mesh: &Mesh, base_faces: usize) -> Progressive {
+    let geometry = Mesh {
+        positions: mesh.positions.clone(),
+        indices: mesh.indices.clone(),
+        ..Default::default()
+    };
+    let options = DecimateOptions {
+        target_faces: base_faces,
+        max_error: None,
+        preserve_boundary: false,
+    };
+    let (_, history) = decimate::decimate_recorded(&geometry, &options);
+
+    // 1. Play the collapses through to the base
+    let mut positions: Vec<[f32; 3]> = (0..mesh.vertex_count()).map(|v| mesh.vertex(v)).collect();
+    let mut faces = history.faces.clone();
+    let mut used = vec![false; positions.len()];
+    for face in &faces {
+        for &v in face {
+            used[v] = true;
+        }
+    }
+    let mut face_alive = vec![true; faces.len()];
+    for collapse in &history.collapses {
+        positions[collapse.kept] = collapse.kept_to;
+        for &f in &collapse.moved {
+            for corner in faces[f].iter_mut() {
+                if *corner == collapse.gone {
+                    *corner = collapse.kept;
+                }
+            }
+        }
+        for &(f, _) in &collapse.removed {
+            face_alive[f] = false;
+        }
+        used[collapse.gone] = false;
+    }
+
+    // 2. Number what's left, then what each refinement brings, in the
+    // order a decoder meets them
+    let mut vertex_id = vec![u32::MAX; positions.len()];
+    let mut progressive = Progressive::default();
+    for v in (0..positions.len()).filter(|&v| used[v]) {
+        vertex_id[v] = progressive.base_positions.len() as u32;
+        progressive.base_positions.push(positions[v]);
+    }
+    let mut face_id = vec![u32::MAX; faces.len()];
+    for f in (0..faces.len()).filter(|&f| face_alive[f]) {
+        face_id[f] = progressive.base_faces.len() as u32;
+        progressive.base_faces.push(faces[f].map(|v| vertex_id[v]));
+    }
+    let first_split = progressive.base_positions.len() as u32;
+    let mut face_count = progressive.base_faces.len() as u32;
+    for (new_vertex, collapse) in (first_split..).zip(history.collapses.iter().rev()) {
+        vertex_id[collapse.gone] = new_vertex;
+        let faces_added = collapse
+            .removed
+            .iter()
+            .map(|&(f, corners)| {
+                face_id[f] = face_count;
+                face_count += 1;
+                corners.map(|v| vertex_id[v])
+            })
+            .collect();
+        progressive.refinements.push(Refinement {
+            vertex: vertex_id[collapse.kept],
+            moved_to: collapse.kept_from,
+            new_vertex: collapse.gone_at,
+            faces_moved: collapse.moved.iter().map(|&f| face_id[f]).collect(),
+            faces_added,
+        });
+    }
+    progressive
+}
+
+impl Progressive {
+    pub fn vertex_count(&self) -> usize {
+        self.base_positions.len() + self.refinements.len()
+    }
+
+    pub fn face_count(&self) -> usize {
+        self.base_faces.len()
+            + self
+                .refinements
+                .iter()
+                .map(|r| r.faces_added.len())
+                .sum::<usize>()
+    }
+
+    /// Bytes of the header and base, what a viewer waits for before
+    /// drawing anything.
+    pub fn base_bytes(&self) -> usize {
+        HEADER_LEN + self.base_positions.len() * 12 + self.base_faces.len() * 12
+    }
+
+    pub fn save(&self, location: &str) -> Result<()> {
+        let mut bytes = Vec::with_capacity(self.base_bytes());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        for count in [
+            self.base_positions.len(),
+            self.base_faces.len(),
+            self.refinements.len(),
+            self.vertex_count(),
+            self.face_count(),
+        ] {
+            bytes.extend_from_slice(&u32::try_from(count)?.to_le_bytes());
+        }
+        let floats = |bytes: &mut Vec<u8>, p: [f32; 3]| {
+            for x in p {
+                bytes.extend_from_slice(&x.to_le_bytes());
+            }
+        };
+        let face = |bytes: &mut Vec<u8>, f: [u32; 3]| {
+            for v in f {
+                bytes.extend_from_slice(&v.to_le_bytes());
+            }
+        };
+        for &p in &self.base_positions {
+            floats(&mut bytes, p);
+        }
+        for &f in &self.base_faces {
+            face(&mut bytes, f);
+        }
+        for refinement in &self.refinements {
+            bytes.extend_from_slice(&refinement.vertex.to_le_bytes());
+            floats(&mut bytes, refinement.moved_to);
+            floats(&mut bytes, refinement.new_vertex);
+            let moved = u16::try_from(refinement.faces_moved.len())
+                .context("a vertex has too many faces round it to refine")?;
+            bytes.extend_from_slice(&moved.to_le_bytes());
+            for f in &refinement.faces_moved {
+                bytes.extend_from_slice(&f.to_le_bytes());
+            }
+            bytes.push(u8::try_from(refinement.faces_added.len())?);
+            for &f in &refinement.faces_added {
+                face(&mut bytes, f);
+            }
+        }
+        let mut out = storage::create(location)?;
+        out.write_all(&bytes)?;
+        out.finish()
+    }
+
+    pub fn load(location: &str, limits: &InputLimits) -> Result<Self> {
+        let mut bytes = Vec::new();
+        storage::open(location, limits)?.read_to_end(&mut bytes)?;
+        if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+            bail!("{} is not a progressive mesh (.mlpm)", location);
+        }
+        let version = u16::from_le_bytes([bytes[4], bytes[5]]);
+        if version != VERSION {
+            bail!(
+                "{} is .mlpm version {}, which we can't read",
+                location,
+                version
+            );
+        }
+        let mut input = Bytes {
+            bytes: &bytes,
+            at: 8,
+            location,
+        };
+        let vertices = input.u32()? as usize;
+        let faces = input.u32()? as usize;
+        let refinements = input.u32()? as usize;
+        let _full_vertices = input.u32()?;
+        limits.check_triangles(input.u32()? as usize)?;
+        // Each refinement is 31 bytes at least, so a corrupt count can't
+        // ask for the moon
+        if bytes.len() < HEADER_LEN + vertices * 12 + faces * 12 + refinements * 31 {
+            bail!("{} is cut short: the header promises more", location);
+        }
+
+        let mut progressive = Progressive::default();
+        for _ in 0..vertices {
+            progressive.base_positions.push(input.point()?);
+        }
+        for _ in 0..faces {
+            progressive.base_faces.push(input.face()?);
+        }
+        for _ in 0..refinements {
+            let vertex = input.u32()?;
+            let moved_to = input.point()?;
+            let new_vertex = input.point()?;
+            let moved = input.u16()?;
+            let faces_moved = (0..moved).map(|_| input.u32()).collect::<Result<_>>()?;
+            let added = input.u8()?;
+            let faces_added = (0..added).map(|_| input.face()).collect::<Result<_>>()?;
+            progressive.refinements.push(Refinement {
+                vertex,
+                moved_to,
+                new_vertex,
+                faces_moved,
+                faces_added,
+            });
+        }
+        Ok(progressive)
+    }
+
+    /// The mesh after the base and the first `refinements` (all of them
+    /// if `None`), decoded as a viewer would.
+    pub fn decode(&self, refinements: Option<usize>) -> Result<Mesh> {
+        let mut positions = self.base_positions.clone();
+        let mut faces = self.base_faces.clone();
+        let check = |faces: &[[u32; 3]], vertices: usize| {
+            if faces.iter().flatten().any(|&v| v as usize >= vertices) {
+                bail!("a face refers to a vertex that hasn't arrived");
+            }
+            Ok(())
+        };
+        check(&faces, positions.len())?;
+        let count = refinements.unwrap_or(self.refinements.len());
+        for refinement in self.refinements.iter().take(count) {
+            let k = refinement.vertex;
+            let new = positions.len() as u32;
+            let Some(at) = positions.get_mut(k as usize) else {
+                bail!("a refinement splits vertex {}, which hasn't arrived", k);
+            };
+            *at = refinement.moved_to;
+            positions.push(refinement.new_vertex);
+            for &f in &refinement.faces_moved {
+                let Some(face) = faces.get_mut(f as usize) else {
+                    bail!("a refinement moves face {}, which hasn't arrived", f);
+                };
+                for corner in face.iter_mut().filter(|c| **c == k) {
+                    *corner = new;
+                }
+            }
+            check(&refinement.faces_added, positions.len())?;
+            faces.extend_from_slice(&refinement.faces_added);
+        }
+        Ok(Mesh {
+            positions: positions.concat(),
+            indices: faces.concat(),
+            ..Default::default()
+        })
+    }
+}
+
+// Little-endian numbers off the front of a file
+struct Bytes<'a> {
+    bytes: &'a [u8],
+    at: usize,
+    location: &'a str,
+}
+
+impl Bytes<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let Some(slice) = self.bytes.get(self.at..self.at + N) else {
+            bail!("{} is cut short", self.location);
+        };
+        self.at += N;
+        Ok(slice.try_into().expect("N bytes"))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn point(&mut self) -> Result<[f32; 3]> {
+        let mut p = [0.0; 3];
+        for x in &mut p {
+            *x = f32::from_le_bytes(self.take()?);
+        }
+        Ok(p)
+    }
+
+    fn face(&mut self) -> Result<[u32; 3]> {
+        Ok([self.u32()?, self.u32()?, self.u32()?])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit;
+    use crate::extract::marching_cubes;
+    use crate::primitives::{Primitive, Shape};
+    use crate::samples::Storage;
+    use crate::slabs::Slabs;
+
+    fn sphere() -> Mesh {
+        let primitive = Primitive {
+            shape: Shape::Sphere,
+            size: 2.0,
+            detail: 0.2,
+            cells: 2,
+            seed: 0,
+        };
+        let field = primitive.sample(16, Storage::Full, &Slabs::default());
+        Mesh::from_triangles(&marching_cubes(&field, 0.0)).unwrap()
+    }
+
+    fn open_edges(mesh: &Mesh) -> usize {
+        audit::edge_faces(mesh)
+            .values()
+            .filter(|faces| faces.len() != 2)
+            .count()
+    }
+
+    // Positions as sortable bits, for comparing meshes numbered differently
+    fn points(mesh: &Mesh) -> Vec<[u32; 3]> {
+        let mut points: Vec<[u32; 3]> = (0..mesh.vertex_count())
+            .map(|v| mesh.vertex(v).map(f32::to_bits))
+            .collect();
+        points.sort_unstable();
+        points
+    }
+
+    fn temp(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}_{}.mlpm", name, std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let mesh = sphere();
+        let encoded = encode(&mesh, 50);
+        assert!(!encoded.refinements.is_empty());
+        let path = temp("mlpm_round_trip");
+        encoded.save(&path).unwrap();
+        let loaded = Progressive::load(&path, &InputLimits::default()).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.base_positions, encoded.base_positions);
+        assert_eq!(loaded.base_faces, encoded.base_faces);
+        assert_eq!(loaded.refinements.len(), encoded.refinements.len());
+        let full = loaded.decode(None).unwrap();
+        assert_eq!(full.face_count(), mesh.face_count());
+        assert_eq!(points(&full), points(&mesh));
+    }
+
+    #[test]
+    fn every_stage_is_closed() {
+        let encoded = encode(&sphere(), 50);
+        let all = encoded.refinements.len();
+        for count in [0, all / 3, all / 2, all] {
+            let mesh = encoded.decode(Some(count)).unwrap();
+            assert_eq!(open_edges(&mesh), 0, "after {} refinements", count);
+        }
+    }
+
+    #[test]
+    fn damaged_files_are_refused() {
+        let path = temp("mlpm_damaged");
+        encode(&sphere(), 50).save(&path).unwrap();
+        let good = std::fs::read(&path).unwrap();
+        let load = |bytes: &[u8]| {
+            std::fs::write(&path, bytes).unwrap();
+            Progressive::load(&path, &InputLimits::default())
+        };
+
+        assert!(load(&good[..HEADER_LEN - 1]).is_err());
+        assert!(load(&good[..good.len() - 1]).is_err());
+        let mut magic = good.clone();
+        magic[0] = b'X';
+        assert!(load(&magic).is_err());
+        // More refinements than there are bytes for
+        let mut count = good.clone();
+        count[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(load(&count).is_err());
+        // A base face naming a vertex that never arrives loads, but won't decode
+        let mut face = good.clone();
+        let vertices = u32::from_le_bytes(good[8..12].try_into().unwrap()) as usize;
+        let first_face = HEADER_LEN + vertices * 12;
+        face[first_face..first_face + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(load(&face).unwrap().decode(Some(0)).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,761 @@
+//! The voxel remesher's fields, which marching cubes draws the skin of.
+//!
+//! A scan with faces gets a signed distance field: each grid point's
+//! distance to the nearest point of a triangle, negative inside. Which
+//! side a grid point is on comes from the normal of what it is nearest:
+//! the face's own if that is inside a face, and otherwise the angle-
+//! weighted pseudo-normal of the edge or corner (Bærentzen and Aanæs,
+//! 2005), which gets the sign right at creases where a face normal alone
+//! would not. The field then runs smoothly through zero at the surface, so
+//! marching cubes places each vertex on it rather than on the nearest
+//! voxel face. That is only as good as the scan's faces agree about which
+//! way round they go; a hole's rim faces one way, so the sign past it is
+//! a guess, and the skin closes the hole along the guess.
+//!
+//! A bare point cloud has no faces to be inside of, so it gets the
+//! occupancy field instead: its points emit a "metaball" density, 1 near a
+//! point and 0 elsewhere, and the skin is drawn where it steps, in
+//! voxel-sized stairs.
+//!
+//! Either way the voxels are cubes. A resolution is the number of grid
+//! points along the longest side of the scan's bounds, and the other sides
+//! get as many as that voxel edge needs to cover them: a long, thin scan
+//! gets a long, thin grid, not a cube mostly of empty space, and its
+//! detail is kept equally well along every axis.
+
+use crate::bvh::Bvh;
+use crate::compose::Pedestal;
+use crate::kdtree::KdTree;
+use crate::labels::LabelFilter;
+use crate::mesh::Mesh;
+use crate::multigrid::{SparseField, MAX_LEVELS};
+use crate::profiles::{Corrected, ScanCorrections};
+use crate::samples::Storage;
+use crate::sdf::{Field, FieldKind, SampledField};
+use crate::slabs::{SlabField, Slabs};
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::OnceLock;
+
+/// Grid points along the longest side when the caller doesn't pick a
+/// resolution.
+pub const DEFAULT_RESOLUTION: usize = 50;
+
+/// How a field is sampled: the grid, how its samples are kept, and how
+/// the work is split across threads.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    /// Grid points along the longest side.
+    pub resolution: usize,
+    /// Size the grid by this voxel edge instead, for the scan at hand.
+    pub voxel_size: Option<f32>,
+    pub storage: Storage,
+    /// Sample coarse to fine from this many levels up (see `multigrid`);
+    /// 0 fills the whole grid.
+    pub coarse_levels: usize,
+    pub slabs: Slabs,
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Grid {
+            resolution: DEFAULT_RESOLUTION,
+            voxel_size: None,
+            storage: Storage::Full,
+            coarse_levels: 0,
+            slabs: Slabs::default(),
+        }
+    }
+}
+
+impl Grid {
+    pub fn validate(&self) -> Result<()> {
+        if self.resolution < 2 {
+            bail!("resolution must be at least 2");
+        }
+        if self.voxel_size.is_some_and(|v| !v.is_finite() || v <= 0.0) {
+            bail!("the voxel size must be a positive distance");
+        }
+        if self.coarse_levels > MAX_LEVELS {
+            bail!("at most {} coarse levels", MAX_LEVELS);
+        }
+        if self.slabs.layers == 0 {
+            bail!("a slab needs at least one layer");
+        }
+        Ok(())
+    }
+}
+
+/// A scan to remesh, and what to do to it on the way through.
+#[derive(Debug, Clone, Copy)]
+pub struct Scan<'a> {
+    pub corrections: ScanCorrections,
+    /// Remesh only these faces, keeping the rest as they are.
+    pub only: Option<&'a LabelFilter>,
+    pub field: ScanField,
+    /// How far each point reaches in the occupancy field, in voxels.
+    pub influence: f32,
+    /// Fuse this under the remeshed model.
+    pub pedestal: Option<Pedestal>,
+}
+
+/// A scan made ready to sample.
+#[derive(Debug, Clone)]
+pub struct Prepared {
+    /// What is to be remeshed, corrected.
+    pub mesh: Mesh,
+    /// The faces set aside, as triangles to write alongside the new skin.
+    pub kept: Vec<f32>,
+    pub corrected: Corrected,
+}
+
+impl Scan<'_> {
+    pub fn validate(&self) -> Result<()> {
+        self.corrections.validate()?;
+        if !INFLUENCE_RANGE.contains(&self.influence) {
+            bail!(
+                "the influence radius must be {} to {} voxels",
+                INFLUENCE_RANGE.start(),
+                INFLUENCE_RANGE.end()
+            );
+        }
+        if let Some(pedestal) = &self.pedestal {
+            pedestal.validate()?;
+        }
+        Ok(())
+    }
+
+    /// Set aside the faces of `mesh` that aren't to be remeshed, and
+    /// correct the rest (on `threads` threads). What is set aside is scaled
+    /// with it, but keeps its strays.
+    pub fn prepare(&self, mesh: Mesh, threads: usize) -> Result<Prepared> {
+        let Some(only) = self.only else {
+            let mut mesh = mesh;
+            let corrected = self.corrections.apply(&mut mesh, threads);
+            return Ok(Prepared {
+                mesh,
+                kept: Vec::new(),
+                corrected,
+            });
+        };
+        let (mut picked, mut rest) = only.split(&mesh)?;
+        if picked.face_count() == 0 {
+            bail!("no face is labelled {}", only.describe());
+        }
+        ScanCorrections {
+            outliers: None,
+            ..self.corrections
+        }
+        .apply(&mut rest, threads);
+        let kept = (0..rest.face_count())
+            .flat_map(|f| rest.face(f))
+            .flat_map(|v| rest.vertex(v))
+            .collect();
+        let corrected = self.corrections.apply(&mut picked, threads);
+        Ok(Prepared {
+            mesh: picked,
+            kept,
+            corrected,
+        })
+    }
+}
+
+/// Which field a scan is remeshed through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ScanField {
+    /// Signed distance to the scan's faces: a smooth skin on the surface
+    #[default]
+    Distance,
+    /// Balls round the scan's points: a blocky shell, but needs no faces
+    Occupancy,
+}
+
+impl ScanField {
+    /// The field `mesh` can have: without faces, only occupancy.
+    pub fn for_scan(self, mesh: &Mesh) -> ScanField {
+        if mesh.face_count() == 0 {
+            ScanField::Occupancy
+        } else {
+            self
+        }
+    }
+
+    pub fn kind(self) -> FieldKind {
+        match self {
+            ScanField::Distance => FieldKind::SignedDistance,
+            ScanField::Occupancy => FieldKind::Density,
+        }
+    }
+
+    /// The isovalue the surface sits at.
+    pub fn iso(self) -> f32 {
+        match self {
+            ScanField::Distance => 0.0,
+            ScanField::Occupancy => ISO,
+        }
+    }
+
+    /// The isovalue to draw the skin at: `iso` if it finds a surface in
+    /// this field, otherwise an error, and the field's own if `None`.
+    pub fn check_iso(self, iso: Option<f32>) -> Result<f32> {
+        let iso = iso.unwrap_or(self.iso());
+        if !iso.is_finite() {
+            bail!("isovalue must be a finite number");
+        }
+        // Density is 0 or 1: a threshold outside that finds nothing
+        if self == ScanField::Occupancy && !(iso > 0.0 && iso < 1.0) {
+            bail!("the occupancy field's isovalue must be between 0 and 1");
+        }
+        Ok(iso)
+    }
+}
+
+/// The density threshold the occupancy field's surface sits at.
+pub const ISO: f32 = 0.5;
+/// How far a point's influence reaches by default, in grid steps along x.
+pub const INFLUENCE: f32 = 3.0;
+/// The reaches `--influence-radius` takes. Under half a step a point can
+/// fall between grid points and vanish; past a few dozen, the balls merge
+/// into one lump and each grid point searches more of the scan for nothing.
+pub const INFLUENCE_RANGE: std::ops::RangeInclusive<f32> = 0.5..=32.0;
+
+/// Sample `mesh` through `field` on a grid `resolution` points along its
+/// longest side, each point reaching `influence` grid steps if it's the
+/// occupancy field. This is what `mesh_lifter remesh`, `remesh_voxel` and
+/// the server's jobs all remesh through; pass `field.for_scan(mesh)` for
+/// a scan that may have no faces.
+pub fn sample(
+    mesh: &Mesh,
+    field: ScanField,
+    resolution: usize,
+    influence: f32,
+    storage: Storage,
+    slabs: &Slabs,
+) -> SampledField {
+    match field {
+        ScanField::Distance => sample_surface(mesh, resolution, storage, slabs),
+        ScanField::Occupancy => sample_scan(&mesh.positions, resolution, influence, storage, slabs),
+    }
+}
+
+/// `sample` coarse to fine (see `multigrid`) on `threads` threads,
+/// following the surface at `iso`.
+pub fn sample_sparse(
+    mesh: &Mesh,
+    field: ScanField,
+    resolution: usize,
+    influence: f32,
+    iso: f32,
+    levels: usize,
+    threads: usize,
+) -> SparseField {
+    match field {
+        ScanField::Distance => sample_surface_sparse(mesh, resolution, iso, levels, threads),
+        ScanField::Occupancy => {
+            sample_scan_sparse(&mesh.positions, resolution, influence, iso, levels, threads)
+        }
+    }
+}
+
+/// Sample the occupancy field of a point set on a grid `resolution`
+/// points along its longest side, each point reaching `influence` grid
+/// steps. The field is only ever 0 or 1, so
+/// `Storage::Bits` keeps it exactly.
+pub fn sample_scan(
+    positions: &[f32],
+    resolution: usize,
+    influence: f32,
+    storage: Storage,
+    slabs: &Slabs,
+) -> SampledField {
+    let field = scan_field(positions, resolution, influence, slabs.threads);
+    let (min, _) = get_bounds(positions);
+    SampledField::sample(
+        &field,
+        [min.0, min.1, min.2],
+        [field.step; 3],
+        FieldKind::Density,
+        ISO,
+        storage,
+        slabs,
+    )
+}
+
+/// The same field sampled coarse to fine (see `multigrid`) on `threads`
+/// threads, following the surface at `iso`.
+pub fn sample_scan_sparse(
+    positions: &[f32],
+    resolution: usize,
+    influence: f32,
+    iso: f32,
+    levels: usize,
+    threads: usize,
+) -> SparseField {
+    let field = scan_field(positions, resolution, influence, threads);
+    let (min, _) = get_bounds(positions);
+    SparseField::sample(
+        &field,
+        [min.0, min.1, min.2],
+        [field.step; 3],
+        FieldKind::Density,
+        iso,
+        levels,
+        threads,
+    )
+}
+
+/// The field `sample_scan` samples, with its grid's origin and spacing,
+/// for callers that only want some of it; its points' tree is built on
+/// `threads` threads.
+pub fn scan_grid(
+    positions: &[f32],
+    resolution: usize,
+    influence: f32,
+    threads: usize,
+) -> (impl Field, [f32; 3], [f32; 3]) {
+    let field = scan_field(positions, resolution, influence, threads);
+    let (min, _) = get_bounds(positions);
+    let step = field.step;
+    (field, [min.0, min.1, min.2], [step; 3])
+}
+
+/// Sample the signed distance to `mesh`'s faces on a grid `resolution`
+/// points along its longest side, with a layer to spare past the surface
+/// all round so the skin closes.
+pub fn sample_surface(
+    mesh: &Mesh,
+    resolution: usize,
+    storage: Storage,
+    slabs: &Slabs,
+) -> SampledField {
+    let field = SurfaceField::new(mesh, resolution);
+    SampledField::sample(
+        &field,
+        field.origin,
+        field.step,
+        FieldKind::SignedDistance,
+        0.0,
+        storage,
+        slabs,
+    )
+}
+
+/// The same distance field sampled coarse to fine (see `multigrid`) on
+/// `threads` threads, following the surface at `iso`.
+pub fn sample_surface_sparse(
+    mesh: &Mesh,
+    resolution: usize,
+    iso: f32,
+    levels: usize,
+    threads: usize,
+) -> SparseField {
+    let field = SurfaceField::new(mesh, resolution);
+    SparseField::sample(
+        &field,
+        field.origin,
+        field.step,
+        FieldKind::SignedDistance,
+        iso,
+        levels,
+        threads,
+    )
+}
+
+/// The field `sample_surface` samples, with its grid's origin and
+/// spacing, for callers that only want some of it.
+pub fn surface_grid(mesh: &Mesh, resolution: usize) -> (impl Field + '_, [f32; 3], [f32; 3]) {
+    let field = SurfaceField::new(mesh, resolution);
+    let (origin, step) = (field.origin, field.step);
+    (field, origin, step)
+}
+
+/// The edge of a voxel when `mesh` is remeshed through `field` at
+/// `resolution`, without building the field.
+pub fn voxel_size(mesh: &Mesh, field: ScanField, resolution: usize) -> f32 {
+    grid(&mesh.positions, field, resolution).1
+}
+
+/// Grid points along each axis when `mesh` is remeshed through `field` at
+/// `resolution`.
+pub fn grid_dims(mesh: &Mesh, field: ScanField, resolution: usize) -> [usize; 3] {
+    grid(&mesh.positions, field, resolution).2
+}
+
+/// The resolution whose voxels are at most `voxel` on edge, for `mesh`
+/// through `field`.
+pub fn resolution_for(mesh: &Mesh, field: ScanField, voxel: f32) -> usize {
+    let (min, max) = get_bounds(&mesh.positions);
+    let longest = (max.0 - min.0).max(max.1 - min.1).max(max.2 - min.2);
+    ((longest / voxel).ceil() as usize + padding(field)).max(2)
+}
+
+// Layers of the grid past the scan's bounds: one either side of the
+// distance field's, and one more for the rounding down of the last
+fn padding(field: ScanField) -> usize {
+    match field {
+        ScanField::Distance => 3,
+        ScanField::Occupancy => 0,
+    }
+}
+
+// The scan's lower bounds, the voxel edge, and the grid points along each
+// axis: `resolution` along the longest side, as many as the same edge
+// needs along the others
+fn grid(
+    positions: &[f32],
+    field: ScanField,
+    resolution: usize,
+) -> ((f32, f32, f32), f32, [usize; 3]) {
+    let (min, max) = get_bounds(positions);
+    let extent = [max.0 - min.0, max.1 - min.1, max.2 - min.2];
+    let padding = padding(field);
+    let cells = resolution.saturating_sub(padding).max(1);
+    let voxel = extent.iter().copied().fold(0.0f32, f32::max) / cells as f32;
+    let dims = extent.map(|e| {
+        let along = if voxel > 0.0 {
+            ((e / voxel).ceil() as usize).min(cells)
+        } else {
+            0
+        };
+        (along + padding).max(2)
+    });
+    (min, voxel, dims)
+}
+
+// Create the "Field" (The Voxel Grid) over the object's bounding box
+fn scan_field(
+    positions: &[f32],
+    resolution: usize,
+    influence: f32,
+    threads: usize,
+) -> MeshDistanceField {
+    let (min, step, dims) = grid(positions, ScanField::Occupancy, resolution);
+    let layers = dims[2];
+    let layer = |p: &[f32]| (((p[2] - min.2) / step) as usize).min(layers - 1);
+
+    // Bucket the points by grid layer, so a slab's are all in one run. All
+    // of them: the tree makes each query logarithmic, so there is nothing
+    // to gain from thinning the scan and an accurate field to lose
+    let mut layer_start = vec![0usize; layers + 1];
+    for p in positions.chunks_exact(3) {
+        layer_start[layer(p) + 1] += 1;
+    }
+    for l in 0..layers {
+        layer_start[l + 1] += layer_start[l];
+    }
+    let mut next = layer_start.clone();
+    let mut points = vec![[0.0f32; 3]; layer_start[layers]];
+    for p in positions.chunks_exact(3) {
+        let l = layer(p);
+        points[next[l]] = [p[0], p[1], p[2]];
+        next[l] += 1;
+    }
+    MeshDistanceField {
+        points,
+        layer_start,
+        tree: OnceLock::new(),
+        threads,
+        min,
+        step,
+        dims,
+        influence,
+    }
+}
+
+// --- HELPER STRUCTURES ---
+
+// This struct defines our "Voxel Grid"
+struct MeshDistanceField {
+    // The scan's points, in order of the grid layer (along z) they fall in
+    points: Vec<[f32; 3]>,
+    // Where each layer's points start, and one past the last
+    layer_start: Vec<usize>,
+    // All of them, for finding the closest one to a lone grid point; only
+    // built if one asks, as slabs build their own
+    tree: OnceLock<KdTree>,
+    threads: usize,
+    min: (f32, f32, f32),
+    // World-space distance between neighbouring grid points
+    step: f32,
+    dims: [usize; 3],
+    // How far a point reaches, in steps
+    influence: f32,
+}
+
+impl MeshDistanceField {
+    // World coordinates of grid point (x, y, z)
+    fn world(&self, x: usize, y: usize, z: usize) -> [f32; 3] {
+        [
+            self.min.0 + (x as f32 * self.step),
+            self.min.1 + (y as f32 * self.step),
+            self.min.2 + (z as f32 * self.step),
+        ]
+    }
+
+    // If we are close to a point, return 1.0. If far, return 0.0.
+    fn density(&self, nearest: Option<(usize, f32)>) -> f64 {
+        let threshold = self.radius().powi(2); // Radius of influence
+        match nearest {
+            Some((_, min_dist_sq)) if min_dist_sq < threshold => 1.0,
+            _ => 0.0,
+        }
+    }
+
+    fn radius(&self) -> f32 {
+        self.step * self.influence
+    }
+
+    fn tree(&self) -> &KdTree {
+        self.tree
+            .get_or_init(|| KdTree::new(&self.points, self.threads))
+    }
+
+    // A whole row at once: one walk of `tree` per chunk of it
+    fn row_from(&self, tree: &KdTree, y: usize, z: usize) -> Vec<f64> {
+        let queries: Vec<[f32; 3]> = (0..self.dims[0]).map(|x| self.world(x, y, z)).collect();
+        tree.nearest_batch(&queries)
+            .into_iter()
+            .map(|nearest| self.density(nearest))
+            .collect()
+    }
+}
+
+// This implements our Voxel Grid trait.
+// It answers the question: "What is the density at coordinates (x,y,z)?"
+impl Field for MeshDistanceField {
+    fn dimensions(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    // This is the heavy lifting.
+    // For every voxel, we calculate its value based on proximity to the scan points.
+    fn z(&self, x: usize, y: usize, z: usize) -> f64 {
+        // SIMPLE ALGORITHM (Metaball Style):
+        // Find the distance to the CLOSEST vertex in the original scan.
+        self.density(self.tree().nearest(self.world(x, y, z)))
+    }
+
+    fn row(&self, y: usize, z: usize) -> Vec<f64> {
+        self.row_from(self.tree(), y, z)
+    }
+
+    // Every point is a ball this big
+    fn feature_radius(&self) -> Option<f32> {
+        Some(self.radius())
+    }
+}
+
+// A slab reads only the points within reach of its layers, in a tree of
+// its own
+impl SlabField for MeshDistanceField {
+    type Local<'a> = ScanSlab<'a>;
+
+    fn local(&self, z: Range<usize>) -> ScanSlab<'_> {
+        // Layers either side whose points could still reach these, and
+        // one for the layer a point is rounded down into
+        let margin = self.influence.ceil() as usize + 1;
+        let first = z.start.saturating_sub(margin);
+        let last = (z.end + margin).min(self.dims[2]);
+        let points = &self.points[self.layer_start[first]..self.layer_start[last]];
+        ScanSlab {
+            field: self,
+            tree: KdTree::new(points, 1),
+        }
+    }
+}
+
+struct ScanSlab<'a> {
+    field: &'a MeshDistanceField,
+    tree: KdTree,
+}
+
+impl Field for ScanSlab<'_> {
+    fn dimensions(&self) -> [usize; 3] {
+        self.field.dimensions()
+    }
+
+    fn z(&self, x: usize, y: usize, z: usize) -> f64 {
+        let field = self.field;
+        field.density(self.tree.nearest(field.world(x, y, z)))
+    }
+
+    fn row(&self, y: usize, z: usize) -> Vec<f64> {
+        self.field.row_from(&self.tree, y, z)
+    }
+}
+
+// The signed distance to a mesh's faces, on a grid over its bounds
+struct SurfaceField<'a> {
+    mesh: &'a Mesh,
+    bvh: Bvh,
+    face_normals: Vec<[f32; 3]>,
+    // Angle-weighted, per vertex
+    vertex_normals: Vec<[f32; 3]>,
+    // The sum of the face normals either side, per edge (low, high)
+    edge_normals: HashMap<(u32, u32), [f32; 3]>,
+    origin: [f32; 3],
+    step: [f32; 3],
+    dims: [usize; 3],
+}
+
+impl<'a> SurfaceField<'a> {
+    fn new(mesh: &'a Mesh, resolution: usize) -> Self {
+        let face_normals: Vec<[f32; 3]> = (0..mesh.face_count())
+            .map(|f| mesh.face_normal(f))
+            .collect();
+        let mut vertex_normals = vec![[0.0f32; 3]; mesh.vertex_count()];
+        let mut edge_normals = HashMap::new();
+        for (f, normal) in face_normals.iter().enumerate() {
+            let corners = mesh.face(f);
+            for k in 0..3 {
+                let (v, next, prev) = (corners[k], corners[(k + 1) % 3], corners[(k + 2) % 3]);
+                let p = mesh.vertex(v);
+                let angle = angle_between(sub(mesh.vertex(next), p), sub(mesh.vertex(prev), p));
+                add(&mut vertex_normals[v], *normal, angle);
+                let edge = (v.min(next) as u32, v.max(next) as u32);
+                add(edge_normals.entry(edge).or_insert([0.0; 3]), *normal, 1.0);
+            }
+        }
+
+        // The scan's bounds, padded as the occupancy grid's are, and a
+        // step more either side so the outermost layers are outside
+        let (min, step, dims) = grid(&mesh.positions, ScanField::Distance, resolution);
+        let origin = [min.0 - step, min.1 - step, min.2 - step];
+        SurfaceField {
+            mesh,
+            bvh: Bvh::new(mesh),
+            face_normals,
+            vertex_normals,
+            edge_normals,
+            origin,
+            step: [step; 3],
+            dims,
+        }
+    }
+
+    fn distance(&self, p: [f32; 3]) -> f64 {
+        let Some((face, nearest, dist_sq)) = self.bvh.nearest(p, f32::INFINITY) else {
+            return f64::from(f32::MAX);
+        };
+        let normal = self.pseudo_normal(face, nearest);
+        let distance = f64::from(dist_sq.sqrt());
+        if dot(sub(p, nearest), normal) < 0.0 {
+            -distance
+        } else {
+            distance
+        }
+    }
+
+    // The normal that tells the sides apart at `q`, a point of `face`: the
+    // face's inside it, the edge's or corner's on its rim
+    fn pseudo_normal(&self, face: usize, q: [f32; 3]) -> [f32; 3] {
+        // Barycentric weights closer to zero than this put `q` on the rim
+        const RIM: f32 = 1e-4;
+        let corners = self.mesh.face(face);
+        let [a, b, c] = corners.map(|v| self.mesh.vertex(v));
+        let (ab, ac, aq) = (sub(b, a), sub(c, a), sub(q, a));
+        let (d00, d01, d11) = (dot(ab, ab), dot(ab, ac), dot(ac, ac));
+        let (d20, d21) = (dot(aq, ab), dot(aq, ac));
+        let denom = d00 * d11 - d01 * d01;
+        if denom <= 0.0 {
+            return self.face_normals[face];
+        }
+        let v = (d11 * d20 - d01 * d21) / denom;
+        let w = (d00 * d21 - d01 * d20) / denom;
+        let weights = [1.0 - v - w, v, w];
+        let on: Vec<usize> = (0..3).filter(|&k| weights[k] > RIM).collect();
+        match on[..] {
+            [k] => self.vertex_normals[corners[k]],
+            [j, k] => {
+                let (p, q) = (corners[j], corners[k]);
+                self.edge_normals[&(p.min(q) as u32, p.max(q) as u32)]
+            }
+            _ => self.face_normals[face],
+        }
+    }
+}
+
+impl Field for SurfaceField<'_> {
+    fn dimensions(&self) -> [usize; 3] {
+        self.dims
+    }
+
+    fn z(&self, x: usize, y: usize, z: usize) -> f64 {
+        let p = [x, y, z].map(|i| i as f32);
+        self.distance([0, 1, 2].map(|k| self.origin[k] + p[k] * self.step[k]))
+    }
+}
+
+// The tree is only read, so every slab shares it
+impl SlabField for SurfaceField<'_> {
+    type Local<'a>
+        = &'a Self
+    where
+        Self: 'a;
+
+    fn local(&self, _z: Range<usize>) -> &Self {
+        self
+    }
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// `into += v * weight`
+fn add(into: &mut [f32; 3], v: [f32; 3], weight: f32) {
+    for k in 0..3 {
+        into[k] += v[k] * weight;
+    }
+}
+
+// Angle between two directions, 0 if either has no length
+fn angle_between(u: [f32; 3], v: [f32; 3]) -> f32 {
+    let lengths = (dot(u, u) * dot(v, v)).sqrt();
+    if lengths > 0.0 {
+        (dot(u, v) / lengths).clamp(-1.0, 1.0).acos()
+    } else {
+        0.0
+    }
+}
+
+// Helper to find the size of the object
+fn get_bounds(positions: &[f32]) -> ((f32, f32, f32), (f32, f32, f32)) {
+    let mut min = (f32::MAX, f32::MAX, f32::MAX);
+    let mut max = (f32::MIN, f32::MIN, f32::MIN);
+
+    for chunk in positions.chunks(3) {
+        if let [x, y, z] = chunk {
+            if *x < min.0 {
+                min.0 = *x;
+            }
+            if *y < min.1 {
+                min.1 = *y;
+            }
+            if *z < min.2 {
+                min.2 = *z;
+            }
+
+            if *x > max.0 {
+                max.0 = *x;
+            }
+            if *y > max.1 {
+                max.1 = *y;
+            }
+            if *z > max.2 {
+                max.2 = *z;
+            }
+        }
+    }
+    // Add some padding so the object isn't touching the edge of the grid
+    let padding = 0.2;
+    (
+        (min.0 - padding, min.1 - padding, min.2 - padding),
+        (max.0 + padding, max.1 + padding, max.2 + padding),
+    )
+}
@@ -0,0 +1,224 @@
+//! `audit --report html`: a single HTML file to hand to people who won't
+//! read terminal output.
+//!
+//! Everything is inline: the tables, the styling, a small WebGL viewer and
+//! the mesh itself as a glTF 2.0 document with its buffer base64-encoded in
+//! a data URI. Faces mentioned by a finding are tinted in that finding's
+//! colour, so the customer sees where the holes and flipped faces are;
+//! the rest keep the scan's own vertex colours, if it had any.
+//! The file opens offline and can be emailed as is.
+
+use crate::audit::{AuditReport, Code};
+use crate::mesh::Mesh;
+use crate::messages;
+use crate::storage;
+use anyhow::Result;
+use base64::Engine;
+use serde_json::json;
+use std::io::Write;
+
+const TEMPLATE: &str = include_str!("report.html");
+/// The WebGL viewer, shared with the server's page.
+pub const VIEWER: &str = include_str!("viewer.js");
+const LABELS: &[&str] = &[
+    "report.lang",
+    "report.title",
+    "report.statistics",
+    "report.issues",
+    "report.code",
+    "report.name",
+    "report.count",
+    "report.details",
+    "report.model",
+    "report.hint",
+];
+
+// Faces nobody complained about
+const CLEAN: [f32; 3] = [0.72, 0.74, 0.78];
+
+/// Output formats for `--report`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ReportFormat {
+    Html,
+}
+
+/// Write `report` for `mesh` to `path` (local, http(s):// or s3://).
+pub fn write_html(title: &str, mesh: &Mesh, report: &AuditReport, path: &str) -> Result<()> {
+    let (min, max) = report.bounds;
+    let stats = [
+        ("audit.vertices", report.vertices.to_string()),
+        ("audit.faces", report.faces.to_string()),
+        ("audit.bounds", report.describe_bounds()),
+        (
+            "audit.size",
+            format!(
+                "{:.3} × {:.3} × {:.3}",
+                max[0] - min[0],
+                max[1] - min[1],
+                max[2] - min[2]
+            ),
+        ),
+        ("audit.watertight", messages::yes_no(report.watertight)),
+        ("audit.colours", messages::yes_no(report.coloured)),
+        ("audit.up", report.placement.describe_up()),
+        ("audit.units", report.placement.describe_units()),
+        ("audit.warnings", report.findings.len().to_string()),
+        ("audit.allowed", report.allowed.to_string()),
+    ];
+    let stats: String = stats
+        .iter()
+        .map(|(k, v)| format!("<tr><th>{}</th><td>{}</td></tr>\n", t(k), escape(v)))
+        .collect();
+
+    let issues: String = if report.findings.is_empty() {
+        format!("<tr><td colspan=\"4\">✅ {}</td></tr>\n", t("audit.clean"))
+    } else {
+        report
+            .findings
+            .iter()
+            .map(|f| {
+                format!(
+                    "<tr><td><span class=\"swatch\" style=\"background:{}\"></span>{}</td>\
+                     <td>{}</td><td>{}</td><td>{}</td></tr>\n",
+                    css(colour(f.code)),
+                    f.code.id(),
+                    f.name,
+                    f.count,
+                    escape(&f.message)
+                )
+            })
+            .collect()
+    };
+
+    // The template's own headings, as {{report.statistics}} and so on
+    let mut html = TEMPLATE.to_string();
+    for key in LABELS {
+        html = html.replace(&format!("{{{{{}}}}}", key), &t(key));
+    }
+    let html = html
+        .replace("{{VIEWER}}", VIEWER)
+        .replace("{{TITLE}}", &escape(title))
+        .replace("{{STATS}}", &stats)
+        .replace("{{ISSUES}}", &issues)
+        // The glTF goes inside a <script>, so only "</" needs defusing
+        .replace(
+            "{{GLTF}}",
+            &highlighted_gltf(mesh, report)
+                .to_string()
+                .replace("</", "<\\/"),
+        );
+
+    let mut out = storage::create(path)?;
+    out.write_all(html.as_bytes())?;
+    out.finish()
+}
+
+// The mesh as glTF, unindexed so each face can have its own colour
+fn highlighted_gltf(mesh: &Mesh, report: &AuditReport) -> serde_json::Value {
+    // 1. Which colour each face gets: the first finding that names it
+    let mut face_colour = vec![None; mesh.face_count()];
+    let mut vertex_colour = vec![None; mesh.vertex_count()];
+    for finding in &report.findings {
+        for &f in &finding.faces {
+            face_colour[f].get_or_insert(colour(finding.code));
+        }
+        for &v in &finding.vertices {
+            vertex_colour[v].get_or_insert(colour(finding.code));
+        }
+    }
+
+    // 2. Three corners per face, each with the face normal and colour
+    let mut positions = Vec::with_capacity(mesh.face_count() * 9);
+    let mut normals = Vec::with_capacity(mesh.face_count() * 9);
+    let mut colours = Vec::with_capacity(mesh.face_count() * 9);
+    for (f, &marked) in face_colour.iter().enumerate() {
+        let corners = mesh.face(f);
+        let [a, b, c] = corners.map(|i| mesh.vertex(i));
+        let normal = mesh.face_normal(f);
+        // Vertex problems (duplicates) show on the faces around them
+        let tint = marked.or_else(|| corners.iter().find_map(|&i| vertex_colour[i]));
+        for (i, p) in corners.into_iter().zip([a, b, c]) {
+            positions.extend_from_slice(&p);
+            normals.extend_from_slice(&normal);
+            colours.extend_from_slice(&tint.or(mesh.colour(i)).unwrap_or(CLEAN));
+        }
+    }
+
+    // 3. One buffer, three views, three accessors
+    let count = positions.len() / 3;
+    let view_len = positions.len() * 4;
+    let mut buffer = Vec::with_capacity(view_len * 3);
+    for value in positions.iter().chain(&normals).chain(&colours) {
+        buffer.extend_from_slice(&value.to_le_bytes());
+    }
+    let (min, max) = report.bounds;
+    let uri = format!(
+        "data:application/octet-stream;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(&buffer)
+    );
+    json!({
+        "asset": { "version": "2.0", "generator": "mesh_lifter" },
+        "scene": 0,
+        "scenes": [{ "nodes": [0] }],
+        "nodes": [{ "mesh": 0 }],
+        "meshes": [{
+            "primitives": [{
+                "attributes": { "POSITION": 0, "NORMAL": 1, "COLOR_0": 2 },
+                "mode": 4
+            }]
+        }],
+        "buffers": [{ "byteLength": buffer.len(), "uri": uri }],
+        "bufferViews": (0..3).map(|i| json!({
+            "buffer": 0,
+            "byteOffset": i * view_len,
+            "byteLength": view_len,
+            "target": 34962
+        })).collect::<Vec<_>>(),
+        "accessors": [
+            { "bufferView": 0, "componentType": 5126, "count": count, "type": "VEC3",
+              "min": min, "max": max },
+            { "bufferView": 1, "componentType": 5126, "count": count, "type": "VEC3" },
+            { "bufferView": 2, "componentType": 5126, "count": count, "type": "VEC3" }
+        ]
+    })
+}
+
+// A colour per code, the same in the table and on the model
+fn colour(code: Code) -> [f32; 3] {
+    match code {
+        Code::NonManifoldEdge => [0.85, 0.10, 0.55],
+        Code::OpenBoundary => [0.90, 0.15, 0.15],
+        Code::DegenerateFace => [0.95, 0.55, 0.10],
+        Code::DuplicateVertex => [0.95, 0.85, 0.15],
+        Code::UnreferencedVertex => [0.55, 0.55, 0.55],
+        Code::HighPolyCount => [0.45, 0.45, 0.90],
+        Code::InvertedNormals => [0.15, 0.45, 0.95],
+        Code::DuplicateFace => [0.10, 0.75, 0.65],
+        Code::EmptyRemesh => [0.40, 0.40, 0.40],
+        Code::ZeroVolume => [0.60, 0.20, 0.85],
+        Code::ImplausibleScale => [0.55, 0.80, 0.20],
+        Code::ThinWalls => [0.95, 0.40, 0.60],
+        Code::CoarseResolution => [0.70, 0.60, 0.35],
+    }
+}
+
+// A message, ready to go into the page
+fn t(key: &str) -> String {
+    escape(&messages::text(key, &[]))
+}
+
+fn css([r, g, b]: [f32; 3]) -> String {
+    format!(
+        "rgb({}, {}, {})",
+        (r * 255.0) as u8,
+        (g * 255.0) as u8,
+        (b * 255.0) as u8
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,203 @@
+//! How a sampled field keeps its samples in memory.
+//!
+//! Most fields need a float per sample, but the voxel remesher's occupancy
+//! field only ever holds 0 or 1, and 32 bits for one of two values is 32
+//! times the memory needed. Packed into bits, a 1000³ grid is 125 MB rather
+//! than 4 GB, so the same machine can remesh at three times the resolution
+//! (the grid grows with its cube).
+//!
+//! Distance fields need more than a bit, but rarely all of a float. Half
+//! floats (IEEE 754 binary16) halve the memory and keep 11 significant
+//! bits, so each sample is off by at most 1/2048 (0.05%) of its own value.
+//! The surface is placed from the samples either side of it, which are
+//! within a voxel or so of the isovalue: extracting at 0, as a signed
+//! distance field normally is, that moves a vertex by well under a
+//! thousandth of a voxel, far below what the grid itself costs. Two
+//! things cost more. Extracting at an isovalue far from zero (a big
+//! `--iso` offset) scales the error up with it, to about the isovalue over
+//! 2048. And samples past ±65504 are saturated there, fine for the sign
+//! but not for the distance, so a grid in large units (a site in
+//! millimetres, say) should stay full.
+
+use half::f16;
+
+/// Ways to store a field's samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Storage {
+    /// A 32-bit float per sample: exact, for any field
+    Full,
+    /// A bit per sample, 1 or 0 rounded at 0.5: exact for occupancy fields
+    Bits,
+    /// A 16-bit float per sample: within 0.05% of each value
+    Half,
+}
+
+impl Storage {
+    /// Memory `samples` samples take up stored this way.
+    pub fn bytes(self, samples: usize) -> usize {
+        match self {
+            Storage::Full => samples * 4,
+            Storage::Bits => samples.div_ceil(64) * 8,
+            Storage::Half => samples * 2,
+        }
+    }
+}
+
+/// A field's samples, in one of the `Storage` layouts.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Samples {
+    Full(Vec<f32>),
+    /// Sample `i` is bit `i % 64` of word `i / 64`.
+    Bits {
+        len: usize,
+        words: Vec<u64>,
+    },
+    Half(Vec<f16>),
+}
+
+impl Samples {
+    /// Room for `capacity` samples, none there yet.
+    pub fn with_capacity(storage: Storage, capacity: usize) -> Self {
+        match storage {
+            Storage::Full => Samples::Full(Vec::with_capacity(capacity)),
+            Storage::Bits => Samples::Bits {
+                len: 0,
+                words: Vec::with_capacity(capacity.div_ceil(64)),
+            },
+            Storage::Half => Samples::Half(Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// `len` samples of `value`.
+    pub fn filled(storage: Storage, len: usize, value: f32) -> Self {
+        match storage {
+            Storage::Full => Samples::Full(vec![value; len]),
+            Storage::Bits => Samples::Bits {
+                len,
+                words: vec![if bit(value) { u64::MAX } else { 0 }; len.div_ceil(64)],
+            },
+            Storage::Half => Samples::Half(vec![half(value); len]),
+        }
+    }
+
+    pub fn storage(&self) -> Storage {
+        match self {
+            Samples::Full(_) => Storage::Full,
+            Samples::Bits { .. } => Storage::Bits,
+            Samples::Half(_) => Storage::Half,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Samples::Full(values) => values.len(),
+            Samples::Bits { len, .. } => *len,
+            Samples::Half(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, i: usize) -> f32 {
+        match self {
+            Samples::Full(values) => values[i],
+            Samples::Half(values) => values[i].to_f32(),
+            Samples::Bits { len, words } => {
+                assert!(i < *len, "sample {} of {}", i, len);
+                if words[i / 64] >> (i % 64) & 1 == 1 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    pub fn set(&mut self, i: usize, value: f32) {
+        match self {
+            Samples::Full(values) => values[i] = value,
+            Samples::Half(values) => values[i] = half(value),
+            Samples::Bits { len, words } => {
+                assert!(i < *len, "sample {} of {}", i, len);
+                let mask = 1u64 << (i % 64);
+                if bit(value) {
+                    words[i / 64] |= mask;
+                } else {
+                    words[i / 64] &= !mask;
+                }
+            }
+        }
+    }
+
+    pub fn push(&mut self, value: f32) {
+        match self {
+            Samples::Full(values) => values.push(value),
+            Samples::Half(values) => values.push(half(value)),
+            Samples::Bits { len, words } => {
+                if (*len).is_multiple_of(64) {
+                    words.push(0);
+                }
+                if bit(value) {
+                    words[*len / 64] |= 1 << (*len % 64);
+                }
+                *len += 1;
+            }
+        }
+    }
+
+    /// Add `other`'s samples after these, converting them to this storage
+    /// if it is different.
+    pub fn append(&mut self, other: &Samples) {
+        match (self, other) {
+            (Samples::Full(values), Samples::Full(more)) => values.extend_from_slice(more),
+            (Samples::Half(values), Samples::Half(more)) => values.extend_from_slice(more),
+            // Whole words at a time while the bits line up
+            (
+                Samples::Bits { len, words },
+                Samples::Bits {
+                    len: more_len,
+                    words: more,
+                },
+            ) if (*len).is_multiple_of(64) => {
+                words.extend_from_slice(more);
+                *len += more_len;
+            }
+            (samples, other) => {
+                for value in other.iter() {
+                    samples.push(value);
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = f32> + '_ {
+        (0..self.len()).map(|i| self.get(i))
+    }
+
+    /// Memory the samples take up.
+    pub fn bytes(&self) -> usize {
+        match self {
+            Samples::Full(values) => values.len() * 4,
+            Samples::Bits { words, .. } => words.len() * 8,
+            Samples::Half(values) => values.len() * 2,
+        }
+    }
+}
+
+impl FromIterator<f32> for Samples {
+    /// Full floats, whatever they are.
+    fn from_iter<I: IntoIterator<Item = f32>>(iter: I) -> Self {
+        Samples::Full(iter.into_iter().collect())
+    }
+}
+
+fn bit(value: f32) -> bool {
+    value >= 0.5
+}
+
+// Nearest half float, saturating rather than going infinite
+fn half(value: f32) -> f16 {
+    f16::from_f32(value.clamp(f16::MIN.to_f32(), f16::MAX.to_f32()))
+}
@@ -0,0 +1,201 @@
+//! Per-job resource limits: a wall-clock timeout and a memory cap.
+//!
+//! Memory is measured by `TrackingAllocator`, which counts every live
+//! allocation. The long-running loops call `checkpoint()` as they go; once
+//! a limit is blown the checkpoint unwinds out of the job, and `run_job`
+//! turns that into an ordinary error saying why. Nothing is killed halfway
+//! through a write, and the process carries on.
+//!
+//! Memory is counted process-wide, so the cap is on how much the process
+//! has grown since the job started.
+
+use anyhow::Result;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, plus a running total of bytes in use.
+pub struct TrackingAllocator;
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            if new_size > layout.size() {
+                grow(new_size - layout.size());
+            } else {
+                ALLOCATED.fetch_sub(layout.size() - new_size, Ordering::Relaxed);
+            }
+        }
+        new_ptr
+    }
+}
+
+fn grow(bytes: usize) {
+    let now = ALLOCATED.fetch_add(bytes, Ordering::Relaxed) + bytes;
+    PEAK.fetch_max(now, Ordering::Relaxed);
+}
+
+/// Bytes currently allocated.
+pub fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+/// Most bytes allocated at once since the last `reset_peak`.
+pub fn peak() -> usize {
+    PEAK.load(Ordering::Relaxed)
+}
+
+/// Start measuring a new high-water mark from what is allocated now.
+pub fn reset_peak() {
+    PEAK.store(allocated(), Ordering::Relaxed);
+}
+
+/// Limits for one job. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JobLimits {
+    pub timeout: Option<Duration>,
+    pub max_memory: Option<usize>,
+}
+
+/// Why a job was stopped.
+#[derive(Debug, Clone)]
+pub enum AbortReason {
+    Timeout { limit: Duration },
+    Memory { limit: usize, used: usize },
+    Cancelled,
+}
+
+impl fmt::Display for AbortReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AbortReason::Timeout { limit } => {
+                write!(
+                    f,
+                    "exceeded the {:.1}s wall-clock limit",
+                    limit.as_secs_f64()
+                )
+            }
+            AbortReason::Memory { limit, used } => write!(
+                f,
+                "exceeded the memory cap ({} MiB used, limit {} MiB)",
+                used >> 20,
+                limit >> 20
+            ),
+            AbortReason::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for AbortReason {}
+
+// The active job's limits, as plain atomics so checkpoints stay cheap.
+// A deadline of 0 and a cap of usize::MAX mean "no limit".
+static DEADLINE_NANOS: AtomicU64 = AtomicU64::new(0);
+static TIMEOUT_NANOS: AtomicU64 = AtomicU64::new(0);
+static MEMORY_CAP: AtomicUsize = AtomicUsize::new(usize::MAX);
+static MEMORY_BASE: AtomicUsize = AtomicUsize::new(0);
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+fn epoch() -> Instant {
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    *EPOCH.get_or_init(Instant::now)
+}
+
+fn now_nanos() -> u64 {
+    epoch().elapsed().as_nanos() as u64
+}
+
+/// Stop the current job if it has run out of time or memory.
+///
+/// Call this regularly from anything that can take a long time or allocate
+/// a lot. It is a couple of atomic loads when no job limits are set.
+pub fn checkpoint() {
+    if CANCELLED.load(Ordering::Relaxed) {
+        abort(AbortReason::Cancelled);
+    }
+    let cap = MEMORY_CAP.load(Ordering::Relaxed);
+    if cap != usize::MAX {
+        let used = allocated().saturating_sub(MEMORY_BASE.load(Ordering::Relaxed));
+        if used > cap {
+            abort(AbortReason::Memory { limit: cap, used });
+        }
+    }
+    let deadline = DEADLINE_NANOS.load(Ordering::Relaxed);
+    if deadline != 0 && now_nanos() > deadline {
+        let limit = Duration::from_nanos(TIMEOUT_NANOS.load(Ordering::Relaxed));
+        abort(AbortReason::Timeout { limit });
+    }
+}
+
+/// Ask the running job to stop at its next checkpoint.
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::Relaxed);
+}
+
+/// Forget a pending cancellation. Whoever schedules jobs calls this between
+/// jobs, so a late cancel can't hit the next one.
+pub fn clear_cancel() {
+    CANCELLED.store(false, Ordering::Relaxed);
+}
+
+fn abort(reason: AbortReason) -> ! {
+    // resume_unwind skips the panic hook, so nothing is printed on the way out
+    panic::resume_unwind(Box::new(reason))
+}
+
+/// Run `job` under `limits`, turning a blown limit into an error.
+pub fn run_job<T>(limits: &JobLimits, job: impl FnOnce() -> Result<T>) -> Result<T> {
+    MEMORY_BASE.store(allocated(), Ordering::Relaxed);
+    MEMORY_CAP.store(limits.max_memory.unwrap_or(usize::MAX), Ordering::Relaxed);
+    match limits.timeout {
+        Some(timeout) => {
+            TIMEOUT_NANOS.store(timeout.as_nanos() as u64, Ordering::Relaxed);
+            DEADLINE_NANOS.store(
+                now_nanos().saturating_add(timeout.as_nanos() as u64).max(1),
+                Ordering::Relaxed,
+            );
+        }
+        None => DEADLINE_NANOS.store(0, Ordering::Relaxed),
+    }
+
+    let result = panic::catch_unwind(AssertUnwindSafe(job));
+
+    MEMORY_CAP.store(usize::MAX, Ordering::Relaxed);
+    DEADLINE_NANOS.store(0, Ordering::Relaxed);
+
+    match result {
+        Ok(value) => value,
+        Err(payload) => match payload.downcast::<AbortReason>() {
+            Ok(reason) => Err(anyhow::Error::new(*reason).context("job aborted")),
+            Err(other) => panic::resume_unwind(other),
+        },
+    }
+}
@@ -0,0 +1,285 @@
+//! Sanity checks: meshes that are well formed but can't be what was meant.
+//!
+//! A mesh can pass every topology check and still be no good to print: a
+//! closed shell with nothing inside it, a part three metres across because it
+//! was modelled in one unit and read in another, walls or details too
+//! fine for the voxel grid that's about to remesh them. These checks look for that and
+//! say what to try instead.
+
+use crate::audit::{self, Code, Finding};
+use crate::bvh::Bvh;
+use crate::mesh::Mesh;
+use crate::messages;
+use crate::placement::{Placement, Units};
+use std::collections::HashMap;
+
+// A closed shell holding less than this share of the volume a ball of the
+// same surface area would have is flat: two sheets back to back
+const FLAT_VOLUME_RATIO: f64 = 1e-6;
+// Volume of a ball over its surface area to the power 3/2
+const BALL_RATIO: f64 = 0.094_031_597;
+// Bigger than this, in millimetres, and it's likely the wrong units
+const LARGEST_MM: f64 = 2000.0;
+// Walls and features under this many voxels across don't survive
+// remeshing
+const MIN_FEATURE_VOXELS: f32 = 2.0;
+// Share of the sampled walls that must be thin before we say so
+const THIN_SHARE: f64 = 0.9;
+// How many faces to measure the walls at
+const WALL_SAMPLES: usize = 2000;
+// Fewer hits than this share and it isn't a solid with walls to measure
+const MIN_WALL_HITS: f64 = 0.25;
+// The smallest feature points this far apart can show, in spacings
+const FEATURE_SPACINGS: f32 = 4.0;
+// How many points to find the spacing at, and how many cells out to look
+const SPACING_SAMPLES: usize = 2000;
+const MAX_RING: i64 = 3;
+
+/// Checks that only need the mesh: flat shells and implausible sizes.
+pub fn plausibility(mesh: &Mesh, placement: &Placement) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    // 1. Closed shells that enclose nothing
+    let flat: Vec<audit::Surface> = audit::surfaces(mesh)
+        .into_iter()
+        .filter(|s| s.closed)
+        .filter(|s| {
+            let area: f64 = s
+                .even
+                .iter()
+                .chain(&s.odd)
+                .map(|&f| face_area(mesh, f))
+                .sum();
+            area > 0.0 && s.volume.abs() < FLAT_VOLUME_RATIO * BALL_RATIO * area.powf(1.5)
+        })
+        .collect();
+    if !flat.is_empty() {
+        let mut finding = Finding::new(
+            Code::ZeroVolume,
+            flat.len(),
+            messages::text("findings.W010", &[("count", &flat.len())]),
+        );
+        let mut faces: Vec<usize> = flat
+            .iter()
+            .flat_map(|s| s.even.iter().chain(&s.odd).copied())
+            .collect();
+        faces.sort_unstable();
+        finding.faces = faces;
+        findings.push(finding);
+    }
+
+    // 2. The longest side, in the units we think it's in
+    let (min, max) = mesh.bounds();
+    let size = (0..3)
+        .map(|k| f64::from(max[k] - min[k]))
+        .fold(0.0f64, f64::max);
+    let size_mm = match placement.units {
+        Units::Millimetres => size,
+        Units::Metres => size * 1000.0,
+    };
+    if mesh.face_count() > 0 && size_mm > LARGEST_MM {
+        let message = match placement.units {
+            Units::Metres => {
+                messages::text("findings.W011_metres", &[("size", &format!("{:.1}", size))])
+            }
+            Units::Millimetres => messages::text(
+                "findings.W011_millimetres",
+                &[("size", &format!("{:.1}", size_mm / 1000.0))],
+            ),
+        };
+        findings.push(Finding::new(Code::ImplausibleScale, 1, message));
+    }
+    findings
+}
+
+/// What a remesh at `resolution` grid points along the longest side,
+/// `voxel` apart, will lose: walls under two voxels thick nearly
+/// everywhere (W012), or otherwise its smallest features (W013), with the
+/// resolution that would keep them.
+///
+/// The smallest feature is the thinnest tenth of the walls, or where there
+/// are no walls to measure (an open scan), a few times the spacing of the
+/// scan's points: nothing finer than that was captured to keep.
+pub fn resolution(mesh: &Mesh, voxel: f32, resolution: usize) -> Option<Finding> {
+    let limit = voxel * MIN_FEATURE_VOXELS;
+    // The resolution that puts two voxels across `size`
+    let enough = |size: f32| (resolution as f32 * limit / size).ceil() as usize;
+
+    // 1. Walls
+    let mut walls = wall_thickness(mesh);
+    walls.sort_by(f32::total_cmp);
+    if !walls.is_empty() {
+        let thin = walls.iter().filter(|&&t| t < limit).count();
+        let median = walls[walls.len() / 2];
+        if thin as f64 >= THIN_SHARE * walls.len() as f64 && median > 0.0 {
+            return Some(Finding::new(
+                Code::ThinWalls,
+                thin,
+                messages::text(
+                    "findings.W012",
+                    &[
+                        ("thickness", &format!("{:.3}", median)),
+                        ("voxels", &MIN_FEATURE_VOXELS),
+                        ("limit", &format!("{:.3}", limit)),
+                        ("resolution", &enough(median)),
+                    ],
+                ),
+            ));
+        }
+    }
+
+    // 2. The smallest features
+    let (smallest, key) = match walls.get(walls.len() / 10) {
+        Some(&thinnest) => (thinnest, "findings.W013_walls"),
+        None => (
+            point_spacing(&mesh.positions) * FEATURE_SPACINGS,
+            "findings.W013_spacing",
+        ),
+    };
+    if smallest <= 0.0 || smallest >= limit {
+        return None;
+    }
+    Some(Finding::new(
+        Code::CoarseResolution,
+        1,
+        messages::text(
+            key,
+            &[
+                ("size", &format!("{:.3}", smallest)),
+                ("voxels", &MIN_FEATURE_VOXELS),
+                ("limit", &format!("{:.3}", limit)),
+                ("current", &resolution),
+                ("resolution", &enough(smallest)),
+            ],
+        ),
+    ))
+}
+
+// Median distance from a point to its nearest neighbour, over up to
+// `SPACING_SAMPLES` points, found by bucketing the points on a grid about
+// as fine as the spacing we expect
+fn point_spacing(positions: &[f32]) -> f32 {
+    let points: Vec<[f32; 3]> = positions
+        .chunks_exact(3)
+        .map(|p| [p[0], p[1], p[2]])
+        .collect();
+    if points.len() < 2 {
+        return 0.0;
+    }
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in &points {
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    let diagonal = (0..3)
+        .map(|k| (max[k] - min[k]).powi(2))
+        .sum::<f32>()
+        .sqrt();
+    // Points on a surface are about diagonal / sqrt(n) apart
+    let cell = diagonal / (points.len() as f32).sqrt();
+    if cell <= 0.0 {
+        return 0.0;
+    }
+    let key = |p: [f32; 3]| [0, 1, 2].map(|k| ((p[k] - min[k]) / cell).floor() as i64);
+    let mut grid: HashMap<[i64; 3], Vec<usize>> = HashMap::new();
+    for (i, &p) in points.iter().enumerate() {
+        grid.entry(key(p)).or_default().push(i);
+    }
+
+    let step = points.len().div_ceil(SPACING_SAMPLES).max(1);
+    let mut nearest = Vec::new();
+    for i in (0..points.len()).step_by(step) {
+        let p = points[i];
+        let centre = key(p);
+        // Widen the search until what we found can't be beaten further out
+        let mut best = f32::MAX;
+        for ring in 1..=MAX_RING {
+            for dx in -ring..=ring {
+                for dy in -ring..=ring {
+                    for dz in -ring..=ring {
+                        let at = [centre[0] + dx, centre[1] + dy, centre[2] + dz];
+                        for &j in grid.get(&at).into_iter().flatten() {
+                            let q = points[j];
+                            let d = (0..3).map(|k| (q[k] - p[k]).powi(2)).sum::<f32>();
+                            if d > 0.0 && d < best {
+                                best = d;
+                            }
+                        }
+                    }
+                }
+            }
+            if best.sqrt() <= ring as f32 * cell {
+                break;
+            }
+        }
+        if best < f32::MAX {
+            nearest.push(best.sqrt());
+        }
+    }
+    if nearest.is_empty() {
+        return 0.0;
+    }
+    nearest.sort_by(f32::total_cmp);
+    nearest[nearest.len() / 2]
+}
+
+// Wall thickness at up to `WALL_SAMPLES` faces: how far a ray goes into
+// the solid from the face's centre before meeting the other side
+fn wall_thickness(mesh: &Mesh) -> Vec<f32> {
+    let faces = mesh.face_count();
+    if faces == 0 {
+        return Vec::new();
+    }
+    // Inside out (negative volume) meshes point their normals in
+    let volume: f64 = (0..faces)
+        .map(|f| {
+            let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+            (a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+                + a[2] * (b[0] * c[1] - b[1] * c[0]))
+                / 6.0
+        })
+        .sum();
+    let inward = if volume < 0.0 { 1.0 } else { -1.0 };
+    let (min, max) = mesh.bounds();
+    let diagonal = (0..3)
+        .map(|k| (max[k] - min[k]).powi(2))
+        .sum::<f32>()
+        .sqrt();
+    let nudge = diagonal * 1e-5;
+
+    let bvh = Bvh::new(mesh);
+    let step = faces.div_ceil(WALL_SAMPLES).max(1);
+    let mut tried = 0;
+    let mut walls = Vec::new();
+    for f in (0..faces).step_by(step) {
+        if face_area(mesh, f) <= 0.0 {
+            continue;
+        }
+        tried += 1;
+        let direction = mesh.face_normal(f).map(|x| x * inward);
+        let centre = mesh.face_centroid(f);
+        let origin = [0, 1, 2].map(|k| centre[k] + direction[k] * nudge);
+        if let Some(hit) = bvh.cast(origin, direction, diagonal) {
+            walls.push(hit.t + nudge);
+        }
+    }
+    if (walls.len() as f64) < MIN_WALL_HITS * tried as f64 {
+        return Vec::new();
+    }
+    walls
+}
+
+fn face_area(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt() * 0.5
+}
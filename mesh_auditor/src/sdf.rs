@@ -0,0 +1,524 @@
+//! Sampled fields and the `.mlsdf` file format.
+//!
+//! Sampling the field is by far the most expensive part of a remesh, while
+//! extracting the surface is cheap. So we sample once into a dense grid and
+//! keep that grid around as a file, which can be re-extracted later at
+//! different isovalues.
+//!
+//! # The `.mlsdf` format (version 2)
+//!
+//! All numbers are little-endian. The file is a fixed 52-byte header followed
+//! by the samples:
+//!
+//! | offset | size | type     | meaning                                       |
+//! |--------|------|----------|-----------------------------------------------|
+//! | 0      | 6    | bytes    | magic `MLSDF\0`                               |
+//! | 6      | 2    | u16      | format version (`1` or `2`)                   |
+//! | 8      | 1    | u8       | field kind (see below)                        |
+//! | 9      | 1    | u8       | sample encoding (see below; `0` in version 1) |
+//! | 10     | 2    | -        | reserved, must be zero                        |
+//! | 12     | 12   | u32 x 3  | grid dimensions `nx, ny, nz`                  |
+//! | 24     | 12   | f32 x 3  | world position of sample `(0, 0, 0)`          |
+//! | 36     | 12   | f32 x 3  | distance between samples along x, y and z     |
+//! | 48     | 4    | f32      | suggested isovalue for extraction             |
+//! | 52     | ...  | ...      | `nx * ny * nz` samples, x varies fastest      |
+//!
+//! Sample `(x, y, z)` is stored at index `x + nx * (y + ny * z)` and sits at
+//! world position `origin + (x, y, z) * spacing`.
+//!
+//! The field kind tells the extractor which side of the isovalue is solid:
+//!
+//! * `0` — density: values **above** the isovalue are inside (the voxel
+//!   remesher's occupancy field, where 1.0 means "near the scan").
+//! * `1` — signed distance: values **below** the isovalue are inside
+//!   (negative inside, positive outside, the usual SDF convention).
+//!
+//! The sample encoding is how the samples are packed:
+//!
+//! * `0` — f32 per sample.
+//! * `1` — a bit per sample, 1.0 or 0.0, for occupancy fields: sample `i`
+//!   is bit `i % 8` of byte `i / 8`, the last byte padded with zeros.
+//! * `2` — IEEE 754 half float (binary16) per sample.
+//!
+//! Fields stored as floats are written as version 1, which older readers
+//! understand; anything packed needs version 2.
+
+use crate::limits::InputLimits;
+use crate::samples::{Samples, Storage};
+use crate::sandbox;
+use crate::slabs::{self, SlabField, Slabs};
+use crate::storage;
+use anyhow::{bail, Context, Result};
+use half::f16;
+use std::io::{BufReader, Read, Write};
+
+const MAGIC: &[u8; 6] = b"MLSDF\0";
+const VERSION: u16 = 2;
+// The version float-only files are written as
+const FLOAT_VERSION: u16 = 1;
+const HEADER_LEN: usize = 52;
+
+// The "Voxel Grid" trait: anything that can answer
+// "What is the value at grid coordinates (x, y, z)?"
+pub trait Field {
+    fn dimensions(&self) -> [usize; 3];
+    fn z(&self, x: usize, y: usize, z: usize) -> f64;
+
+    /// Every sample along row (y, z), x from 0 up. Fields that answer many
+    /// points at once faster than one at a time override this.
+    fn row(&self, y: usize, z: usize) -> Vec<f64> {
+        (0..self.dimensions()[0]).map(|x| self.z(x, y, z)).collect()
+    }
+
+    /// For density fields, the radius (in world units) of the smallest
+    /// ball the solid is made of, if there is one: coarse-to-fine
+    /// sampling needs it to know what a coarse cell can't be hiding.
+    fn feature_radius(&self) -> Option<f32> {
+        None
+    }
+}
+
+impl<F: Field + ?Sized> Field for &F {
+    fn dimensions(&self) -> [usize; 3] {
+        (**self).dimensions()
+    }
+
+    fn z(&self, x: usize, y: usize, z: usize) -> f64 {
+        (**self).z(x, y, z)
+    }
+
+    fn row(&self, y: usize, z: usize) -> Vec<f64> {
+        (**self).row(y, z)
+    }
+
+    fn feature_radius(&self) -> Option<f32> {
+        (**self).feature_radius()
+    }
+}
+
+/// Which side of the isovalue counts as solid material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Density,
+    SignedDistance,
+}
+
+impl FieldKind {
+    fn to_byte(self) -> u8 {
+        match self {
+            FieldKind::Density => 0,
+            FieldKind::SignedDistance => 1,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        match byte {
+            0 => Ok(FieldKind::Density),
+            1 => Ok(FieldKind::SignedDistance),
+            other => bail!("unknown field kind {} in .mlsdf header", other),
+        }
+    }
+
+    /// Is a sample with this value inside the solid at the given isovalue?
+    pub fn is_inside(self, value: f32, iso: f32) -> bool {
+        match self {
+            FieldKind::Density => value > iso,
+            FieldKind::SignedDistance => value < iso,
+        }
+    }
+}
+
+/// A field that has been evaluated at every grid point.
+#[derive(Debug, Clone)]
+pub struct SampledField {
+    pub dims: [usize; 3],
+    pub origin: [f32; 3],
+    pub spacing: [f32; 3],
+    pub kind: FieldKind,
+    pub iso: f32,
+    pub values: Samples,
+}
+
+impl SampledField {
+    /// Evaluate `field` at every grid point, keeping the samples as
+    /// `storage`, split across threads as `slabs` says. This is the slow
+    /// part.
+    pub fn sample<F: SlabField>(
+        field: &F,
+        origin: [f32; 3],
+        spacing: [f32; 3],
+        kind: FieldKind,
+        iso: f32,
+        storage: Storage,
+        slabs: &Slabs,
+    ) -> Self {
+        let dims = field.dimensions();
+        let values = slabs::sample(
+            field,
+            slabs,
+            Samples::with_capacity(storage, dims[0] * dims[1] * dims[2]),
+        );
+        SampledField {
+            dims,
+            origin,
+            spacing,
+            kind,
+            iso,
+            values,
+        }
+    }
+
+    pub fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        x + self.dims[0] * (y + self.dims[1] * z)
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values.get(self.index(x, y, z))
+    }
+
+    /// World coordinates of grid point (x, y, z).
+    pub fn position(&self, x: usize, y: usize, z: usize) -> [f32; 3] {
+        [
+            self.origin[0] + x as f32 * self.spacing[0],
+            self.origin[1] + y as f32 * self.spacing[1],
+            self.origin[2] + z as f32 * self.spacing[2],
+        ]
+    }
+
+    /// World-space corners of the sampled region.
+    pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+        let max = self.position(self.dims[0] - 1, self.dims[1] - 1, self.dims[2] - 1);
+        (self.origin, max)
+    }
+
+    /// Trilinearly interpolate the field at an arbitrary world position.
+    ///
+    /// Points outside the grid are clamped onto it; for signed distance
+    /// fields the distance to the grid is added so the outside stays outside.
+    pub fn value_at(&self, p: [f32; 3]) -> f32 {
+        let mut cell = [0usize; 3];
+        let mut frac = [0.0f32; 3];
+        let mut outside_sq = 0.0f32;
+        for axis in 0..3 {
+            let last = (self.dims[axis] - 1) as f32;
+            let t = if self.spacing[axis] > 0.0 {
+                (p[axis] - self.origin[axis]) / self.spacing[axis]
+            } else {
+                0.0
+            };
+            let clamped = t.clamp(0.0, last);
+            outside_sq += ((t - clamped) * self.spacing[axis]).powi(2);
+            let base = (clamped.floor() as usize).min(self.dims[axis].saturating_sub(2));
+            cell[axis] = base;
+            frac[axis] = if self.dims[axis] > 1 {
+                clamped - base as f32
+            } else {
+                0.0
+            };
+        }
+
+        let step = |axis: usize| usize::from(self.dims[axis] > 1);
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let mut weight = 1.0;
+            let mut idx = [0usize; 3];
+            for axis in 0..3 {
+                let bit = (corner >> axis) & 1;
+                idx[axis] = cell[axis] + bit * step(axis);
+                weight *= if bit == 1 {
+                    frac[axis]
+                } else {
+                    1.0 - frac[axis]
+                };
+            }
+            value += weight * self.get(idx[0], idx[1], idx[2]);
+        }
+
+        match self.kind {
+            FieldKind::SignedDistance => value + outside_sq.sqrt(),
+            FieldKind::Density => value,
+        }
+    }
+
+    /// Re-express the field as a signed distance with the surface at 0.
+    ///
+    /// Density fields just get flipped around their isovalue, which gives
+    /// the right sign everywhere but is only a rough stand-in for distance.
+    pub fn to_signed_distance(&self) -> SampledField {
+        let iso = self.iso;
+        let values = match self.kind {
+            FieldKind::SignedDistance => self.values.iter().map(|v| v - iso).collect(),
+            FieldKind::Density => self.values.iter().map(|v| iso - v).collect(),
+        };
+        SampledField {
+            dims: self.dims,
+            origin: self.origin,
+            spacing: self.spacing,
+            kind: FieldKind::SignedDistance,
+            iso: 0.0,
+            values,
+        }
+    }
+
+    /// Evaluate this field on a different grid.
+    pub fn resample(&self, origin: [f32; 3], spacing: [f32; 3], dims: [usize; 3]) -> SampledField {
+        let mut grid = SampledField {
+            dims,
+            origin,
+            spacing,
+            kind: self.kind,
+            iso: self.iso,
+            values: Samples::with_capacity(Storage::Full, dims[0] * dims[1] * dims[2]),
+        };
+        for z in 0..dims[2] {
+            for y in 0..dims[1] {
+                sandbox::checkpoint();
+                for x in 0..dims[0] {
+                    let v = self.value_at(grid.position(x, y, z));
+                    grid.values.push(v);
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn save(&self, filename: &str) -> Result<()> {
+        let mut out = storage::create(filename)?;
+
+        out.write_all(MAGIC)?;
+        let (version, encoding) = match self.values.storage() {
+            Storage::Full => (FLOAT_VERSION, 0),
+            Storage::Bits => (VERSION, 1),
+            Storage::Half => (VERSION, 2),
+        };
+        out.write_all(&version.to_le_bytes())?;
+        out.write_all(&[self.kind.to_byte(), encoding, 0, 0])?;
+        for d in self.dims {
+            out.write_all(&(d as u32).to_le_bytes())?;
+        }
+        for v in self.origin.iter().chain(self.spacing.iter()) {
+            out.write_all(&v.to_le_bytes())?;
+        }
+        out.write_all(&self.iso.to_le_bytes())?;
+        match &self.values {
+            Samples::Full(values) => {
+                for v in values {
+                    out.write_all(&v.to_le_bytes())?;
+                }
+            }
+            Samples::Bits { len, words } => {
+                let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+                out.write_all(&bytes[..len.div_ceil(8)])?;
+            }
+            Samples::Half(values) => {
+                for v in values {
+                    out.write_all(&v.to_le_bytes())?;
+                }
+            }
+        }
+        out.finish()
+    }
+
+    pub fn load(filename: &str, limits: &InputLimits) -> Result<Self> {
+        let mut input = BufReader::new(storage::open(filename, limits)?);
+
+        let mut header = [0u8; HEADER_LEN];
+        input
+            .read_exact(&mut header)
+            .context("file is too short to be an .mlsdf field")?;
+        if &header[0..6] != MAGIC {
+            bail!("{} is not an .mlsdf file (bad magic)", filename);
+        }
+        let version = u16::from_le_bytes([header[6], header[7]]);
+        if !(FLOAT_VERSION..=VERSION).contains(&version) {
+            bail!(
+                "unsupported .mlsdf version {} (expected {} to {})",
+                version,
+                FLOAT_VERSION,
+                VERSION
+            );
+        }
+        let kind = FieldKind::from_byte(header[8])?;
+        let storage = match header[9] {
+            0 => Storage::Full,
+            1 if version >= 2 => Storage::Bits,
+            2 if version >= 2 => Storage::Half,
+            other => bail!(
+                "unknown sample encoding {} in a version {} .mlsdf header",
+                other,
+                version
+            ),
+        };
+
+        let read_u32 = |at: usize| {
+            u32::from_le_bytes([header[at], header[at + 1], header[at + 2], header[at + 3]])
+        };
+        let read_f32 = |at: usize| f32::from_bits(read_u32(at));
+
+        let dims = [
+            read_u32(12) as usize,
+            read_u32(16) as usize,
+            read_u32(20) as usize,
+        ];
+        let origin = [read_f32(24), read_f32(28), read_f32(32)];
+        let spacing = [read_f32(36), read_f32(40), read_f32(44)];
+        let iso = read_f32(48);
+
+        if dims.contains(&0) {
+            bail!("grid dimensions must be at least 1 ({:?})", dims);
+        }
+        if !origin
+            .iter()
+            .chain(&spacing)
+            .chain([&iso])
+            .all(|v| v.is_finite())
+            || spacing.iter().any(|&s| s < 0.0)
+        {
+            bail!("grid origin, spacing and isovalue must be finite (and spacing positive)");
+        }
+
+        let count = dims[0]
+            .checked_mul(dims[1])
+            .and_then(|n| n.checked_mul(dims[2]))
+            .context("grid dimensions overflow")?;
+        let data_len = match storage {
+            Storage::Full => count.checked_mul(4).context("grid dimensions overflow")?,
+            Storage::Bits => count.div_ceil(8),
+            Storage::Half => count.checked_mul(2).context("grid dimensions overflow")?,
+        };
+
+        // Read what is actually there (bounded by the input limit) rather
+        // than allocating whatever a forged header asks for
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        if bytes.len() != data_len {
+            bail!(
+                "header promises {} bytes of samples but the file holds {}",
+                data_len,
+                bytes.len()
+            );
+        }
+        let values = match storage {
+            Storage::Full => bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+            Storage::Bits => Samples::Bits {
+                len: count,
+                words: bytes
+                    .chunks(8)
+                    .map(|b| {
+                        let mut word = [0u8; 8];
+                        word[..b.len()].copy_from_slice(b);
+                        u64::from_le_bytes(word)
+                    })
+                    .collect(),
+            },
+            Storage::Half => Samples::Half(
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| f16::from_le_bytes([b[0], b[1]]))
+                    .collect(),
+            ),
+        };
+
+        Ok(SampledField {
+            dims,
+            origin,
+            spacing,
+            kind,
+            iso,
+            values,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(storage: Storage) -> SampledField {
+        let dims = [3, 4, 5];
+        let values = (0..60)
+            .map(|i| match storage {
+                Storage::Bits => (i % 3 == 0) as u8 as f32,
+                _ => i as f32 * 0.25 - 4.0,
+            })
+            .collect::<Vec<f32>>();
+        let mut samples = Samples::with_capacity(storage, values.len());
+        for v in values {
+            samples.push(v);
+        }
+        SampledField {
+            dims,
+            origin: [-1.0, 0.5, 2.0],
+            spacing: [0.5, 0.25, 0.125],
+            kind: FieldKind::SignedDistance,
+            iso: 0.75,
+            values: samples,
+        }
+    }
+
+    fn temp(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("{}_{}.mlsdf", name, std::process::id()));
+        path.to_string_lossy().into_owned()
+    }
+
+    fn round_trip(storage: Storage) {
+        let path = temp(&format!("sdf_round_trip_{:?}", storage));
+        let saved = field(storage);
+        saved.save(&path).unwrap();
+        let loaded = SampledField::load(&path, &InputLimits::default()).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(loaded.dims, saved.dims);
+        assert_eq!(loaded.origin, saved.origin);
+        assert_eq!(loaded.spacing, saved.spacing);
+        assert_eq!(loaded.kind, saved.kind);
+        assert_eq!(loaded.iso, saved.iso);
+        assert_eq!(loaded.values.storage(), storage);
+        assert!(loaded.values.iter().eq(saved.values.iter()));
+    }
+
+    #[test]
+    fn floats_round_trip() {
+        round_trip(Storage::Full);
+    }
+
+    #[test]
+    fn halves_round_trip() {
+        round_trip(Storage::Half);
+    }
+
+    #[test]
+    fn bits_round_trip() {
+        round_trip(Storage::Bits);
+    }
+
+    #[test]
+    fn damaged_files_are_refused() {
+        let path = temp("sdf_damaged");
+        field(Storage::Full).save(&path).unwrap();
+        let good = std::fs::read(&path).unwrap();
+        let load = |bytes: &[u8]| {
+            std::fs::write(&path, bytes).unwrap();
+            SampledField::load(&path, &InputLimits::default())
+        };
+
+        assert!(load(&good[..HEADER_LEN - 1]).is_err());
+        assert!(load(&good[..good.len() - 1]).is_err());
+        let mut magic = good.clone();
+        magic[0] = b'X';
+        assert!(load(&magic).is_err());
+        let mut version = good.clone();
+        version[6] = 9;
+        assert!(load(&version).is_err());
+        // A forged header asking for a huge grid over a few bytes of samples
+        let mut huge = good.clone();
+        huge[12..24].copy_from_slice(&[0xff; 12]);
+        assert!(load(&huge).is_err());
+        let mut spacing = good.clone();
+        spacing[36..40].copy_from_slice(&f32::NAN.to_le_bytes());
+        assert!(load(&spacing).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+}
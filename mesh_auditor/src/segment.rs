@@ -0,0 +1,265 @@
+//! Region-growing segmentation: splitting a scan into smooth regions.
+//!
+//! A region is grown face by face across shared edges from a seed, taking
+//! in each neighbour whose normal is within `angle` of the face it was
+//! reached from, so it follows a gently curving surface (a cylinder wall,
+//! a rounded fillet) but stops at a crease. Only faces that are smooth
+//! themselves, whose mean turn to their neighbours is under `curvature`,
+//! grow the region further; a face on a crease joins it without spreading
+//! it over the crease. Seeds are taken flattest first, so regions start in
+//! the middle of faces rather than on their edges.
+//!
+//! Scan noise leaves slivers along every crease, so regions under
+//! `min_faces` are then merged into whichever neighbour they share most
+//! edges with. Regions are numbered biggest first. They are the raw
+//! material for fitting planes and cylinders, or for processing one part
+//! of a scan and not the rest.
+
+use crate::audit;
+use crate::labels::FaceLabels;
+use crate::mesh::Mesh;
+use crate::output::WriteOptions;
+use crate::sandbox;
+use anyhow::{bail, Result};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::Path;
+
+/// How to segment.
+#[derive(Debug, Clone, Copy)]
+pub struct SegmentOptions {
+    /// Neighbouring faces join a region while their normals are within
+    /// this many degrees.
+    pub angle: f64,
+    /// Faces whose mean turn to their neighbours is more than this many
+    /// degrees join a region but don't grow it.
+    pub curvature: f64,
+    /// Regions with fewer faces are merged into a neighbour.
+    pub min_faces: usize,
+}
+
+impl Default for SegmentOptions {
+    fn default() -> Self {
+        SegmentOptions {
+            angle: 12.0,
+            curvature: 6.0,
+            min_faces: 20,
+        }
+    }
+}
+
+/// The regions found.
+#[derive(Debug, Clone)]
+pub struct Segmentation {
+    /// Region of each face.
+    pub face_regions: Vec<usize>,
+    /// Biggest (by area) first.
+    pub regions: Vec<Region>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Region {
+    pub faces: usize,
+    pub area: f64,
+    /// Area-weighted mean of the face normals: long for a flat region,
+    /// shorter the more it curves.
+    pub mean_normal: [f64; 3],
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// Split `mesh` into smooth regions.
+pub fn segment(mesh: &Mesh, options: &SegmentOptions) -> Result<Segmentation> {
+    if mesh.face_count() == 0 {
+        bail!("nothing to segment: the mesh has no faces");
+    }
+    if !(options.angle > 0.0 && options.angle < 180.0) {
+        bail!("the segmentation angle must be between 0 and 180 degrees");
+    }
+    if options.curvature.is_nan() || options.curvature < 0.0 {
+        bail!("the curvature limit can't be negative");
+    }
+    let count = mesh.face_count();
+    let normals: Vec<[f64; 3]> = (0..count)
+        .map(|f| mesh.face_normal(f).map(f64::from))
+        .collect();
+
+    // 1. Faces across each manifold edge, and how much each face bends
+    let mut neighbours = vec![Vec::new(); count];
+    for faces in audit::edge_faces(mesh).values() {
+        if let [(f, _), (g, _)] = faces[..] {
+            neighbours[f].push(g);
+            neighbours[g].push(f);
+        }
+    }
+    let bend: Vec<f64> = (0..count)
+        .map(|f| {
+            let turns = neighbours[f].iter().map(|&g| turn(normals[f], normals[g]));
+            turns.sum::<f64>() / neighbours[f].len().max(1) as f64
+        })
+        .collect();
+
+    // 2. Grow regions from the flattest faces out
+    let joins = options.angle.to_radians().cos();
+    let smooth = options.curvature.to_radians();
+    let mut order: Vec<usize> = (0..count).collect();
+    order.sort_by(|&a, &b| bend[a].total_cmp(&bend[b]).then(a.cmp(&b)));
+    let mut face_regions = vec![usize::MAX; count];
+    let mut regions = 0;
+    for &seed in &order {
+        if face_regions[seed] != usize::MAX {
+            continue;
+        }
+        sandbox::checkpoint();
+        face_regions[seed] = regions;
+        let mut queue = VecDeque::from([seed]);
+        while let Some(f) = queue.pop_front() {
+            // A crease face stops here, unless it is the seed itself
+            if bend[f] > smooth && f != seed {
+                continue;
+            }
+            for &g in &neighbours[f] {
+                if face_regions[g] == usize::MAX && dot(normals[f], normals[g]) >= joins {
+                    face_regions[g] = regions;
+                    queue.push_back(g);
+                }
+            }
+        }
+        regions += 1;
+    }
+
+    // 3. Merge slivers into the neighbour they share most edges with,
+    // smallest first
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); regions];
+    for (f, &r) in face_regions.iter().enumerate() {
+        members[r].push(f);
+    }
+    let mut small: Vec<usize> = (0..regions)
+        .filter(|&r| members[r].len() < options.min_faces)
+        .collect();
+    small.sort_by_key(|&r| (members[r].len(), r));
+    for r in small {
+        // It may have grown from slivers merged into it
+        if members[r].is_empty() || members[r].len() >= options.min_faces {
+            continue;
+        }
+        let mut shared: HashMap<usize, usize> = HashMap::new();
+        for &f in &members[r] {
+            for &g in &neighbours[f] {
+                if face_regions[g] != r {
+                    *shared.entry(face_regions[g]).or_default() += 1;
+                }
+            }
+        }
+        // A piece on its own stays a region of its own
+        let Some((into, _)) = shared
+            .into_iter()
+            .max_by_key(|&(other, edges)| (edges, std::cmp::Reverse(other)))
+        else {
+            continue;
+        };
+        let moved = std::mem::take(&mut members[r]);
+        for &f in &moved {
+            face_regions[f] = into;
+        }
+        members[into].extend(moved);
+    }
+
+    // 4. Number what's left biggest first, and describe each region
+    let mut described: Vec<(usize, Region)> = members
+        .iter()
+        .enumerate()
+        .filter(|(_, faces)| !faces.is_empty())
+        .map(|(r, faces)| (r, describe(mesh, faces, &normals)))
+        .collect();
+    described.sort_by(|a, b| b.1.area.total_cmp(&a.1.area).then(a.0.cmp(&b.0)));
+    let mut renumber = vec![usize::MAX; regions];
+    for (new, (old, _)) in described.iter().enumerate() {
+        renumber[*old] = new;
+    }
+    for r in &mut face_regions {
+        *r = renumber[*r];
+    }
+    Ok(Segmentation {
+        face_regions,
+        regions: described.into_iter().map(|(_, region)| region).collect(),
+    })
+}
+
+impl Segmentation {
+    /// The regions as a face-label map, named `region_0`, `region_1`, ...
+    pub fn labels(&self) -> FaceLabels {
+        FaceLabels {
+            names: (0..self.regions.len())
+                .map(|r| format!("region_{}", r))
+                .collect(),
+            faces: self.face_regions.iter().map(|&r| r as u32).collect(),
+        }
+    }
+
+    /// Write each region as `region_N.obj` in `dir`, with a `regions.json`
+    /// index describing them.
+    pub fn write_set(&self, mesh: &Mesh, dir: &Path, options: &WriteOptions) -> Result<()> {
+        std::fs::create_dir_all(dir)?;
+        let mut entries = Vec::with_capacity(self.regions.len());
+        for (r, region) in self.regions.iter().enumerate() {
+            sandbox::checkpoint();
+            let file = format!("region_{}.obj", r);
+            mesh.select_faces(|f| self.face_regions[f] == r)
+                .save_obj(&dir.join(&file).to_string_lossy(), options)?;
+            entries.push(serde_json::json!({ "file": file, "region": region }));
+        }
+        let index = serde_json::json!({ "faces": mesh.face_count(), "regions": entries });
+        std::fs::write(dir.join("regions.json"), serde_json::to_vec_pretty(&index)?)?;
+        Ok(())
+    }
+}
+
+fn describe(mesh: &Mesh, faces: &[usize], normals: &[[f64; 3]]) -> Region {
+    let mut area = 0.0;
+    let mut weighted = [0.0f64; 3];
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for &f in faces {
+        let a = face_area(mesh, f);
+        area += a;
+        for k in 0..3 {
+            weighted[k] += normals[f][k] * a;
+        }
+        for v in mesh.face(f) {
+            let p = mesh.vertex(v);
+            for k in 0..3 {
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+        }
+    }
+    Region {
+        faces: faces.len(),
+        area,
+        mean_normal: weighted.map(|x| x / area.max(f64::MIN_POSITIVE)),
+        min,
+        max,
+    }
+}
+
+// The angle between two unit normals, in radians
+fn turn(a: [f64; 3], b: [f64; 3]) -> f64 {
+    dot(a, b).clamp(-1.0, 1.0).acos()
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn face_area(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    0.5 * (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt()
+}
@@ -0,0 +1,425 @@
+//! `serve`: the remesher as an HTTP service.
+//!
+//! | method & path             | does                                        |
+//! |---------------------------|---------------------------------------------|
+//! | `POST /jobs`              | submit an OBJ (request body or `input=`); returns the job |
+//! | `GET /jobs`               | list all jobs                               |
+//! | `GET /jobs/<id>`          | poll one job's status                       |
+//! | `GET /jobs/<id>/progress` | how far a running job is, from 0 to 1       |
+//! | `GET /jobs/<id>/result`   | download the remeshed STL once it is done   |
+//! | `DELETE /jobs/<id>`       | cancel a queued or running job              |
+//! | `GET /usage`              | this month's usage for the caller's API key |
+//! | `GET /metrics`            | Prometheus metrics (see `metrics`)          |
+//! | `GET /`                   | a drag-and-drop page for all of the above   |
+//!
+//! `POST /jobs` takes optional query parameters `priority` (higher runs
+//! first, default 0), `resolution`, `iso` and `callback` (a URL to POST a
+//! summary to when the job finishes, overriding the server's default; see
+//! `webhook`). Jobs are remeshed as `mesh_lifter remesh` does by default,
+//! through the signed distance to the scan's faces, so `iso` is an offset
+//! from the surface in model units (a density between 0 and 1 for a bare
+//! point cloud). Instead of uploading the scan, a client can name it with
+//! `input=s3://bucket/key` (or an `https://` URL), and `output=` asks for the
+//! result to be written there as well. Local paths are refused: they would
+//! let any client read or write files on the server. Remote inputs, outputs
+//! and callbacks are refused too unless they're under a prefix the operator
+//! allowed with `--allow-remote` (see `allowlist`).
+//!
+//! When API keys are configured (see `auth`), everything but `/metrics` and
+//! the page needs one, and each key only sees its own jobs. `/metrics` stays
+//! open for the scraper; keep the port off the public internet.
+//!
+//! The page is the service's front-end: drop a scan on it, pick a quality,
+//! and it submits the job, shows its progress and then the result in the
+//! same viewer as the audit report. It's plain HTML over the API, so it
+//! works from any browser that can reach the server. (`mesh_lifter-gui` is
+//! the same on the desktop, with no server.)
+
+use crate::allowlist::Allowlist;
+use crate::auth::{Auth, Usage};
+use crate::jobs::{JobQueue, JobRequest, JobStatus};
+use crate::limits::InputLimits;
+use crate::output::WriteOptions;
+use crate::progress;
+use crate::remesh;
+use crate::report::VIEWER;
+use crate::sandbox::{self, JobLimits};
+use crate::slabs::Slabs;
+use crate::storage::Location;
+use crate::webhook::Callbacks;
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use std::io::Read;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+type HttpResponse = Response<std::io::Cursor<Vec<u8>>>;
+
+const PAGE: &str = include_str!("gui.html");
+
+/// How the server is set up.
+pub struct ServeConfig {
+    pub addr: String,
+    pub data_dir: PathBuf,
+    /// Callback for jobs that don't bring their own.
+    pub callback: Option<String>,
+    /// Base URL clients use to reach us (defaults to `http://<addr>`).
+    pub public_url: Option<String>,
+    /// TOML file of API keys (else `MESH_API_KEYS`, else no auth).
+    pub api_keys: Option<PathBuf>,
+    /// Remote locations clients may name for inputs, outputs and callbacks.
+    pub allow_remote: Allowlist,
+    /// How job results are written.
+    pub write: WriteOptions,
+    /// How jobs split their sampling across threads.
+    pub slabs: Slabs,
+}
+
+pub fn serve(config: ServeConfig, limits: InputLimits, job_limits: JobLimits) -> Result<()> {
+    let addr = &config.addr;
+    if let Some(url) = &config.callback {
+        check_callback_url(url)?;
+    }
+    let callbacks = Callbacks {
+        default_url: config.callback.clone(),
+        public_url: config
+            .public_url
+            .clone()
+            .unwrap_or_else(|| format!("http://{}", addr)),
+    };
+    let auth = Auth::load(config.api_keys.as_deref())?;
+    let queue = Arc::new(JobQueue::open(
+        &config.data_dir,
+        callbacks,
+        config.write,
+        config.slabs,
+    )?);
+    let pending = queue
+        .list()
+        .iter()
+        .filter(|j| j.status == JobStatus::Queued)
+        .count();
+
+    let server = Server::http(addr).map_err(|e| anyhow!("could not listen on {}: {}", addr, e))?;
+    println!("-----------------------------------------");
+    println!("🌐 SERVING on http://{}", addr);
+    println!("   • Data dir: {}", config.data_dir.display());
+    if let Some(url) = &config.callback {
+        println!("   • Default callback: {}", url);
+    }
+    if auth.is_open() {
+        println!("   ⚠️  No API keys configured: anyone who can reach this port can use it");
+    } else {
+        println!("   • API keys: {}", auth.key_count());
+    }
+    if config.allow_remote.is_empty() {
+        println!("   • Remote inputs, outputs and callbacks: off");
+    } else {
+        println!(
+            "   • Remote locations allowed under: {}",
+            config.allow_remote.prefixes().join(", ")
+        );
+    }
+    println!("   • Jobs waiting from last run: {}", pending);
+    println!("-----------------------------------------");
+
+    // One worker: job limits are enforced process-wide
+    let worker_queue = Arc::clone(&queue);
+    std::thread::spawn(move || {
+        if let Err(e) = worker_queue.work(&limits, &job_limits) {
+            println!("❌ Worker stopped: {:#}", e);
+            std::process::exit(1);
+        }
+    });
+
+    for mut request in server.incoming_requests() {
+        let response = match handle(&queue, &auth, &config.allow_remote, &mut request, &limits) {
+            Ok(response) => response,
+            Err(e) => json_response(
+                500,
+                &ErrorBody {
+                    error: format!("{:#}", e),
+                },
+            ),
+        };
+        // The client may have gone away; nothing useful to do about it
+        let _ = request.respond(response);
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Serialize)]
+struct Progress {
+    fraction: f64,
+}
+
+fn handle(
+    queue: &JobQueue,
+    auth: &Auth,
+    allow_remote: &Allowlist,
+    request: &mut Request,
+    limits: &InputLimits,
+) -> Result<HttpResponse> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let parts: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    if let (Method::Get, ["metrics"]) = (request.method(), parts.as_slice()) {
+        let jobs = queue.list();
+        let count = |status| jobs.iter().filter(|j| j.status == status).count();
+        let text = queue.metrics.render(
+            count(JobStatus::Queued),
+            count(JobStatus::Running),
+            sandbox::allocated(),
+        );
+        return Ok(Response::from_data(text.into_bytes())
+            .with_header(header("Content-Type", "text/plain; version=0.0.4")));
+    }
+
+    // The page holds no data, so it needs no key
+    if let (Method::Get, [""]) = (request.method(), parts.as_slice()) {
+        let page = PAGE.replace("{{VIEWER}}", VIEWER);
+        return Ok(Response::from_data(page.into_bytes())
+            .with_header(header("Content-Type", "text/html; charset=utf-8")));
+    }
+
+    // Who's asking? (None when the server is open)
+    let caller = if auth.is_open() {
+        None
+    } else {
+        let Some(key) = api_key(request).and_then(|secret| auth.authenticate(&secret)) else {
+            return Ok(error(401, "missing or unknown API key"));
+        };
+        if let Err(wait) = auth.check_rate(key) {
+            return Ok(error(429, "rate limit exceeded")
+                .with_header(header("Retry-After", &wait.to_string())));
+        }
+        Some(key)
+    };
+    let owner = caller.map(|key| key.name.clone());
+    let find = |id: &str| {
+        queue
+            .get(id)
+            .filter(|job| caller.is_none() || job.owner == owner)
+    };
+
+    match (request.method(), parts.as_slice()) {
+        (Method::Post, ["jobs"]) => {
+            let mut job_request = match parse_job_request(query, allow_remote) {
+                Ok(r) => r,
+                Err(e) => return Ok(error(400, &e)),
+            };
+            if let Some(key) = caller {
+                // Before reading the upload: no point taking it if we'll refuse
+                if let Some(why) = Usage::this_month(key, &queue.list()).exceeded() {
+                    return Ok(error(429, &why));
+                }
+            }
+            job_request.owner = owner.clone();
+            // Read at most one byte past the limit so we can tell it was too big
+            let mut body = Vec::new();
+            request
+                .as_reader()
+                .take(limits.max_input_size.saturating_add(1))
+                .read_to_end(&mut body)?;
+            if body.len() as u64 > limits.max_input_size {
+                return Ok(error(413, "upload exceeds the maximum input size"));
+            }
+            match (&job_request.input, body.is_empty()) {
+                (None, true) => return Ok(error(400, "request body must be an OBJ file")),
+                (Some(_), false) => {
+                    return Ok(error(400, "send either a request body or input=, not both"))
+                }
+                _ => {}
+            }
+            let job = queue.submit(&body, job_request)?;
+            Ok(json_response(201, &job))
+        }
+        (Method::Get, ["jobs"]) => {
+            let mut jobs = queue.list();
+            jobs.retain(|job| caller.is_none() || job.owner == owner);
+            Ok(json_response(200, &jobs))
+        }
+        (Method::Get, ["usage"]) => match caller {
+            Some(key) => Ok(json_response(200, &Usage::this_month(key, &queue.list()))),
+            None => Ok(error(404, "no API keys are configured")),
+        },
+        (Method::Get, ["jobs", id]) => match find(id) {
+            Some(job) => Ok(json_response(200, &job)),
+            None => Ok(error(404, "no such job")),
+        },
+        (Method::Get, ["jobs", id, "progress"]) => match find(id) {
+            // One job runs at a time, so the running one owns the progress
+            Some(job) => {
+                let fraction = match job.status {
+                    JobStatus::Queued => 0.0,
+                    JobStatus::Running => progress::fraction(),
+                    _ => 1.0,
+                };
+                Ok(json_response(200, &Progress { fraction }))
+            }
+            None => Ok(error(404, "no such job")),
+        },
+        (Method::Get, ["jobs", id, "result"]) => match find(id) {
+            Some(job) if job.status == JobStatus::Done => {
+                let bytes = std::fs::read(queue.output_path(id))?;
+                Ok(Response::from_data(bytes).with_header(header("Content-Type", "model/stl")))
+            }
+            Some(_) => Ok(error(409, "job has no result (yet)")),
+            None => Ok(error(404, "no such job")),
+        },
+        (Method::Delete, ["jobs", id]) => match find(id) {
+            Some(job) if job.is_finished() => Ok(error(409, "job has already finished")),
+            Some(_) => Ok(json_response(200, &queue.cancel(id)?)),
+            None => Ok(error(404, "no such job")),
+        },
+        _ => Ok(error(404, "not found")),
+    }
+}
+
+fn parse_job_request(
+    query: &str,
+    allow_remote: &Allowlist,
+) -> std::result::Result<JobRequest, String> {
+    let mut request = JobRequest {
+        priority: 0,
+        resolution: remesh::DEFAULT_RESOLUTION,
+        iso: None,
+        callback: None,
+        input: None,
+        output: None,
+        owner: None,
+    };
+    for pair in query.split('&').filter(|p| !p.is_empty()) {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let bad = || format!("bad value for {}: '{}'", key, value);
+        match key {
+            "priority" => request.priority = value.parse().map_err(|_| bad())?,
+            "resolution" => request.resolution = value.parse().map_err(|_| bad())?,
+            "iso" => request.iso = Some(value.parse().map_err(|_| bad())?),
+            "callback" => {
+                let url = percent_decode(value).ok_or_else(bad)?;
+                check_callback_url(&url).map_err(|e| e.to_string())?;
+                allow_remote
+                    .check(&url)
+                    .map_err(|e| format!("callback: {:#}", e))?;
+                request.callback = Some(url);
+            }
+            "input" | "output" => {
+                let location = remote_location(value).ok_or_else(bad)?;
+                allow_remote
+                    .check(&location)
+                    .map_err(|e| format!("{}: {:#}", key, e))?;
+                if key == "input" {
+                    request.input = Some(location);
+                } else {
+                    request.output = Some(location);
+                }
+            }
+            _ => return Err(format!("unknown parameter '{}'", key)),
+        }
+    }
+    if !(2..=1024).contains(&request.resolution) {
+        return Err("resolution must be between 2 and 1024".to_string());
+    }
+    Ok(request)
+}
+
+// The API key from `Authorization: Bearer <key>` or `X-API-Key: <key>`
+fn api_key(request: &Request) -> Option<String> {
+    request.headers().iter().find_map(|h| {
+        let value = h.value.as_str().trim();
+        if h.field.equiv("Authorization") {
+            value
+                .strip_prefix("Bearer ")
+                .map(|key| key.trim().to_string())
+        } else if h.field.equiv("X-API-Key") {
+            Some(value.to_string())
+        } else {
+            None
+        }
+    })
+}
+
+fn check_callback_url(url: &str) -> Result<()> {
+    if !(url.starts_with("http://") || url.starts_with("https://")) {
+        bail!("callback must be an http:// or https:// URL, got '{}'", url);
+    }
+    Ok(())
+}
+
+// A decoded s3:// or http(s):// location
+fn remote_location(value: &str) -> Option<String> {
+    let location = percent_decode(value)?;
+    match Location::parse(&location) {
+        Ok(parsed) if parsed.is_remote() => Some(location),
+        _ => None,
+    }
+}
+
+// Undo URL query encoding (`%2F`, `+` for space). None if it's malformed.
+fn percent_decode(text: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.bytes();
+    while let Some(b) = rest.next() {
+        match b {
+            b'%' => {
+                let hex = [rest.next()?, rest.next()?];
+                let hex = std::str::from_utf8(&hex).ok()?;
+                bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(b),
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+fn json_response<T: Serialize>(status: u16, body: &T) -> HttpResponse {
+    let text = serde_json::to_vec_pretty(body).unwrap_or_default();
+    Response::from_data(text)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error(status: u16, message: &str) -> HttpResponse {
+    json_response(
+        status,
+        &ErrorBody {
+            error: message.to_string(),
+        },
+    )
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).expect("static header is valid")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remote_locations_are_off_by_default() {
+        let none = Allowlist::default();
+        assert!(parse_job_request("input=s3%3A%2F%2Fany%2Fscan.obj", &none).is_err());
+        assert!(parse_job_request("output=s3://any/out.stl", &none).is_err());
+        assert!(parse_job_request("callback=http://169.254.169.254/", &none).is_err());
+        assert!(parse_job_request("priority=2&resolution=64", &none).is_ok());
+    }
+
+    #[test]
+    fn allowed_locations_pass() {
+        let list = Allowlist::new(vec!["s3://scans/in/".to_string()]).unwrap();
+        let request = parse_job_request("input=s3%3A%2F%2Fscans%2Fin%2Fa.obj", &list).unwrap();
+        assert_eq!(request.input.as_deref(), Some("s3://scans/in/a.obj"));
+        assert!(parse_job_request("output=s3://scans/out/a.stl", &list).is_err());
+        assert!(parse_job_request("input=/etc/passwd", &list).is_err());
+    }
+}
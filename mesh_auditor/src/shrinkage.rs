@@ -0,0 +1,94 @@
+//! `convert --compensate-shrinkage x:0.4%,y:0.4%,z:0.6%`: scaling a part
+//! up so it prints to size.
+//!
+//! Resin cures and sintered powder cools smaller than it was laid down, and
+//! seldom by the same amount along the build axis as across it. A print
+//! that shrinks by `s` along an axis comes out `1 - s` of its size there,
+//! so the mesh is scaled by `1 / (1 - s)` along that axis, about the centre
+//! of its bounding box so it stays where it was placed. The allowance goes
+//! into the output's notes (see `Mesh::notes`), so a compensated file can't
+//! pass for the part at its true size.
+
+use crate::mesh::Mesh;
+
+/// How much a print shrinks along each axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shrinkage {
+    /// Per axis, in percent (0.4 for 0.4%); negative for a material that
+    /// grows.
+    pub percent: [f64; 3],
+}
+
+impl Shrinkage {
+    /// Scale along each axis that makes up for it.
+    pub fn factors(&self) -> [f64; 3] {
+        self.percent.map(|p| 1.0 / (1.0 - p / 100.0))
+    }
+
+    /// Scale `mesh` up about its centre to make up for the shrinkage, and
+    /// note it on the mesh. Returns the factors used.
+    pub fn apply(&self, mesh: &mut Mesh) -> [f64; 3] {
+        let factors = self.factors();
+        let (min, max) = mesh.bounds();
+        let centre = [0, 1, 2].map(|k| (f64::from(min[k]) + f64::from(max[k])) * 0.5);
+        for v in mesh.positions.chunks_exact_mut(3) {
+            for k in 0..3 {
+                v[k] = (centre[k] + (f64::from(v[k]) - centre[k]) * factors[k]) as f32;
+            }
+        }
+        mesh.notes.push(format!(
+            "compensated for print shrinkage of x {}%, y {}%, z {}%: scaled x{:.6} y{:.6} z{:.6} about ({}, {}, {})",
+            self.percent[0],
+            self.percent[1],
+            self.percent[2],
+            factors[0],
+            factors[1],
+            factors[2],
+            centre[0] as f32,
+            centre[1] as f32,
+            centre[2] as f32
+        ));
+        factors
+    }
+}
+
+/// Parse a shrinkage such as `x:0.4%,y:0.4%,z:0.6%` (axes left out don't
+/// shrink) or `0.5%` for every axis. Without a `%` a value is a fraction.
+pub fn parse(s: &str) -> std::result::Result<Shrinkage, String> {
+    let amount = |text: &str| {
+        let text = text.trim();
+        let value = match text.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().ok(),
+            None => text.parse::<f64>().ok().map(|fraction| fraction * 100.0),
+        };
+        // Half the part gone is a mistake, not a material
+        value
+            .filter(|p| p.is_finite() && p.abs() < 50.0)
+            .ok_or_else(|| format!("'{}' is not a shrinkage (e.g. 0.4%)", text))
+    };
+    let s = s.trim();
+    if !s.contains(':') {
+        return Ok(Shrinkage {
+            percent: [amount(s)?; 3],
+        });
+    }
+    let mut percent = [0.0; 3];
+    let mut seen = [false; 3];
+    for part in s.split(',') {
+        let Some((axis, value)) = part.split_once(':') else {
+            return Err(format!("expected axis:amount but got '{}'", part.trim()));
+        };
+        let k = match axis.trim().to_ascii_lowercase().as_str() {
+            "x" => 0,
+            "y" => 1,
+            "z" => 2,
+            other => return Err(format!("'{}' is not an axis (x, y or z)", other)),
+        };
+        if seen[k] {
+            return Err(format!("axis {} is given twice", axis.trim()));
+        }
+        seen[k] = true;
+        percent[k] = amount(value)?;
+    }
+    Ok(Shrinkage { percent })
+}
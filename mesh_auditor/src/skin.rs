@@ -0,0 +1,258 @@
+//! A surface extracted from a field, on its way to a file.
+//!
+//! Marching cubes gives a triangle soup, and STL takes it as it is. Any
+//! other format, or smoothing (which has to know which corners are the
+//! same), makes it a mesh with shared corners first: corners at the same
+//! spot are joined, and those closer than `weld` too if asked, before
+//! Taubin smoothing. Faces kept from the scan are written alongside as they
+//! were, their corners pinned so the new surface still meets them. A sweep
+//! writes one surface per isovalue, numbered after the output, or all of
+//! them as the frames of one glTF flipbook.
+
+use crate::extract::{marching_cubes, soup_volume};
+use crate::mesh::Mesh;
+use crate::metadata::Format;
+use crate::output::{self, WriteOptions};
+use crate::sdf::SampledField;
+use crate::stl::{save_mesh_as_stl, save_triangles_as_stl};
+use crate::{gltf, heal, smooth};
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+
+/// Where an extracted surface goes, and what's done to it on the way.
+#[derive(Debug, Clone, Copy)]
+pub struct SkinOutput<'a> {
+    pub path: &'a str,
+    /// Also join corners closer than this, not only those at the same spot.
+    pub weld: Option<f32>,
+    /// Taubin smoothing passes over the new surface.
+    pub smooth_iterations: usize,
+    pub write: WriteOptions,
+}
+
+/// What was done to a surface on its way out.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Written {
+    /// Welding: the corners shared before it, how many more it joined, and
+    /// the faces that collapsed and were dropped.
+    pub welded: Option<(usize, usize, usize)>,
+    /// How many vertices smoothing moved.
+    pub smoothed: Option<usize>,
+    /// Vertices and faces written, when written with shared corners.
+    pub mesh: Option<(usize, usize)>,
+}
+
+/// One isovalue of a sweep.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub iso: f32,
+    /// 0 if the field has no surface there, and nothing was written.
+    pub triangles: usize,
+    pub volume: f64,
+}
+
+impl SkinOutput<'_> {
+    pub fn validate(&self) -> Result<()> {
+        let Some(tolerance) = self.weld else {
+            return Ok(());
+        };
+        if !tolerance.is_finite() || tolerance <= 0.0 {
+            bail!("the weld tolerance must be a positive distance");
+        }
+        if matches!(Format::of(self.path), None | Some(Format::Stl)) {
+            bail!("--weld shares corners between faces, which STL can't: write .obj, .ply or another indexed format");
+        }
+        Ok(())
+    }
+
+    /// Whether the surface is made a mesh with shared corners on the way:
+    /// to be written so, or to be smoothed (which moves shared corners
+    /// together). STL (and anything unknown, as always) otherwise takes the
+    /// triangles as they are.
+    pub fn shares_corners(&self) -> bool {
+        self.smooth_iterations > 0 || !matches!(Format::of(self.path), None | Some(Format::Stl))
+    }
+
+    /// Write `skin`, and the faces `kept` alongside it as they are.
+    pub fn save(&self, skin: &[f32], kept: &[f32]) -> Result<Written> {
+        let triangles = [skin, kept].concat();
+        if !self.shares_corners() {
+            save_triangles_as_stl(&triangles, self.path, &self.write)?;
+            return Ok(Written::default());
+        }
+        let (mesh, written) = self.mesh(&triangles, kept)?;
+        save_mesh(&mesh, self.path, &self.write)?;
+        Ok(written)
+    }
+
+    /// Surfaces at `frames` isovalues evenly across `from..=to`, each to a
+    /// file numbered after the output, or all as a flipbook `frame_time`
+    /// seconds a frame if that's a .glb.
+    pub fn sweep(
+        &self,
+        field: &SampledField,
+        (from, to): (f32, f32),
+        frames: usize,
+        frame_time: f32,
+    ) -> Result<Vec<Frame>> {
+        if frames < 2 {
+            bail!("a sweep needs at least 2 frames");
+        }
+        if !frame_time.is_finite() || frame_time <= 0.0 {
+            bail!("the frame time must be a positive number of seconds");
+        }
+        let flipbook = Format::of(self.path) == Some(Format::Glb);
+        let mut swept = Vec::new();
+        let mut levels = Vec::new();
+        for frame in 0..frames {
+            let iso = from + (to - from) * frame as f32 / (frames - 1) as f32;
+            let skin = marching_cubes(field, iso);
+            swept.push(Frame {
+                iso,
+                triangles: skin.len() / 9,
+                volume: soup_volume(&skin),
+            });
+            if skin.is_empty() {
+                continue;
+            }
+            let path = output::numbered(self.path, frame, frames);
+            if !self.shares_corners() {
+                save_triangles_as_stl(&skin, &path, &self.write)?;
+                continue;
+            }
+            let (mut mesh, _) = self.mesh(&skin, &[])?;
+            mesh.metadata.name = Some(format!("iso_{}", iso));
+            if flipbook {
+                levels.push((iso, mesh));
+            } else {
+                save_mesh(&mesh, &path, &self.write)?;
+            }
+        }
+        if !flipbook {
+            return Ok(swept);
+        }
+        if levels.is_empty() {
+            bail!("no isovalue in the sweep has a surface");
+        }
+        let primitives: Vec<gltf::Primitive> = levels
+            .iter()
+            .map(|(_, mesh)| gltf::Primitive::new(&self.write.ordered(mesh)))
+            .collect();
+        let nodes: Vec<gltf::Node> = levels
+            .iter()
+            .zip(&primitives)
+            .map(|((iso, mesh), primitive)| gltf::Node {
+                name: mesh.metadata.name.as_deref(),
+                primitive,
+                extras: serde_json::json!({ "iso": iso }),
+            })
+            .collect();
+        gltf::write_flipbook(self.path, &nodes, frame_time)?;
+        Ok(swept)
+    }
+
+    // The triangles as a mesh, welded and smoothed as asked, with the
+    // corners of the `kept` faces (wherever welding left them) pinned
+    fn mesh(&self, triangles: &[f32], kept: &[f32]) -> Result<(Mesh, Written)> {
+        let mut mesh = Mesh::from_triangles(triangles)?;
+        let mut written = Written::default();
+        if let Some(tolerance) = self.weld {
+            let shared = mesh.vertex_count();
+            let (merged, dropped) = heal::weld(&mut mesh, tolerance)?;
+            written.welded = Some((shared, merged, dropped));
+        }
+        if self.smooth_iterations > 0 {
+            let at_kept: HashSet<[u32; 3]> = kept
+                .chunks_exact(3)
+                .map(|p| [p[0], p[1], p[2]].map(f32::to_bits))
+                .collect();
+            let pinned: Vec<bool> = (0..mesh.vertex_count())
+                .map(|v| at_kept.contains(&mesh.vertex(v).map(f32::to_bits)))
+                .collect();
+            let moved = smooth::taubin(&mut mesh, self.smooth_iterations, Some(&pinned));
+            written.smoothed = Some(moved);
+        }
+        written.mesh = Some((mesh.vertex_count(), mesh.face_count()));
+        Ok((mesh, written))
+    }
+}
+
+/// Like `output::save`, but anything unknown is STL, as surfaces extracted
+/// from a field always have been.
+pub fn save_mesh(mesh: &Mesh, path: &str, write: &WriteOptions) -> Result<()> {
+    match Format::of(path) {
+        None => save_mesh_as_stl(mesh, path, write),
+        Some(_) => output::save(mesh, path, write),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::primitives::{Primitive, Shape};
+    use crate::samples::Storage;
+    use crate::slabs::Slabs;
+    use std::path::Path;
+
+    fn sphere() -> SampledField {
+        let primitive = Primitive {
+            shape: Shape::Sphere,
+            size: 2.0,
+            detail: 0.2,
+            cells: 2,
+            seed: 0,
+        };
+        primitive.sample(24, Storage::Full, &Slabs::default())
+    }
+
+    #[test]
+    fn welds_and_smooths_what_is_written_with_shared_corners() {
+        let field = sphere();
+        let dir = std::env::temp_dir().join(format!("skin_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("skin.obj").to_string_lossy().into_owned();
+        let output = SkinOutput {
+            path: &path,
+            weld: Some(1e-4),
+            smooth_iterations: 2,
+            write: WriteOptions::default(),
+        };
+        let written = output
+            .save(&marching_cubes(&field, field.iso), &[])
+            .unwrap();
+        let saved = Mesh::load(&path, &Default::default()).unwrap();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(written.welded.is_some());
+        assert!(written.smoothed.is_some_and(|moved| moved > 0));
+        assert_eq!(
+            written.mesh,
+            Some((saved.vertex_count(), saved.face_count()))
+        );
+    }
+
+    #[test]
+    fn sweeps_to_numbered_files() {
+        let field = sphere();
+        let dir = std::env::temp_dir().join(format!("sweep_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("level.stl").to_string_lossy().into_owned();
+        let output = SkinOutput {
+            path: &path,
+            weld: None,
+            smooth_iterations: 0,
+            write: WriteOptions::default(),
+        };
+        let frames = output
+            .sweep(&field, (field.iso - 0.2, field.iso + 0.2), 3, 1.0)
+            .unwrap();
+        let written: Vec<bool> = (0..3)
+            .map(|frame| Path::new(&output::numbered(&path, frame, 3)).exists())
+            .collect();
+        std::fs::remove_dir_all(&dir).ok();
+        assert_eq!(frames.len(), 3);
+        assert!(frames.iter().all(|f| f.triangles > 0));
+        assert_ne!(frames[0].volume, frames[2].volume);
+        assert_eq!(written, [true, true, true]);
+        assert!(output.sweep(&field, (0.0, 1.0), 1, 1.0).is_err());
+    }
+}
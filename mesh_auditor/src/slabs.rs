@@ -0,0 +1,103 @@
+//! Slab-parallel field sampling.
+//!
+//! The grid is cut into slabs of whole z layers, and each thread samples a
+//! slab at a time into a buffer of its own, from its own copy of whatever
+//! the field reads (for a scan, just the points near the slab, in a
+//! KD-tree of their own). Linux puts a page on the memory node of the
+//! thread that first writes to it, so on a machine with several sockets
+//! that keeps both a thread's samples and its point index next to the
+//! core using them. One shared grid and one shared tree, allocated by the
+//! main thread, would live on one node, and every thread would be reading
+//! and writing through that node's memory controller.
+//!
+//! Slabs go out in rounds of one per thread and are copied into the full
+//! grid in order as each round finishes, so at most a round's worth of
+//! slabs is ever held twice. Thinner slabs balance the threads better and
+//! hold less; thicker ones spend less, relatively, on the margin of points
+//! each local index repeats from its neighbours.
+
+use crate::progress;
+use crate::samples::Samples;
+use crate::sandbox;
+use crate::sdf::Field;
+use crate::threads;
+use std::ops::Range;
+
+/// Grid layers per slab when the caller doesn't say.
+pub const DEFAULT_LAYERS: usize = 8;
+
+/// How to split sampling across threads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Slabs {
+    /// Z layers per slab.
+    pub layers: usize,
+    pub threads: usize,
+}
+
+impl Default for Slabs {
+    fn default() -> Self {
+        Slabs {
+            layers: DEFAULT_LAYERS,
+            threads: threads::count(None),
+        }
+    }
+}
+
+/// A field that can be sampled a slab at a time.
+pub trait SlabField: Field + Sync {
+    /// What sampling some layers needs: the same field, as far as those
+    /// layers go.
+    type Local<'a>: Field
+    where
+        Self: 'a;
+
+    /// The part of the field layers `z` read, set up on the calling
+    /// thread.
+    fn local(&self, z: Range<usize>) -> Self::Local<'_>;
+}
+
+/// Sample every grid point of `field`, slab by slab, in order; `empty` is
+/// the storage to fill (see `Samples::with_capacity`).
+pub fn sample<F: SlabField>(field: &F, slabs: &Slabs, empty: Samples) -> Samples {
+    let [nx, ny, nz] = field.dimensions();
+    let layers = slabs.layers.max(1);
+    let ranges: Vec<Range<usize>> = (0..nz)
+        .step_by(layers)
+        .map(|z| z..(z + layers).min(nz))
+        .collect();
+    let storage = empty.storage();
+    let mut values = empty;
+    progress::start(nz);
+
+    for round in ranges.chunks(slabs.threads.max(1)) {
+        let sample_slab = |z: &Range<usize>| {
+            let local = field.local(z.clone());
+            let mut slab = Samples::with_capacity(storage, nx * ny * z.len());
+            for z in z.clone() {
+                for y in 0..ny {
+                    sandbox::checkpoint();
+                    for value in local.row(y, z) {
+                        slab.push(value as f32);
+                    }
+                }
+                progress::tick();
+            }
+            slab
+        };
+        // One slab needs no thread of its own
+        if let [z] = round {
+            values.append(&sample_slab(z));
+            continue;
+        }
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = round
+                .iter()
+                .map(|z| scope.spawn(move || sample_slab(z)))
+                .collect();
+            for slab in threads::join_all(handles) {
+                values.append(&slab);
+            }
+        });
+    }
+    values
+}
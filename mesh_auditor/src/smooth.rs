@@ -0,0 +1,67 @@
+//! `--smooth-iterations`: taking the stair steps out of a surface fresh
+//! from marching cubes.
+//!
+//! Each iteration is Taubin's pair of Laplacian steps: every vertex moves
+//! `LAMBDA` of the way towards the mean of its neighbours, which smooths
+//! and shrinks, then `MU` of the way, a little further back out, which
+//! undoes the shrinking but not the smoothing. Plain Laplacian smoothing
+//! repeated as often would shrink a part out of tolerance; Taubin's keeps
+//! its size to within a fraction of a voxel while the ripples a voxel or
+//! two wide go.
+//!
+//! Vertices on open edges stay put, and so do those the caller pins (the
+//! faces kept as they were round a partial remesh), so a seam stays joined
+//! to what is on the other side of it.
+
+use crate::audit;
+use crate::mesh::Mesh;
+use crate::sandbox;
+
+/// How far each smoothing step moves a vertex towards its neighbours'
+/// mean.
+pub const LAMBDA: f32 = 0.5;
+/// How far each inflating step moves it, negative so it moves away: Taubin's
+/// pass-band of 0.1 with `LAMBDA`.
+pub const MU: f32 = -0.53;
+
+/// Smooth `mesh` `iterations` times, leaving the vertices `pinned` (if
+/// given, one flag per vertex) and those on open edges where they are.
+/// Returns how many vertices moved.
+pub fn taubin(mesh: &mut Mesh, iterations: usize, pinned: Option<&[bool]>) -> usize {
+    let count = mesh.vertex_count();
+    let mut fixed = pinned.map_or_else(|| vec![false; count], <[bool]>::to_vec);
+    let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); count];
+    for (&(low, high), faces) in &audit::edge_faces(mesh) {
+        if faces.len() != 2 {
+            fixed[low] = true;
+            fixed[high] = true;
+        }
+        neighbours[low].push(high);
+        neighbours[high].push(low);
+    }
+    let moving: Vec<usize> = (0..count)
+        .filter(|&v| !fixed[v] && !neighbours[v].is_empty())
+        .collect();
+
+    let mut next = mesh.positions.clone();
+    for _ in 0..iterations {
+        for factor in [LAMBDA, MU] {
+            sandbox::checkpoint();
+            for &v in &moving {
+                let around = &neighbours[v];
+                let mut mean = [0.0f32; 3];
+                for &u in around {
+                    for (sum, p) in mean.iter_mut().zip(&mesh.positions[u * 3..u * 3 + 3]) {
+                        *sum += p;
+                    }
+                }
+                for k in 0..3 {
+                    let here = mesh.positions[v * 3 + k];
+                    next[v * 3 + k] = here + factor * (mean[k] / around.len() as f32 - here);
+                }
+            }
+            std::mem::swap(&mut mesh.positions, &mut next);
+        }
+    }
+    moving.len()
+}
@@ -0,0 +1,995 @@
+//! `split`: cutting a model in two along a plane, for a printer too small
+//! to take it whole, with alignment pins standing out of one half's cut
+//! face and matching sockets sunk into the other's, so the printed halves
+//! go back together in register.
+//!
+//! Faces crossing the plane are cut where their edges cross it (one new
+//! corner per edge, shared by the faces either side of it, so neither half
+//! opens there), and corners within a hair of the plane are put on it.
+//! Each half is then left with open outlines on the plane, which are closed
+//! by ear clipping, holes in the cut face (the bore of a tube, say) bridged
+//! to the outline around them first. The model has to be closed where it's
+//! cut: an outline that doesn't join up is an error.
+//!
+//! Pins go where the cut has room. A grid of spots over each separate face
+//! of the cut is kept where a socket would have a pin's radius of material
+//! all round it, across the cut and past its end (found by casting into
+//! the model), and the pins are picked as far apart as they'll go. A pin
+//! is a cylinder standing on the lower half's cut face; its socket is the
+//! same cylinder sunk into the upper half's, wider by the clearance all
+//! round and deeper by it at the end. Both are joined into their half's
+//! surface, so a closed model gives two closed halves. Only positions are
+//! kept: the halves have no colours, UVs or materials.
+
+use crate::bvh::Bvh;
+use crate::mesh::Mesh;
+use anyhow::{bail, Result};
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+
+// Sides of a pin (and its socket)
+const SEGMENTS: usize = 32;
+// Spots tried along the longer side of a cut face
+const GRID: usize = 48;
+// Corners this close to the plane, as a share of the model's size, are
+// taken to be on it
+const ON_PLANE: f64 = 1e-6;
+
+/// Pin and socket sizes, in model units.
+#[derive(Debug, Clone, Copy)]
+pub struct Pins {
+    /// Pins on each separate face of the cut; 0 for none.
+    pub per_face: usize,
+    pub diameter: f32,
+    /// How far a pin stands out of the cut.
+    pub length: f32,
+    /// Gap between a pin and its socket, round its side and at its end.
+    pub clearance: f32,
+}
+
+/// The two halves, and what went into cutting them.
+#[derive(Debug)]
+pub struct Split {
+    /// The half the normal points away from, with the pins.
+    pub below: Mesh,
+    /// The half the normal points into, with the sockets.
+    pub above: Mesh,
+    /// Where the plane was, along the normal.
+    pub at: f32,
+    /// Separate faces of the cut (outlines, not counting holes in them).
+    pub faces: usize,
+    /// Area of the cut.
+    pub area: f64,
+    /// Where each pin stands, on the plane.
+    pub pins: Vec<[f32; 3]>,
+    /// Faces of the cut with no room for a pin.
+    pub faces_without_pins: usize,
+}
+
+/// Cut `mesh` by the plane across `normal` at `at` along it (the middle of
+/// the model if not given), with `pins` on each face of the cut.
+pub fn split(mesh: &Mesh, normal: [f32; 3], at: Option<f32>, pins: &Pins) -> Result<Split> {
+    let length = dot(normal.map(f64::from), normal.map(f64::from)).sqrt();
+    if !length.is_finite() || length == 0.0 {
+        bail!("the plane's normal must be a direction");
+    }
+    let n = normal.map(|x| x as f64 / length);
+    if pins.per_face > 0 {
+        if !pins.diameter.is_finite() || pins.diameter <= 0.0 {
+            bail!("the pin diameter must be a positive distance");
+        }
+        if !pins.length.is_finite() || pins.length <= 0.0 {
+            bail!("the pin length must be a positive distance");
+        }
+        if !pins.clearance.is_finite() || pins.clearance < 0.0 {
+            bail!("the clearance must be a distance, 0 or more");
+        }
+    }
+    if mesh.face_count() == 0 {
+        bail!("the mesh has no faces to cut");
+    }
+
+    // 1. Which side of the plane each corner is on, those close to it put
+    // on it
+    let mut points: Vec<[f64; 3]> = (0..mesh.vertex_count())
+        .map(|v| mesh.vertex(v).map(f64::from))
+        .collect();
+    let heights: Vec<f64> = points.iter().map(|&p| dot(p, n)).collect();
+    let (lo, hi) = heights
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(lo, hi), &h| (lo.min(h), hi.max(h)));
+    let at = at.map_or((lo + hi) * 0.5, f64::from);
+    if !(at > lo && at < hi) {
+        bail!(
+            "the plane at {} misses the model, which runs from {} to {} along the normal",
+            at,
+            lo,
+            hi
+        );
+    }
+    let (min, max) = mesh.bounds();
+    let size = (0..3)
+        .map(|k| (max[k] as f64 - min[k] as f64).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    let mut side: Vec<i8> = Vec::with_capacity(points.len());
+    for (p, &h) in points.iter_mut().zip(&heights) {
+        let d = h - at;
+        if d.abs() <= size * ON_PLANE {
+            *p = add(*p, scale(n, -d));
+            side.push(0);
+        } else {
+            side.push(if d < 0.0 { -1 } else { 1 });
+        }
+    }
+
+    // 2. Each face to the side it's on, those crossing cut in two. A new
+    // corner is made once per edge, from its lower-numbered end, so both
+    // faces on the edge get the very same point
+    let mut crossings: HashMap<(usize, usize), usize> = HashMap::new();
+    let mut below: Vec<[usize; 3]> = Vec::new();
+    let mut above: Vec<[usize; 3]> = Vec::new();
+    for f in 0..mesh.face_count() {
+        let corners = mesh.face(f);
+        if corners.iter().all(|&c| side[c] == 0) {
+            // In the plane: the cut face covers it
+            continue;
+        }
+        let mut low = Vec::with_capacity(4);
+        let mut high = Vec::with_capacity(4);
+        for k in 0..3 {
+            let (i, j) = (corners[k], corners[(k + 1) % 3]);
+            if side[i] <= 0 {
+                low.push(i);
+            }
+            if side[i] >= 0 {
+                high.push(i);
+            }
+            if side[i] * side[j] < 0 {
+                let key = (i.min(j), i.max(j));
+                let x = *crossings.entry(key).or_insert_with(|| {
+                    let (a, b) = key;
+                    let t = (heights[a] - at) / (heights[a] - heights[b]);
+                    points.push(add(points[a], scale(sub(points[b], points[a]), t)));
+                    side.push(0);
+                    points.len() - 1
+                });
+                low.push(x);
+                high.push(x);
+            }
+        }
+        fan(&low, &mut below);
+        fan(&high, &mut above);
+    }
+    if below.is_empty() || above.is_empty() {
+        bail!("the plane at {} leaves nothing on one side", at);
+    }
+
+    // 3. The cut faces, in the plane
+    let u = perpendicular(n);
+    let plane = Plane {
+        origin: scale(n, at),
+        n,
+        u,
+        v: cross(n, u),
+    };
+    let lower = Cap::new(cut_outlines(&below, &side)?, &points, &plane);
+    let upper = Cap::new(cut_outlines(&above, &side)?, &points, &plane);
+
+    // 4. Where the pins go
+    let radius = pins.diameter as f64 * 0.5;
+    let socket = radius + pins.clearance as f64;
+    let depth = (pins.length + pins.clearance) as f64;
+    let mut centres: Vec<[f64; 2]> = Vec::new();
+    let mut faces_without_pins = 0;
+    if pins.per_face > 0 {
+        let room = Room {
+            lower: &lower,
+            upper: &upper,
+            plane: &plane,
+            model: Bvh::new(mesh),
+            socket,
+            margin: socket + radius,
+            reach: (depth + radius) as f32,
+            lift: size * ON_PLANE * 10.0,
+        };
+        for face in 0..lower.faces.len() {
+            let picked = room.pick(face, pins.per_face);
+            if picked.is_empty() {
+                faces_without_pins += 1;
+            }
+            centres.extend(picked);
+        }
+    }
+
+    // 5. Each pin a hole in the lower cut face with a cylinder standing in
+    // it, each socket a wider hole in the upper one with a cylinder sunk
+    // from it
+    let mut below_holes: Vec<(usize, Vec<usize>)> = Vec::new();
+    let mut above_holes: Vec<(usize, Vec<usize>)> = Vec::new();
+    let mut placed = Vec::with_capacity(centres.len());
+    for centre in centres {
+        let (Some(low_face), Some(high_face)) = (lower.face_at(centre), upper.face_at(centre))
+        else {
+            continue;
+        };
+        let pin =
+            Tube::new(&mut points, &plane, centre, radius, pins.length as f64).turned_for(&lower);
+        below.extend(pin.triangles());
+        below_holes.push((low_face, pin.ring));
+        let hole = Tube::new(&mut points, &plane, centre, socket, depth).turned_for(&upper);
+        above.extend(hole.triangles());
+        above_holes.push((high_face, hole.ring));
+        placed.push(plane.point(centre, 0.0).map(|x| x as f32));
+    }
+
+    // 6. Close each half
+    lower.close(&below_holes, &points, &plane, &mut below);
+    upper.close(&above_holes, &points, &plane, &mut above);
+
+    Ok(Split {
+        below: to_mesh(&points, &below),
+        above: to_mesh(&points, &above),
+        at: at as f32,
+        faces: lower.faces.len(),
+        area: lower.area,
+        pins: placed,
+        faces_without_pins,
+    })
+}
+
+// The cut plane, with axes across it
+struct Plane {
+    origin: [f64; 3],
+    n: [f64; 3],
+    u: [f64; 3],
+    v: [f64; 3],
+}
+
+impl Plane {
+    // Where a point lies across the plane
+    fn flat(&self, p: [f64; 3]) -> [f64; 2] {
+        let d = sub(p, self.origin);
+        [dot(d, self.u), dot(d, self.v)]
+    }
+
+    // The point `height` along the normal from a spot on the plane
+    fn point(&self, [x, y]: [f64; 2], height: f64) -> [f64; 3] {
+        add(
+            self.origin,
+            add(
+                add(scale(self.u, x), scale(self.v, y)),
+                scale(self.n, height),
+            ),
+        )
+    }
+}
+
+// Triangles for a convex polygon, as a fan from its first corner
+fn fan(polygon: &[usize], triangles: &mut Vec<[usize; 3]>) {
+    for k in 2..polygon.len() {
+        triangles.push([polygon[0], polygon[k - 1], polygon[k]]);
+    }
+}
+
+// The closed loops a half's open edges on the plane make, each the way
+// round that a face closing them has to go
+fn cut_outlines(triangles: &[[usize; 3]], side: &[i8]) -> Result<Vec<Vec<usize>>> {
+    let edges: HashSet<(usize, usize)> = triangles
+        .iter()
+        .flat_map(|t| [(t[0], t[1]), (t[1], t[2]), (t[2], t[0])])
+        .collect();
+    let mut next: HashMap<usize, usize> = HashMap::new();
+    for &(a, b) in &edges {
+        if side[a] == 0 && side[b] == 0 && !edges.contains(&(b, a)) {
+            // The closing face runs the other way along it
+            if next.insert(b, a).is_some() {
+                bail!("the model touches itself where it's cut: try the plane a little higher or lower");
+            }
+        }
+    }
+    let mut starts: Vec<usize> = next.keys().copied().collect();
+    starts.sort_unstable();
+    let mut seen: HashSet<usize> = HashSet::new();
+    let mut loops = Vec::new();
+    for start in starts {
+        if seen.contains(&start) {
+            continue;
+        }
+        let mut outline = Vec::new();
+        let mut at = start;
+        loop {
+            seen.insert(at);
+            outline.push(at);
+            match next.get(&at) {
+                Some(&after) if after == start => break,
+                Some(&after) if !seen.contains(&after) => at = after,
+                _ => bail!("the cut's outline doesn't close: the model has holes where it's cut (try repair first)"),
+            }
+        }
+        loops.push(outline);
+    }
+    Ok(loops)
+}
+
+// One half's cut, as loops of corners and the same in the plane
+struct Cap {
+    loops: Vec<Vec<usize>>,
+    shapes: Vec<Vec<[f64; 2]>>,
+    /// Each separate face: its outline and the holes in it, by loop.
+    faces: Vec<(usize, Vec<usize>)>,
+    /// Whether outlines go clockwise across the plane (the upper half's,
+    /// which faces the other way): faces are closed with it turned over.
+    mirrored: bool,
+    area: f64,
+}
+
+impl Cap {
+    fn new(loops: Vec<Vec<usize>>, points: &[[f64; 3]], plane: &Plane) -> Cap {
+        let shapes: Vec<Vec<[f64; 2]>> = loops
+            .iter()
+            .map(|l| l.iter().map(|&i| plane.flat(points[i])).collect())
+            .collect();
+        let areas: Vec<f64> = shapes.iter().map(|s| signed_area(s)).collect();
+        // The biggest loop is an outline, whichever way round the model's
+        // faces go
+        let mirrored = areas
+            .iter()
+            .max_by(|a, b| a.abs().total_cmp(&b.abs()))
+            .is_some_and(|&a| a < 0.0);
+        let sign = if mirrored { -1.0 } else { 1.0 };
+        let mut faces: Vec<(usize, Vec<usize>)> = (0..loops.len())
+            .filter(|&l| areas[l] * sign > 0.0)
+            .map(|l| (l, Vec::new()))
+            .collect();
+        for hole in (0..loops.len()).filter(|&l| areas[l] * sign <= 0.0) {
+            // Into the smallest outline around it
+            let around = faces
+                .iter_mut()
+                .filter(|(outline, _)| inside(&shapes[*outline], shapes[hole][0]))
+                .min_by(|a, b| areas[a.0].abs().total_cmp(&areas[b.0].abs()));
+            if let Some((_, holes)) = around {
+                holes.push(hole);
+            }
+        }
+        Cap {
+            loops,
+            area: areas.iter().sum::<f64>() * sign,
+            shapes,
+            faces,
+            mirrored,
+        }
+    }
+
+    // Which face a spot on the plane is on, if any
+    fn face_at(&self, p: [f64; 2]) -> Option<usize> {
+        self.faces.iter().position(|(outline, holes)| {
+            inside(&self.shapes[*outline], p) && !holes.iter().any(|&h| inside(&self.shapes[h], p))
+        })
+    }
+
+    // How far a spot on the plane is from the nearest edge of the cut
+    fn clearance(&self, p: [f64; 2]) -> f64 {
+        self.shapes
+            .iter()
+            .flat_map(|s| (0..s.len()).map(move |k| (s[k], s[(k + 1) % s.len()])))
+            .map(|(a, b)| segment_distance(p, a, b))
+            .fold(f64::MAX, f64::min)
+    }
+
+    // Close every face, with the extra holes given for it
+    fn close(
+        &self,
+        extra: &[(usize, Vec<usize>)],
+        points: &[[f64; 3]],
+        plane: &Plane,
+        triangles: &mut Vec<[usize; 3]>,
+    ) {
+        let at = |i: usize| {
+            let [x, y] = plane.flat(points[i]);
+            if self.mirrored {
+                [x, -y]
+            } else {
+                [x, y]
+            }
+        };
+        for (f, (outline, holes)) in self.faces.iter().enumerate() {
+            let mut all: Vec<&[usize]> = holes.iter().map(|&h| &self.loops[h][..]).collect();
+            all.extend(
+                extra
+                    .iter()
+                    .filter(|(face, _)| *face == f)
+                    .map(|(_, hole)| &hole[..]),
+            );
+            triangles.extend(triangulate(&self.loops[*outline], &all, at));
+        }
+    }
+}
+
+// What a pin needs around it
+struct Room<'a> {
+    lower: &'a Cap,
+    upper: &'a Cap,
+    plane: &'a Plane,
+    model: Bvh,
+    socket: f64,
+    /// Least distance from a pin's centre to the edge of the cut.
+    margin: f64,
+    /// How far into the upper half the socket and the wall past its end go.
+    reach: f32,
+    /// How far off the plane the casts start.
+    lift: f64,
+}
+
+impl Room<'_> {
+    // Up to `count` spots for pins on the lower half's `face`, far apart
+    fn pick(&self, face: usize, count: usize) -> Vec<[f64; 2]> {
+        let outline = &self.lower.shapes[self.lower.faces[face].0];
+        let (min, max) = outline
+            .iter()
+            .fold(([f64::MAX; 2], [f64::MIN; 2]), |(min, max), p| {
+                (
+                    [min[0].min(p[0]), min[1].min(p[1])],
+                    [max[0].max(p[0]), max[1].max(p[1])],
+                )
+            });
+        let step = (max[0] - min[0]).max(max[1] - min[1]) / GRID as f64;
+        let mut spots: Vec<([f64; 2], f64)> = Vec::new();
+        let mut y = min[1] + step * 0.5;
+        while y < max[1] {
+            let mut x = min[0] + step * 0.5;
+            while x < max[0] {
+                let p = [x, y];
+                if let Some(clearance) = self.fits(face, p) {
+                    spots.push((p, clearance));
+                }
+                x += step;
+            }
+            y += step;
+        }
+        if spots.is_empty() {
+            return Vec::new();
+        }
+
+        // One pin in the middle of things; more from the two furthest apart
+        // on, each next as far from the others as it can be
+        // Sockets with the same wall between them as round them
+        let apart = self.socket + self.margin;
+        let deepest = |spots: &[([f64; 2], f64)]| {
+            spots.iter().max_by(|a, b| a.1.total_cmp(&b.1)).map(|s| s.0)
+        };
+        if count == 1 {
+            return deepest(&spots).into_iter().collect();
+        }
+        let mut best = (0, 0, 0.0);
+        for i in 0..spots.len() {
+            for j in i + 1..spots.len() {
+                let d = distance(spots[i].0, spots[j].0);
+                if d > best.2 {
+                    best = (i, j, d);
+                }
+            }
+        }
+        if best.2 < apart {
+            return deepest(&spots).into_iter().collect();
+        }
+        let mut picked = vec![spots[best.0].0, spots[best.1].0];
+        while picked.len() < count {
+            let next = spots
+                .iter()
+                .map(|s| {
+                    let nearest = picked
+                        .iter()
+                        .map(|&q| distance(s.0, q))
+                        .fold(f64::MAX, f64::min);
+                    (s.0, nearest)
+                })
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            match next {
+                Some((p, nearest)) if nearest >= apart => picked.push(p),
+                _ => break,
+            }
+        }
+        picked
+    }
+
+    // Whether a pin fits at `p` on `face`, and if so how far it is from the
+    // edge of the cut
+    fn fits(&self, face: usize, p: [f64; 2]) -> Option<f64> {
+        if self.lower.face_at(p) != Some(face) || self.upper.face_at(p).is_none() {
+            return None;
+        }
+        let clearance = self.lower.clearance(p).min(self.upper.clearance(p));
+        if clearance < self.margin {
+            return None;
+        }
+        // Nothing of the model's surface within reach above the socket
+        let up = self.plane.n.map(|x| x as f32);
+        let around = (0..8).map(|k| {
+            let angle = k as f64 * PI / 4.0;
+            [
+                p[0] + self.socket * angle.cos(),
+                p[1] + self.socket * angle.sin(),
+            ]
+        });
+        for spot in std::iter::once(p).chain(around) {
+            let start = self.plane.point(spot, self.lift).map(|x| x as f32);
+            if self.model.cast(start, up, self.reach).is_some() {
+                return None;
+            }
+        }
+        Some(clearance)
+    }
+}
+
+// A cylinder on the plane: the ring where it meets the cut face, the ring
+// at its other end, and the centre of that end
+struct Tube {
+    ring: Vec<usize>,
+    end: Vec<usize>,
+    centre: usize,
+}
+
+impl Tube {
+    // Standing `height` along the normal from `centre`
+    fn new(
+        points: &mut Vec<[f64; 3]>,
+        plane: &Plane,
+        centre: [f64; 2],
+        radius: f64,
+        height: f64,
+    ) -> Tube {
+        let mut ring = Vec::with_capacity(SEGMENTS);
+        let mut end = Vec::with_capacity(SEGMENTS);
+        for k in 0..SEGMENTS {
+            let angle = 2.0 * PI * k as f64 / SEGMENTS as f64;
+            let spot = [
+                centre[0] + radius * angle.cos(),
+                centre[1] + radius * angle.sin(),
+            ];
+            points.push(plane.point(spot, 0.0));
+            ring.push(points.len() - 1);
+            points.push(plane.point(spot, height));
+            end.push(points.len() - 1);
+        }
+        points.push(plane.point(centre, height));
+        Tube {
+            ring,
+            end,
+            centre: points.len() - 1,
+        }
+    }
+
+    // Turned so its ring goes round as a hole in `cap` does: clockwise as
+    // the cap is closed. The rings are made anticlockwise across the plane
+    fn turned_for(mut self, cap: &Cap) -> Tube {
+        if !cap.mirrored {
+            self.ring.reverse();
+            self.end.reverse();
+        }
+        self
+    }
+
+    // The wall and the end, joined to the cap along the ring
+    fn triangles(&self) -> Vec<[usize; 3]> {
+        let (h, t) = (&self.ring, &self.end);
+        let mut triangles = Vec::with_capacity(3 * SEGMENTS);
+        for k in 0..SEGMENTS {
+            let next = (k + 1) % SEGMENTS;
+            triangles.push([h[next], h[k], t[k]]);
+            triangles.push([h[next], t[k], t[next]]);
+            triangles.push([self.centre, t[next], t[k]]);
+        }
+        triangles
+    }
+}
+
+// Triangles filling an anticlockwise `outline` with clockwise `holes` in
+// it, by ear clipping once each hole is bridged to the outline
+fn triangulate(
+    outline: &[usize],
+    holes: &[&[usize]],
+    at: impl Fn(usize) -> [f64; 2],
+) -> Vec<[usize; 3]> {
+    let mut ring: Vec<(usize, [f64; 2])> = outline.iter().map(|&i| (i, at(i))).collect();
+    let mut holes: Vec<Vec<(usize, [f64; 2])>> = holes
+        .iter()
+        .map(|h| h.iter().map(|&i| (i, at(i))).collect())
+        .collect();
+    // Rightmost first: a hole further right is then part of the ring by the
+    // time one to its left bridges across to it
+    holes.sort_by(|a, b| b[rightmost(b)].1[0].total_cmp(&a[rightmost(a)].1[0]));
+    for hole in &holes {
+        bridge(&mut ring, hole);
+    }
+    clip(&ring)
+}
+
+fn rightmost(ring: &[(usize, [f64; 2])]) -> usize {
+    (0..ring.len())
+        .max_by(|&a, &b| ring[a].1[0].total_cmp(&ring[b].1[0]))
+        .unwrap_or(0)
+}
+
+// Splice `hole` into `ring` along a cut from its rightmost corner to a
+// corner of the ring it can see (Eberly, Triangulation by Ear Clipping)
+fn bridge(ring: &mut Vec<(usize, [f64; 2])>, hole: &[(usize, [f64; 2])]) {
+    let m = rightmost(hole);
+    let from = hole[m].1;
+    let len = ring.len();
+
+    // The nearest edge to the right, and the end of it further right
+    let mut hit: Option<(f64, usize)> = None;
+    for i in 0..len {
+        let (a, b) = (ring[i].1, ring[(i + 1) % len].1);
+        if (a[1] > from[1]) == (b[1] > from[1]) {
+            continue;
+        }
+        let x = a[0] + (from[1] - a[1]) * (b[0] - a[0]) / (b[1] - a[1]);
+        if x >= from[0] && hit.is_none_or(|(best, _)| x < best) {
+            hit = Some((x, if a[0] > b[0] { i } else { (i + 1) % len }));
+        }
+    }
+    let to = match hit {
+        Some((x, end)) => {
+            // A corner poking into the triangle between the hole, the edge
+            // and its end would block the view: take the one nearest the
+            // line across instead
+            let (corner, at) = (ring[end].1, [x, from[1]]);
+            let mut to = end;
+            let mut best = f64::MAX;
+            for q in 0..len {
+                let p = ring[q].1;
+                if p == corner || p[0] <= from[0] || !reflex(ring, q) {
+                    continue;
+                }
+                if !in_triangle([from, at, corner], p) {
+                    continue;
+                }
+                let slope = (p[1] - from[1]).abs() / (p[0] - from[0]);
+                if slope < best {
+                    best = slope;
+                    to = q;
+                }
+            }
+            to
+        }
+        // Only rounding gets here: the nearest corner will do
+        None => (0..len)
+            .min_by(|&a, &b| distance(ring[a].1, from).total_cmp(&distance(ring[b].1, from)))
+            .unwrap_or(0),
+    };
+    let mut joined = Vec::with_capacity(len + hole.len() + 2);
+    joined.extend_from_slice(&ring[..=to]);
+    joined.extend(hole[m..].iter().chain(&hole[..=m]).copied());
+    joined.push(ring[to]);
+    joined.extend_from_slice(&ring[to + 1..]);
+    *ring = joined;
+}
+
+fn reflex(ring: &[(usize, [f64; 2])], q: usize) -> bool {
+    let len = ring.len();
+    let (a, b, c) = (
+        ring[(q + len - 1) % len].1,
+        ring[q].1,
+        ring[(q + 1) % len].1,
+    );
+    turn(a, b, c) < 0.0
+}
+
+// Ear clipping: cut off a corner with nothing else inside it until three
+// are left. When rounding leaves no such corner, any convex one goes, and
+// failing that any at all, so the face is always closed
+fn clip(ring: &[(usize, [f64; 2])]) -> Vec<[usize; 3]> {
+    let len = ring.len();
+    let mut triangles = Vec::with_capacity(len.saturating_sub(2));
+    if len < 3 {
+        return triangles;
+    }
+    let mut prev: Vec<usize> = (0..len).map(|i| (i + len - 1) % len).collect();
+    let mut next: Vec<usize> = (0..len).map(|i| (i + 1) % len).collect();
+    let mut left = len;
+    let mut i = 0;
+    let mut tried = 0;
+    while left > 3 {
+        let (p, q) = (prev[i], next[i]);
+        let convex = turn(ring[p].1, ring[i].1, ring[q].1) > 0.0;
+        let ear = (convex && (tried >= left || empty(ring, &next, p, i, q))) || tried >= 2 * left;
+        if ear {
+            triangles.push([ring[p].0, ring[i].0, ring[q].0]);
+            next[p] = q;
+            prev[q] = p;
+            left -= 1;
+            tried = 0;
+        } else {
+            tried += 1;
+        }
+        i = q;
+    }
+    triangles.push([ring[prev[i]].0, ring[i].0, ring[next[i]].0]);
+    triangles
+}
+
+// Whether no other corner of the ring is in the triangle on `p`, `i`, `q`
+fn empty(ring: &[(usize, [f64; 2])], next: &[usize], p: usize, i: usize, q: usize) -> bool {
+    let corners = [ring[p].1, ring[i].1, ring[q].1];
+    let mut j = next[q];
+    while j != p {
+        let x = ring[j].1;
+        if !corners.contains(&x) && in_triangle(corners, x) {
+            return false;
+        }
+        j = next[j];
+    }
+    true
+}
+
+// Whether `p` is in the triangle or on its edge, either way round
+fn in_triangle([a, b, c]: [[f64; 2]; 3], p: [f64; 2]) -> bool {
+    let (x, y, z) = (turn(a, b, p), turn(b, c, p), turn(c, a, p));
+    (x >= 0.0 && y >= 0.0 && z >= 0.0) || (x <= 0.0 && y <= 0.0 && z <= 0.0)
+}
+
+// Twice the signed area of `a`, `b`, `c`: positive turning left
+fn turn(a: [f64; 2], b: [f64; 2], c: [f64; 2]) -> f64 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+fn signed_area(shape: &[[f64; 2]]) -> f64 {
+    (0..shape.len())
+        .map(|k| {
+            let (a, b) = (shape[k], shape[(k + 1) % shape.len()]);
+            a[0] * b[1] - b[0] * a[1]
+        })
+        .sum::<f64>()
+        * 0.5
+}
+
+// Even-odd: whether `p` is inside the closed `shape`
+fn inside(shape: &[[f64; 2]], p: [f64; 2]) -> bool {
+    let mut inside = false;
+    for k in 0..shape.len() {
+        let (a, b) = (shape[k], shape[(k + 1) % shape.len()]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < a[0] + (p[1] - a[1]) * (b[0] - a[0]) / (b[1] - a[1])
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
+
+fn segment_distance(p: [f64; 2], a: [f64; 2], b: [f64; 2]) -> f64 {
+    let ab = [b[0] - a[0], b[1] - a[1]];
+    let length = ab[0] * ab[0] + ab[1] * ab[1];
+    let t = if length > 0.0 {
+        (((p[0] - a[0]) * ab[0] + (p[1] - a[1]) * ab[1]) / length).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    distance(p, [a[0] + ab[0] * t, a[1] + ab[1] * t])
+}
+
+fn distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    (a[0] - b[0]).hypot(a[1] - b[1])
+}
+
+// The triangles over `points`, as a mesh of only the corners they use
+fn to_mesh(points: &[[f64; 3]], triangles: &[[usize; 3]]) -> Mesh {
+    let mut index = vec![u32::MAX; points.len()];
+    let mut mesh = Mesh::default();
+    for &corner in triangles.iter().flatten() {
+        if index[corner] == u32::MAX {
+            index[corner] = (mesh.positions.len() / 3) as u32;
+            mesh.positions.extend(points[corner].map(|x| x as f32));
+        }
+        mesh.indices.push(index[corner]);
+    }
+    mesh
+}
+
+// Some unit vector at right angles to `n`
+fn perpendicular(n: [f64; 3]) -> [f64; 3] {
+    let axis = if n[0].abs() < 0.9 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 1.0, 0.0]
+    };
+    let u = cross(axis, n);
+    scale(u, 1.0 / dot(u, u).sqrt())
+}
+
+fn add(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn scale(a: [f64; 3], s: f64) -> [f64; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extract::{marching_cubes, soup_volume};
+    use crate::primitives::{Primitive, Shape};
+    use crate::samples::Storage;
+    use crate::slabs::Slabs;
+
+    // A cube `size` across, centred on the origin
+    fn cube(size: f32) -> Mesh {
+        let h = size / 2.0;
+        let positions = [
+            [-h, -h, -h],
+            [h, -h, -h],
+            [h, h, -h],
+            [-h, h, -h],
+            [-h, -h, h],
+            [h, -h, h],
+            [h, h, h],
+            [-h, h, h],
+        ];
+        let faces: [[u32; 3]; 12] = [
+            [0, 2, 1],
+            [0, 3, 2],
+            [4, 5, 6],
+            [4, 6, 7],
+            [0, 1, 5],
+            [0, 5, 4],
+            [3, 7, 6],
+            [3, 6, 2],
+            [0, 4, 7],
+            [0, 7, 3],
+            [1, 2, 6],
+            [1, 6, 5],
+        ];
+        Mesh {
+            positions: positions.concat(),
+            indices: faces.concat(),
+            ..Default::default()
+        }
+    }
+
+    // Every edge once each way: closed, and all faces the same way round
+    fn closed(mesh: &Mesh) -> bool {
+        let mut edges: HashMap<(usize, usize), usize> = HashMap::new();
+        for f in 0..mesh.face_count() {
+            let [a, b, c] = mesh.face(f);
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_default() += 1;
+            }
+        }
+        edges
+            .iter()
+            .all(|(&(a, b), &count)| count == 1 && edges.get(&(b, a)) == Some(&1))
+    }
+
+    fn volume(mesh: &Mesh) -> f64 {
+        let triangles: Vec<f32> = mesh
+            .indices
+            .iter()
+            .flat_map(|&i| mesh.vertex(i as usize))
+            .collect();
+        soup_volume(&triangles)
+    }
+
+    // Volume of a pin or socket, as the polygon it's made of
+    fn cylinder(radius: f64, height: f64) -> f64 {
+        let n = SEGMENTS as f64;
+        0.5 * n * radius * radius * (2.0 * PI / n).sin() * height
+    }
+
+    const NO_PINS: Pins = Pins {
+        per_face: 0,
+        diameter: 5.0,
+        length: 6.0,
+        clearance: 0.2,
+    };
+
+    #[test]
+    fn a_cube_cut_in_half_gives_two_closed_halves() {
+        let halves = split(&cube(40.0), [0.0, 0.0, 1.0], None, &NO_PINS).unwrap();
+        assert_eq!(halves.faces, 1);
+        assert!((halves.area - 1600.0).abs() < 1e-6);
+        for half in [&halves.below, &halves.above] {
+            assert!(closed(half));
+            assert!((volume(half) - 32000.0).abs() < 1e-2);
+        }
+        assert!(halves.below.positions.chunks(3).all(|p| p[2] <= 0.0));
+        assert!(halves.above.positions.chunks(3).all(|p| p[2] >= 0.0));
+    }
+
+    #[test]
+    fn pins_stand_on_one_half_and_sockets_sink_into_the_other() {
+        let pins = Pins {
+            per_face: 2,
+            ..NO_PINS
+        };
+        let halves = split(&cube(40.0), [0.0, 0.0, 1.0], Some(5.0), &pins).unwrap();
+        assert_eq!(halves.pins.len(), 2);
+        assert_eq!(halves.faces_without_pins, 0);
+        for [x, y, z] in &halves.pins {
+            assert_eq!(*z, 5.0);
+            // A socket's width and a wall from the sides
+            assert!(20.0 - x.abs().max(y.abs()) >= 2.7 + 2.5);
+        }
+        assert!(closed(&halves.below));
+        assert!(closed(&halves.above));
+        let (below, above) = (25.0 * 1600.0, 15.0 * 1600.0);
+        let pin = 2.0 * cylinder(2.5, 6.0);
+        let socket = 2.0 * cylinder(2.7, 6.2);
+        assert!((volume(&halves.below) - (below + pin)).abs() < 1e-2);
+        assert!((volume(&halves.above) - (above - socket)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn a_ring_cut_across_keeps_its_hole() {
+        let torus = Primitive {
+            shape: Shape::Torus,
+            size: 2.0,
+            detail: 0.2,
+            cells: 2,
+            seed: 0,
+        };
+        let field = torus.sample(40, Storage::Full, &Slabs::default());
+        let mesh = Mesh::from_triangles(&marching_cubes(&field, field.iso)).unwrap();
+        let pins = Pins {
+            per_face: 1,
+            diameter: 0.1,
+            length: 0.1,
+            clearance: 0.02,
+        };
+        let halves = split(&mesh, [0.0, 0.0, 1.0], Some(0.0), &pins).unwrap();
+        // One face, with the hole through the middle left open
+        assert_eq!(halves.faces, 1);
+        assert_eq!(halves.pins.len(), 1);
+        let [x, y, _] = halves.pins[0];
+        let from_axis = x.hypot(y);
+        assert!(from_axis > 0.2 && from_axis < 1.0);
+        assert!(closed(&halves.below));
+        assert!(closed(&halves.above));
+        let whole = volume(&halves.below) - cylinder(0.05, 0.1)
+            + volume(&halves.above)
+            + cylinder(0.07, 0.12);
+        assert!((whole - volume(&mesh)).abs() < 1e-4 * volume(&mesh));
+    }
+
+    #[test]
+    fn a_thin_cut_gets_no_pins() {
+        let pins = Pins {
+            per_face: 2,
+            diameter: 30.0,
+            ..NO_PINS
+        };
+        let halves = split(&cube(40.0), [1.0, 0.0, 0.0], None, &pins).unwrap();
+        assert!(halves.pins.is_empty());
+        assert_eq!(halves.faces_without_pins, 1);
+        assert!(closed(&halves.below));
+        assert!(closed(&halves.above));
+    }
+
+    #[test]
+    fn a_plane_off_the_model_or_an_open_model_is_refused() {
+        assert!(split(&cube(40.0), [0.0, 0.0, 1.0], Some(30.0), &NO_PINS).is_err());
+        assert!(split(&cube(40.0), [0.0, 0.0, 0.0], None, &NO_PINS).is_err());
+        // No side at x = 20: the cut's outline is open there
+        let mut open = cube(40.0);
+        open.indices.truncate(30);
+        assert!(split(&open, [0.0, 0.0, 1.0], None, &NO_PINS).is_err());
+    }
+}
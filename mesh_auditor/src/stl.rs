@@ -0,0 +1,297 @@
+//! STL input and output.
+
+use crate::ascii;
+use crate::limits::InputLimits;
+use crate::mesh::Mesh;
+use crate::output::WriteOptions;
+use crate::storage;
+use anyhow::{bail, Context, Result};
+use std::io::{Read, Write};
+
+/// Read an ASCII or binary STL. STL stores every triangle's corners
+/// separately, so corners at exactly the same position are joined back up
+/// into shared vertices.
+pub fn load_stl(filename: &str, limits: &InputLimits) -> Result<Mesh> {
+    let mut bytes = Vec::new();
+    storage::open(filename, limits)?.read_to_end(&mut bytes)?;
+
+    // Binary files can start with "solid" too, so trust the size first
+    let binary_count = (bytes.len() >= 84)
+        .then(|| u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize);
+    let is_binary =
+        binary_count.is_some_and(|n| 84 + n * 50 == bytes.len()) || !bytes.starts_with(b"solid");
+
+    let mut corners: Vec<[f32; 3]> = Vec::new();
+    let name;
+    if is_binary {
+        let Some(count) = binary_count else {
+            bail!("{} is too short to be an STL file", filename);
+        };
+        limits.check_triangles(count)?;
+        if bytes.len() < 84 + count * 50 {
+            bail!("{} is cut short: header says {} triangles", filename, count);
+        }
+        // The header is free text, padded with spaces or NULs
+        let header = bytes[..80].split(|&b| b == 0).next().unwrap_or_default();
+        name = std::str::from_utf8(header)
+            .ok()
+            .filter(|text| text.chars().all(|c| !c.is_control()))
+            .map(|text| {
+                text.trim()
+                    .trim_start_matches("binary STL")
+                    .trim()
+                    .to_string()
+            });
+        corners.reserve(count * 3);
+        for t in 0..count {
+            // 12 bytes of normal, then three corners, then 2 attribute bytes
+            let at = 84 + t * 50 + 12;
+            for k in 0..3 {
+                let read = |i: usize| {
+                    let o = at + k * 12 + i * 4;
+                    f32::from_le_bytes([bytes[o], bytes[o + 1], bytes[o + 2], bytes[o + 3]])
+                };
+                corners.push([read(0), read(1), read(2)]);
+            }
+        }
+    } else {
+        let text = std::str::from_utf8(&bytes)
+            .with_context(|| format!("{} is not valid ASCII STL", filename))?;
+        name = text
+            .lines()
+            .next()
+            .and_then(|line| line.trim().strip_prefix("solid"))
+            .map(|rest| rest.trim().to_string());
+        for (n, line) in text.lines().enumerate() {
+            let mut parts = line.split_whitespace();
+            if parts.next() != Some("vertex") {
+                continue;
+            }
+            let mut v = [0.0; 3];
+            for slot in &mut v {
+                let part = parts.next().unwrap_or_default();
+                *slot = part.parse().with_context(|| {
+                    format!("{} line {}: bad coordinate '{}'", filename, n + 1, part)
+                })?;
+            }
+            corners.push(v);
+            if corners.len().is_multiple_of(3) {
+                limits.check_triangles(corners.len() / 3)?;
+            }
+        }
+        if !corners.len().is_multiple_of(3) {
+            bail!("{} has a facet without three vertices", filename);
+        }
+    }
+
+    let mut mesh = Mesh::from_triangles(corners.as_flattened())?;
+    mesh.metadata.name = name.filter(|name| !name.is_empty());
+    mesh.validate()?;
+    Ok(mesh)
+}
+
+/// How STL files are written: `--stl-format`. Binary is about a quarter
+/// the size of ASCII and much quicker to read back, which is what matters
+/// for a dense remesh; ASCII is there for diffing and for old tools.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum StlFormat {
+    /// Text facets, rounded by --ascii-precision
+    #[default]
+    Ascii,
+    /// 50 bytes a triangle, at full float precision
+    Binary,
+}
+
+// Basic STL Writer for the output
+pub fn save_triangles_as_stl(
+    triangles: &[f32],
+    filename: &str,
+    options: &WriteOptions,
+) -> Result<()> {
+    // Marching cubes returns a flat list of coordinates
+    // [x1, y1, z1, x2, y2, z2, ...]
+
+    let triangles = options.ordered_soup(triangles);
+    let mut file = storage::create(filename)?;
+    write_soup(&mut file, &triangles, options)?;
+    file.finish()
+}
+
+/// How many bytes `save_triangles_as_stl` would write for `triangles`.
+pub fn soup_size(triangles: &[f32], options: &WriteOptions) -> Result<u64> {
+    let mut counter = Counter(0);
+    write_soup(&mut counter, triangles, options)?;
+    Ok(counter.0)
+}
+
+fn write_soup(out: &mut impl Write, triangles: &[f32], options: &WriteOptions) -> Result<()> {
+    let facets = triangles.chunks_exact(9).map(|chunk| {
+        let corners = [0, 1, 2].map(|k| [chunk[k * 3], chunk[k * 3 + 1], chunk[k * 3 + 2]]);
+        (facet_normal(corners), corners)
+    });
+    write_facets(out, "voxel_skin", triangles.len() / 9, facets, options)
+}
+
+// Same format for an indexed mesh, whose winding means something too. Its
+// name and notes go in the solid's name, the only text STL has room for
+pub fn save_mesh_as_stl(mesh: &Mesh, filename: &str, options: &WriteOptions) -> Result<()> {
+    let mesh = options.ordered(mesh);
+    let text: Vec<&str> = mesh
+        .metadata
+        .name
+        .iter()
+        .chain(&mesh.notes)
+        .map(String::as_str)
+        .collect();
+    let name = if text.is_empty() {
+        "rust_converted_mesh".to_string()
+    } else {
+        text.join("; ").replace(['\n', '\r'], " ")
+    };
+    let mut file = storage::create(filename)?;
+    let facets = (0..mesh.face_count()).map(|f| {
+        let corners = mesh.face(f).map(|i| mesh.vertex(i));
+        (facet_normal(corners), corners)
+    });
+    write_facets(&mut file, &name, mesh.face_count(), facets, options)?;
+    file.finish()
+}
+
+// Write `count` facets (normal and corners) in the chosen format
+fn write_facets(
+    out: &mut impl Write,
+    name: &str,
+    count: usize,
+    facets: impl Iterator<Item = ([f32; 3], [[f32; 3]; 3])>,
+    options: &WriteOptions,
+) -> Result<()> {
+    if options.stl_format == StlFormat::Binary {
+        // An 80 byte header that mustn't start with "solid" (the name is
+        // cut short to fit), the count, then 50 bytes a facet: normal,
+        // corners and an unused attribute
+        let mut header = [b' '; 80];
+        let title = format!("binary STL {}", name);
+        let fits = title.len().min(header.len());
+        header[..fits].copy_from_slice(&title.as_bytes()[..fits]);
+        out.write_all(&header)?;
+        let count = u32::try_from(count).context("too many triangles for a binary STL")?;
+        out.write_all(&count.to_le_bytes())?;
+        let mut record = [0u8; 50];
+        for (normal, corners) in facets {
+            let values = std::iter::once(normal).chain(corners).flatten();
+            for (i, value) in values.enumerate() {
+                record[i * 4..i * 4 + 4].copy_from_slice(&value.to_le_bytes());
+            }
+            out.write_all(&record)?;
+        }
+        return Ok(());
+    }
+
+    writeln!(out, "solid {}", name)?;
+    let precision = options.ascii_precision;
+    // Each facet is formatted into `facet` and written in one go
+    let mut facet = Vec::with_capacity(256);
+    for (normal, corners) in facets {
+        facet.clear();
+        ascii::write_line(&mut facet, "facet normal", &normal, precision)?;
+        facet.extend_from_slice(b"outer loop\n");
+        for v in corners {
+            ascii::write_line(&mut facet, "vertex", &v, precision)?;
+        }
+        facet.extend_from_slice(b"endloop\nendfacet\n");
+        out.write_all(&facet)?;
+    }
+    writeln!(out, "endsolid {}", name)?;
+    Ok(())
+}
+
+// Unit normal by the right-hand rule, or 0 0 0 for a sliver with none
+fn facet_normal([a, b, c]: [[f32; 3]; 3]) -> [f32; 3] {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        n.map(|x| x / length)
+    } else {
+        [0.0; 3]
+    }
+}
+
+// A writer that only counts what it's given
+struct Counter(u64);
+
+impl Write for Counter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Load `bytes` as an STL file
+    fn load(name: &str, bytes: &[u8]) -> Result<Mesh> {
+        let path = std::env::temp_dir().join(format!("{}_{}.stl", name, std::process::id()));
+        let path = path.to_string_lossy().into_owned();
+        std::fs::write(&path, bytes).unwrap();
+        let mesh = load_stl(&path, &InputLimits::default());
+        std::fs::remove_file(&path).ok();
+        mesh
+    }
+
+    fn binary(count: u32, triangles: usize) -> Vec<u8> {
+        let mut bytes = vec![0u8; 80];
+        bytes.extend_from_slice(&count.to_le_bytes());
+        for t in 0..triangles {
+            bytes.extend_from_slice(&[0; 12]);
+            for corner in [[0.0f32, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, t as f32]] {
+                for x in corner {
+                    bytes.extend_from_slice(&x.to_le_bytes());
+                }
+            }
+            bytes.extend_from_slice(&[0; 2]);
+        }
+        bytes
+    }
+
+    #[test]
+    fn binary_and_ascii_load() {
+        let mesh = load("stl_binary", &binary(2, 2)).unwrap();
+        assert_eq!((mesh.vertex_count(), mesh.face_count()), (4, 2));
+        let text = "solid part\nfacet normal 0 0 1\nouter loop\nvertex 0 0 0\nvertex 1 0 0\n\
+                    vertex 0 1 0\nendloop\nendfacet\nendsolid part\n";
+        let mesh = load("stl_ascii", text.as_bytes()).unwrap();
+        assert_eq!(mesh.face_count(), 1);
+        assert_eq!(mesh.metadata.name.as_deref(), Some("part"));
+    }
+
+    #[test]
+    fn short_binary_is_an_error() {
+        assert!(load("stl_tiny", b"\x00\x01\x02").is_err());
+        assert!(load("stl_short", &binary(3, 2)).is_err());
+        // A header promising four billion triangles over none
+        assert!(load("stl_huge", &binary(u32::MAX, 0)).is_err());
+    }
+
+    #[test]
+    fn bad_ascii_is_an_error() {
+        let bad_number = "solid x\nvertex 0 0 zero\nvertex 1 0 0\nvertex 0 1 0\nendsolid x\n";
+        assert!(load("stl_bad_number", bad_number.as_bytes()).is_err());
+        let two_corners = "solid x\nvertex 0 0 0\nvertex 1 0 0\nendsolid x\n";
+        assert!(load("stl_two_corners", two_corners.as_bytes()).is_err());
+        let not_finite = "solid x\nvertex 0 0 inf\nvertex 1 0 0\nvertex 0 1 0\nendsolid x\n";
+        assert!(load("stl_inf", not_finite.as_bytes()).is_err());
+        assert!(load("stl_not_utf8", b"solid \xff\xfe\n").is_err());
+    }
+}
@@ -0,0 +1,429 @@
+//! Where inputs come from and outputs go: local paths, `http(s)://` URLs
+//! and `s3://bucket/key` objects.
+//!
+//! Inputs are streamed straight into the loaders (through the usual
+//! `LimitedReader`), never staged in a temp file. Outputs to a local path
+//! are written as they're produced; remote outputs are collected in memory
+//! and uploaded in one PUT by `Output::finish`, because S3 wants to know
+//! the size of an object before it accepts it.
+//!
+//! S3 requests are signed (AWS Signature V4) with credentials from the
+//! usual environment variables: `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`,
+//! `AWS_SESSION_TOKEN` (optional) and `AWS_REGION` / `AWS_DEFAULT_REGION`
+//! (default `us-east-1`). Set `AWS_ENDPOINT_URL` to talk to something other
+//! than AWS itself (MinIO, a local test server); buckets are then
+//! addressed path-style.
+//!
+//! Redirects aren't followed: a location that redirects is an error naming
+//! where it points, to be given instead. A server job's own locations are
+//! opened with `Hosts::Public`, which also refuses, as it connects, any
+//! host that resolves to an internal address (see `allowlist`).
+
+use crate::allowlist;
+use crate::clock::{self, UtcTime};
+use crate::limits::{InputLimits, LimitedReader};
+use anyhow::{bail, Context, Result};
+use hmac::{Hmac, KeyInit, Mac};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A parsed input or output location.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Local(String),
+    Http(String),
+    S3 { bucket: String, key: String },
+}
+
+impl Location {
+    pub fn parse(location: &str) -> Result<Self> {
+        if let Some(rest) = location.strip_prefix("s3://") {
+            let Some((bucket, key)) = rest.split_once('/') else {
+                bail!(
+                    "'{}' has no object key (expected s3://bucket/key)",
+                    location
+                );
+            };
+            if bucket.is_empty() || key.is_empty() {
+                bail!("'{}' is not a valid s3://bucket/key URI", location);
+            }
+            return Ok(Location::S3 {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            });
+        }
+        if location.starts_with("http://") || location.starts_with("https://") {
+            return Ok(Location::Http(location.to_string()));
+        }
+        Ok(Location::Local(location.to_string()))
+    }
+
+    pub fn is_remote(&self) -> bool {
+        !matches!(self, Location::Local(_))
+    }
+}
+
+/// Which hosts an `http(s)://` location may be on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hosts {
+    /// Wherever the name resolves: locations given on the command line.
+    Any,
+    /// Only public addresses, checked at connect time: locations a server
+    /// job's client named.
+    Public,
+}
+
+/// Open an input for reading, wherever it lives, refusing it once it goes
+/// over the input size limit.
+pub fn open(location: &str, limits: &InputLimits) -> Result<Box<dyn Read + Send>> {
+    open_from(location, limits, Hosts::Any)
+}
+
+/// `open`, with `http(s)://` inputs only from `hosts`.
+pub fn open_from(
+    location: &str,
+    limits: &InputLimits,
+    hosts: Hosts,
+) -> Result<Box<dyn Read + Send>> {
+    let request = match Location::parse(location)? {
+        Location::Local(path) => return Ok(Box::new(limits.open(&path)?)),
+        Location::Http(url) => agent(hosts).get(&url),
+        Location::S3 { bucket, key } => S3Request::new("GET", &bucket, &key, &[])?.build(),
+    };
+    let response = request
+        .call()
+        .with_context(|| format!("could not fetch {}", location))?;
+    refuse_redirect(location, &response)?;
+
+    // Refuse early when the server tells us the size; the reader limit
+    // catches the rest
+    if let Some(size) = response
+        .header("Content-Length")
+        .and_then(|len| len.parse::<u64>().ok())
+    {
+        if size > limits.max_input_size {
+            bail!(
+                "{} is {} bytes, over the {} byte input limit",
+                location,
+                size,
+                limits.max_input_size
+            );
+        }
+    }
+    Ok(Box::new(LimitedReader::new(
+        response.into_reader(),
+        limits.max_input_size,
+    )))
+}
+
+// Big enough that the text writers make a syscall every few thousand lines
+// rather than every line
+const WRITE_BUFFER: usize = 1 << 20;
+
+/// A file being written: local outputs go straight to disk, remote ones are
+/// buffered until `finish` uploads them. Forgetting `finish` loses the
+/// remote upload (and any buffered local bytes).
+pub enum Output {
+    Local(BufWriter<File>),
+    Remote { location: String, buffer: Vec<u8> },
+}
+
+/// Start writing to `location`.
+pub fn create(location: &str) -> Result<Output> {
+    if Location::parse(location)?.is_remote() {
+        return Ok(Output::Remote {
+            location: location.to_string(),
+            buffer: Vec::with_capacity(WRITE_BUFFER),
+        });
+    }
+    let file = File::create(location).with_context(|| format!("could not create {}", location))?;
+    Ok(Output::Local(BufWriter::with_capacity(WRITE_BUFFER, file)))
+}
+
+impl Output {
+    /// Flush a local file, or upload a remote one.
+    pub fn finish(self) -> Result<()> {
+        match self {
+            Output::Local(mut file) => {
+                file.flush()?;
+                Ok(())
+            }
+            Output::Remote { location, buffer } => upload(&location, &buffer, Hosts::Any),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            Output::Local(file) => file.write(buf),
+            Output::Remote { buffer, .. } => buffer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            Output::Local(file) => file.flush(),
+            Output::Remote { .. } => Ok(()),
+        }
+    }
+}
+
+/// PUT `body` to a remote location, an `http(s)://` one only on `hosts`.
+pub fn upload(location: &str, body: &[u8], hosts: Hosts) -> Result<()> {
+    let request = match Location::parse(location)? {
+        Location::Local(path) => bail!("{} is not a remote location", path),
+        Location::Http(url) => agent(hosts).put(&url),
+        Location::S3 { bucket, key } => S3Request::new("PUT", &bucket, &key, body)?.build(),
+    };
+    let response = request
+        .send_bytes(body)
+        .with_context(|| format!("could not upload {}", location))?;
+    refuse_redirect(location, &response)
+}
+
+// S3 requests go to the operator's endpoint (AWS or `AWS_ENDPOINT_URL`),
+// which may well be on the local network, so only URLs are held to `hosts`
+fn agent(hosts: Hosts) -> ureq::Agent {
+    let builder = ureq::AgentBuilder::new()
+        .timeout(REQUEST_TIMEOUT)
+        .redirects(0);
+    match hosts {
+        Hosts::Any => builder.build(),
+        Hosts::Public => builder.resolver(allowlist::resolve_public).build(),
+    }
+}
+
+// With redirects off, a 3xx comes back as a response
+fn refuse_redirect(location: &str, response: &ureq::Response) -> Result<()> {
+    if (300..400).contains(&response.status()) {
+        bail!(
+            "{} redirects to {}; give that location instead",
+            location,
+            response.header("Location").unwrap_or("somewhere else")
+        );
+    }
+    Ok(())
+}
+
+/// One signed S3 request.
+struct S3Request {
+    method: &'static str,
+    url: String,
+    headers: Vec<(String, String)>,
+}
+
+impl S3Request {
+    fn new(method: &'static str, bucket: &str, key: &str, body: &[u8]) -> Result<Self> {
+        let access_key = std::env::var("AWS_ACCESS_KEY_ID")
+            .context("s3:// needs AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY")?;
+        let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+            .context("s3:// needs AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY")?;
+        let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+        let region = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".to_string());
+
+        let path_key = uri_encode(key, false);
+        let (host, path, url) = match std::env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => {
+                let endpoint = endpoint.trim_end_matches('/');
+                let host = endpoint
+                    .split_once("://")
+                    .map_or(endpoint, |(_, host)| host)
+                    .to_string();
+                let path = format!("/{}/{}", uri_encode(bucket, true), path_key);
+                (host, path.clone(), format!("{}{}", endpoint, path))
+            }
+            Err(_) => {
+                let host = format!("{}.s3.{}.amazonaws.com", bucket, region);
+                let path = format!("/{}", path_key);
+                (
+                    host.clone(),
+                    path.clone(),
+                    format!("https://{}{}", host, path),
+                )
+            }
+        };
+
+        let now = utc_timestamp(clock::unix_now());
+        let date = &now[..8];
+        let payload_hash = hex(&Sha256::digest(body));
+        let mut headers = vec![
+            ("host".to_string(), host),
+            ("x-amz-content-sha256".to_string(), payload_hash.clone()),
+            ("x-amz-date".to_string(), now.clone()),
+        ];
+        if let Some(token) = session_token {
+            headers.push(("x-amz-security-token".to_string(), token));
+        }
+
+        let signature = sign(
+            method,
+            &path,
+            &headers,
+            &payload_hash,
+            &now,
+            &region,
+            &secret_key,
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, region);
+        let signed_headers = signed_header_names(&headers);
+        headers.push((
+            "authorization".to_string(),
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                access_key, scope, signed_headers, signature
+            ),
+        ));
+        Ok(S3Request {
+            method,
+            url,
+            headers,
+        })
+    }
+
+    fn build(self) -> ureq::Request {
+        let mut request = agent(Hosts::Any).request(self.method, &self.url);
+        for (name, value) in &self.headers {
+            // ureq sets Host itself from the URL
+            if name != "host" {
+                request = request.set(name, value);
+            }
+        }
+        request
+    }
+}
+
+// The SigV4 signature for a request with no query string. `headers` must
+// be lowercase and sorted by name.
+fn sign(
+    method: &str,
+    path: &str,
+    headers: &[(String, String)],
+    payload_hash: &str,
+    timestamp: &str,
+    region: &str,
+    secret_key: &str,
+) -> String {
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method,
+        path,
+        canonical_headers,
+        signed_header_names(headers),
+        payload_hash
+    );
+    let date = &timestamp[..8];
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let key = hmac(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes());
+    let key = hmac(&key, region.as_bytes());
+    let key = hmac(&key, b"s3");
+    let key = hmac(&key, b"aws4_request");
+    hex(&hmac(&key, string_to_sign.as_bytes()))
+}
+
+fn signed_header_names(headers: &[(String, String)]) -> String {
+    headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// Percent-encode everything but RFC 3986 unreserved characters (and `/`,
+// unless `encode_slash`), the way SigV4 expects
+fn uri_encode(text: &str, encode_slash: bool) -> String {
+    let mut out = String::with_capacity(text.len());
+    for b in text.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            b'/' if !encode_slash => out.push('/'),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+// `YYYYMMDDTHHMMSSZ` in UTC
+fn utc_timestamp(secs: u64) -> String {
+    let t = UtcTime::from_unix(secs);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        t.year, t.month, t.day, t.hour, t.minute, t.second
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+    use std::net::TcpListener;
+
+    // A one-request HTTP server on loopback that answers with `response`
+    fn serve_once(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = std::io::BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            reader.get_mut().write_all(response.as_bytes()).unwrap();
+        });
+        format!("http://{}/scan.obj", address)
+    }
+
+    #[test]
+    fn redirects_are_not_followed() {
+        let url = serve_once(
+            "HTTP/1.1 302 Found\r\nLocation: http://169.254.169.254/\r\nContent-Length: 0\r\n\r\n",
+        );
+        let error = open(&url, &InputLimits::default()).err().unwrap();
+        assert!(error
+            .to_string()
+            .contains("redirects to http://169.254.169.254/"));
+    }
+
+    #[test]
+    fn public_hosts_only_refuses_loopback() {
+        let url = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 7\r\n\r\nv 0 0 0");
+        let limits = InputLimits::default();
+        assert!(open_from(&url, &limits, Hosts::Public).is_err());
+        let mut text = String::new();
+        open_from(&url, &limits, Hosts::Any)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text, "v 0 0 0");
+    }
+}
@@ -0,0 +1,227 @@
+//! `audit --symmetry`: how far a part is from its own mirror image.
+//!
+//! For scanned anatomical parts left/right symmetry is a check of its own.
+//! The mirror plane isn't known, so it is found: each of the three planes
+//! through the centroid across a principal axis is a first guess, refined
+//! by mirroring every vertex, pairing it with the vertex nearest its
+//! mirror image and moving the plane to sit halfway between the pairs, a
+//! few rounds over. The guess whose mirror ends up closest wins. Each
+//! vertex's deviation is then the distance from its mirror image to the
+//! nearest vertex, and the score is the share of vertices within
+//! `tolerance` of their mirror. Distances are to vertices, not the surface
+//! between them, so on a coarse mesh they include up to the point spacing.
+
+use crate::kdtree::KdTree;
+use crate::mesh::Mesh;
+use crate::output::WriteOptions;
+use crate::sandbox;
+use crate::volumes::principal_axes;
+use anyhow::Result;
+use serde::Serialize;
+
+/// The default tolerance, as a fraction of the bounding box diagonal.
+pub const DEFAULT_TOLERANCE: f64 = 0.01;
+// Vertices used to fit the plane; all of them are scored
+const FIT_SAMPLES: usize = 20_000;
+const FIT_ROUNDS: usize = 20;
+// Pairs further apart than this many times the median are left out of a
+// round's fit, so a genuinely lopsided part doesn't drag the plane along
+const TRIM: f64 = 3.0;
+
+/// The best mirror plane and how well the part matches its reflection.
+#[derive(Debug, Clone, Serialize)]
+pub struct Symmetry {
+    /// The plane's unit normal and offset: points with normal·p = offset.
+    pub normal: [f64; 3],
+    pub offset: f64,
+    pub rms_deviation: f64,
+    pub max_deviation: f64,
+    pub tolerance: f64,
+    /// Share of vertices (0 to 1) within `tolerance` of their mirror.
+    pub score: f64,
+    /// Per vertex, for the heatmap.
+    #[serde(skip)]
+    pub deviations: Vec<f64>,
+}
+
+/// Find the mirror plane and score the mesh against it. `tolerance` is in
+/// model units; `None` takes `DEFAULT_TOLERANCE` of the diagonal. The
+/// search tree is built on `threads` threads.
+pub fn measure(mesh: &Mesh, tolerance: Option<f64>, threads: usize) -> Option<Symmetry> {
+    let points: Vec<[f32; 3]> = (0..mesh.vertex_count()).map(|v| mesh.vertex(v)).collect();
+    if points.len() < 4 {
+        return None;
+    }
+    let (min, max) = mesh.bounds();
+    let diagonal = (0..3)
+        .map(|k| f64::from(max[k] - min[k]).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    let tolerance = tolerance.unwrap_or(DEFAULT_TOLERANCE * diagonal);
+    let tree = KdTree::new(&points, threads);
+
+    // 1. The principal planes through the centroid, as first guesses
+    let n = points.len() as f64;
+    let mut centre = [0.0f64; 3];
+    for p in &points {
+        for k in 0..3 {
+            centre[k] += f64::from(p[k]) / n;
+        }
+    }
+    let mut covariance = [[0.0f64; 3]; 3];
+    for p in &points {
+        let d = [0, 1, 2].map(|k| f64::from(p[k]) - centre[k]);
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += d[i] * d[j] / n;
+            }
+        }
+    }
+
+    // 2. Refine each and keep the one whose mirror is closest
+    let step = points.len().div_ceil(FIT_SAMPLES).max(1);
+    let sample: Vec<[f32; 3]> = points.iter().step_by(step).copied().collect();
+    let (normal, offset, deviations) = principal_axes(covariance)
+        .into_iter()
+        .map(|axis| {
+            let (normal, offset) = refine(&tree, &points, &sample, axis, dot(axis, centre));
+            let deviations = deviations(&tree, &points, normal, offset);
+            (normal, offset, deviations)
+        })
+        .min_by(|a, b| rms(&a.2).total_cmp(&rms(&b.2)))?;
+
+    // 3. The score
+    let within = deviations.iter().filter(|&&d| d <= tolerance).count();
+    Some(Symmetry {
+        normal,
+        offset,
+        rms_deviation: rms(&deviations),
+        max_deviation: deviations.iter().copied().fold(0.0, f64::max),
+        tolerance,
+        score: within as f64 / deviations.len() as f64,
+        deviations,
+    })
+}
+
+/// `mesh` coloured by deviation, blue (none) through green and yellow to
+/// red (twice the tolerance or more), saved as a vertex-coloured OBJ.
+pub fn save_heatmap(
+    mesh: &Mesh,
+    symmetry: &Symmetry,
+    path: &str,
+    options: &WriteOptions,
+) -> Result<()> {
+    let mut heatmap = mesh.clone();
+    heatmap.colours = symmetry
+        .deviations
+        .iter()
+        .flat_map(|&d| ramp((d / (2.0 * symmetry.tolerance)).clamp(0.0, 1.0) as f32))
+        .collect();
+    heatmap.save_obj(path, options)
+}
+
+// Move the plane to sit halfway between each vertex and the one nearest
+// its mirror image, round after round
+fn refine(
+    tree: &KdTree,
+    points: &[[f32; 3]],
+    sample: &[[f32; 3]],
+    mut normal: [f64; 3],
+    mut offset: f64,
+) -> ([f64; 3], f64) {
+    for _ in 0..FIT_ROUNDS {
+        sandbox::checkpoint();
+        // One query at a time: on mirror images that is many times faster
+        // than `nearest_batch`
+        let pairs: Vec<([f64; 3], [f64; 3], f64)> = sample
+            .iter()
+            .filter_map(|&p| {
+                let (q, dist_sq) = tree.nearest(reflect(p, normal, offset))?;
+                Some((
+                    p.map(f64::from),
+                    points[q].map(f64::from),
+                    f64::from(dist_sq),
+                ))
+            })
+            .collect();
+        let mut distances: Vec<f64> = pairs.iter().map(|pair| pair.2).collect();
+        let middle = distances.len() / 2;
+        distances.select_nth_unstable_by(middle, f64::total_cmp);
+        let cut = distances[middle] * TRIM * TRIM;
+
+        // Each pair pulls the normal along the line between them, and the
+        // plane through their midpoint
+        let mut direction = [0.0f64; 3];
+        let mut kept = Vec::with_capacity(pairs.len());
+        for &(p, q, dist_sq) in &pairs {
+            if dist_sq > cut {
+                continue;
+            }
+            let mut d = [0, 1, 2].map(|k| p[k] - q[k]);
+            if dot(d, normal) < 0.0 {
+                d = d.map(|x| -x);
+            }
+            for k in 0..3 {
+                direction[k] += d[k];
+            }
+            kept.push((p, q));
+        }
+        let length = dot(direction, direction).sqrt();
+        if length == 0.0 || kept.is_empty() {
+            break;
+        }
+        let new_normal = direction.map(|x| x / length);
+        let new_offset = kept
+            .iter()
+            .map(|(p, q)| dot(new_normal, [0, 1, 2].map(|k| (p[k] + q[k]) * 0.5)))
+            .sum::<f64>()
+            / kept.len() as f64;
+        let settled = dot(new_normal, normal) > 1.0 - 1e-12 && (new_offset - offset).abs() < 1e-9;
+        normal = new_normal;
+        offset = new_offset;
+        if settled {
+            break;
+        }
+    }
+    (normal, offset)
+}
+
+// Every vertex's distance from its mirror image to the nearest vertex
+fn deviations(tree: &KdTree, points: &[[f32; 3]], normal: [f64; 3], offset: f64) -> Vec<f64> {
+    points
+        .iter()
+        .map(|&p| {
+            sandbox::checkpoint();
+            tree.nearest(reflect(p, normal, offset))
+                .map_or(f64::MAX, |(_, dist_sq)| f64::from(dist_sq).sqrt())
+        })
+        .collect()
+}
+
+fn reflect(p: [f32; 3], normal: [f64; 3], offset: f64) -> [f32; 3] {
+    let p = p.map(f64::from);
+    let along = dot(normal, p) - offset;
+    [0, 1, 2].map(|k| (p[k] - 2.0 * along * normal[k]) as f32)
+}
+
+fn rms(values: &[f64]) -> f64 {
+    (values.iter().map(|v| v * v).sum::<f64>() / values.len().max(1) as f64).sqrt()
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// Blue, green, yellow, red at 0, 1/3, 2/3 and 1
+fn ramp(t: f32) -> [f32; 3] {
+    const STOPS: [[f32; 3]; 4] = [
+        [0.15, 0.35, 0.95],
+        [0.2, 0.8, 0.3],
+        [0.95, 0.85, 0.2],
+        [0.9, 0.15, 0.1],
+    ];
+    let at = t * 3.0;
+    let i = (at as usize).min(2);
+    let f = at - i as f32;
+    [0, 1, 2].map(|k| STOPS[i][k] + (STOPS[i + 1][k] - STOPS[i][k]) * f)
+}
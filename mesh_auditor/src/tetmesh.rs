@@ -0,0 +1,285 @@
+//! `tetmesh -o part.msh`: filling a closed surface with tetrahedra, for
+//! finite element analysis.
+//!
+//! The surface's signed distance field is sampled on a grid, as `remesh`
+//! samples it, and every cube of the grid is cut into six tetrahedra round
+//! its main diagonal (the Kuhn split). Neighbouring cubes cut the face
+//! between them along the same diagonal, so the tetrahedra meet face to
+//! face throughout. Those whose corners are inside on average are kept.
+//!
+//! That leaves a block with a stepped outside. Each vertex on it is then
+//! pulled onto the nearest point of the surface, or halfway there, as long
+//! as no tetrahedron round it turns over or goes nearly flat; one that
+//! can't move at all stays on its step. The result follows the surface to
+//! within about a cell, with regular tetrahedra inside and squashed ones
+//! along the boundary, where the snapping distorted them. How squashed is
+//! reported as the volume-to-edge quality (1 for a regular tetrahedron,
+//! towards 0 for a flat one).
+//!
+//! The surface has to be closed and agree about which way round it goes,
+//! or inside is not defined: `remesh` or `heal` a scan first.
+//!
+//! Written as Gmsh MSH 4.1 (the tetrahedra, and the boundary's triangles
+//! in physical groups to hang boundary conditions on, see `msh`) or as VTK
+//! (see `vtk`), by the output's extension.
+
+use crate::audit;
+use crate::bvh::Bvh;
+use crate::labels::FaceLabels;
+use crate::mesh::Mesh;
+use crate::msh;
+use crate::remesh;
+use crate::samples::Storage;
+use crate::sandbox;
+use crate::slabs::Slabs;
+use crate::vtk;
+use anyhow::{bail, Result};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Grid points along the longest side by default: coarser than `remesh`'s, as every
+/// cell becomes six tetrahedra.
+pub const DEFAULT_RESOLUTION: usize = 30;
+
+// A snapped tetrahedron keeps at least this much of a grid tetrahedron's
+// volume, or the vertex doesn't move that far
+const MIN_VOLUME: f64 = 0.1;
+
+// The six tetrahedra of a cube, by corner (bit 0 is +x, bit 1 +y, bit 2
+// +z): one per path along the edges from corner 0 to corner 7
+const KUHN: [[usize; 4]; 6] = [
+    [0, 1, 3, 7],
+    [0, 1, 5, 7],
+    [0, 2, 3, 7],
+    [0, 2, 6, 7],
+    [0, 4, 5, 7],
+    [0, 4, 6, 7],
+];
+
+/// Tetrahedra over shared vertices, each wound so its volume is positive
+/// (the fourth corner is on the side the first three's normal points to).
+#[derive(Debug, Clone, Default)]
+pub struct TetMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub tets: Vec<[u32; 4]>,
+}
+
+impl TetMesh {
+    pub fn volume(&self) -> f64 {
+        (0..self.tets.len()).map(|t| self.signed_volume(t)).sum()
+    }
+
+    pub fn signed_volume(&self, t: usize) -> f64 {
+        let [a, b, c, d] = self.corners(t);
+        tet_volume(a, b, c, d)
+    }
+
+    /// 6√2 V / l³ with l the root mean square edge: 1 for a regular
+    /// tetrahedron, 0 for a flat one, negative for one inside out.
+    pub fn quality(&self, t: usize) -> f64 {
+        let p = self.corners(t);
+        let mut sum_sq = 0.0;
+        for i in 0..4 {
+            for j in i + 1..4 {
+                sum_sq += (0..3).map(|k| (p[i][k] - p[j][k]).powi(2)).sum::<f64>();
+            }
+        }
+        let rms = (sum_sq / 6.0).sqrt();
+        if rms == 0.0 {
+            return 0.0;
+        }
+        6.0 * 2f64.sqrt() * self.signed_volume(t) / rms.powi(3)
+    }
+
+    /// The triangles on the outside, wound to face out of the solid.
+    pub fn boundary(&self) -> Vec<[u32; 3]> {
+        let mut faces: HashMap<[u32; 3], Option<[u32; 3]>> = HashMap::new();
+        for &[a, b, c, d] in &self.tets {
+            for face in [[a, c, b], [a, b, d], [a, d, c], [b, c, d]] {
+                let mut key = face;
+                key.sort_unstable();
+                faces
+                    .entry(key)
+                    .and_modify(|seen| *seen = None)
+                    .or_insert(Some(face));
+            }
+        }
+        let mut boundary: Vec<[u32; 3]> = faces.into_values().flatten().collect();
+        boundary.sort_unstable();
+        boundary
+    }
+
+    fn corners(&self, t: usize) -> [[f64; 3]; 4] {
+        self.tets[t].map(|v| self.positions[v as usize].map(f64::from))
+    }
+}
+
+/// What filling a surface made.
+#[derive(Debug)]
+pub struct Tetrahedralised {
+    pub mesh: TetMesh,
+    /// The surface's cell size along each axis.
+    pub spacing: [f32; 3],
+    /// Boundary vertices moved all the way onto the surface.
+    pub snapped: usize,
+    /// Moved halfway: all the way would have flattened a tetrahedron.
+    pub partly_snapped: usize,
+    /// Left on the grid.
+    pub held: usize,
+    pub min_quality: f64,
+    pub mean_quality: f64,
+}
+
+/// Fill the closed `surface` with tetrahedra from a grid of `resolution`
+/// points along its longest side, sampled on `threads` threads.
+pub fn tetrahedralise(
+    surface: &Mesh,
+    resolution: usize,
+    threads: usize,
+) -> Result<Tetrahedralised> {
+    if resolution < 4 {
+        bail!("resolution must be at least 4");
+    }
+    if surface.face_count() == 0 {
+        bail!("the mesh has no faces to fill");
+    }
+    let edges = audit::edge_faces(surface);
+    let open = edges.values().filter(|f| f.len() == 1).count();
+    let non_manifold = edges.values().filter(|f| f.len() > 2).count();
+    if open > 0 || non_manifold > 0 {
+        bail!(
+            "the surface isn't closed ({} open and {} non-manifold edges), so it has no inside: remesh or heal it first",
+            open,
+            non_manifold
+        );
+    }
+
+    // 1. The field, and the grid's cells cut into tetrahedra
+    let slabs = Slabs {
+        threads,
+        ..Slabs::default()
+    };
+    let field = remesh::sample_surface(surface, resolution, Storage::Full, &slabs);
+    let [nx, ny, nz] = field.dims;
+    let mut vertex_of = vec![u32::MAX; nx * ny * nz];
+    let mut mesh = TetMesh::default();
+    for z in 0..nz - 1 {
+        sandbox::checkpoint();
+        for y in 0..ny - 1 {
+            for x in 0..nx - 1 {
+                let corner = |c: usize| (x + (c & 1), y + (c >> 1 & 1), z + (c >> 2));
+                let values: [f32; 8] = std::array::from_fn(|c| {
+                    let (x, y, z) = corner(c);
+                    field.get(x, y, z)
+                });
+                for tet in KUHN {
+                    if tet.iter().map(|&c| values[c]).sum::<f32>() >= 0.0 {
+                        continue;
+                    }
+                    let mut vertices = tet.map(|c| {
+                        let (x, y, z) = corner(c);
+                        let i = field.index(x, y, z);
+                        if vertex_of[i] == u32::MAX {
+                            vertex_of[i] = mesh.positions.len() as u32;
+                            mesh.positions.push(field.position(x, y, z));
+                        }
+                        vertex_of[i]
+                    });
+                    mesh.tets.push(vertices);
+                    if mesh.signed_volume(mesh.tets.len() - 1) < 0.0 {
+                        vertices.swap(2, 3);
+                        *mesh.tets.last_mut().unwrap() = vertices;
+                    }
+                }
+            }
+        }
+    }
+    if mesh.tets.is_empty() {
+        bail!("the surface encloses less than a grid cell: raise --resolution");
+    }
+
+    // 2. The boundary's vertices onto the surface, as far as the
+    // tetrahedra round them allow
+    let spacing = field.spacing;
+    let floor = MIN_VOLUME * spacing.iter().map(|&s| f64::from(s)).product::<f64>() / 6.0;
+    let mut tets_of: Vec<Vec<usize>> = vec![Vec::new(); mesh.positions.len()];
+    for (t, tet) in mesh.tets.iter().enumerate() {
+        for &v in tet {
+            tets_of[v as usize].push(t);
+        }
+    }
+    let mut on_boundary = vec![false; mesh.positions.len()];
+    for face in mesh.boundary() {
+        for v in face {
+            on_boundary[v as usize] = true;
+        }
+    }
+    let bvh = Bvh::new(surface);
+    let (mut snapped, mut partly_snapped, mut held) = (0, 0, 0);
+    for v in (0..mesh.positions.len()).filter(|&v| on_boundary[v]) {
+        let start = mesh.positions[v];
+        let Some((_, target, _)) = bvh.nearest(start, f32::INFINITY) else {
+            held += 1;
+            continue;
+        };
+        let moved = [1.0, 0.5].into_iter().position(|share| {
+            mesh.positions[v] = [0, 1, 2].map(|k| start[k] + (target[k] - start[k]) * share);
+            tets_of[v].iter().all(|&t| mesh.signed_volume(t) > floor)
+        });
+        match moved {
+            Some(0) => snapped += 1,
+            Some(_) => partly_snapped += 1,
+            None => {
+                mesh.positions[v] = start;
+                held += 1;
+            }
+        }
+    }
+
+    let qualities: Vec<f64> = (0..mesh.tets.len()).map(|t| mesh.quality(t)).collect();
+    let min_quality = qualities.iter().copied().fold(f64::INFINITY, f64::min);
+    let mean_quality = qualities.iter().sum::<f64>() / qualities.len() as f64;
+    Ok(Tetrahedralised {
+        mesh,
+        spacing,
+        snapped,
+        partly_snapped,
+        held,
+        min_quality,
+        mean_quality,
+    })
+}
+
+/// Write `mesh`, filling `surface`, as Gmsh MSH (`.msh`) or VTK (`.vtk` or
+/// `.vtu`). In MSH its boundary is in physical groups by `labels` (see
+/// `msh`), which are returned with their sizes; VTK has none.
+pub fn write(
+    mesh: &TetMesh,
+    surface: &Mesh,
+    labels: Option<&FaceLabels>,
+    location: &str,
+) -> Result<Vec<(String, usize)>> {
+    let extension = Path::new(location)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("msh") => msh::save_volume(mesh, surface, labels, location),
+        Some("vtk" | "vtu") => {
+            vtk::Grid::tetrahedra(mesh).save(location)?;
+            Ok(Vec::new())
+        }
+        _ => bail!("tetrahedra are written as .msh (Gmsh), .vtk or .vtu"),
+    }
+}
+
+fn tet_volume(a: [f64; 3], b: [f64; 3], c: [f64; 3], d: [f64; 3]) -> f64 {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let w = [d[0] - a[0], d[1] - a[1], d[2] - a[2]];
+    let cross = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    (cross[0] * w[0] + cross[1] * w[1] + cross[2] * w[2]) / 6.0
+}
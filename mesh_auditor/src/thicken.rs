@@ -0,0 +1,137 @@
+//! `thicken --offset 2mm`: turning an open surface into a solid.
+//!
+//! A scanned relief or a repaired patch is one sheet with no inside, which
+//! a printer can't fill. A copy of the sheet is moved along the vertex
+//! normals by the offset, one of the two is turned over so both face out
+//! of the slab between them, and a strip of quads joins them along every
+//! open edge. What comes out encloses the slab, as long as the sheet's
+//! own faces agree about which way round they go.
+//!
+//! The copy is moved the same distance everywhere, so a hollow tighter
+//! than the offset folds the copy over on itself. Those faces are counted
+//! rather than fixed: a smaller offset, or a remesh of the result, is the
+//! way out.
+
+use crate::audit;
+use crate::mesh::Mesh;
+use anyhow::{bail, Result};
+
+/// What thickening made.
+#[derive(Debug)]
+pub struct Thickened {
+    pub mesh: Mesh,
+    /// Open edges joined to their copy by a strip of quads.
+    pub rim_edges: usize,
+    /// Edges with three or more faces, which get no rim: the result has a
+    /// gap there.
+    pub non_manifold_edges: usize,
+    /// Faces of the moved copy that the offset turned over.
+    pub folded_faces: usize,
+}
+
+/// Thicken `sheet` by `offset` along its normals (against them if
+/// negative), closing the slab along its open edges.
+pub fn thicken(sheet: &Mesh, offset: f32) -> Result<Thickened> {
+    if !offset.is_finite() || offset == 0.0 {
+        bail!("the offset must be a non-zero distance");
+    }
+    if sheet.face_count() == 0 {
+        bail!("the mesh has no faces to thicken");
+    }
+    let normals = sheet.vertex_normals();
+    let n = sheet.vertex_count();
+
+    // 1. The sheet and its copy, the copy's vertices after the sheet's
+    let mut mesh = Mesh {
+        positions: sheet.positions.clone(),
+        colours: sheet.colours.clone(),
+        vertex_attributes: sheet.vertex_attributes.clone(),
+        ..Default::default()
+    };
+    for (v, normal) in normals.iter().enumerate() {
+        let p = sheet.vertex(v);
+        let moved = [0, 1, 2].map(|k| p[k] + normal[k] * offset);
+        mesh.push_vertex(moved, sheet.colour(v).unwrap_or_default());
+        mesh.copy_vertex_attributes(n + v, sheet, v);
+    }
+
+    // 2. Whichever lies along the normals keeps its winding, the other
+    // turns over so it faces away from the slab too
+    let (outer, inner) = if offset > 0.0 { (n, 0) } else { (0, n) };
+    let mut folded_faces = 0;
+    for f in 0..sheet.face_count() {
+        let [a, b, c] = sheet.face(f);
+        mesh.indices
+            .extend([a + outer, b + outer, c + outer].map(|i| i as u32));
+        mesh.indices
+            .extend([a + inner, c + inner, b + inner].map(|i| i as u32));
+        let before = sheet.face_normal(f);
+        let after = face_normal(&mesh, [a + n, b + n, c + n]);
+        if (0..3).map(|k| before[k] * after[k]).sum::<f32>() < 0.0 {
+            folded_faces += 1;
+        }
+    }
+
+    // 3. The rim: a quad per open edge, walked against the outer layer's
+    // face there and with it on the inner one
+    let mut rim_edges = 0;
+    let mut non_manifold_edges = 0;
+    let mut edges: Vec<_> = audit::edge_faces(sheet).into_iter().collect();
+    edges.sort_unstable_by_key(|&(edge, _)| edge);
+    for ((low, high), faces) in edges {
+        match faces[..] {
+            [(_, forward)] => {
+                let (p, q) = if forward { (low, high) } else { (high, low) };
+                let (p, q, p2, q2) = (p + outer, q + outer, p + inner, q + inner);
+                mesh.indices.extend([q, p, p2, q, p2, q2].map(|i| i as u32));
+                rim_edges += 1;
+            }
+            [_, _] => {}
+            _ => non_manifold_edges += 1,
+        }
+    }
+
+    Ok(Thickened {
+        mesh,
+        rim_edges,
+        non_manifold_edges,
+        folded_faces,
+    })
+}
+
+/// Parse a thickness such as `2`, `2mm`, `0.5cm`, `0.002m` or `0.1in`.
+/// Model units are taken to be millimetres, as everywhere else; a bare
+/// number is in model units.
+pub fn parse_offset(s: &str) -> std::result::Result<f32, String> {
+    let s = s.trim();
+    let lower = s.to_ascii_lowercase();
+    let (digits, scale) = [("mm", 1.0), ("cm", 10.0), ("in", 25.4), ("m", 1000.0)]
+        .iter()
+        .find_map(|&(unit, scale)| lower.strip_suffix(unit).map(|d| (d, scale)))
+        .unwrap_or((&lower, 1.0));
+    digits
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|d| d.is_finite())
+        .map(|d| d * scale)
+        .ok_or_else(|| format!("'{}' is not a distance (e.g. 2mm, 0.5cm)", s))
+}
+
+// Unit normal of the triangle on three of `mesh`'s vertices
+fn face_normal(mesh: &Mesh, [a, b, c]: [usize; 3]) -> [f32; 3] {
+    let [a, b, c] = [a, b, c].map(|i| mesh.vertex(i));
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length > 0.0 {
+        n.map(|x| x / length)
+    } else {
+        [0.0; 3]
+    }
+}
@@ -0,0 +1,74 @@
+//! How many threads the parallel stages (field sampling, KD-tree builds,
+//! texture encoding) may use.
+//!
+//! By default, all the cores this process may run on: the standard library
+//! already counts only those its CPU affinity allows and its cgroup quota
+//! pays for, so pinning the process from outside (`taskset`, a container's
+//! CPU limit) works as is. `--threads` caps it further, as does
+//! `RAYON_NUM_THREADS`, which schedulers and other tools on shared machines
+//! already set for the purpose. The command line works the count out once
+//! and passes it to every stage that spawns threads; where a library
+//! caller doesn't say, a stage uses every core.
+
+use std::thread::ScopedJoinHandle;
+
+/// How many threads a parallel stage should use when capped at `cap`
+/// (`None` or 0 for every core).
+pub fn count(cap: Option<usize>) -> usize {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    effective(cap.unwrap_or(0), cores)
+}
+
+// The threads to use when `requested` were asked for (0 for no cap) and
+// `cores` are free
+fn effective(requested: usize, cores: usize) -> usize {
+    match requested {
+        0 => cores,
+        cap => cap.min(cores),
+    }
+}
+
+/// Wait for every thread in `handles` and collect what each returned, in
+/// order. A thread that panicked (a blown job limit, say) panics this one
+/// with the same payload, so the caller sees it as it was.
+pub fn join_all<'scope, T>(
+    handles: impl IntoIterator<Item = ScopedJoinHandle<'scope, T>>,
+) -> Vec<T> {
+    handles
+        .into_iter()
+        .map(|handle| match handle.join() {
+            Ok(value) => value,
+            Err(payload) => std::panic::resume_unwind(payload),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cap_is_limited_to_the_cores() {
+        assert_eq!(effective(12, 4), 4);
+        assert_eq!(effective(1, 4), 1);
+        assert_eq!(effective(0, 4), 4);
+    }
+
+    #[test]
+    fn join_all_keeps_order_and_passes_panics_on() {
+        let values = std::thread::scope(|scope| {
+            join_all(
+                (0..4)
+                    .map(|i| scope.spawn(move || i * 10))
+                    .collect::<Vec<_>>(),
+            )
+        });
+        assert_eq!(values, [0, 10, 20, 30]);
+
+        let caught = std::panic::catch_unwind(|| {
+            std::thread::scope(|scope| join_all([scope.spawn(|| panic!("job limit"))]))
+        });
+        let payload = caught.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"job limit"));
+    }
+}
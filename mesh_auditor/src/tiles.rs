@@ -0,0 +1,295 @@
+//! Tiled reconstruction, for scans too big for one voxel grid.
+//!
+//! Space is cut into cubes `tile_size` a side and each cube gets its own
+//! grid and its own run of marching cubes, so memory goes with the tile
+//! rather than the whole site. All the tiles sit on one lattice, and each
+//! reads the scan out past its borders by a skirt as wide as a point's
+//! reach, so two neighbours work out the same values on the face they
+//! share and put their vertices in the same places there. Stitching is
+//! then only a matter of welding those vertices together.
+//!
+//! The field is the remesher's (see `remesh`): a grid point is inside if a
+//! scan point lies within three voxels of it. Here every point counts, and
+//! each point marks the grid points near it instead of every grid point
+//! looking for its nearest point, which is what keeps big scans feasible.
+
+use crate::extract::marching_cubes;
+use crate::gltf;
+use crate::mesh::Mesh;
+use crate::samples::{Samples, Storage};
+use crate::sandbox;
+use crate::sdf::{FieldKind, SampledField};
+use anyhow::{bail, Result};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Voxels across a tile when the caller doesn't pick.
+pub const DEFAULT_CELLS: usize = 64;
+// How far a point reaches, in voxels (the remesher's radius of influence)
+const REACH: f32 = 3.0;
+// Refuse tilings bigger than this rather than grind through them
+const MAX_TILES: usize = 100_000;
+// Welded positions are rounded to this fraction of a voxel
+const WELD_STEPS: f32 = 1024.0;
+
+/// How to cut up the scan.
+#[derive(Debug, Clone, Copy)]
+pub struct TileOptions {
+    /// Edge length of a tile, in mesh units.
+    pub tile_size: f32,
+    /// Voxels along each edge of a tile.
+    pub cells: usize,
+}
+
+/// One reconstructed tile.
+#[derive(Debug)]
+pub struct Tile {
+    /// Where the tile sits in the tiling, in tiles from the corner.
+    pub index: [usize; 3],
+    /// The cube the tile covers.
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+    /// Scan points that fed the tile, skirt included.
+    pub points: usize,
+    pub mesh: Mesh,
+}
+
+/// Every tile that came out with a surface in it.
+#[derive(Debug)]
+pub struct Tiling {
+    pub origin: [f32; 3],
+    pub voxel: f32,
+    pub tile_size: f32,
+    /// Tiles along each axis.
+    pub counts: [usize; 3],
+    pub tiles: Vec<Tile>,
+    /// Tiles with scan points nearby but no surface.
+    pub empty: usize,
+}
+
+/// Reconstruct `positions` (flat xyz) tile by tile.
+pub fn reconstruct(positions: &[f32], options: &TileOptions) -> Result<Tiling> {
+    if !(options.tile_size.is_finite() && options.tile_size > 0.0) {
+        bail!("tile size must be a positive number");
+    }
+    if options.cells == 0 {
+        bail!("a tile needs at least one voxel across");
+    }
+    if positions.len() < 3 {
+        bail!("the scan has no points to reconstruct");
+    }
+    let voxel = options.tile_size / options.cells as f32;
+    let reach = REACH * voxel;
+
+    // 1. The lattice: the scan's bounds grown by a point's reach and one
+    // more voxel, so the outermost grid points are always outside
+    let mut min = [f32::MAX; 3];
+    let mut max = [f32::MIN; 3];
+    for p in positions.chunks_exact(3) {
+        for k in 0..3 {
+            min[k] = min[k].min(p[k]);
+            max[k] = max[k].max(p[k]);
+        }
+    }
+    let origin = min.map(|m| m - reach - voxel);
+    let counts = [0, 1, 2].map(|k| {
+        (((max[k] + reach + voxel - origin[k]) / options.tile_size).ceil() as usize).max(1)
+    });
+    let total = counts[0]
+        .checked_mul(counts[1])
+        .and_then(|n| n.checked_mul(counts[2]))
+        .unwrap_or(usize::MAX);
+    if total > MAX_TILES {
+        bail!(
+            "{}x{}x{} tiles is too many (the most is {}); use a bigger tile size",
+            counts[0],
+            counts[1],
+            counts[2],
+            MAX_TILES
+        );
+    }
+
+    // 2. Hand each point to every tile whose skirt it falls in
+    let mut buckets: BTreeMap<[usize; 3], Vec<u32>> = BTreeMap::new();
+    for (i, p) in positions.chunks_exact(3).enumerate() {
+        let range = [0, 1, 2].map(|k| {
+            let g = (p[k] - origin[k]) / voxel;
+            let low = ((g - REACH) / options.cells as f32).floor();
+            let high = ((g + REACH) / options.cells as f32).floor();
+            let last = (counts[k] - 1) as f32;
+            (
+                low.clamp(0.0, last) as usize,
+                high.clamp(0.0, last) as usize,
+            )
+        });
+        for z in range[2].0..=range[2].1 {
+            for y in range[1].0..=range[1].1 {
+                for x in range[0].0..=range[0].1 {
+                    buckets.entry([x, y, z]).or_default().push(i as u32);
+                }
+            }
+        }
+    }
+
+    // 3. Sample and extract each tile on its own
+    let mut tiles = Vec::new();
+    let mut empty = 0;
+    for (index, points) in buckets {
+        sandbox::checkpoint();
+        let field = sample_tile(positions, &points, origin, voxel, index, options.cells);
+        let triangles = marching_cubes(&field, field.iso);
+        if triangles.is_empty() {
+            empty += 1;
+            continue;
+        }
+        let min = [0, 1, 2].map(|k| origin[k] + (index[k] * options.cells) as f32 * voxel);
+        tiles.push(Tile {
+            index,
+            min,
+            max: min.map(|m| m + options.tile_size),
+            points: points.len(),
+            mesh: weld(&triangles, origin, voxel),
+        });
+    }
+    Ok(Tiling {
+        origin,
+        voxel,
+        tile_size: options.tile_size,
+        counts,
+        tiles,
+        empty,
+    })
+}
+
+/// All the tiles as one mesh, welded along the borders.
+pub fn merge(tiling: &Tiling) -> Mesh {
+    let mut mesh = Mesh::default();
+    let mut index_of: HashMap<[u32; 3], u32> = HashMap::new();
+    for tile in &tiling.tiles {
+        // Border vertices were snapped to the lattice, so the copies on
+        // either side of a border are the same bits
+        let remap: Vec<u32> = (0..tile.mesh.vertex_count())
+            .map(|i| {
+                let p = tile.mesh.vertex(i);
+                *index_of.entry(p.map(f32::to_bits)).or_insert_with(|| {
+                    mesh.positions.extend_from_slice(&p);
+                    (mesh.positions.len() / 3 - 1) as u32
+                })
+            })
+            .collect();
+        mesh.indices
+            .extend(tile.mesh.indices.iter().map(|&i| remap[i as usize]));
+    }
+    mesh
+}
+
+/// Write each tile to `dir` as `tile_<x>_<y>_<z>.glb`, with a `tiles.json`
+/// index of where they go.
+pub fn write_set(tiling: &Tiling, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let mut entries = Vec::new();
+    for tile in &tiling.tiles {
+        sandbox::checkpoint();
+        let [x, y, z] = tile.index;
+        let file = format!("tile_{}_{}_{}.glb", x, y, z);
+        let path = dir.join(&file);
+        gltf::write_glb(
+            &path.to_string_lossy(),
+            &gltf::Primitive::new(&tile.mesh),
+            None,
+        )?;
+        let (min, max) = tile.mesh.bounds();
+        entries.push(json!({
+            "index": tile.index,
+            "file": file,
+            "points": tile.points,
+            "faces": tile.mesh.face_count(),
+            "tile": { "min": tile.min, "max": tile.max },
+            "bounds": { "min": min, "max": max }
+        }));
+    }
+    let index = json!({
+        "origin": tiling.origin,
+        "tile_size": tiling.tile_size,
+        "voxel": tiling.voxel,
+        "counts": tiling.counts,
+        "tiles": entries
+    });
+    std::fs::write(dir.join("tiles.json"), serde_json::to_vec_pretty(&index)?)?;
+    Ok(())
+}
+
+// The density on one tile's grid points, borders included: 1 inside a
+// point's reach, 0 outside it
+fn sample_tile(
+    positions: &[f32],
+    points: &[u32],
+    origin: [f32; 3],
+    voxel: f32,
+    index: [usize; 3],
+    cells: usize,
+) -> SampledField {
+    let side = cells + 1;
+    let start = index.map(|i| i * cells);
+    let mut values = Samples::filled(Storage::Bits, side * side * side, 0.0);
+    for &i in points {
+        let i = i as usize * 3;
+        // Distances are worked out in lattice units from the shared origin,
+        // never from the tile's corner, so neighbours round the same way
+        let g = [0, 1, 2].map(|k| (positions[i + k] - origin[k]) / voxel);
+        let range = [0, 1, 2].map(|k| {
+            let low = (g[k] - REACH).ceil().max(start[k] as f32) as usize;
+            let high = (g[k] + REACH).floor().min((start[k] + cells) as f32) as usize;
+            low..=high
+        });
+        for z in range[2].clone() {
+            let dz = z as f32 - g[2];
+            for y in range[1].clone() {
+                let dy = y as f32 - g[1];
+                for x in range[0].clone() {
+                    let dx = x as f32 - g[0];
+                    if dx * dx + dy * dy + dz * dz < REACH * REACH {
+                        let (lx, ly, lz) = (x - start[0], y - start[1], z - start[2]);
+                        values.set(lx + side * (ly + side * lz), 1.0);
+                    }
+                }
+            }
+        }
+    }
+    SampledField {
+        dims: [side; 3],
+        origin: [0, 1, 2].map(|k| origin[k] + start[k] as f32 * voxel),
+        spacing: [voxel; 3],
+        kind: FieldKind::Density,
+        iso: 0.5,
+        values,
+    }
+}
+
+// Index a marching cubes soup, snapping every vertex onto a fine grid
+// fixed to the lattice so a vertex two tiles both made comes out the same
+fn weld(triangles: &[f32], origin: [f32; 3], voxel: f32) -> Mesh {
+    let step = voxel / WELD_STEPS;
+    let mut mesh = Mesh::default();
+    let mut index_of: HashMap<[i64; 3], u32> = HashMap::new();
+    for corner in triangles.chunks_exact(3) {
+        let key = [0, 1, 2].map(|k| ((corner[k] - origin[k]) / step).round() as i64);
+        let index = *index_of.entry(key).or_insert_with(|| {
+            mesh.positions
+                .extend((0..3).map(|k| origin[k] + key[k] as f32 * step));
+            (mesh.positions.len() / 3 - 1) as u32
+        });
+        mesh.indices.push(index);
+    }
+    // Faces that snapping squashed to a line or a point are dropped
+    let faces: Vec<u32> = mesh
+        .indices
+        .chunks_exact(3)
+        .filter(|f| f[0] != f[1] && f[1] != f[2] && f[2] != f[0])
+        .flatten()
+        .copied()
+        .collect();
+    mesh.indices = faces;
+    mesh
+}
@@ -0,0 +1,199 @@
+//! 3D Tiles output: a tiled reconstruction (see `tiles`) as a `tileset.json`
+//! Cesium can stream.
+//!
+//! Each tile is written at several levels of detail, every one a quarter
+//! of the faces of the one before, and chained coarse to fine under the
+//! root with `REPLACE` refinement, so the viewer swaps in detail as the
+//! camera closes in. Tile contents are plain `.glb` files, which 3D Tiles
+//! 1.1 takes directly (no `.b3dm` wrapper needed). Decimation keeps tile
+//! borders locked, so neighbouring tiles still meet whatever level each
+//! is showing.
+//!
+//! 3D Tiles is z-up and glTF y-up, and a viewer turns glTF content upright
+//! on loading, so the `.glb`s are written y-up while the bounding volumes
+//! stay in mesh coordinates. Given a longitude, latitude and height, the
+//! root is placed there on the WGS84 ellipsoid with the mesh's x, y and z
+//! as east, north and up, in metres.
+
+use crate::decimate::{self, DecimateOptions};
+use crate::gltf;
+use crate::mesh::Mesh;
+use crate::sandbox;
+use crate::tiles::Tiling;
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::path::Path;
+
+// WGS84
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const ECCENTRICITY_SQ: f64 = 6.694_379_990_14e-3;
+// A level whose decimation saves less than this share of the faces is the
+// last one
+const MIN_SAVING: f64 = 0.1;
+
+/// Where on the globe the mesh's origin goes.
+#[derive(Debug, Clone, Copy)]
+pub struct Placement {
+    pub longitude: f64,
+    pub latitude: f64,
+    pub height: f64,
+}
+
+/// What `write` made.
+#[derive(Debug)]
+pub struct Written {
+    /// Content files, every level of every tile.
+    pub files: usize,
+    /// Levels of detail of the tile with the most.
+    pub levels: usize,
+}
+
+/// Write `tiling` as `dir/tileset.json`, with each tile in up to `levels`
+/// levels of detail (`tile_<x>_<y>_<z>_lod<n>.glb`, 0 the finest).
+pub fn write(
+    tiling: &Tiling,
+    dir: &Path,
+    levels: usize,
+    placement: Option<Placement>,
+) -> Result<Written> {
+    std::fs::create_dir_all(dir)?;
+    let mut written = Written {
+        files: 0,
+        levels: 0,
+    };
+    let mut children = Vec::new();
+    let mut site_min = [f32::MAX; 3];
+    let mut site_max = [f32::MIN; 3];
+
+    for tile in &tiling.tiles {
+        sandbox::checkpoint();
+        // 1. The levels, finest first, each a quarter of the one before
+        let mut lods = vec![tile.mesh.clone()];
+        while lods.len() < levels.max(1) {
+            let previous = lods.last().expect("the full tile is always there");
+            let options = DecimateOptions {
+                target_faces: previous.face_count() / 4,
+                preserve_boundary: true,
+                max_error: None,
+            };
+            let coarser = decimate::decimate(previous, &options).mesh;
+            let saved = 1.0 - coarser.face_count() as f64 / previous.face_count() as f64;
+            if coarser.face_count() == 0 || saved < MIN_SAVING {
+                break;
+            }
+            lods.push(coarser);
+        }
+        written.levels = written.levels.max(lods.len());
+
+        // 2. Write them, and chain them coarsest at the top. Each level
+        // is off by about one of its edges, which doubles every level. One
+        // box holds them all: decimation can nudge a level past the last
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for (lo, hi) in lods.iter().map(Mesh::bounds) {
+            for k in 0..3 {
+                min[k] = min[k].min(lo[k]);
+                max[k] = max[k].max(hi[k]);
+                site_min[k] = site_min[k].min(lo[k]);
+                site_max[k] = site_max[k].max(hi[k]);
+            }
+        }
+        let [x, y, z] = tile.index;
+        let mut node: Option<Value> = None;
+        for (level, lod) in lods.iter().enumerate() {
+            let file = format!("tile_{}_{}_{}_lod{}.glb", x, y, z, level);
+            gltf::write_glb(
+                &dir.join(&file).to_string_lossy(),
+                &gltf::Primitive::new(&y_up(lod)),
+                None,
+            )?;
+            written.files += 1;
+            let error = if level == 0 {
+                0.0
+            } else {
+                tiling.voxel * 2f32.powi(level as i32)
+            };
+            let mut tile_json = json!({
+                "boundingVolume": { "box": bounding_box(min, max) },
+                "geometricError": error,
+                "content": { "uri": file }
+            });
+            if let Some(finer) = node.take() {
+                tile_json["children"] = json!([finer]);
+            }
+            node = Some(tile_json);
+        }
+        children.extend(node);
+    }
+
+    // 3. The root: no content of its own, just everything under it. Its
+    // error is the size of the site, so a viewer always looks inside
+    let diagonal = (0..3)
+        .map(|k| (site_max[k] - site_min[k]).powi(2))
+        .sum::<f32>()
+        .sqrt();
+    let mut root = json!({
+        "boundingVolume": { "box": bounding_box(site_min, site_max) },
+        "geometricError": diagonal,
+        "refine": "REPLACE",
+        "children": children
+    });
+    if let Some(placement) = placement {
+        root["transform"] = json!(east_north_up(placement));
+    }
+    let tileset = json!({
+        "asset": { "version": "1.1", "generator": "mesh_lifter" },
+        "geometricError": diagonal,
+        "root": root
+    });
+    std::fs::write(
+        dir.join("tileset.json"),
+        serde_json::to_vec_pretty(&tileset)?,
+    )?;
+    Ok(written)
+}
+
+// The mesh turned from z-up to glTF's y-up
+fn y_up(mesh: &Mesh) -> Mesh {
+    let mut turned = mesh.clone();
+    for p in turned.positions.chunks_exact_mut(3) {
+        let (y, z) = (p[1], p[2]);
+        p[1] = z;
+        p[2] = -y;
+    }
+    turned
+}
+
+// A 3D Tiles box: the centre, then the three half-axes
+fn bounding_box(min: [f32; 3], max: [f32; 3]) -> [f32; 12] {
+    let centre = [0, 1, 2].map(|k| (min[k] + max[k]) * 0.5);
+    let half = [0, 1, 2].map(|k| (max[k] - min[k]) * 0.5);
+    [
+        centre[0], centre[1], centre[2], half[0], 0.0, 0.0, 0.0, half[1], 0.0, 0.0, 0.0, half[2],
+    ]
+}
+
+// The column-major matrix taking east/north/up metres at `placement` to
+// Earth-centred, Earth-fixed coordinates
+fn east_north_up(placement: Placement) -> [f64; 16] {
+    let (lon, lat) = (
+        placement.longitude.to_radians(),
+        placement.latitude.to_radians(),
+    );
+    let (sin_lon, cos_lon) = lon.sin_cos();
+    let (sin_lat, cos_lat) = lat.sin_cos();
+    let n = SEMI_MAJOR_AXIS / (1.0 - ECCENTRICITY_SQ * sin_lat * sin_lat).sqrt();
+    let h = placement.height;
+    let origin = [
+        (n + h) * cos_lat * cos_lon,
+        (n + h) * cos_lat * sin_lon,
+        (n * (1.0 - ECCENTRICITY_SQ) + h) * sin_lat,
+    ];
+    let east = [-sin_lon, cos_lon, 0.0];
+    let north = [-sin_lat * cos_lon, -sin_lat * sin_lon, cos_lat];
+    let up = [cos_lat * cos_lon, cos_lat * sin_lon, sin_lat];
+    [
+        east[0], east[1], east[2], 0.0, north[0], north[1], north[2], 0.0, up[0], up[1], up[2],
+        0.0, origin[0], origin[1], origin[2], 1.0,
+    ]
+}
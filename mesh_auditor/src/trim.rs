@@ -0,0 +1,64 @@
+//! `trim --subtract fixture.stl`: cutting known fixtures out of a scan.
+//!
+//! Parts are scanned in clamps and on stands we have CAD for, and the
+//! scanner can't tell them from the part. Every scan vertex within
+//! `tolerance` of a fixture's surface is taken to be the fixture, and each
+//! face touching one goes with it, so the remesher never sees them. The
+//! fixture has to be in the scan's coordinates already (however its
+//! placement was registered); nothing here moves it.
+
+use crate::bvh::Bvh;
+use crate::mesh::Mesh;
+use crate::sandbox;
+use crate::threads;
+
+/// What trimming took out.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Trimmed {
+    pub vertices: usize,
+    pub faces: usize,
+}
+
+/// Drop every face of `mesh` with a corner within `tolerance` of any of
+/// `fixtures`, checking on `threads` threads.
+pub fn trim(mesh: &mut Mesh, fixtures: &[Mesh], tolerance: f32, threads: usize) -> Trimmed {
+    let trees: Vec<Bvh> = fixtures
+        .iter()
+        .filter(|f| f.face_count() > 0)
+        .map(Bvh::new)
+        .collect();
+    let points: Vec<[f32; 3]> = (0..mesh.vertex_count()).map(|v| mesh.vertex(v)).collect();
+    if trees.is_empty() || points.is_empty() {
+        return Trimmed::default();
+    }
+
+    // 1. Which vertices are on a fixture, a run of vertices per thread
+    let touches = |p: [f32; 3]| trees.iter().any(|t| t.nearest(p, tolerance).is_some());
+    let run = points.len().div_ceil(threads.max(1));
+    let mut near = Vec::with_capacity(points.len());
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = points
+            .chunks(run)
+            .map(|chunk| {
+                scope.spawn(|| {
+                    chunk
+                        .iter()
+                        .map(|&p| {
+                            sandbox::checkpoint();
+                            touches(p)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        near.extend(threads::join_all(handles).into_iter().flatten());
+    });
+
+    // 2. The faces clear of them, and the vertices those use
+    let before = (mesh.vertex_count(), mesh.face_count());
+    *mesh = mesh.select_faces(|f| !mesh.face(f).iter().any(|&v| near[v]));
+    Trimmed {
+        vertices: before.0 - mesh.vertex_count(),
+        faces: before.1 - mesh.face_count(),
+    }
+}
@@ -0,0 +1,389 @@
+//! UV unwrapping: cutting a mesh into charts and packing them into a
+//! square texture.
+//!
+//! Charts are grown face by face from a seed across shared edges, taking
+//! in neighbours whose normal is within the seam angle of the seed's, and
+//! each chart is flattened by projecting it along the seed normal. Keeping
+//! every face within (well) under 90° of that direction means a chart never
+//! folds over itself, at the cost of more, smaller charts on very curved
+//! parts: a simple unwrap, not a conformal one, but sound for baking.
+//!
+//! Charts are then packed in shelves at one common scale (so every part of
+//! the mesh gets the same texel density), the biggest scale that fits,
+//! with a gap round each one so bilinear filtering and mipmaps don't bleed
+//! one chart into the next.
+
+use crate::mesh::Mesh;
+use anyhow::{bail, Result};
+use image::{Rgb, RgbImage};
+use std::collections::HashMap;
+
+/// How to unwrap.
+#[derive(Debug, Clone, Copy)]
+pub struct UnwrapOptions {
+    /// Neighbouring faces join a chart while their normal is within this
+    /// many degrees of the chart's first face.
+    pub seam_angle: f64,
+    /// Start a new chart past this many faces.
+    pub max_chart_faces: usize,
+    /// The texture the charts are packed for, in pixels a side.
+    pub texture_size: u32,
+    /// Empty pixels kept round each chart.
+    pub padding: u32,
+}
+
+impl Default for UnwrapOptions {
+    fn default() -> Self {
+        UnwrapOptions {
+            seam_angle: 45.0,
+            max_chart_faces: 20_000,
+            texture_size: 1024,
+            padding: 4,
+        }
+    }
+}
+
+/// What `unwrap` did.
+#[derive(Debug)]
+pub struct Unwrapped {
+    /// Chart of each face.
+    pub face_charts: Vec<usize>,
+    pub charts: usize,
+    /// Pixels per mesh unit.
+    pub density: f64,
+    /// Share of the texture covered by charts.
+    pub coverage: f64,
+}
+
+/// Give `mesh` fresh UVs (replacing any it had).
+pub fn unwrap(mesh: &mut Mesh, options: &UnwrapOptions) -> Result<Unwrapped> {
+    if mesh.face_count() == 0 {
+        bail!("nothing to unwrap: the mesh has no faces");
+    }
+    let normals: Vec<[f64; 3]> = (0..mesh.face_count())
+        .map(|f| mesh.face_normal(f).map(f64::from))
+        .collect();
+
+    // 1. Faces across each manifold edge
+    let mut edges: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for f in 0..mesh.face_count() {
+        let corners = mesh.face(f);
+        for k in 0..3 {
+            let (a, b) = (corners[k], corners[(k + 1) % 3]);
+            edges.entry((a.min(b), a.max(b))).or_default().push(f);
+        }
+    }
+    let mut neighbours = vec![Vec::new(); mesh.face_count()];
+    for faces in edges.values() {
+        if let &[f, g] = faces.as_slice() {
+            neighbours[f].push(g);
+            neighbours[g].push(f);
+        }
+    }
+
+    // 2. Grow charts, biggest faces first as seeds
+    let cone = options.seam_angle.clamp(1.0, 85.0).to_radians().cos();
+    let mut order: Vec<usize> = (0..mesh.face_count()).collect();
+    let areas: Vec<f64> = (0..mesh.face_count()).map(|f| face_area(mesh, f)).collect();
+    order.sort_by(|&a, &b| areas[b].total_cmp(&areas[a]).then(a.cmp(&b)));
+    let mut face_charts = vec![usize::MAX; mesh.face_count()];
+    let mut charts: Vec<Vec<usize>> = Vec::new();
+    for &seed in &order {
+        if face_charts[seed] != usize::MAX {
+            continue;
+        }
+        let id = charts.len();
+        let axis = normals[seed];
+        let mut faces = vec![seed];
+        face_charts[seed] = id;
+        let mut next = 0;
+        while next < faces.len() && faces.len() < options.max_chart_faces.max(1) {
+            let f = faces[next];
+            next += 1;
+            for &g in &neighbours[f] {
+                if face_charts[g] == usize::MAX
+                    && dot(normals[g], axis) >= cone
+                    && faces.len() < options.max_chart_faces.max(1)
+                {
+                    face_charts[g] = id;
+                    faces.push(g);
+                }
+            }
+        }
+        charts.push(faces);
+    }
+
+    // 3. Flatten each chart onto the plane of its seed
+    let mut flat: Vec<Flat> = charts
+        .iter()
+        .map(|faces| {
+            let n = normals[faces[0]];
+            // Any perpendicular will do; this one avoids the degenerate case
+            let helper = if n[0].abs() < 0.9 {
+                [1.0, 0.0, 0.0]
+            } else {
+                [0.0, 1.0, 0.0]
+            };
+            let t = normalize(cross(helper, n));
+            // n × t keeps the winding: a front face stays anticlockwise
+            let b = cross(n, t);
+            let points: Vec<[f64; 2]> = faces
+                .iter()
+                .flat_map(|&f| mesh.face(f))
+                .map(|i| {
+                    let p = mesh.vertex(i).map(f64::from);
+                    [dot(p, t), dot(p, b)]
+                })
+                .collect();
+            Flat::new(points)
+        })
+        .collect();
+
+    // 4. Pack, then write the UVs
+    let size = f64::from(options.texture_size.max(16));
+    let pad = f64::from(options.padding);
+    let Some(density) = best_scale(&mut flat, size, pad) else {
+        bail!(
+            "{} charts don't fit in a {} px texture; use a bigger texture or a wider seam angle",
+            charts.len(),
+            options.texture_size
+        );
+    };
+    let mut texcoords = vec![0.0f32; mesh.indices.len() * 2];
+    let mut covered = 0.0;
+    for (faces, chart) in charts.iter().zip(&flat) {
+        let [x0, y0] = chart.placed;
+        covered += chart.area() * density * density;
+        for (k, &f) in faces.iter().enumerate() {
+            for corner in 0..3 {
+                let p = chart.local(k * 3 + corner);
+                // Pixel rows run down the texture while OBJ's v runs up:
+                // stand the chart on its head so it isn't mirrored
+                let x = (x0 + pad + p[0] * density) / size;
+                let y = (y0 + pad + (chart.size[1] - p[1]) * density) / size;
+                texcoords[(f * 3 + corner) * 2] = x as f32;
+                texcoords[(f * 3 + corner) * 2 + 1] = (1.0 - y) as f32;
+            }
+        }
+    }
+    mesh.texcoords = texcoords;
+    Ok(Unwrapped {
+        face_charts,
+        charts: charts.len(),
+        density,
+        coverage: covered / (size * size),
+    })
+}
+
+/// A picture of the layout: each chart filled in a colour of its own, face
+/// edges drawn darker, on black.
+pub fn preview(mesh: &Mesh, unwrapped: &Unwrapped, size: u32) -> RgbImage {
+    let mut image = RgbImage::new(size, size);
+    let to_pixel = |corner: usize| {
+        let [u, v] = mesh.texcoord(corner).unwrap_or([0.0; 2]);
+        [u * size as f32, (1.0 - v) * size as f32]
+    };
+    for f in 0..mesh.face_count() {
+        let fill = chart_colour(unwrapped.face_charts[f]);
+        let [a, b, c] = [0, 1, 2].map(|k| to_pixel(f * 3 + k));
+        let area = edge(a, b, c);
+        if area.abs() < 1e-12 {
+            continue;
+        }
+        let min_x = a[0].min(b[0]).min(c[0]).floor().max(0.0) as u32;
+        let max_x = a[0].max(b[0]).max(c[0]).ceil().min(size as f32) as u32;
+        let min_y = a[1].min(b[1]).min(c[1]).floor().max(0.0) as u32;
+        let max_y = a[1].max(b[1]).max(c[1]).ceil().min(size as f32) as u32;
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = [x as f32 + 0.5, y as f32 + 0.5];
+                let inside = [edge(b, c, p), edge(c, a, p), edge(a, b, p)]
+                    .iter()
+                    .all(|&w| w / area >= 0.0);
+                if inside {
+                    image.put_pixel(x, y, fill);
+                }
+            }
+        }
+        // Edges, a point per pixel along each
+        let line = Rgb(fill.0.map(|x| x / 2));
+        for (from, to) in [(a, b), (b, c), (c, a)] {
+            let steps = (to[0] - from[0]).abs().max((to[1] - from[1]).abs()).ceil() as usize;
+            for s in 0..=steps {
+                let t = s as f32 / steps.max(1) as f32;
+                let (x, y) = (
+                    from[0] + (to[0] - from[0]) * t,
+                    from[1] + (to[1] - from[1]) * t,
+                );
+                if x >= 0.0 && y >= 0.0 && (x as u32) < size && (y as u32) < size {
+                    image.put_pixel(x as u32, y as u32, line);
+                }
+            }
+        }
+    }
+    image
+}
+
+// Well spread hues, one per chart (the golden angle round the wheel)
+fn chart_colour(chart: usize) -> Rgb<u8> {
+    let hue = (chart as f64 * 0.618_033_988_75).fract() * 6.0;
+    let x = 1.0 - (hue % 2.0 - 1.0).abs();
+    let (r, g, b) = match hue as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    // Pastel, so the darker edges show
+    Rgb([r, g, b].map(|c: f64| (80.0 + c * 175.0) as u8))
+}
+
+// Twice the signed area of (a, b, c)
+fn edge(a: [f32; 2], b: [f32; 2], c: [f32; 2]) -> f32 {
+    (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0])
+}
+
+// A chart flattened to 2D, three points per face, shifted so its box starts
+// at the origin (and turned so it's wider than tall)
+struct Flat {
+    points: Vec<[f64; 2]>,
+    size: [f64; 2],
+    /// Pixel position of its box, once packed.
+    placed: [f64; 2],
+}
+
+impl Flat {
+    fn new(points: Vec<[f64; 2]>) -> Self {
+        let mut min = [f64::MAX; 2];
+        let mut max = [f64::MIN; 2];
+        for p in &points {
+            for k in 0..2 {
+                min[k] = min[k].min(p[k]);
+                max[k] = max[k].max(p[k]);
+            }
+        }
+        let size = [max[0] - min[0], max[1] - min[1]];
+        let turned = size[1] > size[0];
+        let points = points
+            .into_iter()
+            .map(|p| {
+                let q = [p[0] - min[0], p[1] - min[1]];
+                // A quarter turn (anticlockwise, so no mirroring)
+                if turned {
+                    [size[1] - q[1], q[0]]
+                } else {
+                    q
+                }
+            })
+            .collect();
+        Flat {
+            points,
+            size: if turned { [size[1], size[0]] } else { size },
+            placed: [0.0; 2],
+        }
+    }
+
+    fn local(&self, i: usize) -> [f64; 2] {
+        self.points[i]
+    }
+
+    fn area(&self) -> f64 {
+        self.points
+            .chunks_exact(3)
+            .map(|t| {
+                ((t[1][0] - t[0][0]) * (t[2][1] - t[0][1])
+                    - (t[2][0] - t[0][0]) * (t[1][1] - t[0][1]))
+                    .abs()
+                    / 2.0
+            })
+            .sum()
+    }
+}
+
+// The largest pixels-per-unit at which the charts shelf-pack into the
+// texture, leaving their positions in `placed`
+fn best_scale(charts: &mut [Flat], size: f64, pad: f64) -> Option<f64> {
+    let mut order: Vec<usize> = (0..charts.len()).collect();
+    order.sort_by(|&a, &b| {
+        charts[b].size[1]
+            .total_cmp(&charts[a].size[1])
+            .then(a.cmp(&b))
+    });
+    let fits = |charts: &mut [Flat], scale: f64| {
+        let (mut x, mut y, mut row) = (0.0, 0.0, 0.0);
+        for &c in &order {
+            let w = (charts[c].size[0] * scale).ceil() + 2.0 * pad;
+            let h = (charts[c].size[1] * scale).ceil() + 2.0 * pad;
+            if w > size {
+                return false;
+            }
+            if x + w > size {
+                x = 0.0;
+                y += row;
+                row = 0.0;
+            }
+            if y + h > size {
+                return false;
+            }
+            charts[c].placed = [x, y];
+            x += w;
+            row = f64::max(row, h);
+        }
+        true
+    };
+    let largest = charts
+        .iter()
+        .map(|c| c.size[0])
+        .fold(f64::MIN_POSITIVE, f64::max);
+    let (mut low, mut high) = (0.0, size / largest);
+    if !fits(charts, low) {
+        return None;
+    }
+    for _ in 0..40 {
+        let middle = (low + high) / 2.0;
+        if fits(charts, middle) {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    // Leave the charts where the winning scale put them
+    fits(charts, low);
+    (low > 0.0).then_some(low)
+}
+
+fn face_area(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+    length(cross(sub(b, a), sub(c, a))) / 2.0
+}
+
+fn sub(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn length(a: [f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn normalize(a: [f64; 3]) -> [f64; 3] {
+    let l = length(a);
+    if l > 0.0 {
+        a.map(|x| x / l)
+    } else {
+        [1.0, 0.0, 0.0]
+    }
+}
@@ -0,0 +1,105 @@
+// The WebGL viewer the audit report and the server page share. Draws unindexed
+// triangles from flat arrays of float positions, normals and colours, three
+// per corner, in `canvas`: drag to orbit, scroll to zoom.
+"use strict";
+function showModel(canvas, positions, normals, colours) {
+  // 1. WebGL setup
+  const gl = canvas.getContext("webgl");
+  if (!gl) {
+    canvas.replaceWith(document.createTextNode("This browser can't show 3D (no WebGL)."));
+    return;
+  }
+  const shader = (type, source) => {
+    const s = gl.createShader(type);
+    gl.shaderSource(s, source);
+    gl.compileShader(s);
+    return s;
+  };
+  const program = gl.createProgram();
+  gl.attachShader(program, shader(gl.VERTEX_SHADER, `
+    attribute vec3 position, normal, colour;
+    uniform mat4 view, projection;
+    varying vec3 vColour;
+    void main() {
+      vec3 n = normalize(mat3(view) * normal);
+      // Both sides lit, so inverted faces still show their colour
+      float light = 0.35 + 0.65 * abs(n.z);
+      vColour = colour * light;
+      gl_Position = projection * view * vec4(position, 1.0);
+    }`));
+  gl.attachShader(program, shader(gl.FRAGMENT_SHADER, `
+    precision mediump float;
+    varying vec3 vColour;
+    void main() { gl_FragColor = vec4(vColour, 1.0); }`));
+  gl.linkProgram(program);
+  gl.useProgram(program);
+  for (const [name, data] of [["position", positions], ["normal", normals], ["colour", colours]]) {
+    const buffer = gl.createBuffer();
+    gl.bindBuffer(gl.ARRAY_BUFFER, buffer);
+    gl.bufferData(gl.ARRAY_BUFFER, data, gl.STATIC_DRAW);
+    const location = gl.getAttribLocation(program, name);
+    gl.enableVertexAttribArray(location);
+    gl.vertexAttribPointer(location, 3, gl.FLOAT, false, 0, 0);
+  }
+  gl.enable(gl.DEPTH_TEST);
+
+  // 2. Orbit camera around the middle of the bounding box
+  const min = [Infinity, Infinity, Infinity], max = [-Infinity, -Infinity, -Infinity];
+  for (let i = 0; i < positions.length; i++) {
+    min[i % 3] = Math.min(min[i % 3], positions[i]);
+    max[i % 3] = Math.max(max[i % 3], positions[i]);
+  }
+  const centre = [0, 1, 2].map(a => (min[a] + max[a]) / 2);
+  const radius = Math.hypot(...[0, 1, 2].map(a => max[a] - min[a])) / 2 || 1;
+  let yaw = 0.6, pitch = 0.4, distance = radius * 3;
+
+  const multiply = (a, b) => {
+    const out = new Float32Array(16);
+    for (let c = 0; c < 4; c++)
+      for (let r = 0; r < 4; r++)
+        for (let k = 0; k < 4; k++) out[c * 4 + r] += a[k * 4 + r] * b[c * 4 + k];
+    return out;
+  };
+  const viewMatrix = () => {
+    const cy = Math.cos(yaw), sy = Math.sin(yaw), cp = Math.cos(pitch), sp = Math.sin(pitch);
+    const rotate = new Float32Array([cy, sp * sy, -cp * sy, 0, 0, cp, sp, 0, sy, -sp * cy, cp * cy, 0, 0, 0, 0, 1]);
+    const move = new Float32Array([1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, -centre[0], -centre[1], -centre[2], 1]);
+    const back = new Float32Array([1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 0, -distance, 1]);
+    return multiply(back, multiply(rotate, move));
+  };
+  const projectionMatrix = aspect => {
+    const f = 1 / Math.tan(0.4), near = distance / 100, far = distance + radius * 4;
+    return new Float32Array([f / aspect, 0, 0, 0, 0, f, 0, 0, 0, 0, (far + near) / (near - far), -1,
+                             0, 0, 2 * far * near / (near - far), 0]);
+  };
+
+  const draw = () => {
+    const width = canvas.clientWidth * devicePixelRatio, height = canvas.clientHeight * devicePixelRatio;
+    if (canvas.width !== width || canvas.height !== height) [canvas.width, canvas.height] = [width, height];
+    gl.viewport(0, 0, width, height);
+    gl.clearColor(0.96, 0.96, 0.97, 1);
+    gl.clear(gl.COLOR_BUFFER_BIT | gl.DEPTH_BUFFER_BIT);
+    gl.uniformMatrix4fv(gl.getUniformLocation(program, "view"), false, viewMatrix());
+    gl.uniformMatrix4fv(gl.getUniformLocation(program, "projection"), false, projectionMatrix(width / height));
+    gl.drawArrays(gl.TRIANGLES, 0, positions.length / 3);
+  };
+
+  // 3. Mouse: drag to orbit, wheel to zoom
+  let dragging = null;
+  canvas.addEventListener("mousedown", e => { dragging = [e.clientX, e.clientY]; canvas.style.cursor = "grabbing"; });
+  addEventListener("mouseup", () => { dragging = null; canvas.style.cursor = "grab"; });
+  addEventListener("mousemove", e => {
+    if (!dragging) return;
+    yaw += (e.clientX - dragging[0]) * 0.01;
+    pitch = Math.max(-1.5, Math.min(1.5, pitch + (e.clientY - dragging[1]) * 0.01));
+    dragging = [e.clientX, e.clientY];
+    requestAnimationFrame(draw);
+  });
+  canvas.addEventListener("wheel", e => {
+    e.preventDefault();
+    distance = Math.max(radius * 0.2, distance * Math.exp(e.deltaY * 0.001));
+    requestAnimationFrame(draw);
+  }, { passive: false });
+  addEventListener("resize", () => requestAnimationFrame(draw));
+  draw();
+}
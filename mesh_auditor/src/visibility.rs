@@ -0,0 +1,310 @@
+//! Which of a part's faces can be seen from a direction: `analyze
+//! undercut` and `analyze reach`.
+//!
+//! A face is seen from a direction when it faces that way (or is square
+//! to it, a wall seen edge-on) and nothing is in front of it: a ray from
+//! its centre out along the direction leaves the part without hitting
+//! another face. The ray starts off the face by the face's own size, so
+//! roughness smaller than the mesh's triangles doesn't shade anything.
+//! One ray per face, so a face half in shadow counts as whatever its
+//! centre is.
+//!
+//! A mould opening along the pull direction can only form the faces seen
+//! from one end of it or the other. Anything seen from neither is an
+//! undercut, needing a side action in the tool (or a different parting
+//! direction). Neighbouring undercut faces are grouped into patches, and
+//! the biggest are listed with where they are.
+//!
+//! A 3-axis machine cuts from above, and the part is turned over or on
+//! its side between setups, so it can reach whatever is seen from one of
+//! the setups' directions (by default the six along the axes). The faces
+//! seen from none are blind: they need a 5-axis machine, another setup or
+//! a change to the part.
+
+use crate::audit;
+use crate::bvh::Bvh;
+use crate::mesh::Mesh;
+use crate::sandbox;
+use crate::threads;
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+// Faces turned up to about a degree away still count as facing the way
+// (scan noise leaves no wall exactly square)
+const GRAZING: f64 = 0.02;
+// Patches listed in a report
+const MAX_LISTED: usize = 5;
+// Colours of the coded mesh
+const SEEN_FORWARD: [f32; 3] = [0.2, 0.75, 0.3];
+const SEEN_BACK: [f32; 3] = [0.2, 0.45, 0.9];
+const HIDDEN: [f32; 3] = [0.9, 0.15, 0.1];
+const REACHED: [f32; 3] = [0.6, 0.6, 0.6];
+
+/// Undercuts against a parting direction.
+#[derive(Debug, Clone, Serialize)]
+pub struct Undercuts {
+    /// Unit parting direction.
+    pub direction: [f64; 3],
+    pub surface_area: f64,
+    /// Faces seen from the end the direction points to, and only from the
+    /// other end.
+    pub forward_area: f64,
+    pub back_area: f64,
+    pub undercut_area: f64,
+    /// The biggest undercut patches, biggest first.
+    pub patches: Vec<Patch>,
+    // Per face: seen forward, seen back
+    #[serde(skip)]
+    seen: Vec<(bool, bool)>,
+}
+
+/// What a set of tool directions reaches.
+#[derive(Debug, Clone, Serialize)]
+pub struct Reach {
+    pub surface_area: f64,
+    pub directions: Vec<Setup>,
+    /// Faces seen from at least one of the directions, and from none.
+    pub reached_area: f64,
+    pub blind_area: f64,
+    /// The biggest blind patches, biggest first.
+    pub patches: Vec<Patch>,
+    #[serde(skip)]
+    reached: Vec<bool>,
+}
+
+/// One tool direction, and the surface seen from it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Setup {
+    /// Unit direction the tool comes in from.
+    pub direction: [f64; 3],
+    pub area: f64,
+}
+
+/// Neighbouring faces flagged together.
+#[derive(Debug, Clone, Serialize)]
+pub struct Patch {
+    pub faces: usize,
+    pub area: f64,
+    pub centre: [f64; 3],
+}
+
+/// Find the faces of `mesh` seen from neither end of `direction`, casting
+/// on `threads` threads.
+pub fn undercuts(mesh: &Mesh, direction: [f32; 3], threads: usize) -> Result<Undercuts> {
+    let direction = unit(direction)?;
+    let bvh = Bvh::new(mesh);
+    let forward = visible_from(mesh, &bvh, direction, threads);
+    let back = visible_from(mesh, &bvh, direction.map(|x| -x), threads);
+    let areas: Vec<f64> = (0..mesh.face_count()).map(|f| face_area(mesh, f)).collect();
+    let mut report = Undercuts {
+        direction,
+        surface_area: areas.iter().sum(),
+        forward_area: 0.0,
+        back_area: 0.0,
+        undercut_area: 0.0,
+        patches: Vec::new(),
+        seen: forward.into_iter().zip(back).collect(),
+    };
+    for (&(forward, back), area) in report.seen.iter().zip(&areas) {
+        match (forward, back) {
+            (true, _) => report.forward_area += area,
+            (false, true) => report.back_area += area,
+            (false, false) => report.undercut_area += area,
+        }
+    }
+    let hidden: Vec<bool> = report.seen.iter().map(|&(f, b)| !f && !b).collect();
+    report.patches = patches(mesh, &areas, &hidden);
+    Ok(report)
+}
+
+impl Undercuts {
+    /// `mesh` coloured green where it is seen along the direction, blue
+    /// where only against it, and red where it is an undercut.
+    pub fn coloured(&self, mesh: &Mesh) -> Mesh {
+        let colours: Vec<[f32; 3]> = self
+            .seen
+            .iter()
+            .map(|&seen| match seen {
+                (true, _) => SEEN_FORWARD,
+                (false, true) => SEEN_BACK,
+                (false, false) => HIDDEN,
+            })
+            .collect();
+        mesh.with_face_colours(&colours)
+    }
+}
+
+/// Find the faces of `mesh` a tool can reach from any of `directions`,
+/// casting on `threads` threads.
+pub fn reach(mesh: &Mesh, directions: &[[f32; 3]], threads: usize) -> Result<Reach> {
+    if directions.is_empty() {
+        bail!("no tool directions to check");
+    }
+    let bvh = Bvh::new(mesh);
+    let areas: Vec<f64> = (0..mesh.face_count()).map(|f| face_area(mesh, f)).collect();
+    let mut reached = vec![false; mesh.face_count()];
+    let mut setups = Vec::with_capacity(directions.len());
+    for &direction in directions {
+        let direction = unit(direction)?;
+        let seen = visible_from(mesh, &bvh, direction, threads);
+        let mut area = 0.0;
+        for (f, seen) in seen.into_iter().enumerate() {
+            if seen {
+                area += areas[f];
+                reached[f] = true;
+            }
+        }
+        setups.push(Setup { direction, area });
+    }
+    let reached_area: f64 = (0..areas.len())
+        .filter(|&f| reached[f])
+        .map(|f| areas[f])
+        .sum();
+    let surface_area: f64 = areas.iter().sum();
+    let blind: Vec<bool> = reached.iter().map(|&r| !r).collect();
+    Ok(Reach {
+        surface_area,
+        directions: setups,
+        reached_area,
+        blind_area: surface_area - reached_area,
+        patches: patches(mesh, &areas, &blind),
+        reached,
+    })
+}
+
+impl Reach {
+    /// `mesh` coloured grey where a tool reaches and red where it can't.
+    pub fn coloured(&self, mesh: &Mesh) -> Mesh {
+        let colours: Vec<[f32; 3]> = self
+            .reached
+            .iter()
+            .map(|&r| if r { REACHED } else { HIDDEN })
+            .collect();
+        mesh.with_face_colours(&colours)
+    }
+}
+
+/// Whether each face of `mesh` (cast against `bvh`, built from it) is seen
+/// from far off along the unit `direction`, a run of faces on each of
+/// `threads` threads.
+pub fn visible_from(mesh: &Mesh, bvh: &Bvh, direction: [f64; 3], threads: usize) -> Vec<bool> {
+    let (min, max) = mesh.bounds();
+    let diagonal = (0..3)
+        .map(|k| (max[k] - min[k]).powi(2))
+        .sum::<f32>()
+        .sqrt();
+    let towards = direction.map(|x| x as f32);
+    let seen = |f: usize| {
+        let n = mesh.face_normal(f);
+        let facing: f64 = (0..3).map(|k| f64::from(n[k]) * direction[k]).sum();
+        if facing < -GRAZING {
+            return false;
+        }
+        // Off the face by its own size: a ray along a wall would otherwise
+        // catch on the scan noise right beside it
+        let centre = mesh.face_centroid(f);
+        let [a, b, c] = mesh.face(f).map(|v| mesh.vertex(v));
+        let clearance = [(a, b), (b, c), (c, a)]
+            .iter()
+            .map(|(p, q)| (0..3).map(|k| (p[k] - q[k]).powi(2)).sum::<f32>().sqrt())
+            .fold(diagonal * 1e-5, f32::max);
+        let origin = [0, 1, 2].map(|k| centre[k] + n[k] * clearance);
+        bvh.cast(origin, towards, diagonal * 2.0).is_none()
+    };
+
+    // A run of faces per thread
+    let faces: Vec<usize> = (0..mesh.face_count()).collect();
+    let run = faces.len().div_ceil(threads.max(1)).max(1);
+    let mut visible = Vec::with_capacity(faces.len());
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = faces
+            .chunks(run)
+            .map(|chunk| {
+                scope.spawn(|| {
+                    chunk
+                        .iter()
+                        .map(|&f| {
+                            sandbox::checkpoint();
+                            seen(f)
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        visible.extend(threads::join_all(handles).into_iter().flatten());
+    });
+    visible
+}
+
+/// The `flagged` faces grouped across shared edges, biggest first, at
+/// most a report's worth.
+pub fn patches(mesh: &Mesh, areas: &[f64], flagged: &[bool]) -> Vec<Patch> {
+    let count = mesh.face_count();
+    let mut neighbours = vec![Vec::new(); count];
+    for faces in audit::edge_faces(mesh).values() {
+        for (i, &(f, _)) in faces.iter().enumerate() {
+            for &(g, _) in &faces[i + 1..] {
+                neighbours[f].push(g);
+                neighbours[g].push(f);
+            }
+        }
+    }
+    let mut seen = vec![false; count];
+    let mut found = Vec::new();
+    for start in 0..count {
+        if seen[start] || !flagged[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut patch = Patch {
+            faces: 0,
+            area: 0.0,
+            centre: [0.0; 3],
+        };
+        let mut stack = vec![start];
+        while let Some(f) = stack.pop() {
+            patch.faces += 1;
+            patch.area += areas[f];
+            for (sum, x) in patch.centre.iter_mut().zip(mesh.face_centroid(f)) {
+                *sum += f64::from(x) * areas[f];
+            }
+            for &g in &neighbours[f] {
+                if !seen[g] && flagged[g] {
+                    seen[g] = true;
+                    stack.push(g);
+                }
+            }
+        }
+        if patch.area > 0.0 {
+            patch.centre = patch.centre.map(|x| x / patch.area);
+        }
+        found.push(patch);
+    }
+    found.sort_by(|a, b| b.area.total_cmp(&a.area).then(b.faces.cmp(&a.faces)));
+    found.truncate(MAX_LISTED);
+    found
+}
+
+fn unit(direction: [f32; 3]) -> Result<[f64; 3]> {
+    let length = direction
+        .iter()
+        .map(|&x| f64::from(x).powi(2))
+        .sum::<f64>()
+        .sqrt();
+    if length == 0.0 || !length.is_finite() {
+        bail!("the direction must not be zero");
+    }
+    Ok(direction.map(|x| f64::from(x) / length))
+}
+
+fn face_area(mesh: &Mesh, f: usize) -> f64 {
+    let [a, b, c] = mesh.face(f).map(|i| mesh.vertex(i).map(f64::from));
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+    0.5 * (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt()
+}
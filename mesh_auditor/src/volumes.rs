@@ -0,0 +1,178 @@
+//! Bounding volumes for each connected piece of a mesh, for engines that
+//! cull or collide per part.
+//!
+//! Each piece gets an oriented box and a sphere. The box is lined up with
+//! the piece's principal axes (the eigenvectors of its vertices'
+//! covariance), which fits a long or tilted part far tighter than an
+//! axis-aligned one, and is then stretched to take in every vertex. The
+//! sphere shares the box's centre, reaching the furthest vertex.
+
+use crate::audit;
+use crate::mesh::Mesh;
+use serde_json::{json, Value};
+
+// Jacobi sweeps; three or four are plenty for a 3x3
+const SWEEPS: usize = 16;
+
+/// One connected piece's bounds.
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub faces: usize,
+    pub centre: [f32; 3],
+    pub radius: f32,
+    /// Unit axes of the box, longest spread first.
+    pub axes: [[f32; 3]; 3],
+    /// Half the box's size along each axis.
+    pub half_extents: [f32; 3],
+}
+
+impl Component {
+    /// The bounds as glTF extras (or any other JSON).
+    pub fn to_json(&self) -> Value {
+        json!({
+            "faces": self.faces,
+            "sphere": { "centre": self.centre, "radius": self.radius },
+            "obb": {
+                "centre": self.centre,
+                "axes": self.axes,
+                "half_extents": self.half_extents
+            }
+        })
+    }
+}
+
+/// Bounds for every connected piece of `mesh`, biggest first.
+pub fn components(mesh: &Mesh) -> Vec<Component> {
+    let mut components: Vec<Component> = audit::surfaces(mesh)
+        .iter()
+        .map(|surface| {
+            let mut vertices: Vec<usize> = surface
+                .even
+                .iter()
+                .chain(&surface.odd)
+                .flat_map(|&f| mesh.face(f))
+                .collect();
+            vertices.sort_unstable();
+            vertices.dedup();
+            bound(mesh, &vertices, surface.even.len() + surface.odd.len())
+        })
+        .collect();
+    components.sort_by_key(|c| std::cmp::Reverse(c.faces));
+    components
+}
+
+// The box and sphere round the given vertices
+fn bound(mesh: &Mesh, vertices: &[usize], faces: usize) -> Component {
+    // 1. The principal axes
+    let count = vertices.len().max(1) as f64;
+    let mut mean = [0.0f64; 3];
+    for &v in vertices {
+        let p = mesh.vertex(v);
+        for k in 0..3 {
+            mean[k] += f64::from(p[k]) / count;
+        }
+    }
+    let mut covariance = [[0.0f64; 3]; 3];
+    for &v in vertices {
+        let p = mesh.vertex(v);
+        let d = [0, 1, 2].map(|k| f64::from(p[k]) - mean[k]);
+        for i in 0..3 {
+            for j in 0..3 {
+                covariance[i][j] += d[i] * d[j] / count;
+            }
+        }
+    }
+    let axes = principal_axes(covariance);
+
+    // 2. Stretch the box along them to take in every vertex
+    let mut low = [f64::MAX; 3];
+    let mut high = [f64::MIN; 3];
+    for &v in vertices {
+        let p = mesh.vertex(v).map(f64::from);
+        for (k, axis) in axes.iter().enumerate() {
+            let along = dot(p, *axis);
+            low[k] = low[k].min(along);
+            high[k] = high[k].max(along);
+        }
+    }
+    let mut centre = [0.0f64; 3];
+    for (k, axis) in axes.iter().enumerate() {
+        let middle = (low[k] + high[k]) * 0.5;
+        for i in 0..3 {
+            centre[i] += axis[i] * middle;
+        }
+    }
+
+    // 3. The sphere round the same centre
+    let radius = vertices
+        .iter()
+        .map(|&v| {
+            let p = mesh.vertex(v).map(f64::from);
+            let d = [0, 1, 2].map(|k| p[k] - centre[k]);
+            dot(d, d)
+        })
+        .fold(0.0f64, f64::max)
+        .sqrt();
+    Component {
+        faces,
+        centre: centre.map(|x| x as f32),
+        radius: radius as f32,
+        axes: axes.map(|a| a.map(|x| x as f32)),
+        half_extents: [0, 1, 2].map(|k| ((high[k] - low[k]) * 0.5) as f32),
+    }
+}
+
+/// Eigenvectors of a symmetric 3x3 matrix by Jacobi rotations, largest
+/// eigenvalue first, as a right-handed set.
+pub fn principal_axes(mut m: [[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..SWEEPS {
+        let off = m[0][1].abs() + m[0][2].abs() + m[1][2].abs();
+        if off < 1e-30 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if m[p][q].abs() < 1e-300 {
+                continue;
+            }
+            // The rotation that zeroes m[p][q]
+            let theta = (m[q][q] - m[p][p]) / (2.0 * m[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in &mut m {
+                let (a, b) = (row[p], row[q]);
+                row[p] = c * a - s * b;
+                row[q] = s * a + c * b;
+            }
+            let (row_p, row_q) = (m[p], m[q]);
+            for k in 0..3 {
+                m[p][k] = c * row_p[k] - s * row_q[k];
+                m[q][k] = s * row_p[k] + c * row_q[k];
+            }
+            for row in &mut v {
+                let (a, b) = (row[p], row[q]);
+                row[p] = c * a - s * b;
+                row[q] = s * a + c * b;
+            }
+        }
+    }
+    // Columns of `v` are the eigenvectors
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| m[b][b].total_cmp(&m[a][a]));
+    let column = |k: usize| [v[0][k], v[1][k], v[2][k]];
+    let (first, second) = (column(order[0]), column(order[1]));
+    [first, second, cross(first, second)]
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
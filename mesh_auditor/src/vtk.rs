@@ -0,0 +1,264 @@
+//! VTK output for ParaView and VisIt: the legacy `.vtk` format and the
+//! XML `.vtu`, both as unstructured grids, by the output's extension.
+//!
+//! A surface is written as triangles (VTK cell type 5), with what the mesh
+//! knows per vertex as point data (its normal, its colour as RGB in 0..1,
+//! and every vertex attribute by name) and its material index per face as
+//! cell data, -1 where a face has none. Tetrahedra from `tetmesh` are
+//! cell type 10, with their quality per cell. Everything is plain text:
+//! bigger than binary, but readable by every VTK since 4 and easy to
+//! check by eye.
+//!
+//! Array names can't have spaces in legacy files, so whitespace in an
+//! attribute's name becomes `_`, in both formats, to keep them alike.
+
+use crate::mesh::Mesh;
+use crate::output::WriteOptions;
+use crate::storage;
+use crate::tetmesh::TetMesh;
+use anyhow::{bail, Result};
+use std::io::Write;
+use std::path::Path;
+
+/// VTK cell types.
+const TRIANGLE: u8 = 5;
+const TETRA: u8 = 10;
+
+/// Values over a grid's points or cells: `components` floats each.
+#[derive(Debug, Clone)]
+pub struct Array {
+    pub name: String,
+    pub components: usize,
+    pub values: Vec<f32>,
+}
+
+impl Array {
+    fn new(name: &str, components: usize, values: Vec<f32>) -> Self {
+        Array {
+            name: name.replace(char::is_whitespace, "_"),
+            components,
+            values,
+        }
+    }
+}
+
+/// Points and cells of one kind, with data over each.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    pub points: Vec<[f32; 3]>,
+    /// Each cell's point indices; every cell has `corners` of them.
+    pub cells: Vec<u32>,
+    pub corners: usize,
+    pub cell_type: u8,
+    pub point_data: Vec<Array>,
+    pub cell_data: Vec<Array>,
+}
+
+impl Grid {
+    /// `mesh`'s triangles, with its normals, colours, vertex attributes
+    /// and materials.
+    pub fn surface(mesh: &Mesh) -> Self {
+        let normals = mesh.vertex_normals();
+        let mut point_data = vec![Array::new("normal", 3, normals.concat())];
+        if mesh.has_colours() {
+            point_data.push(Array::new("colour", 3, mesh.colours.clone()));
+        }
+        for attribute in &mesh.vertex_attributes {
+            point_data.push(Array::new(&attribute.name, 1, attribute.values.clone()));
+        }
+        let mut cell_data = Vec::new();
+        if !mesh.face_materials.is_empty() {
+            let materials = mesh
+                .face_materials
+                .iter()
+                .map(|m| m.map_or(-1.0, |m| m as f32))
+                .collect();
+            cell_data.push(Array::new("material", 1, materials));
+        }
+        Grid {
+            points: (0..mesh.vertex_count()).map(|v| mesh.vertex(v)).collect(),
+            cells: mesh.indices.clone(),
+            corners: 3,
+            cell_type: TRIANGLE,
+            point_data,
+            cell_data,
+        }
+    }
+
+    /// `mesh`'s tetrahedra, with each one's quality.
+    pub fn tetrahedra(mesh: &TetMesh) -> Self {
+        let quality = (0..mesh.tets.len())
+            .map(|t| mesh.quality(t) as f32)
+            .collect();
+        Grid {
+            points: mesh.positions.clone(),
+            cells: mesh.tets.concat(),
+            corners: 4,
+            cell_type: TETRA,
+            point_data: Vec::new(),
+            cell_data: vec![Array::new("quality", 1, quality)],
+        }
+    }
+
+    fn cell_count(&self) -> usize {
+        self.cells.len() / self.corners
+    }
+
+    /// Write as legacy VTK (`.vtk`) or VTU (`.vtu`).
+    pub fn save(&self, location: &str) -> Result<()> {
+        let extension = Path::new(location)
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("vtk") => self.save_legacy(location),
+            Some("vtu") => self.save_vtu(location),
+            _ => bail!("VTK files are .vtk (legacy) or .vtu (XML)"),
+        }
+    }
+
+    fn save_legacy(&self, location: &str) -> Result<()> {
+        let cells = self.cell_count();
+        let mut out = storage::create(location)?;
+        writeln!(out, "# vtk DataFile Version 3.0")?;
+        writeln!(out, "Written by mesh_lifter")?;
+        writeln!(out, "ASCII\nDATASET UNSTRUCTURED_GRID")?;
+        writeln!(out, "POINTS {} float", self.points.len())?;
+        for p in &self.points {
+            writeln!(out, "{} {} {}", p[0], p[1], p[2])?;
+        }
+        writeln!(out, "CELLS {} {}", cells, cells * (self.corners + 1))?;
+        for cell in self.cells.chunks_exact(self.corners) {
+            write!(out, "{}", self.corners)?;
+            for v in cell {
+                write!(out, " {}", v)?;
+            }
+            writeln!(out)?;
+        }
+        writeln!(out, "CELL_TYPES {}", cells)?;
+        for _ in 0..cells {
+            writeln!(out, "{}", self.cell_type)?;
+        }
+        for (section, count, arrays) in [
+            ("POINT_DATA", self.points.len(), &self.point_data),
+            ("CELL_DATA", cells, &self.cell_data),
+        ] {
+            if arrays.is_empty() {
+                continue;
+            }
+            writeln!(out, "{} {}", section, count)?;
+            for array in arrays {
+                match array.components {
+                    1 => writeln!(out, "SCALARS {} float 1\nLOOKUP_TABLE default", array.name)?,
+                    _ if array.name == "normal" => writeln!(out, "NORMALS normal float")?,
+                    _ if array.name == "colour" => writeln!(out, "COLOR_SCALARS colour 3")?,
+                    _ => writeln!(out, "VECTORS {} float", array.name)?,
+                }
+                for value in array.values.chunks_exact(array.components) {
+                    let value: Vec<String> = value.iter().map(f32::to_string).collect();
+                    writeln!(out, "{}", value.join(" "))?;
+                }
+            }
+        }
+        out.finish()
+    }
+
+    fn save_vtu(&self, location: &str) -> Result<()> {
+        let cells = self.cell_count();
+        let mut out = storage::create(location)?;
+        writeln!(out, "<?xml version=\"1.0\"?>")?;
+        writeln!(
+            out,
+            "<VTKFile type=\"UnstructuredGrid\" version=\"1.0\" byte_order=\"LittleEndian\" header_type=\"UInt64\">"
+        )?;
+        writeln!(out, "  <UnstructuredGrid>")?;
+        writeln!(
+            out,
+            "    <Piece NumberOfPoints=\"{}\" NumberOfCells=\"{}\">",
+            self.points.len(),
+            cells
+        )?;
+        for (section, arrays) in [
+            ("PointData", &self.point_data),
+            ("CellData", &self.cell_data),
+        ] {
+            if arrays.is_empty() {
+                continue;
+            }
+            let normals = if arrays.iter().any(|a| a.name == "normal") {
+                " Normals=\"normal\""
+            } else {
+                ""
+            };
+            writeln!(out, "      <{}{}>", section, normals)?;
+            for array in arrays {
+                writeln!(
+                    out,
+                    "        <DataArray type=\"Float32\" Name=\"{}\" NumberOfComponents=\"{}\" format=\"ascii\">",
+                    escape(&array.name),
+                    array.components
+                )?;
+                write_values(&mut out, array.values.iter())?;
+                writeln!(out, "        </DataArray>")?;
+            }
+            writeln!(out, "      </{}>", section)?;
+        }
+        writeln!(out, "      <Points>")?;
+        writeln!(
+            out,
+            "        <DataArray type=\"Float32\" NumberOfComponents=\"3\" format=\"ascii\">"
+        )?;
+        write_values(&mut out, self.points.iter().flatten())?;
+        writeln!(out, "        </DataArray>\n      </Points>\n      <Cells>")?;
+        let offsets: Vec<usize> = (1..=cells).map(|c| c * self.corners).collect();
+        for (name, kind, values) in [
+            (
+                "connectivity",
+                "Int64",
+                self.cells.iter().map(|&v| u64::from(v)).collect(),
+            ),
+            (
+                "offsets",
+                "Int64",
+                offsets.iter().map(|&o| o as u64).collect(),
+            ),
+            ("types", "UInt8", vec![u64::from(self.cell_type); cells]),
+        ] {
+            writeln!(
+                out,
+                "        <DataArray type=\"{}\" Name=\"{}\" format=\"ascii\">",
+                kind, name
+            )?;
+            write_values(&mut out, values.iter())?;
+            writeln!(out, "        </DataArray>")?;
+        }
+        writeln!(
+            out,
+            "      </Cells>\n    </Piece>\n  </UnstructuredGrid>\n</VTKFile>"
+        )?;
+        out.finish()
+    }
+}
+
+/// Write `mesh` as a VTK surface (`.vtk` or `.vtu`).
+pub fn save_mesh(mesh: &Mesh, location: &str, options: &WriteOptions) -> Result<()> {
+    Grid::surface(&options.ordered(mesh)).save(location)
+}
+
+// Values a dozen to a line, indented under their array
+fn write_values<T: ToString>(
+    out: &mut impl Write,
+    values: impl Iterator<Item = T>,
+) -> std::io::Result<()> {
+    let values: Vec<String> = values.map(|v| v.to_string()).collect();
+    for line in values.chunks(12) {
+        writeln!(out, "          {}", line.join(" "))?;
+    }
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
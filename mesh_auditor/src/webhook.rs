@@ -0,0 +1,129 @@
+//! Completion callbacks for server jobs.
+//!
+//! When a job finishes (done, failed or cancelled) we POST a JSON summary
+//! of it to the job's callback URL, so whoever submitted it doesn't have to
+//! poll. Delivery happens on its own thread and is retried a few times with
+//! backoff; a receiver that stays down only costs us a warning in the log.
+//! Redirects aren't followed, and a callback the client named only goes to
+//! a public address, checked as the connection is made.
+
+use crate::allowlist;
+use crate::jobs::{Job, JobStatus};
+use serde::Serialize;
+use std::time::Duration;
+
+const ATTEMPTS: u32 = 4;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What the receiver gets.
+#[derive(Debug, Serialize)]
+pub struct JobSummary {
+    pub id: String,
+    pub status: JobStatus,
+    pub priority: i32,
+    pub resolution: usize,
+    pub submitted_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+    pub triangles: Option<usize>,
+    pub error: Option<String>,
+    /// Where to download the result; only set when the job is done.
+    pub output_url: Option<String>,
+    /// The remote copy of the result, if the job asked for one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+}
+
+impl JobSummary {
+    pub fn new(job: &Job, public_url: &str) -> Self {
+        let output_url = (job.status == JobStatus::Done).then(|| {
+            format!(
+                "{}/jobs/{}/result",
+                public_url.trim_end_matches('/'),
+                job.id
+            )
+        });
+        JobSummary {
+            id: job.id.clone(),
+            status: job.status,
+            priority: job.priority,
+            resolution: job.resolution,
+            submitted_at: job.submitted_at,
+            started_at: job.started_at,
+            finished_at: job.finished_at,
+            triangles: job.triangles,
+            error: job.error.clone(),
+            output_url,
+            output: job.output.clone(),
+        }
+    }
+}
+
+/// Where callbacks go.
+#[derive(Debug, Clone)]
+pub struct Callbacks {
+    /// Used for jobs submitted without their own callback URL.
+    pub default_url: Option<String>,
+    /// How clients reach this server, for building result links.
+    pub public_url: String,
+}
+
+impl Callbacks {
+    /// Tell the job's callback (if it has one) that it has finished.
+    pub fn job_finished(&self, job: &Job) {
+        let summary = JobSummary::new(job, &self.public_url);
+        match (&job.callback, &self.default_url) {
+            (Some(url), _) => notify(url.clone(), summary, true),
+            (None, Some(url)) => notify(url.clone(), summary, false),
+            (None, None) => {}
+        }
+    }
+}
+
+// Deliver `summary` to `url` in the background; `public_only` for a URL
+// the client gave rather than the operator
+fn notify(url: String, summary: JobSummary, public_only: bool) {
+    std::thread::spawn(move || {
+        let builder = ureq::AgentBuilder::new()
+            .timeout(REQUEST_TIMEOUT)
+            .redirects(0);
+        let agent = if public_only {
+            builder.resolver(allowlist::resolve_public).build()
+        } else {
+            builder.build()
+        };
+        let mut delay = Duration::from_secs(1);
+        for attempt in 1..=ATTEMPTS {
+            match agent.post(&url).send_json(&summary) {
+                Ok(response) if (300..400).contains(&response.status()) => {
+                    println!(
+                        "⚠️  Callback for {} redirected ({}), not followed: {}",
+                        summary.id,
+                        response.status(),
+                        url
+                    );
+                    return;
+                }
+                Ok(_) => return,
+                // 4xx: the receiver understood us and said no, so don't retry
+                Err(ureq::Error::Status(code, _)) if (400..500).contains(&code) => {
+                    println!(
+                        "⚠️  Callback for {} rejected ({}): {}",
+                        summary.id, code, url
+                    );
+                    return;
+                }
+                Err(e) if attempt == ATTEMPTS => {
+                    println!(
+                        "⚠️  Callback for {} gave up after {} tries: {}",
+                        summary.id, ATTEMPTS, e
+                    );
+                }
+                Err(_) => {
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+            }
+        }
+    });
+}